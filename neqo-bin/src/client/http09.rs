@@ -60,7 +60,7 @@ impl super::Handler for Handler<'_> {
     fn handle(&mut self, client: &mut Self::Client) -> Res<bool> {
         while let Some(event) = client.next_event() {
             if self.needs_key_update {
-                match client.initiate_key_update() {
+                match client.initiate_key_update(Instant::now()) {
                     Ok(()) => {
                         qdebug!("Keys updated");
                         self.needs_key_update = false;
@@ -115,7 +115,7 @@ impl super::Handler for Handler<'_> {
             return Ok(false);
         }
 
-        if self.args.resume && self.token.is_none() {
+        if (self.args.resume || self.args.session_file.is_some()) && self.token.is_none() {
             self.token = client.take_resumption_token(Instant::now());
         }
 
@@ -169,6 +169,9 @@ pub fn create_client(
         hostname,
         client.odcid().ok_or(Error::Internal)?,
     )?);
+    if let Some(keylog) = args.shared.keylog() {
+        client.set_keylog(keylog);
+    }
 
     Ok(client)
 }
@@ -12,18 +12,20 @@ use std::{
     cell::RefCell,
     collections::VecDeque,
     fmt::Display,
-    fs::File,
-    io::{BufWriter, Write as _},
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
     net::SocketAddr,
     num::NonZeroUsize,
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
     time::Instant,
 };
 
 use http::Uri as Url;
-use neqo_common::{Datagram, event::Provider, hex, qdebug, qerror, qinfo, qwarn};
-use neqo_crypto::{AuthenticationStatus, ResumptionToken};
+use neqo_common::{
+    Datagram, Header, event::Provider, header::HeadersExt as _, hex, qdebug, qerror, qinfo, qwarn,
+};
+use neqo_crypto::{AuthenticationStatus, ResumptionToken, SecretAgentInfo};
 use neqo_http3::{Error, Http3Client, Http3ClientEvent, Http3Parameters, Http3State, Priority};
 use neqo_transport::{
     AppError, CloseReason, Connection, EmptyConnectionIdGenerator, Error as TransportError,
@@ -31,7 +33,9 @@ use neqo_transport::{
 };
 use rustc_hash::FxHashMap as HashMap;
 
-use super::{Args, CloseState, Res, get_output_file, qlog_new};
+use super::{
+    Args, BUFWRITER_BUFFER_SIZE, CloseState, Request, Res, Timing, get_output_file, qlog_new,
+};
 use crate::{STREAM_IO_BUFFER_SIZE, send_data::SendData};
 
 pub struct Handler {
@@ -43,14 +47,20 @@ pub struct Handler {
 }
 
 impl Handler {
-    pub(crate) fn new(url_queue: VecDeque<Url>, args: Args) -> Self {
+    pub(crate) fn new(url_queue: VecDeque<Request>, args: Args, timing: Timing) -> Self {
         let output_read_data = args.output_read_data;
         let url_handler = UrlHandler {
+            origin: url_queue.front().map(Request::origin),
             url_queue,
             handled_urls: Vec::new(),
             stream_handlers: HashMap::default(),
             all_paths: Vec::new(),
             args,
+            timing,
+            connected: None,
+            zero_rtt: false,
+            redirects: Vec::new(),
+            failed: None,
         };
 
         Self {
@@ -60,6 +70,16 @@ impl Handler {
             read_buffer: vec![0; STREAM_IO_BUFFER_SIZE],
         }
     }
+
+    /// Redirects to other origins, which need another connection.
+    pub(crate) fn take_redirects(&mut self) -> Vec<Request> {
+        std::mem::take(&mut self.url_handler.redirects)
+    }
+
+    /// The first error status, if `--fail` is set.
+    pub(crate) const fn take_failure(&mut self) -> Option<u16> {
+        self.url_handler.failed.take()
+    }
 }
 
 pub fn create_client(
@@ -101,6 +121,9 @@ pub fn create_client(
 
     let qlog = qlog_new(args, hostname, client.connection_id())?;
     client.set_qlog(qlog);
+    if let Some(keylog) = args.shared.keylog() {
+        client.set_keylog(keylog);
+    }
     if let Some(ech) = &args.ech {
         client.enable_ech(ech)?;
     }
@@ -178,16 +201,17 @@ impl super::Handler for Handler {
                 Http3ClientEvent::HeaderReady {
                     stream_id,
                     headers,
+                    interim,
                     fin,
-                    ..
                 } => {
-                    if self.url_handler.stream_handler(stream_id).is_some() {
+                    if let Some(handler) = self.url_handler.stream_handler(stream_id) {
                         qdebug!("READ HEADERS[{stream_id}]: fin={fin} {headers:?}");
+                        handler.process_headers(&headers, interim)?;
                     } else {
                         qwarn!("Data on unexpected stream: {stream_id}");
                     }
                     if fin {
-                        self.url_handler.on_stream_fin(client, stream_id);
+                        self.url_handler.on_stream_fin(client, stream_id)?;
                     }
                 }
                 Http3ClientEvent::DataReadable { stream_id } => {
@@ -222,7 +246,7 @@ impl super::Handler for Handler {
                     }
 
                     if stream_done {
-                        self.url_handler.on_stream_fin(client, stream_id);
+                        self.url_handler.on_stream_fin(client, stream_id)?;
                     }
                 }
                 Http3ClientEvent::DataWritable { stream_id } => {
@@ -238,13 +262,19 @@ impl super::Handler for Handler {
                 Http3ClientEvent::StateChange(Http3State::Connected)
                 | Http3ClientEvent::RequestsCreatable => {
                     qinfo!("{event:?}");
-                    self.url_handler.process_urls(client);
+                    if matches!(event, Http3ClientEvent::StateChange(_)) {
+                        self.url_handler.connected = Some(Instant::now());
+                        self.url_handler.zero_rtt = client
+                            .tls_info()
+                            .is_some_and(SecretAgentInfo::early_data_accepted);
+                    }
+                    self.url_handler.process_urls(client)?;
                 }
                 Http3ClientEvent::ZeroRttRejected => {
                     qinfo!("{event:?}");
                     // All 0-RTT data was rejected. We need to retransmit it.
                     self.url_handler.reinit();
-                    self.url_handler.process_urls(client);
+                    self.url_handler.process_urls(client)?;
                 }
                 Http3ClientEvent::ResumptionToken(t) => self.token = Some(t),
                 _ => {
@@ -262,6 +292,9 @@ impl super::Handler for Handler {
 }
 
 trait StreamHandler {
+    fn process_headers(&mut self, _headers: &[Header], _interim: bool) -> Res<()> {
+        Ok(())
+    }
     fn process_data_readable(
         &mut self,
         stream_id: StreamId,
@@ -275,13 +308,141 @@ trait StreamHandler {
         stream_id: StreamId,
         now: Instant,
     );
+    /// The response is complete.  This returns the request to make next if
+    /// the response is a redirect that is being followed.
+    fn finish(&mut self, _urls: &UrlHandler) -> Res<Option<Request>> {
+        Ok(None)
+    }
+}
+
+/// Open a file for `--output` or `--dump-header`, with "-" meaning stdout.
+fn open_output(path: &Path, append: bool) -> io::Result<Box<dyn Write>> {
+    if path.as_os_str() == "-" {
+        return Ok(Box::new(io::stdout()));
+    }
+    let f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    Ok(Box::new(BufWriter::with_capacity(BUFWRITER_BUFFER_SIZE, f)))
+}
+
+/// Format a response header section as it would appear in HTTP/1.1.
+fn format_headers(headers: &[Header]) -> Vec<u8> {
+    let mut out = b"HTTP/3".to_vec();
+    if let Some(status) = headers.find_header(":status") {
+        out.push(b' ');
+        out.extend_from_slice(status.value());
+    }
+    out.extend_from_slice(b"\r\n");
+    for h in headers.iter().filter(|h| !h.name().starts_with(':')) {
+        out.extend_from_slice(h.name().as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(h.value());
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"\r\n");
+    out
+}
+
+/// Resolve the target of a redirect against the URL that was requested.
+fn resolve(base: &Url, location: &str) -> Option<Url> {
+    if let Ok(url) = location.parse::<Url>()
+        && url.scheme().is_some()
+        && url.authority().is_some()
+    {
+        return Some(url);
+    }
+    let scheme = base.scheme_str()?;
+    let authority = base.authority()?;
+    let target = match location.strip_prefix("//") {
+        Some(rest) => format!("{scheme}://{rest}"),
+        None if location.starts_with('/') => format!("{scheme}://{authority}{location}"),
+        None => {
+            let path = base.path();
+            let dir = &path[..=path.rfind('/').unwrap_or(0)];
+            format!("{scheme}://{authority}{dir}{location}")
+        }
+    };
+    target.parse().ok()
+}
+
+/// Replace variables and escapes in a `--write-out` format.
+fn write_out<F: Fn(&str) -> Option<String>>(format: &str, var: F) -> String {
+    let mut out = String::new();
+    let mut rest = format;
+    while let Some(c) = rest.chars().next() {
+        if let Some(name) = rest.strip_prefix("%{")
+            && let Some(end) = name.find('}')
+            && let Some(value) = var(&name[..end])
+        {
+            out.push_str(&value);
+            rest = &name[end + 1..];
+            continue;
+        }
+        let escaped = match rest.strip_prefix('\\').and_then(|r| r.chars().next()) {
+            Some('n') => Some('\n'),
+            Some('r') => Some('\r'),
+            Some('t') => Some('\t'),
+            Some('\\') => Some('\\'),
+            _ => None,
+        };
+        if let Some(e) = escaped {
+            out.push(e);
+            rest = &rest[2..];
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
 }
 
 struct DownloadStreamHandler {
-    out_file: Option<BufWriter<File>>,
+    request: Request,
+    out_file: Option<Box<dyn Write>>,
+    /// The request body, if there is one.
+    body: Option<SendData>,
+    status: Option<u16>,
+    /// Where a redirect leads, if it is being followed.
+    location: Option<Url>,
+    first_byte: Option<Instant>,
+    size_download: usize,
+    include: bool,
+    dump_header: Option<PathBuf>,
+    follow: bool,
 }
 
 impl StreamHandler for DownloadStreamHandler {
+    fn process_headers(&mut self, headers: &[Header], interim: bool) -> Res<()> {
+        self.first_byte.get_or_insert_with(Instant::now);
+        if self.include || self.dump_header.is_some() {
+            let text = format_headers(headers);
+            if let Some(path) = &self.dump_header {
+                let mut out = open_output(path, true)?;
+                out.write_all(&text)?;
+                out.flush()?;
+            }
+            if let (true, Some(out_file)) = (self.include, &mut self.out_file) {
+                out_file.write_all(&text)?;
+            }
+        }
+        if interim {
+            return Ok(());
+        }
+        self.status = headers
+            .find_header(":status")
+            .and_then(|h| h.value_utf8().ok()?.parse().ok());
+        if self.follow && matches!(self.status, Some(301 | 302 | 303 | 307 | 308)) {
+            self.location = headers
+                .find_header("location")
+                .and_then(|h| resolve(&self.request.url, h.value_utf8().ok()?));
+        }
+        Ok(())
+    }
+
     fn process_data_readable(
         &mut self,
         stream_id: StreamId,
@@ -289,6 +450,11 @@ impl StreamHandler for DownloadStreamHandler {
         data: &[u8],
         output_read_data: bool,
     ) -> Res<()> {
+        self.size_download += data.len();
+        if self.location.is_some() {
+            // Only the content of the last response in a redirect chain is kept.
+            return Ok(());
+        }
         if let Some(out_file) = &mut self.out_file {
             if !data.is_empty() {
                 out_file.write_all(data)?;
@@ -305,13 +471,7 @@ impl StreamHandler for DownloadStreamHandler {
         }
 
         if fin {
-            self.out_file.take().map_or_else(
-                || {
-                    qdebug!("<FIN[{stream_id}]>");
-                    Ok(())
-                },
-                |mut out_file| out_file.flush(),
-            )?;
+            qdebug!("<FIN[{stream_id}]>");
         }
 
         Ok(())
@@ -319,10 +479,76 @@ impl StreamHandler for DownloadStreamHandler {
 
     fn process_data_writable(
         &mut self,
-        _client: &mut Http3Client,
-        _stream_id: StreamId,
-        _now: Instant,
+        client: &mut Http3Client,
+        stream_id: StreamId,
+        now: Instant,
     ) {
+        if let Some(body) = &mut self.body {
+            let done = body.send(|chunk| client.send_data(stream_id, chunk, now).unwrap());
+            if done {
+                self.body = None;
+                client.stream_close_send(stream_id, now).unwrap();
+            }
+        }
+    }
+
+    fn finish(&mut self, urls: &UrlHandler) -> Res<Option<Request>> {
+        if let Some(out_file) = &mut self.out_file {
+            out_file.flush()?;
+        }
+        let args = &urls.args;
+        if let Some(url) = self.location.take() {
+            if self.request.redirects >= args.max_redirs {
+                return Err(super::Error::Redirects(self.request.redirects));
+            }
+            // Like browsers, switch to GET after a POST is redirected with 301 or 302.
+            let get = self.request.get
+                || self.status == Some(303)
+                || (matches!(self.status, Some(301 | 302)) && args.method == "POST");
+            qinfo!("Following redirect from {} to {url}", self.request.url);
+            return Ok(Some(Request {
+                redirects: self.request.redirects + 1,
+                get,
+                cross_origin: self.request.cross_origin || !self.request.same_origin(&url),
+                url,
+                ..self.request.clone()
+            }));
+        }
+        if let Some(format) = &args.write_out {
+            let now = Instant::now();
+            let secs = |t: Option<Instant>| {
+                format!(
+                    "{:.6}",
+                    t.map_or(0.0, |t| t.duration_since(urls.timing.start).as_secs_f64())
+                )
+            };
+            let text = write_out(format, |var| match var {
+                "http_code" => Some(format!("{:03}", self.status.unwrap_or_default())),
+                "url" => Some(self.request.url.to_string()),
+                "num_redirects" => Some(self.request.redirects.to_string()),
+                "size_download" => Some(self.size_download.to_string()),
+                "size_upload" => Some(
+                    args.body
+                        .as_ref()
+                        .filter(|_| !self.request.get)
+                        .map_or(0, Vec::len)
+                        .to_string(),
+                ),
+                "zero_rtt" => Some(u8::from(urls.zero_rtt).to_string()),
+                "time_namelookup" => Some(format!("{:.6}", urls.timing.dns.as_secs_f64())),
+                "time_appconnect" => Some(secs(urls.connected)),
+                "time_starttransfer" => Some(secs(self.first_byte)),
+                "time_total" => Some(secs(Some(now))),
+                _ => None,
+            });
+            let mut stdout = io::stdout();
+            stdout.write_all(text.as_bytes())?;
+            stdout.flush()?;
+        }
+        match self.status {
+            Some(status) if args.fail && status >= 400 => Err(super::Error::Status(status)),
+            _ => Ok(None),
+        }
     }
 }
 
@@ -371,11 +597,22 @@ impl StreamHandler for UploadStreamHandler {
 }
 
 struct UrlHandler {
-    url_queue: VecDeque<Url>,
-    handled_urls: Vec<Url>,
+    /// The origin of the connection.
+    origin: Option<(String, u16)>,
+    url_queue: VecDeque<Request>,
+    handled_urls: Vec<Request>,
     stream_handlers: HashMap<StreamId, Box<dyn StreamHandler>>,
     all_paths: Vec<PathBuf>,
     args: Args,
+    timing: Timing,
+    /// When the handshake completed.
+    connected: Option<Instant>,
+    /// Whether the server accepted 0-RTT.
+    zero_rtt: bool,
+    /// Redirects to other origins.
+    redirects: Vec<Request>,
+    /// The first error status, if `--fail` is set.
+    failed: Option<u16>,
 }
 
 impl UrlHandler {
@@ -383,7 +620,7 @@ impl UrlHandler {
         self.stream_handlers.get_mut(&stream_id)
     }
 
-    fn process_urls(&mut self, client: &mut Http3Client) {
+    fn process_urls(&mut self, client: &mut Http3Client) -> Res<()> {
         loop {
             if self.url_queue.is_empty() {
                 break;
@@ -391,56 +628,79 @@ impl UrlHandler {
             if self.stream_handlers.len() >= self.args.concurrency {
                 break;
             }
-            if !self.next_url(client) {
+            if !self.next_url(client)? {
                 break;
             }
         }
+        Ok(())
     }
 
-    fn next_url(&mut self, client: &mut Http3Client) -> bool {
-        let url = self
+    fn next_url(&mut self, client: &mut Http3Client) -> Res<bool> {
+        let request = self
             .url_queue
             .pop_front()
             .expect("download_next called with empty queue");
+        let url = &request.url;
         let now = Instant::now();
-        match client.fetch(
-            now,
-            &self.args.method,
-            &url,
-            &self.args.headers,
-            Priority::default(),
-        ) {
+        let (method, body) = if request.get {
+            ("GET", None)
+        } else {
+            (self.args.method.as_str(), self.args.body.as_ref())
+        };
+        let mut headers = if request.cross_origin {
+            Vec::new()
+        } else {
+            self.args.headers.clone()
+        };
+        if let Some(body) = body
+            && headers.iter().find_header("content-length").is_none()
+        {
+            headers.push(Header::new("content-length", body.len().to_string()));
+        }
+        match client.fetch(now, method, url, &headers, Priority::default()) {
             Ok(client_stream_id) => {
                 qdebug!("Successfully created stream id {client_stream_id} for {url}");
 
-                let handler: Box<dyn StreamHandler> = match self.args.method.as_str() {
-                    "GET" => {
-                        let out_file = get_output_file(
-                            &url,
-                            self.args.output_dir.as_ref(),
-                            &mut self.all_paths,
-                        );
-                        client.stream_close_send(client_stream_id, now).unwrap();
-                        Box::new(DownloadStreamHandler { out_file })
-                    }
-                    "POST" => Box::new(UploadStreamHandler {
+                let handler: Box<dyn StreamHandler> = if body.is_none() && method == "POST" {
+                    Box::new(UploadStreamHandler {
                         data: SendData::zeroes(self.args.upload_size),
                         start: now,
-                    }),
-                    _ => unimplemented!(),
+                    })
+                } else {
+                    let out_file = if let Some(path) = &request.output {
+                        Some(open_output(path, request.redirects > 0)?)
+                    } else {
+                        get_output_file(url, self.args.output_dir.as_ref(), &mut self.all_paths)
+                            .map(|f| Box::new(f) as Box<dyn Write>)
+                    };
+                    if body.is_none() {
+                        client.stream_close_send(client_stream_id, now)?;
+                    }
+                    Box::new(DownloadStreamHandler {
+                        request: request.clone(),
+                        out_file,
+                        body: body.map(|b| SendData::from(&b[..])),
+                        status: None,
+                        location: None,
+                        first_byte: None,
+                        size_download: 0,
+                        include: self.args.include,
+                        dump_header: self.args.dump_header.clone(),
+                        follow: self.args.location,
+                    })
                 };
 
                 self.stream_handlers.insert(client_stream_id, handler);
-                self.handled_urls.push(url);
-                true
+                self.handled_urls.push(request);
+                Ok(true)
             }
             Err(
                 Error::Transport(TransportError::StreamLimit)
                 | Error::StreamLimit
                 | Error::Unavailable,
             ) => {
-                self.url_queue.push_front(url);
-                false
+                self.url_queue.push_front(request);
+                Ok(false)
             }
             Err(e) => {
                 panic!("Can't create stream {e}");
@@ -452,9 +712,23 @@ impl UrlHandler {
         self.stream_handlers.is_empty() && self.url_queue.is_empty()
     }
 
-    fn on_stream_fin(&mut self, client: &mut Http3Client, stream_id: StreamId) {
-        self.stream_handlers.remove(&stream_id);
-        self.process_urls(client);
+    fn on_stream_fin(&mut self, client: &mut Http3Client, stream_id: StreamId) -> Res<()> {
+        if let Some(mut handler) = self.stream_handlers.remove(&stream_id) {
+            match handler.finish(self) {
+                Ok(Some(next)) if Some(next.origin()) == self.origin => {
+                    self.url_queue.push_back(next);
+                }
+                Ok(Some(next)) => self.redirects.push(next),
+                Ok(None) => {}
+                // Only this request fails, the others continue.
+                Err(super::Error::Status(status)) => {
+                    qerror!("Request failed with status {status}");
+                    self.failed.get_or_insert(status);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.process_urls(client)
     }
 
     fn reinit(&mut self) {
@@ -465,3 +739,49 @@ impl UrlHandler {
         self.all_paths.clear();
     }
 }
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use http::Uri as Url;
+
+    use super::{super::Request, resolve, write_out};
+
+    #[test]
+    fn redirect_target() {
+        let base = "https://example.com:4433/a/b?c".parse::<Url>().unwrap();
+        let target = |location| resolve(&base, location).unwrap().to_string();
+        assert_eq!(target("https://other.example/x"), "https://other.example/x");
+        assert_eq!(target("//other.example/x"), "https://other.example/x");
+        assert_eq!(target("/x?y"), "https://example.com:4433/x?y");
+        assert_eq!(target("x"), "https://example.com:4433/a/x");
+    }
+
+    #[test]
+    fn same_origin() {
+        let request = Request {
+            url: "https://example.com/a".parse().unwrap(),
+            output: None,
+            redirects: 0,
+            get: false,
+            cross_origin: false,
+        };
+        let same = |url: &str| request.same_origin(&url.parse().unwrap());
+        assert!(same("https://example.com/b"));
+        assert!(same("https://example.com:443/b"));
+        assert!(!same("http://example.com/b"));
+        assert!(!same("https://example.com:8443/b"));
+        assert!(!same("https://other.example/b"));
+    }
+
+    #[test]
+    fn write_out_format() {
+        let var = |name: &str| (name == "http_code").then(|| "200".to_string());
+        assert_eq!(write_out("%{http_code}\\n", var), "200\n");
+        assert_eq!(
+            write_out("\\t%{nope}%{http_code", var),
+            "\t%{nope}%{http_code"
+        );
+        assert_eq!(write_out("\\\\n\\x", var), "\\n\\x");
+    }
+}
@@ -9,14 +9,14 @@
 use std::{
     collections::VecDeque,
     fmt::Display,
-    fs::{File, OpenOptions, create_dir_all},
+    fs::{self, File, OpenOptions, create_dir_all},
     io::{self, BufWriter, ErrorKind},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs as _},
     num::NonZeroUsize,
     path::PathBuf,
     pin::Pin,
     process::exit,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use clap::Parser;
@@ -42,6 +42,7 @@ use crate::SharedArgs;
 
 mod http09;
 mod http3;
+mod session;
 
 const BUFWRITER_BUFFER_SIZE: usize = 64 * 1024;
 
@@ -59,6 +60,10 @@ pub enum Error {
     Transport(#[from] neqo_transport::Error),
     #[error("application error: {0}")]
     Application(AppError),
+    #[error("HTTP error status {0}")]
+    Status(u16),
+    #[error("too many redirects: {0}")]
+    Redirects(usize),
     #[error(transparent)]
     Crypto(#[from] neqo_crypto::Error),
 }
@@ -117,6 +122,11 @@ pub struct Args {
     /// Use this for 0-RTT: the stack always attempts 0-RTT on resumption.
     resume: bool,
 
+    #[arg(name = "session-file", long)]
+    /// Load resumption tokens for servers from this file, and save new ones
+    /// to it, so that later runs can resume.
+    session_file: Option<PathBuf>,
+
     #[arg(name = "key-update", long, hide = true)]
     /// Attempt to initiate a key update immediately after confirming the connection.
     key_update: bool,
@@ -150,6 +160,52 @@ pub struct Args {
     #[arg(name = "cid-length", short = 'l', long, default_value = "0",
           value_parser = clap::value_parser!(u8).range(..=20))]
     cid_len: u8,
+
+    #[arg(name = "data", short = 'd', long, conflicts_with = "data-binary")]
+    /// Send this as the request body, with POST unless -m says otherwise.
+    /// With a leading '@', the body is read from the named file, without any
+    /// carriage returns or newlines.
+    data: Option<String>,
+
+    #[arg(name = "data-binary", long)]
+    /// Like --data, except that files are sent unchanged.
+    data_binary: Option<String>,
+
+    /// The request body from `--data` or `--data-binary`.
+    #[arg(skip)]
+    body: Option<Vec<u8>>,
+
+    #[arg(name = "output", short = 'o', long)]
+    /// Save the response to the URL in the same position to this file, or
+    /// write it to stdout for "-".
+    output: Vec<PathBuf>,
+
+    #[arg(name = "write-out", short = 'w', long)]
+    /// Print this after each response, replacing `%{http_code}`, `%{url}`,
+    /// `%{num_redirects}`, `%{size_download}`, `%{size_upload}`, `%{zero_rtt}`,
+    /// and the times since the connection started: `%{time_namelookup}`,
+    /// `%{time_appconnect}`, `%{time_starttransfer}` and `%{time_total}`.
+    write_out: Option<String>,
+
+    #[arg(name = "include", short = 'i', long)]
+    /// Include response headers in the saved response.
+    include: bool,
+
+    #[arg(name = "dump-header", short = 'D', long)]
+    /// Write response headers to this file, or to stdout for "-".
+    dump_header: Option<PathBuf>,
+
+    #[arg(name = "location", short = 'L', long)]
+    /// Follow redirects.
+    location: bool,
+
+    #[arg(name = "max-redirs", long, default_value = "10")]
+    /// The most redirects to follow for each URL.
+    max_redirs: usize,
+
+    #[arg(name = "fail", short = 'f', long)]
+    /// Fail when a response has an error status.
+    fail: bool,
 }
 
 impl Args {
@@ -185,6 +241,7 @@ impl Args {
             output_read_data: false,
             output_dir: Some("/dev/null".into()),
             resume: false,
+            session_file: None,
             key_update: false,
             ech: None,
             ipv4_only: false,
@@ -193,6 +250,16 @@ impl Args {
             upload_size,
             stats: false,
             cid_len: 0,
+            data: None,
+            data_binary: None,
+            body: None,
+            output: Vec::new(),
+            write_out: None,
+            include: false,
+            dump_header: None,
+            location: false,
+            max_redirs: 10,
+            fail: false,
         }
     }
 
@@ -290,6 +357,94 @@ impl Args {
     pub fn set_qlog_dir(&mut self, dir: PathBuf) {
         self.shared.qlog_dir = Some(dir);
     }
+
+    #[cfg(test)]
+    #[expect(clippy::missing_panics_doc, reason = "This is test code.")]
+    pub fn set_paths(&mut self, paths: &[&str]) {
+        let base = self.urls[0].clone();
+        self.urls = paths
+            .iter()
+            .map(|path| {
+                format!(
+                    "{}://{}{path}",
+                    base.scheme_str().unwrap_or("http"),
+                    base.authority().unwrap()
+                )
+                .parse()
+                .unwrap()
+            })
+            .collect();
+    }
+
+    /// Fail on error statuses, fetching one URL at a time.
+    #[cfg(test)]
+    pub const fn set_fail(&mut self) {
+        self.fail = true;
+        self.download_in_series = true;
+    }
+
+    #[cfg(test)]
+    pub fn set_output(&mut self, output: Vec<PathBuf>, dump_header: PathBuf, write_out: &str) {
+        self.output = output;
+        self.dump_header = Some(dump_header);
+        self.write_out = Some(write_out.to_string());
+    }
+
+    /// Read the request body, and switch to POST if there is one.
+    fn load_body(&mut self) -> Res<()> {
+        let (data, binary) = match (&self.data, &self.data_binary) {
+            (Some(data), _) => (data, false),
+            (None, Some(data)) => (data, true),
+            (None, None) => return Ok(()),
+        };
+        let mut body = match data.strip_prefix('@') {
+            Some(path) => fs::read(path)?,
+            None => data.clone().into_bytes(),
+        };
+        if !binary && data.starts_with('@') {
+            body.retain(|&b| b != b'\r' && b != b'\n');
+        }
+        self.body = Some(body);
+        if self.method == "GET" {
+            self.method = String::from("POST");
+        }
+        Ok(())
+    }
+}
+
+/// A URL to fetch.
+#[derive(Debug, Clone)]
+struct Request {
+    url: Url,
+    /// Where to save the response, from `--output`.
+    output: Option<PathBuf>,
+    /// The number of redirects that led to this request.
+    redirects: usize,
+    /// Whether a redirect changed the method to GET.
+    get: bool,
+    /// Whether a redirect led to another origin, so that `--header` fields,
+    /// which might carry credentials, are not sent.
+    cross_origin: bool,
+}
+
+impl Request {
+    fn origin(&self) -> (String, u16) {
+        let authority = self
+            .url
+            .authority()
+            .expect("URL must have an authority (host)");
+        let port = authority.port_u16().unwrap_or(443);
+        (authority.host().to_string(), port)
+    }
+
+    /// Whether `url` has the same scheme, host and port as this request.
+    fn same_origin(&self, url: &Url) -> bool {
+        let next = Self {
+            url: url.clone(),
+            ..self.clone()
+        };
+        self.url.scheme() == url.scheme() && self.origin() == next.origin()
+    }
 }
 
 fn get_output_file(
@@ -352,6 +507,15 @@ async fn ready(
     select(socket_ready, timeout_ready).await.factor_first().0
 }
 
+/// When a connection started, for `--write-out`.  Times are measured from
+/// before the name lookup for the first connection to an origin.
+#[derive(Debug, Clone, Copy)]
+struct Timing {
+    start: Instant,
+    /// How long the name lookup took.
+    dns: Duration,
+}
+
 /// Handles a given task on the provided [`Client`].
 trait Handler {
     type Client: Client;
@@ -412,7 +576,7 @@ impl<'a, H: Handler> Runner<'a, H> {
         }
     }
 
-    async fn run(mut self) -> Res<Option<ResumptionToken>> {
+    async fn run(&mut self) -> Res<()> {
         loop {
             let handler_done = self.handler.handle(&mut self.client)?;
             self.process_output().await?;
@@ -444,7 +608,7 @@ impl<'a, H: Handler> Runner<'a, H> {
             qinfo!("{:?}", self.client.stats());
         }
 
-        Ok(self.handler.take_token())
+        Ok(())
     }
 
     async fn process_output(&mut self) -> Result<(), io::Error> {
@@ -538,15 +702,15 @@ const fn local_addr_for(remote_addr: &SocketAddr, local_port: u16) -> SocketAddr
     }
 }
 
-fn urls_by_origin(urls: &[Url]) -> impl Iterator<Item = ((String, u16), VecDeque<Url>)> + use<> {
-    urls.iter()
+fn requests_by_origin(
+    requests: impl IntoIterator<Item = Request>,
+) -> impl Iterator<Item = ((String, u16), VecDeque<Request>)> {
+    requests
+        .into_iter()
         .fold(
-            HashMap::<(String, u16), VecDeque<Url>>::default(),
-            |mut map, url| {
-                let authority = url.authority().expect("URL must have an authority (host)");
-                let host = authority.host().to_string();
-                let port = authority.port_u16().unwrap_or(443);
-                map.entry((host, port)).or_default().push_back(url.clone());
+            HashMap::<(String, u16), VecDeque<Request>>::default(),
+            |mut map, request| {
+                map.entry(request.origin()).or_default().push_back(request);
                 map
             },
         )
@@ -572,12 +736,33 @@ pub async fn client(mut args: Args) -> Res<()> {
 
     init()?;
 
-    for ((host, port), mut urls) in urls_by_origin(&args.urls) {
+    args.load_body()?;
+    if let Some(path) = args.dump_header.as_ref().filter(|p| p.as_os_str() != "-") {
+        // Start with an empty file; responses are appended.
+        File::create(path)?;
+    }
+
+    let requests = args.urls.iter().enumerate().map(|(i, url)| Request {
+        url: url.clone(),
+        output: args.output.get(i).cloned(),
+        redirects: 0,
+        get: false,
+        cross_origin: false,
+    });
+    let mut origins = requests_by_origin(requests).collect::<VecDeque<_>>();
+    let mut failed = None;
+    let mut sessions = args
+        .session_file
+        .clone()
+        .map(|path| session::SessionFile::load(path, Instant::now()))
+        .transpose()?;
+    while let Some(((host, port), mut urls)) = origins.pop_front() {
         if args.resume && urls.len() < 2 {
             qerror!("Resumption to {host} cannot work without at least 2 URLs");
             exit(127);
         }
 
+        let mut start = Instant::now();
         let remote_addr = format!("{host}:{port}").to_socket_addrs()?.find(|addr| {
             !matches!(
                 (addr, args.ipv4_only, args.ipv6_only),
@@ -588,6 +773,7 @@ pub async fn client(mut args: Args) -> Res<()> {
             qerror!("No compatible address found for: {host}");
             exit(1);
         };
+        let mut dns = start.elapsed();
         let mut socket = crate::udp::Socket::bind(local_addr_for(&remote_addr, 0))?;
         if socket.may_fragment() {
             qinfo!("Datagrams may be fragmented by the IP layer. Disabling PMTUD.");
@@ -599,9 +785,12 @@ pub async fn client(mut args: Args) -> Res<()> {
             args.shared.alpn
         );
 
-        let mut token: Option<ResumptionToken> = None;
+        let mut token = sessions
+            .as_mut()
+            .and_then(|s| s.take(&host, &args.shared.alpn));
         let mut first = true;
-        while !urls.is_empty() {
+        let mut res = Ok(());
+        while res.is_ok() && !urls.is_empty() {
             let to_request = if (args.resume && first) || args.download_in_series {
                 urls.pop_front().into_iter().collect()
             } else {
@@ -610,27 +799,44 @@ pub async fn client(mut args: Args) -> Res<()> {
 
             first = false;
 
-            token = if args.shared.alpn == "h3" {
+            (token, res) = if args.shared.alpn == "h3" {
                 let client = http3::create_client(&args, real_local, remote_addr, &host, token)
                     .expect("failed to create client");
 
-                let handler = http3::Handler::new(to_request, args.clone());
+                let timing = Timing { start, dns };
+                let handler = http3::Handler::new(to_request, args.clone(), timing);
 
-                Runner::new(real_local, &mut socket, client, handler, &args)
-                    .run()
-                    .await?
+                let mut runner = Runner::new(real_local, &mut socket, client, handler, &args);
+                let res = Box::pin(runner.run()).await;
+                // Redirects to the same origin were followed on the connection.
+                origins.extend(requests_by_origin(runner.handler.take_redirects()));
+                failed = failed.or_else(|| runner.handler.take_failure());
+                (runner.handler.take_token(), res)
             } else {
                 let client = http09::create_client(&args, real_local, remote_addr, &host, token)
                     .expect("failed to create client");
 
-                let handler = http09::Handler::new(to_request, &args);
+                let urls = to_request.into_iter().map(|r| r.url).collect();
+                let handler = http09::Handler::new(urls, &args);
 
-                Runner::new(real_local, &mut socket, client, handler, &args)
-                    .run()
-                    .await?
+                let mut runner = Runner::new(real_local, &mut socket, client, handler, &args);
+                let res = runner.run().await;
+                (runner.handler.take_token(), res)
             };
+            start = Instant::now();
+            dns = Duration::ZERO;
+        }
+
+        // Tokens are saved even if a connection failed, so none are lost.
+        if let Some(sessions) = &mut sessions {
+            if let Some(token) = token {
+                sessions.insert(&host, &args.shared.alpn, token);
+            }
+            sessions.save(Instant::now())?;
         }
+        res?;
     }
 
-    Ok(())
+    // With `--fail`, an error status only stops the request that got it.
+    failed.map_or(Ok(()), |status| Err(Error::Status(status)))
 }
@@ -0,0 +1,181 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Resumption tokens that are kept in a file, so that a later run of the
+//! client can use 0-RTT and skip address validation.
+//!
+//! A [`ResumptionToken`] carries both the TLS session ticket and any address
+//! validation token that the server sent in a `NEW_TOKEN` frame.  Each line of
+//! the file holds one token:
+//!
+//! ```text
+//! <server name> <ALPN> <expiry, in seconds since the UNIX epoch> <hex token>
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write as _},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use neqo_common::qdebug;
+use neqo_crypto::ResumptionToken;
+
+use super::Res;
+
+pub struct SessionFile {
+    path: PathBuf,
+    tokens: BTreeMap<(String, String), ResumptionToken>,
+}
+
+impl SessionFile {
+    /// Read the tokens in the file at `path`, dropping any that have expired.
+    /// A missing file is treated as empty.
+    pub fn load(path: PathBuf, now: Instant) -> Res<Self> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let wall = SystemTime::now();
+        let tokens = text
+            .lines()
+            .filter_map(|line| {
+                let entry = parse(line, now, wall);
+                if entry.is_none() {
+                    qdebug!("Dropping session entry: {line}");
+                }
+                entry
+            })
+            .collect();
+        Ok(Self { path, tokens })
+    }
+
+    /// Remove the token for a server, if there is one.  Tokens are only used
+    /// once, so it is replaced if the server provides another.
+    pub fn take(&mut self, server_name: &str, alpn: &str) -> Option<ResumptionToken> {
+        self.tokens
+            .remove(&(server_name.to_string(), alpn.to_string()))
+    }
+
+    pub fn insert(&mut self, server_name: &str, alpn: &str, token: ResumptionToken) {
+        self.tokens
+            .insert((server_name.to_string(), alpn.to_string()), token);
+    }
+
+    /// Write the tokens that have not expired back to the file.
+    pub fn save(&mut self, now: Instant) -> Res<()> {
+        self.tokens.retain(|_, t| t.expiration_time() > now);
+        let wall = SystemTime::now();
+        let mut text = String::new();
+        for ((server_name, alpn), token) in &self.tokens {
+            let expiry = (wall + token.expiration_time().duration_since(now))
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            _ = writeln!(
+                text,
+                "{server_name} {alpn} {} {}",
+                expiry.as_secs(),
+                hex::encode(token)
+            );
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&self.path)?;
+        // The tokens let anyone resume the sessions, so only the owner gets to
+        // read them, even if the file already existed.
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        file.write_all(text.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn parse(
+    line: &str,
+    now: Instant,
+    wall: SystemTime,
+) -> Option<((String, String), ResumptionToken)> {
+    let mut fields = line.split(' ');
+    let (server_name, alpn, expiry, token) = (
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+    );
+    let expiry = UNIX_EPOCH + Duration::from_secs(expiry.parse().ok()?);
+    // This fails for tokens that have expired.
+    let remaining = expiry.duration_since(wall).ok()?;
+    let token = ResumptionToken::new(hex::decode(token).ok()?, now + remaining);
+    Some(((server_name.to_string(), alpn.to_string()), token))
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{
+        fmt::Write as _,
+        fs,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    };
+
+    use neqo_crypto::ResumptionToken;
+
+    use super::SessionFile;
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("neqo-session-{}", std::process::id()));
+        // An existing file that others can read is made private.
+        fs::write(&path, "").unwrap();
+        let now = Instant::now();
+        let mut sessions = SessionFile::load(path.clone(), now).unwrap();
+        assert!(sessions.take("example.com", "h3").is_none());
+
+        let hour = Duration::from_secs(3600);
+        sessions.insert(
+            "example.com",
+            "h3",
+            ResumptionToken::new(vec![1, 2], now + hour),
+        );
+        sessions.insert(
+            "example.com",
+            "hq-interop",
+            ResumptionToken::new(vec![3], now),
+        );
+        sessions.save(now).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // Add an expired entry and one that can't be parsed.
+        let expired = (SystemTime::now() - hour)
+            .duration_since(UNIX_EPOCH)
+            .unwrap();
+        let mut text = fs::read_to_string(&path).unwrap();
+        writeln!(text, "old.example h3 {} 04", expired.as_secs()).unwrap();
+        writeln!(text, "bad.example h3").unwrap();
+        fs::write(&path, text).unwrap();
+
+        let mut sessions = SessionFile::load(path.clone(), now).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(sessions.take("example.com", "hq-interop").is_none());
+        assert!(sessions.take("old.example", "h3").is_none());
+        assert!(sessions.take("bad.example", "h3").is_none());
+        let token = sessions.take("example.com", "h3").unwrap();
+        assert_eq!(token.as_ref(), &[1, 2]);
+        let expiry = token.expiration_time();
+        assert!(expiry + Duration::from_secs(2) > now + hour && expiry <= now + hour);
+        assert!(sessions.take("example.com", "h3").is_none());
+    }
+}
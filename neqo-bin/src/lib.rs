@@ -7,12 +7,15 @@
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 
 use std::{
+    env,
     net::{SocketAddr, ToSocketAddrs as _},
     path::PathBuf,
     time::Duration,
 };
 
 use clap::{Parser, builder::TypedValueParser as _};
+use neqo_common::qwarn;
+use neqo_crypto::KeyLog;
 use neqo_transport::{
     CongestionControlAlgorithm, ConnectionParameters, DEFAULT_INITIAL_RTT, StreamType, Version,
     tparams::PreferredAddress,
@@ -23,7 +26,7 @@ use thiserror::Error;
 pub mod client;
 mod send_data;
 pub mod server;
-pub mod udp;
+pub use neqo_tokio::udp;
 
 /// Firefox default value
 ///
@@ -45,6 +48,11 @@ pub struct SharedArgs {
     /// Enable QLOG logging and QLOG traces to this directory
     qlog_dir: Option<PathBuf>,
 
+    #[arg(long, value_parser=clap::value_parser!(PathBuf))]
+    /// Append TLS secrets to this file, in the format used by `SSLKEYLOGFILE`.
+    /// Defaults to the value of the `SSLKEYLOGFILE` environment variable.
+    keylog: Option<PathBuf>,
+
     #[arg(name = "encoder-table-size", long, default_value = "16384")]
     max_table_size_encoder: u64,
 
@@ -74,6 +82,7 @@ impl Default for SharedArgs {
             verbose: None,
             alpn: "h3".into(),
             qlog_dir: None,
+            keylog: None,
             max_table_size_encoder: 16384,
             max_table_size_decoder: 16384,
             max_blocked_streams: 10,
@@ -89,6 +98,18 @@ impl SharedArgs {
     pub fn get_alpn(&self) -> &str {
         &self.alpn
     }
+
+    /// The key log to write TLS secrets to, if one is configured.
+    #[must_use]
+    pub fn keylog(&self) -> Option<KeyLog> {
+        let path = self
+            .keylog
+            .clone()
+            .or_else(|| env::var_os("SSLKEYLOGFILE").map(PathBuf::from))?;
+        KeyLog::open(&path)
+            .inspect_err(|e| qwarn!("Unable to open key log {}: {e}", path.display()))
+            .ok()
+    }
 }
 
 #[derive(Clone, Debug, Parser)]
@@ -290,9 +311,9 @@ mod tests {
     }
 
     impl TempDir {
-        fn new() -> Self {
+        fn new(name: &str) -> Self {
             let dir = std::env::temp_dir().join(format!(
-                "neqo-bin-test-{}",
+                "neqo-bin-{name}-{}",
                 SystemTime::now()
                     .duration_since(SystemTime::UNIX_EPOCH)
                     .unwrap()
@@ -319,7 +340,7 @@ mod tests {
     async fn write_qlog_file() {
         neqo_crypto::init_db(PathBuf::from_str("../test-fixture/db").unwrap()).unwrap();
 
-        let temp_dir = TempDir::new();
+        let temp_dir = TempDir::new("qlog");
 
         let mut client_args = client::Args::new(None, 1, 0, 1);
         client_args.set_qlog_dir(temp_dir.path());
@@ -346,4 +367,111 @@ mod tests {
             assert!(metadata.len() > 0, "expect file not be empty");
         }
     }
+
+    /// Requests are forwarded to an HTTP/1.1 backend, and responses come back.
+    #[tokio::test]
+    async fn proxy() {
+        neqo_crypto::init_db(PathBuf::from_str("../test-fixture/db").unwrap()).unwrap();
+
+        let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut server_args = server::Args::default();
+        server_args.set_hosts(vec!["[::]:0".to_string()]);
+        server_args.set_backend(backend.local_addr().unwrap());
+        let (server, local_addrs) = server::run(server_args).unwrap();
+        let client = client::client(client::Args::new(Some(local_addrs[0]), 1, 1000, 0));
+
+        let backend = async move {
+            let (tcp, _) = backend.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n0\r\n\r\n") {
+                tcp.readable().await.unwrap();
+                let mut buf = [0; 4096];
+                match tcp.try_read(&mut buf) {
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(e) => panic!("{e}"),
+                }
+            }
+            // The client expects the size of the upload in response.
+            let mut response = &b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n\
+                4\r\n1000\r\n0\r\nx-trailer: 1\r\n\r\n"[..];
+            while !response.is_empty() {
+                tcp.writable().await.unwrap();
+                if let Ok(n) = tcp.try_write(response) {
+                    response = &response[n..];
+                }
+            }
+            request
+        };
+
+        tokio::select! {
+            (res, request) = futures::future::join(client, backend) => {
+                res.unwrap();
+                let request = String::from_utf8_lossy(&request);
+                assert!(request.starts_with("POST /0 HTTP/1.1\r\nhost: "), "{request}");
+                assert!(request.contains("transfer-encoding: chunked\r\n"), "{request}");
+                assert!(request.len() > 1000);
+            }
+            res = server => panic!("expect server not to terminate: {res:?}"),
+        };
+    }
+
+    /// With `--fail`, an error status fails the client, but only after the
+    /// other URLs are fetched.
+    #[tokio::test]
+    async fn fail() {
+        neqo_crypto::init_db(PathBuf::from_str("../test-fixture/db").unwrap()).unwrap();
+
+        let root = TempDir::new("fail-root");
+        fs::write(root.path().join("a"), b"hello").unwrap();
+        let temp_dir = TempDir::new("fail");
+        let body = temp_dir.path().join("body");
+
+        let mut server_args = server::Args::default();
+        server_args.set_hosts(vec!["[::]:0".to_string()]);
+        server_args.set_root(root.path());
+        let (server, local_addrs) = server::run(server_args).unwrap();
+        let mut client_args = client::Args::new(Some(local_addrs[0]), 1, 0, 0);
+        client_args.set_paths(&["/missing", "/a"]);
+        client_args.set_fail();
+        client_args.set_output(
+            vec![temp_dir.path().join("missing"), body.clone()],
+            temp_dir.path().join("headers"),
+            "",
+        );
+        let client = client::client(client_args);
+
+        tokio::select! {
+            res = client => assert!(matches!(res, Err(client::Error::Status(404))), "{res:?}"),
+            res = server => panic!("expect server not to terminate: {res:?}"),
+        };
+        assert_eq!(fs::read(&body).unwrap(), b"hello");
+    }
+
+    /// Responses are saved where `--output` and `--dump-header` say.
+    #[tokio::test]
+    async fn output() {
+        neqo_crypto::init_db(PathBuf::from_str("../test-fixture/db").unwrap()).unwrap();
+
+        let temp_dir = TempDir::new("output");
+        let body = temp_dir.path().join("body");
+        let headers = temp_dir.path().join("headers");
+
+        let mut server_args = server::Args::default();
+        server_args.set_hosts(vec!["[::]:0".to_string()]);
+        let (server, local_addrs) = server::run(server_args).unwrap();
+        let mut client_args = client::Args::new(Some(local_addrs[0]), 1, 0, 100);
+        client_args.set_output(vec![body.clone()], headers.clone(), "%{http_code}\n");
+        let client = client::client(client_args);
+
+        tokio::select! {
+            res = client => res.unwrap(),
+            res = server => panic!("expect server not to terminate: {res:?}"),
+        };
+
+        assert_eq!(fs::read(&body).unwrap().len(), 100);
+        let headers = fs::read_to_string(&headers).unwrap();
+        assert!(headers.starts_with("HTTP/3 200\r\n"), "{headers}");
+        assert!(headers.ends_with("\r\n\r\n"), "{headers}");
+    }
 }
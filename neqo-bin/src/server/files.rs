@@ -0,0 +1,658 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serving the files in a directory.
+//!
+//! Files are read as the stream has space for them, so large files are not
+//! held in memory.  Responses carry an entity tag and modification time, and
+//! `if-none-match`, `range` and `if-range` are supported, as are HEAD
+//! requests.  When the client accepts it, a precompressed variant of a file
+//! (with a ".br" or ".gz" suffix) is served instead of the file itself.
+
+use std::{
+    fs::{self, File},
+    io::{self, ErrorKind, Read as _, Seek as _, SeekFrom},
+    path::{Component, Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use neqo_common::{Header, header::HeadersExt as _, qdebug, qwarn};
+use neqo_http3::{Error, Http3OrWebTransportStream, Http3Server, Http3ServerEvent};
+use rustc_hash::FxHashMap as HashMap;
+
+use super::Res;
+use crate::STREAM_IO_BUFFER_SIZE;
+
+/// Precompressed variants, in order of preference.
+const ENCODINGS: &[(&str, &str)] = &[("br", "br"), ("gzip", "gz")];
+
+fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match ext.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Format a time as an HTTP date, such as "Sun, 06 Nov 1994 08:49:37 GMT".
+fn http_date(t: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let days = secs / 86400;
+    let secs = secs % 86400;
+
+    // From <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 2 } else { mp - 10 };
+    let year = yoe + era * 400 + u64::from(month < 2);
+
+    let idx = |v: u64| usize::try_from(v).unwrap_or_default();
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        DAYS[idx(days % 7)],
+        MONTHS[idx(month)],
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Whether a list of entity tags, from `if-none-match`, includes `etag`.
+/// This uses the weak comparison.
+fn etag_matches(list: &[u8], etag: &str) -> bool {
+    let strip = |t: &[u8]| t.strip_prefix(b"W/").unwrap_or(t).to_vec();
+    list.split(|&b| b == b',')
+        .map(<[u8]>::trim_ascii)
+        .any(|t| t == b"*" || strip(t) == strip(etag.as_bytes()))
+}
+
+/// Whether `accept-encoding` allows `coding`.
+fn accepts(accept_encoding: &[u8], coding: &str) -> bool {
+    accept_encoding.split(|&b| b == b',').any(|item| {
+        let mut parts = item.split(|&b| b == b';').map(<[u8]>::trim_ascii);
+        let name = parts.next().unwrap_or_default();
+        let q = parts
+            .find_map(|p| p.strip_prefix(b"q="))
+            .and_then(|q| std::str::from_utf8(q).ok())
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        (name.eq_ignore_ascii_case(coding.as_bytes()) || name == b"*") && q > 0.0
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Range {
+    /// Send everything, because there was no range or it can't be used.
+    Full,
+    /// The first and last bytes to send.
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Interpret a `range` header for a representation of `len` bytes.  Only one
+/// range is supported; requests for more than one get everything.
+fn parse_range(value: &[u8], len: u64) -> Range {
+    let Some(spec) = value
+        .get(..6)
+        .filter(|unit| unit.eq_ignore_ascii_case(b"bytes="))
+        .and_then(|_| std::str::from_utf8(&value[6..]).ok())
+    else {
+        return Range::Full;
+    };
+    let Some((first, last)) = spec.trim().split_once('-').filter(|_| !spec.contains(',')) else {
+        return Range::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        return match last.parse::<u64>() {
+            Ok(0) => Range::Unsatisfiable,
+            Ok(_) if len == 0 => Range::Unsatisfiable,
+            Ok(suffix) => Range::Partial(len.saturating_sub(suffix), len - 1),
+            Err(_) => Range::Full,
+        };
+    }
+    let Ok(first) = first.parse::<u64>() else {
+        return Range::Full;
+    };
+    let last = if last.is_empty() {
+        Ok(u64::MAX)
+    } else {
+        last.parse::<u64>()
+    };
+    match last {
+        Ok(last) if last >= first => {
+            if first >= len {
+                Range::Unsatisfiable
+            } else {
+                Range::Partial(first, last.min(len - 1))
+            }
+        }
+        _ => Range::Full,
+    }
+}
+
+/// Turn the path from a request into a relative file path, or `None` if
+/// it is invalid or tries to leave the root.
+fn relative_path(path: &[u8]) -> Option<PathBuf> {
+    let end = path
+        .iter()
+        .position(|&b| b == b'?' || b == b'#')
+        .unwrap_or(path.len());
+    let mut decoded = Vec::with_capacity(end);
+    let mut bytes = path[..end].iter();
+    while let Some(&b) = bytes.next() {
+        if b == b'%' {
+            let hex = [*bytes.next()?, *bytes.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            decoded.push(b);
+        }
+    }
+    let decoded = String::from_utf8(decoded).ok()?;
+    let mut rel = PathBuf::new();
+    for part in decoded.split('/').filter(|p| !p.is_empty() && *p != ".") {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(c)), None) if !part.contains(['\\', '\0']) => rel.push(c),
+            _ => return None,
+        }
+    }
+    Some(rel)
+}
+
+/// Part of a file that is being sent.
+#[derive(Debug)]
+struct Body {
+    file: File,
+    remaining: u64,
+    buf: Vec<u8>,
+    offset: usize,
+}
+
+impl Body {
+    fn new(mut file: File, start: u64, len: u64) -> io::Result<Self> {
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            file,
+            remaining: len,
+            buf: Vec::new(),
+            offset: 0,
+        })
+    }
+
+    /// Send as much as the stream takes, returning whether everything has
+    /// been sent.
+    fn send(&mut self, stream: &Http3OrWebTransportStream, now: Instant) -> Res<bool> {
+        loop {
+            if self.offset == self.buf.len() {
+                if self.remaining == 0 {
+                    return Ok(true);
+                }
+                let n = usize::try_from(self.remaining)
+                    .unwrap_or(usize::MAX)
+                    .min(STREAM_IO_BUFFER_SIZE);
+                self.buf.resize(n, 0);
+                let n = self.file.read(&mut self.buf)?;
+                if n == 0 {
+                    return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+                }
+                self.buf.truncate(n);
+                self.offset = 0;
+                self.remaining -= n as u64;
+            }
+            let sent = stream.send_data(&self.buf[self.offset..], now)?;
+            if sent == 0 {
+                return Ok(false);
+            }
+            self.offset += sent;
+        }
+    }
+}
+
+/// What to send in response to a request.
+#[derive(Debug)]
+struct Response {
+    headers: Vec<Header>,
+    body: Option<Body>,
+}
+
+impl Response {
+    fn empty(status: &str) -> Self {
+        Self {
+            headers: vec![
+                Header::new(":status", status),
+                Header::new("content-length", "0"),
+            ],
+            body: None,
+        }
+    }
+}
+
+pub struct FileServer {
+    root: PathBuf,
+    bodies: HashMap<Http3OrWebTransportStream, Body>,
+}
+
+impl FileServer {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: fs::canonicalize(root).unwrap_or_else(|_| root.to_owned()),
+            bodies: HashMap::default(),
+        }
+    }
+
+    /// Open a regular file, following symbolic links only if they stay
+    /// inside the root.  The file is opened once, by its canonical path, and
+    /// the handle is checked, so what is checked is what gets sent.
+    fn open(&self, path: &Path) -> Option<File> {
+        let path = fs::canonicalize(path)
+            .ok()
+            .filter(|p| p.starts_with(&self.root))?;
+        let file = File::open(path).ok()?;
+        file.metadata().ok()?.is_file().then_some(file)
+    }
+
+    /// Find the file for a request path.
+    fn find(&self, path: &[u8]) -> Result<(PathBuf, File), &'static str> {
+        let rel = relative_path(path).ok_or("400")?;
+        let mut path = self.root.join(rel);
+        if path.is_dir() {
+            path.push("index.html");
+        }
+        let file = self.open(&path).ok_or("404")?;
+        Ok((path, file))
+    }
+
+    /// Choose between a file and its precompressed variants, returning the
+    /// content coding, the file to send, and whether there was a choice.
+    fn negotiate(
+        &self,
+        path: &Path,
+        file: File,
+        headers: &[Header],
+    ) -> (Option<&'static str>, File, bool) {
+        let accept_encoding = headers
+            .find_header("accept-encoding")
+            .map_or(&[][..], Header::value);
+        let mut variants = ENCODINGS
+            .iter()
+            .filter_map(|(coding, suffix)| {
+                let mut name = path.as_os_str().to_owned();
+                name.push(".");
+                name.push(suffix);
+                self.open(Path::new(&name)).map(|f| (*coding, f))
+            })
+            .collect::<Vec<_>>();
+        let vary = !variants.is_empty();
+        variants
+            .iter()
+            .position(|(coding, _)| accepts(accept_encoding, coding))
+            .map_or((None, file, vary), |i| {
+                let (coding, variant) = variants.swap_remove(i);
+                (Some(coding), variant, vary)
+            })
+    }
+
+    fn response(&self, headers: &[Header]) -> io::Result<Response> {
+        let head = headers.contains_header(":method", "HEAD");
+        if !head && !headers.contains_header(":method", "GET") {
+            let mut response = Response::empty("405");
+            response.headers.push(Header::new("allow", "GET, HEAD"));
+            return Ok(response);
+        }
+        let path = headers
+            .find_header(":path")
+            .map_or(&b"/"[..], Header::value);
+        let (path, file) = match self.find(path) {
+            Ok(found) => found,
+            Err(status) => return Ok(Response::empty(status)),
+        };
+
+        let (encoding, file, vary) = self.negotiate(&path, file, headers);
+        let meta = file.metadata()?;
+        let len = meta.len();
+        let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+        let mtime = modified
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let etag = encoding.map_or_else(
+            || format!("\"{len:x}-{mtime:x}\""),
+            |coding| format!("\"{len:x}-{mtime:x}-{coding}\""),
+        );
+        let last_modified = http_date(modified);
+
+        let mut validators = vec![
+            Header::new("etag", etag.clone()),
+            Header::new("last-modified", last_modified.clone()),
+        ];
+        if vary {
+            validators.push(Header::new("vary", "accept-encoding"));
+        }
+
+        if headers
+            .find_header("if-none-match")
+            .is_some_and(|h| etag_matches(h.value(), &etag))
+        {
+            let mut headers = vec![Header::new(":status", "304")];
+            headers.append(&mut validators);
+            return Ok(Response {
+                headers,
+                body: None,
+            });
+        }
+
+        // `if-range` needs a strong match, or the exact modification time.
+        let range = headers
+            .find_header("range")
+            .filter(|_| !head)
+            .filter(|_| {
+                headers.find_header("if-range").is_none_or(|h| {
+                    h.value() == etag.as_bytes() || h.value() == last_modified.as_bytes()
+                })
+            })
+            .map_or(Range::Full, |h| parse_range(h.value(), len));
+
+        let (status, start, count) = match range {
+            Range::Full => ("200", 0, len),
+            Range::Partial(first, last) => ("206", first, last - first + 1),
+            Range::Unsatisfiable => {
+                let mut response = Response::empty("416");
+                response
+                    .headers
+                    .push(Header::new("content-range", format!("bytes */{len}")));
+                return Ok(response);
+            }
+        };
+        let mut headers = vec![
+            Header::new(":status", status),
+            Header::new("content-type", content_type(&path)),
+            Header::new("content-length", count.to_string()),
+        ];
+        if let Some(coding) = encoding {
+            headers.push(Header::new("content-encoding", coding));
+        }
+        if let Range::Partial(first, last) = range {
+            headers.push(Header::new(
+                "content-range",
+                format!("bytes {first}-{last}/{len}"),
+            ));
+        }
+        headers.push(Header::new("accept-ranges", "bytes"));
+        headers.append(&mut validators);
+
+        let body = if head {
+            None
+        } else {
+            Some(Body::new(file, start, count)?)
+        };
+        Ok(Response { headers, body })
+    }
+
+    fn respond(
+        &mut self,
+        stream: Http3OrWebTransportStream,
+        headers: &[Header],
+        fin: bool,
+        now: Instant,
+    ) -> Res<()> {
+        let response = self.response(headers).unwrap_or_else(|e| {
+            qwarn!("Unable to serve {headers:?}: {e}");
+            Response::empty("500")
+        });
+        qdebug!("Response on {stream}: {:?}", response.headers);
+        if !fin {
+            // Nothing that follows the request header section is used.
+            stream.stream_stop_sending(Error::HttpNone.code())?;
+        }
+        stream.send_headers(&response.headers)?;
+        if let Some(mut body) = response.body
+            && !body.send(&stream, now)?
+        {
+            self.bodies.insert(stream, body);
+            return Ok(());
+        }
+        stream.stream_close_send(now)?;
+        Ok(())
+    }
+
+    fn send(&mut self, stream: &Http3OrWebTransportStream, now: Instant) -> Res<()> {
+        if let Some(body) = self.bodies.get_mut(stream)
+            && body.send(stream, now)?
+        {
+            self.bodies.remove(stream);
+            stream.stream_close_send(now)?;
+        }
+        Ok(())
+    }
+
+    pub fn process_events(&mut self, server: &Http3Server, now: Instant) {
+        while let Some(event) = server.next_event() {
+            let res = match event {
+                Http3ServerEvent::Headers {
+                    stream,
+                    headers,
+                    fin,
+                } => self.respond(stream, &headers, fin, now),
+                Http3ServerEvent::DataWritable { stream } => {
+                    self.send(&stream, now).inspect_err(|_| {
+                        self.bodies.remove(&stream);
+                        if let Err(e) = stream.stream_reset_send(Error::HttpInternal(0).code()) {
+                            qdebug!("Unable to reset {stream}: {e}");
+                        }
+                    })
+                }
+                Http3ServerEvent::StreamReset { stream, .. }
+                | Http3ServerEvent::StreamStopSending { stream, .. } => {
+                    self.bodies.remove(&stream);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = res {
+                qwarn!("Unable to send response: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use neqo_common::{Header, header::HeadersExt as _};
+
+    use super::{FileServer, Range, accepts, etag_matches, http_date, parse_range, relative_path};
+
+    #[test]
+    fn date() {
+        let t = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(http_date(t), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        let t = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(http_date(t), "Tue, 29 Feb 2000 00:00:00 GMT");
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range(b"bytes=0-9", 100), Range::Partial(0, 9));
+        assert_eq!(parse_range(b"bytes=90-", 100), Range::Partial(90, 99));
+        assert_eq!(parse_range(b"bytes=90-200", 100), Range::Partial(90, 99));
+        assert_eq!(parse_range(b"bytes=-10", 100), Range::Partial(90, 99));
+        assert_eq!(parse_range(b"bytes=-200", 100), Range::Partial(0, 99));
+        assert_eq!(parse_range(b"bytes=100-", 100), Range::Unsatisfiable);
+        assert_eq!(parse_range(b"bytes=-0", 100), Range::Unsatisfiable);
+        assert_eq!(parse_range(b"bytes=0-1,5-6", 100), Range::Full);
+        assert_eq!(parse_range(b"bytes=5-1", 100), Range::Full);
+        assert_eq!(parse_range(b"items=0-1", 100), Range::Full);
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(relative_path(b"/"), Some(PathBuf::new()));
+        assert_eq!(
+            relative_path(b"/a/./b%20c?x=1"),
+            Some(PathBuf::from("a").join("b c"))
+        );
+        assert_eq!(relative_path(b"/../etc/passwd"), None);
+        assert_eq!(relative_path(b"/a/%2e%2e/%2e%2e/x"), None);
+        assert_eq!(relative_path(b"/a%2"), None);
+    }
+
+    #[test]
+    fn validators() {
+        assert!(etag_matches(b"\"a\", W/\"b\"", "\"b\""));
+        assert!(etag_matches(b"*", "\"b\""));
+        assert!(!etag_matches(b"\"a\"", "\"b\""));
+        assert!(accepts(b"gzip, br;q=0.5", "br"));
+        assert!(!accepts(b"gzip, br;q=0", "br"));
+        assert!(accepts(b"*", "gzip"));
+        assert!(!accepts(b"", "gzip"));
+    }
+
+    fn status(headers: &[Header]) -> &str {
+        headers
+            .find_header(":status")
+            .unwrap()
+            .value_utf8()
+            .unwrap()
+    }
+
+    #[test]
+    fn responses() {
+        let root = std::env::temp_dir().join(format!("neqo-bin-files-{}", std::process::id()));
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("dir/index.html"), b"<p>hello</p>").unwrap();
+        fs::write(root.join("dir/index.html.gz"), b"gzipped").unwrap();
+        let server = FileServer::new(&root);
+        let request = |extra: &[Header]| {
+            let mut headers = vec![Header::new(":method", "GET"), Header::new(":path", "/dir/")];
+            headers.extend_from_slice(extra);
+            server.response(&headers).unwrap()
+        };
+
+        let full = request(&[]);
+        assert_eq!(status(&full.headers), "200");
+        assert!(full.headers.iter().contains_header("content-length", "12"));
+        assert!(
+            full.headers
+                .iter()
+                .contains_header("content-type", "text/html; charset=utf-8")
+        );
+        assert!(
+            full.headers
+                .iter()
+                .contains_header("vary", "accept-encoding")
+        );
+        assert_eq!(full.body.unwrap().remaining, 12);
+        let etag = full.headers.iter().find_header("etag").unwrap().clone();
+
+        let gzip = request(&[Header::new("accept-encoding", "gzip")]);
+        assert!(
+            gzip.headers
+                .iter()
+                .contains_header("content-encoding", "gzip")
+        );
+        assert!(gzip.headers.iter().contains_header("content-length", "7"));
+        assert_ne!(gzip.headers.iter().find_header("etag"), Some(&etag));
+
+        let cached = request(&[Header::new("if-none-match", etag.value())]);
+        assert_eq!(status(&cached.headers), "304");
+        assert!(cached.body.is_none());
+
+        let partial = request(&[Header::new("range", "bytes=3-7")]);
+        assert_eq!(status(&partial.headers), "206");
+        assert!(
+            partial
+                .headers
+                .iter()
+                .contains_header("content-range", "bytes 3-7/12")
+        );
+        assert_eq!(partial.body.unwrap().remaining, 5);
+
+        let stale = request(&[
+            Header::new("range", "bytes=3-7"),
+            Header::new("if-range", "\"stale\""),
+        ]);
+        assert_eq!(status(&stale.headers), "200");
+
+        let unsatisfiable = request(&[Header::new("range", "bytes=20-")]);
+        assert_eq!(status(&unsatisfiable.headers), "416");
+
+        let head = server
+            .response(&[
+                Header::new(":method", "HEAD"),
+                Header::new(":path", "/dir/index.html"),
+            ])
+            .unwrap();
+        assert!(head.headers.iter().contains_header("content-length", "12"));
+        assert!(head.body.is_none());
+
+        // A variant that leads outside the root is not used.
+        #[cfg(unix)]
+        {
+            let outside = root.with_extension("outside");
+            fs::write(&outside, b"secret").unwrap();
+            std::os::unix::fs::symlink(&outside, root.join("dir/index.html.br")).unwrap();
+            let br = request(&[Header::new("accept-encoding", "br, gzip")]);
+            assert!(
+                br.headers
+                    .iter()
+                    .contains_header("content-encoding", "gzip")
+            );
+            fs::remove_file(&outside).unwrap();
+        }
+
+        for (method, path, expected) in [
+            ("GET", "/missing", "404"),
+            ("GET", "/../x", "400"),
+            ("POST", "/dir/", "405"),
+        ] {
+            let response = server
+                .response(&[Header::new(":method", method), Header::new(":path", path)])
+                .unwrap();
+            assert_eq!(status(&response.headers), expected);
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
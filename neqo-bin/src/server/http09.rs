@@ -60,6 +60,7 @@ impl HttpServer {
 
         server.set_ciphers(args.get_ciphers());
         server.set_qlog_dir(args.shared.qlog_dir.clone());
+        server.set_keylog(args.shared.keylog());
         if args.retry {
             server.set_validation(ValidateAddress::Always);
         }
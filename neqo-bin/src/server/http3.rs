@@ -10,8 +10,10 @@ use std::{
     cell::RefCell,
     fmt::{self, Display},
     num::NonZeroUsize,
+    pin::Pin,
     rc::Rc,
     slice,
+    task::{Context, Poll},
     time::Instant,
 };
 
@@ -23,7 +25,7 @@ use neqo_http3::{
 use neqo_transport::{ConnectionIdGenerator, OutputBatch, server::ValidateAddress};
 use rustc_hash::FxHashMap as HashMap;
 
-use super::{Args, qns_read_response};
+use super::{Args, files::FileServer, proxy::Proxy, qns_read_response};
 use crate::send_data::SendData;
 
 pub struct HttpServer {
@@ -33,6 +35,40 @@ pub struct HttpServer {
     /// Tracks POST requests: (bytes received, optional response size from path)
     posts: HashMap<Http3OrWebTransportStream, (usize, Option<usize>)>,
     is_qns_test: bool,
+    /// Handles requests instead of generating responses, if configured.
+    handler: Option<Handler>,
+}
+
+/// Alternatives to generating responses.
+enum Handler {
+    Proxy(Proxy),
+    Files(FileServer),
+}
+
+impl Handler {
+    fn new(args: &Args) -> Option<Self> {
+        args.backend
+            .map(|backend| Self::Proxy(Proxy::new(backend)))
+            .or_else(|| {
+                args.root
+                    .as_deref()
+                    .map(|root| Self::Files(FileServer::new(root)))
+            })
+    }
+
+    fn process_events(&mut self, server: &Http3Server, now: Instant) {
+        match self {
+            Self::Proxy(proxy) => proxy.process_events(server, now),
+            Self::Files(files) => files.process_events(server, now),
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match self {
+            Self::Proxy(proxy) => proxy.poll(cx),
+            Self::Files(_) => Poll::Pending,
+        }
+    }
 }
 
 impl HttpServer {
@@ -58,6 +94,7 @@ impl HttpServer {
 
         server.set_ciphers(args.get_ciphers());
         server.set_qlog_dir(args.shared.qlog_dir.clone());
+        server.set_keylog(args.shared.keylog());
         if args.retry {
             server.set_validation(ValidateAddress::Always);
         }
@@ -73,6 +110,7 @@ impl HttpServer {
             remaining_data: HashMap::default(),
             posts: HashMap::default(),
             is_qns_test: args.shared.qns_test.is_some(),
+            handler: Handler::new(args),
         }
     }
 }
@@ -95,6 +133,9 @@ impl super::HttpServer for HttpServer {
 
     fn process_events(&mut self, _now: Instant) {
         let now = Instant::now();
+        if let Some(handler) = &mut self.handler {
+            return handler.process_events(&self.server, now);
+        }
         while let Some(event) = self.server.next_event() {
             match event {
                 Http3ServerEvent::Headers {
@@ -202,4 +243,11 @@ impl super::HttpServer for HttpServer {
     fn has_events(&self) -> bool {
         self.server.has_events()
     }
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.get_mut()
+            .handler
+            .as_mut()
+            .map_or(Poll::Pending, |handler| handler.poll(cx))
+    }
 }
@@ -48,8 +48,10 @@ use crate::SharedArgs;
 
 const ANTI_REPLAY_WINDOW: Duration = Duration::from_secs(10);
 
+mod files;
 pub mod http09;
 pub mod http3;
+mod proxy;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -96,6 +98,15 @@ pub struct Args {
     /// This generates a new set of ECH keys when it is invoked.
     /// The resulting configuration is printed to stdout in hexadecimal format.
     ech: bool,
+
+    #[arg(name = "backend", long, conflicts_with = "root")]
+    /// Forward HTTP/3 requests to the HTTP/1.1 server at this address, rather
+    /// than generating responses.
+    backend: Option<SocketAddr>,
+
+    #[arg(name = "root", long)]
+    /// Serve the files in this directory, rather than generating responses.
+    root: Option<PathBuf>,
 }
 
 #[cfg(any(test, feature = "bench"))]
@@ -109,6 +120,8 @@ impl Default for Args {
             key: "key".to_string(),
             retry: false,
             ech: false,
+            backend: None,
+            root: None,
         }
     }
 }
@@ -166,6 +179,16 @@ impl Args {
         self.shared.qlog_dir = Some(dir);
     }
 
+    #[cfg(test)]
+    pub fn set_root(&mut self, root: PathBuf) {
+        self.root = Some(root);
+    }
+
+    #[cfg(test)]
+    pub const fn set_backend(&mut self, backend: SocketAddr) {
+        self.backend = Some(backend);
+    }
+
     pub fn set_hosts(&mut self, hosts: Vec<String>) {
         self.hosts = hosts;
     }
@@ -460,6 +483,18 @@ pub fn run(
     args.update_for_tests();
     assert!(!args.key.is_empty(), "Need at least one key");
 
+    if args.backend.is_some() && args.shared.alpn != "h3" {
+        return Err(Error::Argument("--backend requires HTTP/3"));
+    }
+    if let Some(root) = &args.root {
+        if args.shared.alpn != "h3" {
+            return Err(Error::Argument("--root requires HTTP/3"));
+        }
+        if !root.is_dir() {
+            return Err(Error::Argument("--root needs to be a directory"));
+        }
+    }
+
     init_db(args.db.clone())?;
 
     let hosts = args.listen_addresses();
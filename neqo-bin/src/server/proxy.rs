@@ -0,0 +1,1163 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A reverse proxy that forwards HTTP/3 requests to an HTTP/1.1 server.
+//!
+//! Each request gets its own TCP connection to the backend, which is closed
+//! once the response is complete.  Responses are read from the backend only as
+//! fast as the HTTP/3 stream takes them, so a slow client slows the backend
+//! down.  Likewise, reading a request stops while too much of its body is
+//! waiting for the backend, so a slow backend slows the client down.
+//!
+//! Request bodies are always forwarded with the chunked transfer coding, and
+//! any content-length from the client is dropped.  That way, a body that
+//! doesn't match its content-length can't be misread by the backend.
+//!
+//! Trailers are forwarded in both directions.
+
+use std::{
+    io::{self, ErrorKind},
+    mem,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use neqo_common::{Header, header::HeadersExt as _, qdebug, qinfo, qwarn};
+use neqo_http3::{Error, Http3OrWebTransportStream, Http3Server, Http3ServerEvent};
+use rustc_hash::FxHashMap as HashMap;
+use tokio::net::TcpStream;
+
+use super::Res;
+
+/// Stop reading from the backend when this much of a response is waiting for
+/// the HTTP/3 stream, and from the HTTP/3 stream when this much of a request
+/// is waiting for the backend.
+const MAX_BUFFERED: usize = 64 * 1024;
+/// How much to read from the backend at a time.
+const READ_SIZE: usize = 16 * 1024;
+/// The longest response header section, or chunk header, that is accepted.
+const MAX_HEAD: usize = 64 * 1024;
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn find(buf: &[u8], needle: &[u8]) -> Option<usize> {
+    buf.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Whether a field can be written to an HTTP/1.1 message without changing
+/// the meaning of the message.
+fn field_ok(h: &Header) -> bool {
+    !h.name().is_empty()
+        && h.name()
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && b != b':' && !b.is_ascii_uppercase())
+        && !h
+            .value()
+            .iter()
+            .any(|&b| b == b'\r' || b == b'\n' || b == 0)
+}
+
+/// Produce the HTTP/1.1 request header section for a request, and whether the
+/// body needs to use the chunked transfer coding.
+fn request_head(headers: &[Header], fin: bool) -> Result<(Vec<u8>, bool), &'static str> {
+    let pseudo = |name| {
+        headers
+            .find_header(name)
+            .map(Header::value)
+            .ok_or("missing pseudo-header")
+    };
+    let method = pseudo(":method")?;
+    let path = pseudo(":path")?;
+    let authority = pseudo(":authority")
+        .or_else(|_| headers.find_header("host").map(Header::value).ok_or(""))
+        .map_err(|_| "missing authority")?;
+    if [method, path, authority]
+        .iter()
+        .any(|v| v.is_empty() || v.iter().any(|b| !b.is_ascii_graphic()))
+    {
+        return Err("invalid pseudo-header");
+    }
+
+    let mut head = Vec::new();
+    head.extend_from_slice(method);
+    head.push(b' ');
+    head.extend_from_slice(path);
+    head.extend_from_slice(b" HTTP/1.1\r\nhost: ");
+    head.extend_from_slice(authority);
+    head.extend_from_slice(b"\r\n");
+
+    let mut cookies = Vec::new();
+    let mut te = false;
+    for h in headers.iter().filter(|h| !h.name().starts_with(':')) {
+        if !field_ok(h) {
+            return Err("invalid field");
+        }
+        match h.name() {
+            // HTTP/3 allows cookies to be split across fields, HTTP/1.1 doesn't.
+            "cookie" => cookies.push(h.value()),
+            "te" => te = true,
+            // The body is framed with the chunked transfer coding instead.
+            "content-length" => {}
+            _ if h.is_allowed_for_response() => {
+                head.extend_from_slice(h.name().as_bytes());
+                head.extend_from_slice(b": ");
+                head.extend_from_slice(h.value());
+                head.extend_from_slice(b"\r\n");
+            }
+            _ => {}
+        }
+    }
+    if !cookies.is_empty() {
+        head.extend_from_slice(b"cookie: ");
+        head.extend_from_slice(&cookies.join(&b"; "[..]));
+        head.extend_from_slice(b"\r\n");
+    }
+    // HTTP/3 only allows "te: trailers".
+    if te {
+        head.extend_from_slice(b"te: trailers\r\nconnection: te, close\r\n");
+    } else {
+        head.extend_from_slice(b"connection: close\r\n");
+    }
+    let chunked = !fin;
+    if chunked {
+        head.extend_from_slice(b"transfer-encoding: chunked\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+    Ok((head, chunked))
+}
+
+/// Split a field line into a header.
+fn parse_field(line: &[u8]) -> io::Result<Header> {
+    let colon = line
+        .iter()
+        .position(|&b| b == b':')
+        .ok_or_else(|| invalid("field without a colon"))?;
+    let name = std::str::from_utf8(&line[..colon])
+        .map_err(|_| invalid("invalid field name"))?
+        .to_ascii_lowercase();
+    let value = line[colon + 1..].trim_ascii();
+    let h = Header::new(name, value);
+    if field_ok(&h) {
+        Ok(h)
+    } else {
+        Err(invalid("invalid field"))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Message {
+    /// The response header section, starting with ":status".
+    Head(Vec<Header>),
+    Data(Vec<u8>),
+    Trailers(Vec<Header>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Head,
+    Length(usize),
+    UntilEof,
+    ChunkSize,
+    ChunkData(usize),
+    ChunkEnd,
+    Trailers,
+    Done,
+}
+
+/// An incremental parser for HTTP/1.1 responses.
+#[derive(Debug)]
+struct ResponseParser {
+    state: State,
+    buf: Vec<u8>,
+    /// The response is to a HEAD request, so it has no content.
+    head: bool,
+    trailers: Vec<Header>,
+}
+
+impl ResponseParser {
+    const fn new(head: bool) -> Self {
+        Self {
+            state: State::Head,
+            buf: Vec::new(),
+            head,
+            trailers: Vec::new(),
+        }
+    }
+
+    fn input(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// The backend closed the connection.
+    fn end(&mut self) -> io::Result<()> {
+        match self.state {
+            State::UntilEof | State::Done => {
+                self.state = State::Done;
+                Ok(())
+            }
+            _ => Err(invalid("response truncated")),
+        }
+    }
+
+    fn line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if let Some(end) = find(&self.buf, b"\r\n") {
+            let mut line = self.buf.drain(..end + 2).collect::<Vec<_>>();
+            line.truncate(end);
+            Ok(Some(line))
+        } else if self.buf.len() > MAX_HEAD {
+            Err(invalid("line too long"))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn data(&mut self, remaining: usize, next: impl FnOnce(usize) -> State) -> Option<Message> {
+        let n = remaining.min(self.buf.len());
+        self.state = next(remaining - n);
+        (n > 0).then(|| Message::Data(self.buf.drain(..n).collect()))
+    }
+
+    fn parse_head(&mut self, head: &[u8]) -> io::Result<Option<Vec<Header>>> {
+        let mut lines = head.split(|&b| b == b'\n').map(<[u8]>::trim_ascii_end);
+        let status_line = lines.next().unwrap_or_default();
+        let mut parts = status_line.split(|&b| b == b' ');
+        if !parts.next().is_some_and(|v| v.starts_with(b"HTTP/1.")) {
+            return Err(invalid("invalid status line"));
+        }
+        let status = parts
+            .next()
+            .and_then(|s| std::str::from_utf8(s).ok())
+            .and_then(|s| s.parse::<u16>().ok())
+            .filter(|s| (100..600).contains(s))
+            .ok_or_else(|| invalid("invalid status code"))?;
+        if status == 101 {
+            return Err(invalid("upgrade not supported"));
+        }
+        if status < 200 {
+            return Ok(None);
+        }
+
+        let mut fields = Vec::new();
+        for line in lines {
+            if line.starts_with(b" ") || line.starts_with(b"\t") {
+                return Err(invalid("obsolete line folding"));
+            }
+            fields.push(parse_field(line)?);
+        }
+
+        let chunked = fields
+            .iter()
+            .rfind(|h| h.name() == "transfer-encoding")
+            .map(|h| {
+                h.value()
+                    .rsplit(|&b| b == b',')
+                    .next()
+                    .is_some_and(|c| c.trim_ascii().eq_ignore_ascii_case(b"chunked"))
+            });
+        let length = fields
+            .iter()
+            .find(|h| h.name() == "content-length")
+            .map(|h| {
+                h.value_utf8()
+                    .ok()
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .ok_or_else(|| invalid("invalid content-length"))
+            })
+            .transpose()?;
+        self.state = if self.head || status == 204 || status == 304 {
+            State::Done
+        } else {
+            match (chunked, length) {
+                (Some(true), _) => State::ChunkSize,
+                (Some(false), _) | (None, None) => State::UntilEof,
+                (None, Some(length)) => State::Length(length),
+            }
+        };
+
+        // Drop fields that only apply to the connection to the backend.
+        let connection = fields
+            .iter()
+            .filter(|h| h.name() == "connection")
+            .flat_map(|h| h.value().split(|&b| b == b','))
+            .map(|v| String::from_utf8_lossy(v.trim_ascii()).to_ascii_lowercase())
+            .collect::<Vec<_>>();
+        let mut headers = vec![Header::new(":status", status.to_string())];
+        headers.extend(fields.into_iter().filter(|h| {
+            h.is_allowed_for_response()
+                && !connection.iter().any(|c| c == h.name())
+                && !(chunked.is_some() && h.name() == "content-length")
+        }));
+        Ok(Some(headers))
+    }
+
+    /// The next part of the response, if enough has been received.
+    fn next(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            match self.state {
+                State::Head => {
+                    let Some(end) = find(&self.buf, b"\r\n\r\n") else {
+                        if self.buf.len() > MAX_HEAD {
+                            return Err(invalid("header section too long"));
+                        }
+                        return Ok(None);
+                    };
+                    let head = self.buf.drain(..end + 4).collect::<Vec<_>>();
+                    if let Some(headers) = self.parse_head(&head[..end])? {
+                        return Ok(Some(Message::Head(headers)));
+                    }
+                }
+                State::Length(0) => self.state = State::Done,
+                State::Length(remaining) => {
+                    return Ok(self.data(remaining, State::Length));
+                }
+                State::UntilEof => {
+                    return Ok(
+                        (!self.buf.is_empty()).then(|| Message::Data(mem::take(&mut self.buf)))
+                    );
+                }
+                State::ChunkSize => {
+                    let Some(line) = self.line()? else {
+                        return Ok(None);
+                    };
+                    let size = line.split(|&b| b == b';').next().unwrap_or_default();
+                    let size = std::str::from_utf8(size.trim_ascii())
+                        .ok()
+                        .and_then(|s| usize::from_str_radix(s, 16).ok())
+                        .ok_or_else(|| invalid("invalid chunk size"))?;
+                    self.state = if size == 0 {
+                        State::Trailers
+                    } else {
+                        State::ChunkData(size)
+                    };
+                }
+                State::ChunkData(0) => self.state = State::ChunkEnd,
+                State::ChunkData(remaining) => {
+                    return Ok(self.data(remaining, State::ChunkData));
+                }
+                State::ChunkEnd => {
+                    let Some(line) = self.line()? else {
+                        return Ok(None);
+                    };
+                    if !line.is_empty() {
+                        return Err(invalid("chunk too long"));
+                    }
+                    self.state = State::ChunkSize;
+                }
+                State::Trailers => {
+                    let Some(line) = self.line()? else {
+                        return Ok(None);
+                    };
+                    if line.is_empty() {
+                        self.state = State::Done;
+                        let trailers = mem::take(&mut self.trailers);
+                        if !trailers.is_empty() {
+                            return Ok(Some(Message::Trailers(trailers)));
+                        }
+                    } else {
+                        let h = parse_field(&line)?;
+                        if h.is_allowed_for_response() && h.name() != "content-length" {
+                            self.trailers.push(h);
+                        }
+                    }
+                }
+                State::Done => return Ok(None),
+            }
+        }
+    }
+}
+
+enum Backend {
+    Connecting(Pin<Box<dyn Future<Output = io::Result<TcpStream>>>>),
+    Connected(TcpStream),
+}
+
+/// A request that is being forwarded, and its response.
+#[expect(clippy::struct_excessive_bools, reason = "These are independent.")]
+struct Exchange {
+    backend: Backend,
+    /// Request bytes that are waiting to be written to the backend.
+    request: Vec<u8>,
+    /// Whether the request body uses the chunked transfer coding.
+    chunked: bool,
+    /// Whether all of the request has been added to `request`.
+    request_done: bool,
+    /// Whether reading from the HTTP/3 stream has been paused.
+    paused: bool,
+    /// Request trailers, which are written after the last chunk.
+    request_trailers: Vec<Header>,
+    response: ResponseParser,
+    /// Response content that is waiting for the HTTP/3 stream.
+    body: Vec<u8>,
+    trailers: Option<Vec<Header>>,
+    headers_sent: bool,
+    finished: bool,
+}
+
+impl Exchange {
+    fn request_data(&mut self, stream: &Http3OrWebTransportStream, data: &[u8], fin: bool) {
+        if self.chunked && !data.is_empty() {
+            self.request
+                .extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+            self.request.extend_from_slice(data);
+            self.request.extend_from_slice(b"\r\n");
+        } else {
+            self.request.extend_from_slice(data);
+        }
+        if fin {
+            if self.chunked {
+                self.request.extend_from_slice(b"0\r\n");
+                for h in mem::take(&mut self.request_trailers) {
+                    self.request.extend_from_slice(h.name().as_bytes());
+                    self.request.extend_from_slice(b": ");
+                    self.request.extend_from_slice(h.value());
+                    self.request.extend_from_slice(b"\r\n");
+                }
+                self.request.extend_from_slice(b"\r\n");
+            }
+            self.request_done = true;
+        } else if !self.paused && self.request.len() >= MAX_BUFFERED {
+            stream.pause_reading();
+            self.paused = true;
+        }
+    }
+
+    fn request_trailers(&mut self, trailers: Vec<Header>) {
+        self.request_trailers = trailers
+            .into_iter()
+            .filter(|h| field_ok(h) && h.is_allowed_for_response() && h.name() != "content-length")
+            .collect();
+    }
+
+    /// Move data between the backend and the stream, returning whether
+    /// anything happened.
+    fn drive(
+        &mut self,
+        stream: &Http3OrWebTransportStream,
+        cx: &mut Context<'_>,
+        now: Instant,
+    ) -> Res<bool> {
+        let mut progress = false;
+        if let Backend::Connecting(connect) = &mut self.backend {
+            match connect.as_mut().poll(cx) {
+                Poll::Pending => return Ok(false),
+                Poll::Ready(tcp) => {
+                    self.backend = Backend::Connected(tcp?);
+                    progress = true;
+                }
+            }
+        }
+        let Backend::Connected(tcp) = &self.backend else {
+            unreachable!("connected above");
+        };
+
+        while !self.request.is_empty() {
+            if let Poll::Ready(res) = tcp.poll_write_ready(cx) {
+                res?;
+            } else {
+                break;
+            }
+            match tcp.try_write(&self.request) {
+                Ok(n) => {
+                    self.request.drain(..n);
+                    progress = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if self.paused && self.request.len() < MAX_BUFFERED {
+            stream.resume_reading();
+            self.paused = false;
+            progress = true;
+        }
+
+        let mut buf = [0; READ_SIZE];
+        while !self.response.is_done() && self.body.len() < MAX_BUFFERED {
+            if let Poll::Ready(res) = tcp.poll_read_ready(cx) {
+                res?;
+            } else {
+                break;
+            }
+            match tcp.try_read(&mut buf) {
+                Ok(0) => {
+                    self.response.end()?;
+                    progress = true;
+                }
+                Ok(n) => {
+                    self.response.input(&buf[..n]);
+                    progress = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e.into()),
+            }
+            while let Some(msg) = self.response.next()? {
+                match msg {
+                    Message::Head(headers) => {
+                        stream.send_headers(&headers)?;
+                        self.headers_sent = true;
+                    }
+                    Message::Data(data) => self.body.extend_from_slice(&data),
+                    Message::Trailers(trailers) => self.trailers = Some(trailers),
+                }
+            }
+        }
+
+        while !self.body.is_empty() {
+            let n = stream.send_data(&self.body, now)?;
+            if n == 0 {
+                break;
+            }
+            self.body.drain(..n);
+            progress = true;
+        }
+        if self.body.is_empty() && self.response.is_done() {
+            if let Some(trailers) = self.trailers.take() {
+                stream.send_headers(&trailers)?;
+            }
+            self.close(stream, now)?;
+            progress = true;
+        }
+        Ok(progress)
+    }
+
+    fn close(&mut self, stream: &Http3OrWebTransportStream, now: Instant) -> Res<()> {
+        self.finished = true;
+        stream.stream_close_send(now)?;
+        if !self.request_done {
+            // The response is complete, so the rest of the request isn't needed.
+            stream.stream_stop_sending(Error::HttpNone.code())?;
+        }
+        Ok(())
+    }
+
+    /// Tell the client that the request failed.
+    fn fail(&mut self, stream: &Http3OrWebTransportStream, now: Instant) {
+        let res = if self.headers_sent {
+            self.finished = true;
+            stream
+                .stream_reset_send(Error::HttpInternal(0).code())
+                .map_err(Into::into)
+        } else {
+            stream
+                .send_headers(&[
+                    Header::new(":status", "502"),
+                    Header::new("content-length", "0"),
+                ])
+                .map_err(Into::into)
+                .and_then(|()| self.close(stream, now))
+        };
+        if let Err(e) = res {
+            qdebug!("Unable to report failure on {stream}: {e}");
+        }
+    }
+}
+
+/// Send a response without content to a request that won't be forwarded.
+fn respond(stream: &Http3OrWebTransportStream, status: &str, fin: bool, now: Instant) {
+    let res = stream
+        .send_headers(&[
+            Header::new(":status", status),
+            Header::new("content-length", "0"),
+        ])
+        .and_then(|()| stream.stream_close_send(now))
+        .and_then(|()| {
+            if fin {
+                Ok(())
+            } else {
+                stream.stream_stop_sending(Error::HttpNone.code())
+            }
+        });
+    if let Err(e) = res {
+        qdebug!("Unable to respond on {stream}: {e}");
+    }
+}
+
+pub struct Proxy {
+    backend: SocketAddr,
+    exchanges: HashMap<Http3OrWebTransportStream, Exchange>,
+}
+
+impl Proxy {
+    pub fn new(backend: SocketAddr) -> Self {
+        Self {
+            backend,
+            exchanges: HashMap::default(),
+        }
+    }
+
+    pub fn process_events(&mut self, server: &Http3Server, now: Instant) {
+        while let Some(event) = server.next_event() {
+            self.process_event(event, now);
+        }
+    }
+
+    fn process_event(&mut self, event: Http3ServerEvent, now: Instant) {
+        match event {
+            Http3ServerEvent::Headers {
+                stream,
+                headers,
+                fin,
+            } => self.request(stream, &headers, fin, now),
+            Http3ServerEvent::Data { stream, data, fin } => {
+                if let Some(exchange) = self.exchanges.get_mut(&stream) {
+                    exchange.request_data(&stream, &data, fin);
+                }
+            }
+            Http3ServerEvent::Trailers { stream, headers } => {
+                if let Some(exchange) = self.exchanges.get_mut(&stream) {
+                    exchange.request_trailers(headers);
+                }
+            }
+            Http3ServerEvent::StreamReset { stream, error }
+            | Http3ServerEvent::StreamStopSending { stream, error } => {
+                qdebug!("Client abandoned request on {stream} with error {error}");
+                self.exchanges.remove(&stream);
+            }
+            _ => {}
+        }
+    }
+
+    fn request(
+        &mut self,
+        stream: Http3OrWebTransportStream,
+        headers: &[Header],
+        fin: bool,
+        now: Instant,
+    ) {
+        if headers.contains_header(":method", "CONNECT") {
+            respond(&stream, "501", fin, now);
+            return;
+        }
+        let (head, chunked) = match request_head(headers, fin) {
+            Ok(head) => head,
+            Err(e) => {
+                qinfo!("Not forwarding request on {stream}: {e}");
+                respond(&stream, "400", fin, now);
+                return;
+            }
+        };
+        qdebug!("Forwarding request on {stream} to {}", self.backend);
+        let mut exchange = Exchange {
+            backend: Backend::Connecting(Box::pin(TcpStream::connect(self.backend))),
+            request: head,
+            chunked,
+            request_done: false,
+            paused: false,
+            request_trailers: Vec::new(),
+            response: ResponseParser::new(headers.contains_header(":method", "HEAD")),
+            body: Vec::new(),
+            trailers: None,
+            headers_sent: false,
+            finished: false,
+        };
+        exchange.request_data(&stream, &[], fin);
+        self.exchanges.insert(stream, exchange);
+    }
+
+    /// Drive the connections to the backend.  This is ready when something
+    /// happened, so that any output is sent.
+    pub fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let now = Instant::now();
+        let mut progress = false;
+        self.exchanges
+            .retain(|stream, exchange| match exchange.drive(stream, cx, now) {
+                Ok(p) => {
+                    progress |= p;
+                    !exchange.finished
+                }
+                Err(e) => {
+                    qwarn!("Forwarding request on {stream} failed: {e}");
+                    exchange.fail(stream, now);
+                    progress = true;
+                    false
+                }
+            });
+        if progress {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{
+        cell::RefCell,
+        future::poll_fn,
+        rc::Rc,
+        task::Poll,
+        time::{Duration, Instant},
+    };
+
+    use neqo_common::{Header, event::Provider as _};
+    use neqo_crypto::AuthenticationStatus;
+    use neqo_http3::{
+        Http3Client, Http3ClientEvent, Http3Parameters, Http3Server, Http3State, Priority,
+    };
+    use neqo_transport::{ConnectionParameters, StreamType};
+    use test_fixture::{
+        CountingConnectionIdGenerator, DEFAULT_ADDR, DEFAULT_ALPN_H3, DEFAULT_KEYS,
+        DEFAULT_SERVER_NAME, anti_replay, fixture_init,
+    };
+    use tokio::net::TcpListener;
+
+    use super::{MAX_BUFFERED, Message, Proxy, ResponseParser, find, request_head};
+
+    fn parse_all(parser: &mut ResponseParser, input: &[u8]) -> Vec<Message> {
+        let mut msgs = Vec::new();
+        // Feed the input one byte at a time to exercise partial parsing.
+        for b in input {
+            parser.input(&[*b]);
+            while let Some(m) = parser.next().unwrap() {
+                msgs.push(m);
+            }
+        }
+        msgs
+    }
+
+    fn data(msgs: &[Message]) -> Vec<u8> {
+        msgs.iter()
+            .filter_map(|m| match m {
+                Message::Data(d) => Some(d.as_slice()),
+                _ => None,
+            })
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn request() {
+        let headers = [
+            Header::new(":method", "POST"),
+            Header::new(":scheme", "https"),
+            Header::new(":authority", "example.com"),
+            Header::new(":path", "/upload"),
+            Header::new("cookie", "a=1"),
+            Header::new("te", "trailers"),
+            Header::new("cookie", "b=2"),
+            Header::new("x-custom", "value"),
+            Header::new("connection", "keep-alive"),
+        ];
+        let (head, chunked) = request_head(&headers, false).unwrap();
+        assert!(chunked);
+        assert_eq!(
+            String::from_utf8(head).unwrap(),
+            "POST /upload HTTP/1.1\r\nhost: example.com\r\nx-custom: value\r\n\
+             cookie: a=1; b=2\r\nte: trailers\r\nconnection: te, close\r\n\
+             transfer-encoding: chunked\r\n\r\n"
+        );
+
+        let smuggle = [
+            Header::new(":method", "GET"),
+            Header::new(":authority", "example.com"),
+            Header::new(":path", "/"),
+            Header::new("x-custom", "a\r\nx-injected: b"),
+        ];
+        assert!(request_head(&smuggle, true).is_err());
+    }
+
+    #[test]
+    fn request_content_length() {
+        let headers = [
+            Header::new(":method", "POST"),
+            Header::new(":authority", "example.com"),
+            Header::new(":path", "/"),
+            Header::new("content-length", "3"),
+            Header::new("content-length", "5"),
+        ];
+        let (head, chunked) = request_head(&headers, false).unwrap();
+        assert!(chunked);
+        assert_eq!(
+            String::from_utf8(head).unwrap(),
+            "POST / HTTP/1.1\r\nhost: example.com\r\nconnection: close\r\n\
+             transfer-encoding: chunked\r\n\r\n"
+        );
+        let (head, chunked) = request_head(&headers, true).unwrap();
+        assert!(!chunked);
+        assert_eq!(
+            String::from_utf8(head).unwrap(),
+            "POST / HTTP/1.1\r\nhost: example.com\r\nconnection: close\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn chunked_with_trailers() {
+        let mut parser = ResponseParser::new(false);
+        let msgs = parse_all(
+            &mut parser,
+            b"HTTP/1.1 100 Continue\r\n\r\n\
+              HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\
+              Connection: close, x-hop\r\nX-Hop: 1\r\n\r\n\
+              5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Checksum: abc\r\n\r\n",
+        );
+        assert_eq!(
+            msgs.first(),
+            Some(&Message::Head(vec![
+                Header::new(":status", "200"),
+                Header::new("content-type", "text/plain"),
+            ]))
+        );
+        assert_eq!(data(&msgs), b"hello world");
+        assert_eq!(
+            msgs.last(),
+            Some(&Message::Trailers(vec![Header::new("x-checksum", "abc")]))
+        );
+        assert!(parser.is_done());
+    }
+
+    #[test]
+    fn content_length() {
+        let mut parser = ResponseParser::new(false);
+        let msgs = parse_all(
+            &mut parser,
+            b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nabcdef",
+        );
+        assert_eq!(
+            msgs.first(),
+            Some(&Message::Head(vec![
+                Header::new(":status", "200"),
+                Header::new("content-length", "3"),
+            ]))
+        );
+        assert_eq!(data(&msgs), b"abc");
+        assert!(parser.is_done());
+    }
+
+    #[test]
+    fn until_eof() {
+        let mut parser = ResponseParser::new(false);
+        let msgs = parse_all(&mut parser, b"HTTP/1.0 200 OK\r\n\r\nabc");
+        assert_eq!(data(&msgs), b"abc");
+        assert!(!parser.is_done());
+        parser.end().unwrap();
+        assert!(parser.is_done());
+
+        let mut parser = ResponseParser::new(false);
+        parse_all(
+            &mut parser,
+            b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\nabc",
+        );
+        parser.end().unwrap_err();
+    }
+
+    #[test]
+    fn no_content() {
+        for (head, response) in [
+            (true, &b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\n"[..]),
+            (false, b"HTTP/1.1 204 No Content\r\n\r\n"),
+            (
+                false,
+                b"HTTP/1.1 304 Not Modified\r\ncontent-length: 10\r\n\r\n",
+            ),
+        ] {
+            let mut parser = ResponseParser::new(head);
+            let msgs = parse_all(&mut parser, response);
+            assert_eq!(msgs.len(), 1);
+            assert!(parser.is_done());
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        for response in [
+            &b"HTTP/1.1 999 Huh\r\n\r\n"[..],
+            b"SPDY/3 200 OK\r\n\r\n",
+            b"HTTP/1.1 101 Switching Protocols\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nx-folded: a\r\n b\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\ncontent-length: x\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\nzz\r\n",
+        ] {
+            let mut parser = ResponseParser::new(false);
+            parser.input(response);
+            let mut res = Ok(None);
+            for _ in 0..3 {
+                res = parser.next();
+                if res.is_err() {
+                    break;
+                }
+            }
+            assert!(res.is_err(), "{}", String::from_utf8_lossy(response));
+        }
+    }
+
+    /// Move packets until neither side has anything to send, returning
+    /// whether anything was sent.
+    fn exchange(client: &mut Http3Client, server: &mut Http3Server) -> bool {
+        let mut sent = false;
+        let mut dgram = None;
+        loop {
+            dgram = client.process(dgram, Instant::now()).dgram();
+            let client_sent = dgram.is_some();
+            dgram = server.process(dgram, Instant::now()).dgram();
+            if !client_sent && dgram.is_none() {
+                return sent;
+            }
+            sent = true;
+        }
+    }
+
+    /// Connect a client and a server that takes at most `window` bytes at a
+    /// time on a request stream.
+    fn connect(window: usize) -> (Http3Client, Http3Server) {
+        fixture_init();
+        let mut client = Http3Client::new(
+            DEFAULT_SERVER_NAME,
+            Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+            DEFAULT_ADDR,
+            DEFAULT_ADDR,
+            Http3Parameters::default()
+                .connection_parameters(ConnectionParameters::default().pacing(false)),
+            Instant::now(),
+        )
+        .unwrap();
+        let mut server = Http3Server::new(
+            Instant::now(),
+            DEFAULT_KEYS,
+            DEFAULT_ALPN_H3,
+            anti_replay(),
+            Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+            Http3Parameters::default().connection_parameters(
+                ConnectionParameters::default().max_stream_data(
+                    StreamType::BiDi,
+                    true,
+                    u64::try_from(window).unwrap(),
+                ),
+            ),
+            None,
+        )
+        .unwrap();
+        while client.state() != Http3State::Connected {
+            exchange(&mut client, &mut server);
+            let auth = |e| matches!(e, Http3ClientEvent::AuthenticationNeeded);
+            if client.events().any(auth) {
+                client.authenticated(AuthenticationStatus::Ok, Instant::now());
+            }
+        }
+        exchange(&mut client, &mut server);
+        (client, server)
+    }
+
+    /// Accept a connection and read a request with a chunked body, then respond.
+    async fn backend(listener: TcpListener) -> Vec<u8> {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while find(&request, b"\r\n0\r\n").is_none() || !request.ends_with(b"\r\n\r\n") {
+            tcp.readable().await.unwrap();
+            let mut buf = [0; 4096];
+            match tcp.try_read(&mut buf) {
+                Ok(n) => request.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{e}"),
+            }
+        }
+        let mut response = &b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok"[..];
+        while !response.is_empty() {
+            tcp.writable().await.unwrap();
+            if let Ok(n) = tcp.try_write(response) {
+                response = &response[n..];
+            }
+        }
+        request
+    }
+
+    /// Split a request that the backend received into its header section and
+    /// the messages in its chunked body.
+    fn split_request(request: &[u8]) -> (String, Vec<Message>) {
+        let end = find(request, b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&request[..end]).into_owned();
+        assert!(head.contains("transfer-encoding: chunked"), "{head}");
+        assert!(!head.contains("content-length"), "{head}");
+        // The chunked body can be parsed as a response would be.
+        let mut parser = ResponseParser::new(false);
+        parser.input(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n");
+        parser.input(&request[end + 4..]);
+        let mut msgs = Vec::new();
+        while let Some(m) = parser.next().unwrap() {
+            msgs.push(m);
+        }
+        assert!(parser.is_done());
+        (head, msgs)
+    }
+
+    /// A request body that the backend is slow to take is buffered only up to
+    /// a limit, and request trailers reach the backend.
+    #[tokio::test]
+    async fn forward_request() {
+        const BODY_LEN: usize = 16 * MAX_BUFFERED;
+        const WINDOW: usize = MAX_BUFFERED;
+        // Reading pauses at `MAX_BUFFERED`, after which the client can send
+        // what is already queued and another flow control window.
+        const BOUND: usize = MAX_BUFFERED + 3 * WINDOW;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut proxy = Proxy::new(listener.local_addr().unwrap());
+        let (mut client, mut server) = connect(WINDOW);
+
+        let stream_id = client
+            .fetch(
+                Instant::now(),
+                "POST",
+                ("https", DEFAULT_SERVER_NAME, "/upload"),
+                &[],
+                Priority::default(),
+            )
+            .unwrap();
+        let body = vec![0x55; BODY_LEN];
+        let buffered = |proxy: &Proxy| {
+            proxy
+                .exchanges
+                .values()
+                .map(|e| e.request.len())
+                .max()
+                .unwrap_or_default()
+        };
+
+        // Until the proxy is polled, nothing is written to the backend.
+        let mut sent = 0;
+        loop {
+            sent += client
+                .send_data(stream_id, &body[sent..], Instant::now())
+                .unwrap();
+            if !exchange(&mut client, &mut server) {
+                break;
+            }
+            proxy.process_events(&server, Instant::now());
+            assert!(buffered(&proxy) < BOUND);
+        }
+        assert!(sent < BODY_LEN);
+        assert!(proxy.exchanges.values().all(|e| e.paused));
+
+        let backend = tokio::spawn(backend(listener));
+
+        let mut closed = false;
+        let mut response_done = false;
+        while !response_done {
+            if sent < BODY_LEN {
+                sent += client
+                    .send_data(stream_id, &body[sent..], Instant::now())
+                    .unwrap();
+            } else if !closed {
+                client
+                    .send_trailers(
+                        stream_id,
+                        &[
+                            Header::new("x-checksum", "abc"),
+                            Header::new("connection", "close"),
+                        ],
+                    )
+                    .unwrap();
+                client.stream_close_send(stream_id, Instant::now()).unwrap();
+                closed = true;
+            }
+            exchange(&mut client, &mut server);
+            proxy.process_events(&server, Instant::now());
+            assert!(buffered(&proxy) < BOUND);
+            poll_fn(|cx| {
+                _ = proxy.poll(cx);
+                Poll::Ready(())
+            })
+            .await;
+            exchange(&mut client, &mut server);
+            while let Some(e) = client.next_event() {
+                if let Http3ClientEvent::DataReadable { stream_id } = e {
+                    let mut buf = [0; 16];
+                    let (n, fin) = client
+                        .read_data(Instant::now(), stream_id, &mut buf)
+                        .unwrap();
+                    assert_eq!(&buf[..n], b"ok");
+                    response_done = fin;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let request = backend.await.unwrap();
+        let (head, msgs) = split_request(&request);
+        assert!(head.starts_with("POST /upload HTTP/1.1\r\n"), "{head}");
+        assert_eq!(data(&msgs), body);
+        assert_eq!(
+            msgs.last(),
+            Some(&Message::Trailers(vec![Header::new("x-checksum", "abc")]))
+        );
+    }
+
+    /// Send a request with `headers` and `body` through the proxy, returning
+    /// what the backend receives.
+    async fn forward(headers: &[Header], body: &[u8]) -> Vec<u8> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut proxy = Proxy::new(listener.local_addr().unwrap());
+        let (mut client, mut server) = connect(MAX_BUFFERED);
+        let backend = tokio::spawn(backend(listener));
+
+        let stream_id = client
+            .fetch(
+                Instant::now(),
+                "POST",
+                ("https", DEFAULT_SERVER_NAME, "/"),
+                headers,
+                Priority::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            client.send_data(stream_id, body, Instant::now()).unwrap(),
+            body.len()
+        );
+        client.stream_close_send(stream_id, Instant::now()).unwrap();
+
+        let mut response_done = false;
+        while !response_done {
+            exchange(&mut client, &mut server);
+            proxy.process_events(&server, Instant::now());
+            poll_fn(|cx| {
+                _ = proxy.poll(cx);
+                Poll::Ready(())
+            })
+            .await;
+            exchange(&mut client, &mut server);
+            while let Some(e) = client.next_event() {
+                if let Http3ClientEvent::DataReadable { stream_id } = e {
+                    let mut buf = [0; 16];
+                    let (_, fin) = client
+                        .read_data(Instant::now(), stream_id, &mut buf)
+                        .unwrap();
+                    response_done = fin;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        backend.await.unwrap()
+    }
+
+    /// A content-length from the client is not passed on, so a body that
+    /// doesn't match it is framed correctly for the backend.
+    #[tokio::test]
+    async fn content_length_mismatch() {
+        let cl = |v: &str| Header::new("content-length", v);
+        for (headers, body) in [
+            // Shorter than the content-length.
+            (vec![cl("10")], &b"abc"[..]),
+            // Longer than the content-length.
+            (vec![cl("1")], b"abcde"),
+            // Conflicting content-length fields.
+            (vec![cl("3"), cl("5")], b"abc"),
+        ] {
+            let request = forward(&headers, body).await;
+            let (_, msgs) = split_request(&request);
+            assert_eq!(data(&msgs), body);
+        }
+    }
+}
@@ -15,8 +15,11 @@ use std::{
 };
 
 use qlog::{
-    CommonFields, Configuration, TraceSeq, VantagePoint, VantagePointType, streamer::QlogStreamer,
+    CommonFields, Configuration, TraceSeq, VantagePoint, VantagePointType,
+    events::{EventImportance, Eventable},
+    streamer::QlogStreamer,
 };
+use serde_derive::Serialize;
 
 use crate::Role;
 
@@ -33,6 +36,8 @@ pub struct Qlog {
 pub struct SharedStreamer {
     qlog_path: PathBuf,
     streamer: QlogStreamer,
+    /// The time of the most recent event logged with [`Qlog::add_event_at`].
+    last_event: Option<Instant>,
 }
 
 impl Qlog {
@@ -65,7 +70,7 @@ impl Qlog {
             None,
             now,
             new_trace(role),
-            qlog::events::EventImportance::Base,
+            EventImportance::Base,
             Box::new(BufWriter::new(file)),
         );
         Self::enabled(streamer, qlog_path)
@@ -86,6 +91,7 @@ impl Qlog {
             inner: Some(Rc::new(RefCell::new(Some(SharedStreamer {
                 qlog_path,
                 streamer,
+                last_event: None,
             })))),
         })
     }
@@ -101,9 +107,53 @@ impl Qlog {
     where
         F: FnOnce() -> Option<qlog::events::EventData>,
     {
-        self.add_event_with_stream(|s| {
+        self.with_shared(|s| {
             if let Some(ev_data) = f() {
-                s.add_event_data_with_instant(ev_data, now)?;
+                s.last_event = Some(now);
+                s.streamer.add_event_data_with_instant(ev_data, now)?;
+            }
+            Ok(())
+        });
+    }
+
+    /// If logging enabled, closure may generate an event to be logged.
+    ///
+    /// This is for code that doesn't know the current time.  The event is
+    /// logged at the time of the most recent event from [`Qlog::add_event_at`],
+    /// or at the start of the log if there is none.
+    pub fn add_event<F>(&mut self, f: F)
+    where
+        F: FnOnce() -> Option<qlog::events::EventData>,
+    {
+        self.with_shared(|s| {
+            if let Some(ev_data) = f() {
+                let now = s.last_event.unwrap_or_else(|| s.streamer.start_time());
+                s.streamer.add_event_data_with_instant(ev_data, now)?;
+            }
+            Ok(())
+        });
+    }
+
+    /// If logging enabled, closure may generate the data for an event called
+    /// `name` that the `qlog` crate has no type for.  Without `now`, the event
+    /// is logged at the same time as for [`Qlog::add_event`].
+    pub fn add_custom_event<D, F>(&mut self, name: &'static str, f: F, now: Option<Instant>)
+    where
+        D: serde::Serialize,
+        F: FnOnce() -> Option<D>,
+    {
+        self.with_shared(|s| {
+            if let Some(data) = f() {
+                if now.is_some() {
+                    s.last_event = now;
+                }
+                let now = s.last_event.unwrap_or_else(|| s.streamer.start_time());
+                let ev = CustomEvent {
+                    time: 0.0,
+                    name,
+                    data,
+                };
+                s.streamer.add_event_with_instant(ev, now)?;
             }
             Ok(())
         });
@@ -114,6 +164,13 @@ impl Qlog {
     pub fn add_event_with_stream<F>(&mut self, f: F)
     where
         F: FnOnce(&mut QlogStreamer) -> Result<(), qlog::Error>,
+    {
+        self.with_shared(|s| f(&mut s.streamer));
+    }
+
+    fn with_shared<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut SharedStreamer) -> Result<(), qlog::Error>,
     {
         let Some(inner) = self.inner.as_mut() else {
             return;
@@ -128,7 +185,11 @@ impl Qlog {
             return;
         };
 
-        if let Err(e) = f(&mut shared_streamer.streamer) {
+        // `Done` means that the event was below the configured importance.
+        if let Err(e) = f(shared_streamer).or_else(|e| match e {
+            qlog::Error::Done => Ok(()),
+            e => Err(e),
+        }) {
             log::error!("Qlog event generation failed with error {e}; closing qlog.");
             // Set the inner Option to None to disable future logging for other references.
             *borrow = None;
@@ -146,6 +207,24 @@ impl fmt::Debug for SharedStreamer {
     }
 }
 
+/// An event that the `qlog` crate has no type for.
+#[derive(Serialize)]
+struct CustomEvent<D> {
+    time: f32,
+    name: &'static str,
+    data: D,
+}
+
+impl<D> Eventable for CustomEvent<D> {
+    fn importance(&self) -> EventImportance {
+        EventImportance::Base
+    }
+
+    fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+}
+
 impl Drop for SharedStreamer {
     fn drop(&mut self) {
         if let Err(e) = self.streamer.finish_log() {
@@ -222,6 +301,23 @@ mod test {
         assert_eq!(output, format!("{EXPECTED_LOG_HEADER}{EXPECTED_LOG_EVENT}"));
     }
 
+    #[test]
+    fn add_event_uses_last_time() {
+        let (mut log, contents) = test_fixture::new_neqo_qlog();
+        log.add_event(|| Some(EV_DATA));
+        log.add_event_at(|| Some(EV_DATA), test_fixture::now());
+        log.add_event(|| Some(EV_DATA));
+        let output = contents.to_string();
+        let times = output
+            .split("\"time\":")
+            .skip(1)
+            .map(|s| s.split(',').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(times.len(), 3);
+        assert_eq!(times[0], "0.0");
+        assert_eq!(times[1], times[2]);
+    }
+
     #[test]
     fn shared_streamer_debug() {
         let (log, _contents) = test_fixture::new_neqo_qlog();
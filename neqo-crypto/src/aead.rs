@@ -5,17 +5,26 @@
 // except according to those terms.
 
 use std::{
+    cell::OnceCell,
     fmt,
     ops::Deref,
     os::raw::{c_char, c_uint},
-    ptr::null_mut,
+    ptr::{addr_of_mut, null, null_mut},
 };
 
 use crate::{
-    constants::{Cipher, Version},
-    err::{Error, Res, sec::SEC_ERROR_BAD_DATA},
+    constants::{
+        Cipher, TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256,
+        Version,
+    },
+    err::{Error, Res, sec::SEC_ERROR_BAD_DATA, secstatus_to_res},
     experimental_api,
-    p11::{PK11SymKey, SymKey},
+    hp::SSL_HkdfExpandLabelWithMech,
+    p11::{
+        CK_GCM_PARAMS_V3, CK_MECHANISM_TYPE, CK_SALSA20_CHACHA20_POLY1305_PARAMS, CK_ULONG,
+        CKM_AES_GCM, CKM_CHACHA20_POLY1305, CKM_HKDF_DERIVE, Item, PK11_Decrypt, PK11_Encrypt,
+        PK11SymKey, SECItem, SymKey,
+    },
     scoped_ptr,
     ssl::{PRUint8, PRUint16, PRUint64, SSLAeadContext},
 };
@@ -76,6 +85,36 @@ pub trait Aead {
     ///
     /// Returns `Error` when decryption or authentication fails.
     fn decrypt_in_place(&self, count: u64, aad: &[u8], data: &mut [u8]) -> Res<usize>;
+
+    /// Encrypt plaintext in place for a specific path.
+    ///
+    /// The QUIC multipath extension forms the nonce from both the path identifier
+    /// and the packet number.  Path 0 uses the same nonce as [`Aead::encrypt_in_place`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error` when encryption fails.
+    fn encrypt_in_place_for_path(
+        &self,
+        path_id: u32,
+        count: u64,
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Res<usize>;
+
+    /// Decrypt ciphertext in place for a specific path.
+    /// See [`Aead::encrypt_in_place_for_path`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error` when decryption or authentication fails.
+    fn decrypt_in_place_for_path(
+        &self,
+        path_id: u32,
+        count: u64,
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Res<usize>;
 }
 
 experimental_api!(SSL_MakeAead(
@@ -111,8 +150,181 @@ experimental_api!(SSL_AeadDecrypt(
 experimental_api!(SSL_DestroyAead(ctx: *mut SSLAeadContext));
 scoped_ptr!(AeadContext, SSLAeadContext, SSL_DestroyAead);
 
+const NONCE_LEN: usize = 12;
+
+/// The key and IV for an AEAD, used when the nonce cannot be formed by `SSL_AeadEncrypt`.
+struct ExplicitKey {
+    mech: CK_MECHANISM_TYPE,
+    key: SymKey,
+    iv: [u8; NONCE_LEN],
+}
+
+impl ExplicitKey {
+    fn derive(version: Version, cipher: Cipher, secret: &SymKey, prefix: &str) -> Res<Self> {
+        let (mech, key_size) = match cipher {
+            TLS_AES_128_GCM_SHA256 => (CK_MECHANISM_TYPE::from(CKM_AES_GCM), 16),
+            TLS_AES_256_GCM_SHA384 => (CK_MECHANISM_TYPE::from(CKM_AES_GCM), 32),
+            TLS_CHACHA20_POLY1305_SHA256 => (CK_MECHANISM_TYPE::from(CKM_CHACHA20_POLY1305), 32),
+            _ => return Err(Error::UnsupportedCipher),
+        };
+        let key = Self::expand(
+            version,
+            cipher,
+            secret,
+            &format!("{prefix}key"),
+            mech,
+            key_size,
+        )?;
+        let iv = Self::expand(
+            version,
+            cipher,
+            secret,
+            &format!("{prefix}iv"),
+            CK_MECHANISM_TYPE::from(CKM_HKDF_DERIVE),
+            c_uint::try_from(NONCE_LEN)?,
+        )?;
+        Ok(Self {
+            mech,
+            key,
+            iv: iv.as_bytes()?.try_into().map_err(|_| Error::Internal)?,
+        })
+    }
+
+    fn expand(
+        version: Version,
+        cipher: Cipher,
+        secret: &SymKey,
+        label: &str,
+        mech: CK_MECHANISM_TYPE,
+        size: c_uint,
+    ) -> Res<SymKey> {
+        let l = label.as_bytes();
+        let mut out: *mut PK11SymKey = null_mut();
+        unsafe {
+            SSL_HkdfExpandLabelWithMech(
+                version,
+                cipher,
+                **secret,
+                null(),
+                0,
+                l.as_ptr().cast(),
+                c_uint::try_from(l.len())?,
+                mech,
+                size,
+                &raw mut out,
+            )
+        }?;
+        SymKey::from_ptr(out).or(Err(Error::Hkdf))
+    }
+
+    /// Form the nonce by combining the IV with the path identifier and packet number,
+    /// both left-padded to the length of the IV, using exclusive or.
+    fn nonce(&self, path_id: u32, count: u64) -> [u8; NONCE_LEN] {
+        let mut nonce = self.iv;
+        let mut input = [0; NONCE_LEN];
+        input[..4].copy_from_slice(&path_id.to_be_bytes());
+        input[4..].copy_from_slice(&count.to_be_bytes());
+        for (n, i) in nonce.iter_mut().zip(input) {
+            *n ^= i;
+        }
+        nonce
+    }
+
+    /// Run `f` with the mechanism parameters for the given nonce and AAD.
+    fn with_params<T>(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        aad: &[u8],
+        f: impl FnOnce(*mut SECItem) -> Res<T>,
+    ) -> Res<T> {
+        if self.mech == CK_MECHANISM_TYPE::from(CKM_AES_GCM) {
+            let params = CK_GCM_PARAMS_V3 {
+                pIv: nonce.as_ptr().cast_mut(),
+                ulIvLen: CK_ULONG::try_from(NONCE_LEN)?,
+                ulIvBits: CK_ULONG::try_from(NONCE_LEN * 8)?,
+                pAAD: aad.as_ptr().cast_mut(),
+                ulAADLen: CK_ULONG::try_from(aad.len())?,
+                ulTagBits: 128,
+            };
+            let mut item = Item::wrap_struct(&params)?;
+            f(addr_of_mut!(item))
+        } else {
+            let params = CK_SALSA20_CHACHA20_POLY1305_PARAMS {
+                pNonce: nonce.as_ptr().cast_mut(),
+                ulNonceLen: CK_ULONG::try_from(NONCE_LEN)?,
+                pAAD: aad.as_ptr().cast_mut(),
+                ulAADLen: CK_ULONG::try_from(aad.len())?,
+            };
+            let mut item = Item::wrap_struct(&params)?;
+            f(addr_of_mut!(item))
+        }
+    }
+
+    fn encrypt_in_place(
+        &self,
+        path_id: u32,
+        count: u64,
+        aad: &[u8],
+        data: &mut [u8],
+        expansion: usize,
+    ) -> Res<usize> {
+        let nonce = self.nonce(path_id, count);
+        let input = data[..data.len() - expansion].to_vec();
+        let mut l: c_uint = 0;
+        self.with_params(&nonce, aad, |params| {
+            secstatus_to_res(unsafe {
+                PK11_Encrypt(
+                    *self.key,
+                    self.mech,
+                    params,
+                    data.as_mut_ptr(),
+                    &raw mut l,
+                    c_uint::try_from(data.len())?,
+                    input.as_ptr(),
+                    c_uint::try_from(input.len())?,
+                )
+            })
+        })?;
+        debug_assert_eq!(usize::try_from(l)?, data.len());
+        Ok(data.len())
+    }
+
+    fn decrypt_in_place(
+        &self,
+        path_id: u32,
+        count: u64,
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Res<usize> {
+        let nonce = self.nonce(path_id, count);
+        let input = data.to_vec();
+        let mut l: c_uint = 0;
+        self.with_params(&nonce, aad, |params| {
+            secstatus_to_res(unsafe {
+                PK11_Decrypt(
+                    *self.key,
+                    self.mech,
+                    params,
+                    data.as_mut_ptr(),
+                    &raw mut l,
+                    c_uint::try_from(data.len())?,
+                    input.as_ptr(),
+                    c_uint::try_from(input.len())?,
+                )
+            })
+        })?;
+        Ok(l.try_into()?)
+    }
+}
+
 pub struct RealAead {
     ctx: AeadContext,
+    version: Version,
+    cipher: Cipher,
+    secret: SymKey,
+    prefix: String,
+    /// Keys for use with paths other than path 0, derived on first use.
+    explicit: OnceCell<ExplicitKey>,
 }
 
 impl RealAead {
@@ -121,7 +333,7 @@ impl RealAead {
         cipher: Cipher,
         secret: *mut PK11SymKey,
         prefix: &str,
-    ) -> Res<Self> {
+    ) -> Res<AeadContext> {
         let p = prefix.as_bytes();
         let mut ctx: *mut SSLAeadContext = null_mut();
         unsafe {
@@ -134,16 +346,29 @@ impl RealAead {
                 &raw mut ctx,
             )?;
         }
-        Ok(Self {
-            ctx: AeadContext::from_ptr(ctx)?,
-        })
+        AeadContext::from_ptr(ctx)
+    }
+
+    fn explicit(&self) -> Res<&ExplicitKey> {
+        if let Some(k) = self.explicit.get() {
+            return Ok(k);
+        }
+        let k = ExplicitKey::derive(self.version, self.cipher, &self.secret, &self.prefix)?;
+        Ok(self.explicit.get_or_init(|| k))
     }
 }
 
 impl Aead for RealAead {
     fn new(version: Version, cipher: Cipher, secret: &SymKey, prefix: &str) -> Res<Self> {
         let s: *mut PK11SymKey = **secret;
-        unsafe { Self::from_raw(version, cipher, s, prefix) }
+        Ok(Self {
+            ctx: unsafe { Self::from_raw(version, cipher, s, prefix) }?,
+            version,
+            cipher,
+            secret: secret.clone(),
+            prefix: prefix.to_string(),
+            explicit: OnceCell::new(),
+        })
     }
 
     fn expansion(&self) -> usize {
@@ -245,6 +470,36 @@ impl Aead for RealAead {
         debug_assert_eq!(usize::try_from(l)?, data.len() - self.expansion());
         Ok(l.try_into()?)
     }
+
+    fn encrypt_in_place_for_path(
+        &self,
+        path_id: u32,
+        count: u64,
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Res<usize> {
+        if path_id == 0 {
+            return self.encrypt_in_place(count, aad, data);
+        }
+        if data.len() < self.expansion() {
+            return Err(Error::from(SEC_ERROR_BAD_DATA));
+        }
+        self.explicit()?
+            .encrypt_in_place(path_id, count, aad, data, self.expansion())
+    }
+
+    fn decrypt_in_place_for_path(
+        &self,
+        path_id: u32,
+        count: u64,
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Res<usize> {
+        if path_id == 0 {
+            return self.decrypt_in_place(count, aad, data);
+        }
+        self.explicit()?.decrypt_in_place(path_id, count, aad, data)
+    }
 }
 
 impl fmt::Debug for RealAead {
@@ -85,6 +85,26 @@ impl Aead for AeadNull {
     fn decrypt_in_place(&self, count: u64, aad: &[u8], data: &mut [u8]) -> Res<usize> {
         self.decrypt_check(count, aad, data)
     }
+
+    fn encrypt_in_place_for_path(
+        &self,
+        _path_id: u32,
+        count: u64,
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Res<usize> {
+        self.encrypt_in_place(count, aad, data)
+    }
+
+    fn decrypt_in_place_for_path(
+        &self,
+        _path_id: u32,
+        count: u64,
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Res<usize> {
+        self.decrypt_in_place(count, aad, data)
+    }
 }
 
 impl fmt::Debug for AeadNull {
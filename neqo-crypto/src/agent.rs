@@ -30,16 +30,18 @@ use crate::{
     assert_initialized,
     auth::AuthenticationStatus,
     constants::{
-        Alert, Cipher, Epoch, Extension, Group, SignatureScheme, TLS_VERSION_1_3, Version,
+        Alert, Cipher, Epoch, Extension, Group, SignatureScheme, TLS_CT_HANDSHAKE,
+        TLS_HS_CLIENT_HELLO, TLS_VERSION_1_3, Version,
     },
     ech,
     err::{Error, PRErrorCode, Res, is_blocked, secstatus_to_res},
     ext::{ExtensionHandler, ExtensionTracker, SSL_CallExtensionWriterOnEchInner},
+    keylog::{KeyLog, KeyLogLabel},
     null_safe_slice,
     p11::{self, PrivateKey, PublicKey},
     prio,
     replay::AntiReplay,
-    secrets::SecretHolder,
+    secrets::{SecretDirection, SecretHolder},
     ssl::{self, PRBool},
     time::{Time, TimeHolder},
 };
@@ -442,6 +444,12 @@ pub struct SecretAgent {
     /// The encrypted client hello (ECH) configuration that is in use.
     /// Empty if ECH is not enabled.
     ech_config: Vec<u8>,
+
+    is_server: bool,
+    keylog: Option<KeyLog>,
+    /// The random value from the `ClientHello`, which identifies the
+    /// connection in a key log.  Only captured when there is a key log.
+    client_random: Option<[u8; 32]>,
 }
 
 impl SecretAgent {
@@ -462,6 +470,10 @@ impl SecretAgent {
             extension_handlers: Vec::new(),
 
             ech_config: Vec::new(),
+
+            is_server: false,
+            keylog: None,
+            client_random: None,
         })
     }
 
@@ -528,6 +540,7 @@ impl SecretAgent {
 
     // Ready this for connecting.
     fn ready(&mut self, is_server: bool, grease: bool) -> Res<()> {
+        self.is_server = is_server;
         secstatus_to_res(unsafe {
             ssl::SSL_AuthCertificateHook(
                 self.fd,
@@ -914,6 +927,7 @@ impl SecretAgent {
 
         // Feed in any records.
         if let Some(rec) = input {
+            self.find_client_random(&rec);
             self.capture_error(rec.write(self.fd))?;
         }
 
@@ -921,7 +935,66 @@ impl SecretAgent {
         let rv = secstatus_to_res(unsafe { ssl::SSL_ForceHandshake(self.fd) });
         self.update_state(rv)?;
 
-        Ok(*Pin::into_inner(records))
+        let records = *Pin::into_inner(records);
+        for rec in records.iter() {
+            self.find_client_random(rec);
+        }
+        self.write_keylog();
+        Ok(records)
+    }
+
+    /// Write secrets to `keylog` as they become available.  This needs to be
+    /// called before the handshake starts.
+    pub fn set_keylog(&mut self, keylog: KeyLog) {
+        self.secrets.enable_log();
+        self.keylog = Some(keylog);
+    }
+
+    /// The key log that this agent writes to, if any.
+    #[must_use]
+    pub const fn keylog(&self) -> Option<&KeyLog> {
+        self.keylog.as_ref()
+    }
+
+    /// The random value from the `ClientHello`.  This is only available if
+    /// there is a key log and the `ClientHello` has been sent or received.
+    #[must_use]
+    pub const fn client_random(&self) -> Option<&[u8; 32]> {
+        self.client_random.as_ref()
+    }
+
+    fn find_client_random(&mut self, rec: &Record) {
+        if self.keylog.is_none()
+            || self.client_random.is_some()
+            || rec.epoch != Epoch::Initial
+            || rec.ct != TLS_CT_HANDSHAKE
+        {
+            return;
+        }
+        // The message type, a 3 byte length, and a 2 byte version precede the random.
+        if rec.data.first() == Some(&TLS_HS_CLIENT_HELLO)
+            && let Some(random) = rec.data.get(6..38)
+        {
+            self.client_random = random.try_into().ok();
+        }
+    }
+
+    fn write_keylog(&mut self) {
+        let (Some(keylog), Some(client_random)) = (&self.keylog, &self.client_random) else {
+            return;
+        };
+        for (dir, epoch, secret) in self.secrets.take_logged() {
+            let client = matches!(dir, SecretDirection::Write) != self.is_server;
+            let label = match epoch {
+                Epoch::Initial => continue,
+                Epoch::ZeroRtt => KeyLogLabel::ClientEarlyTraffic,
+                Epoch::Handshake if client => KeyLogLabel::ClientHandshakeTraffic,
+                Epoch::Handshake => KeyLogLabel::ServerHandshakeTraffic,
+                Epoch::ApplicationData if client => KeyLogLabel::ClientTraffic(0),
+                Epoch::ApplicationData => KeyLogLabel::ServerTraffic(0),
+            };
+            keylog.log(label, client_random, &secret);
+        }
     }
 
     /// # Panics
@@ -4,14 +4,17 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::ptr::NonNull;
+use std::{ffi::CString, ptr::NonNull};
 
 use neqo_common::qerror;
 
 use crate::{
+    err::secstatus_to_res,
     experimental_api, null_safe_slice,
-    p11::{ItemArray, ItemArrayIterator, SECItem, SECItemArray},
-    ssl::{PRFileDesc, SSL_PeerSignedCertTimestamps, SSL_PeerStapledOCSPResponses},
+    p11::{CERT_VerifyCertName, Certificate, ItemArray, ItemArrayIterator, SECItem, SECItemArray},
+    ssl::{
+        PRFileDesc, SSL_PeerCertificate, SSL_PeerSignedCertTimestamps, SSL_PeerStapledOCSPResponses,
+    },
 };
 
 experimental_api!(SSL_PeerCertificateChainDER(
@@ -21,6 +24,8 @@ experimental_api!(SSL_PeerCertificateChainDER(
 
 pub struct CertificateInfo {
     certs: ItemArray,
+    /// The end-entity certificate.
+    cert: Option<Certificate>,
     /// `stapled_ocsp_responses` and `signed_cert_timestamp` are properties
     /// associated with each of the certificates. Right now, NSS only
     /// reports the value for the end-entity certificate (the first).
@@ -81,6 +86,7 @@ impl CertificateInfo {
     pub(crate) fn new(fd: *mut PRFileDesc) -> Option<Self> {
         peer_certificate_chain(fd).map(|certs| Self {
             certs,
+            cert: Certificate::from_ptr(unsafe { SSL_PeerCertificate(fd) }.cast()).ok(),
             stapled_ocsp_responses: stapled_ocsp_responses(fd),
             signed_cert_timestamp: signed_cert_timestamp(fd),
         })
@@ -91,6 +97,17 @@ impl CertificateInfo {
         self.certs.into_iter()
     }
 
+    /// Check that the end-entity certificate is valid for `name`, which is
+    /// either a host name or an IP address.  This uses the subject alternative
+    /// names in the certificate, including any wildcards.
+    #[must_use]
+    pub fn verify_name(&self, name: &str) -> bool {
+        let (Some(cert), Ok(name)) = (&self.cert, CString::new(name)) else {
+            return false;
+        };
+        secstatus_to_res(unsafe { CERT_VerifyCertName(**cert, name.as_ptr()) }).is_ok()
+    }
+
     #[must_use]
     pub const fn stapled_ocsp_responses(&self) -> Option<&Vec<Vec<u8>>> {
         self.stapled_ocsp_responses.as_ref()
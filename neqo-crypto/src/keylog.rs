@@ -0,0 +1,94 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug, Display, Formatter},
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use neqo_common::{hex, qwarn};
+
+use crate::p11::SymKey;
+
+/// The secrets that are written to a [`KeyLog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[expect(
+    clippy::enum_variant_names,
+    reason = "These follow the labels in the log."
+)]
+pub enum KeyLogLabel {
+    ClientEarlyTraffic,
+    ClientHandshakeTraffic,
+    ServerHandshakeTraffic,
+    /// Application data secrets, with the number of key updates.
+    ClientTraffic(usize),
+    ServerTraffic(usize),
+}
+
+impl Display for KeyLogLabel {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::ClientEarlyTraffic => write!(f, "CLIENT_EARLY_TRAFFIC_SECRET"),
+            Self::ClientHandshakeTraffic => write!(f, "CLIENT_HANDSHAKE_TRAFFIC_SECRET"),
+            Self::ServerHandshakeTraffic => write!(f, "SERVER_HANDSHAKE_TRAFFIC_SECRET"),
+            Self::ClientTraffic(n) => write!(f, "CLIENT_TRAFFIC_SECRET_{n}"),
+            Self::ServerTraffic(n) => write!(f, "SERVER_TRAFFIC_SECRET_{n}"),
+        }
+    }
+}
+
+/// A destination for secrets in the NSS key log format, as used by
+/// `SSLKEYLOGFILE`.  Tools like Wireshark use this to decrypt captures.
+///
+/// Clones write to the same destination, so one log can be shared by many
+/// connections.
+#[derive(Clone)]
+pub struct KeyLog {
+    out: Rc<RefCell<Box<dyn Write>>>,
+}
+
+impl Debug for KeyLog {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "KeyLog")
+    }
+}
+
+impl KeyLog {
+    #[must_use]
+    pub fn new<W: Write + 'static>(out: W) -> Self {
+        Self {
+            out: Rc::new(RefCell::new(Box::new(out))),
+        }
+    }
+
+    /// Append to the file at `path`, creating it if necessary.
+    ///
+    /// # Errors
+    ///
+    /// When the file can't be opened.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let f = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(f))
+    }
+
+    /// Write a line for `secret`, which belongs to the connection that is
+    /// identified by the random value from its `ClientHello`.  Failures are
+    /// logged, but otherwise ignored.
+    pub fn log(&self, label: KeyLogLabel, client_random: &[u8], secret: &SymKey) {
+        let res = secret.as_bytes().map_err(io::Error::other).and_then(|s| {
+            let mut out = self.out.borrow_mut();
+            writeln!(out, "{label} {} {}", hex(client_random), hex(s))?;
+            out.flush()
+        });
+        if let Err(e) = res {
+            qwarn!("Unable to write {label} to key log: {e}");
+        }
+    }
+}
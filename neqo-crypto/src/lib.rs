@@ -21,6 +21,7 @@ mod exp;
 pub mod ext;
 pub mod hkdf;
 pub mod hp;
+mod keylog;
 #[macro_use]
 mod p11;
 mod prio;
@@ -53,6 +54,7 @@ pub use self::{
     },
     err::{Error, PRErrorCode, Res},
     ext::{ExtensionHandler, ExtensionHandlerResult, ExtensionWriterResult},
+    keylog::{KeyLog, KeyLogLabel},
     p11::{PrivateKey, PublicKey, SymKey, random, randomize},
     replay::AntiReplay,
     secrets::SecretDirection,
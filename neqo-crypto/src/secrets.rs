@@ -69,6 +69,8 @@ impl DirectionalSecrets {
 pub struct Secrets {
     r: DirectionalSecrets,
     w: DirectionalSecrets,
+    /// Secrets that are waiting to be written to a key log, if there is one.
+    logged: Option<Vec<(SecretDirection, Epoch, SymKey)>>,
 }
 
 impl Secrets {
@@ -99,6 +101,9 @@ impl Secrets {
 
     fn put(&mut self, dir: SecretDirection, epoch: Epoch, key: SymKey) {
         qdebug!("{dir:?} secret available for {epoch:?}: {key:?}");
+        if let Some(logged) = &mut self.logged {
+            logged.push((dir, epoch, key.clone()));
+        }
         let keys = match dir {
             SecretDirection::Read => &mut self.r,
             SecretDirection::Write => &mut self.w,
@@ -131,6 +136,20 @@ impl SecretHolder {
     pub fn take_write(&mut self, epoch: Epoch) -> Option<SymKey> {
         self.secrets.w.take(epoch)
     }
+
+    /// Keep copies of secrets as they become available, for a key log.
+    pub fn enable_log(&mut self) {
+        self.secrets.logged.get_or_insert_default();
+    }
+
+    /// Take the secrets that have become available since the last call.
+    pub fn take_logged(&mut self) -> Vec<(SecretDirection, Epoch, SymKey)> {
+        self.secrets
+            .logged
+            .as_mut()
+            .map(mem::take)
+            .unwrap_or_default()
+    }
 }
 
 impl Default for SecretHolder {
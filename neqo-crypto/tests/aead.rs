@@ -9,7 +9,10 @@
 
 use neqo_crypto::{
     Aead, AeadTrait as _,
-    constants::{Cipher, TLS_AES_128_GCM_SHA256, TLS_VERSION_1_3},
+    constants::{
+        Cipher, TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256,
+        TLS_VERSION_1_3,
+    },
     hkdf,
 };
 use test_fixture::fixture_init;
@@ -149,3 +152,41 @@ fn encrypt_decrypt_in_place() {
     assert_eq!(decrypted_len, plaintext.len());
     assert_eq!(&buffer[..decrypted_len], plaintext);
 }
+
+#[test]
+fn encrypt_decrypt_in_place_for_path() {
+    for cipher in [
+        TLS_AES_128_GCM_SHA256,
+        TLS_AES_256_GCM_SHA384,
+        TLS_CHACHA20_POLY1305_SHA256,
+    ] {
+        let aead = make_aead(cipher);
+        let mut path0 = Vec::from(PLAINTEXT);
+        path0.resize(PLAINTEXT.len() + aead.expansion(), 0);
+        let mut path1 = path0.clone();
+        let mut plain = path0.clone();
+
+        // Path 0 uses the same nonce as packets without a path.
+        aead.encrypt_in_place(7, AAD, &mut plain).unwrap();
+        aead.encrypt_in_place_for_path(0, 7, AAD, &mut path0)
+            .unwrap();
+        assert_eq!(plain, path0);
+
+        aead.encrypt_in_place_for_path(1, 7, AAD, &mut path1)
+            .unwrap();
+        assert_ne!(path0, path1);
+        // The wrong path or packet number fails.
+        assert!(
+            aead.decrypt_in_place_for_path(0, 7, AAD, &mut path1.clone())
+                .is_err()
+        );
+        assert!(
+            aead.decrypt_in_place_for_path(1, 6, AAD, &mut path1.clone())
+                .is_err()
+        );
+        let len = aead
+            .decrypt_in_place_for_path(1, 7, AAD, &mut path1)
+            .unwrap();
+        assert_eq!(&path1[..len], PLAINTEXT);
+    }
+}
@@ -144,6 +144,8 @@ fn raw() {
     assert_eq!(1, certs.into_iter().count());
     assert!(certs.stapled_ocsp_responses().unwrap().is_empty());
     assert!(certs.signed_cert_timestamp().unwrap().is_empty());
+    assert!(certs.verify_name("alt1.example.com"));
+    assert!(!certs.verify_name("other.example"));
 
     // The server shouldn't have a client certificate.
     assert!(server.peer_certificate().is_none());
@@ -0,0 +1,562 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Owned handles for the request and response bodies of an `Http3Client`.
+
+use std::{cell::RefCell, collections::VecDeque, io, rc::Rc, time::Instant};
+
+use neqo_transport::{AppError, StreamId};
+
+use crate::{Error, Http3Client, Res};
+
+/// Why a body handle could not complete an operation.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum BodyError {
+    #[error("stream reset by the peer with error {0}")]
+    Reset(AppError),
+    #[error("peer sent STOP_SENDING with error {0}")]
+    StopSending(AppError),
+    #[error(transparent)]
+    Http3(#[from] Error),
+}
+
+impl From<BodyError> for io::Error {
+    fn from(err: BodyError) -> Self {
+        match err {
+            BodyError::Reset(_) => Self::new(io::ErrorKind::ConnectionReset, err),
+            BodyError::StopSending(_) => Self::new(io::ErrorKind::BrokenPipe, err),
+            BodyError::Http3(_) => Self::other(err),
+        }
+    }
+}
+
+/// Writes the body of a request that was started with [`Http3Client::fetch`].
+///
+/// Up to [`SendBodyHandle::MAX_BUFFERED`] bytes that the client can't take yet
+/// are buffered in the handle and passed on by [`SendBodyHandle::flush`].  This
+/// needs to be called again after [`crate::Http3ClientEvent::DataWritable`].
+pub struct SendBodyHandle {
+    client: Rc<RefCell<Http3Client>>,
+    stream_id: StreamId,
+    buffer: VecDeque<u8>,
+    written: u64,
+    close_pending: bool,
+    final_size: Option<u64>,
+}
+
+impl SendBodyHandle {
+    /// The most data that the handle buffers.  Writes only take what fits.
+    pub const MAX_BUFFERED: usize = 1 << 16;
+
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if there is no request body to send on the stream.
+    pub fn new(client: Rc<RefCell<Http3Client>>, stream_id: StreamId) -> Res<Self> {
+        {
+            let c = client.borrow();
+            if !c.has_send_stream(stream_id) {
+                return Err(Error::InvalidStreamId);
+            }
+            c.client_events().watch_stream(stream_id);
+        }
+        Ok(Self {
+            client,
+            stream_id,
+            buffer: VecDeque::new(),
+            written: 0,
+            close_pending: false,
+            final_size: None,
+        })
+    }
+
+    #[must_use]
+    pub const fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    fn check(&self) -> Result<(), BodyError> {
+        let stop_sending = self
+            .client
+            .borrow()
+            .client_events()
+            .watched(self.stream_id, |w| w.stop_sending)
+            .flatten();
+        stop_sending.map_or(Ok(()), |e| Err(BodyError::StopSending(e)))
+    }
+
+    /// Write as much of `data` as the client and the buffer in the handle can
+    /// take.  Returns the number of bytes taken, which is zero when the stream
+    /// has no flow control credit and the buffer is full.
+    ///
+    /// # Errors
+    ///
+    /// `StopSending` if the server asked to stop sending, or `AlreadyClosed`
+    /// if the body was already closed.
+    pub fn write(&mut self, data: &[u8], now: Instant) -> Result<usize, BodyError> {
+        if self.close_pending || self.final_size.is_some() {
+            return Err(Error::AlreadyClosed.into());
+        }
+        let sent = if self.flush(now)? {
+            self.send(data, now)?
+        } else {
+            0
+        };
+        self.written += u64::try_from(sent).map_err(|_| Error::Internal)?;
+        let buffered = (data.len() - sent).min(Self::MAX_BUFFERED - self.buffer.len());
+        self.buffer.extend(&data[sent..sent + buffered]);
+        Ok(sent + buffered)
+    }
+
+    /// Pass `data` to the client, returning how much it took.
+    fn send(&self, data: &[u8], now: Instant) -> Res<usize> {
+        let mut client = self.client.borrow_mut();
+        let sent = client.send_data(self.stream_id, data, now)?;
+        if sent < data.len() {
+            client
+                .client_events()
+                .update_watched(self.stream_id, |w| w.writable = false);
+        }
+        Ok(sent)
+    }
+
+    /// Pass buffered data to the client.  Returns `true` when nothing is left
+    /// in the buffer.
+    ///
+    /// # Errors
+    ///
+    /// `StopSending` if the server asked to stop sending, or an HTTP/3 error
+    /// if the stream is gone.
+    pub fn flush(&mut self, now: Instant) -> Result<bool, BodyError> {
+        self.check()?;
+        while !self.buffer.is_empty() {
+            let sent = self.send(self.buffer.as_slices().0, now)?;
+            if sent == 0 {
+                break;
+            }
+            self.buffer.drain(..sent);
+            self.written += u64::try_from(sent).map_err(|_| Error::Internal)?;
+        }
+        if self.close_pending && self.buffer.is_empty() {
+            self.client
+                .borrow_mut()
+                .stream_close_send(self.stream_id, now)?;
+            self.close_pending = false;
+            self.final_size = Some(self.written);
+        }
+        Ok(self.buffer.is_empty())
+    }
+
+    /// The number of bytes that are waiting in the handle.
+    #[must_use]
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Whether the client can probably take more data.  This turns false
+    /// when the client stops taking data, and true again with the next
+    /// [`crate::Http3ClientEvent::DataWritable`].
+    #[must_use]
+    pub fn is_writable(&self) -> bool {
+        self.client
+            .borrow()
+            .client_events()
+            .watched(self.stream_id, |w| w.writable && w.stop_sending.is_none())
+            .unwrap_or(false)
+    }
+
+    /// End the body once the buffered data is passed to the client.
+    ///
+    /// # Errors
+    ///
+    /// As for [`SendBodyHandle::flush`].
+    pub fn close(&mut self, now: Instant) -> Result<(), BodyError> {
+        if self.final_size.is_none() {
+            self.close_pending = true;
+        }
+        self.flush(now).map(|_| ())
+    }
+
+    /// Abandon the request body, discarding any buffered data.
+    ///
+    /// # Errors
+    ///
+    /// When the stream is gone.
+    pub fn reset(&mut self, err: AppError) -> Result<(), BodyError> {
+        self.buffer.clear();
+        self.close_pending = false;
+        self.client
+            .borrow_mut()
+            .stream_reset_send(self.stream_id, err)?;
+        self.final_size = Some(self.written);
+        Ok(())
+    }
+
+    /// The size of the body, not counting HTTP/3 framing, once it is closed
+    /// and all data was passed to the client, or once it is reset.
+    #[must_use]
+    pub const fn final_size(&self) -> Option<u64> {
+        self.final_size
+    }
+}
+
+impl io::Write for SendBodyHandle {
+    /// Returns `WouldBlock` when no data can be taken.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match Self::write(self, buf, Instant::now())? {
+            0 if !buf.is_empty() => Err(io::ErrorKind::WouldBlock.into()),
+            n => Ok(n),
+        }
+    }
+
+    /// Returns `WouldBlock` while data is still buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        if Self::flush(self, Instant::now())? {
+            Ok(())
+        } else {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+}
+
+impl Drop for SendBodyHandle {
+    fn drop(&mut self) {
+        if let Ok(client) = self.client.try_borrow() {
+            client.client_events().unwatch_stream(self.stream_id);
+        }
+    }
+}
+
+/// Reads the body of a response.
+pub struct RecvBodyHandle {
+    client: Rc<RefCell<Http3Client>>,
+    stream_id: StreamId,
+    read: u64,
+    fin: bool,
+}
+
+impl RecvBodyHandle {
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if there is no response to receive on the stream.
+    pub fn new(client: Rc<RefCell<Http3Client>>, stream_id: StreamId) -> Res<Self> {
+        {
+            let c = client.borrow();
+            if !c.has_recv_stream(stream_id) {
+                return Err(Error::InvalidStreamId);
+            }
+            c.client_events().watch_stream(stream_id);
+        }
+        Ok(Self {
+            client,
+            stream_id,
+            read: 0,
+            fin: false,
+        })
+    }
+
+    #[must_use]
+    pub const fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    fn reset_error(&self) -> Option<BodyError> {
+        self.client
+            .borrow()
+            .client_events()
+            .watched(self.stream_id, |w| w.reset)
+            .flatten()
+            .map(BodyError::Reset)
+    }
+
+    /// Read into `buf`.  The returned flag is set when the end of the body
+    /// was reached.
+    ///
+    /// # Errors
+    ///
+    /// `Reset` if the server reset the stream, or an HTTP/3 error if the
+    /// stream is gone.
+    pub fn read(&mut self, buf: &mut [u8], now: Instant) -> Result<(usize, bool), BodyError> {
+        if self.fin {
+            return Ok((0, true));
+        }
+        let res = self.client.borrow_mut().read_data(now, self.stream_id, buf);
+        match res {
+            Ok((n, fin)) => {
+                self.read += u64::try_from(n).map_err(|_| Error::Internal)?;
+                self.fin = fin;
+                if !fin && n < buf.len() {
+                    self.client
+                        .borrow()
+                        .client_events()
+                        .update_watched(self.stream_id, |w| w.readable = false);
+                }
+                Ok((n, fin))
+            }
+            Err(e) => Err(self.reset_error().unwrap_or(BodyError::Http3(e))),
+        }
+    }
+
+    /// Read everything that is available onto the end of `out`.  Returns
+    /// `true` when the end of the body was reached.
+    ///
+    /// # Errors
+    ///
+    /// As for [`RecvBodyHandle::read`].
+    pub fn read_to_end(&mut self, out: &mut Vec<u8>, now: Instant) -> Result<bool, BodyError> {
+        let mut buf = [0; 4096];
+        loop {
+            let (n, fin) = self.read(&mut buf, now)?;
+            out.extend_from_slice(&buf[..n]);
+            if fin || n < buf.len() {
+                return Ok(fin);
+            }
+        }
+    }
+
+    /// Whether [`RecvBodyHandle::read`] would probably return data, the end
+    /// of the body, or an error.  This turns false when a read drains the
+    /// available data, and true again with the next
+    /// [`crate::Http3ClientEvent::DataReadable`].
+    #[must_use]
+    pub fn is_readable(&self) -> bool {
+        !self.fin
+            && self
+                .client
+                .borrow()
+                .client_events()
+                .watched(self.stream_id, |w| w.readable)
+                .unwrap_or(true)
+    }
+
+    /// Whether all of the body was read.
+    #[must_use]
+    pub const fn is_finished(&self) -> bool {
+        self.fin
+    }
+
+    /// The size of the body, not counting HTTP/3 framing, once all of it was
+    /// read.
+    #[must_use]
+    pub const fn final_size(&self) -> Option<u64> {
+        if self.fin { Some(self.read) } else { None }
+    }
+
+    /// Ask the server to stop sending the response.
+    ///
+    /// # Errors
+    ///
+    /// When the stream is gone.
+    pub fn stop_sending(&self, err: AppError) -> Result<(), BodyError> {
+        Ok(self
+            .client
+            .borrow_mut()
+            .stream_stop_sending(self.stream_id, err)?)
+    }
+}
+
+impl io::Read for RecvBodyHandle {
+    /// Returns `WouldBlock` when no data is available yet.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match Self::read(self, buf, Instant::now())? {
+            (0, false) if !buf.is_empty() => Err(io::ErrorKind::WouldBlock.into()),
+            (n, _) => Ok(n),
+        }
+    }
+}
+
+impl Drop for RecvBodyHandle {
+    fn drop(&mut self) {
+        if let Ok(client) = self.client.try_borrow() {
+            client.client_events().unwatch_stream(self.stream_id);
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{cell::RefCell, io, rc::Rc};
+
+    use neqo_common::event::Provider as _;
+    use neqo_crypto::AuthenticationStatus;
+    use test_fixture::{
+        CountingConnectionIdGenerator, DEFAULT_ADDR, DEFAULT_ALPN_H3, DEFAULT_KEYS,
+        DEFAULT_SERVER_NAME, anti_replay, fixture_init, now,
+    };
+
+    use super::{BodyError, RecvBodyHandle, SendBodyHandle};
+    use crate::{
+        Error, Header, Http3Client, Http3ClientEvent, Http3Parameters, Http3Server,
+        Http3ServerEvent, Http3State, Priority, server_events::Http3OrWebTransportStream,
+    };
+
+    fn exchange(client: &Rc<RefCell<Http3Client>>, server: &mut Http3Server) {
+        let mut out = None;
+        loop {
+            out = client.borrow_mut().process(out, now()).dgram();
+            out = server.process(out, now()).dgram();
+            if out.is_none() {
+                break;
+            }
+        }
+    }
+
+    fn connect() -> (Rc<RefCell<Http3Client>>, Http3Server) {
+        fixture_init();
+        let client = Http3Client::new(
+            DEFAULT_SERVER_NAME,
+            Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+            DEFAULT_ADDR,
+            DEFAULT_ADDR,
+            Http3Parameters::default(),
+            now(),
+        )
+        .unwrap();
+        let client = Rc::new(RefCell::new(client));
+        let mut server = Http3Server::new(
+            now(),
+            DEFAULT_KEYS,
+            DEFAULT_ALPN_H3,
+            anti_replay(),
+            Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+            Http3Parameters::default(),
+            None,
+        )
+        .unwrap();
+        exchange(&client, &mut server);
+        let authentication_needed = |e| matches!(e, Http3ClientEvent::AuthenticationNeeded);
+        assert!(client.borrow_mut().events().any(authentication_needed));
+        client
+            .borrow_mut()
+            .authenticated(AuthenticationStatus::Ok, now());
+        exchange(&client, &mut server);
+        assert_eq!(client.borrow().state(), Http3State::Connected);
+        (client, server)
+    }
+
+    fn post(client: &Rc<RefCell<Http3Client>>) -> SendBodyHandle {
+        let stream_id = client
+            .borrow_mut()
+            .fetch(
+                now(),
+                "POST",
+                ("https", "something.com", "/"),
+                &[],
+                Priority::default(),
+            )
+            .unwrap();
+        SendBodyHandle::new(Rc::clone(client), stream_id).unwrap()
+    }
+
+    /// Returns the request stream, all of the request body, and whether it ended.
+    fn request_body(server: &Http3Server) -> (Option<Http3OrWebTransportStream>, Vec<u8>, bool) {
+        let mut request = None;
+        let mut body = Vec::new();
+        let mut fin = false;
+        while let Some(e) = server.next_event() {
+            match e {
+                Http3ServerEvent::Headers { stream, .. } => request = Some(stream),
+                Http3ServerEvent::Data { data, fin: f, .. } => {
+                    body.extend_from_slice(&data);
+                    fin |= f;
+                }
+                _ => {}
+            }
+        }
+        (request, body, fin)
+    }
+
+    #[test]
+    fn partial_write() {
+        let (client, mut server) = connect();
+        let mut send = post(&client);
+
+        // The handle takes what the stream has credit for, plus what fits in
+        // its buffer, and nothing after that.
+        let data = vec![0x61; 1 << 22];
+        let taken = send.write(&data, now()).unwrap();
+        assert!(taken > SendBodyHandle::MAX_BUFFERED);
+        assert!(taken < data.len());
+        assert_eq!(send.buffered(), SendBodyHandle::MAX_BUFFERED);
+        assert!(!send.is_writable());
+        assert_eq!(send.write(&data[taken..], now()), Ok(0));
+        let err = io::Write::write(&mut send, &data[taken..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        // Once the server has read the data, there is credit for the rest.
+        exchange(&client, &mut server);
+        let (_, body, fin) = request_body(&server);
+        assert!(!fin);
+        assert!(send.is_writable());
+        assert!(send.flush(now()).unwrap());
+        assert_eq!(send.buffered(), 0);
+        send.close(now()).unwrap();
+        exchange(&client, &mut server);
+        let (_, rest, fin) = request_body(&server);
+        assert!(fin);
+        assert_eq!(body.len() + rest.len(), taken);
+        assert_eq!(send.final_size(), Some(u64::try_from(taken).unwrap()));
+    }
+
+    #[test]
+    fn close_with_buffered_data() {
+        let (client, mut server) = connect();
+        let mut send = post(&client);
+
+        let data = vec![0x62; 1 << 22];
+        let taken = send.write(&data, now()).unwrap();
+        // The body ends only after the buffered data is passed on.
+        send.close(now()).unwrap();
+        assert_eq!(send.final_size(), None);
+        assert_eq!(send.write(b"x", now()), Err(Error::AlreadyClosed.into()));
+
+        let mut received = 0;
+        loop {
+            exchange(&client, &mut server);
+            let (_, body, fin) = request_body(&server);
+            received += body.len();
+            if fin {
+                break;
+            }
+            send.flush(now()).unwrap();
+        }
+        assert_eq!(received, taken);
+        assert_eq!(send.final_size(), Some(u64::try_from(taken).unwrap()));
+    }
+
+    #[test]
+    fn read_after_reset() {
+        let (client, mut server) = connect();
+        let mut send = post(&client);
+        let stream_id = send.stream_id();
+        send.close(now()).unwrap();
+        exchange(&client, &mut server);
+        let (request, _, _) = request_body(&server);
+        let request = request.unwrap();
+
+        let mut recv = RecvBodyHandle::new(Rc::clone(&client), stream_id).unwrap();
+        request
+            .send_headers(&[Header::new(":status", "200")])
+            .unwrap();
+        request.send_data(b"abc", now()).unwrap();
+        exchange(&client, &mut server);
+        let mut buf = [0; 10];
+        assert_eq!(recv.read(&mut buf, now()), Ok((3, false)));
+        assert_eq!(&buf[..3], b"abc");
+
+        request
+            .stream_reset_send(Error::HttpRequestCancelled.code())
+            .unwrap();
+        exchange(&client, &mut server);
+        assert!(recv.is_readable());
+        assert_eq!(
+            recv.read(&mut buf, now()),
+            Err(BodyError::Reset(Error::HttpRequestCancelled.code()))
+        );
+        assert!(!recv.is_finished());
+        assert_eq!(recv.final_size(), None);
+    }
+}
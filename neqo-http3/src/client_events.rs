@@ -6,16 +6,17 @@
 
 use std::{cell::RefCell, collections::VecDeque, rc::Rc};
 
-use neqo_common::{Bytes, Header, event::Provider as EventProvider, qtrace};
+use neqo_common::{Bytes, Header, event::Provider as EventProvider};
 use neqo_crypto::ResumptionToken;
 use neqo_transport::{AppError, StreamId, StreamType};
+use rustc_hash::FxHashMap as HashMap;
 
 use crate::{
     CloseType, Error, Http3StreamInfo, HttpRecvStreamEvents, PushId, RecvStreamEvents, Res,
     SendStreamEvents,
     connection::Http3State,
     features::extended_connect::{self, ExtendedConnectEvents, ExtendedConnectType},
-    settings::HSettingType,
+    frames::{Capsule, ConnectIpFrame},
 };
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -42,6 +43,8 @@ pub enum WebTransportEvent {
         session_id: StreamId,
         datagram: Bytes,
     },
+    /// The server asked the client to stop using the session.
+    SessionDraining { session_id: StreamId },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -60,10 +63,55 @@ pub enum ConnectUdpEvent {
         reason: extended_connect::session::CloseReason,
         headers: Option<Vec<Header>>,
     },
+    /// A UDP payload.
     Datagram {
         session_id: StreamId,
         datagram: Bytes,
     },
+    /// A datagram with a context ID other than 0, which was registered with
+    /// [`Http3Client::connect_udp_register_context`] or
+    /// [`Http3Client::connect_udp_register_peer_context`].
+    ///
+    /// [`Http3Client::connect_udp_register_context`]: crate::Http3Client::connect_udp_register_context
+    /// [`Http3Client::connect_udp_register_peer_context`]: crate::Http3Client::connect_udp_register_peer_context
+    ContextDatagram {
+        session_id: StreamId,
+        context_id: u64,
+        datagram: Bytes,
+    },
+    /// A capsule that CONNECT-UDP does not handle, for use by extensions.
+    Capsule {
+        session_id: StreamId,
+        capsule: Capsule,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ConnectIpEvent {
+    Negotiated(
+        /// Whether CONNECT-IP was negotiated.
+        bool,
+    ),
+    NewSession {
+        stream_id: StreamId,
+        status: u16,
+        headers: Vec<Header>,
+    },
+    SessionClosed {
+        stream_id: StreamId,
+        reason: extended_connect::session::CloseReason,
+        headers: Option<Vec<Header>>,
+    },
+    /// An IP packet.
+    Datagram {
+        session_id: StreamId,
+        datagram: Bytes,
+    },
+    /// An `ADDRESS_ASSIGN`, `ADDRESS_REQUEST` or `ROUTE_ADVERTISEMENT` capsule.
+    Capsule {
+        session_id: StreamId,
+        capsule: ConnectIpFrame,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -130,16 +178,45 @@ pub enum Http3ClientEvent {
     WebTransport(WebTransportEvent),
     /// `ConnectUdp` events
     ConnectUdp(ConnectUdpEvent),
+    /// `ConnectIp` events
+    ConnectIp(ConnectIpEvent),
+}
+
+/// The state of a stream that has body handles.
+#[derive(Debug)]
+pub struct WatchedStream {
+    pub handles: usize,
+    pub readable: bool,
+    pub writable: bool,
+    pub reset: Option<AppError>,
+    pub stop_sending: Option<AppError>,
+}
+
+impl Default for WatchedStream {
+    /// Data might have arrived, or space become available, before the first
+    /// handle was made, so start out assuming both.
+    fn default() -> Self {
+        Self {
+            handles: 0,
+            readable: true,
+            writable: true,
+            reset: None,
+            stop_sending: None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct Http3ClientEvents {
     events: Rc<RefCell<VecDeque<Http3ClientEvent>>>,
+    /// Streams that have body handles, which are told about events on them.
+    watched: Rc<RefCell<HashMap<StreamId, WatchedStream>>>,
 }
 
 impl RecvStreamEvents for Http3ClientEvents {
     /// Add a new `DataReadable` event
     fn data_readable(&self, stream_info: &Http3StreamInfo) {
+        self.update_watched(stream_info.stream_id(), |w| w.readable = true);
         self.insert(Http3ClientEvent::DataReadable {
             stream_id: stream_info.stream_id(),
         });
@@ -155,6 +232,10 @@ impl RecvStreamEvents for Http3ClientEvents {
             }
             CloseType::Done => return,
             CloseType::ResetRemote(e) => {
+                self.update_watched(stream_id, |w| {
+                    w.readable = true;
+                    w.reset = Some(e);
+                });
                 self.remove_recv_stream_events(stream_id);
                 (false, e)
             }
@@ -193,6 +274,7 @@ impl HttpRecvStreamEvents for Http3ClientEvents {
 impl SendStreamEvents for Http3ClientEvents {
     /// Add a new `DataWritable` event.
     fn data_writable(&self, stream_info: &Http3StreamInfo) {
+        self.update_watched(stream_info.stream_id(), |w| w.writable = true);
         self.insert(Http3ClientEvent::DataWritable {
             stream_id: stream_info.stream_id(),
         });
@@ -202,6 +284,7 @@ impl SendStreamEvents for Http3ClientEvents {
         let stream_id = stream_info.stream_id();
         self.remove_send_stream_events(stream_id);
         if let CloseType::ResetRemote(error) = close_type {
+            self.update_watched(stream_id, |w| w.stop_sending = Some(error));
             self.insert(Http3ClientEvent::StopSending { stream_id, error });
         }
     }
@@ -232,6 +315,13 @@ impl ExtendedConnectEvents for Http3ClientEvents {
                     headers,
                 }));
             }
+            ExtendedConnectType::ConnectIp => {
+                self.insert(Http3ClientEvent::ConnectIp(ConnectIpEvent::NewSession {
+                    stream_id,
+                    status,
+                    headers,
+                }));
+            }
         }
     }
 
@@ -257,6 +347,13 @@ impl ExtendedConnectEvents for Http3ClientEvents {
                     headers,
                 })
             }
+            ExtendedConnectType::ConnectIp => {
+                Http3ClientEvent::ConnectIp(ConnectIpEvent::SessionClosed {
+                    stream_id,
+                    reason,
+                    headers,
+                })
+            }
         };
         self.insert(event);
     }
@@ -299,9 +396,45 @@ impl ExtendedConnectEvents for Http3ClientEvents {
                     datagram,
                 })
             }
+            ExtendedConnectType::ConnectIp => {
+                Http3ClientEvent::ConnectIp(ConnectIpEvent::Datagram {
+                    session_id,
+                    datagram,
+                })
+            }
         };
         self.insert(event);
     }
+
+    fn connect_udp_context_datagram(&self, session_id: StreamId, context_id: u64, datagram: Bytes) {
+        self.insert(Http3ClientEvent::ConnectUdp(
+            ConnectUdpEvent::ContextDatagram {
+                session_id,
+                context_id,
+                datagram,
+            },
+        ));
+    }
+
+    fn connect_udp_capsule(&self, session_id: StreamId, capsule: Capsule) {
+        self.insert(Http3ClientEvent::ConnectUdp(ConnectUdpEvent::Capsule {
+            session_id,
+            capsule,
+        }));
+    }
+
+    fn connect_ip_capsule(&self, session_id: StreamId, capsule: ConnectIpFrame) {
+        self.insert(Http3ClientEvent::ConnectIp(ConnectIpEvent::Capsule {
+            session_id,
+            capsule,
+        }));
+    }
+
+    fn webtransport_session_draining(&self, session_id: StreamId) {
+        self.insert(Http3ClientEvent::WebTransport(
+            WebTransportEvent::SessionDraining { session_id },
+        ));
+    }
 }
 
 impl Http3ClientEvents {
@@ -383,6 +516,40 @@ impl Http3ClientEvents {
     }
 
     /// Remove all events for a stream
+    pub(crate) fn watch_stream(&self, stream_id: StreamId) {
+        self.watched
+            .borrow_mut()
+            .entry(stream_id)
+            .or_default()
+            .handles += 1;
+    }
+
+    pub(crate) fn unwatch_stream(&self, stream_id: StreamId) {
+        let mut watched = self.watched.borrow_mut();
+        if let Some(w) = watched.get_mut(&stream_id) {
+            w.handles -= 1;
+            if w.handles == 0 {
+                watched.remove(&stream_id);
+            }
+        }
+    }
+
+    pub(crate) fn update_watched<F>(&self, stream_id: StreamId, f: F)
+    where
+        F: FnOnce(&mut WatchedStream),
+    {
+        if let Some(w) = self.watched.borrow_mut().get_mut(&stream_id) {
+            f(w);
+        }
+    }
+
+    pub(crate) fn watched<T, F>(&self, stream_id: StreamId, f: F) -> Option<T>
+    where
+        F: FnOnce(&WatchedStream) -> T,
+    {
+        self.watched.borrow().get(&stream_id).map(f)
+    }
+
     fn remove_recv_stream_events(&self, stream_id: StreamId) {
         self.remove(|evt| {
             matches!(evt,
@@ -420,19 +587,23 @@ impl Http3ClientEvents {
         });
     }
 
-    pub fn negotiation_done(&self, feature_type: HSettingType, succeeded: bool) {
+    pub(crate) fn negotiation_done(&self, feature_type: ExtendedConnectType, succeeded: bool) {
         match feature_type {
-            HSettingType::EnableWebTransport => {
+            ExtendedConnectType::WebTransport => {
                 self.insert(Http3ClientEvent::WebTransport(
                     WebTransportEvent::Negotiated(succeeded),
                 ));
             }
-            HSettingType::EnableConnect => {
+            ExtendedConnectType::ConnectUdp => {
                 self.insert(Http3ClientEvent::ConnectUdp(ConnectUdpEvent::Negotiated(
                     succeeded,
                 )));
             }
-            _ => qtrace!("HSetting {feature_type:?} {succeeded} not handled"),
+            ExtendedConnectType::ConnectIp => {
+                self.insert(Http3ClientEvent::ConnectIp(ConnectIpEvent::Negotiated(
+                    succeeded,
+                )));
+            }
         }
     }
 }
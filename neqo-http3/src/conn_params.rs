@@ -4,7 +4,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::cmp::min;
+use std::{cmp::min, time::Duration};
 
 use neqo_common::qwarn;
 use neqo_qpack as qpack;
@@ -18,6 +18,8 @@ const WEBTRANSPORT_DEFAULT: bool = false;
 /// Do not support HTTP Extended CONNECT by default.
 const CONNECT_DEFAULT: bool = false;
 const HTTP3_DATAGRAM_DEFAULT: bool = true;
+/// How long a server waits for requests to complete after it sends `GOAWAY`.
+const DRAIN_TIMEOUT_DEFAULT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone)]
 pub struct Http3Parameters {
@@ -28,6 +30,11 @@ pub struct Http3Parameters {
     /// HTTP Extended CONNECT
     connect: bool,
     http3_datagram: bool,
+    drain_timeout: Duration,
+    webtransport_max_data: Option<u64>,
+    webtransport_max_streams_bidi: Option<u64>,
+    webtransport_max_streams_uni: Option<u64>,
+    origins: Vec<String>,
 }
 
 impl Default for Http3Parameters {
@@ -43,6 +50,11 @@ impl Default for Http3Parameters {
             webtransport: WEBTRANSPORT_DEFAULT,
             connect: CONNECT_DEFAULT,
             http3_datagram: HTTP3_DATAGRAM_DEFAULT,
+            drain_timeout: DRAIN_TIMEOUT_DEFAULT,
+            webtransport_max_data: None,
+            webtransport_max_streams_bidi: None,
+            webtransport_max_streams_uni: None,
+            origins: Vec::new(),
         }
     }
 }
@@ -153,6 +165,74 @@ impl Http3Parameters {
         }
         self.http3_datagram
     }
+
+    /// Set how long a server lets requests run after a graceful shutdown
+    /// starts, before it closes the connection.
+    #[must_use]
+    pub const fn drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    #[must_use]
+    pub const fn get_drain_timeout(&self) -> Duration {
+        self.drain_timeout
+    }
+
+    /// Limit the amount of stream data that the peer can send in each
+    /// `WebTransport` session.  The limit is raised as the application reads
+    /// data.  By default, sessions are only limited by the connection.
+    #[must_use]
+    pub const fn webtransport_max_data(mut self, max_data: u64) -> Self {
+        self.webtransport_max_data = Some(max_data);
+        self
+    }
+
+    #[must_use]
+    pub const fn get_webtransport_max_data(&self) -> Option<u64> {
+        self.webtransport_max_data
+    }
+
+    /// Limit the number of bidirectional streams that the peer can have open
+    /// in each `WebTransport` session.
+    #[must_use]
+    pub const fn webtransport_max_streams_bidi(mut self, max_streams: u64) -> Self {
+        self.webtransport_max_streams_bidi = Some(max_streams);
+        self
+    }
+
+    #[must_use]
+    pub const fn get_webtransport_max_streams_bidi(&self) -> Option<u64> {
+        self.webtransport_max_streams_bidi
+    }
+
+    /// Limit the number of unidirectional streams that the peer can have open
+    /// in each `WebTransport` session.
+    #[must_use]
+    pub const fn webtransport_max_streams_uni(mut self, max_streams: u64) -> Self {
+        self.webtransport_max_streams_uni = Some(max_streams);
+        self
+    }
+
+    #[must_use]
+    pub const fn get_webtransport_max_streams_uni(&self) -> Option<u64> {
+        self.webtransport_max_streams_uni
+    }
+
+    /// The origins that a server advertises in an ORIGIN frame, such as
+    /// `https://example.com` or `https://example.com:8443`.  Clients can send
+    /// requests for any of these on the connection, if the server certificate
+    /// is also valid for them.  No ORIGIN frame is sent if this is empty.
+    #[must_use]
+    pub fn origins(mut self, origins: Vec<String>) -> Self {
+        self.origins = origins;
+        self
+    }
+
+    #[must_use]
+    pub fn get_origins(&self) -> &[String] {
+        &self.origins
+    }
 }
 
 #[cfg(test)]
@@ -13,7 +13,7 @@ use std::{
 };
 
 use neqo_common::{
-    Bytes, Decoder, Header, MessageType, Role, qdebug, qerror, qinfo, qtrace, qwarn,
+    Bytes, Decoder, Encoder, Header, MessageType, Role, qdebug, qerror, qinfo, qtrace, qwarn,
 };
 use neqo_qpack as qpack;
 use neqo_transport::{
@@ -34,11 +34,13 @@ use crate::{
         ConnectType,
         extended_connect::{
             self, ExtendedConnectEvents, ExtendedConnectFeature, ExtendedConnectType,
+            webtransport_session::{Limits, SessionLimits, WT_FLOW_CONTROL_ERROR},
             webtransport_streams::{WebTransportRecvStream, WebTransportSendStream},
         },
     },
-    frames::HFrame,
+    frames::{Capsule, ConnectIpFrame, HFrame},
     push_controller::PushController,
+    qlog,
     qpack_decoder_receiver::DecoderRecvStream,
     qpack_encoder_receiver::EncoderRecvStream,
     recv_message::{RecvMessage, RecvMessageInfo},
@@ -251,8 +253,8 @@ impl Http3State {
 /// data. [`extended_connect::session::Session`] sets a [`HttpRecvStreamEvents`]
 /// listener as the [`RecvMessage`] event listener.
 ///
-/// `neqo_http3` implements the WebTransport and MASQUE connect-udp HTTP
-/// Extended CONNECT protocol using [`extended_connect::session::Session`].
+/// `neqo_http3` implements the WebTransport, MASQUE connect-udp and MASQUE
+/// connect-ip HTTP Extended CONNECT protocols using [`extended_connect::session::Session`].
 ///
 /// The WebTransport HTTP Extended CONNECT protocol supports streams.
 /// [`WebTransportSendStream`] and [`WebTransportRecvStream`] are associated
@@ -297,6 +299,7 @@ pub struct Http3Connection {
     recv_streams: HashMap<StreamId, Box<dyn RecvStream>>,
     webtransport: ExtendedConnectFeature,
     connect_udp: ExtendedConnectFeature,
+    connect_ip: ExtendedConnectFeature,
 }
 
 impl Display for Http3Connection {
@@ -326,6 +329,10 @@ impl Http3Connection {
                 ExtendedConnectType::ConnectUdp,
                 conn_params.get_connect(),
             ),
+            connect_ip: ExtendedConnectFeature::new(
+                ExtendedConnectType::ConnectIp,
+                conn_params.get_connect(),
+            ),
             local_params: conn_params,
             settings_state: Http3RemoteSettingsState::NotReceived,
             streams_with_pending_data: HashSet::default(),
@@ -338,30 +345,52 @@ impl Http3Connection {
     /// Listener for non-default feature negotiation. No-op when feature is
     /// disabled. This is currently only used for the
     /// [`crate::features::extended_connect::webtransport_session`] and
-    /// [`crate::features::extended_connect::connect_udp_session`] feature.  The
+    /// [`crate::features::extended_connect::connect_udp_session`] and
+    /// [`crate::features::extended_connect::connect_ip_session`] features.  The
     /// negotiation is done via the `SETTINGS` frame and when the peer's
     /// `SETTINGS` frame has been received the listener will be called.
     pub(crate) fn set_features_listener(&mut self, feature_listener: Http3ClientEvents) {
         self.webtransport.set_listener(feature_listener.clone());
-        self.connect_udp.set_listener(feature_listener);
+        self.connect_udp.set_listener(feature_listener.clone());
+        self.connect_ip.set_listener(feature_listener);
     }
 
     /// This function creates and initializes, i.e. send stream type, the control and qpack
     /// streams.
-    fn initialize_http3_connection(&mut self, conn: &mut Connection) -> Res<()> {
+    fn initialize_http3_connection(&mut self, conn: &mut Connection, now: Instant) -> Res<()> {
         qdebug!("[{self}] Initialize the http3 connection");
+        self.qpack_decoder
+            .borrow_mut()
+            .set_qlog(conn.qlog_mut().clone());
         self.control_stream_local.create(conn)?;
+        if let Some(stream_id) = self.control_stream_local.stream_id() {
+            qlog::h3_stream_type_set(
+                conn.qlog_mut(),
+                qlog::H3Owner::Local,
+                stream_id,
+                qlog::H3StreamType::Control,
+                None,
+                Some(now),
+            );
+        }
 
-        self.send_settings();
-        self.create_qpack_streams(conn)?;
+        self.send_settings(conn, now);
+        self.create_qpack_streams(conn, now)?;
         Ok(())
     }
 
-    fn send_settings(&mut self) {
+    fn send_settings(&mut self, conn: &mut Connection, now: Instant) {
         qdebug!("[{self}] Send settings");
-        self.control_stream_local.queue_frame(&HFrame::Settings {
-            settings: HSettings::from(&self.local_params),
-        });
+        let settings = HSettings::from(&self.local_params);
+        qlog::h3_parameters_set(conn.qlog_mut(), qlog::H3Owner::Local, &settings, now);
+        self.control_stream_local
+            .queue_frame(&HFrame::Settings { settings });
+        let origins = self.local_params.get_origins();
+        if self.role == Role::Server && !origins.is_empty() {
+            self.control_stream_local.queue_frame(&HFrame::Origin {
+                origins: origins.to_vec(),
+            });
+        }
         self.control_stream_local.queue_frame(&HFrame::Grease);
     }
 
@@ -370,14 +399,29 @@ impl Http3Connection {
         HttpZeroRttChecker::save(&self.local_params)
     }
 
-    fn create_qpack_streams(&self, conn: &mut Connection) -> Res<()> {
+    fn create_qpack_streams(&self, conn: &mut Connection, now: Instant) -> Res<()> {
         qdebug!("[{self}] create_qpack_streams");
+        let encoder_stream_id = conn.stream_create(StreamType::UniDi)?;
         self.qpack_encoder
             .borrow_mut()
-            .add_send_stream(conn.stream_create(StreamType::UniDi)?);
+            .add_send_stream(encoder_stream_id);
+        let decoder_stream_id = conn.stream_create(StreamType::UniDi)?;
         self.qpack_decoder
             .borrow_mut()
-            .add_send_stream(conn.stream_create(StreamType::UniDi)?);
+            .add_send_stream(decoder_stream_id);
+        for (stream_id, stream_type) in [
+            (encoder_stream_id, qlog::H3StreamType::QpackEncode),
+            (decoder_stream_id, qlog::H3StreamType::QpackDecode),
+        ] {
+            qlog::h3_stream_type_set(
+                conn.qlog_mut(),
+                qlog::H3Owner::Local,
+                stream_id,
+                stream_type,
+                None,
+                Some(now),
+            );
+        }
         Ok(())
     }
 
@@ -443,8 +487,9 @@ impl Http3Connection {
         &mut self,
         conn: &mut Connection,
         settings: HSettings,
+        now: Instant,
     ) -> Res<()> {
-        self.initialize_http3_connection(conn)?;
+        self.initialize_http3_connection(conn, now)?;
         self.set_qpack_settings(&settings)?;
         self.settings_state = Http3RemoteSettingsState::ZeroRtt(settings);
         self.state = Http3State::ZeroRtt;
@@ -460,6 +505,18 @@ impl Http3Connection {
         }
     }
 
+    /// The flow control limits for a new session.
+    fn session_limits(&self) -> SessionLimits {
+        SessionLimits {
+            local: Limits::from_params(&self.local_params),
+            remote: match &self.settings_state {
+                Http3RemoteSettingsState::Received(settings)
+                | Http3RemoteSettingsState::ZeroRtt(settings) => Limits::from_settings(settings),
+                Http3RemoteSettingsState::NotReceived => Limits::default(),
+            },
+        }
+    }
+
     /// This is called when a [`neqo_transport::ConnectionEvent::NewStream`]
     /// event is received.  This registers the stream with a
     /// [`NewStreamHeadReader`] handler.
@@ -483,10 +540,19 @@ impl Http3Connection {
         qtrace!("[{self}] Readable stream {stream_id}");
 
         if let Some(recv_stream) = self.recv_streams.get_mut(&stream_id) {
+            let session_id = match recv_stream.stream_type() {
+                Http3StreamType::WebTransport(session_id) => Some(session_id),
+                Http3StreamType::ExtendedConnect => Some(stream_id),
+                _ => None,
+            };
             let res = recv_stream.receive(conn, now);
-            return self
+            let res = self
                 .handle_stream_manipulation_output(res, stream_id, conn)
                 .map(|(output, _)| output);
+            if let Some(session_id) = session_id {
+                self.extended_connect_update(conn, session_id);
+            }
+            return res;
         }
         Ok(ReceiveOutput::NoOutput)
     }
@@ -539,7 +605,7 @@ impl Http3Connection {
             ReceiveOutput::ControlFrames(control_frames) => {
                 let mut rest = Vec::new();
                 for cf in control_frames {
-                    if let Some(not_handled) = self.handle_control_frame(cf)? {
+                    if let Some(not_handled) = self.handle_control_frame(cf, conn, now)? {
                         rest.push(not_handled);
                     }
                 }
@@ -591,6 +657,7 @@ impl Http3Connection {
         &mut self,
         conn: &mut Connection,
         state: &State,
+        now: Instant,
     ) -> Res<bool> {
         qdebug!("[{self}] Handle state change {state:?}");
         match state {
@@ -599,7 +666,7 @@ impl Http3Connection {
                     && conn.zero_rtt_state() == ZeroRttState::AcceptedServer
                 {
                     self.state = Http3State::ZeroRtt;
-                    self.initialize_http3_connection(conn)?;
+                    self.initialize_http3_connection(conn, now)?;
                     Ok(true)
                 } else {
                     Ok(false)
@@ -611,7 +678,7 @@ impl Http3Connection {
                     Http3State::Initializing | Http3State::ZeroRtt
                 ));
                 if self.state == Http3State::Initializing {
-                    self.initialize_http3_connection(conn)?;
+                    self.initialize_http3_connection(conn, now)?;
                 }
                 self.state = Http3State::Connected;
                 Ok(true)
@@ -707,6 +774,25 @@ impl Http3Connection {
         stream_id: StreamId,
         now: Instant,
     ) -> Res<ReceiveOutput> {
+        let (qlog_stream_type, push_id) = match stream_type {
+            NewStreamType::Control => (qlog::H3StreamType::Control, None),
+            NewStreamType::Push(push_id) => (qlog::H3StreamType::Push, Some(push_id)),
+            NewStreamType::Decoder => (qlog::H3StreamType::QpackEncode, None),
+            NewStreamType::Encoder => (qlog::H3StreamType::QpackDecode, None),
+            NewStreamType::Http(_) => (qlog::H3StreamType::Request, None),
+            NewStreamType::WebTransportStream(_) | NewStreamType::Unknown => {
+                (qlog::H3StreamType::Unknown, None)
+            }
+        };
+        qlog::h3_stream_type_set(
+            conn.qlog_mut(),
+            qlog::H3Owner::Remote,
+            stream_id,
+            qlog_stream_type,
+            push_id,
+            Some(now),
+        );
+
         match stream_type {
             NewStreamType::Control => {
                 self.check_stream_exists(Http3StreamType::Control)?;
@@ -950,6 +1036,14 @@ impl Http3Connection {
             Http3StreamType::Http
         };
 
+        qlog::h3_stream_type_set(
+            conn.qlog_mut(),
+            qlog::H3Owner::Local,
+            stream_id,
+            qlog::H3StreamType::Request,
+            None,
+            Some(now),
+        );
         let mut send_message = SendMessage::new(
             MessageType::Request,
             stream_type,
@@ -1005,12 +1099,17 @@ impl Http3Connection {
         now: Instant,
     ) -> Res<(usize, bool)> {
         qdebug!("[{self}] read_data from stream {stream_id}");
-        let res = self
+        let recv_stream = self
             .recv_streams
             .get_mut(&stream_id)
-            .ok_or(Error::InvalidStreamId)?
-            .read_data(conn, buf, now);
-        self.handle_stream_manipulation_output(res, stream_id, conn)
+            .ok_or(Error::InvalidStreamId)?;
+        let stream_type = recv_stream.stream_type();
+        let res = recv_stream.read_data(conn, buf, now);
+        let res = self.handle_stream_manipulation_output(res, stream_id, conn);
+        if let Http3StreamType::WebTransport(session_id) = stream_type {
+            self.extended_connect_update(conn, session_id);
+        }
+        res
     }
 
     /// This is called when an application resets a stream.
@@ -1032,6 +1131,29 @@ impl Http3Connection {
         Ok(())
     }
 
+    /// This is called when an application resets a `WebTransport` stream, but wants
+    /// the first `reliable_size` bytes of application data to be delivered.
+    pub fn stream_reset_send_at(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: StreamId,
+        error: AppError,
+        reliable_size: u64,
+    ) -> Res<()> {
+        qinfo!(
+            "[{self}] Reset sending side of stream {stream_id} error={error} reliable={reliable_size}"
+        );
+
+        let offset = self
+            .send_streams
+            .get(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .reliable_offset(reliable_size)?;
+        conn.stream_reset_send_at(stream_id, error, offset)?;
+        self.close_send(stream_id, CloseType::ResetApp(error), conn);
+        Ok(())
+    }
+
     pub fn stream_stop_sending(
         &mut self,
         conn: &mut Connection,
@@ -1094,7 +1216,9 @@ impl Http3Connection {
             (Some(s), None) => {
                 if !matches!(
                     s.stream_type(),
-                    Http3StreamType::Http | Http3StreamType::ExtendedConnect
+                    Http3StreamType::Http
+                        | Http3StreamType::Push
+                        | Http3StreamType::ExtendedConnect
                 ) {
                     return Err(Error::InvalidStreamId);
                 }
@@ -1147,11 +1271,15 @@ impl Http3Connection {
         // The following function may return InvalidStreamId from the transport layer if the stream
         // has been closed already. It is ok to ignore it here.
         drop(send_stream.close(conn, now));
+        let stream_type = send_stream.stream_type();
         if send_stream.done() {
             self.remove_send_stream(stream_id, conn);
         } else if send_stream.has_data_to_send() {
             self.streams_with_pending_data.insert(stream_id);
         }
+        if let Http3StreamType::WebTransport(session_id) = stream_type {
+            self.extended_connect_update(conn, session_id);
+        }
         Ok(())
     }
 
@@ -1201,6 +1329,29 @@ impl Http3Connection {
         )
     }
 
+    pub fn connect_ip_create_session<T>(
+        &mut self,
+        conn: &mut Connection,
+        events: Box<dyn ExtendedConnectEvents>,
+        target: T,
+        headers: &[Header],
+    ) -> Res<StreamId>
+    where
+        T: RequestTarget,
+    {
+        qinfo!("[{self}] Create ConnectIp");
+        if !self.connect_ip_enabled() {
+            return Err(Error::Unavailable);
+        }
+        self.extended_connect_create_session(
+            conn,
+            events,
+            target,
+            headers,
+            ExtendedConnectType::ConnectIp,
+        )
+    }
+
     pub fn extended_connect_create_session<T>(
         &mut self,
         conn: &mut Connection,
@@ -1221,6 +1372,7 @@ impl Http3Connection {
             Rc::clone(&self.qpack_encoder),
             Rc::clone(&self.qpack_decoder),
             connect_type,
+            &self.session_limits(),
         )));
         self.add_streams(
             id,
@@ -1286,6 +1438,28 @@ impl Http3Connection {
         )
     }
 
+    pub(crate) fn connect_ip_session_accept(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: StreamId,
+        events: Box<dyn ExtendedConnectEvents>,
+        accept_res: &SessionAcceptAction,
+        now: Instant,
+    ) -> Res<()> {
+        qtrace!("Respond to ConnectIp session with accept={accept_res}");
+        if !self.connect_ip_enabled() {
+            return Err(Error::Unavailable);
+        }
+        self.extended_connect_session_accept(
+            conn,
+            stream_id,
+            events,
+            accept_res,
+            ExtendedConnectType::ConnectIp,
+            now,
+        )
+    }
+
     fn extended_connect_session_accept(
         &mut self,
         conn: &mut Connection,
@@ -1336,6 +1510,7 @@ impl Http3Connection {
                     .send_headers(&[Header::new(":status", "200")], conn)
                     .is_ok()
                 {
+                    let limits = self.session_limits();
                     let extended_conn = Rc::new(RefCell::new(
                         extended_connect::session::Session::new_with_http_streams(
                             stream_id,
@@ -1348,6 +1523,7 @@ impl Http3Connection {
                                 .remove(&stream_id)
                                 .ok_or(Error::Internal)?,
                             connect_type,
+                            &limits,
                         )?,
                     ));
                     self.add_streams(
@@ -1389,6 +1565,125 @@ impl Http3Connection {
         self.extended_connect_close_session(conn, session_id, error, message, now)
     }
 
+    pub(crate) fn connect_ip_close_session(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        error: u32,
+        message: &str,
+        now: Instant,
+    ) -> Res<()> {
+        qtrace!("Close ConnectIp session {session_id:?}");
+        self.extended_connect_close_session(conn, session_id, error, message, now)
+    }
+
+    pub(crate) fn connect_ip_send_capsule(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        capsule: &ConnectIpFrame,
+        now: Instant,
+    ) -> Res<()> {
+        qtrace!("Send capsule on ConnectIp session {session_id:?}: {capsule:?}");
+        let mut buf = Encoder::default();
+        capsule.encode(&mut buf);
+        self.recv_streams
+            .get_mut(&session_id)
+            .ok_or(Error::InvalidStreamId)?
+            .extended_connect_session()
+            .ok_or(Error::InvalidStreamId)?
+            .borrow_mut()
+            .send_capsule(conn, ExtendedConnectType::ConnectIp, buf.as_ref(), now)?;
+        if self
+            .send_streams
+            .get(&session_id)
+            .is_some_and(|s| s.has_data_to_send())
+        {
+            self.streams_with_pending_data.insert(session_id);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn connect_udp_send_capsule(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        capsule: &Capsule,
+        now: Instant,
+    ) -> Res<()> {
+        qtrace!("Send capsule on ConnectUdp session {session_id:?}: {capsule:?}");
+        let mut buf = Encoder::default();
+        capsule.encode(&mut buf);
+        self.recv_streams
+            .get_mut(&session_id)
+            .ok_or(Error::InvalidStreamId)?
+            .extended_connect_session()
+            .ok_or(Error::InvalidStreamId)?
+            .borrow_mut()
+            .send_capsule(conn, ExtendedConnectType::ConnectUdp, buf.as_ref(), now)?;
+        if self
+            .send_streams
+            .get(&session_id)
+            .is_some_and(|s| s.has_data_to_send())
+        {
+            self.streams_with_pending_data.insert(session_id);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn webtransport_drain_session(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        now: Instant,
+    ) -> Res<()> {
+        qtrace!("Drain WebTransport session {session_id:?}");
+        let session = self
+            .recv_streams
+            .get(&session_id)
+            .ok_or(Error::InvalidStreamId)?
+            .extended_connect_session()
+            .ok_or(Error::InvalidStreamId)?;
+        if session.borrow().connect_type() != ExtendedConnectType::WebTransport {
+            return Err(Error::InvalidStreamId);
+        }
+        session.borrow_mut().drain_session(conn, now)?;
+        self.extended_connect_update(conn, session_id);
+        Ok(())
+    }
+
+    /// Follow up on a change to a session, or to one of its streams.  The
+    /// session might have capsules to send or streams that can send again,
+    /// or it needs to be reset because the peer exceeded a flow control limit.
+    pub(crate) fn extended_connect_update(&mut self, conn: &mut Connection, session_id: StreamId) {
+        let Some(session) = self
+            .recv_streams
+            .get(&session_id)
+            .and_then(|s| s.extended_connect_session())
+        else {
+            return;
+        };
+        if let Some(error) = session.borrow_mut().take_reset() {
+            drop(conn.stream_reset_send(session_id, error));
+            drop(conn.stream_stop_sending(session_id, error));
+            self.remove_recv_stream(session_id, conn);
+            return;
+        }
+        let unblocked = session.borrow_mut().take_unblocked_streams();
+        for stream_id in unblocked {
+            if let Some(s) = self.send_streams.get(&stream_id) {
+                s.stream_writable();
+            }
+        }
+        if self
+            .send_streams
+            .get(&session_id)
+            .is_some_and(|s| s.has_data_to_send())
+        {
+            self.streams_with_pending_data.insert(session_id);
+        }
+    }
+
     fn extended_connect_close_session(
         &mut self,
         conn: &mut Connection,
@@ -1431,6 +1726,10 @@ impl Http3Connection {
         if !wt.borrow().is_active() {
             return Err(Error::InvalidStreamId);
         }
+        if !wt.borrow_mut().stream_creatable(stream_type) {
+            self.extended_connect_update(conn, session_id);
+            return Err(Error::StreamLimit);
+        }
 
         let stream_id = conn
             .stream_create(stream_type)
@@ -1451,6 +1750,7 @@ impl Http3Connection {
 
     pub(crate) fn webtransport_create_stream_remote(
         &mut self,
+        conn: &mut Connection,
         session_id: StreamId,
         stream_id: StreamId,
         send_events: Box<dyn SendStreamEvents>,
@@ -1464,6 +1764,15 @@ impl Http3Connection {
             .ok_or(Error::InvalidStreamId)?
             .extended_connect_session()
             .ok_or(Error::InvalidStreamId)?;
+        if !wt.borrow_mut().accept_stream(stream_id) {
+            self.recv_streams.remove(&stream_id);
+            drop(conn.stream_stop_sending(stream_id, WT_FLOW_CONTROL_ERROR));
+            if stream_id.is_bidi() {
+                drop(conn.stream_reset_send(stream_id, WT_FLOW_CONTROL_ERROR));
+            }
+            self.extended_connect_update(conn, session_id);
+            return Ok(());
+        }
 
         self.webtransport_create_stream_internal(
             wt,
@@ -1550,6 +1859,16 @@ impl Http3Connection {
         self.extended_connect_send_datagram(session_id, conn, buf, id)
     }
 
+    pub fn connect_ip_send_datagram<I: Into<DatagramTracking>>(
+        &mut self,
+        session_id: StreamId,
+        conn: &mut Connection,
+        buf: &[u8],
+        id: I,
+    ) -> Res<()> {
+        self.extended_connect_send_datagram(session_id, conn, buf, id)
+    }
+
     fn extended_connect_send_datagram<I: Into<DatagramTracking>>(
         &mut self,
         session_id: StreamId,
@@ -1557,19 +1876,86 @@ impl Http3Connection {
         buf: &[u8],
         id: I,
     ) -> Res<()> {
+        let h3_datagrams = self.h3_datagrams_available(conn);
         self.recv_streams
             .get_mut(&session_id)
             .ok_or(Error::InvalidStreamId)?
             .extended_connect_session()
             .ok_or(Error::InvalidStreamId)?
             .borrow_mut()
-            .send_datagram(conn, buf, id)
+            .send_datagram(conn, buf, id, h3_datagrams)?;
+        self.extended_connect_update(conn, session_id);
+        Ok(())
     }
 
-    /// If the control stream has received frames `MaxPushId`, `Goaway`, `PriorityUpdateRequest` or
-    /// `PriorityUpdateRequestPush` which handling is specific to the client and server, we must
+    pub fn connect_udp_send_context_datagram<I: Into<DatagramTracking>>(
+        &mut self,
+        session_id: StreamId,
+        conn: &mut Connection,
+        context_id: u64,
+        buf: &[u8],
+        id: I,
+    ) -> Res<()> {
+        let h3_datagrams = self.h3_datagrams_available(conn);
+        self.connect_udp_session(session_id)?
+            .borrow_mut()
+            .send_context_datagram(conn, context_id, buf, id, h3_datagrams)?;
+        self.extended_connect_update(conn, session_id);
+        Ok(())
+    }
+
+    /// Register a CONNECT-UDP context ID, see
+    /// [`extended_connect::session::Session::register_context`].
+    pub(crate) fn connect_udp_register_context(
+        &self,
+        session_id: StreamId,
+        context_id: Option<u64>,
+    ) -> Res<u64> {
+        self.connect_udp_session(session_id)?
+            .borrow_mut()
+            .register_context(context_id)
+    }
+
+    fn connect_udp_session(
+        &self,
+        session_id: StreamId,
+    ) -> Res<Rc<RefCell<extended_connect::session::Session>>> {
+        let session = self
+            .recv_streams
+            .get(&session_id)
+            .ok_or(Error::InvalidStreamId)?
+            .extended_connect_session()
+            .ok_or(Error::InvalidStreamId)?;
+        if session.borrow().connect_type() != ExtendedConnectType::ConnectUdp {
+            return Err(Error::InvalidStreamId);
+        }
+        Ok(session)
+    }
+
+    /// Whether both endpoints can use HTTP/3 datagrams.  If not, datagrams are
+    /// sent in capsules where possible.
+    ///
+    /// See <https://www.rfc-editor.org/rfc/rfc9297#section-3.5>.
+    fn h3_datagrams_available(&self, conn: &Connection) -> bool {
+        let remote = match &self.settings_state {
+            Http3RemoteSettingsState::Received(settings)
+            | Http3RemoteSettingsState::ZeroRtt(settings) => {
+                settings.get(HSettingType::EnableH3Datagram) == 1
+            }
+            Http3RemoteSettingsState::NotReceived => false,
+        };
+        remote && self.local_params.get_http3_datagram() && conn.max_datagram_size().is_ok()
+    }
+
+    /// If the control stream has received frames `MaxPushId`, `Goaway`, `PriorityUpdateRequest`,
+    /// `PriorityUpdateRequestPush` or `Origin` which handling is specific to the client and server, we must
     /// give them to the specific client/server handler.
-    fn handle_control_frame(&mut self, f: HFrame) -> Res<Option<HFrame>> {
+    fn handle_control_frame(
+        &mut self,
+        f: HFrame,
+        conn: &mut Connection,
+        now: Instant,
+    ) -> Res<Option<HFrame>> {
         qdebug!("[{self}] Handle a control frame {f:?}");
         if !matches!(f, HFrame::Settings { .. })
             && !matches!(
@@ -1581,6 +1967,7 @@ impl Http3Connection {
         }
         match f {
             HFrame::Settings { settings } => {
+                qlog::h3_parameters_set(conn.qlog_mut(), qlog::H3Owner::Remote, &settings, now);
                 self.handle_settings(settings)?;
                 Ok(None)
             }
@@ -1588,7 +1975,8 @@ impl Http3Connection {
             | HFrame::MaxPushId { .. }
             | HFrame::CancelPush { .. }
             | HFrame::PriorityUpdateRequest { .. }
-            | HFrame::PriorityUpdatePush { .. } => Ok(Some(f)),
+            | HFrame::PriorityUpdatePush { .. }
+            | HFrame::Origin { .. } => Ok(Some(f)),
             _ => Err(Error::HttpFrameUnexpected),
         }
     }
@@ -1607,12 +1995,14 @@ impl Http3Connection {
                 self.set_qpack_settings(&new_settings)?;
                 self.webtransport.handle_settings(&new_settings);
                 self.connect_udp.handle_settings(&new_settings);
+                self.connect_ip.handle_settings(&new_settings);
                 self.settings_state = Http3RemoteSettingsState::Received(new_settings);
                 Ok(())
             }
             Http3RemoteSettingsState::ZeroRtt(settings) => {
                 self.webtransport.handle_settings(&new_settings);
                 self.connect_udp.handle_settings(&new_settings);
+                self.connect_ip.handle_settings(&new_settings);
                 let mut qpack_changed = false;
                 for st in &[
                     HSettingType::MaxHeaderListSize,
@@ -1642,7 +2032,10 @@ impl Http3Connection {
                         HSettingType::MaxHeaderListSize
                         | HSettingType::EnableWebTransport
                         | HSettingType::EnableH3Datagram
-                        | HSettingType::EnableConnect => (),
+                        | HSettingType::EnableConnect
+                        | HSettingType::WebTransportMaxData
+                        | HSettingType::WebTransportMaxStreamsBidi
+                        | HSettingType::WebTransportMaxStreamsUni => (),
                     }
                 }
                 if qpack_changed {
@@ -1670,6 +2063,18 @@ impl Http3Connection {
         self.recv_streams.insert(stream_id, recv_stream);
     }
 
+    /// Add a new send stream. This is used for server push streams.
+    pub(crate) fn add_send_stream(
+        &mut self,
+        stream_id: StreamId,
+        send_stream: Box<dyn SendStream>,
+    ) {
+        if send_stream.has_data_to_send() {
+            self.streams_with_pending_data.insert(stream_id);
+        }
+        self.send_streams.insert(stream_id, send_stream);
+    }
+
     /// Add a new recv stream. This is used for push streams.
     pub(crate) fn add_recv_stream(
         &mut self,
@@ -1725,6 +2130,9 @@ impl Http3Connection {
     fn close_send(&mut self, stream_id: StreamId, close_type: CloseType, conn: &mut Connection) {
         if let Some(mut s) = self.remove_send_stream(stream_id, conn) {
             s.handle_stop_sending(close_type);
+            if let Http3StreamType::WebTransport(session_id) = s.stream_type() {
+                self.extended_connect_update(conn, session_id);
+            }
         }
     }
 
@@ -1736,6 +2144,9 @@ impl Http3Connection {
     ) -> Res<()> {
         if let Some(mut s) = self.remove_recv_stream(stream_id, conn) {
             s.reset(close_type)?;
+            if let Http3StreamType::WebTransport(session_id) = s.stream_type() {
+                self.extended_connect_update(conn, session_id);
+            }
         }
         Ok(())
     }
@@ -1816,6 +2227,10 @@ impl Http3Connection {
         self.connect_udp.enabled()
     }
 
+    pub const fn connect_ip_enabled(&self) -> bool {
+        self.connect_ip.enabled()
+    }
+
     #[must_use]
     pub const fn state(&self) -> &Http3State {
         &self.state
@@ -18,7 +18,9 @@ use neqo_common::{
     Datagram, Decoder, Encoder, Header, MessageType, Role, event::Provider as EventProvider, hex,
     hex_with_len, qdebug, qinfo, qlog::Qlog, qtrace, qwarn,
 };
-use neqo_crypto::{AuthenticationStatus, ResumptionToken, SecretAgentInfo, agent::CertificateInfo};
+use neqo_crypto::{
+    AuthenticationStatus, KeyLog, ResumptionToken, SecretAgentInfo, agent::CertificateInfo,
+};
 use neqo_qpack::Stats as QpackStats;
 use neqo_transport::{
     AppError, Connection, ConnectionEvent, ConnectionId, ConnectionIdGenerator, DatagramTracking,
@@ -27,8 +29,8 @@ use neqo_transport::{
 };
 
 use crate::{
-    Error, Http3Parameters, Http3StreamType, NewStreamType, Priority, PriorityHandler, PushId,
-    ReceiveOutput, Res,
+    Capsule, ConnectIpCapsule, Error, Http3Parameters, Http3StreamType, NewStreamType, Priority,
+    PriorityHandler, PushId, ReceiveOutput, Res,
     client_events::{Http3ClientEvent, Http3ClientEvents},
     connection::{Http3Connection, Http3State, RequestDescription},
     features::ConnectType,
@@ -48,6 +50,33 @@ where
     move |(id, _)| (*id >= base && !(id.is_bidi() ^ base.is_bidi())).then_some(*id)
 }
 
+/// Split an authority into a host and a port, using the default port for HTTPS.
+fn split_authority(authority: &str) -> Option<(&str, u16)> {
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, a)| a);
+    let (host, port) = if let Some(v6) = authority.strip_prefix('[') {
+        let (host, rest) = v6.split_once(']')?;
+        if rest.is_empty() {
+            (host, None)
+        } else {
+            (host, Some(rest.strip_prefix(':')?))
+        }
+    } else {
+        authority
+            .split_once(':')
+            .map_or((authority, None), |(h, p)| (h, Some(p)))
+    };
+    let port = port.map_or(Some(443), |p| p.parse().ok())?;
+    (!host.is_empty()).then_some((host, port))
+}
+
+/// Check if the ASCII serialization of an origin matches the host and port.
+fn origin_matches(origin: &str, host: &str, port: u16) -> bool {
+    origin
+        .strip_prefix("https://")
+        .and_then(split_authority)
+        .is_some_and(|(h, p)| p == port && h.eq_ignore_ascii_case(host))
+}
+
 const fn alpn_from_quic_version(version: Version) -> &'static str {
     match version {
         Version::Version2 | Version::Version1 => "h3",
@@ -281,6 +310,7 @@ pub struct Http3Client {
     base_handler: Http3Connection,
     events: Http3ClientEvents,
     push_handler: Rc<RefCell<PushController>>,
+    origins: Option<Vec<String>>,
 }
 
 impl Display for Http3Client {
@@ -336,6 +366,7 @@ impl Http3Client {
             events: events.clone(),
             push_handler: Rc::new(RefCell::new(PushController::new(push_streams, events))),
             base_handler,
+            origins: None,
         }
     }
 
@@ -356,6 +387,40 @@ impl Http3Client {
         self.conn.peer_certificate()
     }
 
+    /// The origins that the server listed in ORIGIN frames, or `None` if the
+    /// server has not sent any.  The connection can also be used for the
+    /// server name that it was created with.
+    #[must_use]
+    pub fn origins(&self) -> Option<&[String]> {
+        self.origins.as_deref()
+    }
+
+    /// Check whether a request for `authority` can be sent on this connection.
+    /// The host that the connection was made for is always allowed, whatever
+    /// the port.  Once the server has sent ORIGIN frames, the connection can
+    /// also be reused for other hosts that are listed there, if the server
+    /// certificate is valid for them.
+    #[must_use]
+    pub fn origin_allowed(&self, authority: &str) -> bool {
+        let Some((host, port)) = split_authority(authority) else {
+            return false;
+        };
+        // The server name might be an IPv6 literal in brackets.
+        if self
+            .conn
+            .server_name()
+            .and_then(split_authority)
+            .is_some_and(|(n, _)| n.eq_ignore_ascii_case(host))
+        {
+            return true;
+        }
+        self.origins
+            .iter()
+            .flatten()
+            .any(|o| origin_matches(o, host, port))
+            && self.peer_certificate().is_some_and(|c| c.verify_name(host))
+    }
+
     /// This called when peer certificates have been verified.
     ///
     /// `Http3ClientEvent::AuthenticationNeeded` event is emitted when peer’s certificates are
@@ -369,6 +434,12 @@ impl Http3Client {
         self.conn.set_qlog(qlog);
     }
 
+    /// Write TLS secrets to `keylog`.  This needs to be called before the
+    /// handshake starts.
+    pub fn set_keylog(&mut self, keylog: KeyLog) {
+        self.conn.set_keylog(keylog);
+    }
+
     /// Enable encrypted client hello (ECH).
     ///
     /// # Errors
@@ -434,13 +505,13 @@ impl Http3Client {
             let state = self.conn.state().clone();
             let res = self
                 .base_handler
-                .handle_state_change(&mut self.conn, &state);
+                .handle_state_change(&mut self.conn, &state, now);
             debug_assert_eq!(Ok(true), res);
             return Err(Error::Fatal);
         }
         if self.conn.zero_rtt_state() == ZeroRttState::Sending {
             self.base_handler
-                .set_0rtt_settings(&mut self.conn, settings)?;
+                .set_0rtt_settings(&mut self.conn, settings, now)?;
             self.events
                 .connection_state_change(self.base_handler.state().clone());
             self.push_handler
@@ -477,6 +548,8 @@ impl Http3Client {
     /// # Errors
     ///
     /// If a new stream cannot be created an error will be return.
+    /// `InvalidOrigin` if the server sent an ORIGIN frame and the authority of
+    /// `target` is not allowed; see [`Http3Client::origin_allowed`].
     ///
     /// # Panics
     ///
@@ -496,6 +569,14 @@ impl Http3Client {
             qwarn!("Invalid method CONNECT in fetch. Use Http3Client::connect instead.");
             return Err(Error::InvalidInput);
         }
+        // Once the server has listed its origins, only send requests for those.
+        if self.origins.is_some() && !self.origin_allowed(target.authority()) {
+            qwarn!(
+                "[{self}] Origin {} is not served by this connection",
+                target.authority()
+            );
+            return Err(Error::InvalidOrigin);
+        }
         let output = self.base_handler.request(
             &mut self.conn,
             Box::new(self.events.clone()),
@@ -604,6 +685,25 @@ impl Http3Client {
             .stream_reset_send(&mut self.conn, stream_id, error)
     }
 
+    /// Reset a `WebTransport` stream, but make sure that the first `reliable_size` bytes
+    /// are delivered to the peer.  This uses `RESET_STREAM_AT`, which the server needs
+    /// to support.
+    ///
+    /// # Errors
+    ///
+    /// An error will be return if a stream does not exist, if it isn't a `WebTransport`
+    /// stream, or if the server does not support `RESET_STREAM_AT`.
+    pub fn stream_reset_send_at(
+        &mut self,
+        stream_id: StreamId,
+        error: AppError,
+        reliable_size: u64,
+    ) -> Res<()> {
+        qinfo!("[{self}] stream_reset_send_at {stream_id} error={error} reliable={reliable_size}");
+        self.base_handler
+            .stream_reset_send_at(&mut self.conn, stream_id, error, reliable_size)
+    }
+
     /// # Errors
     ///
     /// An error will be return if a stream does not exist.
@@ -613,6 +713,27 @@ impl Http3Client {
             .stream_stop_sending(&mut self.conn, stream_id, error)
     }
 
+    /// Send trailers after the request body.  The request can't carry any more
+    /// data after this, only the end of the stream.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the stream does not exist or is not a request stream,
+    /// `InvalidInput` if trailers have already been sent or the request is done and
+    /// `InvalidHeader` if `headers` contain pseudo-headers.
+    pub fn send_trailers(&mut self, stream_id: StreamId, headers: &[Header]) -> Res<()> {
+        qinfo!("[{self}] send_trailers on stream {stream_id}");
+        self.base_handler
+            .send_streams_mut()
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .http_stream()
+            .ok_or(Error::InvalidStreamId)?
+            .send_headers(headers, &mut self.conn)?;
+        self.base_handler.stream_has_pending_data(stream_id);
+        Ok(())
+    }
+
     /// This function is used for regular HTTP requests and `WebTransport` streams.
     /// In the case of regular HTTP requests, the request body is supplied using this function, and
     /// headers are supplied through the `fetch` function.
@@ -630,11 +751,17 @@ impl Http3Client {
             "[{self}] end_data from stream {stream_id} sending {} bytes",
             buf.len()
         );
-        self.base_handler
+        let send_stream = self
+            .base_handler
             .send_streams_mut()
             .get_mut(&stream_id)
-            .ok_or(Error::InvalidStreamId)?
-            .send_data(&mut self.conn, buf, now)
+            .ok_or(Error::InvalidStreamId)?;
+        let res = send_stream.send_data(&mut self.conn, buf, now);
+        if let Http3StreamType::WebTransport(session_id) = send_stream.stream_type() {
+            self.base_handler
+                .extended_connect_update(&mut self.conn, session_id);
+        }
+        res
     }
 
     /// Response data are read directly into a buffer supplied as a parameter of this function to
@@ -755,6 +882,34 @@ impl Http3Client {
         output
     }
 
+    /// # Errors
+    ///
+    /// If MASQUE connect-ip session cannot be created, e.g. the HTTP CONNECT
+    /// setting is not negotiated or the HTTP/3 connection is closed.
+    pub fn connect_ip_create_session<T>(
+        &mut self,
+        now: Instant,
+        target: T,
+        headers: &[Header],
+    ) -> Res<StreamId>
+    where
+        T: RequestTarget,
+    {
+        let output = self.base_handler.connect_ip_create_session(
+            &mut self.conn,
+            Box::new(self.events.clone()),
+            target,
+            headers,
+        );
+
+        if let Err(e) = &output
+            && e.connection_error()
+        {
+            self.close(now, e.code(), "");
+        }
+        output
+    }
+
     /// Close `WebTransport` cleanly
     ///
     /// # Errors
@@ -780,6 +935,18 @@ impl Http3Client {
         )
     }
 
+    /// Ask the server to stop using a `WebTransport` session, e.g. when the
+    /// client wants to move to a new session.  The session stays open until
+    /// one side closes it.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the session does not exist or is not active.
+    pub fn webtransport_drain_session(&mut self, session_id: StreamId, now: Instant) -> Res<()> {
+        self.base_handler
+            .webtransport_drain_session(&mut self.conn, session_id, now)
+    }
+
     /// Close `ConnectUdp` cleanly
     ///
     /// # Errors
@@ -800,6 +967,42 @@ impl Http3Client {
             .connect_udp_close_session(&mut self.conn, session_id, error, message, now)
     }
 
+    /// Close `ConnectIp` cleanly
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidStreamId`] if the stream does not exist,
+    /// [`Error::TransportStreamDoesNotExist`] if the transport stream does not
+    /// exist (this may happen if [`Http3Client::process_output`] has not been
+    /// called when needed, and HTTP3 layer has not picked up the info that the
+    /// stream has been closed.)
+    pub fn connect_ip_close_session(
+        &mut self,
+        session_id: StreamId,
+        error: u32,
+        message: &str,
+        now: Instant,
+    ) -> Res<()> {
+        self.base_handler
+            .connect_ip_close_session(&mut self.conn, session_id, error, message, now)
+    }
+
+    /// Send a `ConnectIp` capsule, e.g. to request addresses from the proxy.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidStreamId`] if the session does not exist or has not
+    /// been accepted yet.
+    pub fn connect_ip_send_capsule(
+        &mut self,
+        session_id: StreamId,
+        capsule: &ConnectIpCapsule,
+        now: Instant,
+    ) -> Res<()> {
+        self.base_handler
+            .connect_ip_send_capsule(&mut self.conn, session_id, capsule, now)
+    }
+
     /// # Errors
     ///
     /// This may return an error if the particular session does not exist
@@ -836,7 +1039,8 @@ impl Http3Client {
             .webtransport_send_datagram(session_id, &mut self.conn, buf, id)
     }
 
-    /// Send `ConnectUdp` datagram.
+    /// Send `ConnectUdp` datagram.  If HTTP/3 datagrams were not negotiated,
+    /// it is sent in a DATAGRAM capsule on the request stream instead.
     ///
     /// # Errors
     ///
@@ -854,6 +1058,97 @@ impl Http3Client {
             .connect_udp_send_datagram(session_id, &mut self.conn, buf, id)
     }
 
+    /// Send a `ConnectUdp` datagram with a context ID that was registered
+    /// with [`Self::connect_udp_register_context`] or
+    /// [`Self::connect_udp_register_peer_context`].
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    /// Returns `InvalidInput` if the context ID is not registered and
+    /// `TooMuchData` if the supply buffer is bigger than the allowed remote
+    /// datagram size.
+    pub fn connect_udp_send_context_datagram<I: Into<DatagramTracking>>(
+        &mut self,
+        session_id: StreamId,
+        context_id: u64,
+        buf: &[u8],
+        id: I,
+    ) -> Res<()> {
+        qtrace!("connect_udp_send_context_datagram session:{session_id:?} context:{context_id}");
+        self.base_handler.connect_udp_send_context_datagram(
+            session_id,
+            &mut self.conn,
+            context_id,
+            buf,
+            id,
+        )
+    }
+
+    /// Allocate a context ID for `ConnectUdp` datagrams, e.g. for an extension
+    /// to CONNECT-UDP.  The proxy has to be told about it, and how it is used,
+    /// in the way that the extension defines.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    pub fn connect_udp_register_context(&self, session_id: StreamId) -> Res<u64> {
+        self.base_handler
+            .connect_udp_register_context(session_id, None)
+    }
+
+    /// Accept `ConnectUdp` datagrams with a context ID that the proxy
+    /// allocated.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    /// Returns `InvalidInput` if the proxy can't allocate the context ID or
+    /// it is already registered.
+    pub fn connect_udp_register_peer_context(
+        &self,
+        session_id: StreamId,
+        context_id: u64,
+    ) -> Res<()> {
+        self.base_handler
+            .connect_udp_register_context(session_id, Some(context_id))
+            .map(|_| ())
+    }
+
+    /// Send a `ConnectUdp` capsule, e.g. for an extension to CONNECT-UDP.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidStreamId`] if the session does not exist or has not
+    /// been accepted yet.
+    pub fn connect_udp_send_capsule(
+        &mut self,
+        session_id: StreamId,
+        capsule: &Capsule,
+        now: Instant,
+    ) -> Res<()> {
+        self.base_handler
+            .connect_udp_send_capsule(&mut self.conn, session_id, capsule, now)
+    }
+
+    /// Send an IP packet in a `ConnectIp` datagram.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    /// The function returns `TooMuchData` if the supply buffer is bigger than
+    /// the allowed remote datagram size.
+    pub fn connect_ip_send_datagram<I: Into<DatagramTracking>>(
+        &mut self,
+        session_id: StreamId,
+        buf: &[u8],
+        id: I,
+    ) -> Res<()> {
+        qtrace!("connect_ip_send_datagram session:{session_id:?}");
+        self.base_handler
+            .connect_ip_send_datagram(session_id, &mut self.conn, buf, id)
+    }
+
     /// Returns the current max size of a datagram that can fit into a packet.
     /// The value will change over time depending on the encoded size of the
     /// packet number, ack frames, etc.
@@ -1138,7 +1433,7 @@ impl Http3Client {
                 ConnectionEvent::StateChange(state) => {
                     if self
                         .base_handler
-                        .handle_state_change(&mut self.conn, &state)?
+                        .handle_state_change(&mut self.conn, &state, now)?
                     {
                         self.events
                             .connection_state_change(self.base_handler.state().clone());
@@ -1159,7 +1454,9 @@ impl Http3Client {
                 }
                 ConnectionEvent::SendStreamComplete { .. }
                 | ConnectionEvent::OutgoingDatagramOutcome { .. }
-                | ConnectionEvent::IncomingDatagramDropped => {}
+                | ConnectionEvent::IncomingDatagramDropped
+                | ConnectionEvent::PathOpened { .. }
+                | ConnectionEvent::PathClosed { .. } => {}
             }
         }
         Ok(())
@@ -1181,7 +1478,8 @@ impl Http3Client {
     ///     - `HFrame::MaxPushId { .. }`, `HFrame::PriorityUpdateRequest { .. } ` and
     ///       `HFrame::PriorityUpdatePush` can only be receive on the server side,
     ///     - `HFrame::Goaway { stream_id }` needs specific handling by the client by the protocol
-    ///       specification.
+    ///       specification,
+    ///     - `HFrame::Origin` is only sent by the server.
     ///
     /// [1]: https://github.com/mozilla/neqo/blob/main/neqo-http3/src/connection.rs
     fn handle_stream_readable(&mut self, stream_id: StreamId, now: Instant) -> Res<()> {
@@ -1195,6 +1493,7 @@ impl Http3Client {
             ReceiveOutput::NewStream(NewStreamType::Http(_)) => Err(Error::HttpStreamCreation),
             ReceiveOutput::NewStream(NewStreamType::WebTransportStream(session_id)) => {
                 self.base_handler.webtransport_create_stream_remote(
+                    &mut self.conn,
                     StreamId::from(session_id),
                     stream_id,
                     Box::new(self.events.clone()),
@@ -1209,17 +1508,25 @@ impl Http3Client {
             ReceiveOutput::ControlFrames(control_frames) => {
                 for f in control_frames {
                     match f {
-                        HFrame::CancelPush { push_id } => self
-                            .push_handler
-                            .borrow_mut()
-                            .handle_cancel_push(push_id, &mut self.conn, &mut self.base_handler),
+                        HFrame::CancelPush { push_id } => {
+                            self.push_handler.borrow_mut().handle_cancel_push(
+                                push_id,
+                                &mut self.conn,
+                                &mut self.base_handler,
+                                now,
+                            )
+                        }
                         HFrame::MaxPushId { .. }
                         | HFrame::PriorityUpdateRequest { .. }
                         | HFrame::PriorityUpdatePush { .. } => Err(Error::HttpFrameUnexpected),
                         HFrame::Goaway { stream_id } => self.handle_goaway(stream_id),
+                        HFrame::Origin { origins } => {
+                            self.handle_origin(origins);
+                            Ok(())
+                        }
                         _ => {
                             unreachable!(
-                                "we should only put MaxPushId, Goaway and PriorityUpdates into control_frames"
+                                "we should only put MaxPushId, Goaway, PriorityUpdates and Origin into control_frames"
                             );
                         }
                     }?;
@@ -1244,11 +1551,12 @@ impl Http3Client {
         // (this will be a connection error) or a bool.
         // If false is returned that means that the stream should be reset because the push has
         // been already canceled (CANCEL_PUSH frame or canceling push from the application).
-        if !self
-            .push_handler
-            .borrow_mut()
-            .add_new_push_stream(push_id, stream_id)?
-        {
+        if !self.push_handler.borrow_mut().add_new_push_stream(
+            push_id,
+            stream_id,
+            self.conn.qlog_mut(),
+            now,
+        )? {
             // We are not interested in the result of stream_stop_sending, we are not interested
             // in this stream.
             drop(
@@ -1281,6 +1589,17 @@ impl Http3Client {
         Ok(())
     }
 
+    /// Each ORIGIN frame adds to the origin set.
+    fn handle_origin(&mut self, origins: Vec<String>) {
+        qinfo!("[{self}] handle_origin {origins:?}");
+        let set = self.origins.get_or_insert_default();
+        for origin in origins {
+            if !set.contains(&origin) {
+                set.push(origin);
+            }
+        }
+    }
+
     fn handle_goaway(&mut self, goaway_stream_id: StreamId) -> Res<()> {
         qinfo!("[{self}] handle_goaway {goaway_stream_id}");
 
@@ -1339,6 +1658,18 @@ impl Http3Client {
         Ok(())
     }
 
+    pub(crate) const fn client_events(&self) -> &Http3ClientEvents {
+        &self.events
+    }
+
+    pub(crate) fn has_send_stream(&self, stream_id: StreamId) -> bool {
+        self.base_handler.send_streams().contains_key(&stream_id)
+    }
+
+    pub(crate) fn has_recv_stream(&self, stream_id: StreamId) -> bool {
+        self.base_handler.recv_streams().contains_key(&stream_id)
+    }
+
     #[must_use]
     pub fn qpack_encoder_stats(&self) -> QpackStats {
         self.base_handler.qpack_encoder().borrow().stats()
@@ -1374,7 +1705,7 @@ impl EventProvider for Http3Client {
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 mod tests {
-    use std::time::Duration;
+    use std::{io, time::Duration};
 
     use http::Uri;
     use neqo_common::{Datagram, Decoder, Encoder, event::Provider as _, qtrace};
@@ -1391,10 +1722,10 @@ mod tests {
 
     use super::{
         AuthenticationStatus, Connection, Error, HSettings, Header, Http3Client, Http3ClientEvent,
-        Http3Parameters, Http3State, Rc, RefCell,
+        Http3Parameters, Http3State, Rc, RefCell, origin_matches, split_authority,
     };
     use crate::{
-        Http3Server, Priority, PushId, RecvStream as _,
+        BodyError, Http3Server, Priority, PushId, RecvBodyHandle, RecvStream as _, SendBodyHandle,
         frames::{HFrame, HFrameType},
         qpack_encoder_receiver::EncoderRecvStream,
         settings::{H3_RESERVED_SETTINGS, HSetting, HSettingType},
@@ -7576,4 +7907,123 @@ mod tests {
             }
         );
     }
+
+    fn exchange_from_server(client: &Rc<RefCell<Http3Client>>, server: &mut TestServer) {
+        let out = server.conn.process_output(now());
+        client
+            .borrow_mut()
+            .process_input(out.dgram().unwrap(), now());
+    }
+
+    #[test]
+    fn body_handles() {
+        let (client, mut server, request_stream_id) = connect_and_send_request(false);
+        let client = Rc::new(RefCell::new(client));
+
+        let mut send = SendBodyHandle::new(Rc::clone(&client), request_stream_id).unwrap();
+        assert!(send.is_writable());
+        assert_eq!(send.write(b"abc", now()), Ok(3));
+        send.close(now()).unwrap();
+        assert_eq!(send.final_size(), Some(3));
+        assert_eq!(send.write(b"d", now()), Err(Error::AlreadyClosed.into()));
+
+        let out = client.borrow_mut().process_output(now());
+        server.conn.process_input(out.dgram().unwrap(), now());
+        server.read_and_check_stream_data(request_stream_id, &[0x0, 0x3, 0x61, 0x62, 0x63], true);
+
+        let mut recv = RecvBodyHandle::new(Rc::clone(&client), request_stream_id).unwrap();
+        server_send_response_and_exchange_packet(
+            &mut client.borrow_mut(),
+            &mut server,
+            request_stream_id,
+            HTTP_RESPONSE_1,
+            true,
+        );
+        assert!(recv.is_readable());
+        let mut body = Vec::new();
+        assert_eq!(recv.read_to_end(&mut body, now()), Ok(true));
+        assert_eq!(body, EXPECTED_RESPONSE_DATA_1);
+        assert!(recv.is_finished());
+        assert!(!recv.is_readable());
+        assert_eq!(recv.final_size(), Some(7));
+    }
+
+    #[test]
+    fn recv_body_handle_would_block() {
+        let (client, _server, request_stream_id) = connect_and_send_request(true);
+        let client = Rc::new(RefCell::new(client));
+        let mut recv = RecvBodyHandle::new(Rc::clone(&client), request_stream_id).unwrap();
+        let err = io::Read::read(&mut recv, &mut [0; 10]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(!recv.is_readable());
+    }
+
+    #[test]
+    fn recv_body_handle_reset() {
+        let (client, mut server, request_stream_id) = connect_and_send_request(true);
+        let client = Rc::new(RefCell::new(client));
+        let mut recv = RecvBodyHandle::new(Rc::clone(&client), request_stream_id).unwrap();
+
+        server
+            .conn
+            .stream_reset_send(request_stream_id, Error::HttpRequestRejected.code())
+            .unwrap();
+        exchange_from_server(&client, &mut server);
+
+        assert!(recv.is_readable());
+        assert_eq!(
+            recv.read(&mut [0; 10], now()),
+            Err(BodyError::Reset(Error::HttpRequestRejected.code()))
+        );
+        let err = io::Read::read(&mut recv, &mut [0; 10]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn send_body_handle_stop_sending() {
+        let (client, mut server, request_stream_id) = connect_and_send_request(false);
+        let client = Rc::new(RefCell::new(client));
+        let mut send = SendBodyHandle::new(Rc::clone(&client), request_stream_id).unwrap();
+
+        server
+            .conn
+            .stream_stop_sending(request_stream_id, Error::HttpRequestRejected.code())
+            .unwrap();
+        exchange_from_server(&client, &mut server);
+
+        assert!(!send.is_writable());
+        assert_eq!(
+            send.write(b"abc", now()),
+            Err(BodyError::StopSending(Error::HttpRequestRejected.code()))
+        );
+        let err = io::Write::write(&mut send, b"abc").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn authority_parsing() {
+        assert_eq!(split_authority("example.com"), Some(("example.com", 443)));
+        assert_eq!(
+            split_authority("example.com:8443"),
+            Some(("example.com", 8443))
+        );
+        assert_eq!(
+            split_authority("user@example.com:1"),
+            Some(("example.com", 1))
+        );
+        assert_eq!(split_authority("[::1]"), Some(("::1", 443)));
+        assert_eq!(split_authority("[::1]:8443"), Some(("::1", 8443)));
+        assert_eq!(split_authority("[::1]8443"), None);
+        assert_eq!(split_authority("example.com:x"), None);
+        assert_eq!(split_authority(":443"), None);
+
+        assert!(origin_matches("https://Example.com", "example.com", 443));
+        assert!(origin_matches(
+            "https://example.com:443",
+            "example.com",
+            443
+        ));
+        assert!(!origin_matches("https://example.com", "example.com", 8443));
+        assert!(!origin_matches("http://example.com", "example.com", 443));
+    }
 }
@@ -7,19 +7,23 @@
 use std::{
     fmt::{self, Display, Formatter},
     rc::Rc,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use neqo_common::{Header, MessageType, Role, event::Provider as _, qdebug, qinfo, qtrace};
+use neqo_common::{
+    Header, MessageType, Role, event::Provider as _, header::HeadersExt as _, qdebug, qinfo, qtrace,
+};
 use neqo_transport::{
     AppError, Connection, ConnectionEvent, DatagramTracking, StreamId, StreamType,
 };
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
 
 use crate::{
-    Error, Http3Parameters, Http3StreamType, NewStreamType, Priority, PriorityHandler,
-    ReceiveOutput, Res,
+    Capsule, ConnectIpCapsule, Error, Http3Parameters, Http3StreamInfo, Http3StreamType,
+    NewStreamType, Priority, PriorityHandler, PushId, ReceiveOutput, RecvStreamEvents as _, Res,
     connection::{Http3Connection, Http3State, SessionAcceptAction},
     frames::HFrame,
+    qlog,
     recv_message::{RecvMessage, RecvMessageInfo},
     send_message::SendMessage,
     server_connection_events::{Http3ServerConnEvent, Http3ServerConnEvents},
@@ -30,6 +34,29 @@ pub struct Http3ServerHandler {
     base_handler: Http3Connection,
     events: Http3ServerConnEvents,
     needs_processing: bool,
+    /// The maximum push ID allowed by the client. This is `None` until the client sends a
+    /// `MAX_PUSH_ID` frame.
+    max_push_id: Option<PushId>,
+    next_push_id: PushId,
+    /// Push streams that may still be active, keyed by push ID.
+    push_streams: HashMap<PushId, StreamId>,
+    /// Priorities from `PRIORITY_UPDATE` frames for request streams.  These take
+    /// precedence over the priority header field of the request.
+    priority_updates: HashMap<StreamId, Priority>,
+    /// The lowest request stream ID that the client has not opened.  A
+    /// `GOAWAY` frame carries this value.
+    next_request: StreamId,
+    /// Requests that have not completed.
+    requests: HashSet<StreamId>,
+    /// Streams that the application has stopped reading from.
+    read_paused: HashSet<StreamId>,
+    drain_timeout: Duration,
+    /// When a graceful shutdown has started, the time at which the connection
+    /// is closed even if requests are still active.
+    drain_deadline: Option<Instant>,
+    /// When `GOAWAY` has been sent and had a PTO to reach the client, after
+    /// which a connection without requests can be closed.
+    goaway_grace: Option<Instant>,
 }
 
 impl Display for Http3ServerHandler {
@@ -41,9 +68,19 @@ impl Display for Http3ServerHandler {
 impl Http3ServerHandler {
     pub(crate) fn new(http3_parameters: Http3Parameters) -> Self {
         Self {
+            drain_timeout: http3_parameters.get_drain_timeout(),
             base_handler: Http3Connection::new(http3_parameters, Role::Server),
             events: Http3ServerConnEvents::default(),
             needs_processing: false,
+            max_push_id: None,
+            next_push_id: PushId::default(),
+            push_streams: HashMap::default(),
+            priority_updates: HashMap::default(),
+            next_request: StreamId::new(0),
+            requests: HashSet::default(),
+            read_paused: HashSet::default(),
+            drain_deadline: None,
+            goaway_grace: None,
         }
     }
 
@@ -52,6 +89,69 @@ impl Http3ServerHandler {
         self.base_handler.state().clone()
     }
 
+    /// Start a graceful shutdown of the connection.  A `GOAWAY` frame tells
+    /// the client that requests it has not yet sent are rejected, and those
+    /// requests fail with `H3_REQUEST_REJECTED`.  The connection is closed
+    /// once the requests that were accepted complete, or when the drain
+    /// timeout from [`Http3Parameters`] runs out.
+    pub fn shutdown_gracefully(&mut self, now: Instant) {
+        self.shutdown_gracefully_by(now + self.drain_timeout);
+    }
+
+    /// Start a graceful shutdown that ends at `deadline`.  Calling this again
+    /// can only make the deadline earlier.
+    pub(crate) fn shutdown_gracefully_by(&mut self, deadline: Instant) {
+        qinfo!("[{self}] Graceful shutdown");
+        self.drain_deadline = Some(self.drain_deadline.map_or(deadline, |d| d.min(deadline)));
+        self.needs_processing = true;
+    }
+
+    /// When the connection will be closed, if it is shutting down.
+    pub(crate) const fn drain_deadline(&self) -> Option<Instant> {
+        self.drain_deadline
+    }
+
+    /// Send `GOAWAY` once that is possible, then close the connection when it
+    /// is idle or the deadline has passed.  An idle connection stays open for
+    /// a PTO after `GOAWAY` is sent, so that the client learns why it closes.
+    fn drain(&mut self, conn: &mut Connection, now: Instant) {
+        let Some(deadline) = self.drain_deadline else {
+            return;
+        };
+        match self.base_handler.state() {
+            Http3State::Connected => {
+                let stream_id = self.next_request;
+                qdebug!("[{self}] Send GOAWAY {stream_id}");
+                self.base_handler
+                    .queue_control_frame(&HFrame::Goaway { stream_id });
+                self.base_handler
+                    .set_state(Http3State::GoingAway(stream_id));
+                self.events
+                    .connection_state_change(self.base_handler.state().clone());
+                // `GOAWAY` is sent along with the output of this call.
+                self.goaway_grace = Some(now + conn.pto());
+            }
+            Http3State::Closing(_) | Http3State::Closed(_) => {
+                self.drain_deadline = None;
+                return;
+            }
+            Http3State::Initializing | Http3State::ZeroRtt | Http3State::GoingAway(_) => {}
+        }
+        let idle = matches!(self.base_handler.state(), Http3State::GoingAway(_))
+            && self.requests.is_empty();
+        let close_at = self
+            .goaway_grace
+            .filter(|_| idle)
+            .map_or(deadline, |grace| grace.min(deadline));
+        if now >= close_at {
+            self.drain_deadline = None;
+            self.close(conn, now, &Error::HttpNone);
+        } else {
+            // Make sure that there is a callback when the connection can close.
+            self.drain_deadline = Some(close_at);
+        }
+    }
+
     /// Supply a response for a request.
     ///
     /// # Errors
@@ -69,12 +169,15 @@ impl Http3ServerHandler {
         conn: &mut Connection,
         now: Instant,
     ) -> Res<usize> {
-        let n = self
+        let send_stream = self
             .base_handler
             .send_streams_mut()
             .get_mut(&stream_id)
-            .ok_or(Error::InvalidStreamId)?
-            .send_data(conn, data, now)?;
+            .ok_or(Error::InvalidStreamId)?;
+        let n = send_stream.send_data(conn, data, now)?;
+        if let Http3StreamType::WebTransport(session_id) = send_stream.stream_type() {
+            self.base_handler.extended_connect_update(conn, session_id);
+        }
         if n > 0 {
             self.base_handler.stream_has_pending_data(stream_id);
         }
@@ -82,6 +185,22 @@ impl Http3ServerHandler {
         Ok(n)
     }
 
+    pub(crate) fn set_read_paused(&mut self, stream_info: Http3StreamInfo, paused: bool) {
+        let stream_id = stream_info.stream_id();
+        if paused {
+            let streams = self.base_handler.recv_streams();
+            self.read_paused.retain(|id| streams.contains_key(id));
+            self.read_paused.insert(stream_id);
+        } else if self.read_paused.remove(&stream_id) {
+            // Data that arrived while paused is read when this event is processed.
+            self.events.data_readable(&stream_info);
+        }
+    }
+
+    pub(crate) fn read_paused(&self, stream_id: StreamId) -> bool {
+        self.read_paused.contains(&stream_id)
+    }
+
     /// Supply response heeaders for a request.
     pub(crate) fn send_headers(
         &mut self,
@@ -101,6 +220,149 @@ impl Http3ServerHandler {
         Ok(())
     }
 
+    /// Promise a pushed response on the request stream `stream_id` and create the push stream
+    /// that will carry the response. `headers` are the headers of the promised request.
+    ///
+    /// # Errors
+    ///
+    /// `StreamLimit` if the client does not allow any more pushes or a new unidirectional stream
+    /// cannot be created, `InvalidStreamId` if the request stream does not exist and
+    /// `InvalidInput` if the response on the request stream is already complete.
+    pub(crate) fn send_push_promise(
+        &mut self,
+        stream_id: StreamId,
+        headers: &[Header],
+        conn: &mut Connection,
+    ) -> Res<(PushId, StreamId)> {
+        let push_id = self.next_push_id;
+        if self.max_push_id.is_none_or(|max| push_id > max) {
+            qdebug!("[{self}] push_id={push_id} is not allowed by the peer");
+            return Err(Error::StreamLimit);
+        }
+        let request_stream = self
+            .base_handler
+            .send_streams_mut()
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .http_stream()
+            .ok_or(Error::InvalidStreamId)?;
+        // Check the promise before a push stream is used up for it.
+        request_stream.push_promise_allowed(headers)?;
+        let push_stream_id = conn
+            .stream_create(StreamType::UniDi)
+            .map_err(|e| Error::map_stream_create_errors(&e))?;
+        let res = request_stream.send_push_promise(push_id, headers, conn);
+        if let Err(e) = res {
+            // Nothing has been written to the push stream yet, so the peer will ignore it.
+            conn.stream_reset_send(push_stream_id, Error::HttpRequestCancelled.code())?;
+            return Err(e);
+        }
+        qdebug!(
+            "[{self}] Promised push_id={push_id} on stream={stream_id} push_stream={push_stream_id}"
+        );
+        qlog::h3_stream_type_set(
+            conn.qlog_mut(),
+            qlog::H3Owner::Local,
+            push_stream_id,
+            qlog::H3StreamType::Push,
+            Some(push_id),
+            None,
+        );
+        self.base_handler.stream_has_pending_data(stream_id);
+        self.base_handler.add_send_stream(
+            push_stream_id,
+            Box::new(SendMessage::new_push(
+                push_id,
+                push_stream_id,
+                Rc::clone(self.base_handler.qpack_encoder()),
+                Box::new(self.events.clone()),
+            )),
+        );
+        Self::set_stream_priority(conn, push_stream_id, Priority::default());
+        self.next_push_id.next();
+        let send_streams = self.base_handler.send_streams();
+        self.push_streams
+            .retain(|_, id| send_streams.contains_key(id));
+        self.push_streams.insert(push_id, push_stream_id);
+        self.needs_processing = true;
+        Ok((push_id, push_stream_id))
+    }
+
+    /// Serve `stream_id` according to `priority`.  The stream might already be gone,
+    /// in which case there is nothing to do.
+    fn set_stream_priority(conn: &mut Connection, stream_id: StreamId, priority: Priority) {
+        qdebug!("Stream {stream_id} priority {priority:?}");
+        drop(conn.stream_sendorder(stream_id, Some(priority.sendorder(stream_id))));
+    }
+
+    /// Forget about a request that has completed or was abandoned.
+    fn request_done(&mut self, stream_id: StreamId) {
+        self.requests.remove(&stream_id);
+        self.priority_updates.remove(&stream_id);
+    }
+
+    /// Whether `stream_id` is a request stream that the client has yet to open,
+    /// or one that is still open.
+    fn request_pending(&self, stream_id: StreamId) -> bool {
+        stream_id >= self.next_request
+            || self.requests.contains(&stream_id)
+            || self.base_handler.recv_streams().contains_key(&stream_id)
+    }
+
+    /// Apply the priority header field of a request, unless the client has
+    /// already sent a `PRIORITY_UPDATE` frame for the stream.
+    pub(crate) fn request_priority(
+        &self,
+        conn: &mut Connection,
+        stream_id: StreamId,
+        headers: &[Header],
+    ) {
+        if self.priority_updates.contains_key(&stream_id) {
+            return;
+        }
+        if let Some(priority) = headers
+            .find_header("priority")
+            .and_then(|h| Priority::from_bytes(h.value()).ok())
+        {
+            Self::set_stream_priority(conn, stream_id, priority);
+        }
+    }
+
+    fn handle_max_push_id(&mut self, push_id: PushId) -> Res<()> {
+        qdebug!("[{self}] MAX_PUSH_ID frame received push_id={push_id}");
+        // A client must not reduce the maximum push ID.
+        if self.max_push_id.is_some_and(|max| push_id < max) {
+            return Err(Error::HttpId);
+        }
+        self.max_push_id = Some(push_id);
+        Ok(())
+    }
+
+    fn handle_cancel_push(&mut self, push_id: PushId, conn: &mut Connection) -> Res<()> {
+        qdebug!("[{self}] CANCEL_PUSH frame received push_id={push_id}");
+        // The push ID must have been mentioned in a PUSH_PROMISE frame.
+        if push_id >= self.next_push_id {
+            return Err(Error::HttpId);
+        }
+        let Some(stream_id) = self.push_streams.remove(&push_id) else {
+            return Ok(());
+        };
+        if self
+            .base_handler
+            .send_streams_mut()
+            .remove(&stream_id)
+            .is_some()
+        {
+            // Stream may be already be closed and we may get an error here, but we do not care.
+            drop(conn.stream_reset_send(stream_id, Error::HttpRequestCancelled.code()));
+            self.events.push_canceled(
+                Http3StreamInfo::new(stream_id, Http3StreamType::Push),
+                push_id,
+            );
+        }
+        Ok(())
+    }
+
     /// This is called when application is done sending a request.
     ///
     /// # Errors
@@ -132,6 +394,7 @@ impl Http3ServerHandler {
     ) -> Res<()> {
         qinfo!("[{self}] cancel_fetch {stream_id} error={error}");
         self.needs_processing = true;
+        self.request_done(stream_id);
         self.base_handler.cancel_fetch(stream_id, error, conn)
     }
 
@@ -155,9 +418,24 @@ impl Http3ServerHandler {
     ) -> Res<()> {
         qinfo!("[{self}] stream_reset_send {stream_id} error={error}");
         self.needs_processing = true;
+        self.request_done(stream_id);
         self.base_handler.stream_reset_send(conn, stream_id, error)
     }
 
+    pub fn stream_reset_send_at(
+        &mut self,
+        stream_id: StreamId,
+        error: AppError,
+        reliable_size: u64,
+        conn: &mut Connection,
+    ) -> Res<()> {
+        qinfo!("[{self}] stream_reset_send_at {stream_id} error={error} reliable={reliable_size}");
+        self.needs_processing = true;
+        self.request_done(stream_id);
+        self.base_handler
+            .stream_reset_send_at(conn, stream_id, error, reliable_size)
+    }
+
     /// Accept a `WebTransport` Session request
     pub(crate) fn webtransport_session_accept(
         &mut self,
@@ -194,6 +472,24 @@ impl Http3ServerHandler {
         )
     }
 
+    /// Accept a `ConnectIp` Session request
+    pub(crate) fn connect_ip_session_accept(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: StreamId,
+        accept: &SessionAcceptAction,
+        now: Instant,
+    ) -> Res<()> {
+        self.needs_processing = true;
+        self.base_handler.connect_ip_session_accept(
+            conn,
+            stream_id,
+            Box::new(self.events.clone()),
+            accept,
+            now,
+        )
+    }
+
     /// Close `WebTransport` cleanly
     ///
     /// # Errors
@@ -216,6 +512,22 @@ impl Http3ServerHandler {
             .webtransport_close_session(conn, session_id, error, message, now)
     }
 
+    /// Ask the peer to stop using a `WebTransport` session.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the session does not exist or is not active.
+    pub fn webtransport_drain_session(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        now: Instant,
+    ) -> Res<()> {
+        self.needs_processing = true;
+        self.base_handler
+            .webtransport_drain_session(conn, session_id, now)
+    }
+
     /// Close `ConnectUdp` cleanly
     ///
     /// # Errors
@@ -238,6 +550,44 @@ impl Http3ServerHandler {
             .connect_udp_close_session(conn, session_id, error, message, now)
     }
 
+    /// Close `ConnectIp` cleanly
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the stream does not exist,
+    /// `TransportStreamDoesNotExist` if the transport stream does not exist (this may happen if
+    /// `process_output` has not been called when needed, and HTTP3 layer has not picked up the
+    /// info that the stream has been closed.)
+    pub fn connect_ip_close_session(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        error: u32,
+        message: &str,
+        now: Instant,
+    ) -> Res<()> {
+        self.needs_processing = true;
+        self.base_handler
+            .connect_ip_close_session(conn, session_id, error, message, now)
+    }
+
+    /// Send a `ConnectIp` capsule.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the session does not exist or is not active.
+    pub fn connect_ip_send_capsule(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        capsule: &ConnectIpCapsule,
+        now: Instant,
+    ) -> Res<()> {
+        self.needs_processing = true;
+        self.base_handler
+            .connect_ip_send_capsule(conn, session_id, capsule, now)
+    }
+
     pub fn webtransport_create_stream(
         &mut self,
         conn: &mut Connection,
@@ -278,6 +628,64 @@ impl Http3ServerHandler {
             .connect_udp_send_datagram(session_id, conn, buf, id)
     }
 
+    pub fn connect_udp_send_context_datagram<I: Into<DatagramTracking>>(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        context_id: u64,
+        buf: &[u8],
+        id: I,
+    ) -> Res<()> {
+        self.needs_processing = true;
+        self.base_handler
+            .connect_udp_send_context_datagram(session_id, conn, context_id, buf, id)
+    }
+
+    /// Register a CONNECT-UDP context ID.  With `None`, a new context ID is
+    /// allocated; otherwise `context_id` is one that the client allocated.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the session does not exist, or `InvalidInput` if
+    /// the client can't allocate `context_id`.
+    pub fn connect_udp_register_context(
+        &self,
+        session_id: StreamId,
+        context_id: Option<u64>,
+    ) -> Res<u64> {
+        self.base_handler
+            .connect_udp_register_context(session_id, context_id)
+    }
+
+    /// Send a `ConnectUdp` capsule.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the session does not exist or is not active.
+    pub fn connect_udp_send_capsule(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        capsule: &Capsule,
+        now: Instant,
+    ) -> Res<()> {
+        self.needs_processing = true;
+        self.base_handler
+            .connect_udp_send_capsule(conn, session_id, capsule, now)
+    }
+
+    pub fn connect_ip_send_datagram<I: Into<DatagramTracking>>(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+        buf: &[u8],
+        id: I,
+    ) -> Res<()> {
+        self.needs_processing = true;
+        self.base_handler
+            .connect_ip_send_datagram(session_id, conn, buf, id)
+    }
+
     /// Process HTTTP3 layer.
     pub fn process_http3(&mut self, conn: &mut Connection, now: Instant) {
         qtrace!("[{self}] Process http3 internal");
@@ -286,9 +694,12 @@ impl Http3ServerHandler {
         }
 
         let res = self.check_connection_events(conn, now);
-        if !self.check_result(conn, now, &res) && self.base_handler.state().active() {
-            let res = self.base_handler.process_sending(conn, now);
-            self.check_result(conn, now, &res);
+        if !self.check_result(conn, now, &res) {
+            self.drain(conn, now);
+            if self.base_handler.state().active() {
+                let res = self.base_handler.process_sending(conn, now);
+                self.check_result(conn, now, &res);
+            }
         }
     }
 
@@ -299,7 +710,7 @@ impl Http3ServerHandler {
 
     /// Whether this connection has events to process or data to send.
     pub(crate) fn should_be_processed(&mut self) -> bool {
-        if self.needs_processing {
+        if self.needs_processing || self.drain_deadline.is_some() {
             self.needs_processing = false;
             return true;
         }
@@ -333,6 +744,10 @@ impl Http3ServerHandler {
             qdebug!("[{self}] check_connection_events - event {e:?}");
             match e {
                 ConnectionEvent::NewStream { stream_id } => {
+                    if stream_id.is_bidi() && stream_id >= self.next_request {
+                        self.next_request = stream_id;
+                        self.next_request.next();
+                    }
                     self.base_handler.add_new_stream(stream_id);
                 }
                 ConnectionEvent::RecvStreamReadable { stream_id } => {
@@ -342,17 +757,20 @@ impl Http3ServerHandler {
                     stream_id,
                     app_error,
                 } => {
+                    self.request_done(stream_id);
                     self.base_handler
                         .handle_stream_reset(stream_id, app_error, conn)?;
                 }
                 ConnectionEvent::SendStreamStopSending {
                     stream_id,
                     app_error,
-                } => self
-                    .base_handler
-                    .handle_stream_stop_sending(stream_id, app_error, conn)?,
+                } => {
+                    self.request_done(stream_id);
+                    self.base_handler
+                        .handle_stream_stop_sending(stream_id, app_error, conn)?;
+                }
                 ConnectionEvent::StateChange(state) => {
-                    if self.base_handler.handle_state_change(conn, &state)? {
+                    if self.base_handler.handle_state_change(conn, &state, now)? {
                         if self.base_handler.state() == &Http3State::Connected {
                             let settings = self.base_handler.save_settings();
                             conn.send_ticket(now, &settings)?;
@@ -371,10 +789,14 @@ impl Http3ServerHandler {
                 | ConnectionEvent::EchFallbackAuthenticationNeeded { .. }
                 | ConnectionEvent::ZeroRttRejected
                 | ConnectionEvent::ResumptionToken(..) => return Err(Error::HttpInternal(4)),
-                ConnectionEvent::SendStreamComplete { .. }
-                | ConnectionEvent::SendStreamCreatable { .. }
+                ConnectionEvent::SendStreamComplete { stream_id } => {
+                    self.request_done(stream_id);
+                }
+                ConnectionEvent::SendStreamCreatable { .. }
                 | ConnectionEvent::OutgoingDatagramOutcome { .. }
-                | ConnectionEvent::IncomingDatagramDropped => {}
+                | ConnectionEvent::IncomingDatagramDropped
+                | ConnectionEvent::PathOpened { .. }
+                | ConnectionEvent::PathClosed { .. } => {}
             }
         }
         Ok(())
@@ -392,36 +814,11 @@ impl Http3ServerHandler {
         {
             ReceiveOutput::NewStream(NewStreamType::Push(_)) => Err(Error::HttpStreamCreation),
             ReceiveOutput::NewStream(NewStreamType::Http(first_frame_type)) => {
-                self.base_handler.add_streams(
-                    stream_id,
-                    Box::new(SendMessage::new(
-                        MessageType::Response,
-                        Http3StreamType::Http,
-                        stream_id,
-                        Rc::clone(self.base_handler.qpack_encoder()),
-                        Box::new(self.events.clone()),
-                    )),
-                    Box::new(RecvMessage::new(
-                        &RecvMessageInfo {
-                            message_type: MessageType::Request,
-                            stream_type: Http3StreamType::Http,
-                            stream_id,
-                            first_frame_type: Some(first_frame_type),
-                        },
-                        Rc::clone(self.base_handler.qpack_decoder()),
-                        Box::new(self.events.clone()),
-                        None,
-                        PriorityHandler::new(false, Priority::default()),
-                    )),
-                );
-                let res = self
-                    .base_handler
-                    .handle_stream_readable(conn, stream_id, now)?;
-                assert_eq!(ReceiveOutput::NoOutput, res);
-                Ok(())
+                self.handle_new_request(conn, stream_id, first_frame_type, now)
             }
             ReceiveOutput::NewStream(NewStreamType::WebTransportStream(session_id)) => {
                 self.base_handler.webtransport_create_stream_remote(
+                    conn,
                     StreamId::from(session_id),
                     stream_id,
                     Box::new(self.events.clone()),
@@ -436,21 +833,24 @@ impl Http3ServerHandler {
             ReceiveOutput::ControlFrames(control_frames) => {
                 for f in control_frames {
                     match f {
-                        HFrame::MaxPushId { .. } => {
-                            // TODO implement push
-                            Ok(())
-                        }
-                        HFrame::Goaway { .. } | HFrame::CancelPush { .. } => {
+                        HFrame::MaxPushId { push_id } => self.handle_max_push_id(push_id),
+                        HFrame::CancelPush { push_id } => self.handle_cancel_push(push_id, conn),
+                        HFrame::Goaway { .. } | HFrame::Origin { .. } => {
                             Err(Error::HttpFrameUnexpected)
                         }
                         HFrame::PriorityUpdatePush {
                             element_id,
                             priority,
                         } => {
-                            // TODO: check if the element_id references a promised push stream or
-                            // is greater than the maximum Push ID.
-                            self.events
-                                .priority_update(StreamId::from(element_id), priority);
+                            // check that the element_id references a promised push
+                            let push_id = PushId::new(element_id);
+                            if push_id >= self.next_push_id {
+                                return Err(Error::HttpId);
+                            }
+                            if let Some(&stream_id) = self.push_streams.get(&push_id) {
+                                Self::set_stream_priority(conn, stream_id, priority);
+                                self.events.priority_update(stream_id, priority);
+                            }
                             Ok(())
                         }
                         HFrame::PriorityUpdateRequest {
@@ -467,11 +867,16 @@ impl Http3ServerHandler {
                                 return Err(Error::HttpId);
                             }
 
-                            self.events.priority_update(element_stream_id, priority);
+                            // Updates for requests that are done are not useful.
+                            if self.request_pending(element_stream_id) {
+                                self.priority_updates.insert(element_stream_id, priority);
+                                Self::set_stream_priority(conn, element_stream_id, priority);
+                                self.events.priority_update(element_stream_id, priority);
+                            }
                             Ok(())
                         }
                         _ => unreachable!(
-                            "we should only put MaxPushId, Goaway and PriorityUpdates into control_frames"
+                            "we should only put MaxPushId, CancelPush, Goaway and PriorityUpdates into control_frames"
                         ),
                     }?;
                 }
@@ -481,6 +886,60 @@ impl Http3ServerHandler {
         }
     }
 
+    fn handle_new_request(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: StreamId,
+        first_frame_type: u64,
+        now: Instant,
+    ) -> Res<()> {
+        self.base_handler.add_streams(
+            stream_id,
+            Box::new(SendMessage::new(
+                MessageType::Response,
+                Http3StreamType::Http,
+                stream_id,
+                Rc::clone(self.base_handler.qpack_encoder()),
+                Box::new(self.events.clone()),
+            )),
+            Box::new(RecvMessage::new(
+                &RecvMessageInfo {
+                    message_type: MessageType::Request,
+                    stream_type: Http3StreamType::Http,
+                    stream_id,
+                    first_frame_type: Some(first_frame_type),
+                },
+                Rc::clone(self.base_handler.qpack_decoder()),
+                Box::new(self.events.clone()),
+                None,
+                PriorityHandler::new(false, Priority::default()),
+            )),
+        );
+        if let Http3State::GoingAway(goaway) = self.base_handler.state()
+            && stream_id >= *goaway
+        {
+            qinfo!("[{self}] Reject request {stream_id} after GOAWAY");
+            self.request_done(stream_id);
+            return self.base_handler.cancel_fetch(
+                stream_id,
+                Error::HttpRequestRejected.code(),
+                conn,
+            );
+        }
+        self.requests.insert(stream_id);
+        let priority = self
+            .priority_updates
+            .get(&stream_id)
+            .copied()
+            .unwrap_or_default();
+        Self::set_stream_priority(conn, stream_id, priority);
+        let res = self
+            .base_handler
+            .handle_stream_readable(conn, stream_id, now)?;
+        assert_eq!(ReceiveOutput::NoOutput, res);
+        Ok(())
+    }
+
     /// Response data are read directly into a buffer supplied as a parameter of this function to
     /// avoid copying data.
     ///
@@ -14,7 +14,7 @@ use neqo_common::qtrace;
 use neqo_transport::{Connection, StreamId, StreamType};
 use rustc_hash::FxHashMap as HashMap;
 
-use crate::{BufferedStream, Error, Http3StreamType, RecvStream, Res, frames::HFrame};
+use crate::{BufferedStream, Error, Http3StreamType, RecvStream, Res, frames::HFrame, qlog};
 
 pub const HTTP3_UNI_STREAM_TYPE_CONTROL: u64 = 0x0;
 
@@ -24,6 +24,8 @@ pub struct ControlStreamLocal {
     stream: BufferedStream,
     /// `stream_id`s of outstanding request streams
     outstanding_priority_update: VecDeque<StreamId>,
+    /// Frames that are queued, but not yet logged to qlog.
+    unlogged: Vec<HFrame>,
 }
 
 impl Display for ControlStreamLocal {
@@ -36,6 +38,7 @@ impl ControlStreamLocal {
     /// Add a new frame that needs to be send.
     pub fn queue_frame(&mut self, f: &HFrame) {
         self.stream.encode_with(|e| f.encode(e));
+        self.unlogged.push(f.clone());
     }
 
     pub fn queue_update_priority(&mut self, stream_id: StreamId) {
@@ -49,6 +52,11 @@ impl ControlStreamLocal {
         recv_conn: &mut HashMap<StreamId, Box<dyn RecvStream>>,
         now: Instant,
     ) -> Res<()> {
+        if let Some(stream_id) = self.stream_id() {
+            for f in self.unlogged.drain(..) {
+                qlog::h3_frame_created(conn.qlog_mut(), stream_id, &f, &[], Some(now));
+            }
+        }
         self.stream.send_buffer(conn, now)?;
         self.send_priority_update(conn, recv_conn, now)
     }
@@ -79,6 +87,9 @@ impl ControlStreamLocal {
                     .stream
                     .send_atomic_with(conn, |e| hframe.encode(e), now)?
                 {
+                    if let Some(stream_id) = self.stream_id() {
+                        qlog::h3_frame_created(conn.qlog_mut(), stream_id, &hframe, &[], Some(now));
+                    }
                     stream.priority_update_sent()?;
                 } else {
                     self.outstanding_priority_update.push_front(update_id);
@@ -15,6 +15,7 @@ use neqo_transport::{Connection, StreamId};
 use crate::{
     CloseType, Error, Http3StreamType, ReceiveOutput, RecvStream, Res, Stream,
     frames::{FrameReader, HFrame, StreamReaderConnectionWrapper},
+    qlog,
 };
 
 /// The remote control stream is responsible only for reading frames. The frames are handled by
@@ -49,6 +50,9 @@ impl ControlStreamRemote {
             (_, true) => Err(Error::HttpClosedCriticalStream),
             (s, false) => {
                 qdebug!("[{self}] received {s:?}");
+                if let Some(f) = &s {
+                    qlog::h3_frame_parsed(conn.qlog_mut(), self.stream_id, f, now);
+                }
                 Ok(s)
             }
         }
@@ -0,0 +1,138 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Instant,
+};
+
+use neqo_common::{Bytes, Decoder, Encoder, qtrace};
+use neqo_transport::{Connection, StreamId};
+
+use crate::{
+    Error, RecvStream, Res,
+    features::extended_connect::{
+        CloseReason, ExtendedConnectEvents, ExtendedConnectType, Protocol,
+        session::{DgramContextIdError, State},
+    },
+    frames::{ConnectIpFrame, FrameReader, StreamReaderRecvStreamWrapper},
+};
+
+/// A CONNECT-IP session, see <https://www.rfc-editor.org/rfc/rfc9484>.
+#[derive(Debug)]
+pub struct Session {
+    frame_reader: FrameReader,
+    session_id: StreamId,
+}
+
+impl Session {
+    #[must_use]
+    pub(crate) fn new(session_id: StreamId) -> Self {
+        Self {
+            session_id,
+            frame_reader: FrameReader::new(),
+        }
+    }
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "ConnectIpSession")
+    }
+}
+
+impl Protocol for Session {
+    fn connect_type(&self) -> ExtendedConnectType {
+        ExtendedConnectType::ConnectIp
+    }
+
+    fn read_control_stream(
+        &mut self,
+        conn: &mut Connection,
+        events: &mut Box<dyn ExtendedConnectEvents>,
+        control_stream_recv: &mut Box<dyn RecvStream>,
+        now: Instant,
+    ) -> Res<Option<State>> {
+        loop {
+            let (f, fin) = self
+                .frame_reader
+                .receive::<ConnectIpFrame>(
+                    &mut StreamReaderRecvStreamWrapper::new(conn, control_stream_recv),
+                    now,
+                )
+                .map_err(|_| Error::HttpGeneralProtocolStream)?;
+            qtrace!("[{self}] Received capsule: {f:?} fin={fin}");
+
+            let received = f.is_some();
+            if let Some(f) = f {
+                events.connect_ip_capsule(self.session_id, f);
+            }
+
+            if fin {
+                events.session_end(
+                    ExtendedConnectType::ConnectIp,
+                    self.session_id,
+                    CloseReason::Clean {
+                        error: 0,
+                        message: String::new(),
+                    },
+                    None,
+                );
+                return Ok(Some(State::Done));
+            }
+            if !received {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn write_datagram_prefix(&self, encoder: &mut Encoder) {
+        encoder.encode_varint(0u64);
+    }
+
+    fn dgram_context_id(&self, datagram: Bytes) -> Result<(u64, Bytes), DgramContextIdError> {
+        let (context_id, offset) = {
+            let mut decoder = Decoder::new(datagram.as_ref());
+            (decoder.decode_varint(), decoder.offset())
+        };
+        match context_id {
+            // > A context ID of 0 indicates that the HTTP Datagram Payload
+            // > contains a full IP packet.
+            //
+            // <https://www.rfc-editor.org/rfc/rfc9484#section-6>
+            Some(0) => Ok((0, datagram.skip(offset))),
+            Some(context_id) => Err(DgramContextIdError::UnknownIdentifier(context_id)),
+            None => Err(DgramContextIdError::MissingIdentifier),
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use neqo_common::Bytes;
+    use neqo_transport::StreamId;
+
+    use super::Session;
+    use crate::features::extended_connect::session::Protocol as _;
+
+    #[test]
+    fn context_id() {
+        let session = Session::new(StreamId::new(42));
+        assert_eq!(
+            session
+                .dgram_context_id(Bytes::from(vec![0x00, 0x45, 0x00]))
+                .unwrap(),
+            (0, Bytes::from(vec![0x45, 0x00]))
+        );
+        assert!(
+            session
+                .dgram_context_id(Bytes::from(vec![0x02, 0x45, 0x00]))
+                .is_err()
+        );
+        assert!(session.dgram_context_id(Bytes::from(vec![])).is_err());
+    }
+}
@@ -5,11 +5,13 @@
 // except according to those terms.
 
 use std::{
+    collections::HashSet,
     fmt::{self, Display, Formatter},
+    mem,
     time::Instant,
 };
 
-use neqo_common::{Bytes, Decoder, Encoder};
+use neqo_common::{Bytes, Decoder, Encoder, Role, qtrace};
 use neqo_transport::{Connection, StreamId};
 
 use crate::{
@@ -18,21 +20,59 @@ use crate::{
         CloseReason, ExtendedConnectEvents, ExtendedConnectType, Protocol,
         session::{DgramContextIdError, State},
     },
-    frames::{ConnectUdpFrame, FrameReader, StreamReaderRecvStreamWrapper},
+    frames::{Capsule, FrameReader, StreamReaderRecvStreamWrapper},
 };
 
+/// Context IDs are varints.
+const MAX_CONTEXT_ID: u64 = (1 << 62) - 1;
+
+/// A CONNECT-UDP session, see <https://www.rfc-editor.org/rfc/rfc9298>.
 #[derive(Debug)]
 pub struct Session {
     frame_reader: FrameReader,
-    session_id: StreamId,
+    id: StreamId,
+    role: Role,
+    /// The registered context IDs, other than 0, which is always used for UDP
+    /// payloads.
+    contexts: HashSet<u64>,
+    /// The next context ID to allocate.  Clients allocate even context IDs and
+    /// proxies odd ones.
+    next_context_id: u64,
+    capsules: Vec<Vec<u8>>,
 }
 
 impl Session {
     #[must_use]
-    pub(crate) fn new(session_id: StreamId) -> Self {
+    pub(crate) fn new(session_id: StreamId, role: Role) -> Self {
         Self {
-            session_id,
+            id: session_id,
             frame_reader: FrameReader::new(),
+            role,
+            contexts: HashSet::new(),
+            next_context_id: match role {
+                Role::Client => 2,
+                Role::Server => 1,
+            },
+            capsules: Vec::new(),
+        }
+    }
+
+    /// Whether the peer allocates `context_id`.
+    fn peer_context(&self, context_id: u64) -> bool {
+        context_id != 0 && (context_id % 2 == 1) == (self.role == Role::Client)
+    }
+
+    fn datagram(&self, events: &dyn ExtendedConnectEvents, datagram: Bytes) {
+        match self.dgram_context_id(datagram) {
+            Ok((0, payload)) => {
+                events.new_datagram(self.id, payload, ExtendedConnectType::ConnectUdp);
+            }
+            Ok((context_id, payload)) => {
+                events.connect_udp_context_datagram(self.id, context_id, payload);
+            }
+            Err(e) => {
+                qtrace!("[{self}] Dropping DATAGRAM capsule: {e}");
+            }
         }
     }
 }
@@ -55,46 +95,102 @@ impl Protocol for Session {
         control_stream_recv: &mut Box<dyn RecvStream>,
         now: Instant,
     ) -> Res<Option<State>> {
-        let (f, fin) = self
-            .frame_reader
-            .receive::<ConnectUdpFrame>(
-                &mut StreamReaderRecvStreamWrapper::new(conn, control_stream_recv),
-                now,
-            )
-            .map_err(|_| Error::HttpGeneralProtocolStream)?;
-
-        if let Some(f) = f {
-            // TODO: Implement HTTP Datagram <https://github.com/mozilla/neqo/issues/2843>.
-            match f {}
+        loop {
+            let (c, fin) = self
+                .frame_reader
+                .receive::<Capsule>(
+                    &mut StreamReaderRecvStreamWrapper::new(conn, control_stream_recv),
+                    now,
+                )
+                .map_err(|_| Error::HttpGeneralProtocolStream)?;
+            qtrace!("[{self}] Received capsule: {c:?} fin={fin}");
+
+            let received = c.is_some();
+            match c {
+                Some(Capsule::Datagram(payload)) => {
+                    self.datagram(events.as_ref(), Bytes::from(payload));
+                }
+                Some(capsule) => events.connect_udp_capsule(self.id, capsule),
+                None => {}
+            }
+
+            if fin {
+                events.session_end(
+                    ExtendedConnectType::ConnectUdp,
+                    self.id,
+                    CloseReason::Clean {
+                        error: 0,
+                        message: String::new(),
+                    },
+                    None,
+                );
+                return Ok(Some(State::Done));
+            }
+            if !received {
+                return Ok(None);
+            }
         }
+    }
 
-        if fin {
-            events.session_end(
-                ExtendedConnectType::ConnectUdp,
-                self.session_id,
-                CloseReason::Clean {
-                    error: 0,
-                    message: String::new(),
-                },
-                None,
-            );
-            Ok(Some(State::Done))
+    fn register_context(&mut self, context_id: Option<u64>) -> Res<u64> {
+        let context_id = if let Some(context_id) = context_id {
+            // > Non-zero even-numbered context IDs are client-allocated, and
+            // > odd-numbered context IDs are proxy-allocated. [...] Context IDs
+            // > MUST NOT be re-allocated within a given HTTP namespace
+            //
+            // <https://www.rfc-editor.org/rfc/rfc9298#section-4>
+            if context_id > MAX_CONTEXT_ID
+                || !self.peer_context(context_id)
+                || !self.contexts.insert(context_id)
+            {
+                return Err(Error::InvalidInput);
+            }
+            context_id
         } else {
-            Ok(None)
-        }
+            let context_id = self.next_context_id;
+            if context_id > MAX_CONTEXT_ID {
+                return Err(Error::Unavailable);
+            }
+            self.next_context_id += 2;
+            self.contexts.insert(context_id);
+            context_id
+        };
+        qtrace!("[{self}] Registered context ID {context_id}");
+        Ok(context_id)
+    }
+
+    fn context_registered(&self, context_id: u64) -> bool {
+        context_id == 0 || self.contexts.contains(&context_id)
+    }
+
+    fn datagram_capsule(&mut self, payload: &[u8]) -> bool {
+        let mut enc = Encoder::default();
+        Capsule::Datagram(payload.to_vec()).encode(&mut enc);
+        self.capsules.push(enc.into());
+        true
+    }
+
+    fn take_capsules(&mut self) -> Vec<Vec<u8>> {
+        mem::take(&mut self.capsules)
+    }
+
+    fn has_capsules(&self) -> bool {
+        !self.capsules.is_empty()
     }
 
     fn write_datagram_prefix(&self, encoder: &mut Encoder) {
         encoder.encode_varint(0u64);
     }
 
-    fn dgram_context_id(&self, datagram: Bytes) -> Result<Bytes, DgramContextIdError> {
+    fn dgram_context_id(&self, datagram: Bytes) -> Result<(u64, Bytes), DgramContextIdError> {
         let (context_id, offset) = {
             let mut decoder = Decoder::new(datagram.as_ref());
             (decoder.decode_varint(), decoder.offset())
         };
         match context_id {
-            Some(0) => Ok(datagram.skip(offset)),
+            Some(context_id) if self.context_registered(context_id) => {
+                Ok((context_id, datagram.skip(offset)))
+            }
             Some(context_id) => Err(DgramContextIdError::UnknownIdentifier(context_id)),
             None => {
                 // > all HTTP Datagrams associated with UDP Proxying request streams start with a Context ID field;
@@ -109,28 +205,75 @@ impl Protocol for Session {
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 mod tests {
-    use neqo_common::Bytes;
+    use neqo_common::{Bytes, Role};
     use neqo_transport::StreamId;
 
-    use super::Session;
-    use crate::features::extended_connect::session::Protocol as _;
+    use super::{MAX_CONTEXT_ID, Session};
+    use crate::{Error, features::extended_connect::session::Protocol as _};
 
     #[test]
     fn varint_0_context_id() {
-        let session = Session::new(StreamId::new(42));
+        let session = Session::new(StreamId::new(42), Role::Client);
         // Varint [0x00] is 0, i.e. a supported connect-udp context ID.
         assert_eq!(
             session
                 .dgram_context_id(Bytes::from(vec![0x00, 0x00, 0x00]))
                 .unwrap(),
-            Bytes::from(vec![0x00, 0x00])
+            (0, Bytes::from(vec![0x00, 0x00]))
         );
         // Varint [0x40 0x00] is 0 as well, thus a supported connect-udp context ID, too.
         assert_eq!(
             session
                 .dgram_context_id(Bytes::from(vec![0x40, 0x00, 0x00, 0x00]))
                 .unwrap(),
-            Bytes::from(vec![0x00, 0x00])
+            (0, Bytes::from(vec![0x00, 0x00]))
+        );
+        assert!(session.dgram_context_id(Bytes::from(vec![])).is_err());
+    }
+
+    #[test]
+    fn register_context() {
+        let mut client = Session::new(StreamId::new(0), Role::Client);
+        assert!(
+            client
+                .dgram_context_id(Bytes::from(vec![0x02, 0x00]))
+                .is_err()
         );
+        assert_eq!(client.register_context(None), Ok(2));
+        assert_eq!(client.register_context(None), Ok(4));
+        assert!(client.context_registered(2));
+        assert_eq!(
+            client
+                .dgram_context_id(Bytes::from(vec![0x02, 0x00]))
+                .unwrap(),
+            (2, Bytes::from(vec![0x00]))
+        );
+
+        // The client can only register context IDs that the proxy allocates.
+        assert_eq!(client.register_context(Some(3)), Ok(3));
+        assert_eq!(client.register_context(Some(3)), Err(Error::InvalidInput));
+        assert_eq!(client.register_context(Some(6)), Err(Error::InvalidInput));
+        assert_eq!(client.register_context(Some(0)), Err(Error::InvalidInput));
+        assert!(client.context_registered(3));
+        assert!(!client.context_registered(5));
+
+        let mut proxy = Session::new(StreamId::new(0), Role::Server);
+        assert_eq!(proxy.register_context(None), Ok(1));
+        assert_eq!(proxy.register_context(Some(2)), Ok(2));
+        assert_eq!(proxy.register_context(Some(0)), Err(Error::InvalidInput));
+        assert_eq!(
+            proxy.register_context(Some(MAX_CONTEXT_ID + 1)),
+            Err(Error::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn datagram_capsule() {
+        let mut session = Session::new(StreamId::new(0), Role::Client);
+        assert!(!session.has_capsules());
+        assert!(session.datagram_capsule(&[0x00, 0x01]));
+        assert!(session.has_capsules());
+        assert_eq!(session.take_capsules(), vec![vec![0x00, 0x02, 0x00, 0x01]]);
+        assert!(!session.has_capsules());
     }
 }
@@ -4,6 +4,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+pub(crate) mod connect_ip_session;
 pub(crate) mod connect_udp_session;
 pub mod session;
 pub(crate) mod webtransport_session;
@@ -23,8 +24,12 @@ use crate::{
     client_events::Http3ClientEvents,
     features::{
         NegotiationState,
-        extended_connect::session::{CloseReason, Protocol},
+        extended_connect::{
+            session::{CloseReason, Protocol},
+            webtransport_session::SessionLimits,
+        },
     },
+    frames::{Capsule, ConnectIpFrame},
     settings::{HSettingType, HSettings},
 };
 
@@ -54,6 +59,10 @@ pub(crate) trait ExtendedConnectEvents: Debug {
         datagram: Bytes,
         connect_type: ExtendedConnectType,
     );
+    fn connect_udp_context_datagram(&self, session_id: StreamId, context_id: u64, datagram: Bytes);
+    fn connect_udp_capsule(&self, session_id: StreamId, capsule: Capsule);
+    fn connect_ip_capsule(&self, session_id: StreamId, capsule: ConnectIpFrame);
+    fn webtransport_session_draining(&self, session_id: StreamId);
 }
 
 #[derive(Debug, PartialEq, Copy, Clone, Eq, strum::Display)]
@@ -62,13 +71,23 @@ pub(crate) enum ExtendedConnectType {
     WebTransport,
     #[strum(to_string = "connect-udp")]
     ConnectUdp,
+    #[strum(to_string = "connect-ip")]
+    ConnectIp,
 }
 
 impl ExtendedConnectType {
-    pub(crate) fn new_protocol(self, session_id: StreamId, role: Role) -> Box<dyn Protocol> {
+    pub(crate) fn new_protocol(
+        self,
+        session_id: StreamId,
+        role: Role,
+        limits: &SessionLimits,
+    ) -> Box<dyn Protocol> {
         match self {
-            Self::WebTransport => Box::new(webtransport_session::Session::new(session_id, role)),
-            Self::ConnectUdp => Box::new(connect_udp_session::Session::new(session_id)),
+            Self::WebTransport => {
+                Box::new(webtransport_session::Session::new(session_id, role, limits))
+            }
+            Self::ConnectUdp => Box::new(connect_udp_session::Session::new(session_id, role)),
+            Self::ConnectIp => Box::new(connect_ip_session::Session::new(session_id)),
         }
     }
 }
@@ -77,7 +96,7 @@ impl From<ExtendedConnectType> for HSettingType {
     fn from(from: ExtendedConnectType) -> Self {
         match from {
             ExtendedConnectType::WebTransport => Self::EnableWebTransport,
-            ExtendedConnectType::ConnectUdp => Self::EnableConnect,
+            ExtendedConnectType::ConnectUdp | ExtendedConnectType::ConnectIp => Self::EnableConnect,
         }
     }
 }
@@ -89,9 +108,9 @@ pub(crate) struct ExtendedConnectFeature {
 
 impl ExtendedConnectFeature {
     #[must_use]
-    pub fn new(connect_type: ExtendedConnectType, enable: bool) -> Self {
+    pub const fn new(connect_type: ExtendedConnectType, enable: bool) -> Self {
         Self {
-            feature_negotiation: NegotiationState::new(enable, HSettingType::from(connect_type)),
+            feature_negotiation: NegotiationState::new(enable, connect_type),
         }
     }
 
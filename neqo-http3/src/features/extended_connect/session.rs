@@ -14,16 +14,18 @@ use std::{
 };
 
 use neqo_common::{Bytes, Encoder, Header, MessageType, Role, qdebug, qtrace};
-use neqo_transport::{AppError, Connection, DatagramTracking, StreamId};
+use neqo_transport::{AppError, Connection, DatagramTracking, StreamId, StreamType};
 
 use crate::{
     CloseType, Error, Http3StreamType, HttpRecvStream, Priority, ReceiveOutput, RecvStream, Res,
     SendStream, Stream,
     features::extended_connect::{
         ExtendedConnectEvents, ExtendedConnectType, HeaderListener, Headers,
+        webtransport_session::{SessionLimits, WT_FLOW_CONTROL_ERROR},
     },
     frames::HFrame,
     priority::PriorityHandler,
+    qlog,
     recv_message::{RecvMessage, RecvMessageInfo},
     send_message::SendMessage,
 };
@@ -60,6 +62,8 @@ pub(crate) struct Session {
     /// Corresponds to the `:protocol` pseudo-header in the HTTP EXTENDED
     /// CONNECT request.
     protocol: Box<dyn Protocol>,
+    /// The error to reset the session with, after the peer exceeded a limit.
+    reset: Option<AppError>,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -91,9 +95,10 @@ impl Session {
         qpack_encoder: Rc<RefCell<neqo_qpack::Encoder>>,
         qpack_decoder: Rc<RefCell<neqo_qpack::Decoder>>,
         connect_type: ExtendedConnectType,
+        limits: &SessionLimits,
     ) -> Self {
         let stream_event_listener = Rc::new(RefCell::new(HeaderListener::default()));
-        let protocol = connect_type.new_protocol(session_id, role);
+        let protocol = connect_type.new_protocol(session_id, role, limits);
         Self {
             control_stream_recv: Box::new(RecvMessage::new(
                 &RecvMessageInfo {
@@ -119,6 +124,7 @@ impl Session {
             state: State::Negotiating,
             events,
             protocol,
+            reset: None,
         }
     }
 
@@ -129,9 +135,10 @@ impl Session {
         mut control_stream_recv: Box<dyn RecvStream>,
         mut control_stream_send: Box<dyn SendStream>,
         connect_type: ExtendedConnectType,
+        limits: &SessionLimits,
     ) -> Res<Self> {
         let stream_event_listener = Rc::new(RefCell::new(HeaderListener::default()));
-        let protocol = connect_type.new_protocol(session_id, role);
+        let protocol = connect_type.new_protocol(session_id, role, limits);
         control_stream_recv
             .http_stream()
             .ok_or(Error::Internal)?
@@ -148,6 +155,7 @@ impl Session {
             state: State::Active,
             events,
             protocol,
+            reset: None,
         })
     }
 
@@ -208,6 +216,14 @@ impl Session {
     }
 
     fn send(&mut self, conn: &mut Connection, now: Instant) -> Res<()> {
+        let capsules = self.protocol.take_capsules();
+        if self.state == State::Active {
+            for capsule in capsules {
+                qlog::wt_frame_created(conn.qlog_mut(), self.id, &capsule, now);
+                self.control_stream_send
+                    .send_data_atomic(conn, &capsule, now)?;
+            }
+        }
         self.control_stream_send.send(conn, now)?;
         if self.control_stream_send.done() {
             self.state = State::Done;
@@ -314,10 +330,73 @@ impl Session {
         self.protocol.remove_recv_stream(stream_id);
     }
 
+    /// Returns `false` if the peer can't open any more streams in this
+    /// session.  The session is then reset.
+    pub(crate) fn accept_stream(&mut self, stream_id: StreamId) -> bool {
+        self.protocol.accept_stream(stream_id) || {
+            self.flow_control_error();
+            false
+        }
+    }
+
+    pub(crate) fn stream_creatable(&mut self, stream_type: StreamType) -> bool {
+        self.protocol.stream_creatable(stream_type)
+    }
+
+    /// Returns `false` if the peer sent more data than this session allows.
+    /// The session is then reset.
+    pub(crate) fn stream_data_received(&mut self, stream_id: StreamId, received: u64) -> bool {
+        self.protocol.stream_data_received(stream_id, received) || {
+            self.flow_control_error();
+            false
+        }
+    }
+
+    pub(crate) fn stream_data_read(&mut self, stream_id: StreamId, amount: usize) {
+        self.protocol.stream_data_read(stream_id, amount);
+    }
+
+    pub(crate) fn stream_data_sendable(&mut self, amount: usize) -> usize {
+        self.protocol.stream_data_sendable(amount)
+    }
+
+    pub(crate) fn stream_data_sent(&mut self, amount: usize) {
+        self.protocol.stream_data_sent(amount);
+    }
+
+    pub(crate) fn take_unblocked_streams(&mut self) -> Vec<StreamId> {
+        self.protocol.take_unblocked_streams()
+    }
+
+    /// Take the error to reset the session with, if the peer exceeded a flow
+    /// control limit.
+    pub(crate) const fn take_reset(&mut self) -> Option<AppError> {
+        self.reset.take()
+    }
+
+    fn flow_control_error(&mut self) {
+        if self.state.closing_state() {
+            return;
+        }
+        qdebug!("[{self}]: flow control error");
+        self.state = State::Done;
+        self.reset = Some(WT_FLOW_CONTROL_ERROR);
+        self.events.session_end(
+            self.protocol.connect_type(),
+            self.id,
+            CloseReason::Error(WT_FLOW_CONTROL_ERROR),
+            None,
+        );
+    }
+
     pub(crate) fn remove_send_stream(&mut self, stream_id: StreamId) {
         self.protocol.remove_send_stream(stream_id);
     }
 
+    pub(crate) fn connect_type(&self) -> ExtendedConnectType {
+        self.protocol.connect_type()
+    }
+
     #[must_use]
     pub(crate) const fn is_active(&self) -> bool {
         matches!(self.state, State::Active)
@@ -358,6 +437,7 @@ impl Session {
         self.state = State::Done;
 
         if let Some(close_frame) = self.protocol.close_frame(error, message) {
+            qlog::wt_frame_created(conn.qlog_mut(), self.id, &close_frame, now);
             self.control_stream_send
                 .send_data_atomic(conn, close_frame.as_ref(), now)?;
         }
@@ -371,34 +451,131 @@ impl Session {
         Ok(())
     }
 
+    /// # Errors
+    ///
+    /// Returns `InvalidStreamId` if the session is not of type `connect_type`
+    /// or it is not active.
+    pub(crate) fn send_capsule(
+        &mut self,
+        conn: &mut Connection,
+        connect_type: ExtendedConnectType,
+        capsule: &[u8],
+        now: Instant,
+    ) -> Res<()> {
+        qtrace!("[{self}] send_capsule state={:?}", self.state);
+        if self.protocol.connect_type() != connect_type || self.state != State::Active {
+            return Err(Error::InvalidStreamId);
+        }
+        self.control_stream_send
+            .send_data_atomic(conn, capsule, now)
+    }
+
+    /// Ask the peer to stop using the session.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidStreamId` if the session does not support draining or
+    /// is not active.
+    pub(crate) fn drain_session(&mut self, conn: &mut Connection, now: Instant) -> Res<()> {
+        qtrace!("[{self}] drain_session state={:?}", self.state);
+        if self.state != State::Active {
+            return Err(Error::InvalidStreamId);
+        }
+        let drain_frame = self.protocol.drain_frame().ok_or(Error::InvalidStreamId)?;
+        qlog::wt_frame_created(conn.qlog_mut(), self.id, &drain_frame, now);
+        self.control_stream_send
+            .send_data_atomic(conn, &drain_frame, now)
+    }
+
     fn send_data(&mut self, conn: &mut Connection, buf: &[u8], now: Instant) -> Res<usize> {
         self.control_stream_send.send_data(conn, buf, now)
     }
 
+    /// Send a datagram.  If HTTP/3 datagrams can't be used, it is sent in a
+    /// DATAGRAM capsule instead, if the protocol supports that.
+    ///
     /// # Errors
     ///
     /// Returns an error if the datagram exceeds the remote datagram size limit.
     pub(crate) fn send_datagram<I: Into<DatagramTracking>>(
-        &self,
+        &mut self,
         conn: &mut Connection,
         buf: &[u8],
         id: I,
+        h3_datagrams: bool,
     ) -> Res<()> {
         qtrace!("[{self}] send_datagram state={:?}", self.state);
-        if self.state == State::Active {
-            let mut dgram_data = Encoder::default();
-            dgram_data.encode_varint(self.id.as_u64() / 4);
-            self.protocol.write_datagram_prefix(&mut dgram_data);
-            dgram_data.encode(buf);
-            conn.send_datagram(dgram_data.into(), id)?;
-        } else {
+        if self.state != State::Active {
             qdebug!("[{self}]: cannot send datagram in {:?} state.", self.state);
             debug_assert!(false);
             return Err(Error::Unavailable);
         }
+        let mut payload = Encoder::default();
+        self.protocol.write_datagram_prefix(&mut payload);
+        payload.encode(buf);
+        self.send_datagram_payload(conn, payload.as_ref(), id, h3_datagrams)
+    }
+
+    /// Send a datagram with a context ID that was registered with
+    /// [`Self::register_context`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if the context ID is not registered,
+    /// `Unavailable` if the session is not active, or an error if the datagram
+    /// exceeds the remote datagram size limit.
+    pub(crate) fn send_context_datagram<I: Into<DatagramTracking>>(
+        &mut self,
+        conn: &mut Connection,
+        context_id: u64,
+        buf: &[u8],
+        id: I,
+        h3_datagrams: bool,
+    ) -> Res<()> {
+        qtrace!(
+            "[{self}] send_context_datagram context_id={context_id} state={:?}",
+            self.state
+        );
+        if self.state != State::Active {
+            return Err(Error::Unavailable);
+        }
+        if !self.protocol.context_registered(context_id) {
+            return Err(Error::InvalidInput);
+        }
+        let mut payload = Encoder::default();
+        payload.encode_varint(context_id);
+        payload.encode(buf);
+        self.send_datagram_payload(conn, payload.as_ref(), id, h3_datagrams)
+    }
+
+    fn send_datagram_payload<I: Into<DatagramTracking>>(
+        &mut self,
+        conn: &mut Connection,
+        payload: &[u8],
+        id: I,
+        h3_datagrams: bool,
+    ) -> Res<()> {
+        if !h3_datagrams && self.protocol.datagram_capsule(payload) {
+            return Ok(());
+        }
+        let mut dgram_data = Encoder::default();
+        dgram_data.encode_varint(self.id.as_u64() / 4);
+        dgram_data.encode(payload);
+        conn.send_datagram(dgram_data.into(), id)?;
         Ok(())
     }
 
+    /// Register a context ID for datagrams.  With `None`, a new context ID is
+    /// allocated; otherwise `context_id` is one that the peer allocated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidStreamId` if the protocol does not use context IDs, or
+    /// `InvalidInput` if the peer can't allocate `context_id`.
+    pub(crate) fn register_context(&mut self, context_id: Option<u64>) -> Res<u64> {
+        self.protocol.register_context(context_id)
+    }
+
     pub(crate) fn datagram(&self, datagram: Bytes) {
         if self.state != State::Active {
             qdebug!("[{self}]: received datagram on {:?} session.", self.state);
@@ -407,10 +584,14 @@ impl Session {
 
         // dgram_context_id returns the payload after stripping any context ID
         match self.protocol.dgram_context_id(datagram) {
-            Ok(slice) => {
+            Ok((0, slice)) => {
                 self.events
                     .new_datagram(self.id, slice, self.protocol.connect_type());
             }
+            Ok((context_id, slice)) => {
+                self.events
+                    .connect_udp_context_datagram(self.id, context_id, slice);
+            }
             Err(e) => {
                 qdebug!("[{self}]: received datagram with invalid context identifier: {e}");
             }
@@ -419,6 +600,7 @@ impl Session {
 
     fn has_data_to_send(&self) -> bool {
         self.control_stream_send.has_data_to_send()
+            || (self.state == State::Active && self.protocol.has_capsules())
     }
 
     fn done(&self) -> bool {
@@ -562,9 +744,73 @@ pub(crate) trait Protocol: Debug + Display {
         (HashSet::default(), HashSet::default())
     }
 
+    /// Whether a stream of this type can be created within the peer's limit.
+    fn stream_creatable(&mut self, _stream_type: StreamType) -> bool {
+        true
+    }
+
+    /// Count a new stream from the peer.  Returns `false` if the peer exceeded
+    /// its stream limit.
+    fn accept_stream(&mut self, _stream_id: StreamId) -> bool {
+        true
+    }
+
+    /// Count data from the peer, where `received` is the amount that has
+    /// arrived on the stream in total.  Returns `false` if the peer exceeded
+    /// its data limit.
+    fn stream_data_received(&mut self, _stream_id: StreamId, _received: u64) -> bool {
+        true
+    }
+
+    fn stream_data_read(&mut self, _stream_id: StreamId, _amount: usize) {}
+
+    /// How much of `amount` the peer's data limit allows to be sent.
+    fn stream_data_sendable(&mut self, amount: usize) -> usize {
+        amount
+    }
+
+    fn stream_data_sent(&mut self, _amount: usize) {}
+
+    /// Capsules that the session needs to send, e.g. to raise flow control
+    /// limits.
+    fn take_capsules(&mut self) -> Vec<Vec<u8>> {
+        Vec::new()
+    }
+
+    fn has_capsules(&self) -> bool {
+        false
+    }
+
+    /// The send streams that can send again, after the peer raised a limit.
+    fn take_unblocked_streams(&mut self) -> Vec<StreamId> {
+        Vec::new()
+    }
+
+    fn drain_frame(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Register a context ID for datagrams, see [`Session::register_context`].
+    fn register_context(&mut self, _context_id: Option<u64>) -> Res<u64> {
+        Err(Error::InvalidStreamId)
+    }
+
+    /// Whether datagrams can be sent with `context_id`.
+    fn context_registered(&self, _context_id: u64) -> bool {
+        false
+    }
+
+    /// Queue a DATAGRAM capsule with `payload`, for when HTTP/3 datagrams
+    /// can't be used.  Returns `false` if the protocol does not do that.
+    fn datagram_capsule(&mut self, _payload: &[u8]) -> bool {
+        false
+    }
+
     fn write_datagram_prefix(&self, encoder: &mut Encoder);
 
-    fn dgram_context_id(&self, datagram: Bytes) -> Result<Bytes, DgramContextIdError>;
+    /// Split a received datagram into its context ID and payload.  The
+    /// context ID is 0 for protocols that don't use them.
+    fn dgram_context_id(&self, datagram: Bytes) -> Result<(u64, Bytes), DgramContextIdError>;
 }
 
 #[derive(Debug, Error)]
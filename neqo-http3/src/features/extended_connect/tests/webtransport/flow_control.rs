@@ -0,0 +1,110 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use neqo_common::event::Provider as _;
+use neqo_transport::StreamType;
+use test_fixture::now;
+
+use crate::{
+    Error, Http3ClientEvent, Http3ServerEvent, WebTransportEvent, WebTransportServerEvent,
+    features::extended_connect::tests::webtransport::{WtTest, wt_default_parameters},
+};
+
+#[test]
+fn wt_drain_session_server() {
+    let mut wt = WtTest::new();
+    let wt_session = wt.create_wt_session();
+
+    wt_session.drain_session(now()).unwrap();
+    wt.exchange_packets();
+    let session_id = wt_session.stream_id();
+    assert!(wt.client.events().any(|e| matches!(
+        e,
+        Http3ClientEvent::WebTransport(WebTransportEvent::SessionDraining { session_id: id })
+            if id == session_id
+    )));
+
+    // The session can still be used.
+    let wt_stream = wt.create_wt_stream_client(session_id, StreamType::UniDi);
+    wt.send_data_client(wt_stream, &[0; 10]);
+    wt.receive_data_server(wt_stream, true, &[0; 10], false);
+}
+
+#[test]
+fn wt_drain_session_client() {
+    let mut wt = WtTest::new();
+    let wt_session = wt.create_wt_session();
+
+    wt.client
+        .webtransport_drain_session(wt_session.stream_id(), now())
+        .unwrap();
+    wt.exchange_packets();
+    assert!(wt.server.events().any(|e| matches!(
+        e,
+        Http3ServerEvent::WebTransport(WebTransportServerEvent::SessionDraining { session })
+            if session.stream_id() == wt_session.stream_id()
+    )));
+}
+
+#[test]
+fn wt_drain_unknown_session() {
+    let mut wt = WtTest::new();
+    let wt_session = wt.create_wt_session();
+    let wt_stream = wt.create_wt_stream_client(wt_session.stream_id(), StreamType::BiDi);
+    assert_eq!(
+        wt.client.webtransport_drain_session(wt_stream, now()),
+        Err(Error::InvalidStreamId)
+    );
+}
+
+#[test]
+fn wt_stream_limit() {
+    let mut wt = WtTest::new_with_params(
+        wt_default_parameters(),
+        wt_default_parameters().webtransport_max_streams_uni(1),
+    );
+    let wt_session = wt.create_wt_session();
+    let session_id = wt_session.stream_id();
+
+    let wt_stream = wt.create_wt_stream_client(session_id, StreamType::UniDi);
+    assert_eq!(
+        wt.client
+            .webtransport_create_stream(session_id, StreamType::UniDi),
+        Err(Error::StreamLimit)
+    );
+
+    // Once the server is done with the first stream, it allows another.
+    wt.send_data_client(wt_stream, &[0; 10]);
+    wt.close_stream_sending_client(wt_stream);
+    wt.receive_data_server(wt_stream, true, &[0; 10], true);
+    wt.exchange_packets();
+    wt.create_wt_stream_client(session_id, StreamType::UniDi);
+
+    // The limit only applies to unidirectional streams.
+    wt.create_wt_stream_client(session_id, StreamType::BiDi);
+}
+
+#[test]
+fn wt_data_limit() {
+    let mut wt = WtTest::new_with_params(
+        wt_default_parameters(),
+        wt_default_parameters().webtransport_max_data(10),
+    );
+    let wt_session = wt.create_wt_session();
+    let wt_stream = wt.create_wt_stream_client(wt_session.stream_id(), StreamType::UniDi);
+
+    assert_eq!(wt.client.send_data(wt_stream, &[0; 20], now()), Ok(10));
+    assert_eq!(wt.client.send_data(wt_stream, &[0; 20], now()), Ok(0));
+    wt.receive_data_server(wt_stream, true, &[0; 10], false);
+
+    // Reading the data lets the server raise the limit.
+    wt.exchange_packets();
+    assert!(wt.client.events().any(|e| matches!(
+        e,
+        Http3ClientEvent::DataWritable { stream_id } if stream_id == wt_stream
+    )));
+    assert_eq!(wt.client.send_data(wt_stream, &[0; 10], now()), Ok(10));
+}
@@ -5,6 +5,7 @@
 // except according to those terms.
 
 mod datagrams;
+mod flow_control;
 mod negotiation;
 mod sessions;
 mod streams;
@@ -5,24 +5,166 @@
 // except according to those terms.
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fmt::{self, Display, Formatter},
     mem,
     time::Instant,
 };
 
-use neqo_common::{Bytes, Encoder, Role, qtrace};
-use neqo_transport::{Connection, StreamId};
+use neqo_common::{Bytes, Encoder, Role, qdebug, qtrace};
+use neqo_transport::{AppError, Connection, StreamId, StreamType};
 
 use crate::{
-    Error, Http3StreamInfo, Http3StreamType, RecvStream, Res,
+    Error, Http3Parameters, Http3StreamInfo, Http3StreamType, RecvStream, Res,
     features::extended_connect::{
         CloseReason, ExtendedConnectEvents, ExtendedConnectType,
         session::{DgramContextIdError, Protocol, State},
+        webtransport_streams::{WEBTRANSPORT_STREAM, WEBTRANSPORT_UNI_STREAM},
     },
     frames::{FrameReader, StreamReaderRecvStreamWrapper, WebTransportFrame},
+    qlog,
+    settings::{HSettingType, HSettings},
 };
 
+/// The error that a session is reset with when the peer exceeds a limit.
+///
+/// See <https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3-13#section-9.5>.
+pub const WT_FLOW_CONTROL_ERROR: AppError = 0x045d_4487;
+
+/// Flow control limits for a session.  Each of these is `u64::MAX` when the
+/// limit is not used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub data: u64,
+    pub streams_bidi: u64,
+    pub streams_uni: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            data: u64::MAX,
+            streams_bidi: u64::MAX,
+            streams_uni: u64::MAX,
+        }
+    }
+}
+
+impl Limits {
+    /// The initial limits that a peer sent in its settings.
+    pub fn from_settings(settings: &HSettings) -> Self {
+        Self {
+            data: settings.get(HSettingType::WebTransportMaxData),
+            streams_bidi: settings.get(HSettingType::WebTransportMaxStreamsBidi),
+            streams_uni: settings.get(HSettingType::WebTransportMaxStreamsUni),
+        }
+    }
+
+    /// The limits that are configured locally.
+    pub fn from_params(params: &Http3Parameters) -> Self {
+        Self {
+            data: params.get_webtransport_max_data().unwrap_or(u64::MAX),
+            streams_bidi: params
+                .get_webtransport_max_streams_bidi()
+                .unwrap_or(u64::MAX),
+            streams_uni: params
+                .get_webtransport_max_streams_uni()
+                .unwrap_or(u64::MAX),
+        }
+    }
+}
+
+/// The limits for both directions of a session.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SessionLimits {
+    /// What the peer can send.
+    pub local: Limits,
+    /// What we can send.
+    pub remote: Limits,
+}
+
+/// A limit on the peer, which is raised as the application uses up what the
+/// peer sent.
+#[derive(Debug)]
+struct RecvLimit {
+    /// The initial limit.  The peer can always have this much outstanding.
+    window: u64,
+    max: u64,
+    used: u64,
+    retired: u64,
+}
+
+impl RecvLimit {
+    const fn new(window: u64) -> Self {
+        Self {
+            window,
+            max: window,
+            used: 0,
+            retired: 0,
+        }
+    }
+
+    /// Returns `false` if the peer exceeded the limit.
+    const fn consume(&mut self, amount: u64) -> bool {
+        self.used = self.used.saturating_add(amount);
+        self.used <= self.max
+    }
+
+    /// Returns a new limit to send, once enough of the window is retired.
+    const fn retire(&mut self, amount: u64) -> Option<u64> {
+        self.retired += amount;
+        if self.window == u64::MAX {
+            return None;
+        }
+        let next = self.retired.saturating_add(self.window);
+        if next - self.max > self.window / 2 {
+            self.max = next;
+            Some(next)
+        } else {
+            None
+        }
+    }
+}
+
+/// A limit that the peer sets.
+#[derive(Debug)]
+struct SendLimit {
+    max: u64,
+    used: u64,
+    /// The limit that the peer was last told blocked us.
+    blocked: Option<u64>,
+}
+
+impl SendLimit {
+    const fn new(max: u64) -> Self {
+        Self {
+            max,
+            used: 0,
+            blocked: None,
+        }
+    }
+
+    const fn available(&self) -> u64 {
+        self.max - self.used
+    }
+
+    /// Returns `true` if the peer needs to be told that we are blocked.
+    fn blocked(&mut self) -> bool {
+        let new = self.blocked != Some(self.max);
+        self.blocked = Some(self.max);
+        new
+    }
+
+    /// Returns `true` if this lifts the limit for a sender that was blocked.
+    const fn update(&mut self, max: u64) -> bool {
+        if max <= self.max {
+            return false;
+        }
+        self.max = max;
+        self.blocked.is_some()
+    }
+}
+
 #[derive(Debug)]
 pub struct Session {
     frame_reader: FrameReader,
@@ -34,6 +176,18 @@ pub struct Session {
     ///
     /// [`HashSet`] size limited by QUIC connection stream limit.
     pending_streams: HashSet<StreamId>,
+    recv_data: RecvLimit,
+    recv_streams_bidi: RecvLimit,
+    recv_streams_uni: RecvLimit,
+    send_data: SendLimit,
+    send_streams_bidi: SendLimit,
+    send_streams_uni: SendLimit,
+    /// The amount of data received and read on each receive stream.
+    recv_offsets: HashMap<StreamId, (u64, u64)>,
+    /// Flow control capsules that need to be sent.
+    capsules: Vec<WebTransportFrame>,
+    /// Set when streams were blocked on `send_data`, but can now send.
+    send_unblocked: bool,
 }
 
 impl Display for Session {
@@ -44,7 +198,7 @@ impl Display for Session {
 
 impl Session {
     #[must_use]
-    pub(crate) fn new(session_id: StreamId, role: Role) -> Self {
+    pub(crate) fn new(session_id: StreamId, role: Role, limits: &SessionLimits) -> Self {
         Self {
             id: session_id,
             frame_reader: FrameReader::new(),
@@ -52,8 +206,78 @@ impl Session {
             recv_streams: HashSet::default(),
             role,
             pending_streams: HashSet::default(),
+            recv_data: RecvLimit::new(limits.local.data),
+            recv_streams_bidi: RecvLimit::new(limits.local.streams_bidi),
+            recv_streams_uni: RecvLimit::new(limits.local.streams_uni),
+            send_data: SendLimit::new(limits.remote.data),
+            send_streams_bidi: SendLimit::new(limits.remote.streams_bidi),
+            send_streams_uni: SendLimit::new(limits.remote.streams_uni),
+            recv_offsets: HashMap::default(),
+            capsules: Vec::new(),
+            send_unblocked: false,
+        }
+    }
+
+    const fn recv_streams_limit(&mut self, stream_type: StreamType) -> &mut RecvLimit {
+        match stream_type {
+            StreamType::BiDi => &mut self.recv_streams_bidi,
+            StreamType::UniDi => &mut self.recv_streams_uni,
         }
     }
+
+    const fn send_streams_limit(&mut self, stream_type: StreamType) -> &mut SendLimit {
+        match stream_type {
+            StreamType::BiDi => &mut self.send_streams_bidi,
+            StreamType::UniDi => &mut self.send_streams_uni,
+        }
+    }
+
+    /// The peer can open more streams once both sides of a stream are done.
+    fn maybe_retire_stream(&mut self, stream_id: StreamId) {
+        if stream_id.is_self_initiated(self.role)
+            || self.recv_streams.contains(&stream_id)
+            || self.send_streams.contains(&stream_id)
+        {
+            return;
+        }
+        let stream_type = stream_id.stream_type();
+        if let Some(max) = self.recv_streams_limit(stream_type).retire(1) {
+            self.capsules
+                .push(WebTransportFrame::MaxStreams { stream_type, max });
+        }
+    }
+
+    fn retire_data(&mut self, amount: u64) {
+        if let Some(max) = self.recv_data.retire(amount) {
+            self.capsules.push(WebTransportFrame::MaxData(max));
+        }
+    }
+
+    fn handle_frame(
+        &mut self,
+        frame: WebTransportFrame,
+        events: &dyn ExtendedConnectEvents,
+    ) -> Option<CloseReason> {
+        match frame {
+            WebTransportFrame::CloseSession { error, message } => {
+                return Some(CloseReason::Clean { error, message });
+            }
+            WebTransportFrame::DrainSession => {
+                events.webtransport_session_draining(self.id);
+            }
+            WebTransportFrame::MaxData(max) => {
+                self.send_unblocked |= self.send_data.update(max);
+            }
+            WebTransportFrame::MaxStreams { stream_type, max } => {
+                self.send_streams_limit(stream_type).update(max);
+            }
+            WebTransportFrame::DataBlocked(_) | WebTransportFrame::StreamsBlocked { .. } => {
+                // The limits are raised as the application consumes data and
+                // closes streams, so there is nothing to do.
+            }
+        }
+        None
+    }
 }
 
 impl Protocol for Session {
@@ -98,39 +322,40 @@ impl Protocol for Session {
         control_stream_recv: &mut Box<dyn RecvStream>,
         now: Instant,
     ) -> Res<Option<State>> {
-        let (f, fin) = self
-            .frame_reader
-            .receive::<WebTransportFrame>(
-                &mut StreamReaderRecvStreamWrapper::new(conn, control_stream_recv),
-                now,
-            )
-            .map_err(|_| Error::HttpGeneralProtocolStream)?;
-        qtrace!("[{self}] Received frame: {f:?} fin={fin}");
-        if let Some(WebTransportFrame::CloseSession { error, message }) = f {
-            events.session_end(
-                ExtendedConnectType::WebTransport,
-                self.id,
-                CloseReason::Clean { error, message },
-                None,
-            );
+        loop {
+            let (f, fin) = self
+                .frame_reader
+                .receive::<WebTransportFrame>(
+                    &mut StreamReaderRecvStreamWrapper::new(conn, control_stream_recv),
+                    now,
+                )
+                .map_err(|_| Error::HttpGeneralProtocolStream)?;
+            qtrace!("[{self}] Received frame: {f:?} fin={fin}");
+            if let Some(frame) = f {
+                qlog::wt_frame_parsed(conn.qlog_mut(), self.id, &frame, now);
+                if let Some(reason) = self.handle_frame(frame, events.as_ref()) {
+                    events.session_end(ExtendedConnectType::WebTransport, self.id, reason, None);
+                    return if fin {
+                        Ok(Some(State::Done))
+                    } else {
+                        Ok(Some(State::FinPending))
+                    };
+                }
+            } else if !fin {
+                return Ok(None);
+            }
             if fin {
-                Ok(Some(State::Done))
-            } else {
-                Ok(Some(State::FinPending))
+                events.session_end(
+                    ExtendedConnectType::WebTransport,
+                    self.id,
+                    CloseReason::Clean {
+                        error: 0,
+                        message: String::new(),
+                    },
+                    None,
+                );
+                return Ok(Some(State::Done));
             }
-        } else if fin {
-            events.session_end(
-                ExtendedConnectType::WebTransport,
-                self.id,
-                CloseReason::Clean {
-                    error: 0,
-                    message: String::new(),
-                },
-                None,
-            );
-            Ok(Some(State::Done))
-        } else {
-            Ok(None)
         }
     }
 
@@ -145,6 +370,9 @@ impl Protocol for Session {
             State::FinPending | State::Done => return Ok(()),
         }
 
+        if stream_id.is_self_initiated(self.role) {
+            self.send_streams_limit(stream_id.stream_type()).used += 1;
+        }
         if stream_id.is_bidi() {
             self.send_streams.insert(stream_id);
             self.recv_streams.insert(stream_id);
@@ -187,11 +415,117 @@ impl Protocol for Session {
     }
 
     fn remove_recv_stream(&mut self, stream_id: StreamId) {
-        self.recv_streams.remove(&stream_id);
+        if !self.recv_streams.remove(&stream_id) {
+            return;
+        }
+        // Data that the application didn't read still counts as read.
+        if let Some((received, read)) = self.recv_offsets.remove(&stream_id) {
+            self.retire_data(received - read);
+        }
+        self.maybe_retire_stream(stream_id);
     }
 
     fn remove_send_stream(&mut self, stream_id: StreamId) {
-        self.send_streams.remove(&stream_id);
+        if self.send_streams.remove(&stream_id) {
+            self.maybe_retire_stream(stream_id);
+        }
+    }
+
+    fn stream_creatable(&mut self, stream_type: StreamType) -> bool {
+        if self.send_streams_limit(stream_type).available() > 0 {
+            return true;
+        }
+        qdebug!("[{self}] Blocked by the stream limit for {stream_type:?}");
+        let limit = self.send_streams_limit(stream_type);
+        if limit.blocked() {
+            let max = limit.max;
+            self.capsules
+                .push(WebTransportFrame::StreamsBlocked { stream_type, max });
+        }
+        false
+    }
+
+    fn accept_stream(&mut self, stream_id: StreamId) -> bool {
+        let accepted = self.recv_streams_limit(stream_id.stream_type()).consume(1);
+        if !accepted {
+            qdebug!("[{self}] Stream {stream_id} exceeds the stream limit");
+        }
+        accepted
+    }
+
+    fn stream_data_received(&mut self, stream_id: StreamId, received: u64) -> bool {
+        let header = if stream_id.is_self_initiated(self.role) {
+            0
+        } else {
+            let stream_type = if stream_id.is_uni() {
+                WEBTRANSPORT_UNI_STREAM
+            } else {
+                WEBTRANSPORT_STREAM
+            };
+            (Encoder::varint_len(stream_type) + Encoder::varint_len(self.id.as_u64())) as u64
+        };
+        let received = received.saturating_sub(header);
+        let (total, _) = self.recv_offsets.entry(stream_id).or_default();
+        let new = received.saturating_sub(*total);
+        *total += new;
+        let allowed = self.recv_data.consume(new);
+        if !allowed {
+            qdebug!("[{self}] Stream {stream_id} exceeds the data limit");
+        }
+        allowed
+    }
+
+    fn stream_data_read(&mut self, stream_id: StreamId, amount: usize) {
+        if let Some((_, read)) = self.recv_offsets.get_mut(&stream_id) {
+            *read += amount as u64;
+        }
+        self.retire_data(amount as u64);
+    }
+
+    fn stream_data_sendable(&mut self, amount: usize) -> usize {
+        let available = usize::try_from(self.send_data.available()).unwrap_or(usize::MAX);
+        if available >= amount {
+            return amount;
+        }
+        qdebug!("[{self}] Blocked by the data limit");
+        if self.send_data.blocked() {
+            self.capsules
+                .push(WebTransportFrame::DataBlocked(self.send_data.max));
+        }
+        available
+    }
+
+    fn stream_data_sent(&mut self, amount: usize) {
+        self.send_data.used += amount as u64;
+    }
+
+    fn take_capsules(&mut self) -> Vec<Vec<u8>> {
+        self.capsules
+            .drain(..)
+            .map(|frame| {
+                let mut encoder = Encoder::default();
+                frame.encode(&mut encoder);
+                encoder.into()
+            })
+            .collect()
+    }
+
+    fn has_capsules(&self) -> bool {
+        !self.capsules.is_empty()
+    }
+
+    fn take_unblocked_streams(&mut self) -> Vec<StreamId> {
+        if mem::take(&mut self.send_unblocked) {
+            self.send_streams.iter().copied().collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn drain_frame(&self) -> Option<Vec<u8>> {
+        let mut encoder = Encoder::default();
+        WebTransportFrame::DrainSession.encode(&mut encoder);
+        Some(encoder.into())
     }
 
     fn take_sub_streams(&mut self) -> (HashSet<StreamId>, HashSet<StreamId>) {
@@ -205,8 +539,8 @@ impl Protocol for Session {
         // WebTransport does not add prefix (i.e. context ID).
     }
 
-    fn dgram_context_id(&self, datagram: Bytes) -> Result<Bytes, DgramContextIdError> {
+    fn dgram_context_id(&self, datagram: Bytes) -> Result<(u64, Bytes), DgramContextIdError> {
         // WebTransport does not use a prefix (i.e. context ID).
-        Ok(datagram)
+        Ok((0, datagram))
     }
 }
@@ -11,8 +11,8 @@ use neqo_transport::{Connection, StreamId, recv_stream, send_stream};
 
 use super::session::Session;
 use crate::{
-    CloseType, Http3StreamInfo, Http3StreamType, ReceiveOutput, RecvStream, RecvStreamEvents, Res,
-    SendStream, SendStreamEvents, Stream,
+    CloseType, Error, Http3StreamInfo, Http3StreamType, ReceiveOutput, RecvStream,
+    RecvStreamEvents, Res, SendStream, SendStreamEvents, Stream,
 };
 
 pub const WEBTRANSPORT_UNI_STREAM: u64 = 0x54;
@@ -53,7 +53,16 @@ impl Stream for WebTransportRecvStream {
 }
 
 impl RecvStream for WebTransportRecvStream {
-    fn receive(&mut self, _conn: &mut Connection, _now: Instant) -> Res<(ReceiveOutput, bool)> {
+    fn receive(&mut self, conn: &mut Connection, _now: Instant) -> Res<(ReceiveOutput, bool)> {
+        if let Ok(stats) = conn.recv_stream_stats(self.stream_id)
+            && !self
+                .session
+                .borrow_mut()
+                .stream_data_received(self.stream_id, stats.bytes_received())
+        {
+            // The session is reset, which also resets this stream.
+            return Ok((ReceiveOutput::NoOutput, false));
+        }
         if self.session.as_ref().borrow().is_active() {
             self.events.data_readable(&self.stream_info);
         }
@@ -76,6 +85,9 @@ impl RecvStream for WebTransportRecvStream {
     ) -> Res<(usize, bool)> {
         let (amount, fin) = conn.stream_recv(self.stream_id, buf)?;
         self.fin = fin;
+        self.session
+            .borrow_mut()
+            .stream_data_read(self.stream_id, amount);
         if fin {
             self.session.borrow_mut().remove_recv_stream(self.stream_id);
         }
@@ -166,6 +178,23 @@ impl WebTransportSendStream {
         self.events.send_closed(&self.stream_info, close_type);
         self.session.borrow_mut().remove_send_stream(self.stream_id);
     }
+
+    /// The size of the stream header that precedes application data.
+    const fn header_size(&self) -> u64 {
+        const TYPE_LEN_UNI: usize = Encoder::varint_len(WEBTRANSPORT_UNI_STREAM);
+        const TYPE_LEN_BIDI: usize = Encoder::varint_len(WEBTRANSPORT_STREAM);
+
+        if self.stream_id.is_client_initiated() {
+            let id_len = if self.stream_id.is_uni() {
+                TYPE_LEN_UNI
+            } else {
+                TYPE_LEN_BIDI
+            };
+            (id_len + Encoder::varint_len(self.session_id.as_u64())) as u64
+        } else {
+            0
+        }
+    }
 }
 
 impl Stream for WebTransportSendStream {
@@ -211,7 +240,12 @@ impl SendStream for WebTransportSendStream {
     fn send_data(&mut self, conn: &mut Connection, buf: &[u8], now: Instant) -> Res<usize> {
         self.send(conn, now)?;
         if self.state == WebTransportSenderStreamState::SendingData {
-            let sent = conn.stream_send(self.stream_id, buf)?;
+            let sendable = self.session.borrow_mut().stream_data_sendable(buf.len());
+            if sendable == 0 && !buf.is_empty() {
+                return Ok(0);
+            }
+            let sent = conn.stream_send(self.stream_id, &buf[..sendable])?;
+            self.session.borrow_mut().stream_data_sent(sent);
             Ok(sent)
         } else {
             Ok(0)
@@ -233,21 +267,19 @@ impl SendStream for WebTransportSendStream {
         Ok(())
     }
 
-    fn stats(&mut self, conn: &mut Connection) -> Res<send_stream::Stats> {
-        const TYPE_LEN_UNI: usize = Encoder::varint_len(WEBTRANSPORT_UNI_STREAM);
-        const TYPE_LEN_BIDI: usize = Encoder::varint_len(WEBTRANSPORT_STREAM);
-
-        let stream_header_size = if self.stream_id.is_client_initiated() {
-            let id_len = if self.stream_id.is_uni() {
-                TYPE_LEN_UNI
-            } else {
-                TYPE_LEN_BIDI
-            };
-            (id_len + Encoder::varint_len(self.session_id.as_u64())) as u64
-        } else {
-            0
-        };
+    fn reliable_offset(&self, reliable_size: u64) -> Res<u64> {
+        // The stream header is always delivered, if it was written.
+        if let WebTransportSenderStreamState::SendingInit { buf, .. } = &self.state {
+            if reliable_size > 0 {
+                return Err(Error::InvalidInput);
+            }
+            return Ok(self.header_size() - buf.len() as u64);
+        }
+        Ok(self.header_size() + reliable_size)
+    }
 
+    fn stats(&mut self, conn: &mut Connection) -> Res<send_stream::Stats> {
+        let stream_header_size = self.header_size();
         let stats = conn.send_stream_stats(self.stream_id)?;
         if stream_header_size == 0 {
             return Ok(stats);
@@ -24,10 +24,10 @@ pub mod extended_connect;
 /// - `NegotiationFailed` - the settings have been received and the peer does not support the
 ///   feature.
 #[derive(Debug)]
-pub enum NegotiationState {
+pub(crate) enum NegotiationState {
     Disabled,
     Negotiating {
-        feature_type: HSettingType,
+        feature_type: ExtendedConnectType,
         listener: Option<Http3ClientEvents>,
     },
     Negotiated,
@@ -36,7 +36,7 @@ pub enum NegotiationState {
 
 impl NegotiationState {
     #[must_use]
-    pub const fn new(enable: bool, feature_type: HSettingType) -> Self {
+    pub const fn new(enable: bool, feature_type: ExtendedConnectType) -> Self {
         if enable {
             Self::Negotiating {
                 feature_type,
@@ -63,13 +63,11 @@ impl NegotiationState {
             listener,
         } = self
         {
-            qtrace!(
-                "set_negotiated {feature_type:?} to {}",
-                settings.get(*feature_type)
-            );
-            let cb = mem::take(listener);
             let ft = *feature_type;
-            *self = if settings.get(ft) == 1 {
+            let setting = HSettingType::from(ft);
+            qtrace!("set_negotiated {ft} to {}", settings.get(setting));
+            let cb = mem::take(listener);
+            *self = if settings.get(setting) == 1 {
                 Self::Negotiated
             } else {
                 Self::Failed
@@ -104,14 +102,14 @@ pub(crate) enum ConnectType {
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 mod tests {
-    use crate::{features::NegotiationState, settings::HSettingType};
+    use crate::features::{NegotiationState, extended_connect::ExtendedConnectType};
 
     #[test]
     fn negotiation_state_locally_enabled() {
-        let disabled = NegotiationState::new(false, HSettingType::EnableWebTransport);
+        let disabled = NegotiationState::new(false, ExtendedConnectType::WebTransport);
         assert!(!disabled.locally_enabled());
 
-        let negotiating = NegotiationState::new(true, HSettingType::EnableWebTransport);
+        let negotiating = NegotiationState::new(true, ExtendedConnectType::WebTransport);
         assert!(negotiating.locally_enabled());
 
         assert!(NegotiationState::Negotiated.locally_enabled());
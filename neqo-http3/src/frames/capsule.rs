@@ -0,0 +1,176 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use neqo_common::Encoder;
+
+use super::hframe::HFrameType;
+use crate::{Error, Res, frames::reader::FrameDecoder};
+
+/// A capsule, as exchanged on a request stream that uses the capsule protocol.
+///
+/// See <https://www.rfc-editor.org/rfc/rfc9297#section-3.2>.
+///
+/// This is used by CONNECT-UDP sessions.  WebTransport and CONNECT-IP sessions
+/// decode their capsules with their own frame types.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Capsule {
+    /// An HTTP Datagram that is sent on the request stream, for when HTTP/3
+    /// datagrams can't be used.  The payload includes the context ID, for
+    /// protocols that have one.
+    ///
+    /// See <https://www.rfc-editor.org/rfc/rfc9297#section-3.5>.
+    Datagram(Vec<u8>),
+    /// A capsule of a type that is not handled here.  These are passed to the
+    /// application, so that it can implement extensions.  Capsules that are
+    /// longer than 64 KiB are skipped instead.
+    Unknown { capsule_type: u64, value: Vec<u8> },
+}
+
+impl Capsule {
+    const DATAGRAM: HFrameType = HFrameType(0x00);
+
+    /// This fits any UDP payload.  Limiting the size avoids buffering an
+    /// arbitrary amount of data for a capsule.
+    const MAX_LEN: u64 = 1 << 16;
+
+    pub fn encode(&self, enc: &mut Encoder) {
+        let (capsule_type, value) = match self {
+            Self::Datagram(payload) => (Self::DATAGRAM.0, payload),
+            Self::Unknown {
+                capsule_type,
+                value,
+            } => (*capsule_type, value),
+        };
+        enc.encode_varint(capsule_type);
+        enc.encode_vvec(value);
+    }
+
+    /// Capsule types of the form `0x29 * N + 0x17` are reserved for greasing.
+    ///
+    /// See <https://www.rfc-editor.org/rfc/rfc9297#section-5.4>.
+    const fn is_reserved(capsule_type: u64) -> bool {
+        capsule_type >= 0x17 && (capsule_type - 0x17).is_multiple_of(0x29)
+    }
+}
+
+impl FrameDecoder<Self> for Capsule {
+    fn decode(frame_type: HFrameType, frame_len: u64, data: Option<&[u8]>) -> Res<Option<Self>> {
+        if !Self::is_known_type(frame_type) {
+            return Ok(None);
+        }
+        if frame_len > Self::MAX_LEN {
+            // Unknown capsules of any length have to be tolerated.
+            return if frame_type == Self::DATAGRAM {
+                Err(Error::HttpMessage)
+            } else {
+                Ok(None)
+            };
+        }
+        let Some(payload) = data else {
+            return Ok(None);
+        };
+        let capsule = match frame_type {
+            Self::DATAGRAM => Self::Datagram(payload.to_vec()),
+            HFrameType(capsule_type) => Self::Unknown {
+                capsule_type,
+                value: payload.to_vec(),
+            },
+        };
+        Ok(Some(capsule))
+    }
+
+    fn is_known_type(frame_type: HFrameType) -> bool {
+        // All other capsules are read, so that unknown ones can be passed on.
+        !Self::is_reserved(frame_type.0)
+    }
+
+    fn is_buffered(frame_type: HFrameType, frame_len: u64) -> bool {
+        Self::is_known_type(frame_type) && frame_len <= Self::MAX_LEN
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use neqo_common::{Decoder, Encoder};
+
+    use super::{Capsule, HFrameType};
+    use crate::frames::reader::FrameDecoder as _;
+
+    fn roundtrip(capsule: &Capsule, hex: &str) -> Capsule {
+        let mut enc = Encoder::default();
+        capsule.encode(&mut enc);
+        assert_eq!(enc, Encoder::from_hex(hex));
+        let mut dec = Decoder::from(enc.as_ref());
+        let capsule_type = HFrameType(dec.decode_varint().unwrap());
+        let payload = dec.decode_vvec().unwrap();
+        assert_eq!(dec.remaining(), 0);
+        Capsule::decode(capsule_type, payload.len() as u64, Some(payload))
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn datagram() {
+        let c = Capsule::Datagram(vec![0x00, 0x01, 0x02]);
+        assert_eq!(roundtrip(&c, "0003000102"), c);
+        let c = Capsule::Datagram(Vec::new());
+        assert_eq!(roundtrip(&c, "0000"), c);
+    }
+
+    #[test]
+    fn unknown() {
+        let c = Capsule::Unknown {
+            capsule_type: 0x1234,
+            value: vec![0xab],
+        };
+        assert_eq!(roundtrip(&c, "523401ab"), c);
+    }
+
+    #[test]
+    fn reserved() {
+        for capsule_type in [0x17, 0x17 + 0x29, 0x17 + 0x29 * 1000] {
+            assert_eq!(
+                Capsule::decode(HFrameType(capsule_type), 1, Some(&[0xab])).unwrap(),
+                None
+            );
+            // These are skipped, whatever their size.
+            assert_eq!(
+                Capsule::decode(HFrameType(capsule_type), Capsule::MAX_LEN + 1, None).unwrap(),
+                None
+            );
+        }
+        assert!(!Capsule::is_reserved(0x18));
+    }
+
+    #[test]
+    fn too_large() {
+        assert!(Capsule::decode(Capsule::DATAGRAM, Capsule::MAX_LEN + 1, None).is_err());
+        assert_eq!(
+            Capsule::decode(Capsule::DATAGRAM, Capsule::MAX_LEN, None).unwrap(),
+            None
+        );
+        assert!(Capsule::is_buffered(Capsule::DATAGRAM, Capsule::MAX_LEN));
+    }
+
+    #[test]
+    fn unknown_too_large() {
+        let capsule_type = HFrameType(0x1234);
+        assert_eq!(
+            Capsule::decode(capsule_type, Capsule::MAX_LEN + 1, None).unwrap(),
+            None
+        );
+        assert!(Capsule::is_buffered(capsule_type, Capsule::MAX_LEN));
+        assert!(!Capsule::is_buffered(capsule_type, Capsule::MAX_LEN + 1));
+    }
+
+    #[test]
+    fn is_known_type() {
+        assert!(Capsule::is_known_type(Capsule::DATAGRAM));
+        assert!(Capsule::is_known_type(HFrameType(0x1234)));
+        assert!(!Capsule::is_known_type(HFrameType(0x17)));
+    }
+}
@@ -0,0 +1,405 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use neqo_common::{Decoder, Encoder};
+
+use super::hframe::HFrameType;
+use crate::{Error, Res, frames::reader::FrameDecoder};
+
+/// An address that is assigned to, or requested by, an endpoint, as carried in
+/// the `ADDRESS_ASSIGN` and `ADDRESS_REQUEST` capsules.
+///
+/// See <https://www.rfc-editor.org/rfc/rfc9484#section-4.7.1>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssignedAddress {
+    /// Links an assignment to the request that it answers.  A request ID of
+    /// zero is used for unsolicited assignments.
+    pub request_id: u64,
+    /// The address.  In a request, this can be the unspecified address to
+    /// indicate that any address is acceptable.
+    pub address: IpAddr,
+    /// The length of the prefix that is assigned or requested.
+    pub prefix_len: u8,
+}
+
+/// A range of addresses that an endpoint can route packets to, as carried in the
+/// `ROUTE_ADVERTISEMENT` capsule.
+///
+/// See <https://www.rfc-editor.org/rfc/rfc9484#section-4.7.3>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpAddressRange {
+    /// The first address in the range.
+    pub start: IpAddr,
+    /// The last address in the range; this must be of the same IP version as `start`.
+    pub end: IpAddr,
+    /// The IP protocol number that the route applies to, or zero for all protocols.
+    pub ip_protocol: u8,
+}
+
+impl IpAddressRange {
+    /// The ordering of ranges within a `ROUTE_ADVERTISEMENT` capsule: IPv4 ranges
+    /// come before IPv6 ranges, then ranges are ordered by protocol and start.
+    const fn sort_key(&self) -> (bool, u8, IpAddr) {
+        (self.start.is_ipv6(), self.ip_protocol, self.start)
+    }
+}
+
+/// Capsules that are exchanged on a CONNECT-IP request stream.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Frame {
+    AddressAssign(Vec<AssignedAddress>),
+    AddressRequest(Vec<AssignedAddress>),
+    RouteAdvertisement(Vec<IpAddressRange>),
+}
+
+impl Frame {
+    const ADDRESS_ASSIGN: HFrameType = HFrameType(0x01);
+    const ADDRESS_REQUEST: HFrameType = HFrameType(0x02);
+    const ROUTE_ADVERTISEMENT: HFrameType = HFrameType(0x03);
+
+    /// These capsules are small.  Limiting their size avoids buffering an
+    /// arbitrary amount of data for them.
+    const MAX_LEN: u64 = 1 << 14;
+
+    const fn frame_type(&self) -> HFrameType {
+        match self {
+            Self::AddressAssign(_) => Self::ADDRESS_ASSIGN,
+            Self::AddressRequest(_) => Self::ADDRESS_REQUEST,
+            Self::RouteAdvertisement(_) => Self::ROUTE_ADVERTISEMENT,
+        }
+    }
+
+    pub fn encode(&self, enc: &mut Encoder) {
+        enc.encode_varint(self.frame_type().0);
+        enc.encode_vvec_with(|enc_inner| match self {
+            Self::AddressAssign(addresses) | Self::AddressRequest(addresses) => {
+                for a in addresses {
+                    enc_inner.encode_varint(a.request_id);
+                    encode_address(enc_inner, a.address, true);
+                    enc_inner.encode_byte(a.prefix_len);
+                }
+            }
+            Self::RouteAdvertisement(ranges) => {
+                for r in ranges {
+                    encode_address(enc_inner, r.start, true);
+                    encode_address(enc_inner, r.end, false);
+                    enc_inner.encode_byte(r.ip_protocol);
+                }
+            }
+        });
+    }
+
+    /// Checks the rules from RFC 9484 that are not enforced by the encoding.
+    fn validate(&self) -> Res<()> {
+        match self {
+            Self::AddressAssign(addresses) => {
+                if addresses.iter().any(|a| !prefix_len_valid(a)) {
+                    return Err(Error::HttpMessage);
+                }
+            }
+            Self::AddressRequest(addresses) => {
+                // > If an endpoint receives an ADDRESS_REQUEST capsule that contains
+                // > zero Requested Addresses, it MUST abort the IP proxying request stream.
+                // > [...] The Request ID MUST NOT be zero.
+                if addresses.is_empty()
+                    || addresses
+                        .iter()
+                        .any(|a| a.request_id == 0 || !prefix_len_valid(a))
+                {
+                    return Err(Error::HttpMessage);
+                }
+            }
+            Self::RouteAdvertisement(ranges) => {
+                let ordered = ranges.iter().zip(ranges.iter().skip(1)).all(|(a, b)| {
+                    // Ranges for the same IP version and protocol must not overlap.
+                    let same =
+                        (a.start.is_ipv6(), a.ip_protocol) == (b.start.is_ipv6(), b.ip_protocol);
+                    a.sort_key() < b.sort_key() && (!same || a.end < b.start)
+                });
+                if !ordered
+                    || ranges
+                        .iter()
+                        .any(|r| r.start.is_ipv6() != r.end.is_ipv6() || r.start > r.end)
+                {
+                    return Err(Error::HttpMessage);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+const fn prefix_len_valid(a: &AssignedAddress) -> bool {
+    let bits = if a.address.is_ipv4() { 32 } else { 128 };
+    a.prefix_len <= bits
+}
+
+fn encode_address(enc: &mut Encoder, address: IpAddr, with_version: bool) {
+    match address {
+        IpAddr::V4(v4) => {
+            if with_version {
+                enc.encode_byte(4);
+            }
+            enc.encode(v4.octets());
+        }
+        IpAddr::V6(v6) => {
+            if with_version {
+                enc.encode_byte(6);
+            }
+            enc.encode(v6.octets());
+        }
+    }
+}
+
+fn decode_address(dec: &mut Decoder, version: u8) -> Res<IpAddr> {
+    match version {
+        4 => {
+            let octets: [u8; 4] = dec
+                .decode(4)
+                .ok_or(Error::HttpMessage)?
+                .try_into()
+                .map_err(|_| Error::HttpMessage)?;
+            Ok(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        6 => {
+            let octets: [u8; 16] = dec
+                .decode(16)
+                .ok_or(Error::HttpMessage)?
+                .try_into()
+                .map_err(|_| Error::HttpMessage)?;
+            Ok(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => Err(Error::HttpMessage),
+    }
+}
+
+fn decode_assigned_address(dec: &mut Decoder) -> Res<AssignedAddress> {
+    let request_id = dec.decode_varint().ok_or(Error::HttpMessage)?;
+    let version = dec.decode_uint().ok_or(Error::HttpMessage)?;
+    let address = decode_address(dec, version)?;
+    let prefix_len = dec.decode_uint().ok_or(Error::HttpMessage)?;
+    Ok(AssignedAddress {
+        request_id,
+        address,
+        prefix_len,
+    })
+}
+
+fn decode_range(dec: &mut Decoder) -> Res<IpAddressRange> {
+    let version = dec.decode_uint().ok_or(Error::HttpMessage)?;
+    let start = decode_address(dec, version)?;
+    let end = decode_address(dec, version)?;
+    let ip_protocol = dec.decode_uint().ok_or(Error::HttpMessage)?;
+    Ok(IpAddressRange {
+        start,
+        end,
+        ip_protocol,
+    })
+}
+
+fn decode_all<T>(dec: &mut Decoder, f: fn(&mut Decoder) -> Res<T>) -> Res<Vec<T>> {
+    let mut items = Vec::new();
+    while dec.remaining() > 0 {
+        items.push(f(dec)?);
+    }
+    Ok(items)
+}
+
+impl FrameDecoder<Self> for Frame {
+    fn decode(frame_type: HFrameType, frame_len: u64, data: Option<&[u8]>) -> Res<Option<Self>> {
+        if !Self::is_known_type(frame_type) {
+            return Ok(None);
+        }
+        if frame_len > Self::MAX_LEN {
+            return Err(Error::HttpMessage);
+        }
+        let Some(payload) = data else {
+            return Ok(None);
+        };
+        let mut dec = Decoder::from(payload);
+        let frame = match frame_type {
+            Self::ADDRESS_ASSIGN => {
+                Self::AddressAssign(decode_all(&mut dec, decode_assigned_address)?)
+            }
+            Self::ADDRESS_REQUEST => {
+                Self::AddressRequest(decode_all(&mut dec, decode_assigned_address)?)
+            }
+            _ => Self::RouteAdvertisement(decode_all(&mut dec, decode_range)?),
+        };
+        frame.validate()?;
+        Ok(Some(frame))
+    }
+
+    fn is_known_type(frame_type: HFrameType) -> bool {
+        matches!(
+            frame_type,
+            Self::ADDRESS_ASSIGN | Self::ADDRESS_REQUEST | Self::ROUTE_ADVERTISEMENT
+        )
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use neqo_common::{Decoder, Encoder};
+
+    use super::{AssignedAddress, Frame, HFrameType, IpAddressRange};
+    use crate::frames::reader::FrameDecoder as _;
+
+    const V4: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const V6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+
+    fn roundtrip(frame: &Frame) -> Frame {
+        let mut enc = Encoder::default();
+        frame.encode(&mut enc);
+        let mut dec = Decoder::from(enc.as_ref());
+        let frame_type = HFrameType(dec.decode_varint().unwrap());
+        let payload = dec.decode_vvec().unwrap();
+        assert_eq!(dec.remaining(), 0);
+        Frame::decode(frame_type, payload.len() as u64, Some(payload))
+            .unwrap()
+            .unwrap()
+    }
+
+    fn decode(frame_type: HFrameType, payload: &[u8]) -> Result<Option<Frame>, crate::Error> {
+        Frame::decode(frame_type, payload.len() as u64, Some(payload))
+    }
+
+    #[test]
+    fn address_assign() {
+        let f = Frame::AddressAssign(vec![
+            AssignedAddress {
+                request_id: 0,
+                address: V4,
+                prefix_len: 32,
+            },
+            AssignedAddress {
+                request_id: 7,
+                address: V6,
+                prefix_len: 64,
+            },
+        ]);
+        assert_eq!(roundtrip(&f), f);
+        assert_eq!(
+            roundtrip(&Frame::AddressAssign(Vec::new())),
+            Frame::AddressAssign(Vec::new())
+        );
+    }
+
+    #[test]
+    fn address_request() {
+        let f = Frame::AddressRequest(vec![AssignedAddress {
+            request_id: 1,
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            prefix_len: 32,
+        }]);
+        assert_eq!(roundtrip(&f), f);
+    }
+
+    #[test]
+    fn address_request_invalid() {
+        // Empty request.
+        assert!(decode(Frame::ADDRESS_REQUEST, &[]).is_err());
+        // Request ID 0.
+        assert!(decode(Frame::ADDRESS_REQUEST, &[0, 4, 0, 0, 0, 0, 32]).is_err());
+        // Prefix too long.
+        assert!(decode(Frame::ADDRESS_REQUEST, &[1, 4, 0, 0, 0, 0, 33]).is_err());
+        // Unknown IP version.
+        assert!(decode(Frame::ADDRESS_REQUEST, &[1, 5, 0, 0, 0, 0, 32]).is_err());
+        // Truncated.
+        assert!(decode(Frame::ADDRESS_REQUEST, &[1, 4, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn route_advertisement() {
+        let f = Frame::RouteAdvertisement(vec![
+            IpAddressRange {
+                start: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 0)),
+                end: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 255)),
+                ip_protocol: 0,
+            },
+            IpAddressRange {
+                start: IpAddr::V4(Ipv4Addr::new(198, 51, 100, 0)),
+                end: IpAddr::V4(Ipv4Addr::new(198, 51, 100, 255)),
+                ip_protocol: 0,
+            },
+            IpAddressRange {
+                start: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 0)),
+                end: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 255)),
+                ip_protocol: 17,
+            },
+            IpAddressRange {
+                start: V6,
+                end: V6,
+                ip_protocol: 0,
+            },
+        ]);
+        assert_eq!(roundtrip(&f), f);
+    }
+
+    #[test]
+    fn route_advertisement_invalid() {
+        let range = |start: [u8; 4], end: [u8; 4]| IpAddressRange {
+            start: IpAddr::from(start),
+            end: IpAddr::from(end),
+            ip_protocol: 0,
+        };
+        let encode = |ranges: Vec<IpAddressRange>| {
+            let mut enc = Encoder::default();
+            Frame::RouteAdvertisement(ranges).encode(&mut enc);
+            let mut dec = Decoder::from(enc.as_ref());
+            _ = dec.decode_varint();
+            dec.decode_vvec().unwrap().to_vec()
+        };
+        // Start after end.
+        let payload = encode(vec![range([10, 0, 0, 1], [10, 0, 0, 0])]);
+        assert!(decode(Frame::ROUTE_ADVERTISEMENT, &payload).is_err());
+        // Overlapping.
+        let payload = encode(vec![
+            range([10, 0, 0, 0], [10, 0, 0, 9]),
+            range([10, 0, 0, 9], [10, 0, 0, 19]),
+        ]);
+        assert!(decode(Frame::ROUTE_ADVERTISEMENT, &payload).is_err());
+        // Out of order.
+        let payload = encode(vec![
+            range([10, 0, 1, 0], [10, 0, 1, 9]),
+            range([10, 0, 0, 0], [10, 0, 0, 9]),
+        ]);
+        assert!(decode(Frame::ROUTE_ADVERTISEMENT, &payload).is_err());
+        // IPv6 before IPv4.
+        let payload = encode(vec![
+            IpAddressRange {
+                start: V6,
+                end: V6,
+                ip_protocol: 0,
+            },
+            range([10, 0, 0, 0], [10, 0, 0, 9]),
+        ]);
+        assert!(decode(Frame::ROUTE_ADVERTISEMENT, &payload).is_err());
+    }
+
+    #[test]
+    fn too_large() {
+        assert!(Frame::decode(Frame::ADDRESS_ASSIGN, Frame::MAX_LEN + 1, None).is_err());
+        assert_eq!(
+            Frame::decode(Frame::ADDRESS_ASSIGN, 10, None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn is_known_type() {
+        assert!(Frame::is_known_type(Frame::ADDRESS_ASSIGN));
+        assert!(Frame::is_known_type(Frame::ADDRESS_REQUEST));
+        assert!(Frame::is_known_type(Frame::ROUTE_ADVERTISEMENT));
+        assert!(!Frame::is_known_type(HFrameType(0x00)));
+        assert!(!Frame::is_known_type(HFrameType(0x2843)));
+    }
+}
@@ -22,6 +22,7 @@ impl HFrameType {
     pub const SETTINGS: Self = Self(0x4);
     pub const PUSH_PROMISE: Self = Self(0x5);
     pub const GOAWAY: Self = Self(0x7);
+    pub const ORIGIN: Self = Self(0xc);
     pub const MAX_PUSH_ID: Self = Self(0xd);
     pub const PRIORITY_UPDATE_REQUEST: Self = Self(0xf0700);
     pub const PRIORITY_UPDATE_PUSH: Self = Self(0xf0701);
@@ -37,7 +38,7 @@ impl From<HFrameType> for u64 {
 }
 
 // data for DATA frame is not read into HFrame::Data.
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum HFrame {
     Data {
         len: u64, // length of the data
@@ -61,6 +62,10 @@ pub enum HFrame {
     MaxPushId {
         push_id: PushId,
     },
+    /// See <https://www.rfc-editor.org/rfc/rfc9412.html>.
+    Origin {
+        origins: Vec<String>,
+    },
     Grease,
     PriorityUpdateRequest {
         element_id: u64,
@@ -82,6 +87,7 @@ impl HFrame {
             Self::PushPromise { .. } => HFrameType::PUSH_PROMISE,
             Self::Goaway { .. } => HFrameType::GOAWAY,
             Self::MaxPushId { .. } => HFrameType::MAX_PUSH_ID,
+            Self::Origin { .. } => HFrameType::ORIGIN,
             Self::PriorityUpdateRequest { .. } => HFrameType::PRIORITY_UPDATE_REQUEST,
             Self::PriorityUpdatePush { .. } => HFrameType::PRIORITY_UPDATE_PUSH,
             Self::Grease => {
@@ -131,6 +137,13 @@ impl HFrame {
                     enc_inner.encode_varint(*push_id);
                 });
             }
+            Self::Origin { origins } => {
+                enc.encode_vvec_with(|enc_inner| {
+                    for origin in origins {
+                        enc_inner.encode_vec(2, origin.as_bytes());
+                    }
+                });
+            }
             Self::Grease => {
                 // Encode some number of random bytes.
                 let r = random::<8>();
@@ -198,6 +211,17 @@ impl FrameDecoder<Self> for HFrame {
                 HFrameType::MAX_PUSH_ID => Some(Self::MaxPushId {
                     push_id: dec.decode_varint().ok_or(Error::HttpFrame)?.into(),
                 }),
+                HFrameType::ORIGIN => {
+                    let mut origins = Vec::new();
+                    while dec.remaining() > 0 {
+                        let origin = dec.decode_vec(2).ok_or(Error::HttpFrame)?;
+                        if !origin.is_ascii() {
+                            return Err(Error::HttpFrame);
+                        }
+                        origins.push(String::from_utf8_lossy(origin).into_owned());
+                    }
+                    Some(Self::Origin { origins })
+                }
                 HFrameType::PRIORITY_UPDATE_REQUEST | HFrameType::PRIORITY_UPDATE_PUSH => {
                     let element_id = dec.decode_varint().ok_or(Error::HttpFrame)?;
                     let priority = dec.decode_remainder();
@@ -231,6 +255,7 @@ impl FrameDecoder<Self> for HFrame {
                 | HFrameType::PUSH_PROMISE
                 | HFrameType::GOAWAY
                 | HFrameType::MAX_PUSH_ID
+                | HFrameType::ORIGIN
                 | HFrameType::PRIORITY_UPDATE_REQUEST
                 | HFrameType::PRIORITY_UPDATE_PUSH
         )
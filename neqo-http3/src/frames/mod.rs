@@ -4,12 +4,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-pub mod connect_udp_frame;
+pub mod capsule;
+pub mod connect_ip_frame;
 pub mod hframe;
 pub mod reader;
 pub mod wtframe;
 
-pub use connect_udp_frame::Frame as ConnectUdpFrame;
+pub use capsule::Capsule;
+pub use connect_ip_frame::Frame as ConnectIpFrame;
 #[allow(
     clippy::allow_attributes,
     unused_imports,
@@ -25,6 +25,12 @@ pub trait FrameDecoder<T> {
 
     fn is_known_type(frame_type: HFrameType) -> bool;
 
+    /// Whether the payload of a frame is read and passed to `decode`.  Other
+    /// frames are skipped.
+    fn is_buffered(frame_type: HFrameType, _frame_len: u64) -> bool {
+        Self::is_known_type(frame_type)
+    }
+
     /// # Errors
     ///
     /// Returns `HttpFrameUnexpected` if frames is not allowed, i.e. is a `H3_RESERVED_FRAME_TYPES`.
@@ -278,7 +284,7 @@ impl FrameReader {
                 return Ok(Some(f));
             }
             None => {
-                if T::is_known_type(self.frame_type) {
+                if T::is_buffered(self.frame_type, len) {
                     self.state = FrameReaderState::GetData {
                         decoder: IncrementalDecoderBuffer::new(
                             usize::try_from(len).or(Err(Error::HttpFrame))?,
@@ -10,8 +10,8 @@ use test_fixture::fixture_init;
 
 use super::enc_dec_hframe;
 use crate::{
-    Priority, PushId,
-    frames::HFrame,
+    Error, Priority, PushId,
+    frames::{HFrame, HFrameType, reader::FrameDecoder as _},
     settings::{HSetting, HSettingType, HSettings},
 };
 
@@ -62,6 +62,33 @@ fn goaway_frame4() {
     enc_dec_hframe(&f, "070105", 0);
 }
 
+#[test]
+fn origin_frame() {
+    let f = HFrame::Origin {
+        origins: vec!["https://a.com".into(), "https://b.com:8443".into()],
+    };
+    enc_dec_hframe(
+        &f,
+        "0c23000d68747470733a2f2f612e636f6d001268747470733a2f2f622e636f6d3a38343433",
+        0,
+    );
+    enc_dec_hframe(&HFrame::Origin { origins: vec![] }, "0c00", 0);
+}
+
+#[test]
+fn origin_frame_invalid() {
+    // An entry that is longer than the frame.
+    assert_eq!(
+        HFrame::decode(HFrameType::ORIGIN, 3, Some(&[0, 2, 0x61])),
+        Err(Error::HttpFrame)
+    );
+    // An entry that is not ASCII.
+    assert_eq!(
+        HFrame::decode(HFrameType::ORIGIN, 3, Some(&[0, 1, 0xff])),
+        Err(Error::HttpFrame)
+    );
+}
+
 #[test]
 fn grease() {
     fn make_grease() -> u64 {
@@ -4,6 +4,8 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use neqo_transport::StreamType;
+
 use super::enc_dec_wtframe;
 use crate::frames::WebTransportFrame;
 
@@ -15,3 +17,46 @@ fn wt_close_session() {
     };
     enc_dec_wtframe(&f, "6843090000000548656c6c6f", 0);
 }
+
+#[test]
+fn wt_drain_session() {
+    enc_dec_wtframe(&WebTransportFrame::DrainSession, "800078ae00", 0);
+}
+
+#[test]
+fn wt_max_data() {
+    enc_dec_wtframe(&WebTransportFrame::MaxData(0x1000), "990b4d3d025000", 0);
+}
+
+#[test]
+fn wt_data_blocked() {
+    enc_dec_wtframe(&WebTransportFrame::DataBlocked(0), "990b4d410100", 0);
+}
+
+#[test]
+fn wt_max_streams() {
+    let f = WebTransportFrame::MaxStreams {
+        stream_type: StreamType::BiDi,
+        max: 100,
+    };
+    enc_dec_wtframe(&f, "990b4d3f024064", 0);
+    let f = WebTransportFrame::MaxStreams {
+        stream_type: StreamType::UniDi,
+        max: 10,
+    };
+    enc_dec_wtframe(&f, "990b4d40010a", 0);
+}
+
+#[test]
+fn wt_streams_blocked() {
+    let f = WebTransportFrame::StreamsBlocked {
+        stream_type: StreamType::BiDi,
+        max: 100,
+    };
+    enc_dec_wtframe(&f, "990b4d43024064", 0);
+    let f = WebTransportFrame::StreamsBlocked {
+        stream_type: StreamType::UniDi,
+        max: 10,
+    };
+    enc_dec_wtframe(&f, "990b4d44010a", 0);
+}
@@ -5,6 +5,7 @@
 // except according to those terms.
 
 use neqo_common::{Decoder, Encoder};
+use neqo_transport::StreamType;
 
 use super::hframe::HFrameType;
 use crate::{Error, Res, frames::reader::FrameDecoder};
@@ -14,6 +15,11 @@ pub type WebTransportFrameType = u64;
 #[derive(PartialEq, Eq, Debug)]
 pub enum WebTransportFrame {
     CloseSession { error: u32, message: String },
+    DrainSession,
+    MaxData(u64),
+    MaxStreams { stream_type: StreamType, max: u64 },
+    DataBlocked(u64),
+    StreamsBlocked { stream_type: StreamType, max: u64 },
 }
 
 impl WebTransportFrame {
@@ -27,19 +33,92 @@ impl WebTransportFrame {
     /// The value 1024 is used to limit the message size for security and interoperability.
     const CLOSE_MAX_MESSAGE_SIZE: u64 = 1024;
 
+    /// `WT_DRAIN_SESSION` asks the peer to finish up and stop using a session.
+    ///
+    /// See <https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3-13#section-4.7>.
+    const DRAIN_SESSION: WebTransportFrameType = 0x78ae;
+
+    // The flow control capsules, see
+    // <https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3-13#section-5.6>.
+    const MAX_DATA: WebTransportFrameType = 0x190b_4d3d;
+    const MAX_STREAMS_BIDI: WebTransportFrameType = 0x190b_4d3f;
+    const MAX_STREAMS_UNI: WebTransportFrameType = 0x190b_4d40;
+    const DATA_BLOCKED: WebTransportFrameType = 0x190b_4d41;
+    const STREAMS_BLOCKED_BIDI: WebTransportFrameType = 0x190b_4d43;
+    const STREAMS_BLOCKED_UNI: WebTransportFrameType = 0x190b_4d44;
+
+    /// A stream limit can't allow stream IDs that QUIC can't encode.
+    const MAX_STREAMS_LIMIT: u64 = 1 << 60;
+
+    const fn frame_type(&self) -> WebTransportFrameType {
+        match self {
+            Self::CloseSession { .. } => Self::CLOSE_SESSION,
+            Self::DrainSession => Self::DRAIN_SESSION,
+            Self::MaxData(_) => Self::MAX_DATA,
+            Self::MaxStreams {
+                stream_type: StreamType::BiDi,
+                ..
+            } => Self::MAX_STREAMS_BIDI,
+            Self::MaxStreams {
+                stream_type: StreamType::UniDi,
+                ..
+            } => Self::MAX_STREAMS_UNI,
+            Self::DataBlocked(_) => Self::DATA_BLOCKED,
+            Self::StreamsBlocked {
+                stream_type: StreamType::BiDi,
+                ..
+            } => Self::STREAMS_BLOCKED_BIDI,
+            Self::StreamsBlocked {
+                stream_type: StreamType::UniDi,
+                ..
+            } => Self::STREAMS_BLOCKED_UNI,
+        }
+    }
+
     pub fn encode(&self, enc: &mut Encoder) {
         #[cfg(feature = "build-fuzzing-corpus")]
         let start = enc.len();
 
-        enc.encode_varint(Self::CLOSE_SESSION);
-        let Self::CloseSession { error, message } = &self;
-        enc.encode_varint(4 + message.len() as u64);
-        enc.encode_uint(4, *error);
-        enc.encode(message.as_bytes());
+        enc.encode_varint(self.frame_type());
+        match self {
+            Self::CloseSession { error, message } => {
+                enc.encode_varint(4 + message.len() as u64);
+                enc.encode_uint(4, *error);
+                enc.encode(message.as_bytes());
+            }
+            Self::DrainSession => {
+                enc.encode_varint(0_u64);
+            }
+            Self::MaxData(v)
+            | Self::DataBlocked(v)
+            | Self::MaxStreams { max: v, .. }
+            | Self::StreamsBlocked { max: v, .. } => {
+                enc.encode_varint(Encoder::varint_len(*v) as u64);
+                enc.encode_varint(*v);
+            }
+        }
 
         #[cfg(feature = "build-fuzzing-corpus")]
         neqo_common::write_item_to_fuzzing_corpus("wtframe", &enc.as_ref()[start..]);
     }
+
+    /// Decode the single varint that the flow control capsules carry.
+    fn decode_value(payload: &[u8]) -> Res<u64> {
+        let mut dec = Decoder::from(payload);
+        let v = dec.decode_varint().ok_or(Error::HttpMessage)?;
+        if dec.remaining() > 0 {
+            return Err(Error::HttpMessage);
+        }
+        Ok(v)
+    }
+
+    fn decode_streams(payload: &[u8]) -> Res<u64> {
+        let max = Self::decode_value(payload)?;
+        if max > Self::MAX_STREAMS_LIMIT {
+            return Err(Error::HttpMessage);
+        }
+        Ok(max)
+    }
 }
 
 impl FrameDecoder<Self> for WebTransportFrame {
@@ -47,27 +126,65 @@ impl FrameDecoder<Self> for WebTransportFrame {
     const FUZZING_CORPUS: Option<&'static str> = Some("wtframe");
 
     fn decode(frame_type: HFrameType, frame_len: u64, data: Option<&[u8]>) -> Res<Option<Self>> {
-        if let Some(payload) = data {
-            let mut dec = Decoder::from(payload);
-            if frame_type == HFrameType(Self::CLOSE_SESSION) {
-                if frame_len > Self::CLOSE_MAX_MESSAGE_SIZE + 4 {
-                    return Err(Error::HttpMessage);
-                }
+        if !Self::is_known_type(frame_type) {
+            return Ok(None);
+        }
+        let HFrameType(frame_type) = frame_type;
+        let max_len = match frame_type {
+            Self::CLOSE_SESSION => Self::CLOSE_MAX_MESSAGE_SIZE + 4,
+            Self::DRAIN_SESSION => 0,
+            _ => 8,
+        };
+        if frame_len > max_len {
+            return Err(Error::HttpMessage);
+        }
+        let Some(payload) = data else {
+            return Ok(None);
+        };
+        let frame = match frame_type {
+            Self::CLOSE_SESSION => {
+                let mut dec = Decoder::from(payload);
                 let error = dec.decode_uint().ok_or(Error::HttpMessage)?;
                 let Ok(message) = String::from_utf8(dec.decode_remainder().to_vec()) else {
                     return Err(Error::HttpMessage);
                 };
-                Ok(Some(Self::CloseSession { error, message }))
-            } else {
-                Ok(None)
+                Self::CloseSession { error, message }
             }
-        } else {
-            Ok(None)
-        }
+            Self::DRAIN_SESSION => Self::DrainSession,
+            Self::MAX_DATA => Self::MaxData(Self::decode_value(payload)?),
+            Self::DATA_BLOCKED => Self::DataBlocked(Self::decode_value(payload)?),
+            Self::MAX_STREAMS_BIDI | Self::MAX_STREAMS_UNI => Self::MaxStreams {
+                stream_type: if frame_type == Self::MAX_STREAMS_BIDI {
+                    StreamType::BiDi
+                } else {
+                    StreamType::UniDi
+                },
+                max: Self::decode_streams(payload)?,
+            },
+            _ => Self::StreamsBlocked {
+                stream_type: if frame_type == Self::STREAMS_BLOCKED_BIDI {
+                    StreamType::BiDi
+                } else {
+                    StreamType::UniDi
+                },
+                max: Self::decode_streams(payload)?,
+            },
+        };
+        Ok(Some(frame))
     }
 
     fn is_known_type(frame_type: HFrameType) -> bool {
-        frame_type == HFrameType(Self::CLOSE_SESSION)
+        matches!(
+            frame_type.0,
+            Self::CLOSE_SESSION
+                | Self::DRAIN_SESSION
+                | Self::MAX_DATA
+                | Self::MAX_STREAMS_BIDI
+                | Self::MAX_STREAMS_UNI
+                | Self::DATA_BLOCKED
+                | Self::STREAMS_BLOCKED_BIDI
+                | Self::STREAMS_BLOCKED_UNI
+        )
     }
 }
 
@@ -121,4 +238,49 @@ mod tests {
         );
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn is_known_type_flow_control() {
+        for t in [
+            WebTransportFrame::DRAIN_SESSION,
+            WebTransportFrame::MAX_DATA,
+            WebTransportFrame::MAX_STREAMS_BIDI,
+            WebTransportFrame::MAX_STREAMS_UNI,
+            WebTransportFrame::DATA_BLOCKED,
+            WebTransportFrame::STREAMS_BLOCKED_BIDI,
+            WebTransportFrame::STREAMS_BLOCKED_UNI,
+        ] {
+            assert!(WebTransportFrame::is_known_type(HFrameType(t)));
+        }
+    }
+
+    #[test]
+    fn decode_drain_session_with_payload() {
+        let result =
+            WebTransportFrame::decode(HFrameType(WebTransportFrame::DRAIN_SESSION), 1, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn decode_max_data_trailing_bytes() {
+        let payload = [0x01, 0x00];
+        let result = WebTransportFrame::decode(
+            HFrameType(WebTransportFrame::MAX_DATA),
+            payload.len() as u64,
+            Some(&payload),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn decode_max_streams_too_large() {
+        // 2^60 + 1 does not fit in a stream ID.
+        let payload = [0xd0, 0, 0, 0, 0, 0, 0, 0x01];
+        let result = WebTransportFrame::decode(
+            HFrameType(WebTransportFrame::MAX_STREAMS_UNI),
+            payload.len() as u64,
+            Some(&payload),
+        );
+        assert!(result.is_err());
+    }
 }
@@ -133,6 +133,7 @@ while let Some(event) = client.next_event() {
 
 */
 
+mod body_handle;
 mod buffered_send_stream;
 mod client_events;
 mod conn_params;
@@ -164,12 +165,17 @@ mod stream_type_reader;
 
 use std::{cell::RefCell, fmt::Debug, rc::Rc, time::Instant};
 
+pub use body_handle::{BodyError, RecvBodyHandle, SendBodyHandle};
 use buffered_send_stream::BufferedStream;
-pub use client_events::{ConnectUdpEvent, Http3ClientEvent, WebTransportEvent};
+pub use client_events::{ConnectIpEvent, ConnectUdpEvent, Http3ClientEvent, WebTransportEvent};
 pub use conn_params::Http3Parameters;
 pub use connection::{Http3State, SessionAcceptAction};
 pub use connection_client::Http3Client;
 use frames::HFrame;
+pub use frames::{
+    capsule::Capsule,
+    connect_ip_frame::{AssignedAddress, Frame as ConnectIpCapsule, IpAddressRange},
+};
 pub use neqo_common::Header;
 use neqo_common::MessageType;
 use neqo_qpack::Error as QpackError;
@@ -177,10 +183,11 @@ use neqo_transport::{AppError, Connection, Error as TransportError, recv_stream,
 pub use neqo_transport::{Output, StreamId, streams::SendOrder};
 pub use priority::Priority;
 pub use push_id::PushId;
+pub use request_target::RequestTarget;
 pub use server::Http3Server;
 pub use server_events::{
-    ConnectUdpRequest, ConnectUdpServerEvent, Http3OrWebTransportStream, Http3ServerEvent,
-    WebTransportRequest, WebTransportServerEvent,
+    ConnectIpRequest, ConnectIpServerEvent, ConnectUdpRequest, ConnectUdpServerEvent,
+    Http3OrWebTransportStream, Http3ServerEvent, WebTransportRequest, WebTransportServerEvent,
 };
 #[cfg(fuzzing)]
 pub use settings::HSettings;
@@ -251,6 +258,8 @@ pub enum Error {
     InvalidHeader,
     #[error("Invalid input")]
     InvalidInput,
+    #[error("Origin not served by this connection")]
+    InvalidOrigin,
     #[error("Invalid request target")]
     InvalidRequestTarget,
     #[error("Invalid resumption token")]
@@ -552,6 +561,9 @@ trait HttpRecvStreamEvents: RecvStreamEvents {
         interim: bool,
         fin: bool,
     );
+    /// Trailers were received after the content of a message.  These are
+    /// dropped unless the listener wants them.
+    fn trailers_ready(&self, _stream_info: &Http3StreamInfo, _headers: Vec<Header>) {}
     fn extended_connect_new_session(&self, _stream_id: StreamId, _headers: Vec<Header>) {}
 }
 
@@ -601,6 +613,17 @@ trait SendStream: Stream {
         Err(Error::InvalidStreamId)
     }
 
+    /// Convert an amount of application data into an offset in the underlying stream,
+    /// for use with `RESET_STREAM_AT`.
+    /// This function is only implemented by `WebTransportSendStream`.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the stream does not support a reliable reset.
+    fn reliable_offset(&self, _reliable_size: u64) -> Res<u64> {
+        Err(Error::InvalidStreamId)
+    }
+
     /// This function is only implemented by `WebTransportSendStream`.
     fn stats(&mut self, _conn: &mut Connection) -> Res<send_stream::Stats> {
         Err(Error::Unavailable)
@@ -616,6 +639,30 @@ trait HttpSendStream: SendStream {
     ///
     /// This can also return an error if the underlying stream is closed.
     fn send_headers(&mut self, headers: &[Header], conn: &mut Connection) -> Res<()>;
+    /// Checks whether a server can promise a pushed response with `headers` on this stream.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the stream is not a response stream, `InvalidInput` if the
+    /// response is already complete and `InvalidHeader` if `headers` are not valid request
+    /// headers.
+    fn push_promise_allowed(&self, _headers: &[Header]) -> Res<()> {
+        Err(Error::InvalidStreamId)
+    }
+    /// This function is used by a server to promise a pushed response on a request stream.
+    /// The `headers` are the headers of the promised request.
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [`Self::push_promise_allowed`].
+    fn send_push_promise(
+        &mut self,
+        _push_id: PushId,
+        _headers: &[Header],
+        _conn: &mut Connection,
+    ) -> Res<()> {
+        Err(Error::InvalidStreamId)
+    }
     fn set_new_listener(&mut self, _conn_events: Box<dyn SendStreamEvents>) {}
 }
 
@@ -6,7 +6,7 @@
 
 use std::fmt;
 
-use neqo_transport::StreamId;
+use neqo_transport::{StreamId, streams::SendOrder};
 use sfv::{BareItem, Dictionary, Integer, Item, ListEntry, Parser};
 
 use crate::{Error, Res, frames::HFrame};
@@ -73,6 +73,34 @@ impl Priority {
             incremental,
         })
     }
+
+    #[must_use]
+    pub const fn urgency(self) -> u8 {
+        self.urgency
+    }
+
+    #[must_use]
+    pub const fn incremental(self) -> bool {
+        self.incremental
+    }
+
+    /// The `SendOrder` for a stream with this priority, following the scheduling
+    /// guidance of RFC 9218.  Streams with a lower urgency are served first.  Within
+    /// an urgency level, non-incremental streams are served one at a time in
+    /// stream ID order, ahead of incremental streams, which share one `SendOrder`
+    /// so that they are served round-robin.
+    pub(crate) fn sendorder(self, stream_id: StreamId) -> SendOrder {
+        const BAND: SendOrder = 1 << 59;
+        let base = SendOrder::from(7 - self.urgency) * BAND;
+        if self.incremental {
+            base
+        } else {
+            let index = SendOrder::try_from(stream_id.as_u64() >> 2)
+                .unwrap_or(SendOrder::MAX)
+                .min(BAND - 2);
+            base + BAND - 1 - index
+        }
+    }
 }
 
 impl fmt::Display for Priority {
@@ -233,6 +261,29 @@ mod test {
         assert_eq!(Priority::new(5, true).to_string(), "u=5,i");
     }
 
+    #[test]
+    fn sendorder_urgency() {
+        let s = StreamId::new(0);
+        for u in 1..8 {
+            assert!(Priority::new(u - 1, true).sendorder(s) > Priority::new(u, false).sendorder(s));
+        }
+    }
+
+    #[test]
+    fn sendorder_incremental() {
+        // Incremental streams share a `SendOrder`.
+        let p = Priority::new(2, true);
+        assert_eq!(
+            p.sendorder(StreamId::new(0)),
+            p.sendorder(StreamId::new(400))
+        );
+        // Non-incremental streams come before incremental ones, in stream ID order.
+        let np = Priority::new(2, false);
+        assert!(np.sendorder(StreamId::new(400)) > p.sendorder(StreamId::new(0)));
+        assert!(np.sendorder(StreamId::new(0)) > np.sendorder(StreamId::new(4)));
+        assert!(np.sendorder(StreamId::new((1 << 62) - 4)) > p.sendorder(StreamId::new(0)));
+    }
+
     #[test]
     fn priority_update_push_stream() {
         let mut p = PriorityHandler::new(true, Priority::new(5, false));
@@ -11,9 +11,10 @@ use std::{
     mem,
     rc::Rc,
     slice::SliceIndex,
+    time::Instant,
 };
 
-use neqo_common::{Header, qerror, qinfo, qtrace};
+use neqo_common::{Header, qerror, qinfo, qlog::Qlog, qtrace};
 use neqo_transport::{Connection, StreamId};
 
 use crate::{
@@ -21,6 +22,7 @@ use crate::{
     client_events::{Http3ClientEvent, Http3ClientEvents},
     connection::Http3Connection,
     frames::HFrame,
+    qlog,
 };
 
 /// `PushStates`:
@@ -184,6 +186,8 @@ impl PushController {
         push_id: PushId,
         ref_stream_id: StreamId,
         new_headers: Vec<Header>,
+        qlog: &mut Qlog,
+        now: Instant,
     ) -> Res<()> {
         qtrace!(
             "[{self}] New push promise push_id={push_id} headers={new_headers:?} max_push={}",
@@ -226,6 +230,13 @@ impl PushController {
                         stream_id: stream_id_tmp,
                         headers: new_headers,
                     };
+                    qlog::h3_push_resolved(
+                        qlog,
+                        push_id,
+                        Some(stream_id_tmp),
+                        qlog::H3PushDecision::Claimed,
+                        Some(now),
+                    );
                     Ok(())
                 }
                 PushState::Closed => unreachable!("This is only internal; it is transfer to None"),
@@ -233,7 +244,13 @@ impl PushController {
         }
     }
 
-    pub fn add_new_push_stream(&mut self, push_id: PushId, stream_id: StreamId) -> Res<bool> {
+    pub fn add_new_push_stream(
+        &mut self,
+        push_id: PushId,
+        stream_id: StreamId,
+        qlog: &mut Qlog,
+        now: Instant,
+    ) -> Res<bool> {
         qtrace!("A new push stream with push_id={push_id} stream_id={stream_id}");
         self.check_push_id(push_id)?;
 
@@ -256,6 +273,13 @@ impl PushController {
                         stream_id,
                         headers: tmp,
                     };
+                    qlog::h3_push_resolved(
+                        qlog,
+                        push_id,
+                        Some(stream_id),
+                        qlog::H3PushDecision::Claimed,
+                        Some(now),
+                    );
                     Ok(true)
                 }
                 // The following state have already have a push stream:
@@ -283,6 +307,7 @@ impl PushController {
         push_id: PushId,
         conn: &mut Connection,
         base_handler: &mut Http3Connection,
+        now: Instant,
     ) -> Res<()> {
         qtrace!("CANCEL_PUSH frame has been received, push_id={push_id}");
 
@@ -298,6 +323,13 @@ impl PushController {
                 PushState::PushPromise { .. } => {
                     self.conn_events.remove_events_for_push_id(push_id);
                     self.conn_events.push_canceled(push_id);
+                    qlog::h3_push_resolved(
+                        conn.qlog_mut(),
+                        push_id,
+                        None,
+                        qlog::H3PushDecision::Abandoned,
+                        Some(now),
+                    );
                     Ok(())
                 }
                 PushState::OnlyPushStream { stream_id, .. }
@@ -309,6 +341,13 @@ impl PushController {
                     ));
                     self.conn_events.remove_events_for_push_id(push_id);
                     self.conn_events.push_canceled(push_id);
+                    qlog::h3_push_resolved(
+                        conn.qlog_mut(),
+                        push_id,
+                        Some(stream_id),
+                        qlog::H3PushDecision::Abandoned,
+                        Some(now),
+                    );
                     Ok(())
                 }
                 PushState::Closed => unreachable!("This is only internal; it is transfer to None"),
@@ -352,17 +391,32 @@ impl PushController {
                 self.conn_events.remove_events_for_push_id(push_id);
                 base_handler.queue_control_frame(&HFrame::CancelPush { push_id });
                 self.push_streams.close(push_id);
+                qlog::h3_push_resolved(
+                    conn.qlog_mut(),
+                    push_id,
+                    None,
+                    qlog::H3PushDecision::Abandoned,
+                    None,
+                );
                 Ok(())
             }
             Some(PushState::Active { stream_id, .. }) => {
+                let stream_id = *stream_id;
                 self.conn_events.remove_events_for_push_id(push_id);
                 // Cancel the stream. The transport stream may already be done, so ignore an error.
                 drop(base_handler.stream_stop_sending(
                     conn,
-                    *stream_id,
+                    stream_id,
                     Error::HttpRequestCancelled.code(),
                 ));
                 self.push_streams.close(push_id);
+                qlog::h3_push_resolved(
+                    conn.qlog_mut(),
+                    push_id,
+                    Some(stream_id),
+                    qlog::H3PushDecision::Abandoned,
+                    None,
+                );
                 Ok(())
             }
             Some(_) => Err(Error::InvalidStreamId),
@@ -9,7 +9,7 @@ use std::{
     ops::{Add, Sub},
 };
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Default)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Default, Hash)]
 pub struct PushId(u64);
 
 impl PushId {
@@ -8,9 +8,26 @@
 
 use std::time::Instant;
 
-use neqo_common::qlog::Qlog;
+use neqo_common::{Decoder, Encoder, header::Header, hex, qlog::Qlog};
+use neqo_qpack::{decoder::QPACK_UNI_STREAM_TYPE_DECODER, encoder::QPACK_UNI_STREAM_TYPE_ENCODER};
 use neqo_transport::StreamId;
-use qlog::events::{DataRecipient, EventData};
+pub use qlog::events::h3::{H3Owner, H3PushDecision, H3StreamType};
+use qlog::events::{
+    DataRecipient, EventData, RawInfo,
+    h3::{
+        H3FrameCreated, H3FrameParsed, H3ParametersSet, H3PriorityTargetStreamType,
+        H3StreamTypeSet, Http3Frame, HttpHeader, Setting,
+    },
+};
+use serde_derive::Serialize;
+
+use crate::{
+    PushId,
+    control_stream_local::HTTP3_UNI_STREAM_TYPE_CONTROL,
+    frames::{HFrame, HFrameType, WebTransportFrame},
+    settings::{HSettingType, HSettings},
+    stream_type_reader::HTTP3_UNI_STREAM_TYPE_PUSH,
+};
 
 pub fn h3_data_moved_up(qlog: &mut Qlog, stream_id: StreamId, amount: usize, now: Instant) {
     qlog.add_event_at(
@@ -47,3 +64,367 @@ pub fn h3_data_moved_down(qlog: &mut Qlog, stream_id: StreamId, amount: usize, n
         now,
     );
 }
+
+/// Log a frame that is sent on `stream_id`.  `headers` are the fields that
+/// are encoded in a HEADERS or `PUSH_PROMISE` frame.  Without `now`, the event
+/// is logged at the time of the previous event.
+pub fn h3_frame_created(
+    qlog: &mut Qlog,
+    stream_id: StreamId,
+    frame: &HFrame,
+    headers: &[Header],
+    now: Option<Instant>,
+) {
+    add_event(
+        qlog,
+        || {
+            let ev_data = EventData::H3FrameCreated(H3FrameCreated {
+                stream_id: stream_id.as_u64(),
+                length: Some(payload_length(frame)),
+                frame: http3_frame(frame, headers),
+                raw: None,
+            });
+
+            Some(ev_data)
+        },
+        now,
+    );
+}
+
+/// Log a frame that was received on `stream_id`.  The fields of HEADERS and
+/// `PUSH_PROMISE` frames are logged when they are decoded, see
+/// `neqo_qpack::qlog`.
+pub fn h3_frame_parsed(qlog: &mut Qlog, stream_id: StreamId, frame: &HFrame, now: Instant) {
+    qlog.add_event_at(
+        || {
+            let ev_data = EventData::H3FrameParsed(H3FrameParsed {
+                stream_id: stream_id.as_u64(),
+                length: Some(payload_length(frame)),
+                frame: http3_frame(frame, &[]),
+                raw: None,
+            });
+
+            Some(ev_data)
+        },
+        now,
+    );
+}
+
+/// Log an encoded WebTransport frame that is sent on `stream_id`.  These are
+/// not part of the HTTP/3 schema, so they are logged as unknown frames.
+pub fn wt_frame_created(qlog: &mut Qlog, stream_id: StreamId, encoded: &[u8], now: Instant) {
+    qlog.add_event_at(
+        || {
+            let (length, frame) = unknown_frame(encoded)?;
+            let ev_data = EventData::H3FrameCreated(H3FrameCreated {
+                stream_id: stream_id.as_u64(),
+                length: Some(length),
+                frame,
+                raw: None,
+            });
+
+            Some(ev_data)
+        },
+        now,
+    );
+}
+
+/// Log a WebTransport frame that was received on `stream_id`.
+pub fn wt_frame_parsed(
+    qlog: &mut Qlog,
+    stream_id: StreamId,
+    frame: &WebTransportFrame,
+    now: Instant,
+) {
+    qlog.add_event_at(
+        || {
+            let mut enc = Encoder::default();
+            frame.encode(&mut enc);
+            let (length, frame) = unknown_frame(enc.as_ref())?;
+            let ev_data = EventData::H3FrameParsed(H3FrameParsed {
+                stream_id: stream_id.as_u64(),
+                length: Some(length),
+                frame,
+                raw: None,
+            });
+
+            Some(ev_data)
+        },
+        now,
+    );
+}
+
+pub fn h3_parameters_set(qlog: &mut Qlog, owner: H3Owner, settings: &HSettings, now: Instant) {
+    qlog.add_event_at(
+        || {
+            // Only log the settings that were sent.
+            let value = |setting_type| {
+                settings
+                    .iter()
+                    .find(|s| s.setting_type == setting_type)
+                    .map(|s| s.value)
+            };
+            let ev_data = EventData::H3ParametersSet(H3ParametersSet {
+                owner: Some(owner),
+                max_field_section_size: value(HSettingType::MaxHeaderListSize),
+                max_table_capacity: value(HSettingType::MaxTableCapacity),
+                blocked_streams_count: value(HSettingType::BlockedStreams),
+                enable_connect_protocol: value(HSettingType::EnableConnect),
+                h3_datagram: value(HSettingType::EnableH3Datagram),
+                waits_for_settings: None,
+            });
+
+            Some(ev_data)
+        },
+        now,
+    );
+}
+
+/// Log the type of a unidirectional stream, or of a request stream.  Without
+/// `now`, the event is logged at the time of the previous event.
+pub fn h3_stream_type_set(
+    qlog: &mut Qlog,
+    owner: H3Owner,
+    stream_id: StreamId,
+    stream_type: H3StreamType,
+    push_id: Option<PushId>,
+    now: Option<Instant>,
+) {
+    add_event(
+        qlog,
+        || {
+            let stream_type_value = match stream_type {
+                H3StreamType::Control => Some(HTTP3_UNI_STREAM_TYPE_CONTROL),
+                H3StreamType::Push => Some(HTTP3_UNI_STREAM_TYPE_PUSH),
+                H3StreamType::QpackEncode => Some(QPACK_UNI_STREAM_TYPE_ENCODER),
+                H3StreamType::QpackDecode => Some(QPACK_UNI_STREAM_TYPE_DECODER),
+                H3StreamType::Request | H3StreamType::Reserved | H3StreamType::Unknown => None,
+            };
+            let ev_data = EventData::H3StreamTypeSet(H3StreamTypeSet {
+                owner: Some(owner),
+                stream_id: stream_id.as_u64(),
+                stream_type,
+                stream_type_value,
+                associated_push_id: push_id.map(u64::from),
+            });
+
+            Some(ev_data)
+        },
+        now,
+    );
+}
+
+/// The data of an `http:push_resolved` event.  `H3PushResolved` has no public
+/// constructor.
+#[derive(Serialize)]
+struct PushResolved {
+    push_id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_id: Option<u64>,
+    decision: H3PushDecision,
+}
+
+/// Log whether a push was used.  Without `now`, the event is logged at the
+/// time of the previous event.
+pub fn h3_push_resolved(
+    qlog: &mut Qlog,
+    push_id: PushId,
+    stream_id: Option<StreamId>,
+    decision: H3PushDecision,
+    now: Option<Instant>,
+) {
+    qlog.add_custom_event(
+        "http:push_resolved",
+        || {
+            Some(PushResolved {
+                push_id: u64::from(push_id),
+                stream_id: stream_id.map(StreamId::as_u64),
+                decision,
+            })
+        },
+        now,
+    );
+}
+
+fn add_event<F>(qlog: &mut Qlog, f: F, now: Option<Instant>)
+where
+    F: FnOnce() -> Option<EventData>,
+{
+    if let Some(now) = now {
+        qlog.add_event_at(f, now);
+    } else {
+        qlog.add_event(f);
+    }
+}
+
+/// The length of the frame payload, which follows the type and length.
+fn payload_length(frame: &HFrame) -> u64 {
+    if let HFrame::Data { len } = frame {
+        return *len;
+    }
+    let mut enc = Encoder::default();
+    frame.encode(&mut enc);
+    let mut dec = Decoder::from(enc.as_ref());
+    dec.decode_varint();
+    dec.decode_varint().unwrap_or(0)
+}
+
+fn unknown_frame(encoded: &[u8]) -> Option<(u64, Http3Frame)> {
+    let mut dec = Decoder::from(encoded);
+    let frame_type_value = dec.decode_varint()?;
+    let length = dec.decode_varint()?;
+    let payload = dec.decode_remainder();
+    let frame = Http3Frame::Unknown {
+        frame_type_value,
+        raw: Some(RawInfo {
+            length: Some(u64::try_from(encoded.len()).ok()?),
+            payload_length: Some(length),
+            data: Some(hex(payload)),
+        }),
+    };
+    Some((length, frame))
+}
+
+fn http_headers(headers: &[Header]) -> Vec<HttpHeader> {
+    headers
+        .iter()
+        .map(|h| HttpHeader {
+            name: h.name().to_string(),
+            value: String::from_utf8_lossy(h.value()).into_owned(),
+        })
+        .collect()
+}
+
+const fn setting_name(setting_type: HSettingType) -> &'static str {
+    match setting_type {
+        HSettingType::MaxHeaderListSize => "SETTINGS_MAX_FIELD_SECTION_SIZE",
+        HSettingType::MaxTableCapacity => "SETTINGS_QPACK_MAX_TABLE_CAPACITY",
+        HSettingType::BlockedStreams => "SETTINGS_QPACK_BLOCKED_STREAMS",
+        HSettingType::EnableWebTransport => "SETTINGS_ENABLE_WEBTRANSPORT",
+        HSettingType::EnableH3Datagram => "SETTINGS_H3_DATAGRAM",
+        HSettingType::EnableConnect => "SETTINGS_ENABLE_CONNECT_PROTOCOL",
+        HSettingType::WebTransportMaxData => "SETTINGS_WT_INITIAL_MAX_DATA",
+        HSettingType::WebTransportMaxStreamsBidi => "SETTINGS_WT_INITIAL_MAX_STREAMS_BIDI",
+        HSettingType::WebTransportMaxStreamsUni => "SETTINGS_WT_INITIAL_MAX_STREAMS_UNI",
+    }
+}
+
+fn http3_frame(frame: &HFrame, headers: &[Header]) -> Http3Frame {
+    match frame {
+        HFrame::Data { .. } => Http3Frame::Data { raw: None },
+        HFrame::Headers { .. } => Http3Frame::Headers {
+            headers: http_headers(headers),
+        },
+        HFrame::CancelPush { push_id } => Http3Frame::CancelPush {
+            push_id: u64::from(*push_id),
+        },
+        HFrame::Settings { settings } => Http3Frame::Settings {
+            settings: settings
+                .iter()
+                .map(|s| Setting {
+                    name: setting_name(s.setting_type).to_string(),
+                    value: s.value,
+                })
+                .collect(),
+        },
+        HFrame::PushPromise { push_id, .. } => Http3Frame::PushPromise {
+            push_id: u64::from(*push_id),
+            headers: http_headers(headers),
+        },
+        HFrame::Goaway { stream_id } => Http3Frame::Goaway {
+            id: stream_id.as_u64(),
+        },
+        HFrame::MaxPushId { push_id } => Http3Frame::MaxPushId {
+            push_id: u64::from(*push_id),
+        },
+        HFrame::Origin { .. } => Http3Frame::Unknown {
+            frame_type_value: HFrameType::ORIGIN.into(),
+            raw: None,
+        },
+        HFrame::Grease => Http3Frame::Reserved {
+            length: Some(payload_length(frame)),
+        },
+        HFrame::PriorityUpdateRequest {
+            element_id,
+            priority,
+        } => Http3Frame::PriorityUpdate {
+            target_stream_type: H3PriorityTargetStreamType::Request,
+            prioritized_element_id: *element_id,
+            priority_field_value: priority.to_string(),
+        },
+        HFrame::PriorityUpdatePush {
+            element_id,
+            priority,
+        } => Http3Frame::PriorityUpdate {
+            target_stream_type: H3PriorityTargetStreamType::Push,
+            prioritized_element_id: *element_id,
+            priority_field_value: priority.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use neqo_common::header::Header;
+    use neqo_transport::StreamId;
+    use test_fixture::{new_neqo_qlog, now};
+
+    use super::{H3Owner, H3PushDecision, h3_frame_created, h3_parameters_set, h3_push_resolved};
+    use crate::{
+        PushId,
+        frames::HFrame,
+        settings::{HSetting, HSettingType, HSettings},
+    };
+
+    #[test]
+    fn frame_created() {
+        let (mut log, contents) = new_neqo_qlog();
+        let headers = [Header::new(":method", "GET")];
+        let frame = HFrame::Headers {
+            header_block: vec![0; 3],
+        };
+        h3_frame_created(&mut log, StreamId::new(0), &frame, &headers, Some(now()));
+        let contents = contents.to_string();
+        assert!(contents.contains(r#""name":"http:frame_created""#));
+        assert!(contents.contains(r#""headers":[{"name":":method","value":"GET"}]"#));
+        assert!(contents.contains(r#""length":3"#));
+    }
+
+    #[test]
+    fn parameters_set() {
+        let (mut log, contents) = new_neqo_qlog();
+        let settings = HSettings::new(&[HSetting::new(HSettingType::MaxTableCapacity, 100)]);
+        h3_parameters_set(&mut log, H3Owner::Remote, &settings, now());
+        let contents = contents.to_string();
+        assert!(contents.contains(r#""name":"http:parameters_set""#));
+        assert!(contents.contains(r#""owner":"remote""#));
+        assert!(contents.contains(r#""max_table_capacity":100"#));
+        // Settings that were not sent are not logged.
+        assert!(!contents.contains("blocked_streams_count"));
+    }
+
+    #[test]
+    fn push_resolved() {
+        let (mut log, contents) = new_neqo_qlog();
+        h3_push_resolved(
+            &mut log,
+            PushId::new(3),
+            Some(StreamId::new(15)),
+            H3PushDecision::Claimed,
+            Some(now()),
+        );
+        h3_push_resolved(
+            &mut log,
+            PushId::new(4),
+            None,
+            H3PushDecision::Abandoned,
+            None,
+        );
+        let contents = contents.to_string();
+        assert!(contents.contains(
+            r#""name":"http:push_resolved","data":{"push_id":3,"stream_id":15,"decision":"claimed"}"#
+        ));
+        assert!(contents.contains(r#""data":{"push_id":4,"decision":"abandoned"}"#));
+    }
+}
@@ -21,7 +21,7 @@ use crate::{
     CloseType, Error, Http3StreamInfo, Http3StreamType, HttpRecvStream, HttpRecvStreamEvents,
     MessageType, Priority, PushId, ReceiveOutput, RecvStream, Res, Stream,
     frames::{FrameReader, HFrame, StreamReaderConnectionWrapper, hframe::HFrameType},
-    headers_checks::{headers_valid, is_interim},
+    headers_checks::{headers_valid, is_interim, trailers_valid},
     priority::PriorityHandler,
     push_controller::PushController,
     qlog,
@@ -43,6 +43,9 @@ pub struct RecvMessageInfo {
  *    WaitingForData : we got HEADERS, we are waiting for one or more data
  *                     frames. In this state we can receive one or more
  *                     PUSH_PROMIS frames or a HEADERS frame carrying trailers.
+ *    DecodingTrailers : In this step the trailers of a request will be
+ *                       decoded. As with headers, this may be blocked on
+ *                       encoder instructions. Response trailers are ignored.
  *    ReadingData : we got a DATA frame, now we letting the app read payload.
  *                  From here we will go back to WaitingForData state to wait
  *                  for more data frames or to CLosed state
@@ -60,6 +63,7 @@ enum RecvMessageState {
     DecodingHeaders { header_block: Vec<u8>, fin: bool },
     WaitingForData { frame_reader: FrameReader },
     ReadingData { remaining_data_len: usize },
+    DecodingTrailers { header_block: Vec<u8>, fin: bool },
     WaitingForFinAfterTrailers { frame_reader: FrameReader },
     ClosePending, // Close must first be read by application
     Closed,
@@ -84,6 +88,9 @@ pub struct RecvMessage {
     stream_id: StreamId,
     priority_handler: PriorityHandler,
     blocked_push_promise: VecDeque<PushInfo>,
+    /// Trailers were passed on, so the end of the stream needs to be reported
+    /// separately from any data.
+    trailers_received: bool,
 }
 
 impl Display for RecvMessage {
@@ -117,6 +124,7 @@ impl RecvMessage {
             stream_id: message_info.stream_id,
             priority_handler,
             blocked_push_promise: VecDeque::new(),
+            trailers_received: false,
         }
     }
 
@@ -129,10 +137,16 @@ impl RecvMessage {
                 self.state = RecvMessageState::DecodingHeaders { header_block, fin };
             }
             RecvMessageState::WaitingForData { .. } => {
-                // TODO implement trailers, for now just ignore them.
-                self.state = RecvMessageState::WaitingForFinAfterTrailers {
-                    frame_reader: FrameReader::new(),
-                };
+                if self.message_type == MessageType::Response {
+                    // TODO implement response trailers, for now just ignore them.
+                    self.state = RecvMessageState::WaitingForFinAfterTrailers {
+                        frame_reader: FrameReader::new(),
+                    };
+                } else if header_block.is_empty() {
+                    return Err(Error::HttpGeneralProtocolStream);
+                } else {
+                    self.state = RecvMessageState::DecodingTrailers { header_block, fin };
+                }
             }
             RecvMessageState::WaitingForFinAfterTrailers { .. } => {
                 return Err(Error::HttpFrameUnexpected);
@@ -212,6 +226,44 @@ impl RecvMessage {
         Ok(())
     }
 
+    fn add_trailers(
+        &mut self,
+        headers: Vec<Header>,
+        fin: bool,
+        post_readable_event: bool,
+    ) -> Res<()> {
+        qtrace!("[{self}] Add trailers fin={fin}");
+        trailers_valid(&headers)?;
+        self.trailers_received = true;
+        self.conn_events.trailers_ready(&self.stream_info, headers);
+        self.state = RecvMessageState::WaitingForFinAfterTrailers {
+            frame_reader: FrameReader::new(),
+        };
+        if fin {
+            self.set_state_to_close_pending(post_readable_event)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the trailers were decoded and the stream can be read
+    /// further.
+    fn decode_trailers(&mut self, post_readable_event: bool) -> Res<bool> {
+        let RecvMessageState::DecodingTrailers { header_block, fin } = &mut self.state else {
+            return Ok(false);
+        };
+        let done = *fin;
+        let Some(trailers) = self
+            .qpack_decoder
+            .borrow_mut()
+            .decode_header_block(header_block, self.stream_id)?
+        else {
+            qinfo!("[{self}] decoding trailers is blocked");
+            return Ok(false);
+        };
+        self.add_trailers(trailers, done, post_readable_event)?;
+        Ok(!done)
+    }
+
     fn set_state_to_close_pending(&mut self, post_readable_event: bool) -> Res<()> {
         // Stream has received fin. Depending on headers state set header_ready
         // or data_readable event so that app can pick up the fin.
@@ -239,7 +291,13 @@ impl RecvMessage {
         Ok(())
     }
 
-    fn handle_push_promise(&mut self, push_id: PushId, header_block: Vec<u8>) -> Res<()> {
+    fn handle_push_promise(
+        &mut self,
+        push_id: PushId,
+        header_block: Vec<u8>,
+        conn: &mut Connection,
+        now: Instant,
+    ) -> Res<()> {
         if self.push_handler.is_none() {
             return Err(Error::HttpFrameUnexpected);
         }
@@ -258,7 +316,7 @@ impl RecvMessage {
                 .as_ref()
                 .ok_or(Error::HttpFrameUnexpected)?
                 .borrow_mut()
-                .new_push_promise(push_id, self.stream_id, headers)?;
+                .new_push_promise(push_id, self.stream_id, headers, conn.qlog_mut(), now)?;
         } else {
             self.blocked_push_promise.push_back(PushInfo {
                 push_id,
@@ -294,6 +352,7 @@ impl RecvMessage {
                                 "[{self}] A new frame has been received: {frame:?}; state={:?} fin={fin}",
                                 self.state,
                             );
+                            qlog::h3_frame_parsed(conn.qlog_mut(), self.stream_id, &frame, now);
                             match frame {
                                 HFrame::Headers { header_block } => {
                                     self.handle_headers_frame(header_block, fin)?;
@@ -302,14 +361,18 @@ impl RecvMessage {
                                 HFrame::PushPromise {
                                     push_id,
                                     header_block,
-                                } => self.handle_push_promise(push_id, header_block)?,
+                                } => self.handle_push_promise(push_id, header_block, conn, now)?,
                                 _ => break Err(Error::HttpFrameUnexpected),
                             }
                             if matches!(self.state, RecvMessageState::Closed) {
                                 break Ok(());
                             }
                             if fin
-                                && !matches!(self.state, RecvMessageState::DecodingHeaders { .. })
+                                && !matches!(
+                                    self.state,
+                                    RecvMessageState::DecodingHeaders { .. }
+                                        | RecvMessageState::DecodingTrailers { .. }
+                                )
                             {
                                 break self.set_state_to_close_pending(post_readable_event);
                             }
@@ -346,6 +409,11 @@ impl RecvMessage {
                         break Ok(());
                     }
                 }
+                RecvMessageState::DecodingTrailers { .. } => {
+                    if !self.decode_trailers(post_readable_event)? {
+                        break Ok(());
+                    }
+                }
                 RecvMessageState::ReadingData { .. } => {
                     if post_readable_event {
                         self.conn_events.data_readable(&self.stream_info);
@@ -442,6 +510,14 @@ impl RecvStream for RecvMessage {
                             frame_reader: FrameReader::new(),
                         };
                         self.receive_internal(conn, false, now)?;
+                        if self.trailers_received
+                            && matches!(self.state, RecvMessageState::ClosePending)
+                        {
+                            // The trailers event has to come before the end of
+                            // the stream, so that is left for the next read.
+                            self.conn_events.data_readable(&self.stream_info);
+                            break Ok((written, false));
+                        }
                     } else {
                         break Ok((written, false));
                     }
@@ -476,7 +552,7 @@ impl HttpRecvStream for RecvMessage {
                     .as_ref()
                     .ok_or(Error::HttpFrameUnexpected)?
                     .borrow_mut()
-                    .new_push_promise(p.push_id, self.stream_id, headers)?;
+                    .new_push_promise(p.push_id, self.stream_id, headers, conn.qlog_mut(), now)?;
                 self.blocked_push_promise.pop_front();
             } else {
                 return Ok((ReceiveOutput::NoOutput, false));
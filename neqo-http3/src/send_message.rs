@@ -18,10 +18,12 @@ use neqo_qpack as qpack;
 use neqo_transport::{Connection, StreamId};
 
 use crate::{
-    BufferedStream, CloseType, Error, Http3StreamInfo, Http3StreamType, HttpSendStream, Res,
-    SendStream, SendStreamEvents, Stream,
+    BufferedStream, CloseType, Error, Http3StreamInfo, Http3StreamType, HttpSendStream, PushId,
+    Res, SendStream, SendStreamEvents, Stream,
     frames::HFrame,
     headers_checks::{headers_valid, is_interim, trailers_valid},
+    qlog,
+    stream_type_reader::HTTP3_UNI_STREAM_TYPE_PUSH,
 };
 
 const MIN_DATA_FRAME_SIZE: usize = 3; // Minimal DATA frame size: 2 (header) + 1 (payload)
@@ -106,6 +108,14 @@ impl MessageState {
     fn done(&self) -> bool {
         &Self::Done == self
     }
+
+    /// A `PUSH_PROMISE` may only be sent before the message is complete and before trailers.
+    const fn push_promise(&self) -> Res<()> {
+        match self {
+            Self::WaitingForHeaders | Self::WaitingForData => Ok(()),
+            Self::TrailersSet | Self::Done => Err(Error::InvalidInput),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -139,6 +149,31 @@ impl SendMessage {
         }
     }
 
+    /// Create a server push stream. The stream type and the push ID are written at the
+    /// beginning of the stream, the pushed response follows.
+    pub fn new_push(
+        push_id: PushId,
+        stream_id: StreamId,
+        encoder: Rc<RefCell<qpack::Encoder>>,
+        conn_events: Box<dyn SendStreamEvents>,
+    ) -> Self {
+        qdebug!("Create a push stream_id={stream_id} push_id={push_id}");
+        let mut stream = BufferedStream::new(stream_id);
+        stream.encode_with(|e| {
+            e.encode_varint(HTTP3_UNI_STREAM_TYPE_PUSH);
+            e.encode_varint(u64::from(push_id));
+        });
+        Self {
+            state: MessageState::WaitingForHeaders,
+            stream_info: Http3StreamInfo::new(stream_id, Http3StreamType::Push),
+            message_type: MessageType::Response,
+            stream_type: Http3StreamType::Push,
+            stream,
+            encoder,
+            conn_events,
+        }
+    }
+
     /// # Errors
     ///
     /// `ClosedCriticalStream` if the encoder stream is closed.
@@ -156,6 +191,7 @@ impl SendMessage {
             header_block: header_block.to_vec(),
         };
         hframe.encode(encoder);
+        qlog::h3_frame_created(conn.qlog_mut(), stream_id, &hframe, headers, None);
     }
 
     fn stream_id(&self) -> StreamId {
@@ -214,6 +250,13 @@ impl SendStream for SendMessage {
             .send_atomic_with(conn, |e| data_frame.encode(e), now)
             .map_err(|e| Error::map_stream_send_errors(&e))?;
         debug_assert!(sent_fh);
+        qlog::h3_frame_created(
+            conn.qlog_mut(),
+            self.stream_id(),
+            &data_frame,
+            &[],
+            Some(now),
+        );
 
         let sent = self
             .stream
@@ -298,6 +341,13 @@ impl SendStream for SendMessage {
             len: buf.len() as u64,
         };
         self.stream.encode_with(|e| data_frame.encode(e));
+        qlog::h3_frame_created(
+            conn.qlog_mut(),
+            self.stream_id(),
+            &data_frame,
+            &[],
+            Some(now),
+        );
         self.stream.buffer(buf);
         _ = self.stream.send_buffer(conn, now)?;
         Ok(())
@@ -314,6 +364,35 @@ impl HttpSendStream for SendMessage {
         Ok(())
     }
 
+    fn push_promise_allowed(&self, headers: &[Header]) -> Res<()> {
+        if self.message_type != MessageType::Response || self.stream_type != Http3StreamType::Http {
+            return Err(Error::InvalidStreamId);
+        }
+        self.state.push_promise()?;
+        headers_valid(headers, MessageType::Request).map_err(|_| Error::InvalidHeader)
+    }
+
+    fn send_push_promise(
+        &mut self,
+        push_id: PushId,
+        headers: &[Header],
+        conn: &mut Connection,
+    ) -> Res<()> {
+        self.push_promise_allowed(headers)?;
+        let stream_id = self.stream_id();
+        let header_block = self
+            .encoder
+            .borrow_mut()
+            .encode_header_block(conn, headers, stream_id);
+        let hframe = HFrame::PushPromise {
+            push_id,
+            header_block: header_block.to_vec(),
+        };
+        self.stream.encode_with(|e| hframe.encode(e));
+        qlog::h3_frame_created(conn.qlog_mut(), stream_id, &hframe, headers, None);
+        Ok(())
+    }
+
     fn set_new_listener(&mut self, conn_events: Box<dyn SendStreamEvents>) {
         self.stream_type = Http3StreamType::ExtendedConnect;
         self.conn_events = conn_events;
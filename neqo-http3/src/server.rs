@@ -14,10 +14,10 @@ use std::{
 };
 
 use neqo_common::{Datagram, qtrace};
-use neqo_crypto::{AntiReplay, Cipher, PrivateKey, PublicKey, ZeroRttChecker};
+use neqo_crypto::{AntiReplay, Cipher, KeyLog, PrivateKey, PublicKey, ZeroRttChecker};
 use neqo_transport::{
     ConnectionIdGenerator, Output, OutputBatch,
-    server::{ConnectionRef, Server, ValidateAddress},
+    server::{AdmissionControl, AdmissionStats, ConnectionRef, Server, ValidateAddress},
 };
 use rustc_hash::FxHashMap as HashMap;
 
@@ -25,10 +25,12 @@ use crate::{
     Http3Parameters, Http3StreamInfo, Res,
     connection::Http3State,
     connection_server::Http3ServerHandler,
-    server_connection_events::{ConnectUdpEvent, Http3ServerConnEvent, WebTransportEvent},
+    server_connection_events::{
+        ConnectIpEvent, ConnectUdpEvent, Http3ServerConnEvent, WebTransportEvent,
+    },
     server_events::{
-        ConnectUdpRequest, Http3OrWebTransportStream, Http3ServerEvent, Http3ServerEvents,
-        WebTransportRequest,
+        ConnectIpRequest, ConnectUdpRequest, Http3OrWebTransportStream, Http3ServerEvent,
+        Http3ServerEvents, WebTransportRequest,
     },
     settings::HttpZeroRttChecker,
 };
@@ -42,6 +44,9 @@ pub struct Http3Server {
     http3_parameters: Http3Parameters,
     http3_handlers: HashMap<ConnectionRef, HandlerRef>,
     events: Http3ServerEvents,
+    /// When a graceful shutdown has started, the time by which all
+    /// connections are closed.
+    drain_deadline: Option<Instant>,
 }
 
 impl Display for Http3Server {
@@ -78,13 +83,41 @@ impl Http3Server {
             http3_parameters,
             http3_handlers: HashMap::default(),
             events: Http3ServerEvents::default(),
+            drain_deadline: None,
         })
     }
 
+    /// Start a graceful shutdown of all connections, as described for
+    /// [`Http3ServerHandler::shutdown_gracefully`].  Connections that are
+    /// established afterwards are shut down immediately, so they only serve
+    /// requests that arrive before `GOAWAY` is sent.
+    pub fn shutdown_gracefully(&mut self, now: Instant) {
+        let deadline = now + self.http3_parameters.get_drain_timeout();
+        self.drain_deadline = Some(deadline);
+        #[expect(
+            clippy::iter_over_hash_type,
+            reason = "OK to loop over connections in an undefined order."
+        )]
+        for handler in self.http3_handlers.values() {
+            handler.borrow_mut().shutdown_gracefully_by(deadline);
+        }
+    }
+
+    /// Start a graceful shutdown of one connection.
+    pub fn shutdown_connection_gracefully(&self, conn: &ConnectionRef, now: Instant) {
+        if let Some(handler) = self.http3_handlers.get(conn) {
+            handler.borrow_mut().shutdown_gracefully(now);
+        }
+    }
+
     pub fn set_qlog_dir(&mut self, dir: Option<PathBuf>) {
         self.server.set_qlog_dir(dir);
     }
 
+    pub fn set_keylog(&mut self, keylog: Option<KeyLog>) {
+        self.server.set_keylog(keylog);
+    }
+
     pub fn set_validation(&self, v: ValidateAddress) {
         self.server.set_validation(v);
     }
@@ -93,6 +126,15 @@ impl Http3Server {
         self.server.set_ciphers(ciphers);
     }
 
+    pub fn set_admission_control(&mut self, control: AdmissionControl) {
+        self.server.set_admission_control(control);
+    }
+
+    #[must_use]
+    pub const fn admission_stats(&self) -> AdmissionStats {
+        self.server.admission_stats()
+    }
+
     /// Enable encrypted client hello (ECH).
     ///
     /// # Errors
@@ -142,7 +184,7 @@ impl Http3Server {
         let out = self.server.process_multiple_input(dgrams, now);
         self.process_http3(now);
         // If we do not that a dgram already try again after process_http3.
-        match out {
+        let out = match out {
             OutputBatch::DatagramBatch(d) => {
                 qtrace!("[{self}] Send packet: {d:?}");
                 OutputBatch::DatagramBatch(d)
@@ -150,6 +192,24 @@ impl Http3Server {
             _ => self
                 .server
                 .process_multiple(Option::<Datagram>::None, now, max_datagrams),
+        };
+        self.drain_callback(out, now)
+    }
+
+    /// Make sure that there is a callback when connections that are shutting
+    /// down need to be closed.
+    fn drain_callback(&self, out: OutputBatch, now: Instant) -> OutputBatch {
+        let deadline = self
+            .http3_handlers
+            .values()
+            .filter_map(|h| h.borrow().drain_deadline())
+            .min();
+        match (out, deadline) {
+            (OutputBatch::Callback(t), Some(d)) => {
+                OutputBatch::Callback(t.min(d.saturating_duration_since(now)))
+            }
+            (OutputBatch::None, Some(d)) => OutputBatch::Callback(d.saturating_duration_since(now)),
+            (out, _) => out,
         }
     }
 
@@ -186,10 +246,13 @@ impl Http3Server {
         let mut remove = false;
         let http3_parameters = &self.http3_parameters;
         {
+            let drain_deadline = self.drain_deadline;
             let handler = self.http3_handlers.entry(conn.clone()).or_insert_with(|| {
-                Rc::new(RefCell::new(Http3ServerHandler::new(
-                    http3_parameters.clone(),
-                )))
+                let mut handler = Http3ServerHandler::new(http3_parameters.clone());
+                if let Some(deadline) = drain_deadline {
+                    handler.shutdown_gracefully_by(deadline);
+                }
+                Rc::new(RefCell::new(handler))
             });
             handler
                 .borrow_mut()
@@ -201,15 +264,29 @@ impl Http3Server {
                         stream_info,
                         headers,
                         fin,
-                    } => self.events.headers(
-                        Http3OrWebTransportStream::new(
-                            conn.clone(),
-                            Rc::clone(handler),
-                            stream_info,
-                        ),
-                        headers,
-                        fin,
-                    ),
+                    } => {
+                        if stream_info.is_http() {
+                            handler_borrowed.request_priority(
+                                &mut conn.borrow_mut(),
+                                stream_info.stream_id(),
+                                &headers,
+                            );
+                        }
+                        self.events.headers(
+                            Http3OrWebTransportStream::new(
+                                conn.clone(),
+                                Rc::clone(handler),
+                                stream_info,
+                            ),
+                            headers,
+                            fin,
+                        );
+                    }
+                    Http3ServerConnEvent::DataReadable { stream_info }
+                        if handler_borrowed.read_paused(stream_info.stream_id()) =>
+                    {
+                        qtrace!("Not reading paused stream {}", stream_info.stream_id());
+                    }
                     Http3ServerConnEvent::DataReadable { stream_info } => {
                         prepare_data(
                             stream_info,
@@ -220,6 +297,17 @@ impl Http3Server {
                             &self.events,
                         );
                     }
+                    Http3ServerConnEvent::Trailers {
+                        stream_info,
+                        headers,
+                    } => self.events.trailers(
+                        Http3OrWebTransportStream::new(
+                            conn.clone(),
+                            Rc::clone(handler),
+                            stream_info,
+                        ),
+                        headers,
+                    ),
                     Http3ServerConnEvent::DataWritable { stream_info } => self
                         .events
                         .data_writable(conn.clone(), Rc::clone(handler), stream_info),
@@ -239,6 +327,17 @@ impl Http3Server {
                             error,
                         );
                     }
+                    Http3ServerConnEvent::PushCanceled {
+                        stream_info,
+                        push_id,
+                    } => self.events.push_canceled(
+                        Http3OrWebTransportStream::new(
+                            conn.clone(),
+                            Rc::clone(handler),
+                            stream_info,
+                        ),
+                        push_id,
+                    ),
                     Http3ServerConnEvent::StateChange(state) => {
                         self.events
                             .connection_state_change(conn.clone(), state.clone());
@@ -308,6 +407,15 @@ impl Http3Server {
                             datagram,
                         );
                     }
+                    Http3ServerConnEvent::WebTransport(WebTransportEvent::SessionDraining {
+                        session_id,
+                    }) => self
+                        .events
+                        .webtransport_session_draining(WebTransportRequest::new(
+                            conn.clone(),
+                            Rc::clone(handler),
+                            session_id,
+                        )),
                     Http3ServerConnEvent::ConnectUdp(ConnectUdpEvent::Datagram {
                         session_id,
                         datagram,
@@ -317,6 +425,62 @@ impl Http3Server {
                             datagram,
                         );
                     }
+                    Http3ServerConnEvent::ConnectUdp(ConnectUdpEvent::ContextDatagram {
+                        session_id,
+                        context_id,
+                        datagram,
+                    }) => {
+                        self.events.connect_udp_context_datagram(
+                            ConnectUdpRequest::new(conn.clone(), Rc::clone(handler), session_id),
+                            context_id,
+                            datagram,
+                        );
+                    }
+                    Http3ServerConnEvent::ConnectUdp(ConnectUdpEvent::Capsule {
+                        session_id,
+                        capsule,
+                    }) => {
+                        self.events.connect_udp_capsule(
+                            ConnectUdpRequest::new(conn.clone(), Rc::clone(handler), session_id),
+                            capsule,
+                        );
+                    }
+                    Http3ServerConnEvent::ConnectIp(ConnectIpEvent::Session {
+                        stream_id,
+                        headers,
+                    }) => {
+                        self.events.connect_ip_new_session(
+                            ConnectIpRequest::new(conn.clone(), Rc::clone(handler), stream_id),
+                            headers,
+                        );
+                    }
+                    Http3ServerConnEvent::ConnectIp(ConnectIpEvent::SessionClosed {
+                        stream_id,
+                        reason,
+                        headers,
+                    }) => self.events.connect_ip_session_closed(
+                        ConnectIpRequest::new(conn.clone(), Rc::clone(handler), stream_id),
+                        reason,
+                        headers,
+                    ),
+                    Http3ServerConnEvent::ConnectIp(ConnectIpEvent::Datagram {
+                        session_id,
+                        datagram,
+                    }) => {
+                        self.events.connect_ip_datagram(
+                            ConnectIpRequest::new(conn.clone(), Rc::clone(handler), session_id),
+                            datagram,
+                        );
+                    }
+                    Http3ServerConnEvent::ConnectIp(ConnectIpEvent::Capsule {
+                        session_id,
+                        capsule,
+                    }) => {
+                        self.events.connect_ip_capsule(
+                            ConnectIpRequest::new(conn.clone(), Rc::clone(handler), session_id),
+                            capsule,
+                        );
+                    }
                 }
             }
         }
@@ -400,7 +564,7 @@ mod tests {
     };
 
     use super::{Http3Server, Http3ServerEvent, Http3State, Rc, RefCell};
-    use crate::{Error, HFrame, Header, Http3Parameters, Priority};
+    use crate::{Error, HFrame, Header, Http3Parameters, Priority, PushId};
 
     const DEFAULT_SETTINGS: qpack::Settings = qpack::Settings {
         max_table_size_encoder: 100,
@@ -757,6 +921,120 @@ mod tests {
         priority_update_check_id(StreamId::new(1_000_000_000), false);
     }
 
+    /// A priority update for a request that has completed is ignored.
+    #[test]
+    fn priority_update_completed_request() {
+        let (mut hconn, mut peer_conn) = connect();
+        let stream_id = peer_conn.stream_create(StreamType::BiDi).unwrap();
+        peer_conn.stream_send(stream_id, REQUEST_WITH_BODY).unwrap();
+        peer_conn.stream_close_send(stream_id).unwrap();
+        let out = peer_conn.process_output(now());
+        hconn.process(out.dgram(), now());
+
+        let request = hconn
+            .events()
+            .find_map(|e| match e {
+                Http3ServerEvent::Headers { stream, .. } => Some(stream),
+                _ => None,
+            })
+            .unwrap();
+        request
+            .send_headers(&[Header::new(":status", "200")])
+            .unwrap();
+        request.stream_close_send(now()).unwrap();
+        let out = hconn.process_output(now());
+        let out = peer_conn.process(out.dgram(), now());
+        hconn.process(out.dgram(), now());
+        drop(hconn.events());
+
+        let update = |element_id| HFrame::PriorityUpdateRequest {
+            element_id,
+            priority: Priority::new(1, false),
+        };
+        let mut e = Encoder::default();
+        update(stream_id.as_u64()).encode(&mut e);
+        // A request that isn't open yet can still have its priority updated.
+        update(stream_id.as_u64() + 4).encode(&mut e);
+        peer_conn.control_send(e.as_ref());
+        let out = peer_conn.process_output(now());
+        hconn.process(out.dgram(), now());
+
+        let updated = hconn
+            .events()
+            .filter_map(|e| match e {
+                Http3ServerEvent::PriorityUpdate { stream_id, .. } => Some(stream_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(updated, [StreamId::new(stream_id.as_u64() + 4)]);
+        assert_not_closed(&hconn);
+    }
+
+    fn push_control_frames(frames: &[HFrame]) -> Http3Server {
+        let (mut hconn, mut peer_conn) = connect();
+        let mut e = Encoder::default();
+        for frame in frames {
+            frame.encode(&mut e);
+        }
+        peer_conn.control_send(e.as_ref());
+        let out = peer_conn.process_output(now());
+        hconn.process(out.dgram(), now());
+        hconn
+    }
+
+    #[test]
+    fn server_max_push_id() {
+        let hconn = push_control_frames(&[
+            HFrame::MaxPushId {
+                push_id: PushId::new(3),
+            },
+            HFrame::MaxPushId {
+                push_id: PushId::new(5),
+            },
+        ]);
+        assert_not_closed(&hconn);
+    }
+
+    #[test]
+    fn server_max_push_id_decreased() {
+        let hconn = push_control_frames(&[
+            HFrame::MaxPushId {
+                push_id: PushId::new(5),
+            },
+            HFrame::MaxPushId {
+                push_id: PushId::new(3),
+            },
+        ]);
+        assert_closed(&hconn, &Error::HttpId);
+    }
+
+    #[test]
+    fn server_cancel_push_not_promised() {
+        let hconn = push_control_frames(&[
+            HFrame::MaxPushId {
+                push_id: PushId::new(5),
+            },
+            HFrame::CancelPush {
+                push_id: PushId::new(0),
+            },
+        ]);
+        assert_closed(&hconn, &Error::HttpId);
+    }
+
+    #[test]
+    fn server_priority_update_push_not_promised() {
+        let hconn = push_control_frames(&[
+            HFrame::MaxPushId {
+                push_id: PushId::new(5),
+            },
+            HFrame::PriorityUpdatePush {
+                element_id: 0,
+                priority: Priority::default(),
+            },
+        ]);
+        assert_closed(&hconn, &Error::HttpId);
+    }
+
     fn test_wrong_frame_on_control_stream(v: &[u8]) {
         let (mut hconn, mut peer_conn) = connect();
 
@@ -1008,18 +1286,71 @@ mod tests {
                     data_received += 1;
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
+                | Http3ServerEvent::PushCanceled { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
                 | Http3ServerEvent::WebTransport(_)
-                | Http3ServerEvent::ConnectUdp(_) => {}
+                | Http3ServerEvent::ConnectUdp(_)
+                | Http3ServerEvent::ConnectIp(_) => {}
             }
         }
         assert_eq!(headers_frames, 1);
         assert_eq!(data_received, 1);
     }
 
+    #[test]
+    fn server_cancel_push() {
+        let (mut hconn, mut peer_conn) = connect();
+        let mut e = Encoder::default();
+        HFrame::MaxPushId {
+            push_id: PushId::new(5),
+        }
+        .encode(&mut e);
+        peer_conn.control_send(e.as_ref());
+        let stream_id = peer_conn.stream_create(StreamType::BiDi).unwrap();
+        peer_conn.stream_send(stream_id, REQUEST_WITH_BODY).unwrap();
+        let out = peer_conn.process_output(now());
+        hconn.process(out.dgram(), now());
+
+        let request = hconn
+            .events()
+            .find_map(|e| match e {
+                Http3ServerEvent::Headers { stream, .. } => Some(stream),
+                _ => None,
+            })
+            .unwrap();
+        let (push_id, push) = request
+            .send_push_promise(&[
+                Header::new(":method", "GET"),
+                Header::new(":scheme", "https"),
+                Header::new(":authority", "something.com"),
+                Header::new(":path", "/push"),
+            ])
+            .unwrap();
+        assert_eq!(push_id, PushId::new(0));
+
+        // The client cancels the push before it has seen the push stream.
+        let mut e = Encoder::default();
+        HFrame::CancelPush { push_id }.encode(&mut e);
+        peer_conn.control_send(e.as_ref());
+        let out = peer_conn.process_output(now());
+        hconn.process(out.dgram(), now());
+        assert_not_closed(&hconn);
+
+        let canceled = hconn.events().find_map(|e| match e {
+            Http3ServerEvent::PushCanceled { stream, push_id } => Some((stream, push_id)),
+            _ => None,
+        });
+        assert_eq!(canceled, Some((push.clone(), push_id)));
+        assert_eq!(
+            push.send_headers(&[Header::new(":status", "200")]),
+            Err(Error::InvalidStreamId)
+        );
+    }
+
     #[test]
     fn server_request_with_body_send_stop_sending() {
         let (mut hconn, mut peer_conn) = connect();
@@ -1058,12 +1389,15 @@ mod tests {
                     panic!("We should not have a Data event");
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
+                | Http3ServerEvent::PushCanceled { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
                 | Http3ServerEvent::WebTransport(_)
-                | Http3ServerEvent::ConnectUdp(_) => {}
+                | Http3ServerEvent::ConnectUdp(_)
+                | Http3ServerEvent::ConnectIp(_) => {}
             }
         }
         let out = hconn.process_output(now());
@@ -1086,12 +1420,15 @@ mod tests {
                     panic!("We should not have a Data event");
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
+                | Http3ServerEvent::PushCanceled { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
                 | Http3ServerEvent::WebTransport(_)
-                | Http3ServerEvent::ConnectUdp(_) => {}
+                | Http3ServerEvent::ConnectUdp(_)
+                | Http3ServerEvent::ConnectIp(_) => {}
             }
         }
         assert_eq!(headers_frames, 1);
@@ -1131,12 +1468,15 @@ mod tests {
                     panic!("We should not have a Data event");
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
+                | Http3ServerEvent::PushCanceled { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
                 | Http3ServerEvent::WebTransport(_)
-                | Http3ServerEvent::ConnectUdp(_) => {}
+                | Http3ServerEvent::ConnectUdp(_)
+                | Http3ServerEvent::ConnectIp(_) => {}
             }
         }
         let out = hconn.process_output(now());
@@ -1353,12 +1693,15 @@ mod tests {
                     assert!(requests.contains_key(&stream.stream_id()));
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
+                | Http3ServerEvent::PushCanceled { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
                 | Http3ServerEvent::WebTransport(_)
-                | Http3ServerEvent::ConnectUdp(_) => {}
+                | Http3ServerEvent::ConnectUdp(_)
+                | Http3ServerEvent::ConnectIp(_) => {}
             }
         }
         assert_eq!(requests.len(), 2);
@@ -10,10 +10,11 @@ use neqo_common::{Bytes, Header, header::HeadersExt as _};
 use neqo_transport::{AppError, StreamId};
 
 use crate::{
-    CloseType, Http3StreamInfo, HttpRecvStreamEvents, Priority, RecvStreamEvents, Res,
+    CloseType, Http3StreamInfo, HttpRecvStreamEvents, Priority, PushId, RecvStreamEvents, Res,
     SendStreamEvents,
     connection::Http3State,
     features::extended_connect::{self, ExtendedConnectEvents, ExtendedConnectType},
+    frames::{Capsule, ConnectIpFrame},
 };
 
 /// Server events for a single connection.
@@ -33,6 +34,11 @@ pub enum Http3ServerConnEvent {
     DataReadable {
         stream_info: Http3StreamInfo,
     },
+    /// Request trailers are ready.
+    Trailers {
+        stream_info: Http3StreamInfo,
+        headers: Vec<Header>,
+    },
     DataWritable {
         stream_info: Http3StreamInfo,
     },
@@ -44,10 +50,16 @@ pub enum Http3ServerConnEvent {
         stream_info: Http3StreamInfo,
         error: AppError,
     },
+    /// The client has canceled a push and the push stream has been reset.
+    PushCanceled {
+        stream_info: Http3StreamInfo,
+        push_id: PushId,
+    },
     /// Connection state change.
     StateChange(Http3State),
     WebTransport(WebTransportEvent),
     ConnectUdp(ConnectUdpEvent),
+    ConnectIp(ConnectIpEvent),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -66,6 +78,9 @@ pub enum WebTransportEvent {
         session_id: StreamId,
         datagram: Bytes,
     },
+    SessionDraining {
+        session_id: StreamId,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -83,6 +98,36 @@ pub enum ConnectUdpEvent {
         session_id: StreamId,
         datagram: Bytes,
     },
+    ContextDatagram {
+        session_id: StreamId,
+        context_id: u64,
+        datagram: Bytes,
+    },
+    Capsule {
+        session_id: StreamId,
+        capsule: Capsule,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ConnectIpEvent {
+    Session {
+        stream_id: StreamId,
+        headers: Vec<Header>,
+    },
+    SessionClosed {
+        stream_id: StreamId,
+        reason: extended_connect::session::CloseReason,
+        headers: Option<Vec<Header>>,
+    },
+    Datagram {
+        session_id: StreamId,
+        datagram: Bytes,
+    },
+    Capsule {
+        session_id: StreamId,
+        capsule: ConnectIpFrame,
+    },
 }
 
 #[derive(Debug, Default, Clone)]
@@ -146,6 +191,14 @@ impl HttpRecvStreamEvents for Http3ServerConnEvents {
         });
     }
 
+    /// Add a new `Trailers` event.
+    fn trailers_ready(&self, stream_info: &Http3StreamInfo, headers: Vec<Header>) {
+        self.insert(Http3ServerConnEvent::Trailers {
+            stream_info: *stream_info,
+            headers,
+        });
+    }
+
     fn extended_connect_new_session(&self, stream_id: StreamId, headers: Vec<Header>) {
         match headers.find_header(":protocol").map(Header::value) {
             Some(b"webtransport") => {
@@ -159,8 +212,16 @@ impl HttpRecvStreamEvents for Http3ServerConnEvents {
                     headers,
                 }));
             }
+            Some(b"connect-ip") => {
+                self.insert(Http3ServerConnEvent::ConnectIp(ConnectIpEvent::Session {
+                    stream_id,
+                    headers,
+                }));
+            }
             Some(_) => {
-                unimplemented!("Extended connect other than webtransport or connect-udp")
+                unimplemented!(
+                    "Extended connect other than webtransport, connect-udp or connect-ip"
+                )
             }
             None => {
                 unimplemented!("connect without :protocol header");
@@ -201,6 +262,13 @@ impl ExtendedConnectEvents for Http3ServerConnEvents {
                     headers,
                 })
             }
+            ExtendedConnectType::ConnectIp => {
+                Http3ServerConnEvent::ConnectIp(ConnectIpEvent::SessionClosed {
+                    stream_id,
+                    reason,
+                    headers,
+                })
+            }
         };
         self.insert(event);
     }
@@ -236,9 +304,45 @@ impl ExtendedConnectEvents for Http3ServerConnEvents {
                     datagram,
                 })
             }
+            ExtendedConnectType::ConnectIp => {
+                Http3ServerConnEvent::ConnectIp(ConnectIpEvent::Datagram {
+                    session_id,
+                    datagram,
+                })
+            }
         };
         self.insert(event);
     }
+
+    fn connect_udp_context_datagram(&self, session_id: StreamId, context_id: u64, datagram: Bytes) {
+        self.insert(Http3ServerConnEvent::ConnectUdp(
+            ConnectUdpEvent::ContextDatagram {
+                session_id,
+                context_id,
+                datagram,
+            },
+        ));
+    }
+
+    fn connect_udp_capsule(&self, session_id: StreamId, capsule: Capsule) {
+        self.insert(Http3ServerConnEvent::ConnectUdp(ConnectUdpEvent::Capsule {
+            session_id,
+            capsule,
+        }));
+    }
+
+    fn connect_ip_capsule(&self, session_id: StreamId, capsule: ConnectIpFrame) {
+        self.insert(Http3ServerConnEvent::ConnectIp(ConnectIpEvent::Capsule {
+            session_id,
+            capsule,
+        }));
+    }
+
+    fn webtransport_session_draining(&self, session_id: StreamId) {
+        self.insert(Http3ServerConnEvent::WebTransport(
+            WebTransportEvent::SessionDraining { session_id },
+        ));
+    }
 }
 
 impl Http3ServerConnEvents {
@@ -272,10 +376,17 @@ impl Http3ServerConnEvents {
         });
     }
 
+    pub fn push_canceled(&self, stream_info: Http3StreamInfo, push_id: PushId) {
+        self.insert(Http3ServerConnEvent::PushCanceled {
+            stream_info,
+            push_id,
+        });
+    }
+
     fn remove_events_for_stream_id(&self, stream_info: &Http3StreamInfo) {
         self.remove(|evt| {
             matches!(evt,
-                Http3ServerConnEvent::Headers { stream_info: x, .. } | Http3ServerConnEvent::DataReadable { stream_info: x, .. } if x == stream_info)
+                Http3ServerConnEvent::Headers { stream_info: x, .. } | Http3ServerConnEvent::DataReadable { stream_info: x, .. } | Http3ServerConnEvent::Trailers { stream_info: x, .. } if x == stream_info)
         });
     }
 }
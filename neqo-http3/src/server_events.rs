@@ -19,7 +19,7 @@ use neqo_transport::{
 };
 
 use crate::{
-    Error, Http3StreamInfo, Http3StreamType, Priority, Res,
+    Capsule, ConnectIpCapsule, Error, Http3StreamInfo, Http3StreamType, Priority, PushId, Res,
     connection::{Http3State, SessionAcceptAction},
     connection_server::Http3ServerHandler,
     features::extended_connect,
@@ -81,6 +81,22 @@ impl StreamHandler {
             .send_data(self.stream_id(), buf, &mut self.conn.borrow_mut(), now)
     }
 
+    /// Stop reading request data, so that flow control holds the client back.
+    /// There are no more `Data` or `Trailers` events for the stream until
+    /// [`StreamHandler::resume_reading`] is called.
+    pub fn pause_reading(&self) {
+        self.handler
+            .borrow_mut()
+            .set_read_paused(self.stream_info, true);
+    }
+
+    /// Continue reading request data after [`StreamHandler::pause_reading`].
+    pub fn resume_reading(&self) {
+        self.handler
+            .borrow_mut()
+            .set_read_paused(self.stream_info, false);
+    }
+
     /// Bytes sendable on stream at the QUIC layer.
     ///
     /// Note that this does not yet account for HTTP3 frame headers.
@@ -141,6 +157,27 @@ impl StreamHandler {
         )
     }
 
+    /// Reset sending side of a `WebTransport` stream, but make sure that the first
+    /// `reliable_size` bytes are delivered.  This uses `RESET_STREAM_AT`, which the
+    /// client needs to support.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore or if it isn't a
+    /// `WebTransport` stream.
+    pub fn stream_reset_send_at(&self, app_error: AppError, reliable_size: u64) -> Res<()> {
+        qdebug!(
+            "[{self}] reset send stream_id:{} error:{app_error} reliable:{reliable_size}",
+            self.stream_info.stream_id()
+        );
+        self.handler.borrow_mut().stream_reset_send_at(
+            self.stream_info.stream_id(),
+            app_error,
+            reliable_size,
+            &mut self.conn.borrow_mut(),
+        )
+    }
+
     /// Reset a stream/request.
     ///
     /// # Errors
@@ -210,6 +247,46 @@ impl Http3OrWebTransportStream {
         qdebug!("[{self}] Set new response");
         self.stream_handler.stream_close_send(now)
     }
+
+    /// Stop reading the request body, so that flow control holds the client
+    /// back.  There are no more `Data` or `Trailers` events for this stream
+    /// until [`Http3OrWebTransportStream::resume_reading`] is called.
+    pub fn pause_reading(&self) {
+        self.stream_handler.pause_reading();
+    }
+
+    /// Continue reading the request body after
+    /// [`Http3OrWebTransportStream::pause_reading`].
+    pub fn resume_reading(&self) {
+        self.stream_handler.resume_reading();
+    }
+
+    /// Promise a pushed response for a request. `headers` are the headers of the promised
+    /// request. A `PUSH_PROMISE` frame is sent on this stream and a push stream is opened. The
+    /// returned push stream is used like a request stream to send the pushed response.
+    ///
+    /// # Errors
+    ///
+    /// It may return `StreamLimit` if the client does not allow more pushes or a new stream
+    /// cannot be created, `InvalidStreamId` if a stream does not exist anymore or is not a
+    /// request stream, and `InvalidInput` if the response is already complete.
+    pub fn send_push_promise(&self, headers: &[Header]) -> Res<(PushId, Self)> {
+        qdebug!("[{self}] Send push promise");
+        let (push_id, push_stream_id) =
+            self.stream_handler.handler.borrow_mut().send_push_promise(
+                self.stream_handler.stream_id(),
+                headers,
+                &mut self.stream_handler.conn.borrow_mut(),
+            )?;
+        Ok((
+            push_id,
+            Self::new(
+                self.stream_handler.conn.clone(),
+                Rc::clone(&self.stream_handler.handler),
+                Http3StreamInfo::new(push_stream_id, Http3StreamType::Push),
+            ),
+        ))
+    }
 }
 
 impl Deref for Http3OrWebTransportStream {
@@ -300,6 +377,24 @@ impl WebTransportRequest {
             )
     }
 
+    /// Ask the client to stop using the session, e.g. before the server shuts
+    /// down.  The session stays open until one side closes it.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore or
+    /// the session is not active.
+    pub fn drain_session(&self, now: Instant) -> Res<()> {
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .webtransport_drain_session(
+                &mut self.stream_handler.conn.borrow_mut(),
+                self.stream_handler.stream_info.stream_id(),
+                now,
+            )
+    }
+
     #[must_use]
     pub const fn stream_id(&self) -> StreamId {
         self.stream_handler.stream_id()
@@ -468,6 +563,81 @@ impl ConnectUdpRequest {
             )
     }
 
+    /// Allocate a context ID for datagrams, e.g. for an extension to
+    /// CONNECT-UDP.  The client has to be told about it, and how it is used, in
+    /// the way that the extension defines.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    pub fn register_context(&self) -> Res<u64> {
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .connect_udp_register_context(self.stream_handler.stream_id(), None)
+    }
+
+    /// Accept datagrams with a context ID that the client allocated.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    /// Returns `InvalidInput` if the client can't allocate the context ID
+    /// or it is already registered.
+    pub fn register_peer_context(&self, context_id: u64) -> Res<()> {
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .connect_udp_register_context(self.stream_handler.stream_id(), Some(context_id))
+            .map(|_| ())
+    }
+
+    /// Send a datagram with a registered context ID.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    /// Returns `InvalidInput` if the context ID is not registered and
+    /// `TooMuchData` if the supply buffer is bigger than the allowed remote
+    /// datagram size.
+    pub fn send_context_datagram<I: Into<DatagramTracking>>(
+        &self,
+        context_id: u64,
+        buf: &[u8],
+        id: I,
+    ) -> Res<()> {
+        let session_id = self.stream_handler.stream_id();
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .connect_udp_send_context_datagram(
+                &mut self.stream_handler.conn.borrow_mut(),
+                session_id,
+                context_id,
+                buf,
+                id,
+            )
+    }
+
+    /// Send a capsule, e.g. for an extension to CONNECT-UDP.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore or
+    /// the session has not been accepted.
+    pub fn send_capsule(&self, capsule: &Capsule, now: Instant) -> Res<()> {
+        let session_id = self.stream_handler.stream_id();
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .connect_udp_send_capsule(
+                &mut self.stream_handler.conn.borrow_mut(),
+                session_id,
+                capsule,
+                now,
+            )
+    }
+
     #[must_use]
     pub fn remote_datagram_size(&self) -> u64 {
         self.stream_handler.conn.borrow().remote_datagram_size()
@@ -487,6 +657,123 @@ impl ConnectUdpRequest {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct ConnectIpRequest {
+    stream_handler: StreamHandler,
+}
+
+impl Display for ConnectIpRequest {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "ConnectIp session {}", self.stream_handler)
+    }
+}
+
+impl ConnectIpRequest {
+    pub(crate) const fn new(
+        conn: ConnectionRef,
+        handler: Rc<RefCell<Http3ServerHandler>>,
+        stream_id: StreamId,
+    ) -> Self {
+        Self {
+            stream_handler: StreamHandler {
+                conn,
+                handler,
+                stream_info: Http3StreamInfo::new(stream_id, Http3StreamType::Http),
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn state(&self) -> Http3State {
+        self.stream_handler.handler.borrow().state()
+    }
+
+    /// Respond to a `ConnectIp` session request.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    pub fn response(&self, accept: &SessionAcceptAction, now: Instant) -> Res<()> {
+        qdebug!("[{self}] Set a response for a ConnectIp session");
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .connect_ip_session_accept(
+                &mut self.stream_handler.conn.borrow_mut(),
+                self.stream_handler.stream_info.stream_id(),
+                accept,
+                now,
+            )
+    }
+
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    /// Also return an error if the stream was closed on the transport layer,
+    /// but that information is not yet consumed on the  http/3 layer.
+    pub fn close_session(&self, error: u32, message: &str, now: Instant) -> Res<()> {
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .connect_ip_close_session(
+                &mut self.stream_handler.conn.borrow_mut(),
+                self.stream_handler.stream_info.stream_id(),
+                error,
+                message,
+                now,
+            )
+    }
+
+    #[must_use]
+    pub const fn stream_id(&self) -> StreamId {
+        self.stream_handler.stream_id()
+    }
+
+    /// Send an IP packet in a connect-ip datagram.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    /// The function returns `TooMuchData` if the supply buffer is bigger than
+    /// the allowed remote datagram size.
+    pub fn send_datagram<I: Into<DatagramTracking>>(&self, buf: &[u8], id: I) -> Res<()> {
+        let session_id = self.stream_handler.stream_id();
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .connect_ip_send_datagram(
+                &mut self.stream_handler.conn.borrow_mut(),
+                session_id,
+                buf,
+                id,
+            )
+    }
+
+    /// Send a capsule, e.g. to assign addresses to the client or to advertise routes.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore or
+    /// the session has not been accepted.
+    pub fn send_capsule(&self, capsule: &ConnectIpCapsule, now: Instant) -> Res<()> {
+        let session_id = self.stream_handler.stream_id();
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .connect_ip_send_capsule(
+                &mut self.stream_handler.conn.borrow_mut(),
+                session_id,
+                capsule,
+                now,
+            )
+    }
+
+    #[must_use]
+    pub fn remote_datagram_size(&self) -> u64 {
+        self.stream_handler.conn.borrow().remote_datagram_size()
+    }
+}
+
 impl Deref for WebTransportRequest {
     type Target = StreamHandler;
     fn deref(&self) -> &Self::Target {
@@ -510,6 +797,10 @@ pub enum WebTransportServerEvent {
         session: WebTransportRequest,
         datagram: Bytes,
     },
+    /// The client asked the server to stop using the session.
+    SessionDraining {
+        session: WebTransportRequest,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -523,10 +814,45 @@ pub enum ConnectUdpServerEvent {
         reason: extended_connect::session::CloseReason,
         headers: Option<Vec<Header>>,
     },
+    /// A UDP payload.
     Datagram {
         session: ConnectUdpRequest,
         datagram: Bytes,
     },
+    /// A datagram with a registered context ID other than 0.
+    ContextDatagram {
+        session: ConnectUdpRequest,
+        context_id: u64,
+        datagram: Bytes,
+    },
+    /// A capsule that CONNECT-UDP does not handle, for use by extensions.
+    Capsule {
+        session: ConnectUdpRequest,
+        capsule: Capsule,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum ConnectIpServerEvent {
+    NewSession {
+        session: ConnectIpRequest,
+        headers: Vec<Header>,
+    },
+    SessionClosed {
+        session: ConnectIpRequest,
+        reason: extended_connect::session::CloseReason,
+        headers: Option<Vec<Header>>,
+    },
+    /// An IP packet.
+    Datagram {
+        session: ConnectIpRequest,
+        datagram: Bytes,
+    },
+    /// An `ADDRESS_ASSIGN`, `ADDRESS_REQUEST` or `ROUTE_ADVERTISEMENT` capsule.
+    Capsule {
+        session: ConnectIpRequest,
+        capsule: ConnectIpCapsule,
+    },
 }
 
 /// Server events for one or more connections.
@@ -544,6 +870,12 @@ pub enum Http3ServerEvent {
         data: Vec<u8>,
         fin: bool,
     },
+    /// Request trailers are ready.  These follow all of the request data, but
+    /// come before the `Data` event that ends the request.
+    Trailers {
+        stream: Http3OrWebTransportStream,
+        headers: Vec<Header>,
+    },
     DataWritable {
         stream: Http3OrWebTransportStream,
     },
@@ -555,6 +887,11 @@ pub enum Http3ServerEvent {
         stream: Http3OrWebTransportStream,
         error: AppError,
     },
+    /// The client has canceled a push. The push stream has been reset and cannot be used anymore.
+    PushCanceled {
+        stream: Http3OrWebTransportStream,
+        push_id: PushId,
+    },
     /// When individual connection change state. It is only used for tests.
     StateChange {
         conn: ConnectionRef,
@@ -566,6 +903,7 @@ pub enum Http3ServerEvent {
     },
     WebTransport(WebTransportServerEvent),
     ConnectUdp(ConnectUdpServerEvent),
+    ConnectIp(ConnectIpServerEvent),
 }
 
 #[derive(Debug, Default, Clone)]
@@ -628,6 +966,14 @@ impl Http3ServerEvents {
         });
     }
 
+    /// Insert a `Trailers` event.
+    pub(crate) fn trailers(&self, request: Http3OrWebTransportStream, headers: Vec<Header>) {
+        self.insert(Http3ServerEvent::Trailers {
+            stream: request,
+            headers,
+        });
+    }
+
     pub(crate) fn data_writable(
         &self,
         conn: ConnectionRef,
@@ -665,6 +1011,10 @@ impl Http3ServerEvents {
         });
     }
 
+    pub(crate) fn push_canceled(&self, stream: Http3OrWebTransportStream, push_id: PushId) {
+        self.insert(Http3ServerEvent::PushCanceled { stream, push_id });
+    }
+
     pub(crate) fn priority_update(&self, stream_id: StreamId, priority: Priority) {
         self.insert(Http3ServerEvent::PriorityUpdate {
             stream_id,
@@ -729,9 +1079,70 @@ impl Http3ServerEvents {
             WebTransportServerEvent::Datagram { session, datagram },
         ));
     }
+
+    pub(crate) fn webtransport_session_draining(&self, session: WebTransportRequest) {
+        self.insert(Http3ServerEvent::WebTransport(
+            WebTransportServerEvent::SessionDraining { session },
+        ));
+    }
     pub(crate) fn connect_udp_datagram(&self, session: ConnectUdpRequest, datagram: Bytes) {
         self.insert(Http3ServerEvent::ConnectUdp(
             ConnectUdpServerEvent::Datagram { session, datagram },
         ));
     }
+
+    pub(crate) fn connect_udp_context_datagram(
+        &self,
+        session: ConnectUdpRequest,
+        context_id: u64,
+        datagram: Bytes,
+    ) {
+        self.insert(Http3ServerEvent::ConnectUdp(
+            ConnectUdpServerEvent::ContextDatagram {
+                session,
+                context_id,
+                datagram,
+            },
+        ));
+    }
+
+    pub(crate) fn connect_udp_capsule(&self, session: ConnectUdpRequest, capsule: Capsule) {
+        self.insert(Http3ServerEvent::ConnectUdp(
+            ConnectUdpServerEvent::Capsule { session, capsule },
+        ));
+    }
+
+    pub(crate) fn connect_ip_new_session(&self, session: ConnectIpRequest, headers: Vec<Header>) {
+        self.insert(Http3ServerEvent::ConnectIp(
+            ConnectIpServerEvent::NewSession { session, headers },
+        ));
+    }
+
+    pub(crate) fn connect_ip_session_closed(
+        &self,
+        session: ConnectIpRequest,
+        reason: extended_connect::session::CloseReason,
+        headers: Option<Vec<Header>>,
+    ) {
+        self.insert(Http3ServerEvent::ConnectIp(
+            ConnectIpServerEvent::SessionClosed {
+                session,
+                reason,
+                headers,
+            },
+        ));
+    }
+
+    pub(crate) fn connect_ip_datagram(&self, session: ConnectIpRequest, datagram: Bytes) {
+        self.insert(Http3ServerEvent::ConnectIp(
+            ConnectIpServerEvent::Datagram { session, datagram },
+        ));
+    }
+
+    pub(crate) fn connect_ip_capsule(&self, session: ConnectIpRequest, capsule: ConnectIpCapsule) {
+        self.insert(Http3ServerEvent::ConnectIp(ConnectIpServerEvent::Capsule {
+            session,
+            capsule,
+        }));
+    }
 }
@@ -28,6 +28,12 @@ const SETTINGS_H3_DATAGRAM_DRAFT04: SettingsType = 0x00ff_d277;
 
 const SETTINGS_H3_DATAGRAM: SettingsType = 0x33;
 
+// The initial WebTransport session flow control limits, see
+// <https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3-13#section-5.6.2>.
+const SETTINGS_WT_INITIAL_MAX_DATA: SettingsType = 0x2b61;
+const SETTINGS_WT_INITIAL_MAX_STREAMS_UNI: SettingsType = 0x2b64;
+const SETTINGS_WT_INITIAL_MAX_STREAMS_BIDI: SettingsType = 0x2b65;
+
 /// Advertises support for HTTP Extended CONNECT.
 ///
 /// See <https://www.rfc-editor.org/rfc/rfc9220#section-5>
@@ -43,6 +49,9 @@ pub enum HSettingType {
     EnableWebTransport,
     EnableH3Datagram,
     EnableConnect,
+    WebTransportMaxData,
+    WebTransportMaxStreamsBidi,
+    WebTransportMaxStreamsUni,
 }
 
 const fn hsetting_default(setting_type: HSettingType) -> u64 {
@@ -53,6 +62,10 @@ const fn hsetting_default(setting_type: HSettingType) -> u64 {
         | HSettingType::EnableWebTransport
         | HSettingType::EnableH3Datagram
         | HSettingType::EnableConnect => 0,
+        // Without these settings, WebTransport sessions are not flow controlled.
+        HSettingType::WebTransportMaxData
+        | HSettingType::WebTransportMaxStreamsBidi
+        | HSettingType::WebTransportMaxStreamsUni => u64::MAX,
     }
 }
 
@@ -130,6 +143,18 @@ impl HSettings {
                             enc_inner.encode_varint(iter.value);
                         }
                     }
+                    HSettingType::WebTransportMaxData => {
+                        enc_inner.encode_varint(SETTINGS_WT_INITIAL_MAX_DATA);
+                        enc_inner.encode_varint(iter.value);
+                    }
+                    HSettingType::WebTransportMaxStreamsBidi => {
+                        enc_inner.encode_varint(SETTINGS_WT_INITIAL_MAX_STREAMS_BIDI);
+                        enc_inner.encode_varint(iter.value);
+                    }
+                    HSettingType::WebTransportMaxStreamsUni => {
+                        enc_inner.encode_varint(SETTINGS_WT_INITIAL_MAX_STREAMS_UNI);
+                        enc_inner.encode_varint(iter.value);
+                    }
                 }
             }
 
@@ -204,6 +229,15 @@ impl HSettings {
                     self.settings
                         .push(HSetting::new(HSettingType::EnableConnect, value));
                 }
+                (Some(SETTINGS_WT_INITIAL_MAX_DATA), Some(value)) => self
+                    .settings
+                    .push(HSetting::new(HSettingType::WebTransportMaxData, value)),
+                (Some(SETTINGS_WT_INITIAL_MAX_STREAMS_BIDI), Some(value)) => self.settings.push(
+                    HSetting::new(HSettingType::WebTransportMaxStreamsBidi, value),
+                ),
+                (Some(SETTINGS_WT_INITIAL_MAX_STREAMS_UNI), Some(value)) => self.settings.push(
+                    HSetting::new(HSettingType::WebTransportMaxStreamsUni, value),
+                ),
                 (Some(t), Some(v)) => {
                     qdebug!("Ignoring unknown setting type {t} with value {v}");
                 }
@@ -223,30 +257,50 @@ impl Deref for HSettings {
 
 impl From<&Http3Parameters> for HSettings {
     fn from(conn_param: &Http3Parameters) -> Self {
-        Self {
-            settings: vec![
-                HSetting {
-                    setting_type: HSettingType::MaxTableCapacity,
-                    value: conn_param.get_max_table_size_decoder(),
-                },
-                HSetting {
-                    setting_type: HSettingType::BlockedStreams,
-                    value: u64::from(conn_param.get_max_blocked_streams()),
-                },
-                HSetting {
-                    setting_type: HSettingType::EnableWebTransport,
-                    value: u64::from(conn_param.get_webtransport()),
-                },
-                HSetting {
-                    setting_type: HSettingType::EnableH3Datagram,
-                    value: u64::from(conn_param.get_http3_datagram()),
-                },
-                HSetting {
-                    setting_type: HSettingType::EnableConnect,
-                    value: u64::from(conn_param.get_connect()),
-                },
-            ],
+        let mut settings = vec![
+            HSetting {
+                setting_type: HSettingType::MaxTableCapacity,
+                value: conn_param.get_max_table_size_decoder(),
+            },
+            HSetting {
+                setting_type: HSettingType::BlockedStreams,
+                value: u64::from(conn_param.get_max_blocked_streams()),
+            },
+            HSetting {
+                setting_type: HSettingType::EnableWebTransport,
+                value: u64::from(conn_param.get_webtransport()),
+            },
+            HSetting {
+                setting_type: HSettingType::EnableH3Datagram,
+                value: u64::from(conn_param.get_http3_datagram()),
+            },
+            HSetting {
+                setting_type: HSettingType::EnableConnect,
+                value: u64::from(conn_param.get_connect()),
+            },
+        ];
+        if conn_param.get_webtransport() {
+            let limits = [
+                (
+                    HSettingType::WebTransportMaxData,
+                    conn_param.get_webtransport_max_data(),
+                ),
+                (
+                    HSettingType::WebTransportMaxStreamsBidi,
+                    conn_param.get_webtransport_max_streams_bidi(),
+                ),
+                (
+                    HSettingType::WebTransportMaxStreamsUni,
+                    conn_param.get_webtransport_max_streams_uni(),
+                ),
+            ];
+            settings.extend(
+                limits
+                    .into_iter()
+                    .filter_map(|(setting_type, value)| Some(HSetting::new(setting_type, value?))),
+            );
         }
+        Self { settings }
     }
 }
 
@@ -328,7 +382,10 @@ impl ZeroRttChecker for HttpZeroRttChecker {
                 let value = setting.value == 1;
                 self.settings.get_connect() || !value
             }
-            HSettingType::MaxHeaderListSize => true,
+            HSettingType::MaxHeaderListSize
+            | HSettingType::WebTransportMaxData
+            | HSettingType::WebTransportMaxStreamsBidi
+            | HSettingType::WebTransportMaxStreamsUni => true,
         }) {
             ZeroRttCheckResult::Accept
         } else {
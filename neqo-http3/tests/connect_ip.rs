@@ -0,0 +1,242 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg(test)]
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use http::Uri;
+use neqo_common::{event::Provider as _, header::HeadersExt as _};
+use neqo_http3::{
+    AssignedAddress, ConnectIpCapsule, ConnectIpEvent, ConnectIpRequest, ConnectIpServerEvent,
+    Error, Http3Client, Http3ClientEvent, Http3Parameters, Http3Server, Http3ServerEvent,
+    IpAddressRange, Priority, SessionAcceptAction, StreamId,
+};
+use test_fixture::{exchange_packets, http3_client_with_params, http3_server_with_params, now};
+
+/// The start of an IPv4 header, standing in for a full packet.
+const IP_PACKET: &[u8] = &[0x45, 0x00, 0x00, 0x14];
+
+fn connect_ip_params() -> Http3Parameters {
+    Http3Parameters::default().connect(true)
+}
+
+fn initiate_new_session() -> (Http3Client, Http3Server, StreamId) {
+    let mut client = http3_client_with_params(connect_ip_params());
+    let mut proxy = http3_server_with_params(connect_ip_params());
+
+    let out = test_fixture::connect_peers(&mut client, &mut proxy);
+    exchange_packets(&mut client, &mut proxy, false, out);
+    assert!(
+        client
+            .events()
+            .any(|e| e == Http3ClientEvent::ConnectIp(ConnectIpEvent::Negotiated(true)))
+    );
+
+    let session_id = client
+        .connect_ip_create_session(
+            now(),
+            &Uri::from_static("https://proxy.example/.well-known/masque/ip/*/*/"),
+            &[],
+        )
+        .expect("create session");
+    (client, proxy, session_id)
+}
+
+fn new_session(proxy: &Http3Server) -> ConnectIpRequest {
+    proxy
+        .events()
+        .find_map(|event| match event {
+            Http3ServerEvent::ConnectIp(ConnectIpServerEvent::NewSession { session, headers }) => {
+                assert!(
+                    headers.contains_header(":method", "CONNECT")
+                        && headers.contains_header(":protocol", "connect-ip")
+                );
+                Some(session)
+            }
+            _ => None,
+        })
+        .expect("a new session")
+}
+
+fn establish_new_session() -> (Http3Client, Http3Server, StreamId, ConnectIpRequest) {
+    let (mut client, mut proxy, session_id) = initiate_new_session();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    let proxy_session = new_session(&proxy);
+    assert_eq!(proxy_session.stream_id(), session_id);
+    proxy_session
+        .response(&SessionAcceptAction::Accept, now())
+        .unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(client.events().any(|e| matches!(
+        e,
+        Http3ClientEvent::ConnectIp(ConnectIpEvent::NewSession { stream_id, status, .. })
+            if stream_id == session_id && status == 200
+    )));
+    (client, proxy, session_id, proxy_session)
+}
+
+fn client_capsules(client: &mut Http3Client, session_id: StreamId) -> Vec<ConnectIpCapsule> {
+    client
+        .events()
+        .filter_map(|e| match e {
+            Http3ClientEvent::ConnectIp(ConnectIpEvent::Capsule {
+                session_id: id,
+                capsule,
+            }) => {
+                assert_eq!(id, session_id);
+                Some(capsule)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn address_request() -> ConnectIpCapsule {
+    ConnectIpCapsule::AddressRequest(vec![AssignedAddress {
+        request_id: 1,
+        address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        prefix_len: 32,
+    }])
+}
+
+#[test]
+fn exchange_capsules() {
+    let (mut client, mut proxy, session_id, proxy_session) = establish_new_session();
+
+    client
+        .connect_ip_send_capsule(session_id, &address_request(), now())
+        .unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    let request = proxy
+        .events()
+        .find_map(|e| match e {
+            Http3ServerEvent::ConnectIp(ConnectIpServerEvent::Capsule { session, capsule }) => {
+                assert_eq!(session.stream_id(), session_id);
+                Some(capsule)
+            }
+            _ => None,
+        })
+        .expect("a capsule");
+    assert_eq!(request, address_request());
+
+    let assign = ConnectIpCapsule::AddressAssign(vec![AssignedAddress {
+        request_id: 1,
+        address: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7)),
+        prefix_len: 32,
+    }]);
+    let routes = ConnectIpCapsule::RouteAdvertisement(vec![IpAddressRange {
+        start: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        end: IpAddr::V4(Ipv4Addr::BROADCAST),
+        ip_protocol: 0,
+    }]);
+    proxy_session.send_capsule(&assign, now()).unwrap();
+    proxy_session.send_capsule(&routes, now()).unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert_eq!(client_capsules(&mut client, session_id), [assign, routes]);
+}
+
+#[test]
+fn session_lifecycle() {
+    let (mut client, mut proxy, session_id, proxy_session) = establish_new_session();
+
+    client
+        .connect_ip_send_datagram(session_id, IP_PACKET, None)
+        .unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    let datagram = proxy
+        .events()
+        .find_map(|e| match e {
+            Http3ServerEvent::ConnectIp(ConnectIpServerEvent::Datagram { session, datagram }) => {
+                assert_eq!(session.stream_id(), session_id);
+                Some(datagram)
+            }
+            _ => None,
+        })
+        .expect("a datagram");
+    assert_eq!(&datagram, IP_PACKET);
+
+    proxy_session.send_datagram(IP_PACKET, None).unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(client.events().any(|e| matches!(
+        e,
+        Http3ClientEvent::ConnectIp(ConnectIpEvent::Datagram { session_id: id, datagram })
+            if id == session_id && datagram.as_ref() == IP_PACKET
+    )));
+
+    proxy_session.close_session(0, "", now()).unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(client.events().any(|e| matches!(
+        e,
+        Http3ClientEvent::ConnectIp(ConnectIpEvent::SessionClosed { stream_id, .. })
+            if stream_id == session_id
+    )));
+}
+
+#[test]
+fn client_closes_session() {
+    let (mut client, mut proxy, session_id, _proxy_session) = establish_new_session();
+    client
+        .connect_ip_close_session(session_id, 0, "", now())
+        .unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(proxy.events().any(|e| matches!(
+        e,
+        Http3ServerEvent::ConnectIp(ConnectIpServerEvent::SessionClosed { session, .. })
+            if session.stream_id() == session_id
+    )));
+}
+
+#[test]
+fn capsule_before_accept() {
+    let (mut client, mut proxy, session_id) = initiate_new_session();
+    assert_eq!(
+        client.connect_ip_send_capsule(session_id, &address_request(), now()),
+        Err(Error::InvalidStreamId)
+    );
+    exchange_packets(&mut client, &mut proxy, false, None);
+    let proxy_session = new_session(&proxy);
+    assert_eq!(
+        proxy_session.send_capsule(&address_request(), now()),
+        Err(Error::InvalidStreamId)
+    );
+}
+
+#[test]
+fn create_session_without_connect_setting() {
+    let mut client = http3_client_with_params(Http3Parameters::default().connect(false));
+    assert_eq!(
+        client.connect_ip_create_session(now(), &Uri::from_static("https://example.com/"), &[]),
+        Err(Error::Unavailable)
+    );
+}
+
+#[test]
+fn connect_ip_operation_on_fetch_stream() {
+    let (mut client, _proxy, _session_id, _proxy_session) = establish_new_session();
+    let fetch_stream = client
+        .fetch(
+            now(),
+            "GET",
+            ("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .unwrap();
+
+    assert_eq!(
+        client.connect_ip_send_capsule(fetch_stream, &address_request(), now()),
+        Err(Error::InvalidStreamId)
+    );
+    assert_eq!(
+        client.connect_ip_send_datagram(fetch_stream, IP_PACKET, None),
+        Err(Error::InvalidStreamId)
+    );
+    assert_eq!(
+        client.connect_ip_close_session(fetch_stream, 0, "", now()),
+        Err(Error::InvalidStreamId)
+    );
+}
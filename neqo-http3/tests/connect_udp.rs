@@ -10,7 +10,7 @@ use http::Uri;
 use neqo_common::{Datagram, Tos, event::Provider as _, header::HeadersExt as _, qinfo};
 use neqo_crypto::AuthenticationStatus;
 use neqo_http3::{
-    ConnectUdpEvent, ConnectUdpRequest, ConnectUdpServerEvent, Error, Http3Client,
+    Capsule, ConnectUdpEvent, ConnectUdpRequest, ConnectUdpServerEvent, Error, Http3Client,
     Http3ClientEvent, Http3Parameters, Http3Server, Http3ServerEvent, Http3State, Priority,
     SessionAcceptAction,
 };
@@ -24,6 +24,14 @@ const PING: &[u8] = b"ping";
 const PONG: &[u8] = b"pong";
 
 fn initiate_new_session() -> (Http3Client, Http3Server, neqo_http3::StreamId) {
+    initiate_new_session_with(true)
+}
+
+/// Without `http3_datagram`, the client does not support HTTP/3 or QUIC
+/// datagrams.
+fn initiate_new_session_with(
+    http3_datagram: bool,
+) -> (Http3Client, Http3Server, neqo_http3::StreamId) {
     let conn_params = ConnectionParameters::default()
         .pmtud(true)
         .datagram_size(1500);
@@ -31,7 +39,12 @@ fn initiate_new_session() -> (Http3Client, Http3Server, neqo_http3::StreamId) {
     let mut client = http3_client_with_params(
         Http3Parameters::default()
             .connect(true)
-            .connection_parameters(conn_params.clone()),
+            .http3_datagram(http3_datagram)
+            .connection_parameters(conn_params.clone().datagram_size(if http3_datagram {
+                1500
+            } else {
+                0
+            })),
     );
 
     let mut proxy = http3_server_with_params(
@@ -64,7 +77,18 @@ fn establish_new_session() -> (
     neqo_http3::StreamId,
     ConnectUdpRequest,
 ) {
-    let (mut client, mut proxy, connect_udp_session_id) = initiate_new_session();
+    establish_new_session_with(true)
+}
+
+fn establish_new_session_with(
+    http3_datagram: bool,
+) -> (
+    Http3Client,
+    Http3Server,
+    neqo_http3::StreamId,
+    ConnectUdpRequest,
+) {
+    let (mut client, mut proxy, connect_udp_session_id) = initiate_new_session_with(http3_datagram);
     exchange_packets(&mut client, &mut proxy, false, None);
     let proxy_session = proxy
         .events()
@@ -290,6 +314,139 @@ fn session_lifecycle_server_closes() {
     session_lifecycle(false);
 }
 
+/// Without HTTP/3 datagrams, datagrams are sent in capsules on the request stream.
+#[test]
+fn datagram_capsules() {
+    let (mut client, mut proxy, session_id, proxy_session) = establish_new_session_with(false);
+
+    client
+        .connect_udp_send_datagram(session_id, PING, None)
+        .unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(proxy.events().any(|e| matches!(
+        e,
+        Http3ServerEvent::ConnectUdp(ConnectUdpServerEvent::Datagram { datagram, .. })
+            if datagram == *PING
+    )));
+
+    proxy_session.send_datagram(PONG, None).unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(client.events().any(|e| matches!(
+        e,
+        Http3ClientEvent::ConnectUdp(ConnectUdpEvent::Datagram { session_id: id, datagram })
+            if id == session_id && datagram == *PONG
+    )));
+}
+
+#[test]
+fn context_datagrams() {
+    let (mut client, mut proxy, session_id, proxy_session) = establish_new_session();
+
+    // The client allocates even context IDs and the proxy odd ones.
+    let client_context = client.connect_udp_register_context(session_id).unwrap();
+    assert_eq!(client_context, 2);
+    let proxy_context = proxy_session.register_context().unwrap();
+    assert_eq!(proxy_context, 1);
+    assert_eq!(
+        proxy_session.register_peer_context(proxy_context),
+        Err(Error::InvalidInput)
+    );
+    proxy_session.register_peer_context(client_context).unwrap();
+    client
+        .connect_udp_register_peer_context(session_id, proxy_context)
+        .unwrap();
+    assert_eq!(
+        client.connect_udp_send_context_datagram(session_id, 4, PING, None),
+        Err(Error::InvalidInput)
+    );
+
+    client
+        .connect_udp_send_context_datagram(session_id, client_context, PING, None)
+        .unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(proxy.events().any(|e| matches!(
+        e,
+        Http3ServerEvent::ConnectUdp(ConnectUdpServerEvent::ContextDatagram {
+            context_id,
+            datagram,
+            ..
+        }) if context_id == client_context && datagram == *PING
+    )));
+
+    proxy_session
+        .send_context_datagram(proxy_context, PONG, None)
+        .unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(client.events().any(|e| matches!(
+        e,
+        Http3ClientEvent::ConnectUdp(ConnectUdpEvent::ContextDatagram {
+            session_id: id,
+            context_id,
+            datagram,
+        }) if id == session_id && context_id == proxy_context && datagram == *PONG
+    )));
+}
+
+/// Capsules that CONNECT-UDP does not handle are passed to the application.
+#[test]
+fn unknown_capsule() {
+    let (mut client, mut proxy, session_id, proxy_session) = establish_new_session();
+    let capsule = Capsule::Unknown {
+        capsule_type: 0x1234,
+        value: vec![1, 2, 3],
+    };
+
+    client
+        .connect_udp_send_capsule(session_id, &capsule, now())
+        .unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(proxy.events().any(|e| matches!(
+        e,
+        Http3ServerEvent::ConnectUdp(ConnectUdpServerEvent::Capsule { capsule: c, .. })
+            if c == capsule
+    )));
+
+    proxy_session.send_capsule(&capsule, now()).unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    assert!(client.events().any(|e| e
+        == Http3ClientEvent::ConnectUdp(ConnectUdpEvent::Capsule {
+            session_id,
+            capsule: capsule.clone(),
+        })));
+}
+
+#[test]
+fn large_unknown_capsule() {
+    let (mut client, mut proxy, session_id, _proxy_session) = establish_new_session();
+    let large = Capsule::Unknown {
+        capsule_type: 0x1234,
+        value: vec![0; 70_000],
+    };
+    let capsule = Capsule::Unknown {
+        capsule_type: 0x1234,
+        value: vec![1, 2, 3],
+    };
+
+    // Unknown capsules that are too large to buffer are skipped.
+    client
+        .connect_udp_send_capsule(session_id, &large, now())
+        .unwrap();
+    client
+        .connect_udp_send_capsule(session_id, &capsule, now())
+        .unwrap();
+    exchange_packets(&mut client, &mut proxy, false, None);
+    let capsules = proxy
+        .events()
+        .filter_map(|e| match e {
+            Http3ServerEvent::ConnectUdp(ConnectUdpServerEvent::Capsule { capsule, .. }) => {
+                Some(capsule)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(capsules, [capsule]);
+}
+
 #[test]
 fn connect_via_proxy() {
     fixture_init();
@@ -0,0 +1,222 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg(test)]
+
+use std::time::Duration;
+
+use neqo_common::event::Provider as _;
+use neqo_crypto::AuthenticationStatus;
+use neqo_http3::{
+    Error, Header, Http3Client, Http3ClientEvent, Http3OrWebTransportStream, Http3Parameters,
+    Http3Server, Http3ServerEvent, Http3State, Priority,
+};
+use neqo_transport::{CloseReason, Error as TransportError, StreamId};
+use test_fixture::*;
+
+fn connect(client: &mut Http3Client, server: &mut Http3Server) {
+    exchange_packets(client, server, false, None);
+    assert!(
+        client
+            .events()
+            .any(|e| e == Http3ClientEvent::AuthenticationNeeded)
+    );
+    client.authenticated(AuthenticationStatus::Ok, now());
+    exchange_packets(client, server, false, None);
+    assert_eq!(client.state(), Http3State::Connected);
+}
+
+fn fetch(client: &mut Http3Client) -> StreamId {
+    let stream_id = client
+        .fetch(
+            now(),
+            "GET",
+            ("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .unwrap();
+    client.stream_close_send(stream_id, now()).unwrap();
+    stream_id
+}
+
+fn requests(server: &Http3Server) -> Vec<Http3OrWebTransportStream> {
+    server
+        .events()
+        .filter_map(|e| match e {
+            Http3ServerEvent::Headers { stream, .. } => Some(stream),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Deliver the response, which the client has to read before the connection
+/// closes.
+fn respond(
+    client: &mut Http3Client,
+    server: &mut Http3Server,
+    request: &Http3OrWebTransportStream,
+) {
+    request
+        .send_headers(&[Header::new(":status", "200")])
+        .unwrap();
+    request.send_data(&[1, 2, 3], now()).unwrap();
+    request.stream_close_send(now()).unwrap();
+    let out = server.process_output(now()).dgram().unwrap();
+    client.process_input(out, now());
+    let stream_id = request.stream_id();
+    assert!(
+        client.events().any(
+            |e| matches!(e, Http3ClientEvent::DataReadable { stream_id: id } if id == stream_id)
+        )
+    );
+}
+
+/// Have the server close an idle connection, which happens a PTO after it
+/// sent `GOAWAY`.
+fn close_after_grace(client: &mut Http3Client, server: &mut Http3Server) {
+    exchange_packets(client, server, false, None);
+    assert!(matches!(client.state(), Http3State::GoingAway(_)));
+    let delay = server.process_output(now()).callback();
+    assert!(delay > Duration::ZERO);
+    let out = server.process_output(now() + delay);
+    client.process_input(out.dgram().unwrap(), now() + delay);
+    assert_closed_cleanly(client);
+}
+
+fn assert_closed_cleanly(client: &Http3Client) {
+    let code = Error::HttpNone.code();
+    assert!(matches!(
+        client.state(),
+        Http3State::Closing(CloseReason::Transport(TransportError::PeerApplication(e)))
+            if e == code
+    ));
+}
+
+/// Send a request, and have the server start shutting down before it responds.
+fn shutdown_with_request(
+    params: Http3Parameters,
+) -> (Http3Client, Http3Server, Http3OrWebTransportStream) {
+    let mut client = default_http3_client();
+    let mut server = http3_server_with_params(params);
+    connect(&mut client, &mut server);
+
+    let stream_id = fetch(&mut client);
+    exchange_packets(&mut client, &mut server, false, None);
+    let request = requests(&server).pop().unwrap();
+    assert_eq!(request.stream_id(), stream_id);
+
+    server.shutdown_gracefully(now());
+    exchange_packets(&mut client, &mut server, false, None);
+    assert!(
+        client
+            .events()
+            .any(|e| e == Http3ClientEvent::GoawayReceived)
+    );
+    // The connection stays open while the request is active.
+    assert_eq!(client.state(), Http3State::GoingAway(StreamId::new(4)));
+    (client, server, request)
+}
+
+#[test]
+fn goaway_then_close_when_idle() {
+    let (mut client, mut server, request) = shutdown_with_request(Http3Parameters::default());
+    respond(&mut client, &mut server, &request);
+    close_after_grace(&mut client, &mut server);
+}
+
+/// An idle connection gets `GOAWAY` before it is closed.
+#[test]
+fn goaway_when_idle() {
+    let mut client = default_http3_client();
+    let mut server = default_http3_server();
+    connect(&mut client, &mut server);
+
+    server.shutdown_gracefully(now());
+    let out = server.process_output(now()).dgram().unwrap();
+    client.process_input(out, now());
+    assert!(
+        client
+            .events()
+            .any(|e| e == Http3ClientEvent::GoawayReceived)
+    );
+    close_after_grace(&mut client, &mut server);
+}
+
+/// Shutting down one connection leaves the others alone.
+#[test]
+fn shutdown_one_connection() {
+    let mut client = default_http3_client();
+    let mut other = default_http3_client();
+    let mut server = default_http3_server();
+    connect(&mut client, &mut server);
+    let conn = server
+        .events()
+        .find_map(|e| match e {
+            Http3ServerEvent::StateChange { conn, .. } => Some(conn),
+            _ => None,
+        })
+        .unwrap();
+    connect(&mut other, &mut server);
+
+    server.shutdown_connection_gracefully(&conn, now());
+    exchange_packets(&mut client, &mut server, false, None);
+    assert!(
+        client
+            .events()
+            .any(|e| e == Http3ClientEvent::GoawayReceived)
+    );
+    close_after_grace(&mut client, &mut server);
+    assert_eq!(other.state(), Http3State::Connected);
+}
+
+#[test]
+fn goaway_then_close_at_deadline() {
+    let timeout = Duration::from_secs(5);
+    let (mut client, mut server, _request) =
+        shutdown_with_request(Http3Parameters::default().drain_timeout(timeout));
+
+    // The server needs to be called again when the deadline passes.
+    let delay = server.process_output(now()).callback();
+    assert!(delay <= timeout);
+    let out = server.process_output(now() + timeout);
+    client.process_input(out.dgram().unwrap(), now() + timeout);
+    assert_closed_cleanly(&client);
+}
+
+/// A request that the client sends before it receives GOAWAY is rejected.
+#[test]
+fn reject_request_after_goaway() {
+    let mut client = default_http3_client();
+    let mut server = default_http3_server();
+    connect(&mut client, &mut server);
+
+    let first = fetch(&mut client);
+    exchange_packets(&mut client, &mut server, false, None);
+    let request = requests(&server).pop().unwrap();
+
+    server.shutdown_gracefully(now());
+    let goaway = server.process_output(now()).dgram();
+    let second = fetch(&mut client);
+    let out = client.process_output(now()).dgram();
+    let reset = server.process(out, now()).dgram();
+    assert!(requests(&server).is_empty());
+
+    for d in [goaway, reset].into_iter().flatten() {
+        client.process_input(d, now());
+    }
+    let rejected = client.events().any(|e| {
+        matches!(e, Http3ClientEvent::Reset { stream_id, error, .. }
+            if stream_id == second && error == Error::HttpRequestRejected.code())
+    });
+    assert!(rejected);
+    assert_eq!(client.state(), Http3State::GoingAway(StreamId::new(4)));
+
+    // The first request still completes.
+    assert_eq!(request.stream_id(), first);
+    respond(&mut client, &mut server, &request);
+    close_after_grace(&mut client, &mut server);
+}
@@ -512,3 +512,97 @@ fn fetch_noresponse_will_idletimeout() {
         }
     }
 }
+
+#[test]
+fn request_trailers() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+    let req = hconn_c
+        .fetch(
+            now(),
+            "POST",
+            ("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .unwrap();
+    hconn_c.send_data(req, RESPONSE_DATA, now()).unwrap();
+    let trailers = [Header::new("checksum", "abc")];
+    hconn_c.send_trailers(req, &trailers).unwrap();
+    assert_eq!(
+        hconn_c.send_data(req, RESPONSE_DATA, now()),
+        Err(neqo_http3::Error::InvalidInput)
+    );
+    hconn_c.stream_close_send(req, now()).unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s, false, dgram);
+
+    // The trailers are reported after the body and before the end of the request.
+    let mut body = Vec::new();
+    let mut got_trailers = false;
+    let mut fin = false;
+    while let Some(event) = hconn_s.next_event() {
+        match event {
+            Http3ServerEvent::Headers { fin, .. } => assert!(!fin),
+            Http3ServerEvent::Data {
+                data, fin: done, ..
+            } => {
+                assert!(!fin);
+                assert!(!got_trailers || data.is_empty());
+                body.extend_from_slice(&data);
+                fin = done;
+            }
+            Http3ServerEvent::Trailers { headers, .. } => {
+                assert!(!fin);
+                assert_eq!(headers, trailers);
+                got_trailers = true;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(body, RESPONSE_DATA);
+    assert!(got_trailers);
+    assert!(fin);
+}
+
+#[test]
+fn pause_reading() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+    let req = hconn_c
+        .fetch(
+            now(),
+            "POST",
+            ("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s, false, dgram);
+    let request = loop {
+        if let Some(Http3ServerEvent::Headers { stream, .. }) = hconn_s.next_event() {
+            break stream;
+        }
+    };
+    request.pause_reading();
+
+    hconn_c.send_data(req, RESPONSE_DATA, now()).unwrap();
+    hconn_c.stream_close_send(req, now()).unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s, false, None);
+    let data = |e| matches!(e, Http3ServerEvent::Data { .. });
+    assert!(!hconn_s.events().any(data));
+
+    // Once reading resumes, the buffered data is delivered.
+    request.resume_reading();
+    drop(hconn_s.process_output(now()));
+    let mut body = Vec::new();
+    let mut fin = false;
+    while let Some(event) = hconn_s.next_event() {
+        if let Http3ServerEvent::Data {
+            data, fin: done, ..
+        } = event
+        {
+            body.extend_from_slice(&data);
+            fin = done;
+        }
+    }
+    assert_eq!(body, RESPONSE_DATA);
+    assert!(fin);
+}
@@ -0,0 +1,90 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg(test)]
+
+use neqo_common::event::Provider as _;
+use neqo_crypto::AuthenticationStatus;
+use neqo_http3::{Error, Http3Client, Http3ClientEvent, Http3Parameters, Http3Server, Priority};
+use test_fixture::*;
+
+fn connect(client: &mut Http3Client, server: &mut Http3Server) {
+    exchange_packets(client, server, false, None);
+    assert!(
+        client
+            .events()
+            .any(|e| e == Http3ClientEvent::AuthenticationNeeded)
+    );
+    client.authenticated(AuthenticationStatus::Ok, now());
+    exchange_packets(client, server, false, None);
+}
+
+fn fetch(client: &mut Http3Client, authority: &str) -> Result<(), Error> {
+    client
+        .fetch(
+            now(),
+            "GET",
+            ("https", authority, "/"),
+            &[],
+            Priority::default(),
+        )
+        .map(|_| ())
+}
+
+// The test certificate is valid for `alt1.example.com` and `alt2.example.com`.
+
+#[test]
+fn no_origin_frame() {
+    let mut client = default_http3_client();
+    let mut server = default_http3_server();
+    // The server name can be used before the certificate is available.
+    assert!(client.origin_allowed(DEFAULT_SERVER_NAME));
+    connect(&mut client, &mut server);
+
+    assert_eq!(client.origins(), None);
+    // The port of the server name doesn't matter, as with Alt-Svc.
+    assert!(client.origin_allowed(&format!("{DEFAULT_SERVER_NAME}:8443")));
+    assert!(!client.origin_allowed("alt1.example.com"));
+    // Requests are not checked without an ORIGIN frame.
+    fetch(&mut client, "alt1.example.com").unwrap();
+}
+
+#[test]
+fn origin_frame() {
+    let mut client = default_http3_client();
+    let mut server = http3_server_with_params(Http3Parameters::default().origins(vec![
+        "https://alt1.example.com".into(),
+        "https://other.example".into(),
+    ]));
+    connect(&mut client, &mut server);
+
+    assert_eq!(
+        client.origins(),
+        Some(
+            &[
+                "https://alt1.example.com".to_string(),
+                "https://other.example".to_string()
+            ][..]
+        )
+    );
+    assert!(client.origin_allowed("ALT1.example.com:443"));
+    // The port has to match.
+    assert!(!client.origin_allowed("alt1.example.com:8443"));
+    // This is in the origin set, but the certificate is not valid for it.
+    assert!(!client.origin_allowed("other.example"));
+    // The certificate is valid for this, but it is not in the origin set.
+    assert!(!client.origin_allowed("alt2.example.com"));
+    // The server name is always in the origin set.
+    assert!(client.origin_allowed(DEFAULT_SERVER_NAME));
+    assert!(client.origin_allowed(&format!("{DEFAULT_SERVER_NAME}:8443")));
+    fetch(&mut client, &format!("{DEFAULT_SERVER_NAME}:8443")).unwrap();
+
+    fetch(&mut client, "alt1.example.com").unwrap();
+    assert_eq!(
+        fetch(&mut client, "other.example"),
+        Err(Error::InvalidOrigin)
+    );
+}
@@ -9,6 +9,7 @@ use neqo_crypto::AuthenticationStatus;
 use neqo_http3::{
     Header, Http3Client, Http3ClientEvent, Http3Server, Http3ServerEvent, Http3State, Priority,
 };
+use neqo_transport::StreamId;
 use test_fixture::*;
 
 // Perform only QUIC transport handshake.
@@ -132,3 +133,148 @@ fn priority_update_dont_send_for_cancelled_stream() {
         }
     }
 }
+
+/// Send two requests, then respond to them in order, with `len` bytes each.
+/// The responses are left for the server to send.
+fn send_responses(
+    client: &mut Http3Client,
+    server: &mut Http3Server,
+    priorities: [&str; 2],
+    update: Option<Priority>,
+    len: usize,
+) {
+    let requests = priorities.map(|p| {
+        let headers = if p.is_empty() {
+            vec![]
+        } else {
+            vec![Header::new("priority", p)]
+        };
+        let stream_id = client
+            .fetch(
+                now(),
+                "GET",
+                ("https", "something.com", "/"),
+                &headers,
+                Priority::default(),
+            )
+            .expect("fetch");
+        client
+            .stream_close_send(stream_id, now())
+            .expect("close request");
+        stream_id
+    });
+    if let Some(priority) = update {
+        client
+            .priority_update(requests[1], priority)
+            .expect("priority update");
+    }
+    exchange_packets(client, server, false, None);
+
+    let streams = server
+        .events()
+        .filter_map(|e| match e {
+            Http3ServerEvent::Headers { stream, .. } => Some(stream),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(streams.len(), 2);
+    for stream in &streams {
+        stream
+            .send_headers(&[Header::new(":status", "200")])
+            .expect("send headers");
+        let data = vec![0x62; len];
+        assert_eq!(stream.send_data(&data, now()).expect("send data"), len);
+        stream.stream_close_send(now()).expect("close response");
+    }
+}
+
+/// Send two requests and respond to them in order.
+/// Returns the order in which the client sees the responses arrive.
+fn response_order(
+    client: &mut Http3Client,
+    server: &mut Http3Server,
+    priorities: [&str; 2],
+    update: Option<Priority>,
+) -> Vec<StreamId> {
+    send_responses(client, server, priorities, update, 5_000);
+
+    // Deliver the response one datagram at a time.
+    let mut order = Vec::new();
+    while let Some(dgram) = server.process_output(now()).dgram() {
+        client.process_input(dgram, now());
+        for e in client.events() {
+            if let Http3ClientEvent::HeaderReady { stream_id, .. } = e {
+                order.push(stream_id);
+            }
+        }
+    }
+    order
+}
+
+#[test]
+fn urgent_response_first() {
+    let (mut client, mut server) = connect();
+    let order = response_order(&mut client, &mut server, ["u=5", "u=1"], None);
+    assert_eq!(order, [StreamId::new(4), StreamId::new(0)]);
+}
+
+#[test]
+fn same_urgency_in_stream_order() {
+    let (mut client, mut server) = connect();
+    let order = response_order(&mut client, &mut server, ["u=2", "u=2"], None);
+    assert_eq!(order, [StreamId::new(0), StreamId::new(4)]);
+}
+
+/// Incremental responses of the same urgency take turns, so the data of the
+/// two responses is interleaved.
+#[test]
+fn incremental_responses_round_robin() {
+    let (mut client, mut server) = connect();
+    send_responses(&mut client, &mut server, ["u=2,i", "u=2,i"], None, 20_000);
+
+    // Deliver the responses one datagram at a time, noting which stream
+    // each part of the response data arrives on.
+    let mut order = Vec::<StreamId>::new();
+    let mut buf = vec![0; 100_000];
+    let mut to_server = None;
+    loop {
+        let from_server = server.process(to_server, now()).dgram();
+        let Some(dgram) = from_server else {
+            // The server might be waiting for flow control credit.
+            to_server = client.process_output(now()).dgram();
+            if to_server.is_none() {
+                break;
+            }
+            continue;
+        };
+        to_server = None;
+        client.process_input(dgram, now());
+        while let Some(e) = client.next_event() {
+            if let Http3ClientEvent::DataReadable { stream_id } = e {
+                while let Ok((amount, _)) = client.read_data(now(), stream_id, &mut buf) {
+                    if amount == 0 {
+                        break;
+                    }
+                    if order.last() != Some(&stream_id) {
+                        order.push(stream_id);
+                    }
+                }
+            }
+        }
+    }
+    // Both responses arrive, and the data switches between them more than once.
+    assert!(order.contains(&StreamId::new(0)) && order.contains(&StreamId::new(4)));
+    assert!(order.len() > 2, "responses were not interleaved: {order:?}");
+}
+
+#[test]
+fn priority_update_reorders_responses() {
+    let (mut client, mut server) = connect();
+    let order = response_order(
+        &mut client,
+        &mut server,
+        ["", ""],
+        Some(Priority::new(0, false)),
+    );
+    assert_eq!(order, [StreamId::new(4), StreamId::new(0)]);
+}
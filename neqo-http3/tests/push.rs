@@ -0,0 +1,219 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use neqo_common::event::Provider as _;
+use neqo_http3::{
+    Error, Header, Http3Client, Http3ClientEvent, Http3OrWebTransportStream, Http3Parameters,
+    Http3Server, Http3ServerEvent, Priority, PushId,
+};
+use test_fixture::*;
+
+const PUSH_DATA: &[u8] = b"body { color: red }";
+
+fn promised_request() -> Vec<Header> {
+    vec![
+        Header::new(":method", "GET"),
+        Header::new(":scheme", "https"),
+        Header::new(":authority", "something.com"),
+        Header::new(":path", "/style.css"),
+    ]
+}
+
+fn connect_with(client: Http3Client) -> (Http3Client, Http3Server) {
+    let mut client = client;
+    let mut server = default_http3_server();
+    let out = connect_peers(&mut client, &mut server);
+    exchange_packets(&mut client, &mut server, false, out);
+    (client, server)
+}
+
+fn connect() -> (Http3Client, Http3Server) {
+    connect_with(default_http3_client())
+}
+
+/// Send a request from the client and return the server's handle for it.
+fn request(client: &mut Http3Client, server: &mut Http3Server) -> Http3OrWebTransportStream {
+    let stream_id = client
+        .fetch(
+            now(),
+            "GET",
+            ("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .expect("fetch");
+    client
+        .stream_close_send(stream_id, now())
+        .expect("close request");
+    exchange_packets(client, server, false, None);
+    server
+        .events()
+        .find_map(|e| match e {
+            Http3ServerEvent::Headers { stream, .. } => Some(stream),
+            _ => None,
+        })
+        .expect("a request")
+}
+
+fn respond(stream: &Http3OrWebTransportStream, data: &[u8]) {
+    stream
+        .send_headers(&[Header::new(":status", "200")])
+        .expect("send headers");
+    stream.send_data(data, now()).expect("send data");
+    stream.stream_close_send(now()).expect("close response");
+}
+
+#[test]
+fn push() {
+    let (mut client, mut server) = connect();
+    let req = request(&mut client, &mut server);
+
+    let (push_id, push) = req.send_push_promise(&promised_request()).unwrap();
+    assert_eq!(push_id, PushId::new(0));
+    respond(&push, PUSH_DATA);
+    respond(&req, &[0x61]);
+    exchange_packets(&mut client, &mut server, false, None);
+
+    let mut promise = false;
+    let mut headers = false;
+    let mut data = false;
+    while let Some(e) = client.next_event() {
+        match e {
+            Http3ClientEvent::PushPromise {
+                push_id: id,
+                request_stream_id,
+                headers,
+            } => {
+                assert_eq!(id, push_id);
+                assert_eq!(request_stream_id, req.stream_id());
+                assert_eq!(headers, promised_request());
+                promise = true;
+            }
+            Http3ClientEvent::PushHeaderReady {
+                push_id: id,
+                headers: h,
+                fin,
+                ..
+            } => {
+                assert_eq!(id, push_id);
+                assert_eq!(h, [Header::new(":status", "200")]);
+                assert!(!fin);
+                headers = true;
+            }
+            Http3ClientEvent::PushDataReadable { push_id: id } => {
+                assert_eq!(id, push_id);
+                let mut buf = [0; 100];
+                let (amount, fin) = client.push_read_data(now(), push_id, &mut buf).unwrap();
+                assert!(fin);
+                assert_eq!(&buf[..amount], PUSH_DATA);
+                data = true;
+            }
+            _ => {}
+        }
+    }
+    assert!(promise && headers && data);
+}
+
+#[test]
+fn push_canceled_by_client() {
+    let (mut client, mut server) = connect();
+    let req = request(&mut client, &mut server);
+
+    let (push_id, push) = req.send_push_promise(&promised_request()).unwrap();
+    exchange_packets(&mut client, &mut server, false, None);
+    assert!(
+        client
+            .events()
+            .any(|e| matches!(e, Http3ClientEvent::PushPromise { .. }))
+    );
+
+    // The client has already seen the push stream, so it stops it instead of sending CANCEL_PUSH.
+    client.cancel_push(push_id).unwrap();
+    exchange_packets(&mut client, &mut server, false, None);
+
+    let stopped = server.events().find_map(|e| match e {
+        Http3ServerEvent::StreamStopSending { stream, error } => Some((stream, error)),
+        _ => None,
+    });
+    assert_eq!(
+        stopped,
+        Some((push.clone(), Error::HttpRequestCancelled.code()))
+    );
+    assert_eq!(
+        push.send_headers(&[Header::new(":status", "200")]),
+        Err(Error::InvalidStreamId)
+    );
+}
+
+#[test]
+fn push_not_allowed() {
+    let (mut client, mut server) = connect_with(http3_client_with_params(
+        Http3Parameters::default().max_concurrent_push_streams(0),
+    ));
+    let req = request(&mut client, &mut server);
+    assert_eq!(
+        req.send_push_promise(&promised_request()),
+        Err(Error::StreamLimit)
+    );
+}
+
+#[test]
+fn push_limit() {
+    // The client sends MAX_PUSH_ID with push ID 1, which allows two pushes.
+    let (mut client, mut server) = connect_with(http3_client_with_params(
+        Http3Parameters::default().max_concurrent_push_streams(1),
+    ));
+    let req = request(&mut client, &mut server);
+    for expected in 0..2 {
+        let (push_id, _) = req.send_push_promise(&promised_request()).unwrap();
+        assert_eq!(push_id, PushId::new(expected));
+    }
+    assert_eq!(
+        req.send_push_promise(&promised_request()),
+        Err(Error::StreamLimit)
+    );
+}
+
+#[test]
+fn push_promise_after_trailers() {
+    let (mut client, mut server) = connect();
+    let req = request(&mut client, &mut server);
+    req.send_headers(&[Header::new(":status", "200")]).unwrap();
+    req.send_headers(&[Header::new("something", "3")]).unwrap();
+    assert_eq!(
+        req.send_push_promise(&promised_request()),
+        Err(Error::InvalidInput)
+    );
+}
+
+#[test]
+fn push_promise_on_push_stream() {
+    let (mut client, mut server) = connect();
+    let req = request(&mut client, &mut server);
+    let (_, push) = req.send_push_promise(&promised_request()).unwrap();
+    assert_eq!(
+        push.send_push_promise(&promised_request()),
+        Err(Error::InvalidStreamId)
+    );
+}
+
+#[test]
+fn push_promise_invalid_headers() {
+    let (mut client, mut server) = connect();
+    let req = request(&mut client, &mut server);
+    let (_, expected) = req.send_push_promise(&promised_request()).unwrap();
+
+    let (mut client, mut server) = connect();
+    let req = request(&mut client, &mut server);
+    let mut headers = promised_request();
+    headers.retain(|h| h.name() != ":path");
+    assert_eq!(req.send_push_promise(&headers), Err(Error::InvalidHeader));
+
+    // The failed promise did not use up a push ID or a push stream.
+    let (push_id, push) = req.send_push_promise(&promised_request()).unwrap();
+    assert_eq!(push_id, PushId::new(0));
+    assert_eq!(push.stream_id(), expected.stream_id());
+}
@@ -6,7 +6,7 @@
 
 use std::fmt::{self, Display, Formatter};
 
-use neqo_common::{Encoder, Header, qdebug};
+use neqo_common::{Encoder, Header, qdebug, qlog::Qlog};
 use neqo_transport::{Connection, StreamId};
 
 use crate::{
@@ -14,6 +14,7 @@ use crate::{
     decoder_instructions::DecoderInstruction,
     encoder_instructions::{DecodedEncoderInstruction, EncoderInstructionReader},
     header_block::{HeaderDecoder, HeaderDecoderResult},
+    qlog,
     reader::{ReadByte, Reader, ReceiverConnWrapper},
     stats::Stats,
     table::HeaderTable,
@@ -33,6 +34,7 @@ pub struct Decoder {
     max_blocked_streams: usize,
     blocked_streams: Vec<(StreamId, u64)>, // stream_id and requested inserts count.
     stats: Stats,
+    qlog: Qlog,
 }
 
 impl Decoder {
@@ -56,9 +58,15 @@ impl Decoder {
             max_blocked_streams,
             blocked_streams: Vec::with_capacity(max_blocked_streams),
             stats: Stats::default(),
+            qlog: Qlog::default(),
         }
     }
 
+    /// Set the qlog that instructions and header blocks are logged to.
+    pub fn set_qlog(&mut self, qlog: Qlog) {
+        self.qlog = qlog;
+    }
+
     #[must_use]
     const fn capacity(&self) -> u64 {
         self.table.capacity()
@@ -102,6 +110,7 @@ impl Decoder {
     }
 
     fn execute_instruction(&mut self, instruction: DecodedEncoderInstruction) -> Res<()> {
+        qlog::qpack_encoder_instruction_parsed(&mut self.qlog, &instruction);
         match instruction {
             DecodedEncoderInstruction::Capacity { value } => self.set_capacity(value)?,
             DecodedEncoderInstruction::InsertWithNameRefStatic { index, value } => {
@@ -144,8 +153,18 @@ impl Decoder {
         self.table.set_capacity(cap)
     }
 
+    fn queue_instruction(&mut self, instruction: &DecoderInstruction) {
+        let start = self.send_buf.len();
+        instruction.marshal(&mut self.send_buf);
+        qlog::qpack_decoder_instruction_created(
+            &mut self.qlog,
+            instruction,
+            &self.send_buf.as_ref()[start..],
+        );
+    }
+
     fn header_ack(&mut self, stream_id: StreamId, required_inserts: u64) {
-        DecoderInstruction::HeaderAck { stream_id }.marshal(&mut self.send_buf);
+        self.queue_instruction(&DecoderInstruction::HeaderAck { stream_id });
         if required_inserts > self.acked_inserts {
             self.acked_inserts = required_inserts;
         }
@@ -154,7 +173,7 @@ impl Decoder {
     pub fn cancel_stream(&mut self, stream_id: StreamId) {
         if self.table.capacity() > 0 {
             self.blocked_streams.retain(|(id, _)| *id != stream_id);
-            DecoderInstruction::StreamCancellation { stream_id }.marshal(&mut self.send_buf);
+            self.queue_instruction(&DecoderInstruction::StreamCancellation { stream_id });
         }
     }
 
@@ -169,7 +188,7 @@ impl Decoder {
         // Encode increment instruction if needed.
         let increment = self.table.base() - self.acked_inserts;
         if increment > 0 {
-            DecoderInstruction::InsertCountIncrement { increment }.marshal(&mut self.send_buf);
+            self.queue_instruction(&DecoderInstruction::InsertCountIncrement { increment });
             self.acked_inserts = self.table.base();
         }
         if !self.send_buf.is_empty() && self.local_stream_id.is_some() {
@@ -229,6 +248,7 @@ impl Decoder {
                 }
             }
             Ok(HeaderDecoderResult::Headers(h)) => {
+                qlog::qpack_headers_decoded(&mut self.qlog, stream_id, buf);
                 if decoder.get_req_insert_cnt() != 0 {
                     self.header_ack(stream_id, decoder.get_req_insert_cnt());
                     self.stats.dynamic_table_references += 1;
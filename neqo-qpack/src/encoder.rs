@@ -18,7 +18,7 @@ use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
 use crate::{
     Error, Res, Settings,
     decoder_instructions::{DecoderInstruction, DecoderInstructionReader},
-    encoder_instructions::EncoderInstruction,
+    encoder_instructions::{DecodedEncoderInstruction, EncoderInstruction},
     header_block::HeaderEncoder,
     qlog,
     reader::ReceiverConnWrapper,
@@ -228,15 +228,9 @@ impl Encoder {
         now: Instant,
     ) -> Res<()> {
         qdebug!("[{self}] call instruction {instruction:?}");
+        qlog::qpack_decoder_instruction_parsed(qlog, &instruction, now);
         match instruction {
             DecoderInstruction::InsertCountIncrement { increment } => {
-                qlog::qpack_read_insert_count_increment_instruction(
-                    qlog,
-                    increment,
-                    &increment.to_be_bytes(),
-                    now,
-                );
-
                 self.insert_count_instruction(increment)
             }
             DecoderInstruction::HeaderAck { stream_id } => {
@@ -281,8 +275,8 @@ impl Encoder {
         }
 
         let mut buf = neqo_common::Encoder::default();
-        EncoderInstruction::InsertWithNameLiteral { name, value }
-            .marshal(&mut buf, self.use_huffman);
+        let instruction = EncoderInstruction::InsertWithNameLiteral { name, value };
+        instruction.marshal(&mut buf, self.use_huffman);
 
         let stream_id = self.local_stream.stream_id().ok_or(Error::Internal)?;
 
@@ -292,6 +286,12 @@ impl Encoder {
         if !sent {
             return Err(Error::EncoderStreamBlocked);
         }
+        qlog::qpack_encoder_instruction_created(
+            conn.qlog_mut(),
+            &DecodedEncoderInstruction::from(&instruction),
+            self.use_huffman,
+            buf.as_ref(),
+        );
 
         self.stats.dynamic_table_inserts += 1;
 
@@ -321,10 +321,17 @@ impl Encoder {
                 return Err(Error::DynamicTableFull);
             }
             let mut buf = neqo_common::Encoder::default();
-            EncoderInstruction::Capacity { value: cap }.marshal(&mut buf, self.use_huffman);
+            let instruction = EncoderInstruction::Capacity { value: cap };
+            instruction.marshal(&mut buf, self.use_huffman);
             if !conn.stream_send_atomic(stream_id, buf.as_ref())? {
                 return Err(Error::EncoderStreamBlocked);
             }
+            qlog::qpack_encoder_instruction_created(
+                conn.qlog_mut(),
+                &DecodedEncoderInstruction::from(&instruction),
+                self.use_huffman,
+                buf.as_ref(),
+            );
             if self.table.set_capacity(cap).is_err() {
                 debug_assert!(
                     false,
@@ -473,6 +480,7 @@ impl Encoder {
         }
 
         encoded_h.encode_header_block_prefix();
+        qlog::qpack_headers_encoded(conn.qlog_mut(), stream_id, &encoded_h);
 
         if !stream_is_blocker {
             // The streams was not a blocker, check if the stream is a blocker now.
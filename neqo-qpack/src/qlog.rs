@@ -5,34 +5,94 @@
 // except according to those terms.
 
 // Functions that handle capturing QLOG traces.
+//
+// Except where noted, the functions in this module are called where the
+// current time is not known, so they log at the time of the previous event.
 
 use std::time::Instant;
 
 use neqo_common::{hex, qlog::Qlog};
+use neqo_transport::StreamId;
 use qlog::events::{
     EventData, RawInfo,
-    qpack::{QPackInstruction, QpackInstructionParsed, QpackInstructionTypeName},
+    qpack::{
+        QPackInstruction, QpackHeaderBlockPrefix, QpackHeaderBlockRepresentation,
+        QpackHeaderBlockRepresentationTypeName, QpackHeadersDecoded, QpackHeadersEncoded,
+        QpackInstructionCreated, QpackInstructionParsed, QpackInstructionTypeName, QpackTableType,
+    },
 };
 
-pub fn qpack_read_insert_count_increment_instruction(
+use crate::{
+    Res,
+    decoder_instructions::DecoderInstruction,
+    encoder_instructions::DecodedEncoderInstruction,
+    prefix::{
+        HEADER_FIELD_INDEX_DYNAMIC, HEADER_FIELD_INDEX_DYNAMIC_POST, HEADER_FIELD_INDEX_STATIC,
+        HEADER_FIELD_LITERAL_NAME_LITERAL, HEADER_FIELD_LITERAL_NAME_REF_DYNAMIC,
+        HEADER_FIELD_LITERAL_NAME_REF_DYNAMIC_POST, HEADER_FIELD_LITERAL_NAME_REF_STATIC,
+    },
+    reader::ReceiverBufferWrapper,
+};
+
+/// Log an instruction that the encoder sends.  `raw` is the encoded instruction.
+pub fn qpack_encoder_instruction_created(
+    qlog: &mut Qlog,
+    instruction: &DecodedEncoderInstruction,
+    use_huffman: bool,
+    raw: &[u8],
+) {
+    qlog.add_event(|| {
+        let ev_data = EventData::QpackInstructionCreated(QpackInstructionCreated {
+            instruction: encoder_instruction(instruction, use_huffman)?,
+            raw: Some(raw_info(raw)),
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// Log an instruction that the decoder received.  Whether it used Huffman
+/// coding is not known at this point.
+pub fn qpack_encoder_instruction_parsed(qlog: &mut Qlog, instruction: &DecodedEncoderInstruction) {
+    qlog.add_event(|| {
+        let ev_data = EventData::QpackInstructionParsed(QpackInstructionParsed {
+            instruction: encoder_instruction(instruction, false)?,
+            raw: None,
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// Log an instruction that the decoder sends.  `raw` is the encoded instruction.
+pub fn qpack_decoder_instruction_created(
     qlog: &mut Qlog,
-    increment: u64,
-    data: &[u8],
+    instruction: &DecoderInstruction,
+    raw: &[u8],
+) {
+    qlog.add_event(|| {
+        let ev_data = EventData::QpackInstructionCreated(QpackInstructionCreated {
+            instruction: decoder_instruction(instruction)?,
+            raw: Some(raw_info(raw)),
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// Log an instruction that the encoder received, at `now`.
+pub fn qpack_decoder_instruction_parsed(
+    qlog: &mut Qlog,
+    instruction: &DecoderInstruction,
     now: Instant,
 ) {
     qlog.add_event_at(
         || {
-            let raw = RawInfo {
-                length: Some(8),
-                payload_length: None,
-                data: Some(hex(data)),
-            };
+            let mut raw = neqo_common::Encoder::default();
+            instruction.marshal(&mut raw);
             let ev_data = EventData::QpackInstructionParsed(QpackInstructionParsed {
-                instruction: QPackInstruction::InsertCountIncrementInstruction {
-                    instruction_type: QpackInstructionTypeName::InsertCountIncrementInstruction,
-                    increment,
-                },
-                raw: Some(raw),
+                instruction: decoder_instruction(instruction)?,
+                raw: Some(raw_info(raw.as_ref())),
             });
 
             Some(ev_data)
@@ -40,3 +100,281 @@ pub fn qpack_read_insert_count_increment_instruction(
         now,
     );
 }
+
+pub fn qpack_headers_encoded(qlog: &mut Qlog, stream_id: StreamId, header_block: &[u8]) {
+    qlog.add_event(|| {
+        let (block_prefix, representations) = parse_header_block(header_block).ok()?;
+        let ev_data = EventData::QpackHeadersEncoded(QpackHeadersEncoded {
+            stream_id: Some(stream_id.as_u64()),
+            headers: None,
+            block_prefix,
+            header_block: representations,
+            raw: Some(raw_info(header_block)),
+        });
+
+        Some(ev_data)
+    });
+}
+
+pub fn qpack_headers_decoded(qlog: &mut Qlog, stream_id: StreamId, header_block: &[u8]) {
+    qlog.add_event(|| {
+        let (block_prefix, representations) = parse_header_block(header_block).ok()?;
+        let ev_data = EventData::QpackHeadersDecoded(QpackHeadersDecoded {
+            stream_id: Some(stream_id.as_u64()),
+            headers: None,
+            block_prefix,
+            header_block: representations,
+            raw: Some(raw_info(header_block)),
+        });
+
+        Some(ev_data)
+    });
+}
+
+fn raw_info(raw: &[u8]) -> RawInfo {
+    RawInfo {
+        length: u64::try_from(raw.len()).ok(),
+        payload_length: None,
+        data: Some(hex(raw)),
+    }
+}
+
+fn len(v: &[u8]) -> u64 {
+    u64::try_from(v.len()).expect("usize fits in u64")
+}
+
+fn string(v: &[u8]) -> String {
+    String::from_utf8_lossy(v).into_owned()
+}
+
+fn encoder_instruction(
+    instruction: &DecodedEncoderInstruction,
+    use_huffman: bool,
+) -> Option<QPackInstruction> {
+    let instruction = match instruction {
+        DecodedEncoderInstruction::Capacity { value } => {
+            QPackInstruction::SetDynamicTableCapacityInstruction {
+                instruction_type: QpackInstructionTypeName::SetDynamicTableCapacityInstruction,
+                capacity: *value,
+            }
+        }
+        DecodedEncoderInstruction::InsertWithNameRefStatic { index, value }
+        | DecodedEncoderInstruction::InsertWithNameRefDynamic { index, value } => {
+            QPackInstruction::InsertWithNameReferenceInstruction {
+                instruction_type: QpackInstructionTypeName::InsertWithNameReferenceInstruction,
+                table_type: if matches!(
+                    instruction,
+                    DecodedEncoderInstruction::InsertWithNameRefStatic { .. }
+                ) {
+                    QpackTableType::Static
+                } else {
+                    QpackTableType::Dynamic
+                },
+                name_index: *index,
+                huffman_encoded_value: use_huffman,
+                value_length: len(value),
+                value: string(value),
+            }
+        }
+        DecodedEncoderInstruction::InsertWithNameLiteral { name, value } => {
+            QPackInstruction::InsertWithoutNameReferenceInstruction {
+                instruction_type: QpackInstructionTypeName::InsertWithoutNameReferenceInstruction,
+                huffman_encoded_name: use_huffman,
+                name_length: len(name),
+                name: string(name),
+                huffman_encoded_value: use_huffman,
+                value_length: len(value),
+                value: string(value),
+            }
+        }
+        DecodedEncoderInstruction::Duplicate { index } => QPackInstruction::DuplicateInstruction {
+            instruction_type: QpackInstructionTypeName::DuplicateInstruction,
+            index: *index,
+        },
+        DecodedEncoderInstruction::NoInstruction => return None,
+    };
+    Some(instruction)
+}
+
+fn decoder_instruction(instruction: &DecoderInstruction) -> Option<QPackInstruction> {
+    let instruction = match instruction {
+        DecoderInstruction::InsertCountIncrement { increment } => {
+            QPackInstruction::InsertCountIncrementInstruction {
+                instruction_type: QpackInstructionTypeName::InsertCountIncrementInstruction,
+                increment: *increment,
+            }
+        }
+        DecoderInstruction::HeaderAck { stream_id } => {
+            QPackInstruction::HeaderAcknowledgementInstruction {
+                instruction_type: QpackInstructionTypeName::HeaderAcknowledgementInstruction,
+                stream_id: stream_id.to_string(),
+            }
+        }
+        DecoderInstruction::StreamCancellation { stream_id } => {
+            QPackInstruction::StreamCancellationInstruction {
+                instruction_type: QpackInstructionTypeName::StreamCancellationInstruction,
+                stream_id: stream_id.to_string(),
+            }
+        }
+        DecoderInstruction::NoInstruction => return None,
+    };
+    Some(instruction)
+}
+
+/// Read a literal, returning whether it was Huffman encoded and its value.
+fn read_literal(buf: &mut ReceiverBufferWrapper, prefix_len: u8) -> Res<(bool, Vec<u8>)> {
+    let huffman = buf.peek()? & (0x80 >> prefix_len) != 0;
+    Ok((huffman, buf.read_literal_from_buffer(prefix_len)?))
+}
+
+fn indexed(
+    buf: &mut ReceiverBufferWrapper,
+    table_type: QpackTableType,
+    prefix_len: u8,
+    is_post_base: bool,
+) -> Res<QpackHeaderBlockRepresentation> {
+    Ok(QpackHeaderBlockRepresentation::IndexedHeaderField {
+        header_field_type: QpackHeaderBlockRepresentationTypeName::IndexedHeaderField,
+        table_type,
+        index: buf.read_prefixed_int(prefix_len)?,
+        is_post_base: Some(is_post_base),
+    })
+}
+
+/// Split a header block into its representations.  This does not use the
+/// dynamic table, so it works for blocked header blocks too.
+fn parse_header_block(
+    header_block: &[u8],
+) -> Res<(QpackHeaderBlockPrefix, Vec<QpackHeaderBlockRepresentation>)> {
+    let mut buf = ReceiverBufferWrapper::new(header_block);
+    let required_insert_count = buf.read_prefixed_int(0)?;
+    let sign_bit = buf.peek()? & 0x80 != 0;
+    let delta_base = buf.read_prefixed_int(1)?;
+    let prefix = QpackHeaderBlockPrefix {
+        required_insert_count,
+        sign_bit,
+        delta_base,
+    };
+
+    let mut representations = Vec::new();
+    while !buf.done() {
+        let b = buf.peek()?;
+        let representation = if HEADER_FIELD_INDEX_STATIC.cmp_prefix(b) {
+            let len = HEADER_FIELD_INDEX_STATIC.len();
+            indexed(&mut buf, QpackTableType::Static, len, false)?
+        } else if HEADER_FIELD_INDEX_DYNAMIC.cmp_prefix(b) {
+            let len = HEADER_FIELD_INDEX_DYNAMIC.len();
+            indexed(&mut buf, QpackTableType::Dynamic, len, false)?
+        } else if HEADER_FIELD_INDEX_DYNAMIC_POST.cmp_prefix(b) {
+            let len = HEADER_FIELD_INDEX_DYNAMIC_POST.len();
+            indexed(&mut buf, QpackTableType::Dynamic, len, true)?
+        } else if HEADER_FIELD_LITERAL_NAME_LITERAL.cmp_prefix(b) {
+            let (huffman_encoded_name, name) =
+                read_literal(&mut buf, HEADER_FIELD_LITERAL_NAME_LITERAL.len())?;
+            let (huffman_encoded_value, value) = read_literal(&mut buf, 0)?;
+            QpackHeaderBlockRepresentation::LiteralHeaderFieldWithoutName {
+                header_field_type:
+                    QpackHeaderBlockRepresentationTypeName::LiteralHeaderFieldWithoutName,
+                preserve_literal: b & 0x10 != 0,
+                table_type: QpackTableType::Static,
+                name_index: 0,
+                huffman_encoded_name,
+                name_length: len(&name),
+                name: string(&name),
+                huffman_encoded_value,
+                value_length: len(&value),
+                value: string(&value),
+                is_post_base: None,
+            }
+        } else {
+            let (table_type, prefix_len, is_post_base, preserve_literal) =
+                if HEADER_FIELD_LITERAL_NAME_REF_STATIC.cmp_prefix(b) {
+                    let len = HEADER_FIELD_LITERAL_NAME_REF_STATIC.len();
+                    (QpackTableType::Static, len, false, b & 0x20 != 0)
+                } else if HEADER_FIELD_LITERAL_NAME_REF_DYNAMIC.cmp_prefix(b) {
+                    let len = HEADER_FIELD_LITERAL_NAME_REF_DYNAMIC.len();
+                    (QpackTableType::Dynamic, len, false, b & 0x20 != 0)
+                } else {
+                    debug_assert!(HEADER_FIELD_LITERAL_NAME_REF_DYNAMIC_POST.cmp_prefix(b));
+                    let len = HEADER_FIELD_LITERAL_NAME_REF_DYNAMIC_POST.len();
+                    (QpackTableType::Dynamic, len, true, b & 0x08 != 0)
+                };
+            let name_index = buf.read_prefixed_int(prefix_len)?;
+            let (huffman_encoded_value, value) = read_literal(&mut buf, 0)?;
+            QpackHeaderBlockRepresentation::LiteralHeaderFieldWithName {
+                header_field_type:
+                    QpackHeaderBlockRepresentationTypeName::LiteralHeaderFieldWithName,
+                preserve_literal,
+                table_type,
+                name_index,
+                huffman_encoded_value,
+                value_length: len(&value),
+                value: string(&value),
+                is_post_base: Some(is_post_base),
+            }
+        };
+        representations.push(representation);
+    }
+    Ok((prefix, representations))
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use qlog::events::qpack::{QpackHeaderBlockRepresentation, QpackTableType};
+
+    use super::parse_header_block;
+    use crate::header_block::HeaderEncoder;
+
+    #[test]
+    fn header_block_representations() {
+        let mut block = HeaderEncoder::new(1, true, 100);
+        block.encode_indexed_static(17);
+        block.encode_indexed_dynamic(1);
+        block.encode_literal_with_name_ref(true, 1, b"/index.html");
+        block.encode_literal_with_name_literal(b"my-header", b"my-value");
+        block.encode_header_block_prefix();
+
+        let (prefix, representations) = parse_header_block(&block).unwrap();
+        // Two entries are needed, and the base is one less than that.
+        assert_eq!(prefix.required_insert_count, 3);
+        assert!(prefix.sign_bit);
+        assert_eq!(prefix.delta_base, 0);
+        assert_eq!(representations.len(), 4);
+        assert!(matches!(
+            representations[0],
+            QpackHeaderBlockRepresentation::IndexedHeaderField {
+                table_type: QpackTableType::Static,
+                index: 17,
+                ..
+            }
+        ));
+        assert!(matches!(
+            representations[1],
+            QpackHeaderBlockRepresentation::IndexedHeaderField {
+                table_type: QpackTableType::Dynamic,
+                index: 0,
+                is_post_base: Some(true),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &representations[2],
+            QpackHeaderBlockRepresentation::LiteralHeaderFieldWithName {
+                table_type: QpackTableType::Static,
+                name_index: 1,
+                huffman_encoded_value: true,
+                value,
+                ..
+            } if value == "/index.html"
+        ));
+        assert!(matches!(
+            &representations[3],
+            QpackHeaderBlockRepresentation::LiteralHeaderFieldWithoutName {
+                name,
+                value,
+                ..
+            } if name == "my-header" && value == "my-value"
+        ));
+    }
+}
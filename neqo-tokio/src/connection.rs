@@ -0,0 +1,550 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// QUIC connections and streams.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::poll_fn,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    num::NonZeroUsize,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Instant,
+};
+
+use neqo_common::event::Provider as _;
+use neqo_transport::{
+    AppError, Connection, ConnectionEvent, ConnectionParameters, OutputBatch,
+    RandomConnectionIdGenerator, State, StreamId, StreamType,
+    server::{ConnectionRef, Server},
+};
+use neqo_udp::DatagramIter;
+use rustc_hash::FxHashMap as HashMap;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::Notify,
+};
+
+use crate::{
+    Error, Res, Verifier,
+    driver::{self, Endpoint},
+    tasks::Tasks,
+    udp::Socket,
+};
+
+/// The length of the connection IDs that clients choose.
+const CID_LEN: usize = 8;
+
+/// The address to bind to for talking to `remote`.
+pub const fn local_addr_for(remote: &SocketAddr) -> SocketAddr {
+    match remote {
+        SocketAddr::V4(..) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        SocketAddr::V6(..) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+    }
+}
+
+#[derive(Debug, Default)]
+struct ConnectionState {
+    tasks: Tasks<StreamId>,
+    /// Streams that the peer opened and that have not been accepted yet.
+    incoming: VecDeque<StreamId>,
+    /// Whether a server has handed out this connection.
+    accepted: bool,
+    stopped: bool,
+}
+
+/// A connection and the state that its handles share with the driver.
+#[derive(Debug)]
+struct Shared {
+    conn: Rc<RefCell<Connection>>,
+    state: RefCell<ConnectionState>,
+    notify: Rc<Notify>,
+    /// Checks the certificate of the server, for client connections.
+    verifier: Option<Verifier>,
+}
+
+impl Shared {
+    fn new(conn: Rc<RefCell<Connection>>, notify: Rc<Notify>, verifier: Option<Verifier>) -> Self {
+        Self {
+            conn,
+            state: RefCell::default(),
+            notify,
+            verifier,
+        }
+    }
+
+    fn handle_events(&self, now: Instant) {
+        let mut conn = self.conn.borrow_mut();
+        let mut state = self.state.borrow_mut();
+        while let Some(event) = conn.next_event() {
+            match event {
+                ConnectionEvent::AuthenticationNeeded => {
+                    if let Some(verifier) = &self.verifier {
+                        let status = verifier.verify(conn.peer_certificate());
+                        conn.authenticated(status, now);
+                    }
+                }
+                ConnectionEvent::NewStream { stream_id } => {
+                    state.incoming.push_back(stream_id);
+                    state.tasks.wake();
+                }
+                ConnectionEvent::RecvStreamReadable { stream_id }
+                | ConnectionEvent::RecvStreamReset { stream_id, .. } => {
+                    state.tasks.wake_read(&stream_id);
+                }
+                ConnectionEvent::SendStreamWritable { stream_id }
+                | ConnectionEvent::SendStreamStopSending { stream_id, .. } => {
+                    state.tasks.wake_write(&stream_id);
+                }
+                ConnectionEvent::SendStreamCreatable { .. } | ConnectionEvent::StateChange(_) => {
+                    state.tasks.wake();
+                }
+                _ => log::debug!("Ignoring event {event:?}"),
+            }
+        }
+        if conn.state().closed() {
+            state.tasks.wake_all();
+        }
+    }
+
+    fn stop(&self) {
+        let mut state = self.state.borrow_mut();
+        state.stopped = true;
+        state.tasks.wake_all();
+    }
+
+    /// Returns an error if the connection can no longer make progress.
+    fn check(&self) -> Res<()> {
+        if self.state.borrow().stopped {
+            return Err(Error::Stopped);
+        }
+        self.conn
+            .borrow()
+            .state()
+            .error()
+            .map_or(Ok(()), |e| Err(Error::Closed(e.clone())))
+    }
+
+    /// Wait with `waker` for any change, unless the connection is closed.
+    fn wait<T>(&self, waker: &Waker) -> Poll<Res<T>> {
+        if let Err(e) = self.check() {
+            return Poll::Ready(Err(e));
+        }
+        self.state.borrow_mut().tasks.wait(waker);
+        Poll::Pending
+    }
+}
+
+/// A QUIC connection.
+///
+/// The connection is closed once all handles to it and its streams are dropped.
+#[derive(Debug, Clone)]
+pub struct AsyncConnection {
+    shared: Rc<Shared>,
+}
+
+impl AsyncConnection {
+    /// Connect to `server` and complete the handshake.
+    /// The certificate of the server is accepted if `verifier` accepts it.
+    ///
+    /// # Errors
+    ///
+    /// When the socket can't be created or the handshake fails.
+    ///
+    /// # Panics
+    ///
+    /// When not called from within a [`tokio::task::LocalSet`].
+    pub async fn connect<A: AsRef<str>>(
+        server_name: &str,
+        protocols: &[A],
+        server: SocketAddr,
+        params: ConnectionParameters,
+        verifier: Verifier,
+    ) -> Res<Self> {
+        let socket = Socket::bind(local_addr_for(&server))?;
+        let conn = Connection::new_client(
+            server_name,
+            protocols,
+            Rc::new(RefCell::new(RandomConnectionIdGenerator::new(CID_LEN))),
+            socket.local_addr()?,
+            server,
+            params,
+            Instant::now(),
+        )?;
+        let shared = Rc::new(Shared::new(
+            Rc::new(RefCell::new(conn)),
+            Rc::new(Notify::new()),
+            Some(verifier),
+        ));
+        tokio::task::spawn_local(driver::run(socket, ClientDriver(Rc::clone(&shared))));
+
+        let c = Self { shared };
+        c.connected().await?;
+        Ok(c)
+    }
+
+    async fn connected(&self) -> Res<()> {
+        poll_fn(|cx| {
+            if self.shared.conn.borrow().state().connected() {
+                return Poll::Ready(Ok(()));
+            }
+            self.shared.wait(cx.waker())
+        })
+        .await
+    }
+
+    /// Open a new stream, waiting until the peer allows it.
+    ///
+    /// # Errors
+    ///
+    /// When the connection is closed.
+    pub async fn open_stream(&self, stream_type: StreamType) -> Res<Stream> {
+        poll_fn(|cx| {
+            let res = self.shared.conn.borrow_mut().stream_create(stream_type);
+            match res {
+                Ok(stream_id) => Poll::Ready(Ok(Stream::new(Rc::clone(&self.shared), stream_id))),
+                Err(neqo_transport::Error::StreamLimit) => self.shared.wait(cx.waker()),
+                Err(e) => Poll::Ready(Err(e.into())),
+            }
+        })
+        .await
+    }
+
+    /// Wait for the peer to open a stream.  Returns `None` once the connection
+    /// is closed.
+    pub async fn accept_stream(&self) -> Option<Stream> {
+        poll_fn(|cx| {
+            if let Some(stream_id) = self.shared.state.borrow_mut().incoming.pop_front() {
+                return Poll::Ready(Some(Stream::new(Rc::clone(&self.shared), stream_id)));
+            }
+            self.shared.wait::<()>(cx.waker()).map(|_| None)
+        })
+        .await
+    }
+
+    /// Close the connection.
+    pub fn close(&self, error: AppError, msg: &str) {
+        self.shared
+            .conn
+            .borrow_mut()
+            .close(Instant::now(), error, msg);
+        self.shared.notify.notify_one();
+    }
+
+    #[must_use]
+    pub fn state(&self) -> State {
+        self.shared.conn.borrow().state().clone()
+    }
+}
+
+/// Runs the connection of an [`AsyncConnection`] that is a client.
+struct ClientDriver(Rc<Shared>);
+
+impl Endpoint for ClientDriver {
+    fn process(
+        &self,
+        dgrams: Option<DatagramIter<'_>>,
+        now: Instant,
+        max_datagrams: NonZeroUsize,
+    ) -> OutputBatch {
+        let mut conn = self.0.conn.borrow_mut();
+        if let Some(dgrams) = dgrams {
+            conn.process_multiple_input(dgrams, now);
+        }
+        conn.process_multiple_output(now, max_datagrams)
+    }
+
+    fn handle_events(&self, now: Instant) -> bool {
+        self.0.handle_events(now);
+        let mut conn = self.0.conn.borrow_mut();
+        if Rc::strong_count(&self.0) == 1 && !conn.state().closed() {
+            conn.close(now, 0, "");
+        }
+        matches!(conn.state(), State::Closed(_))
+    }
+
+    fn has_events(&self) -> bool {
+        self.0.conn.borrow().has_events()
+    }
+
+    fn notify(&self) -> &Notify {
+        &self.0.notify
+    }
+
+    fn fail(&self, _error: &io::Error) {
+        self.0.stop();
+    }
+}
+
+/// The connections that are not yet accepted from an [`AsyncServer`].
+#[derive(Debug, Default)]
+struct AcceptQueue {
+    connections: VecDeque<AsyncConnection>,
+    tasks: Tasks<()>,
+    stopped: bool,
+}
+
+struct ServerShared {
+    server: RefCell<Server>,
+    connections: RefCell<HashMap<ConnectionRef, Rc<Shared>>>,
+    accept: RefCell<AcceptQueue>,
+    notify: Rc<Notify>,
+}
+
+/// A QUIC server that accepts connections.
+///
+/// The server stops once this handle and all the connections that it accepted
+/// are dropped.
+pub struct AsyncServer {
+    shared: Rc<ServerShared>,
+    local_addr: SocketAddr,
+}
+
+impl AsyncServer {
+    /// Run `server` on a socket that is bound to `addr`.
+    ///
+    /// # Errors
+    ///
+    /// When the socket can't be created.
+    ///
+    /// # Panics
+    ///
+    /// When not called from within a [`tokio::task::LocalSet`].
+    pub fn bind(addr: SocketAddr, server: Server) -> Res<Self> {
+        let socket = Socket::bind(addr)?;
+        let local_addr = socket.local_addr()?;
+        let shared = Rc::new(ServerShared {
+            server: RefCell::new(server),
+            connections: RefCell::default(),
+            accept: RefCell::default(),
+            notify: Rc::new(Notify::new()),
+        });
+        tokio::task::spawn_local(driver::run(socket, ServerDriver(Rc::clone(&shared))));
+        Ok(Self { shared, local_addr })
+    }
+
+    #[must_use]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Wait for a connection that completed the handshake.  Returns `None` if
+    /// the server stopped.
+    pub async fn accept(&self) -> Option<AsyncConnection> {
+        poll_fn(|cx| {
+            let mut accept = self.shared.accept.borrow_mut();
+            if let Some(c) = accept.connections.pop_front() {
+                return Poll::Ready(Some(c));
+            }
+            if accept.stopped {
+                return Poll::Ready(None);
+            }
+            accept.tasks.wait(cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Runs the [`Server`] of an [`AsyncServer`].
+struct ServerDriver(Rc<ServerShared>);
+
+impl Endpoint for ServerDriver {
+    fn process(
+        &self,
+        dgrams: Option<DatagramIter<'_>>,
+        now: Instant,
+        max_datagrams: NonZeroUsize,
+    ) -> OutputBatch {
+        self.0.server.borrow_mut().process_multiple(
+            dgrams.into_iter().flatten(),
+            now,
+            max_datagrams,
+        )
+    }
+
+    fn handle_events(&self, now: Instant) -> bool {
+        let mut connections = self.0.connections.borrow_mut();
+        let mut accept = self.0.accept.borrow_mut();
+        #[expect(
+            clippy::iter_over_hash_type,
+            reason = "OK to loop over active connections in an undefined order."
+        )]
+        for c in self.0.server.borrow().active_connections() {
+            connections.entry(c.clone()).or_insert_with(|| {
+                Rc::new(Shared::new(c.connection(), Rc::clone(&self.0.notify), None))
+            });
+        }
+        connections.retain(|_, shared| {
+            shared.handle_events(now);
+            let mut conn = shared.conn.borrow_mut();
+            let mut state = shared.state.borrow_mut();
+            if conn.state().connected() && !state.accepted {
+                state.accepted = true;
+                accept.connections.push_back(AsyncConnection {
+                    shared: Rc::clone(shared),
+                });
+                accept.tasks.wake();
+            } else if state.accepted && Rc::strong_count(shared) == 1 && !conn.state().closed() {
+                conn.close(now, 0, "");
+            }
+            !matches!(conn.state(), State::Closed(_))
+        });
+        Rc::strong_count(&self.0) == 1 && connections.is_empty()
+    }
+
+    fn has_events(&self) -> bool {
+        self.0.server.borrow().has_active_connections()
+    }
+
+    fn notify(&self) -> &Notify {
+        &self.0.notify
+    }
+
+    fn fail(&self, _error: &io::Error) {
+        self.0
+            .connections
+            .borrow()
+            .values()
+            .for_each(|shared| shared.stop());
+        let mut accept = self.0.accept.borrow_mut();
+        accept.stopped = true;
+        accept.tasks.wake_all();
+    }
+}
+
+/// A QUIC stream.  Streams that are only for sending can't be read from, and
+/// streams that are only for receiving can't be written to.
+#[derive(Debug)]
+pub struct Stream {
+    shared: Rc<Shared>,
+    id: StreamId,
+    fin: bool,
+}
+
+impl Stream {
+    const fn new(shared: Rc<Shared>, id: StreamId) -> Self {
+        Self {
+            shared,
+            id,
+            fin: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn stream_id(&self) -> StreamId {
+        self.id
+    }
+
+    /// Abandon sending on the stream.
+    ///
+    /// # Errors
+    ///
+    /// When the stream does not exist anymore.
+    pub fn reset(&self, error: AppError) -> Res<()> {
+        self.shared
+            .conn
+            .borrow_mut()
+            .stream_reset_send(self.id, error)?;
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+
+    /// Ask the peer to stop sending on the stream.
+    ///
+    /// # Errors
+    ///
+    /// When the stream does not exist anymore.
+    pub fn stop_sending(&self, error: AppError) -> Res<()> {
+        self.shared
+            .conn
+            .borrow_mut()
+            .stream_stop_sending(self.id, error)?;
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.fin || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        let res = self
+            .shared
+            .conn
+            .borrow_mut()
+            .stream_recv(self.id, buf.initialize_unfilled());
+        match res {
+            Ok((0, false)) => {
+                self.shared.check()?;
+                self.shared
+                    .state
+                    .borrow_mut()
+                    .tasks
+                    .wait_read(self.id, cx.waker());
+                Poll::Pending
+            }
+            Ok((n, fin)) => {
+                buf.advance(n);
+                self.fin = fin;
+                // Reading might have made room for the peer to send more.
+                self.shared.notify.notify_one();
+                Poll::Ready(Ok(()))
+            }
+            Err(e) => Poll::Ready(Err(Error::from(e).into())),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let res = self.shared.conn.borrow_mut().stream_send(self.id, buf);
+        match res {
+            Ok(0) => {
+                self.shared.check()?;
+                self.shared
+                    .state
+                    .borrow_mut()
+                    .tasks
+                    .wait_write(self.id, cx.waker());
+                Poll::Pending
+            }
+            Ok(n) => {
+                self.shared.notify.notify_one();
+                Poll::Ready(Ok(n))
+            }
+            Err(e) => Poll::Ready(Err(Error::from(e).into())),
+        }
+    }
+
+    /// Data is handed to the connection as soon as it is written, so there is
+    /// nothing to flush.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let res = self.shared.conn.borrow_mut().stream_close_send(self.id);
+        self.shared.notify.notify_one();
+        Poll::Ready(res.map_err(|e| Error::from(e).into()))
+    }
+}
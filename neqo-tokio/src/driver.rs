@@ -0,0 +1,130 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// The task that moves datagrams between a socket and an endpoint.
+
+use std::{
+    future,
+    io::{self, ErrorKind},
+    num::NonZeroUsize,
+    time::{Duration, Instant},
+};
+
+use neqo_transport::OutputBatch;
+use neqo_udp::{DatagramIter, RecvBuf};
+use tokio::sync::Notify;
+
+use crate::udp::Socket;
+
+/// A client or server that a driver runs.
+///
+/// The driver and the handles that the application holds share the endpoint,
+/// so all methods take `&self`.
+pub trait Endpoint {
+    /// Process incoming datagrams, if any, and return the next output.
+    fn process(
+        &self,
+        dgrams: Option<DatagramIter<'_>>,
+        now: Instant,
+        max_datagrams: NonZeroUsize,
+    ) -> OutputBatch;
+
+    /// Handle the events of the endpoint, waking the tasks that wait for them.
+    /// Returns `true` when the endpoint is done and the driver can stop.
+    fn handle_events(&self, now: Instant) -> bool;
+
+    /// Whether the endpoint has events that [`Endpoint::handle_events`] has not
+    /// handled yet.
+    fn has_events(&self) -> bool;
+
+    /// Handles notify this when they have done something that might produce
+    /// output.
+    fn notify(&self) -> &Notify;
+
+    /// The driver stopped, because of an error on the socket.
+    fn fail(&self, error: &io::Error);
+}
+
+/// Run `endpoint` on `socket` until the endpoint is done.
+pub async fn run<E: Endpoint>(socket: Socket, endpoint: E) {
+    if let Err(e) = drive(&socket, &endpoint).await {
+        log::error!("Driver stopped: {e}");
+        endpoint.fail(&e);
+    }
+}
+
+async fn drive<E: Endpoint>(socket: &Socket, endpoint: &E) -> io::Result<()> {
+    let local_addr = socket.local_addr()?;
+    let max_datagrams = NonZeroUsize::new(socket.max_gso_segments())
+        .ok_or_else(|| io::Error::from(ErrorKind::Unsupported))?;
+    let mut recv_buf = RecvBuf::default();
+
+    loop {
+        let done = endpoint.handle_events(Instant::now());
+        let timeout = process_output(socket, endpoint, None, max_datagrams).await?;
+        if done {
+            return Ok(());
+        }
+        if endpoint.has_events() {
+            continue;
+        }
+
+        let timer = async {
+            match timeout {
+                Some(t) => tokio::time::sleep(t).await,
+                None => future::pending().await,
+            }
+        };
+        tokio::select! {
+            r = socket.readable() => {
+                r?;
+                while let Some(dgrams) = socket.recv(local_addr, &mut recv_buf)? {
+                    process_output(socket, endpoint, Some(dgrams), max_datagrams).await?;
+                }
+            }
+            () = timer => {}
+            () = endpoint.notify().notified() => {}
+        }
+    }
+}
+
+/// Pass `dgrams` to the endpoint and send everything that it produces.
+/// Returns the time until the endpoint next needs to be called.
+async fn process_output<E: Endpoint>(
+    socket: &Socket,
+    endpoint: &E,
+    mut dgrams: Option<DatagramIter<'_>>,
+    max_datagrams: NonZeroUsize,
+) -> io::Result<Option<Duration>> {
+    loop {
+        match endpoint.process(dgrams.take(), Instant::now(), max_datagrams) {
+            OutputBatch::DatagramBatch(dgram) => loop {
+                // Optimistically attempt sending datagram. In case the OS
+                // buffer is full, wait till socket is writable then try
+                // again.
+                match socket.send(&dgram) {
+                    Ok(()) => break,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        socket.writable().await?;
+                    }
+                    Err(e) if e.raw_os_error() == Some(libc::EIO) && dgram.num_datagrams() > 1 => {
+                        log::info!(
+                            "`libc::sendmsg` failed with {e}; quinn-udp will halt segmentation offload"
+                        );
+                        // Drop the packets and let QUIC handle retransmission.
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            },
+            OutputBatch::Callback(timeout) => {
+                log::debug!("Setting timeout of {timeout:?}");
+                return Ok(Some(timeout));
+            }
+            OutputBatch::None => return Ok(None),
+        }
+    }
+}
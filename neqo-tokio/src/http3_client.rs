@@ -0,0 +1,476 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// HTTP/3 clients and their requests.
+
+use std::{
+    cell::RefCell,
+    future::poll_fn,
+    io,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Instant,
+};
+
+use neqo_common::{event::Provider as _, header::Header};
+use neqo_http3::{
+    Http3Client, Http3ClientEvent, Http3Parameters, Http3State, Priority, RequestTarget,
+};
+use neqo_transport::{AppError, OutputBatch, RandomConnectionIdGenerator, StreamId};
+use neqo_udp::DatagramIter;
+use rustc_hash::FxHashMap as HashMap;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::Notify,
+};
+
+use crate::{
+    Error, Res, Verifier,
+    connection::local_addr_for,
+    driver::{self, Endpoint},
+    tasks::Tasks,
+    udp::Socket,
+};
+
+/// The length of the connection IDs that clients choose.
+const CID_LEN: usize = 8;
+
+#[derive(Debug, Default)]
+struct Response {
+    headers: Option<Vec<Header>>,
+    /// Whether the response headers arrived, even if they were taken.
+    headers_received: bool,
+    /// Whether the response ended with the headers.
+    fin: bool,
+    reset: Option<AppError>,
+}
+
+#[derive(Debug, Default)]
+struct ClientState {
+    tasks: Tasks<StreamId>,
+    responses: HashMap<StreamId, Response>,
+    stopped: bool,
+}
+
+struct Shared {
+    client: RefCell<Http3Client>,
+    state: RefCell<ClientState>,
+    notify: Notify,
+    verifier: Verifier,
+}
+
+impl Shared {
+    fn handle_events(&self, now: Instant) {
+        let mut client = self.client.borrow_mut();
+        let mut state = self.state.borrow_mut();
+        while let Some(event) = client.next_event() {
+            match event {
+                Http3ClientEvent::AuthenticationNeeded => {
+                    let status = self.verifier.verify(client.peer_certificate());
+                    client.authenticated(status, now);
+                }
+                Http3ClientEvent::HeaderReady {
+                    stream_id,
+                    headers,
+                    interim,
+                    fin,
+                } => {
+                    if !interim {
+                        let response = state.responses.entry(stream_id).or_default();
+                        response.headers = Some(headers);
+                        response.headers_received = true;
+                        response.fin = fin;
+                    }
+                    state.tasks.wake_read(&stream_id);
+                }
+                Http3ClientEvent::DataReadable { stream_id } => {
+                    state.tasks.wake_read(&stream_id);
+                }
+                Http3ClientEvent::DataWritable { stream_id }
+                | Http3ClientEvent::StopSending { stream_id, .. } => {
+                    state.tasks.wake_write(&stream_id);
+                }
+                Http3ClientEvent::Reset {
+                    stream_id, error, ..
+                } => {
+                    state.responses.entry(stream_id).or_default().reset = Some(error);
+                    state.tasks.wake_stream(&stream_id);
+                }
+                Http3ClientEvent::RequestsCreatable
+                | Http3ClientEvent::GoawayReceived
+                | Http3ClientEvent::StateChange(_) => {
+                    state.tasks.wake();
+                }
+                _ => log::debug!("Ignoring event {event:?}"),
+            }
+        }
+        if matches!(
+            client.state(),
+            Http3State::Closing(_) | Http3State::Closed(_)
+        ) {
+            state.tasks.wake_all();
+        }
+    }
+
+    /// Returns an error if the client can no longer make progress.
+    fn check(&self) -> Res<()> {
+        if self.state.borrow().stopped {
+            return Err(Error::Stopped);
+        }
+        match self.client.borrow().state() {
+            Http3State::Closing(e) | Http3State::Closed(e) => Err(Error::Closed(e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wait with `waker` for any change, unless the client is closed.
+    fn wait<T>(&self, waker: &Waker) -> Poll<Res<T>> {
+        if let Err(e) = self.check() {
+            return Poll::Ready(Err(e));
+        }
+        self.state.borrow_mut().tasks.wait(waker);
+        Poll::Pending
+    }
+}
+
+/// An HTTP/3 client.
+///
+/// The connection is closed once all handles to it and its requests are dropped.
+#[derive(Clone)]
+pub struct AsyncHttp3Client {
+    shared: Rc<Shared>,
+}
+
+impl AsyncHttp3Client {
+    /// Connect to `server` and complete the handshake.
+    /// The certificate of the server is accepted if `verifier` accepts it.
+    ///
+    /// # Errors
+    ///
+    /// When the socket can't be created or the handshake fails.
+    ///
+    /// # Panics
+    ///
+    /// When not called from within a [`tokio::task::LocalSet`].
+    pub async fn connect(
+        server_name: &str,
+        server: SocketAddr,
+        params: Http3Parameters,
+        verifier: Verifier,
+    ) -> Res<Self> {
+        let socket = Socket::bind(local_addr_for(&server))?;
+        let client = Http3Client::new(
+            server_name,
+            Rc::new(RefCell::new(RandomConnectionIdGenerator::new(CID_LEN))),
+            socket.local_addr()?,
+            server,
+            params,
+            Instant::now(),
+        )?;
+        let shared = Rc::new(Shared {
+            client: RefCell::new(client),
+            state: RefCell::default(),
+            notify: Notify::new(),
+            verifier,
+        });
+        tokio::task::spawn_local(driver::run(socket, ClientDriver(Rc::clone(&shared))));
+
+        let c = Self { shared };
+        c.connected().await?;
+        Ok(c)
+    }
+
+    async fn connected(&self) -> Res<()> {
+        poll_fn(|cx| {
+            if self.shared.client.borrow().state() == Http3State::Connected {
+                return Poll::Ready(Ok(()));
+            }
+            self.shared.wait(cx.waker())
+        })
+        .await
+    }
+
+    /// Send a request, waiting until the server allows another one.  The
+    /// request body is written to the returned [`Request`], which must then
+    /// be shut down.
+    ///
+    /// # Errors
+    ///
+    /// When the request is invalid or the connection is closed.
+    pub async fn fetch<T: RequestTarget + Copy>(
+        &self,
+        method: &str,
+        target: T,
+        headers: &[Header],
+    ) -> Res<Request> {
+        let stream_id = poll_fn(|cx| {
+            let res = self.shared.client.borrow_mut().fetch(
+                Instant::now(),
+                method,
+                target,
+                headers,
+                Priority::default(),
+            );
+            match res {
+                Ok(stream_id) => Poll::Ready(Ok(stream_id)),
+                Err(neqo_http3::Error::StreamLimit) => self.shared.wait(cx.waker()),
+                Err(e) => Poll::Ready(Err(e.into())),
+            }
+        })
+        .await?;
+        self.shared.notify.notify_one();
+        Ok(Request {
+            shared: Rc::clone(&self.shared),
+            stream_id,
+            done: false,
+        })
+    }
+
+    /// Close the connection.
+    pub fn close(&self, error: AppError, msg: &str) {
+        self.shared
+            .client
+            .borrow_mut()
+            .close(Instant::now(), error, msg);
+        self.shared.notify.notify_one();
+    }
+
+    #[must_use]
+    pub fn state(&self) -> Http3State {
+        self.shared.client.borrow().state()
+    }
+}
+
+/// Runs the connection of an [`AsyncHttp3Client`].
+struct ClientDriver(Rc<Shared>);
+
+impl Endpoint for ClientDriver {
+    fn process(
+        &self,
+        dgrams: Option<DatagramIter<'_>>,
+        now: Instant,
+        max_datagrams: NonZeroUsize,
+    ) -> OutputBatch {
+        let mut client = self.0.client.borrow_mut();
+        if let Some(dgrams) = dgrams {
+            client.process_multiple_input(dgrams, now);
+        }
+        client.process_multiple_output(now, max_datagrams)
+    }
+
+    fn handle_events(&self, now: Instant) -> bool {
+        self.0.handle_events(now);
+        let mut client = self.0.client.borrow_mut();
+        if Rc::strong_count(&self.0) == 1 && client.state().active() {
+            client.close(now, 0, "");
+        }
+        matches!(client.state(), Http3State::Closed(_))
+    }
+
+    fn has_events(&self) -> bool {
+        self.0.client.borrow().has_events()
+    }
+
+    fn notify(&self) -> &Notify {
+        &self.0.notify
+    }
+
+    fn fail(&self, _error: &io::Error) {
+        let mut state = self.0.state.borrow_mut();
+        state.stopped = true;
+        state.tasks.wake_all();
+    }
+}
+
+/// A request that an [`AsyncHttp3Client`] sent.
+///
+/// The request body is written to this, and the response body is read from it.
+/// A request that is dropped before its response was read is cancelled.
+pub struct Request {
+    shared: Rc<Shared>,
+    stream_id: StreamId,
+    /// Whether the whole response was read.
+    done: bool,
+}
+
+impl Request {
+    #[must_use]
+    pub const fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Wait for the response headers.  Interim responses are skipped.
+    ///
+    /// # Errors
+    ///
+    /// When the stream is reset, the connection is closed, or the headers were
+    /// already taken.
+    pub async fn response(&self) -> Res<Vec<Header>> {
+        poll_fn(|cx| {
+            let mut state = self.shared.state.borrow_mut();
+            let response = state.responses.entry(self.stream_id).or_default();
+            if let Some(headers) = response.headers.take() {
+                return Poll::Ready(Ok(headers));
+            }
+            if let Some(error) = response.reset {
+                return Poll::Ready(Err(Error::Reset(error)));
+            }
+            if response.headers_received {
+                return Poll::Ready(Err(neqo_http3::Error::InvalidInput.into()));
+            }
+            state.tasks.wait_read(self.stream_id, cx.waker());
+            drop(state);
+            self.shared.check()?;
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Cancel the request.
+    ///
+    /// # Errors
+    ///
+    /// When the stream does not exist anymore.
+    pub fn cancel(mut self, error: AppError) -> Res<()> {
+        self.done = true;
+        self.shared
+            .client
+            .borrow_mut()
+            .cancel_fetch(self.stream_id, error)?;
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        self.shared
+            .state
+            .borrow_mut()
+            .responses
+            .remove(&self.stream_id);
+        if !self.done {
+            let mut client = self.shared.client.borrow_mut();
+            if client.state().active() {
+                drop(client.cancel_fetch(
+                    self.stream_id,
+                    neqo_http3::Error::HttpRequestCancelled.code(),
+                ));
+                self.shared.notify.notify_one();
+            }
+        }
+    }
+}
+
+impl AsyncRead for Request {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.done || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        let mut state = self.shared.state.borrow_mut();
+        let response = state.responses.entry(self.stream_id).or_default();
+        if let Some(error) = response.reset {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                Error::Reset(error),
+            )));
+        }
+        if !response.headers_received {
+            state.tasks.wait_read(self.stream_id, cx.waker());
+            drop(state);
+            self.shared.check()?;
+            return Poll::Pending;
+        }
+        if response.fin {
+            drop(state);
+            self.done = true;
+            return Poll::Ready(Ok(()));
+        }
+        drop(state);
+
+        let res = self.shared.client.borrow_mut().read_data(
+            Instant::now(),
+            self.stream_id,
+            buf.initialize_unfilled(),
+        );
+        match res {
+            Ok((0, false)) => {
+                self.shared.check()?;
+                self.shared
+                    .state
+                    .borrow_mut()
+                    .tasks
+                    .wait_read(self.stream_id, cx.waker());
+                Poll::Pending
+            }
+            Ok((n, fin)) => {
+                buf.advance(n);
+                self.done = fin;
+                // Reading might have made room for the server to send more.
+                self.shared.notify.notify_one();
+                Poll::Ready(Ok(()))
+            }
+            Err(e) => Poll::Ready(Err(Error::from(e).into())),
+        }
+    }
+}
+
+impl AsyncWrite for Request {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let res = self
+            .shared
+            .client
+            .borrow_mut()
+            .send_data(self.stream_id, buf, Instant::now());
+        match res {
+            Ok(0) => {
+                self.shared.check()?;
+                self.shared
+                    .state
+                    .borrow_mut()
+                    .tasks
+                    .wait_write(self.stream_id, cx.waker());
+                Poll::Pending
+            }
+            Ok(n) => {
+                self.shared.notify.notify_one();
+                Poll::Ready(Ok(n))
+            }
+            Err(e) => Poll::Ready(Err(Error::from(e).into())),
+        }
+    }
+
+    /// Data is handed to the connection as soon as it is written, so there is
+    /// nothing to flush.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// End the request body.
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let res = self
+            .shared
+            .client
+            .borrow_mut()
+            .stream_close_send(self.stream_id, Instant::now());
+        self.shared.notify.notify_one();
+        Poll::Ready(res.map_err(|e| Error::from(e).into()))
+    }
+}
@@ -0,0 +1,317 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// HTTP/3 servers and the requests that they receive.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::poll_fn,
+    io,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use neqo_common::header::Header;
+use neqo_http3::{Http3OrWebTransportStream, Http3Server, Http3ServerEvent};
+use neqo_transport::{AppError, OutputBatch, StreamId};
+use neqo_udp::DatagramIter;
+use rustc_hash::FxHashMap as HashMap;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::Notify,
+};
+
+use crate::{
+    Error, Res,
+    driver::{self, Endpoint},
+    tasks::Tasks,
+    udp::Socket,
+};
+
+/// The part of a request body that was received but not read yet.
+#[derive(Debug, Default)]
+struct Body {
+    data: VecDeque<u8>,
+    fin: bool,
+    reset: Option<AppError>,
+}
+
+#[derive(Debug, Default)]
+struct ServerState {
+    tasks: Tasks<Http3OrWebTransportStream>,
+    /// Requests that have not been accepted yet.
+    requests: VecDeque<(Http3OrWebTransportStream, Vec<Header>)>,
+    bodies: HashMap<Http3OrWebTransportStream, Body>,
+    stopped: bool,
+}
+
+struct Shared {
+    server: RefCell<Http3Server>,
+    state: RefCell<ServerState>,
+    notify: Notify,
+}
+
+/// An HTTP/3 server that accepts requests.
+///
+/// The server stops once this handle and all the requests that it accepted
+/// are dropped.
+pub struct AsyncHttp3Server {
+    shared: Rc<Shared>,
+    local_addr: SocketAddr,
+}
+
+impl AsyncHttp3Server {
+    /// Run `server` on a socket that is bound to `addr`.
+    ///
+    /// # Errors
+    ///
+    /// When the socket can't be created.
+    ///
+    /// # Panics
+    ///
+    /// When not called from within a [`tokio::task::LocalSet`].
+    pub fn bind(addr: SocketAddr, server: Http3Server) -> Res<Self> {
+        let socket = Socket::bind(addr)?;
+        let local_addr = socket.local_addr()?;
+        let shared = Rc::new(Shared {
+            server: RefCell::new(server),
+            state: RefCell::default(),
+            notify: Notify::new(),
+        });
+        tokio::task::spawn_local(driver::run(socket, ServerDriver(Rc::clone(&shared))));
+        Ok(Self { shared, local_addr })
+    }
+
+    #[must_use]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Wait for a request.  Returns `None` if the server stopped.
+    pub async fn accept(&self) -> Option<IncomingRequest> {
+        poll_fn(|cx| {
+            let mut state = self.shared.state.borrow_mut();
+            if let Some((stream, headers)) = state.requests.pop_front() {
+                return Poll::Ready(Some(IncomingRequest {
+                    shared: Rc::clone(&self.shared),
+                    stream,
+                    headers,
+                }));
+            }
+            if state.stopped {
+                return Poll::Ready(None);
+            }
+            state.tasks.wait(cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Runs the [`Http3Server`] of an [`AsyncHttp3Server`].
+struct ServerDriver(Rc<Shared>);
+
+impl Endpoint for ServerDriver {
+    fn process(
+        &self,
+        dgrams: Option<DatagramIter<'_>>,
+        now: Instant,
+        max_datagrams: NonZeroUsize,
+    ) -> OutputBatch {
+        self.0.server.borrow_mut().process_multiple(
+            dgrams.into_iter().flatten(),
+            now,
+            max_datagrams,
+        )
+    }
+
+    fn handle_events(&self, _now: Instant) -> bool {
+        let server = self.0.server.borrow();
+        let mut state = self.0.state.borrow_mut();
+        while let Some(event) = server.next_event() {
+            match event {
+                Http3ServerEvent::Headers {
+                    stream,
+                    headers,
+                    fin,
+                } => {
+                    state.bodies.insert(
+                        stream.clone(),
+                        Body {
+                            fin,
+                            ..Body::default()
+                        },
+                    );
+                    state.requests.push_back((stream, headers));
+                    state.tasks.wake();
+                }
+                Http3ServerEvent::Data { stream, data, fin } => {
+                    if let Some(body) = state.bodies.get_mut(&stream) {
+                        body.data.extend(data);
+                        body.fin |= fin;
+                        state.tasks.wake_read(&stream);
+                    }
+                }
+                Http3ServerEvent::DataWritable { stream }
+                | Http3ServerEvent::StreamStopSending { stream, .. } => {
+                    state.tasks.wake_write(&stream);
+                }
+                Http3ServerEvent::StreamReset { stream, error } => {
+                    if let Some(body) = state.bodies.get_mut(&stream) {
+                        body.reset = Some(error);
+                    }
+                    state.tasks.wake_stream(&stream);
+                }
+                _ => log::debug!("Ignoring event {event:?}"),
+            }
+        }
+        Rc::strong_count(&self.0) == 1
+    }
+
+    fn has_events(&self) -> bool {
+        self.0.server.borrow().has_events()
+    }
+
+    fn notify(&self) -> &Notify {
+        &self.0.notify
+    }
+
+    fn fail(&self, _error: &io::Error) {
+        let mut state = self.0.state.borrow_mut();
+        state.stopped = true;
+        state.tasks.wake_all();
+    }
+}
+
+/// A request that an [`AsyncHttp3Server`] received.
+///
+/// The request body is read from this.  The response body is written to it
+/// after [`IncomingRequest::send_response`], and then it must be shut down.
+pub struct IncomingRequest {
+    shared: Rc<Shared>,
+    stream: Http3OrWebTransportStream,
+    headers: Vec<Header>,
+}
+
+impl IncomingRequest {
+    #[must_use]
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    #[must_use]
+    pub fn stream_id(&self) -> StreamId {
+        self.stream.stream_id()
+    }
+
+    /// Send the response headers.
+    ///
+    /// # Errors
+    ///
+    /// When the headers were already sent or the stream does not exist anymore.
+    pub fn send_response(&self, headers: &[Header]) -> Res<()> {
+        self.stream.send_headers(headers)?;
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+
+    /// Abandon the request.
+    ///
+    /// # Errors
+    ///
+    /// When the stream does not exist anymore.
+    pub fn cancel(&self, error: AppError) -> Res<()> {
+        self.stream.cancel_fetch(error)?;
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+
+    fn stopped(&self) -> bool {
+        self.shared.state.borrow().stopped
+    }
+}
+
+impl Drop for IncomingRequest {
+    fn drop(&mut self) {
+        self.shared.state.borrow_mut().bodies.remove(&self.stream);
+    }
+}
+
+impl AsyncRead for IncomingRequest {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut state = self.shared.state.borrow_mut();
+        let body = state.bodies.entry(self.stream.clone()).or_default();
+        if !body.data.is_empty() {
+            let n = buf.remaining().min(body.data.len());
+            let data = body.data.drain(..n).collect::<Vec<_>>();
+            buf.put_slice(&data);
+            return Poll::Ready(Ok(()));
+        }
+        if let Some(error) = body.reset {
+            return Poll::Ready(Err(Error::Reset(error).into()));
+        }
+        if body.fin || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        if state.stopped {
+            return Poll::Ready(Err(Error::Stopped.into()));
+        }
+        state.tasks.wait_read(self.stream.clone(), cx.waker());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for IncomingRequest {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        match self.stream.send_data(buf, Instant::now()) {
+            Ok(0) => {
+                if self.stopped() {
+                    return Poll::Ready(Err(Error::Stopped.into()));
+                }
+                self.shared
+                    .state
+                    .borrow_mut()
+                    .tasks
+                    .wait_write(self.stream.clone(), cx.waker());
+                Poll::Pending
+            }
+            Ok(n) => {
+                self.shared.notify.notify_one();
+                Poll::Ready(Ok(n))
+            }
+            Err(e) => Poll::Ready(Err(Error::from(e).into())),
+        }
+    }
+
+    /// Data is handed to the connection as soon as it is written, so there is
+    /// nothing to flush.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// End the response body.
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let res = self.stream.stream_close_send(Instant::now());
+        self.shared.notify.notify_one();
+        Poll::Ready(res.map_err(|e| Error::from(e).into()))
+    }
+}
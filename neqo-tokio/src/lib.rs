@@ -0,0 +1,117 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An asynchronous interface to Neqo, built on [`tokio`] and [`neqo_udp`].
+//!
+//! Each endpoint owns a UDP socket and a driver task that moves datagrams
+//! between the socket and the Neqo state machine and runs its timers.  Neqo
+//! types are not `Send`, so the driver is started with
+//! [`tokio::task::spawn_local`].  Endpoints must therefore be created from
+//! within a [`tokio::task::LocalSet`].
+//!
+//! Streams implement [`tokio::io::AsyncRead`] and [`tokio::io::AsyncWrite`].
+//!
+//! Clients pass the certificate of the server to a [`Verifier`], which
+//! decides whether to accept it.
+
+#![cfg_attr(coverage_nightly, feature(coverage_attribute))]
+#![expect(
+    clippy::future_not_send,
+    reason = "Neqo types are not Send, everything runs on a LocalSet."
+)]
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    io,
+};
+
+use neqo_crypto::{AuthenticationStatus, agent::CertificateInfo};
+use neqo_transport::{AppError, CloseReason};
+use thiserror::Error;
+
+mod connection;
+mod driver;
+mod http3_client;
+mod http3_server;
+mod tasks;
+pub mod udp;
+
+pub use connection::{AsyncConnection, AsyncServer, Stream};
+pub use http3_client::{AsyncHttp3Client, Request};
+pub use http3_server::{AsyncHttp3Server, IncomingRequest};
+
+/// Checks the certificate that a server presents.
+pub struct Verifier(VerifierKind);
+
+enum VerifierKind {
+    Callback(Box<dyn Fn(&CertificateInfo) -> AuthenticationStatus>),
+    AcceptAny,
+}
+
+impl Verifier {
+    /// Pass the certificate of the server to `f`, which decides whether to
+    /// accept it.  The server name is not passed in, so `f` needs to capture
+    /// it if it checks it, for example with [`CertificateInfo::verify_name`].
+    pub fn new<F: Fn(&CertificateInfo) -> AuthenticationStatus + 'static>(f: F) -> Self {
+        Self(VerifierKind::Callback(Box::new(f)))
+    }
+
+    /// Accept any certificate.
+    ///
+    /// Anyone who can intercept the traffic of a client that uses this can
+    /// impersonate the server.  Only use it for testing.
+    #[must_use]
+    pub const fn insecure_accept_any() -> Self {
+        Self(VerifierKind::AcceptAny)
+    }
+
+    fn verify(&self, cert: Option<CertificateInfo>) -> AuthenticationStatus {
+        match &self.0 {
+            VerifierKind::Callback(f) => {
+                cert.map_or(AuthenticationStatus::CertUntrusted, |cert| f(&cert))
+            }
+            VerifierKind::AcceptAny => AuthenticationStatus::Ok,
+        }
+    }
+}
+
+impl Debug for Verifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            VerifierKind::Callback(_) => write!(f, "Verifier"),
+            VerifierKind::AcceptAny => write!(f, "Verifier(insecure)"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Transport(#[from] neqo_transport::Error),
+    #[error(transparent)]
+    Http3(#[from] neqo_http3::Error),
+    #[error("stream reset with error {0}")]
+    Reset(AppError),
+    #[error("connection closed: {0:?}")]
+    Closed(CloseReason),
+    #[error("the driver has stopped")]
+    Stopped,
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(e) => e,
+            Error::Closed(_) | Error::Stopped => Self::new(io::ErrorKind::NotConnected, err),
+            Error::Reset(_) => Self::new(io::ErrorKind::ConnectionReset, err),
+            Error::Transport(_) | Error::Http3(_) => Self::other(err),
+        }
+    }
+}
+
+pub type Res<T> = Result<T, Error>;
@@ -0,0 +1,87 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Tasks that wait for the driver to make progress.
+
+use std::{hash::Hash, mem, task::Waker};
+
+use rustc_hash::FxHashMap as HashMap;
+
+/// The tasks that wait to read from or write to a stream.
+#[derive(Debug, Default)]
+struct StreamTasks {
+    read: Option<Waker>,
+    write: Option<Waker>,
+}
+
+/// Keeps the wakers of tasks that wait on an endpoint, so that the driver
+/// can wake them when an event arrives.  Streams are identified by `K`.
+#[derive(Debug)]
+pub struct Tasks<K> {
+    streams: HashMap<K, StreamTasks>,
+    other: Vec<Waker>,
+}
+
+impl<K> Default for Tasks<K> {
+    fn default() -> Self {
+        Self {
+            streams: HashMap::default(),
+            other: Vec::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> Tasks<K> {
+    /// Wait until stream `id` is readable.
+    pub fn wait_read(&mut self, id: K, waker: &Waker) {
+        self.streams.entry(id).or_default().read = Some(waker.clone());
+    }
+
+    /// Wait until stream `id` is writable.
+    pub fn wait_write(&mut self, id: K, waker: &Waker) {
+        self.streams.entry(id).or_default().write = Some(waker.clone());
+    }
+
+    /// Wait for any change that is not specific to a stream.
+    pub fn wait(&mut self, waker: &Waker) {
+        if !self.other.iter().any(|w| w.will_wake(waker)) {
+            self.other.push(waker.clone());
+        }
+    }
+
+    pub fn wake_read(&mut self, id: &K) {
+        if let Some(w) = self.streams.get_mut(id).and_then(|t| t.read.take()) {
+            w.wake();
+        }
+    }
+
+    pub fn wake_write(&mut self, id: &K) {
+        if let Some(w) = self.streams.get_mut(id).and_then(|t| t.write.take()) {
+            w.wake();
+        }
+    }
+
+    /// Wake both readers and writers of stream `id`, and forget about it.
+    pub fn wake_stream(&mut self, id: &K) {
+        if let Some(t) = self.streams.remove(id) {
+            t.read.into_iter().chain(t.write).for_each(Waker::wake);
+        }
+    }
+
+    /// Wake the tasks that wait for changes that are not specific to a stream.
+    pub fn wake(&mut self) {
+        mem::take(&mut self.other).into_iter().for_each(Waker::wake);
+    }
+
+    /// Wake every task, for instance after the connection closed.
+    pub fn wake_all(&mut self) {
+        self.wake();
+        mem::take(&mut self.streams)
+            .into_values()
+            .flat_map(|t| t.read.into_iter().chain(t.write))
+            .for_each(Waker::wake);
+    }
+}
@@ -8,7 +8,7 @@
 
 use std::{io, net::SocketAddr};
 
-use neqo_common::{datagram, qdebug};
+use neqo_common::datagram;
 use neqo_udp::{DatagramIter, RecvBuf};
 
 /// Ideally this would live in [`neqo_udp`]. [`neqo_udp`] is used in Firefox.
@@ -36,11 +36,11 @@ impl Socket {
         // if send_buf_before < ONE_MB {
         //     state.set_send_buffer_size((&socket).into(), ONE_MB)?;
         //     let send_buf_after = state.send_buffer_size((&socket).into())?;
-        //     qdebug!("Increasing socket send buffer size from {send_buf_before} to {ONE_MB}, now:
+        //     log::debug!("Increasing socket send buffer size from {send_buf_before} to {ONE_MB}, now:
         // {send_buf_after}"); } else {
-        //     qdebug!("Default socket send buffer size is {send_buf_before}, not changing");
+        //     log::debug!("Default socket send buffer size is {send_buf_before}, not changing");
         // }
-        qdebug!(
+        log::debug!(
             "Default socket send buffer size is {:?}",
             state.send_buffer_size((&socket).into())
         );
@@ -50,12 +50,12 @@ impl Socket {
             // Same as Firefox.
             // <https://searchfox.org/mozilla-central/rev/fa5b44a4ea5c98b6a15f39638ea4cd04dc271f3d/modules/libpref/init/StaticPrefList.yaml#13474-13477>
             state.set_recv_buffer_size((&socket).into(), ONE_MB)?;
-            qdebug!(
+            log::debug!(
                 "Increasing socket recv buffer size from {recv_buf_before} to {ONE_MB}, now: {:?}",
                 state.recv_buffer_size((&socket).into())
             );
         } else {
-            qdebug!("Default socket receive buffer size is {recv_buf_before}, not changing");
+            log::debug!("Default socket receive buffer size is {recv_buf_before}, not changing");
         }
 
         Ok(Self {
@@ -0,0 +1,307 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg(test)]
+
+use std::{
+    cell::RefCell,
+    net::{Ipv4Addr, SocketAddr},
+    rc::Rc,
+    time::Instant,
+};
+
+use neqo_common::header::{Header, HeadersExt as _};
+use neqo_crypto::{AllowZeroRtt, AuthenticationStatus};
+use neqo_http3::{Http3Parameters, Http3Server};
+use neqo_tokio::{
+    AsyncConnection, AsyncHttp3Client, AsyncHttp3Server, AsyncServer, Error, Verifier,
+};
+use neqo_transport::{
+    ConnectionParameters, RandomConnectionIdGenerator, StreamType, server::Server,
+};
+use test_fixture::{
+    DEFAULT_ALPN, DEFAULT_ALPN_H3, DEFAULT_KEYS, DEFAULT_SERVER_NAME, anti_replay, fixture_init,
+};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    task::{LocalSet, spawn_local},
+};
+
+/// Enough data to need more than the initial flow control credit.
+const LARGE: usize = 3 << 20;
+
+fn localhost() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::LOCALHOST, 0))
+}
+
+fn body(len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| u8::try_from(i % 251).expect("fits"))
+        .collect()
+}
+
+fn run_local<F: Future<Output = ()>>(f: F) {
+    fixture_init();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("build runtime");
+    LocalSet::new().block_on(&rt, f);
+}
+
+fn transport_server() -> AsyncServer {
+    let server = Server::new(
+        Instant::now(),
+        DEFAULT_KEYS,
+        DEFAULT_ALPN,
+        anti_replay(),
+        Box::new(AllowZeroRtt {}),
+        Rc::new(RefCell::new(RandomConnectionIdGenerator::new(8))),
+        ConnectionParameters::default(),
+    )
+    .expect("create server");
+    AsyncServer::bind(localhost(), server).expect("bind")
+}
+
+fn h3_server() -> AsyncHttp3Server {
+    let server = Http3Server::new(
+        Instant::now(),
+        DEFAULT_KEYS,
+        DEFAULT_ALPN_H3,
+        anti_replay(),
+        Rc::new(RefCell::new(RandomConnectionIdGenerator::new(8))),
+        Http3Parameters::default(),
+        None,
+    )
+    .expect("create server");
+    AsyncHttp3Server::bind(localhost(), server).expect("bind")
+}
+
+#[test]
+fn transport_echo() {
+    run_local(async {
+        let server = transport_server();
+        let addr = server.local_addr();
+        let echo = spawn_local(async move {
+            let conn = server.accept().await.expect("a connection");
+            let mut stream = conn.accept_stream().await.expect("a stream");
+            let mut data = Vec::new();
+            stream.read_to_end(&mut data).await.expect("read");
+            stream.write_all(&data).await.expect("write");
+            stream.shutdown().await.expect("shutdown");
+            // Keep the connection until the client is done with it.
+            conn.accept_stream().await;
+        });
+
+        let client = AsyncConnection::connect(
+            DEFAULT_SERVER_NAME,
+            DEFAULT_ALPN,
+            addr,
+            ConnectionParameters::default(),
+            Verifier::insecure_accept_any(),
+        )
+        .await
+        .expect("connect");
+        let mut stream = client
+            .open_stream(StreamType::BiDi)
+            .await
+            .expect("open stream");
+        let sent = body(LARGE);
+        stream.write_all(&sent).await.expect("write");
+        stream.shutdown().await.expect("shutdown");
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.expect("read");
+        assert_eq!(received, sent);
+
+        client.close(0, "");
+        echo.await.expect("server task");
+    });
+}
+
+#[test]
+fn transport_server_stream() {
+    run_local(async {
+        let server = transport_server();
+        let addr = server.local_addr();
+        spawn_local(async move {
+            let conn = server.accept().await.expect("a connection");
+            let mut stream = conn
+                .open_stream(StreamType::UniDi)
+                .await
+                .expect("open stream");
+            stream.write_all(b"hello").await.expect("write");
+            stream.shutdown().await.expect("shutdown");
+            conn.accept_stream().await;
+        });
+
+        let client = AsyncConnection::connect(
+            DEFAULT_SERVER_NAME,
+            DEFAULT_ALPN,
+            addr,
+            ConnectionParameters::default(),
+            Verifier::insecure_accept_any(),
+        )
+        .await
+        .expect("connect");
+        let mut stream = client.accept_stream().await.expect("a stream");
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.expect("read");
+        assert_eq!(received, b"hello");
+    });
+}
+
+#[test]
+fn http3_post() {
+    run_local(async {
+        let server = h3_server();
+        let addr = server.local_addr();
+        spawn_local(async move {
+            while let Some(mut request) = server.accept().await {
+                assert!(request.headers().contains_header(":method", "POST"));
+                let mut data = Vec::new();
+                request.read_to_end(&mut data).await.expect("read");
+                request
+                    .send_response(&[Header::new(":status", "200")])
+                    .expect("send response");
+                request.write_all(&data).await.expect("write");
+                request.shutdown().await.expect("shutdown");
+            }
+        });
+
+        let client = AsyncHttp3Client::connect(
+            DEFAULT_SERVER_NAME,
+            addr,
+            Http3Parameters::default(),
+            Verifier::insecure_accept_any(),
+        )
+        .await
+        .expect("connect");
+        let mut request = client
+            .fetch("POST", ("https", DEFAULT_SERVER_NAME, "/echo"), &[])
+            .await
+            .expect("fetch");
+        let sent = body(LARGE);
+        request.write_all(&sent).await.expect("write");
+        request.shutdown().await.expect("shutdown");
+        let headers = request.response().await.expect("response");
+        assert!(headers.contains_header(":status", "200"));
+        let mut received = Vec::new();
+        request.read_to_end(&mut received).await.expect("read");
+        assert_eq!(received, sent);
+    });
+}
+
+#[test]
+fn http3_concurrent_requests() {
+    const REQUESTS: usize = 10;
+
+    run_local(async {
+        let server = h3_server();
+        let addr = server.local_addr();
+        spawn_local(async move {
+            while let Some(mut request) = server.accept().await {
+                let path = request
+                    .headers()
+                    .find_header(":path")
+                    .map(|h| h.value().to_vec())
+                    .expect("a path");
+                spawn_local(async move {
+                    request
+                        .send_response(&[Header::new(":status", "200")])
+                        .expect("send response");
+                    request.write_all(&path).await.expect("write");
+                    request.shutdown().await.expect("shutdown");
+                });
+            }
+        });
+
+        let client = AsyncHttp3Client::connect(
+            DEFAULT_SERVER_NAME,
+            addr,
+            Http3Parameters::default(),
+            Verifier::insecure_accept_any(),
+        )
+        .await
+        .expect("connect");
+        let mut tasks = Vec::new();
+        for i in 0..REQUESTS {
+            let client = client.clone();
+            tasks.push(spawn_local(async move {
+                let path = format!("/{i}");
+                let mut request = client
+                    .fetch("GET", ("https", DEFAULT_SERVER_NAME, path.as_str()), &[])
+                    .await
+                    .expect("fetch");
+                request.shutdown().await.expect("shutdown");
+                request.response().await.expect("response");
+                let mut received = Vec::new();
+                request.read_to_end(&mut received).await.expect("read");
+                assert_eq!(received, path.as_bytes());
+            }));
+        }
+        for t in tasks {
+            t.await.expect("client task");
+        }
+    });
+}
+
+#[test]
+fn verify_certificate() {
+    run_local(async {
+        let server = h3_server();
+        let addr = server.local_addr();
+        spawn_local(async move { while server.accept().await.is_some() {} });
+
+        let client = AsyncHttp3Client::connect(
+            DEFAULT_SERVER_NAME,
+            addr,
+            Http3Parameters::default(),
+            Verifier::new(|cert| {
+                if cert.verify_name(DEFAULT_SERVER_NAME) {
+                    AuthenticationStatus::Ok
+                } else {
+                    AuthenticationStatus::CertSubjectInvalid
+                }
+            }),
+        )
+        .await;
+        assert!(client.is_ok());
+
+        let client = AsyncHttp3Client::connect(
+            "example.com",
+            addr,
+            Http3Parameters::default(),
+            Verifier::new(|cert| {
+                if cert.verify_name("example.com") {
+                    AuthenticationStatus::Ok
+                } else {
+                    AuthenticationStatus::CertSubjectInvalid
+                }
+            }),
+        )
+        .await;
+        assert!(matches!(client, Err(Error::Closed(_))));
+    });
+}
+
+#[test]
+fn reject_certificate() {
+    run_local(async {
+        let server = transport_server();
+        let addr = server.local_addr();
+        spawn_local(async move { while server.accept().await.is_some() {} });
+
+        let client = AsyncConnection::connect(
+            DEFAULT_SERVER_NAME,
+            DEFAULT_ALPN,
+            addr,
+            ConnectionParameters::default(),
+            Verifier::new(|_| AuthenticationStatus::CertUntrusted),
+        )
+        .await;
+        assert!(matches!(client, Err(Error::Closed(_))));
+    });
+}
@@ -219,9 +219,35 @@ impl AddressValidation {
         now: Instant,
     ) -> AddressValidationResult {
         qtrace!("AddressValidation {self:p}: validate {:?}", self.validation);
+        self.validate_inner(
+            token,
+            peer_address,
+            self.validation == ValidateAddress::Never,
+            now,
+        )
+    }
+
+    /// As `validate()`, but with validation required at least for clients
+    /// without a valid `NEW_TOKEN` token, even if the policy is `Never`.
+    pub fn validate_under_load(
+        &self,
+        token: &[u8],
+        peer_address: SocketAddr,
+        now: Instant,
+    ) -> AddressValidationResult {
+        qtrace!("AddressValidation {self:p}: validate under load");
+        self.validate_inner(token, peer_address, false, now)
+    }
 
+    fn validate_inner(
+        &self,
+        token: &[u8],
+        peer_address: SocketAddr,
+        never: bool,
+        now: Instant,
+    ) -> AddressValidationResult {
         if token.is_empty() {
-            if self.validation == ValidateAddress::Never {
+            if never {
                 qinfo!("AddressValidation: no token; accepting");
                 return AddressValidationResult::Pass;
             }
@@ -268,7 +294,7 @@ impl AddressValidation {
                     // If this looked like a Retry, treat it as being bad.
                     qinfo!("AddressValidation: invalid Retry token; rejecting");
                     AddressValidationResult::Invalid
-                } else if self.validation == ValidateAddress::Never {
+                } else if never {
                     // We don't require validation, so OK.
                     qinfo!("AddressValidation: invalid NEW_TOKEN token; accepting");
                     AddressValidationResult::Pass
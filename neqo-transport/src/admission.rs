@@ -0,0 +1,353 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Deciding whether a server takes on new connections.
+
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use neqo_common::qdebug;
+use rustc_hash::FxHashMap as HashMap;
+
+/// The number of source prefixes that are tracked for rate limiting.  When
+/// this is reached, prefixes that are back to a full burst are forgotten.  If
+/// that doesn't free up space, new connections are refused.
+const MAX_PREFIXES: usize = 1 << 16;
+
+/// A limit on how quickly new connections are accepted from each source
+/// prefix.  This is a token bucket: each prefix can open `burst` connections
+/// at once, and gets `per_second` more each second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    interval: Duration,
+    burst: u32,
+    ipv4_prefix: u8,
+    ipv6_prefix: u8,
+}
+
+impl RateLimit {
+    /// # Panics
+    ///
+    /// When `per_second` or `burst` is zero.
+    #[must_use]
+    pub const fn new(per_second: u32, burst: u32) -> Self {
+        assert!(per_second > 0 && burst > 0, "rate limit must be positive");
+        Self {
+            interval: Duration::from_secs(1)
+                .checked_div(per_second)
+                .expect("non-zero"),
+            burst,
+            ipv4_prefix: 32,
+            ipv6_prefix: 64,
+        }
+    }
+
+    /// Set how many leading bits of the source address identify a prefix.
+    /// The default is 32 for IPv4 and 64 for IPv6.
+    #[must_use]
+    pub fn prefix_lengths(mut self, ipv4: u8, ipv6: u8) -> Self {
+        self.ipv4_prefix = ipv4.min(32);
+        self.ipv6_prefix = ipv6.min(128);
+        self
+    }
+
+    fn prefix(&self, addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V4(a) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.ipv4_prefix));
+                IpAddr::V4(Ipv4Addr::from(u32::from(a) & mask.unwrap_or(0)))
+            }
+            IpAddr::V6(a) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.ipv6_prefix));
+                IpAddr::V6(Ipv6Addr::from(u128::from(a) & mask.unwrap_or(0)))
+            }
+        }
+    }
+}
+
+/// Limits on the new connections that a server accepts.
+///
+/// By default, there are no limits.  As the number of connections grows,
+/// a server can first require address validation with Retry, see
+/// [`AdmissionControl::retry_above`], and then refuse new connections
+/// outright, see [`AdmissionControl::max_connections`].
+#[derive(Debug, Clone, Default)]
+pub struct AdmissionControl {
+    max_connections: Option<usize>,
+    retry_threshold: Option<usize>,
+    rate_limit: Option<RateLimit>,
+}
+
+impl AdmissionControl {
+    /// Refuse new connections with `CONNECTION_REFUSED` while `max`
+    /// connections are open.
+    #[must_use]
+    pub const fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Send a Retry to clients that have not validated their address while
+    /// `threshold` connections are open.  This applies regardless of the
+    /// address validation policy of the server.
+    #[must_use]
+    pub const fn retry_above(mut self, threshold: usize) -> Self {
+        self.retry_threshold = Some(threshold);
+        self
+    }
+
+    /// Refuse new connections with `CONNECTION_REFUSED` from source prefixes
+    /// that exceed `limit`.  Clients that are asked to Retry only count once
+    /// they come back with a valid token.
+    #[must_use]
+    pub const fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+}
+
+/// Counts of the connection attempts that admission control turned away.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AdmissionStats {
+    /// Refused because the server had the maximum number of connections.
+    pub refused_full: usize,
+    /// Refused because the source prefix exceeded its rate limit.
+    pub refused_rate: usize,
+    /// Sent a Retry because the server was under load.
+    pub retried: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    Accept,
+    Refuse,
+}
+
+#[derive(Debug, Default)]
+pub struct Admitter {
+    control: AdmissionControl,
+    /// For each source prefix, the time at which its bucket is full again.
+    buckets: HashMap<IpAddr, Instant>,
+    /// Each prefix in `buckets`, in order of when to check whether it can be
+    /// forgotten.  The bucket of a prefix is full again at most one burst
+    /// after it was last used, so these times only increase.
+    expiry: VecDeque<(Instant, IpAddr)>,
+    stats: AdmissionStats,
+}
+
+impl Admitter {
+    pub fn set_control(&mut self, control: AdmissionControl) {
+        self.control = control;
+        self.buckets.clear();
+        self.expiry.clear();
+    }
+
+    pub const fn stats(&self) -> AdmissionStats {
+        self.stats
+    }
+
+    /// Whether a new connection has to be refused because there are already
+    /// `active` connections.
+    pub fn full(&mut self, active: usize) -> bool {
+        let full = self
+            .control
+            .max_connections
+            .is_some_and(|max| active >= max);
+        if full {
+            qdebug!("Admission: {active} connections, refusing");
+            self.stats.refused_full += 1;
+        }
+        full
+    }
+
+    /// Whether clients need to validate their address because there are
+    /// `active` connections.
+    pub fn under_load(&self, active: usize) -> bool {
+        self.control
+            .retry_threshold
+            .is_some_and(|threshold| active >= threshold)
+    }
+
+    pub const fn retried(&mut self) {
+        self.stats.retried += 1;
+    }
+
+    /// Decide whether to accept a connection from `source`, taking a token
+    /// from its bucket if so.
+    pub fn admit(&mut self, source: SocketAddr, now: Instant) -> Admission {
+        let Some(limit) = self.control.rate_limit else {
+            return Admission::Accept;
+        };
+        // The bucket is empty once it is `burst` intervals from full.
+        let capacity = limit.interval * limit.burst;
+        self.expire(capacity, now);
+
+        let prefix = limit.prefix(source.ip());
+        if self.buckets.len() >= MAX_PREFIXES && !self.buckets.contains_key(&prefix) {
+            qdebug!("Admission: too many prefixes, refusing {prefix}");
+            self.stats.refused_rate += 1;
+            return Admission::Refuse;
+        }
+
+        let full_at = self.buckets.get(&prefix).map_or(now, |t| (*t).max(now));
+        let next = full_at + limit.interval;
+        if next.saturating_duration_since(now) > capacity {
+            qdebug!("Admission: rate limit exceeded for {prefix}, refusing");
+            self.stats.refused_rate += 1;
+            return Admission::Refuse;
+        }
+        if self.buckets.insert(prefix, next).is_none() {
+            self.expiry.push_back((now + capacity, prefix));
+        }
+        Admission::Accept
+    }
+
+    /// Forget the prefixes whose buckets are full again.  Prefixes that were
+    /// used since they were queued are checked again one burst from `now`.
+    fn expire(&mut self, capacity: Duration, now: Instant) {
+        while let Some(&(check_at, prefix)) = self.expiry.front() {
+            if check_at > now {
+                break;
+            }
+            self.expiry.pop_front();
+            if self
+                .buckets
+                .get(&prefix)
+                .is_some_and(|full_at| *full_at > now)
+            {
+                self.expiry.push_back((now + capacity, prefix));
+            } else {
+                self.buckets.remove(&prefix);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+        time::Duration,
+    };
+
+    use test_fixture::now;
+
+    use super::{Admission, AdmissionControl, Admitter, MAX_PREFIXES, RateLimit};
+
+    fn addr(ip: [u8; 4]) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::from(ip)), 443)
+    }
+
+    fn admitter(control: AdmissionControl) -> Admitter {
+        let mut a = Admitter::default();
+        a.set_control(control);
+        a
+    }
+
+    #[test]
+    fn unlimited() {
+        let mut a = Admitter::default();
+        for _ in 0..1000 {
+            assert!(!a.full(usize::MAX - 1));
+            assert_eq!(a.admit(addr([192, 0, 2, 1]), now()), Admission::Accept);
+        }
+        assert!(!a.under_load(usize::MAX - 1));
+        assert_eq!(a.stats().refused_full + a.stats().refused_rate, 0);
+    }
+
+    #[test]
+    fn max_connections() {
+        let mut a = admitter(AdmissionControl::default().max_connections(2));
+        assert!(!a.full(1));
+        assert!(a.full(2));
+        assert_eq!(a.stats().refused_full, 1);
+    }
+
+    #[test]
+    fn retry_above() {
+        let a = admitter(AdmissionControl::default().retry_above(5));
+        assert!(!a.under_load(4));
+        assert!(a.under_load(5));
+    }
+
+    #[test]
+    fn token_bucket() {
+        let mut a = admitter(AdmissionControl::default().rate_limit(RateLimit::new(10, 3)));
+        let src = addr([192, 0, 2, 1]);
+        let mut t = now();
+        for _ in 0..3 {
+            assert_eq!(a.admit(src, t), Admission::Accept);
+        }
+        assert_eq!(a.admit(src, t), Admission::Refuse);
+        // Other prefixes have their own buckets.
+        assert_eq!(a.admit(addr([192, 0, 2, 2]), t), Admission::Accept);
+
+        // One more token after 100ms.
+        t += Duration::from_millis(100);
+        assert_eq!(a.admit(src, t), Admission::Accept);
+        assert_eq!(a.admit(src, t), Admission::Refuse);
+
+        // A full bucket after a long wait, but no more.
+        t += Duration::from_secs(10);
+        for _ in 0..3 {
+            assert_eq!(a.admit(src, t), Admission::Accept);
+        }
+        assert_eq!(a.admit(src, t), Admission::Refuse);
+        assert_eq!(a.stats().refused_rate, 3);
+    }
+
+    #[test]
+    fn prefixes() {
+        let limit = RateLimit::new(1, 1).prefix_lengths(24, 48);
+        let mut a = admitter(AdmissionControl::default().rate_limit(limit));
+        assert_eq!(a.admit(addr([192, 0, 2, 1]), now()), Admission::Accept);
+        assert_eq!(a.admit(addr([192, 0, 2, 200]), now()), Admission::Refuse);
+        assert_eq!(a.admit(addr([192, 0, 3, 1]), now()), Admission::Accept);
+
+        let v6 = |last| {
+            SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, last)),
+                443,
+            )
+        };
+        assert_eq!(a.admit(v6(1), now()), Admission::Accept);
+        assert_eq!(a.admit(v6(2), now()), Admission::Refuse);
+    }
+
+    #[test]
+    fn forget_prefixes() {
+        let mut a = admitter(AdmissionControl::default().rate_limit(RateLimit::new(10, 2)));
+        let v4 = |i: usize| addr(u32::try_from(i).unwrap().to_be_bytes());
+        let mut t = now();
+        for i in 0..MAX_PREFIXES {
+            assert_eq!(a.admit(v4(i), t), Admission::Accept);
+        }
+        // The table is full, but prefixes that are already there are tracked.
+        assert_eq!(a.admit(v4(MAX_PREFIXES), t), Admission::Refuse);
+        assert_eq!(a.admit(v4(0), t), Admission::Accept);
+        assert_eq!(a.stats().refused_rate, 1);
+
+        t += Duration::from_millis(150);
+        assert_eq!(a.admit(v4(0), t), Admission::Accept);
+
+        // Once their buckets are full again, prefixes are forgotten, except
+        // for the one that was used since.
+        t += Duration::from_millis(100);
+        assert_eq!(a.admit(v4(MAX_PREFIXES), t), Admission::Accept);
+        assert_eq!(a.buckets.len(), 2);
+        assert_eq!(a.expiry.len(), 2);
+        assert_eq!(a.admit(v4(0), t), Admission::Accept);
+        assert_eq!(a.admit(v4(0), t), Admission::Refuse);
+
+        t += Duration::from_secs(1);
+        assert_eq!(a.admit(v4(1), t), Admission::Accept);
+        assert_eq!(a.buckets.len(), 1);
+    }
+}
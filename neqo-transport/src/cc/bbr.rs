@@ -0,0 +1,1130 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! BBR congestion control (draft-ietf-ccwg-bbr)
+//!
+//! This is a model-based congestion controller: rather than reacting to loss, it
+//! estimates the bottleneck bandwidth and the minimum round-trip time of the path
+//! and sends at (a multiple of) the resulting rate. This implements `BBRv3`, which
+//! also bounds the amount of data in flight in response to loss and ECN-CE marks.
+//!
+//! See <https://datatracker.ietf.org/doc/html/draft-ietf-ccwg-bbr>.
+
+use std::{
+    cmp::{max, min},
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+
+use neqo_common::{qdebug, qinfo, qlog::Qlog, qtrace};
+use rustc_hash::FxHashMap as HashMap;
+
+use super::{CongestionControl, CongestionEvent, classic_cc::cwnd_initial};
+use crate::{
+    Pmtud, packet, qlog, recovery::sent, rtt::RttEstimate, sender::PACING_BURST_SIZE,
+    stats::CongestionControlStats,
+};
+
+/// Gains are expressed in percent.
+const GAIN_UNIT: usize = 100;
+/// The pacing gain used in Startup, `4 * ln(2)`, which allows the sending rate to
+/// double every round trip.
+const STARTUP_PACING_GAIN: usize = 277;
+/// The pacing gain used in Drain, which drains the queue built up in Startup.
+const DRAIN_PACING_GAIN: usize = 35;
+/// The congestion window gain used outside of `ProbeBW_UP` and `ProbeRTT`.
+const DEFAULT_CWND_GAIN: usize = 200;
+/// The congestion window gain used in `ProbeBW_UP`.
+const PROBE_UP_CWND_GAIN: usize = 225;
+/// The congestion window gain used in `ProbeRTT`.
+const PROBE_RTT_CWND_GAIN: usize = 50;
+/// Pace at slightly below the estimated bandwidth to avoid building a queue.
+const PACING_MARGIN_PERCENT: usize = 1;
+/// The multiplicative decrease applied to the lower bounds on a congestion event.
+const BETA: usize = 70;
+/// The maximum tolerated loss rate, in percent, before deeming the volume of data in
+/// flight to be too high.
+const LOSS_THRESH: usize = 2;
+/// The fraction of `inflight_hi` that is left unused to make room for other flows.
+const HEADROOM: usize = 15;
+/// The bandwidth needs to grow by this much per round to not be considered at a plateau.
+const FULL_BW_THRESH: usize = 125;
+/// The number of non-app-limited rounds without growth after which the pipe is full.
+const FULL_BW_COUNT: usize = 3;
+/// The number of losses in a round that, together with a high loss rate, end `Startup`.
+const STARTUP_FULL_LOSS_CNT: usize = 6;
+/// The minimum congestion window, in packets.
+const MIN_PIPE_CWND_PKTS: usize = 4;
+/// How long a minimum RTT sample is valid for.
+const MIN_RTT_FILTER_LEN: Duration = Duration::from_secs(10);
+/// How often to enter `ProbeRTT` to refresh the minimum RTT.
+const PROBE_RTT_INTERVAL: Duration = Duration::from_secs(5);
+/// How long to stay in `ProbeRTT`.
+const PROBE_RTT_DURATION: Duration = Duration::from_millis(200);
+/// The minimum time between probing for bandwidth; up to a second is added to this.
+const PROBE_BW_WAIT_BASE: Duration = Duration::from_secs(2);
+/// The maximum number of rounds between probing for bandwidth, which bounds the time
+/// it takes to probe when sharing the bottleneck with Reno flows.
+const MAX_RENO_ROUNDS: usize = 63;
+
+/// Scale `v` by `gain` percent.
+const fn scale(v: usize, gain: usize) -> usize {
+    v.saturating_mul(gain) / GAIN_UNIT
+}
+
+/// The delivery rate, in bytes per second, of `bytes` delivered over `interval`.
+fn rate(bytes: usize, interval: Duration) -> usize {
+    let nanos = interval.as_nanos();
+    if nanos == 0 {
+        return 0;
+    }
+    u128::try_from(bytes)
+        .expect("usize fits into u128")
+        .saturating_mul(1_000_000_000)
+        .checked_div(nanos)
+        .and_then(|r| usize::try_from(r).ok())
+        .unwrap_or(usize::MAX)
+}
+
+/// The number of bytes that `bw`, in bytes per second, delivers in `rtt`.
+fn volume(bw: usize, rtt: Duration) -> usize {
+    usize::try_from(
+        u128::try_from(bw)
+            .expect("usize fits into u128")
+            .saturating_mul(rtt.as_nanos())
+            / 1_000_000_000,
+    )
+    .unwrap_or(usize::MAX)
+}
+
+/// The pacing rate, in bytes per second, for a bandwidth of `bw` and a pacing gain
+/// of `gain` percent, less a small margin.  This is at least one datagram of `mtu`
+/// bytes every `min_rtt`, so that small estimates don't round down to zero.
+pub(super) fn paced_rate(bw: usize, gain: usize, mtu: usize, min_rtt: Option<Duration>) -> usize {
+    let floor = min_rtt.map_or(mtu, |min_rtt| rate(mtu, min_rtt)).max(1);
+    scale(scale(bw, gain), GAIN_UNIT - PACING_MARGIN_PERCENT).max(floor)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Rapidly grow the sending rate to find the available bandwidth.
+    Startup,
+    /// Drain the queue created during `Startup`.
+    Drain,
+    /// Slow down to leave headroom and drain any queue created while probing.
+    ProbeBwDown,
+    /// Cruise at the estimated bandwidth.
+    ProbeBwCruise,
+    /// Refill the pipe after the lower bounds were reset, before probing.
+    ProbeBwRefill,
+    /// Probe for more bandwidth.
+    ProbeBwUp,
+    /// Reduce the amount of data in flight to measure the minimum RTT.
+    ProbeRtt,
+}
+
+impl Mode {
+    const fn pacing_gain(self) -> usize {
+        match self {
+            Self::Startup => STARTUP_PACING_GAIN,
+            Self::Drain => DRAIN_PACING_GAIN,
+            Self::ProbeBwDown => 90,
+            Self::ProbeBwUp => 125,
+            Self::ProbeBwCruise | Self::ProbeBwRefill | Self::ProbeRtt => GAIN_UNIT,
+        }
+    }
+
+    const fn cwnd_gain(self) -> usize {
+        match self {
+            Self::ProbeBwUp => PROBE_UP_CWND_GAIN,
+            Self::ProbeRtt => PROBE_RTT_CWND_GAIN,
+            _ => DEFAULT_CWND_GAIN,
+        }
+    }
+
+    const fn is_probe_bw(self) -> bool {
+        matches!(
+            self,
+            Self::ProbeBwDown | Self::ProbeBwCruise | Self::ProbeBwRefill | Self::ProbeBwUp
+        )
+    }
+
+    /// Whether this mode deliberately sends faster than the estimated bandwidth.
+    const fn is_probing_bw(self) -> bool {
+        matches!(self, Self::Startup | Self::ProbeBwRefill | Self::ProbeBwUp)
+    }
+
+    const fn to_qlog(self) -> &'static str {
+        match self {
+            Self::Startup => "startup",
+            Self::Drain => "drain",
+            Self::ProbeBwDown => "probe_bw_down",
+            Self::ProbeBwCruise => "probe_bw_cruise",
+            Self::ProbeBwRefill => "probe_bw_refill",
+            Self::ProbeBwUp => "probe_bw_up",
+            Self::ProbeRtt => "probe_rtt",
+        }
+    }
+}
+
+/// The delivery state of the connection at the time a packet was sent.
+#[derive(Debug)]
+struct SentState {
+    /// The total number of bytes delivered.
+    delivered: usize,
+    /// The time at which `delivered` was last updated.
+    delivered_time: Instant,
+    /// The send time of the packet that was most recently acknowledged.
+    first_sent_time: Instant,
+    /// Whether the connection was application-limited.
+    app_limited: bool,
+    /// The number of bytes in flight, including this packet.
+    tx_in_flight: usize,
+    /// The total number of bytes lost.
+    lost: usize,
+}
+
+/// A delivery rate sample, derived from an acknowledgment.
+#[derive(Debug, Default)]
+struct RateSample {
+    /// The delivery rate, in bytes per second, or zero if the sample is invalid.
+    delivery_rate: usize,
+    /// Whether the sample was taken while application-limited.
+    is_app_limited: bool,
+    /// The number of bytes delivered over the sampling interval.
+    delivered: usize,
+    /// The value of `Bbr::delivered` when the sampled packet was sent.
+    prior_delivered: usize,
+    /// The number of bytes in flight when the sampled packet was sent.
+    tx_in_flight: usize,
+    /// The number of bytes lost since the sampled packet was sent.
+    lost: usize,
+    /// The number of bytes newly acknowledged.
+    newly_acked: usize,
+}
+
+#[derive(Debug)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "These track independent parts of the BBR state machine."
+)]
+pub struct Bbr {
+    pmtud: Pmtud,
+    qlog: Qlog,
+    mode: Mode,
+    congestion_window: usize,
+    /// The congestion window to restore when leaving `ProbeRTT`.
+    prior_cwnd: usize,
+    pacing_rate: Option<usize>,
+    bytes_in_flight: usize,
+    /// Delivery state for packets in flight, keyed by `(packet::Number, packet::Type)` to
+    /// identify packets across packet number spaces.
+    sent: HashMap<(packet::Number, packet::Type), SentState>,
+
+    // Delivery rate estimation.
+    delivered: usize,
+    delivered_time: Option<Instant>,
+    first_sent_time: Option<Instant>,
+    lost: usize,
+    /// When set, the connection is application-limited until `delivered` exceeds this.
+    app_limited_until: Option<usize>,
+
+    // Round counting.
+    next_round_delivered: usize,
+    round_start: bool,
+    rounds_since_bw_probe: usize,
+
+    // The bandwidth model.
+    /// The maximum bandwidth over the current and previous bandwidth probing cycle.
+    bw_hi: [usize; 2],
+    /// The lower bound on the bandwidth, reduced on congestion.
+    bw_lo: Option<usize>,
+    /// The bandwidth used by the model, `min(max_bw, bw_lo)`.
+    bw: usize,
+    /// The upper bound on the volume of data in flight, learned from loss while probing.
+    inflight_hi: Option<usize>,
+    /// The lower bound on the volume of data in flight, reduced on congestion.
+    inflight_lo: Option<usize>,
+    bw_latest: usize,
+    inflight_latest: usize,
+    min_rtt: Option<Duration>,
+    min_rtt_stamp: Option<Instant>,
+    probe_rtt_min_delay: Option<Duration>,
+    probe_rtt_min_stamp: Option<Instant>,
+    probe_rtt_expired: bool,
+    probe_rtt_done_stamp: Option<Instant>,
+    probe_rtt_round_done: bool,
+
+    // Congestion signals.
+    loss_round_delivered: usize,
+    loss_round_start: bool,
+    /// The type of the last congestion event in this round, if any.
+    congestion_in_round: Option<CongestionEvent>,
+    loss_events_in_round: usize,
+    startup_high_loss: bool,
+
+    // Startup and bandwidth probing.
+    full_bw: usize,
+    full_bw_count: usize,
+    full_bw_now: bool,
+    filled_pipe: bool,
+    cycle_stamp: Option<Instant>,
+    bw_probe_wait: Duration,
+    bw_probe_samples: bool,
+    bw_probe_up_rounds: u32,
+    bw_probe_up_acks: usize,
+    probe_up_cnt: usize,
+}
+
+impl Display for Bbr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Bbr [mode: {:?}, bw: {}, min_rtt: {:?}, cwnd: {}, bif: {}]",
+            self.mode, self.bw, self.min_rtt, self.congestion_window, self.bytes_in_flight
+        )
+    }
+}
+
+impl Bbr {
+    #[must_use]
+    pub fn new(pmtud: Pmtud) -> Self {
+        let cwnd = cwnd_initial(pmtud.plpmtu());
+        Self {
+            pmtud,
+            qlog: Qlog::disabled(),
+            mode: Mode::Startup,
+            congestion_window: cwnd,
+            prior_cwnd: cwnd,
+            pacing_rate: None,
+            bytes_in_flight: 0,
+            sent: HashMap::default(),
+            delivered: 0,
+            delivered_time: None,
+            first_sent_time: None,
+            lost: 0,
+            app_limited_until: None,
+            next_round_delivered: 0,
+            round_start: false,
+            rounds_since_bw_probe: 0,
+            bw_hi: [0; 2],
+            bw_lo: None,
+            bw: 0,
+            inflight_hi: None,
+            inflight_lo: None,
+            bw_latest: 0,
+            inflight_latest: 0,
+            min_rtt: None,
+            min_rtt_stamp: None,
+            probe_rtt_min_delay: None,
+            probe_rtt_min_stamp: None,
+            probe_rtt_expired: false,
+            probe_rtt_done_stamp: None,
+            probe_rtt_round_done: false,
+            loss_round_delivered: 0,
+            loss_round_start: false,
+            congestion_in_round: None,
+            loss_events_in_round: 0,
+            startup_high_loss: false,
+            full_bw: 0,
+            full_bw_count: 0,
+            full_bw_now: false,
+            filled_pipe: false,
+            cycle_stamp: None,
+            bw_probe_wait: PROBE_BW_WAIT_BASE,
+            bw_probe_samples: false,
+            bw_probe_up_rounds: 0,
+            bw_probe_up_acks: 0,
+            probe_up_cnt: usize::MAX,
+        }
+    }
+
+    /// The bandwidth estimate of the model, in bytes per second.
+    #[cfg(test)]
+    #[must_use]
+    pub const fn bw(&self) -> usize {
+        self.bw
+    }
+
+    /// The minimum RTT estimate of the model.
+    #[cfg(test)]
+    #[must_use]
+    pub const fn min_rtt(&self) -> Option<Duration> {
+        self.min_rtt
+    }
+
+    /// Whether BBR is still in `Startup`.
+    #[cfg(test)]
+    #[must_use]
+    pub fn in_startup(&self) -> bool {
+        self.mode == Mode::Startup
+    }
+
+    pub const fn max_datagram_size(&self) -> usize {
+        self.pmtud.plpmtu()
+    }
+
+    const fn min_pipe_cwnd(&self) -> usize {
+        MIN_PIPE_CWND_PKTS * self.max_datagram_size()
+    }
+
+    const fn max_bw(&self) -> usize {
+        if self.bw_hi[0] > self.bw_hi[1] {
+            self.bw_hi[0]
+        } else {
+            self.bw_hi[1]
+        }
+    }
+
+    /// The estimated bandwidth-delay product, scaled by `gain` percent.
+    fn bdp(&self, bw: usize, gain: usize) -> usize {
+        self.min_rtt.map_or_else(
+            || cwnd_initial(self.max_datagram_size()),
+            |min_rtt| scale(volume(bw, min_rtt), gain),
+        )
+    }
+
+    /// The volume of data in flight that is the target of the model, with an allowance
+    /// for the bursts the pacer permits.
+    fn inflight(&self, bw: usize, gain: usize) -> usize {
+        self.bdp(bw, gain) + PACING_BURST_SIZE * self.max_datagram_size()
+    }
+
+    fn target_inflight(&self) -> usize {
+        min(self.bdp(self.bw, GAIN_UNIT), self.congestion_window)
+    }
+
+    fn inflight_with_headroom(&self) -> usize {
+        self.inflight_hi.map_or(usize::MAX, |inflight_hi| {
+            let headroom = max(self.max_datagram_size(), scale(inflight_hi, HEADROOM));
+            max(inflight_hi.saturating_sub(headroom), self.min_pipe_cwnd())
+        })
+    }
+
+    fn probe_rtt_cwnd(&self) -> usize {
+        max(self.bdp(self.bw, PROBE_RTT_CWND_GAIN), self.min_pipe_cwnd())
+    }
+
+    /// Whether the sender is unable to use the capacity that the model allows.
+    /// The application can be prevented from sending by flow control or by not having
+    /// anything to send.  As with the classic congestion controllers, this is inferred
+    /// from the volume of data in flight, except that the target here is set by the
+    /// pacing rate rather than the congestion window.
+    fn app_limited(&self) -> bool {
+        if self.mode == Mode::Startup || self.bw == 0 {
+            // Allow for the doubling of the sending rate during startup.
+            return self.bytes_in_flight < self.congestion_window / 2;
+        }
+        let target = min(
+            self.congestion_window,
+            self.bdp(self.bw, self.mode.pacing_gain()),
+        );
+        self.bytes_in_flight + self.max_datagram_size() * PACING_BURST_SIZE < target
+    }
+
+    fn set_mode(&mut self, mode: Mode, now: Instant) {
+        if self.mode == mode {
+            return;
+        }
+        qdebug!("[{self}] mode -> {mode:?}");
+        qlog::congestion_state_updated(
+            &mut self.qlog,
+            self.mode.to_qlog(),
+            mode.to_qlog(),
+            None,
+            now,
+        );
+        self.mode = mode;
+    }
+
+    /// Produce a delivery rate sample from the newly acknowledged packets.
+    /// See <https://datatracker.ietf.org/doc/html/draft-ietf-ccwg-bbr#section-4.5.2>.
+    fn generate_rate_sample(
+        &mut self,
+        acked_pkts: &[sent::Packet],
+        now: Instant,
+    ) -> Option<RateSample> {
+        let mut rs = RateSample::default();
+        let mut sample: Option<(SentState, Instant)> = None;
+        for pkt in acked_pkts {
+            if !pkt.cc_outstanding() {
+                continue;
+            }
+            // BIF is set to 0 on a path change, but in case that was because of a simple
+            // rebinding event, we may still get ACKs for packets sent before the rebinding.
+            self.bytes_in_flight = self.bytes_in_flight.saturating_sub(pkt.len());
+            let Some(state) = self.sent.remove(&(pkt.pn(), pkt.packet_type())) else {
+                continue;
+            };
+            self.delivered += pkt.len();
+            self.delivered_time = Some(now);
+            rs.newly_acked += pkt.len();
+            // Use the most recently sent packet for the sample.
+            if sample
+                .as_ref()
+                .is_none_or(|(s, _)| state.delivered >= s.delivered)
+            {
+                self.first_sent_time = Some(pkt.time_sent());
+                sample = Some((state, pkt.time_sent()));
+            }
+        }
+        if self
+            .app_limited_until
+            .is_some_and(|until| self.delivered > until)
+        {
+            self.app_limited_until = None;
+        }
+
+        let (state, time_sent) = sample?;
+        rs.prior_delivered = state.delivered;
+        rs.delivered = self.delivered - state.delivered;
+        rs.is_app_limited = state.app_limited;
+        rs.tx_in_flight = state.tx_in_flight;
+        rs.lost = self.lost - state.lost;
+
+        // Use the longer of the send and ACK intervals, to avoid overestimating the
+        // bandwidth due to ACK compression.
+        let send_elapsed = time_sent.saturating_duration_since(state.first_sent_time);
+        let ack_elapsed = now.saturating_duration_since(state.delivered_time);
+        let interval = max(send_elapsed, ack_elapsed);
+        // Samples with an interval below the minimum RTT are not reliable.
+        if self.min_rtt.is_none_or(|min_rtt| interval >= min_rtt) {
+            rs.delivery_rate = rate(rs.delivered, interval);
+        }
+        qtrace!(
+            "[{self}] rate sample: delivered {} over {interval:?} = {}, app_limited {}",
+            rs.delivered,
+            rs.delivery_rate,
+            rs.is_app_limited
+        );
+        Some(rs)
+    }
+
+    const fn start_round(&mut self) {
+        self.next_round_delivered = self.delivered;
+    }
+
+    const fn update_round(&mut self, rs: &RateSample) {
+        if rs.prior_delivered >= self.next_round_delivered {
+            self.start_round();
+            self.rounds_since_bw_probe += 1;
+            self.round_start = true;
+        } else {
+            self.round_start = false;
+        }
+    }
+
+    fn update_max_bw(&mut self, rs: &RateSample) {
+        self.update_round(rs);
+        if rs.delivery_rate > 0 && (rs.delivery_rate >= self.max_bw() || !rs.is_app_limited) {
+            self.bw_hi[1] = max(self.bw_hi[1], rs.delivery_rate);
+        }
+    }
+
+    /// Age the maximum bandwidth filter, which covers two bandwidth probing cycles.
+    const fn advance_max_bw_filter(&mut self) {
+        if self.bw_hi[1] == 0 {
+            return;
+        }
+        self.bw_hi[0] = self.bw_hi[1];
+        self.bw_hi[1] = 0;
+    }
+
+    fn update_latest_delivery_signals(&mut self, rs: &RateSample) {
+        self.loss_round_start = false;
+        self.bw_latest = max(self.bw_latest, rs.delivery_rate);
+        self.inflight_latest = max(self.inflight_latest, rs.delivered);
+        if rs.prior_delivered >= self.loss_round_delivered {
+            self.loss_round_delivered = self.delivered;
+            self.loss_round_start = true;
+            self.loss_events_in_round = 0;
+        }
+    }
+
+    const fn advance_latest_delivery_signals(&mut self, rs: &RateSample) {
+        if self.loss_round_start {
+            self.bw_latest = rs.delivery_rate;
+            self.inflight_latest = rs.delivered;
+        }
+    }
+
+    fn update_congestion_signals(
+        &mut self,
+        rs: &RateSample,
+        cc_stats: &mut CongestionControlStats,
+    ) {
+        self.update_max_bw(rs);
+        if !self.loss_round_start {
+            return;
+        }
+        if let Some(event) = self.congestion_in_round.take() {
+            self.adapt_lower_bounds_from_congestion(event, cc_stats);
+        }
+    }
+
+    /// Reduce the lower bounds once per round in which there was congestion.
+    fn adapt_lower_bounds_from_congestion(
+        &mut self,
+        event: CongestionEvent,
+        cc_stats: &mut CongestionControlStats,
+    ) {
+        if self.mode.is_probing_bw() {
+            return;
+        }
+        let bw_lo = self.bw_lo.unwrap_or_else(|| self.max_bw());
+        let inflight_lo = self.inflight_lo.unwrap_or(self.congestion_window);
+        self.bw_lo = Some(max(self.bw_latest, scale(bw_lo, BETA)));
+        self.inflight_lo = Some(max(self.inflight_latest, scale(inflight_lo, BETA)));
+        qinfo!(
+            "[{self}] {event:?} -> bw_lo {:?}, inflight_lo {:?}",
+            self.bw_lo,
+            self.inflight_lo
+        );
+        cc_stats.congestion_events[event] += 1;
+    }
+
+    const fn reset_congestion_signals(&mut self) {
+        self.congestion_in_round = None;
+        self.bw_latest = 0;
+        self.inflight_latest = 0;
+    }
+
+    const fn reset_lower_bounds(&mut self) {
+        self.bw_lo = None;
+        self.inflight_lo = None;
+    }
+
+    fn bound_bw_for_model(&mut self) {
+        self.bw = min(self.max_bw(), self.bw_lo.unwrap_or(usize::MAX));
+    }
+
+    /// Track whether the bandwidth has stopped growing.
+    const fn update_full_bw(&mut self, rs: &RateSample) {
+        if !self.round_start || rs.is_app_limited || self.full_bw_now {
+            return;
+        }
+        if self.max_bw() >= scale(self.full_bw, FULL_BW_THRESH) {
+            self.full_bw = self.max_bw();
+            self.full_bw_count = 0;
+            return;
+        }
+        self.full_bw_count += 1;
+        self.full_bw_now = self.full_bw_count >= FULL_BW_COUNT;
+    }
+
+    const fn reset_full_bw(&mut self) {
+        self.full_bw = 0;
+        self.full_bw_count = 0;
+        self.full_bw_now = false;
+    }
+
+    fn check_startup_done(&mut self, now: Instant, cc_stats: &mut CongestionControlStats) {
+        if self.mode != Mode::Startup {
+            return;
+        }
+        if self.startup_high_loss {
+            qinfo!("[{self}] excessive loss in startup");
+            self.inflight_hi = Some(max(
+                self.bdp(self.max_bw(), GAIN_UNIT),
+                self.inflight_latest,
+            ));
+            self.filled_pipe = true;
+        }
+        self.filled_pipe |= self.full_bw_now;
+        if self.filled_pipe {
+            cc_stats.slow_start_exited = true;
+            self.set_mode(Mode::Drain, now);
+        }
+    }
+
+    fn check_drain_done(&mut self, now: Instant) {
+        if self.mode == Mode::Drain
+            && self.bytes_in_flight <= self.inflight(self.max_bw(), GAIN_UNIT)
+        {
+            self.start_probe_bw_down(now);
+        }
+    }
+
+    /// Pick the time to wait before the next bandwidth probe.  Rather than use a random
+    /// number generator, this derives the jitter from the amount of delivered data,
+    /// which is enough to avoid synchronizing with other flows.
+    fn pick_probe_wait(&mut self) {
+        let jitter = u64::try_from(self.delivered % 1000).expect("less than 1000");
+        self.rounds_since_bw_probe = self.delivered % 2;
+        self.bw_probe_wait = PROBE_BW_WAIT_BASE + Duration::from_millis(jitter);
+    }
+
+    fn start_probe_bw_down(&mut self, now: Instant) {
+        self.reset_congestion_signals();
+        self.probe_up_cnt = usize::MAX;
+        self.pick_probe_wait();
+        self.cycle_stamp = Some(now);
+        self.start_round();
+        self.advance_max_bw_filter();
+        self.set_mode(Mode::ProbeBwDown, now);
+    }
+
+    fn start_probe_bw_cruise(&mut self, now: Instant) {
+        self.set_mode(Mode::ProbeBwCruise, now);
+    }
+
+    fn start_probe_bw_refill(&mut self, now: Instant) {
+        self.reset_lower_bounds();
+        self.bw_probe_up_rounds = 0;
+        self.bw_probe_up_acks = 0;
+        self.start_round();
+        self.set_mode(Mode::ProbeBwRefill, now);
+    }
+
+    fn start_probe_bw_up(&mut self, now: Instant) {
+        self.bw_probe_samples = true;
+        self.start_round();
+        self.reset_full_bw();
+        self.cycle_stamp = Some(now);
+        self.set_mode(Mode::ProbeBwUp, now);
+        self.raise_inflight_hi_slope();
+    }
+
+    fn has_elapsed_in_phase(&self, interval: Duration, now: Instant) -> bool {
+        self.cycle_stamp.is_some_and(|t| now > t + interval)
+    }
+
+    /// Whether to probe for bandwidth now, which either happens after a wall-clock
+    /// interval, or after a number of rounds that is comparable to the time it takes
+    /// Reno to grow its congestion window after a loss.
+    fn is_time_to_probe_bw(&mut self, now: Instant) -> bool {
+        let reno_rounds = min(
+            self.target_inflight() / self.max_datagram_size(),
+            MAX_RENO_ROUNDS,
+        );
+        if self.has_elapsed_in_phase(self.bw_probe_wait, now)
+            || self.rounds_since_bw_probe >= reno_rounds
+        {
+            self.start_probe_bw_refill(now);
+            return true;
+        }
+        false
+    }
+
+    fn is_time_to_cruise(&self) -> bool {
+        self.bytes_in_flight <= self.inflight_with_headroom()
+            && self.bytes_in_flight <= self.inflight(self.max_bw(), GAIN_UNIT)
+    }
+
+    fn is_time_to_go_down(&self, now: Instant) -> bool {
+        self.full_bw_now
+            || (self
+                .min_rtt
+                .is_some_and(|min_rtt| self.has_elapsed_in_phase(min_rtt, now))
+                && self.bytes_in_flight
+                    > self.inflight(self.max_bw(), Mode::ProbeBwUp.pacing_gain()))
+    }
+
+    fn update_probe_bw_cycle_phase(&mut self, rs: &RateSample, now: Instant) {
+        if !self.filled_pipe {
+            return;
+        }
+        self.adapt_upper_bounds(rs);
+        match self.mode {
+            Mode::ProbeBwDown => {
+                if !self.is_time_to_probe_bw(now) && self.is_time_to_cruise() {
+                    self.start_probe_bw_cruise(now);
+                }
+            }
+            Mode::ProbeBwCruise => {
+                self.is_time_to_probe_bw(now);
+            }
+            Mode::ProbeBwRefill => {
+                // After one round of refilling, start probing.
+                if self.round_start {
+                    self.start_probe_bw_up(now);
+                }
+            }
+            Mode::ProbeBwUp => {
+                if self.is_time_to_go_down(now) {
+                    self.start_probe_bw_down(now);
+                }
+            }
+            Mode::Startup | Mode::Drain | Mode::ProbeRtt => {}
+        }
+    }
+
+    /// Whether the loss rate indicated by the sample exceeds [`LOSS_THRESH`].
+    const fn is_inflight_too_high(rs: &RateSample) -> bool {
+        rs.lost > 0 && rs.lost * GAIN_UNIT > rs.tx_in_flight * LOSS_THRESH
+    }
+
+    fn adapt_upper_bounds(&mut self, rs: &RateSample) {
+        if Self::is_inflight_too_high(rs) {
+            return;
+        }
+        let Some(inflight_hi) = self.inflight_hi else {
+            return;
+        };
+        if rs.tx_in_flight > inflight_hi {
+            self.inflight_hi = Some(rs.tx_in_flight);
+        }
+        if self.mode == Mode::ProbeBwUp {
+            self.probe_inflight_hi_upward(rs);
+        }
+    }
+
+    /// Grow `inflight_hi` while probing, at an exponentially increasing rate.
+    fn probe_inflight_hi_upward(&mut self, rs: &RateSample) {
+        let Some(inflight_hi) = self.inflight_hi else {
+            return;
+        };
+        let cwnd_limited = rs.tx_in_flight + self.max_datagram_size() >= self.congestion_window;
+        if !cwnd_limited || self.congestion_window < inflight_hi {
+            return;
+        }
+        self.bw_probe_up_acks += rs.newly_acked;
+        if self.bw_probe_up_acks >= self.probe_up_cnt {
+            let delta = self.bw_probe_up_acks / self.probe_up_cnt;
+            self.bw_probe_up_acks -= delta * self.probe_up_cnt;
+            self.inflight_hi = Some(inflight_hi + delta * self.max_datagram_size());
+        }
+        if self.round_start {
+            self.raise_inflight_hi_slope();
+        }
+    }
+
+    fn raise_inflight_hi_slope(&mut self) {
+        let growth_this_round = self.max_datagram_size() << self.bw_probe_up_rounds;
+        self.bw_probe_up_rounds = min(self.bw_probe_up_rounds + 1, 30);
+        self.probe_up_cnt = max(self.congestion_window / growth_this_round, 1);
+    }
+
+    /// React to the loss of a packet, bounding the volume of data in flight when the loss
+    /// rate indicates that a probe for bandwidth went too far.
+    fn handle_lost_packet(&mut self, state: &SentState, now: Instant) {
+        let rs = RateSample {
+            tx_in_flight: state.tx_in_flight,
+            lost: self.lost - state.lost,
+            is_app_limited: state.app_limited,
+            ..RateSample::default()
+        };
+        if !Self::is_inflight_too_high(&rs) {
+            return;
+        }
+        if self.mode == Mode::Startup && self.loss_events_in_round >= STARTUP_FULL_LOSS_CNT {
+            self.startup_high_loss = true;
+        }
+        if !self.bw_probe_samples {
+            return;
+        }
+        self.bw_probe_samples = false;
+        if !rs.is_app_limited {
+            self.inflight_hi = Some(max(rs.tx_in_flight, scale(self.target_inflight(), BETA)));
+            qinfo!(
+                "[{self}] inflight too high -> inflight_hi {:?}",
+                self.inflight_hi
+            );
+        }
+        if self.mode == Mode::ProbeBwUp {
+            self.start_probe_bw_down(now);
+        }
+    }
+
+    fn update_min_rtt(&mut self, rtt_est: &RttEstimate, now: Instant) {
+        self.probe_rtt_expired = self
+            .probe_rtt_min_stamp
+            .is_some_and(|t| now > t + PROBE_RTT_INTERVAL);
+        if rtt_est.first_sample_time().is_none() {
+            return;
+        }
+        let rtt = rtt_est.latest();
+        if self.probe_rtt_min_delay.is_none_or(|d| rtt < d) || self.probe_rtt_expired {
+            self.probe_rtt_min_delay = Some(rtt);
+            self.probe_rtt_min_stamp = Some(now);
+        }
+        let min_rtt_expired = self
+            .min_rtt_stamp
+            .is_some_and(|t| now > t + MIN_RTT_FILTER_LEN);
+        if self.min_rtt.is_none_or(|min_rtt| rtt < min_rtt) || min_rtt_expired {
+            self.min_rtt = self.probe_rtt_min_delay;
+            self.min_rtt_stamp = self.probe_rtt_min_stamp;
+        }
+    }
+
+    fn check_probe_rtt(&mut self, now: Instant) {
+        if self.mode != Mode::ProbeRtt && self.probe_rtt_expired {
+            self.prior_cwnd = self.congestion_window;
+            self.probe_rtt_done_stamp = None;
+            self.start_round();
+            self.set_mode(Mode::ProbeRtt, now);
+        }
+        if self.mode == Mode::ProbeRtt {
+            self.handle_probe_rtt(now);
+        }
+    }
+
+    fn handle_probe_rtt(&mut self, now: Instant) {
+        if let Some(done) = self.probe_rtt_done_stamp {
+            if self.round_start {
+                self.probe_rtt_round_done = true;
+            }
+            if self.probe_rtt_round_done && now > done {
+                self.probe_rtt_min_stamp = Some(now);
+                self.congestion_window = max(self.congestion_window, self.prior_cwnd);
+                self.exit_probe_rtt(now);
+            }
+        } else if self.bytes_in_flight <= self.probe_rtt_cwnd() {
+            self.probe_rtt_done_stamp = Some(now + PROBE_RTT_DURATION);
+            self.probe_rtt_round_done = false;
+            self.start_round();
+        }
+    }
+
+    fn exit_probe_rtt(&mut self, now: Instant) {
+        self.reset_lower_bounds();
+        if self.filled_pipe {
+            self.start_probe_bw_down(now);
+            self.start_probe_bw_cruise(now);
+        } else {
+            self.set_mode(Mode::Startup, now);
+        }
+    }
+
+    fn set_pacing_rate(&mut self) {
+        if self.bw == 0 {
+            return;
+        }
+        let rate = paced_rate(
+            self.bw,
+            self.mode.pacing_gain(),
+            self.max_datagram_size(),
+            self.min_rtt,
+        );
+        // Don't slow down during startup, as the bandwidth estimate is still growing.
+        if self.filled_pipe || self.pacing_rate.is_none_or(|r| rate > r) {
+            self.pacing_rate = Some(rate);
+        }
+    }
+
+    fn set_cwnd(&mut self, rs: &RateSample) {
+        let max_inflight = self.inflight(self.bw, self.mode.cwnd_gain());
+        let mut cwnd = self.congestion_window;
+        if self.filled_pipe {
+            cwnd = min(cwnd + rs.newly_acked, max_inflight);
+        } else if cwnd < max_inflight || self.delivered < cwnd_initial(self.max_datagram_size()) {
+            cwnd += rs.newly_acked;
+        }
+        cwnd = max(cwnd, self.min_pipe_cwnd());
+        if self.mode == Mode::ProbeRtt {
+            cwnd = min(cwnd, self.probe_rtt_cwnd());
+        }
+
+        // Apply the upper and lower bounds on the volume of data in flight.
+        let cap = if self.mode.is_probe_bw() && self.mode != Mode::ProbeBwCruise {
+            self.inflight_hi.unwrap_or(usize::MAX)
+        } else if matches!(self.mode, Mode::ProbeRtt | Mode::ProbeBwCruise) {
+            self.inflight_with_headroom()
+        } else {
+            usize::MAX
+        };
+        let cap = max(
+            min(cap, self.inflight_lo.unwrap_or(usize::MAX)),
+            self.min_pipe_cwnd(),
+        );
+        self.congestion_window = min(cwnd, cap);
+    }
+}
+
+impl CongestionControl for Bbr {
+    fn set_qlog(&mut self, qlog: Qlog) {
+        self.qlog = qlog;
+    }
+
+    fn cwnd(&self) -> usize {
+        self.congestion_window
+    }
+
+    fn bytes_in_flight(&self) -> usize {
+        self.bytes_in_flight
+    }
+
+    fn cwnd_avail(&self) -> usize {
+        self.congestion_window.saturating_sub(self.bytes_in_flight)
+    }
+
+    fn cwnd_min(&self) -> usize {
+        self.min_pipe_cwnd()
+    }
+
+    #[cfg(test)]
+    fn cwnd_initial(&self) -> usize {
+        cwnd_initial(self.max_datagram_size())
+    }
+
+    fn pmtud(&self) -> &Pmtud {
+        &self.pmtud
+    }
+
+    fn pmtud_mut(&mut self) -> &mut Pmtud {
+        &mut self.pmtud
+    }
+
+    fn pacing_rate(&self) -> Option<usize> {
+        self.pacing_rate
+    }
+
+    fn on_packets_acked(
+        &mut self,
+        acked_pkts: &[sent::Packet],
+        rtt_est: &RttEstimate,
+        now: Instant,
+        cc_stats: &mut CongestionControlStats,
+    ) {
+        let Some(rs) = self.generate_rate_sample(acked_pkts, now) else {
+            return;
+        };
+
+        self.update_latest_delivery_signals(&rs);
+        self.update_congestion_signals(&rs, cc_stats);
+        self.update_full_bw(&rs);
+        self.check_startup_done(now, cc_stats);
+        self.check_drain_done(now);
+        self.update_probe_bw_cycle_phase(&rs, now);
+        self.update_min_rtt(rtt_est, now);
+        self.check_probe_rtt(now);
+        self.advance_latest_delivery_signals(&rs);
+        self.bound_bw_for_model();
+
+        self.set_pacing_rate();
+        self.set_cwnd(&rs);
+
+        let mut metrics = vec![
+            qlog::Metric::CongestionWindow(self.congestion_window),
+            qlog::Metric::BytesInFlight(self.bytes_in_flight),
+        ];
+        if let Some(rate) = self.pacing_rate {
+            metrics.push(qlog::Metric::PacingRate(
+                u64::try_from(rate).expect("usize fits into u64"),
+            ));
+        }
+        qlog::metrics_updated(&mut self.qlog, &metrics, now);
+        qdebug!("[{self}] on_packets_acked newly_acked={}", rs.newly_acked);
+    }
+
+    fn on_packets_lost(
+        &mut self,
+        _first_rtt_sample_time: Option<Instant>,
+        _prev_largest_acked_sent: Option<Instant>,
+        _pto: Duration,
+        lost_packets: &[sent::Packet],
+        now: Instant,
+        _cc_stats: &mut CongestionControlStats,
+    ) -> bool {
+        if lost_packets.is_empty() {
+            return false;
+        }
+
+        for pkt in lost_packets {
+            if pkt.cc_in_flight() {
+                qdebug!("[{self}] packet_lost pn={}, ps={}", pkt.pn(), pkt.len());
+                // bytes_in_flight is set to 0 on a path change, but in case that was because of
+                // a simple rebinding event, we may still declare packets lost that
+                // were sent before the rebinding.
+                self.bytes_in_flight = self.bytes_in_flight.saturating_sub(pkt.len());
+            }
+            let Some(state) = self.sent.remove(&(pkt.pn(), pkt.packet_type())) else {
+                continue;
+            };
+            // Lost PMTUD probes do not elicit a congestion control reaction.
+            if pkt.is_pmtud_probe() {
+                continue;
+            }
+            self.lost += pkt.len();
+            self.loss_events_in_round += 1;
+            self.congestion_in_round = Some(CongestionEvent::Loss);
+            self.handle_lost_packet(&state, now);
+        }
+
+        qlog::metrics_updated(
+            &mut self.qlog,
+            &[
+                qlog::Metric::CongestionWindow(self.congestion_window),
+                qlog::Metric::BytesInFlight(self.bytes_in_flight),
+            ],
+            now,
+        );
+        // Reductions are applied at the end of the round, see
+        // `adapt_lower_bounds_from_congestion`.
+        false
+    }
+
+    /// ECN-CE marks are treated as a congestion signal equivalent to loss, which
+    /// reduces the lower bounds of the model at the end of the round.
+    fn on_ecn_ce_received(
+        &mut self,
+        _largest_acked_pkt: &sent::Packet,
+        _now: Instant,
+        _cc_stats: &mut CongestionControlStats,
+    ) -> bool {
+        self.congestion_in_round = Some(CongestionEvent::Ecn);
+        false
+    }
+
+    fn recovery_packet(&self) -> bool {
+        false
+    }
+
+    fn discard(&mut self, pkt: &sent::Packet, now: Instant) {
+        if pkt.cc_outstanding() {
+            self.sent.remove(&(pkt.pn(), pkt.packet_type()));
+            self.bytes_in_flight = self.bytes_in_flight.saturating_sub(pkt.len());
+            qlog::metrics_updated(
+                &mut self.qlog,
+                &[qlog::Metric::BytesInFlight(self.bytes_in_flight)],
+                now,
+            );
+            qtrace!("[{self}] Ignore pkt with size {}", pkt.len());
+        }
+    }
+
+    fn discard_in_flight(&mut self, now: Instant) {
+        self.bytes_in_flight = 0;
+        self.sent.clear();
+        qlog::metrics_updated(
+            &mut self.qlog,
+            &[qlog::Metric::BytesInFlight(self.bytes_in_flight)],
+            now,
+        );
+    }
+
+    fn on_packet_sent(&mut self, pkt: &sent::Packet, now: Instant) {
+        if !pkt.cc_in_flight() {
+            return;
+        }
+        let time_sent = pkt.time_sent();
+        if self.bytes_in_flight == 0 {
+            self.first_sent_time = Some(time_sent);
+            self.delivered_time = Some(time_sent);
+        }
+        if self.app_limited() {
+            self.app_limited_until = Some(max(self.delivered + self.bytes_in_flight, 1));
+        }
+        self.bytes_in_flight += pkt.len();
+        self.sent.insert(
+            (pkt.pn(), pkt.packet_type()),
+            SentState {
+                delivered: self.delivered,
+                delivered_time: *self.delivered_time.get_or_insert(time_sent),
+                first_sent_time: *self.first_sent_time.get_or_insert(time_sent),
+                app_limited: self.app_limited_until.is_some(),
+                tx_in_flight: self.bytes_in_flight,
+                lost: self.lost,
+            },
+        );
+        qdebug!("[{self}] packet_sent pn={}, ps={}", pkt.pn(), pkt.len());
+        qlog::metrics_updated(
+            &mut self.qlog,
+            &[qlog::Metric::BytesInFlight(self.bytes_in_flight)],
+            now,
+        );
+    }
+}
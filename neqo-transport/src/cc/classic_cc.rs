@@ -12,7 +12,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use ::qlog::events::{EventData, quic::CongestionStateUpdated};
+use ::qlog::events::quic::CongestionStateUpdatedTrigger;
 use neqo_common::{const_max, const_min, qdebug, qinfo, qlog::Qlog, qtrace};
 use rustc_hash::FxHashMap as HashMap;
 
@@ -246,7 +246,7 @@ impl<T: WindowAdjustment> CongestionControl for ClassicCongestionControl<T> {
         // big a PTO does no harm here.
         self.cleanup_maybe_lost_packets(now, rtt_est.pto(true));
 
-        self.detect_spurious_congestion_event(acked_pkts, cc_stats);
+        self.detect_spurious_congestion_event(acked_pkts, now, cc_stats);
 
         for pkt in acked_pkts {
             qtrace!(
@@ -273,7 +273,7 @@ impl<T: WindowAdjustment> CongestionControl for ClassicCongestionControl<T> {
             }
 
             if self.current.phase.in_recovery() {
-                self.set_phase(Phase::CongestionAvoidance, now);
+                self.set_phase(Phase::CongestionAvoidance, None, now);
                 qlog::metrics_updated(&mut self.qlog, &[qlog::Metric::InRecovery(false)], now);
             }
 
@@ -304,7 +304,7 @@ impl<T: WindowAdjustment> CongestionControl for ClassicCongestionControl<T> {
             if self.current.congestion_window == self.current.ssthresh {
                 // This doesn't look like it is necessary, but it can happen
                 // after persistent congestion.
-                self.set_phase(Phase::CongestionAvoidance, now);
+                self.set_phase(Phase::CongestionAvoidance, None, now);
             }
         }
         // Congestion avoidance, above the slow start threshold.
@@ -502,7 +502,7 @@ impl<T: WindowAdjustment> CongestionControl for ClassicCongestionControl<T> {
     }
 }
 
-const fn cwnd_initial(mtu: usize) -> usize {
+pub const fn cwnd_initial(mtu: usize) -> usize {
     const_min(CWND_INITIAL_PKTS * mtu, const_max(2 * mtu, 14_720))
 }
 
@@ -551,22 +551,26 @@ impl<T: WindowAdjustment> ClassicCongestionControl<T> {
         self.current.acked_bytes
     }
 
-    fn set_phase(&mut self, phase: Phase, now: Instant) {
+    fn set_phase(
+        &mut self,
+        phase: Phase,
+        trigger: Option<CongestionStateUpdatedTrigger>,
+        now: Instant,
+    ) {
         if self.current.phase == phase {
             return;
         }
         qdebug!("[{self}] phase -> {phase:?}");
         let old_state = self.current.phase;
-        // No need to tell qlog about exit from transient states.
-        if !old_state.transient() {
-            self.qlog.add_event_at(
-                || {
-                    Some(EventData::CongestionStateUpdated(CongestionStateUpdated {
-                        old: Some(old_state.to_qlog().to_owned()),
-                        new: phase.to_qlog().to_owned(),
-                        trigger: None,
-                    }))
-                },
+        // No need to tell qlog about exit from transient states, unless
+        // something specific caused the change, like persistent congestion
+        // during the recovery that the same loss started.
+        if !old_state.transient() || trigger.is_some() {
+            qlog::congestion_state_updated(
+                &mut self.qlog,
+                old_state.to_qlog(),
+                phase.to_qlog(),
+                trigger,
                 now,
             );
         }
@@ -581,6 +585,7 @@ impl<T: WindowAdjustment> ClassicCongestionControl<T> {
     fn detect_spurious_congestion_event(
         &mut self,
         acked_packets: &[sent::Packet],
+        now: Instant,
         cc_stats: &mut CongestionControlStats,
     ) {
         if self.maybe_lost_packets.is_empty() {
@@ -607,7 +612,7 @@ impl<T: WindowAdjustment> ClassicCongestionControl<T> {
             qdebug!(
                 "Spurious detection: maybe_lost_packets emptied -> calling on_spurious_congestion_event"
             );
-            self.on_spurious_congestion_event(cc_stats);
+            self.on_spurious_congestion_event(now, cc_stats);
         }
     }
 
@@ -628,7 +633,11 @@ impl<T: WindowAdjustment> ClassicCongestionControl<T> {
         });
     }
 
-    fn on_spurious_congestion_event(&mut self, cc_stats: &mut CongestionControlStats) {
+    fn on_spurious_congestion_event(
+        &mut self,
+        now: Instant,
+        cc_stats: &mut CongestionControlStats,
+    ) {
         let Some(stored) = self.stored.take() else {
             qdebug!(
                 "[{self}] Spurious cong event -> ABORT, no stored params to restore available."
@@ -651,7 +660,26 @@ impl<T: WindowAdjustment> ClassicCongestionControl<T> {
             "Spurious cong event: recovering cc params from {} to {stored}",
             self.current
         );
+        let old_phase = self.current.phase;
         self.current = stored;
+        if old_phase != self.current.phase {
+            qlog::congestion_state_updated(
+                &mut self.qlog,
+                old_phase.to_qlog(),
+                self.current.phase.to_qlog(),
+                None,
+                now,
+            );
+        }
+        qlog::metrics_updated(
+            &mut self.qlog,
+            &[
+                qlog::Metric::CongestionWindow(self.current.congestion_window),
+                qlog::Metric::SsThresh(self.current.ssthresh),
+                qlog::Metric::InRecovery(self.current.phase.in_recovery()),
+            ],
+            now,
+        );
 
         if self.current.phase.in_slow_start() {
             cc_stats.slow_start_exited = false;
@@ -704,7 +732,11 @@ impl<T: WindowAdjustment> ClassicCongestionControl<T> {
                     qinfo!("[{self}] persistent congestion");
                     self.current.congestion_window = self.cwnd_min();
                     self.current.acked_bytes = 0;
-                    self.set_phase(Phase::PersistentCongestion, now);
+                    self.set_phase(
+                        Phase::PersistentCongestion,
+                        Some(CongestionStateUpdatedTrigger::PersistentCongestion),
+                        now,
+                    );
                     qlog::metrics_updated(
                         &mut self.qlog,
                         &[qlog::Metric::CongestionWindow(
@@ -788,7 +820,9 @@ impl<T: WindowAdjustment> ClassicCongestionControl<T> {
             ],
             now,
         );
-        self.set_phase(Phase::RecoveryStart, now);
+        let trigger = (congestion_event == CongestionEvent::Ecn)
+            .then_some(CongestionStateUpdatedTrigger::Ecn);
+        self.set_phase(Phase::RecoveryStart, trigger, now);
         true
     }
 
@@ -874,6 +908,7 @@ mod tests {
                 Cubic::default(),
                 Pmtud::new(IP_ADDR, MTU),
             )),
+            CongestionControlAlgorithm::Bbr => unreachable!("BBR is not a classic algorithm"),
         }
     }
 
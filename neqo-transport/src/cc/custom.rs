@@ -0,0 +1,367 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Congestion controllers that are provided by the application.
+
+use std::{
+    fmt::{self, Debug, Display},
+    time::{Duration, Instant},
+};
+
+use neqo_common::{qdebug, qlog::Qlog};
+
+use super::CongestionControl;
+use crate::{Pmtud, qlog, recovery::sent, rtt::RttEstimate, stats::CongestionControlStats};
+
+/// A packet that a [`CongestionController`] is told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketInfo {
+    /// The packet number.
+    pub pn: u64,
+    /// The size of the packet, in bytes.
+    pub len: usize,
+    /// The time that the packet was sent.
+    pub time_sent: Instant,
+    /// Whether the packet was a probe for path MTU discovery.  Loss of a probe
+    /// is not a signal of congestion.
+    pub pmtud_probe: bool,
+}
+
+impl From<&sent::Packet> for PacketInfo {
+    fn from(pkt: &sent::Packet) -> Self {
+        Self {
+            pn: pkt.pn(),
+            len: pkt.len(),
+            time_sent: pkt.time_sent(),
+            pmtud_probe: pkt.is_pmtud_probe(),
+        }
+    }
+}
+
+/// The round-trip time estimates at the time that packets are acknowledged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttSample {
+    /// The most recent RTT sample.
+    pub latest: Duration,
+    /// The smoothed RTT.
+    pub smoothed: Duration,
+    /// The RTT variation.
+    pub rttvar: Duration,
+    /// The minimum RTT seen.
+    pub min: Duration,
+}
+
+impl From<&RttEstimate> for RttSample {
+    fn from(rtt_est: &RttEstimate) -> Self {
+        Self {
+            latest: rtt_est.latest(),
+            smoothed: rtt_est.estimate(),
+            rttvar: rtt_est.rttvar(),
+            min: rtt_est.minimum(),
+        }
+    }
+}
+
+/// A congestion controller that is implemented outside of this crate.
+///
+/// The controller is told about packets that count toward bytes in flight as they
+/// are sent, acknowledged, or declared lost, and it decides the congestion window
+/// and, optionally, the pacing rate.  The connection takes care of counting bytes
+/// in flight and of pacing.
+pub trait CongestionController: Debug {
+    /// The congestion window, in bytes.  A window that is smaller than two
+    /// datagrams is treated as two datagrams.
+    #[must_use]
+    fn cwnd(&self) -> usize;
+
+    /// The rate at which to pace packets, in bytes per second.  When `None`, the pacer
+    /// derives the rate from the congestion window and the RTT.  A rate of zero
+    /// is treated as `None`.
+    #[must_use]
+    fn pacing_rate(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether the controller is in slow start.  This is only used for reporting,
+    /// in [`CongestionControlStats::slow_start_exited`].
+    #[must_use]
+    fn in_slow_start(&self) -> bool {
+        false
+    }
+
+    /// Called when the maximum datagram size changes, as a result of path MTU discovery.
+    fn on_max_datagram_size(&mut self, _max_datagram_size: usize) {}
+
+    /// Called when a packet is sent.  `bytes_in_flight` includes the packet.
+    fn on_packet_sent(&mut self, _pkt: &PacketInfo, _bytes_in_flight: usize, _now: Instant) {}
+
+    /// Called when packets are acknowledged.  `bytes_in_flight` no longer includes
+    /// the acknowledged packets.
+    fn on_packets_acked(
+        &mut self,
+        acked: &[PacketInfo],
+        rtt: &RttSample,
+        bytes_in_flight: usize,
+        now: Instant,
+    );
+
+    /// Called when packets are declared lost.  `pto` is the current probe timeout,
+    /// which a controller can use to detect persistent congestion.
+    /// Returns true if this is treated as a congestion event.
+    fn on_packets_lost(
+        &mut self,
+        lost: &[PacketInfo],
+        pto: Duration,
+        bytes_in_flight: usize,
+        now: Instant,
+    ) -> bool;
+
+    /// Called when the peer reports packets with ECN-CE marks.
+    /// Returns true if this is treated as a congestion event.
+    fn on_ecn_ce_received(&mut self, _largest_acked: &PacketInfo, _now: Instant) -> bool {
+        false
+    }
+}
+
+/// Creates a [`CongestionController`] for each path of a connection.
+/// See [`crate::ConnectionParameters::congestion_controller`].
+pub trait CongestionControllerFactory: Debug {
+    /// Create a controller for a path that uses datagrams of `max_datagram_size` bytes.
+    fn new_controller(&self, max_datagram_size: usize) -> Box<dyn CongestionController>;
+}
+
+/// Adapts a [`CongestionController`] to the [`CongestionControl`] interface that
+/// the connection uses.
+#[derive(Debug)]
+pub struct Custom {
+    controller: Box<dyn CongestionController>,
+    bytes_in_flight: usize,
+    in_slow_start: bool,
+    max_datagram_size: usize,
+    #[cfg(test)]
+    cwnd_initial: usize,
+    pmtud: Pmtud,
+    qlog: Qlog,
+}
+
+impl Display for Custom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Custom [cwnd: {}, bif: {}]",
+            self.controller.cwnd(),
+            self.bytes_in_flight
+        )
+    }
+}
+
+impl Custom {
+    #[must_use]
+    pub fn new(factory: &dyn CongestionControllerFactory, pmtud: Pmtud) -> Self {
+        let max_datagram_size = pmtud.plpmtu();
+        let controller = factory.new_controller(max_datagram_size);
+        Self {
+            in_slow_start: controller.in_slow_start(),
+            #[cfg(test)]
+            cwnd_initial: controller.cwnd(),
+            controller,
+            bytes_in_flight: 0,
+            max_datagram_size,
+            pmtud,
+            qlog: Qlog::disabled(),
+        }
+    }
+
+    pub const fn max_datagram_size(&self) -> usize {
+        self.pmtud.plpmtu()
+    }
+
+    fn update_max_datagram_size(&mut self) {
+        let max_datagram_size = self.max_datagram_size();
+        if max_datagram_size != self.max_datagram_size {
+            self.max_datagram_size = max_datagram_size;
+            self.controller.on_max_datagram_size(max_datagram_size);
+        }
+    }
+
+    fn update_stats(&mut self, cc_stats: &mut CongestionControlStats) {
+        let in_slow_start = self.controller.in_slow_start();
+        if self.in_slow_start && !in_slow_start {
+            cc_stats.slow_start_exited = true;
+        }
+        self.in_slow_start = in_slow_start;
+    }
+
+    fn qlog_metrics(&mut self, now: Instant) {
+        let mut metrics = vec![
+            qlog::Metric::CongestionWindow(self.cwnd()),
+            qlog::Metric::BytesInFlight(self.bytes_in_flight),
+        ];
+        if let Some(rate) = self.pacing_rate() {
+            metrics.push(qlog::Metric::PacingRate(
+                u64::try_from(rate).expect("usize fits into u64"),
+            ));
+        }
+        qlog::metrics_updated(&mut self.qlog, &metrics, now);
+    }
+}
+
+impl CongestionControl for Custom {
+    fn set_qlog(&mut self, qlog: Qlog) {
+        self.qlog = qlog;
+    }
+
+    fn cwnd(&self) -> usize {
+        // Never let the window close entirely.
+        self.controller.cwnd().max(self.cwnd_min())
+    }
+
+    fn bytes_in_flight(&self) -> usize {
+        self.bytes_in_flight
+    }
+
+    fn cwnd_avail(&self) -> usize {
+        self.cwnd().saturating_sub(self.bytes_in_flight)
+    }
+
+    fn cwnd_min(&self) -> usize {
+        self.max_datagram_size() * 2
+    }
+
+    #[cfg(test)]
+    fn cwnd_initial(&self) -> usize {
+        self.cwnd_initial
+    }
+
+    fn pmtud(&self) -> &Pmtud {
+        &self.pmtud
+    }
+
+    fn pmtud_mut(&mut self) -> &mut Pmtud {
+        &mut self.pmtud
+    }
+
+    fn pacing_rate(&self) -> Option<usize> {
+        // A rate of zero would stop sending, so pace using the window instead.
+        self.controller.pacing_rate().filter(|&rate| rate > 0)
+    }
+
+    fn on_packets_acked(
+        &mut self,
+        acked_pkts: &[sent::Packet],
+        rtt_est: &RttEstimate,
+        now: Instant,
+        cc_stats: &mut CongestionControlStats,
+    ) {
+        self.update_max_datagram_size();
+        let acked = acked_pkts
+            .iter()
+            .filter(|pkt| pkt.cc_outstanding())
+            .map(PacketInfo::from)
+            .collect::<Vec<_>>();
+        if acked.is_empty() {
+            return;
+        }
+        for pkt in &acked {
+            self.bytes_in_flight = self.bytes_in_flight.saturating_sub(pkt.len);
+        }
+        self.controller
+            .on_packets_acked(&acked, &rtt_est.into(), self.bytes_in_flight, now);
+        self.update_stats(cc_stats);
+        self.qlog_metrics(now);
+        qdebug!("[{self}] on_packets_acked newly_acked={}", acked.len());
+    }
+
+    fn on_packets_lost(
+        &mut self,
+        _first_rtt_sample_time: Option<Instant>,
+        _prev_largest_acked_sent: Option<Instant>,
+        pto: Duration,
+        lost_packets: &[sent::Packet],
+        now: Instant,
+        cc_stats: &mut CongestionControlStats,
+    ) -> bool {
+        self.update_max_datagram_size();
+        let lost = lost_packets
+            .iter()
+            .filter(|pkt| pkt.cc_in_flight())
+            .map(PacketInfo::from)
+            .collect::<Vec<_>>();
+        if lost.is_empty() {
+            return false;
+        }
+        for pkt in &lost {
+            // bytes_in_flight is set to 0 on a path change, so packets sent before
+            // that might no longer be counted.
+            self.bytes_in_flight = self.bytes_in_flight.saturating_sub(pkt.len);
+        }
+        let congestion = self
+            .controller
+            .on_packets_lost(&lost, pto, self.bytes_in_flight, now);
+        if congestion {
+            cc_stats.congestion_events[super::CongestionEvent::Loss] += 1;
+        }
+        self.update_stats(cc_stats);
+        self.qlog_metrics(now);
+        qdebug!("[{self}] on_packets_lost lost={}", lost.len());
+        congestion
+    }
+
+    fn on_ecn_ce_received(
+        &mut self,
+        largest_acked_pkt: &sent::Packet,
+        now: Instant,
+        cc_stats: &mut CongestionControlStats,
+    ) -> bool {
+        let congestion = self
+            .controller
+            .on_ecn_ce_received(&largest_acked_pkt.into(), now);
+        if congestion {
+            cc_stats.congestion_events[super::CongestionEvent::Ecn] += 1;
+        }
+        self.update_stats(cc_stats);
+        self.qlog_metrics(now);
+        congestion
+    }
+
+    fn recovery_packet(&self) -> bool {
+        false
+    }
+
+    fn discard(&mut self, pkt: &sent::Packet, now: Instant) {
+        if pkt.cc_outstanding() {
+            self.bytes_in_flight = self.bytes_in_flight.saturating_sub(pkt.len());
+            qlog::metrics_updated(
+                &mut self.qlog,
+                &[qlog::Metric::BytesInFlight(self.bytes_in_flight)],
+                now,
+            );
+        }
+    }
+
+    fn discard_in_flight(&mut self, now: Instant) {
+        self.bytes_in_flight = 0;
+        qlog::metrics_updated(
+            &mut self.qlog,
+            &[qlog::Metric::BytesInFlight(self.bytes_in_flight)],
+            now,
+        );
+    }
+
+    fn on_packet_sent(&mut self, pkt: &sent::Packet, now: Instant) {
+        if !pkt.cc_in_flight() {
+            return;
+        }
+        self.bytes_in_flight += pkt.len();
+        self.controller
+            .on_packet_sent(&pkt.into(), self.bytes_in_flight, now);
+        qlog::metrics_updated(
+            &mut self.qlog,
+            &[qlog::Metric::BytesInFlight(self.bytes_in_flight)],
+            now,
+        );
+    }
+}
@@ -16,14 +16,20 @@ use neqo_common::qlog::Qlog;
 
 use crate::{Pmtud, recovery::sent, rtt::RttEstimate, stats::CongestionControlStats};
 
+mod bbr;
 mod classic_cc;
 mod cubic;
+mod custom;
 mod new_reno;
 
+pub use bbr::Bbr;
 #[cfg(test)]
 pub use classic_cc::CWND_INITIAL_PKTS;
 pub use classic_cc::ClassicCongestionControl;
 pub use cubic::Cubic;
+pub use custom::{
+    CongestionController, CongestionControllerFactory, Custom, PacketInfo, RttSample,
+};
 pub use new_reno::NewReno;
 
 #[derive(Clone, Copy, PartialEq, Eq, Enum, Debug)]
@@ -58,6 +64,13 @@ pub trait CongestionControl: Display + Debug {
     #[must_use]
     fn pmtud_mut(&mut self) -> &mut Pmtud;
 
+    /// The rate at which to pace packets, in bytes per second.  When `None`, the pacer
+    /// derives the rate from the congestion window and the RTT.
+    #[must_use]
+    fn pacing_rate(&self) -> Option<usize> {
+        None
+    }
+
     fn on_packets_acked(
         &mut self,
         acked_pkts: &[sent::Packet],
@@ -103,6 +116,8 @@ pub enum CongestionControlAlgorithm {
     #[strum(serialize = "cubic")]
     #[default]
     Cubic,
+    #[strum(serialize = "bbr")]
+    Bbr,
 }
 
 #[cfg(test)]
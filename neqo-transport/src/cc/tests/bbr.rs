@@ -0,0 +1,228 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use neqo_common::qlog::Qlog;
+use test_fixture::now;
+
+use super::{IP_ADDR, MTU, RTT};
+use crate::{
+    cc::{Bbr, CongestionControl as _, CongestionEvent},
+    packet,
+    pmtud::Pmtud,
+    recovery::{self, sent},
+    rtt::{RttEstimate, RttSource},
+    stats::CongestionControlStats,
+};
+
+/// The bottleneck bandwidth, in bytes per second.
+const BW: usize = 1_250_000; // 10 Mbit/s
+
+fn bbr() -> Bbr {
+    Bbr::new(Pmtud::new(IP_ADDR, MTU))
+}
+
+fn packet(pn: packet::Number, time: Instant, len: usize) -> sent::Packet {
+    sent::Packet::new(
+        packet::Type::Short,
+        pn,
+        time,
+        true,
+        recovery::Tokens::new(),
+        len,
+    )
+}
+
+/// A sender that always has data to send, behind a bottleneck of [`BW`] with an
+/// unloaded round-trip time of [`RTT`].  Packets are acknowledged individually.
+struct Bottleneck {
+    cc: Bbr,
+    rtt_est: RttEstimate,
+    stats: CongestionControlStats,
+    now: Instant,
+    next_pn: packet::Number,
+    /// The time at which the bottleneck is next idle.
+    link_free: Instant,
+    in_flight: VecDeque<(sent::Packet, Instant)>,
+}
+
+impl Bottleneck {
+    fn new() -> Self {
+        Self {
+            cc: bbr(),
+            rtt_est: RttEstimate::new(RTT),
+            stats: CongestionControlStats::default(),
+            now: now(),
+            next_pn: 0,
+            link_free: now(),
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    fn fill(&mut self) {
+        let len = self.cc.max_datagram_size();
+        while self.cc.cwnd_avail() >= len {
+            let pkt = packet(self.next_pn, self.now, len);
+            self.next_pn += 1;
+            self.cc.on_packet_sent(&pkt, self.now);
+            let serialization = Duration::from_nanos(
+                u64::try_from(len * 1_000_000_000 / BW).expect("fits into u64"),
+            );
+            self.link_free = self.link_free.max(self.now) + serialization;
+            self.in_flight.push_back((pkt, self.link_free + RTT));
+        }
+    }
+
+    /// Run until `until` returns true, or for at most `acks` acknowledgments.
+    fn run(&mut self, acks: usize, until: impl Fn(&Bbr) -> bool) {
+        for _ in 0..acks {
+            if until(&self.cc) {
+                return;
+            }
+            self.fill();
+            let (pkt, ack_time) = self.in_flight.pop_front().expect("a packet in flight");
+            self.now = ack_time;
+            self.rtt_est.update(
+                &mut Qlog::disabled(),
+                self.now - pkt.time_sent(),
+                Duration::ZERO,
+                RttSource::Ack,
+                self.now,
+            );
+            self.cc
+                .on_packets_acked(&[pkt], &self.rtt_est, self.now, &mut self.stats);
+        }
+    }
+}
+
+#[test]
+fn initial_state() {
+    let cc = bbr();
+    assert_eq!(cc.cwnd(), cc.cwnd_initial());
+    assert_eq!(cc.bytes_in_flight(), 0);
+    assert!(cc.pacing_rate().is_none());
+    assert!(cc.in_startup());
+    assert!(!cc.recovery_packet());
+}
+
+#[test]
+fn startup_grows_cwnd() {
+    let mut cc = bbr();
+    let mut stats = CongestionControlStats::default();
+    let mut rtt_est = RttEstimate::new(RTT);
+    let t = now();
+    let pkts = (0..10)
+        .map(|pn| packet(pn, t, cc.max_datagram_size()))
+        .collect::<Vec<_>>();
+    for p in &pkts {
+        cc.on_packet_sent(p, t);
+    }
+    assert_eq!(cc.cwnd_avail(), 0);
+    let t = t + RTT;
+    rtt_est.update(
+        &mut Qlog::disabled(),
+        RTT,
+        Duration::ZERO,
+        RttSource::Ack,
+        t,
+    );
+    cc.on_packets_acked(&pkts, &rtt_est, t, &mut stats);
+    assert_eq!(cc.bytes_in_flight(), 0);
+    assert_eq!(cc.cwnd(), 2 * cc.cwnd_initial());
+    assert_eq!(cc.min_rtt(), Some(RTT));
+    assert!(cc.pacing_rate().is_some());
+    assert!(cc.in_startup());
+}
+
+#[test]
+fn startup_exits_at_bottleneck() {
+    let mut b = Bottleneck::new();
+    b.run(10_000, |cc| !cc.in_startup());
+    assert!(!b.cc.in_startup());
+    assert!(b.stats.slow_start_exited);
+
+    // The estimates should match the bottleneck.
+    let bw = b.cc.bw();
+    assert!(bw > BW * 9 / 10 && bw <= BW * 11 / 10, "bw {bw}");
+    assert!(b.cc.min_rtt().unwrap() >= RTT);
+}
+
+#[test]
+fn steady_state_tracks_bottleneck() {
+    let mut b = Bottleneck::new();
+    b.run(20_000, |_| false);
+
+    let bw = b.cc.bw();
+    assert!(bw > BW * 9 / 10 && bw <= BW * 11 / 10, "bw {bw}");
+    // The congestion window is at most a couple of BDPs, plus some allowance.
+    let bdp = BW * usize::try_from(RTT.as_millis()).unwrap() / 1000;
+    assert!(b.cc.cwnd() <= bdp * 3, "cwnd {} bdp {bdp}", b.cc.cwnd());
+    assert!(b.cc.cwnd() >= b.cc.cwnd_min());
+    // BBR does not react to the absence of loss like a loss-based controller would.
+    assert_eq!(b.stats.congestion_events[CongestionEvent::Loss], 0);
+}
+
+#[test]
+fn loss_reduces_lower_bounds() {
+    let mut b = Bottleneck::new();
+    b.run(10_000, |cc| !cc.in_startup());
+    b.run(2_000, |_| false);
+    let cwnd_before = b.cc.cwnd();
+
+    // Lose a large part of the flight.
+    b.fill();
+    let mut lost = b
+        .in_flight
+        .drain(..b.in_flight.len() / 2)
+        .map(|(p, _)| p)
+        .collect::<Vec<_>>();
+    assert!(!lost.is_empty());
+    for p in &mut lost {
+        p.declare_lost(b.now);
+    }
+    assert!(
+        !b.cc
+            .on_packets_lost(Some(now()), None, RTT * 3, &lost, b.now, &mut b.stats)
+    );
+
+    // The reaction happens once the round ends.
+    b.run(1_000, |_| false);
+    assert!(b.stats.congestion_events[CongestionEvent::Loss] > 0);
+    assert!(
+        b.cc.cwnd() <= cwnd_before,
+        "{} > {cwnd_before}",
+        b.cc.cwnd()
+    );
+}
+
+#[test]
+fn discard_in_flight() {
+    let mut cc = bbr();
+    let t = now();
+    cc.on_packet_sent(&packet(0, t, 1_000), t);
+    cc.on_packet_sent(&packet(1, t, 1_000), t);
+    assert_eq!(cc.bytes_in_flight(), 2_000);
+    cc.discard(&packet(0, t, 1_000), t);
+    assert_eq!(cc.bytes_in_flight(), 1_000);
+    cc.discard_in_flight(t);
+    assert_eq!(cc.bytes_in_flight(), 0);
+}
+
+#[test]
+fn paced_rate_floor() {
+    use crate::cc::bbr::paced_rate;
+
+    let mtu = bbr().max_datagram_size();
+    // A tiny bandwidth with a gain below one doesn't round down to zero.
+    assert_eq!(paced_rate(1, 90, mtu, Some(RTT)), mtu * 10);
+    assert_eq!(paced_rate(1, 90, mtu, None), mtu);
+    assert_eq!(paced_rate(1, 90, mtu, Some(Duration::ZERO)), 1);
+    assert_eq!(paced_rate(BW, 100, mtu, Some(RTT)), BW * 99 / 100);
+}
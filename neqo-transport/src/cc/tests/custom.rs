@@ -0,0 +1,149 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::time::{Duration, Instant};
+
+use test_fixture::now;
+
+use super::{IP_ADDR, MTU, RTT};
+use crate::{
+    cc::{
+        CongestionControl as _, CongestionController, CongestionControllerFactory, CongestionEvent,
+        Custom, PacketInfo, RttSample,
+    },
+    packet,
+    pmtud::Pmtud,
+    recovery::{self, sent},
+    rtt::RttEstimate,
+    stats::CongestionControlStats,
+};
+
+/// Halves the window on every congestion event and grows it by the number of
+/// bytes acknowledged.
+#[derive(Debug)]
+struct Halving {
+    cwnd: usize,
+    slow_start: bool,
+}
+
+impl CongestionController for Halving {
+    fn cwnd(&self) -> usize {
+        self.cwnd
+    }
+
+    fn in_slow_start(&self) -> bool {
+        self.slow_start
+    }
+
+    fn on_packets_acked(
+        &mut self,
+        acked: &[PacketInfo],
+        _rtt: &RttSample,
+        _bytes_in_flight: usize,
+        _now: Instant,
+    ) {
+        self.cwnd += acked.iter().map(|p| p.len).sum::<usize>();
+    }
+
+    fn on_packets_lost(
+        &mut self,
+        lost: &[PacketInfo],
+        _pto: Duration,
+        _bytes_in_flight: usize,
+        _now: Instant,
+    ) -> bool {
+        if lost.iter().all(|p| p.pmtud_probe) {
+            return false;
+        }
+        self.cwnd /= 2;
+        self.slow_start = false;
+        true
+    }
+}
+
+#[derive(Debug)]
+struct HalvingFactory;
+
+impl CongestionControllerFactory for HalvingFactory {
+    fn new_controller(&self, max_datagram_size: usize) -> Box<dyn CongestionController> {
+        Box::new(Halving {
+            cwnd: max_datagram_size * 10,
+            slow_start: true,
+        })
+    }
+}
+
+fn custom() -> Custom {
+    Custom::new(&HalvingFactory, Pmtud::new(IP_ADDR, MTU))
+}
+
+fn packet(pn: packet::Number, len: usize) -> sent::Packet {
+    sent::Packet::new(
+        packet::Type::Short,
+        pn,
+        now(),
+        true,
+        recovery::Tokens::new(),
+        len,
+    )
+}
+
+#[test]
+fn initial_state() {
+    let cc = custom();
+    assert_eq!(cc.cwnd(), cc.max_datagram_size() * 10);
+    assert_eq!(cc.cwnd_initial(), cc.cwnd());
+    assert_eq!(cc.bytes_in_flight(), 0);
+    assert!(cc.pacing_rate().is_none());
+}
+
+#[test]
+fn bytes_in_flight() {
+    let mut cc = custom();
+    let mut stats = CongestionControlStats::default();
+    let pkts = (0..3).map(|pn| packet(pn, 1_000)).collect::<Vec<_>>();
+    for p in &pkts {
+        cc.on_packet_sent(p, now());
+    }
+    assert_eq!(cc.bytes_in_flight(), 3_000);
+    assert_eq!(cc.cwnd_avail(), cc.cwnd() - 3_000);
+
+    let cwnd = cc.cwnd();
+    cc.on_packets_acked(&pkts[..1], &RttEstimate::new(RTT), now(), &mut stats);
+    assert_eq!(cc.bytes_in_flight(), 2_000);
+    assert_eq!(cc.cwnd(), cwnd + 1_000);
+
+    cc.discard(&pkts[1], now());
+    assert_eq!(cc.bytes_in_flight(), 1_000);
+    cc.discard_in_flight(now());
+    assert_eq!(cc.bytes_in_flight(), 0);
+}
+
+#[test]
+fn loss() {
+    let mut cc = custom();
+    let mut stats = CongestionControlStats::default();
+    let mut pkt = packet(0, 1_000);
+    cc.on_packet_sent(&pkt, now());
+    let cwnd = cc.cwnd();
+
+    pkt.declare_lost(now());
+    assert!(cc.on_packets_lost(Some(now()), None, RTT * 3, &[pkt], now(), &mut stats));
+    assert_eq!(cc.cwnd(), cwnd / 2);
+    assert_eq!(cc.bytes_in_flight(), 0);
+    assert_eq!(stats.congestion_events[CongestionEvent::Loss], 1);
+    assert!(stats.slow_start_exited);
+}
+
+#[test]
+fn ecn_ce_ignored_by_default() {
+    let mut cc = custom();
+    let mut stats = CongestionControlStats::default();
+    let pkt = packet(0, 1_000);
+    cc.on_packet_sent(&pkt, now());
+    assert!(!cc.on_ecn_ce_received(&pkt, now(), &mut stats));
+    assert_eq!(stats.congestion_events[CongestionEvent::Ecn], 0);
+}
@@ -11,7 +11,9 @@ use std::{
 
 use super::CongestionControlAlgorithm;
 
+mod bbr;
 mod cubic;
+mod custom;
 mod new_reno;
 
 pub const IP_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
@@ -22,5 +24,7 @@ pub const RTT: Duration = Duration::from_millis(100);
 fn congestion_control_algorithm_from_str() {
     assert_eq!("cubic".parse(), Ok(CongestionControlAlgorithm::Cubic));
     assert_eq!("reno".parse(), Ok(CongestionControlAlgorithm::NewReno));
+    assert_eq!("bbr".parse(), Ok(CongestionControlAlgorithm::Bbr));
+    assert_eq!("BBR".parse(), Ok(CongestionControlAlgorithm::Bbr));
     assert!("invalid".parse::<CongestionControlAlgorithm>().is_err());
 }
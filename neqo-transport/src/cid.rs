@@ -10,6 +10,7 @@ use std::{
     borrow::Borrow,
     cell::{Ref, RefCell},
     cmp::{max, min},
+    collections::{BTreeMap, BTreeSet},
     fmt::{self, Debug, Display, Formatter},
     ops::Deref,
     rc::Rc,
@@ -22,6 +23,7 @@ use smallvec::{SmallVec, smallvec};
 use crate::{
     Error, Res,
     frame::{FrameEncoder as _, FrameType},
+    multipath::PathId,
     packet, recovery,
     stateless_reset::Token as Srt,
     stats::FrameStats,
@@ -286,12 +288,41 @@ impl ConnectionIdEntry<Srt> {
         builder: &mut packet::Builder<B>,
         stats: &mut FrameStats,
     ) -> bool {
-        let len = 1 + Encoder::varint_len(self.seqno) + 1 + 1 + self.cid.len() + Srt::LEN;
+        self.write_for_path(PathId::INITIAL, builder, stats)
+    }
+
+    /// Write the entry out in a `PATH_NEW_CONNECTION_ID` frame for the identified path,
+    /// or a `NEW_CONNECTION_ID` frame for the initial path.
+    /// Returns `true` if the frame was written, `false` if there is insufficient space.
+    pub fn write_for_path<B: Buffer>(
+        &self,
+        path_id: PathId,
+        builder: &mut packet::Builder<B>,
+        stats: &mut FrameStats,
+    ) -> bool {
+        let (frame_type, path_len) = if path_id.is_initial() {
+            (FrameType::NewConnectionId, 0)
+        } else {
+            (
+                FrameType::PathNewConnectionId,
+                Encoder::varint_len(path_id.as_u64()),
+            )
+        };
+        let len = Encoder::varint_len(u64::from(frame_type))
+            + path_len
+            + Encoder::varint_len(self.seqno)
+            + 1
+            + 1
+            + self.cid.len()
+            + Srt::LEN;
         if builder.remaining() < len {
             return false;
         }
 
-        builder.encode_frame(FrameType::NewConnectionId, |b| {
+        builder.encode_frame(frame_type, |b| {
+            if !path_id.is_initial() {
+                b.encode_varint(path_id.as_u64());
+            }
             b.encode_varint(self.seqno);
             b.encode_varint(0u64);
             b.encode_vec(1, &self.cid);
@@ -446,6 +477,22 @@ pub struct ConnectionIdManager {
     next_seqno: u64,
     /// Outstanding, but lost `NEW_CONNECTION_ID` frames will be stored here.
     lost_new_connection_id: Vec<ConnectionIdEntry<Srt>>,
+    /// When multipath is used, each path other than the initial path has
+    /// its own set of connection IDs.
+    paths: BTreeMap<PathId, PathConnectionIds>,
+    /// The largest path identifier that connection IDs can be issued for.
+    max_path_id: PathId,
+    /// Paths that have been closed, which will not get any more connection IDs.
+    closed_paths: BTreeSet<PathId>,
+    /// Outstanding, but lost `PATH_NEW_CONNECTION_ID` frames.
+    lost_path_new_connection_id: Vec<(PathId, ConnectionIdEntry<Srt>)>,
+}
+
+/// The connection IDs that have been issued for a single path, for multipath.
+#[derive(Debug, Default)]
+struct PathConnectionIds {
+    connection_ids: ConnectionIdStore<()>,
+    next_seqno: u64,
 }
 
 impl ConnectionIdManager {
@@ -475,6 +522,10 @@ impl ConnectionIdManager {
             limit: 2,
             next_seqno: 1,
             lost_new_connection_id: Vec::new(),
+            paths: BTreeMap::new(),
+            max_path_id: PathId::INITIAL,
+            closed_paths: BTreeSet::new(),
+            lost_path_new_connection_id: Vec::new(),
         }
     }
 
@@ -510,7 +561,40 @@ impl ConnectionIdManager {
     }
 
     pub fn is_valid(&self, cid: ConnectionIdRef) -> bool {
-        self.connection_ids.contains(cid)
+        self.path_id(cid).is_some()
+    }
+
+    /// Find the path that a connection ID was issued for.
+    /// Connection IDs that are not tied to a specific path are for the initial path.
+    pub fn path_id(&self, cid: ConnectionIdRef) -> Option<PathId> {
+        if self.connection_ids.contains(cid) {
+            return Some(PathId::INITIAL);
+        }
+        self.paths
+            .iter()
+            .find_map(|(id, p)| p.connection_ids.contains(cid).then_some(*id))
+    }
+
+    /// Allow connection IDs to be issued for paths up to and including `max_path_id`.
+    pub fn set_max_path_id(&mut self, max_path_id: PathId) {
+        self.max_path_id = max(self.max_path_id, max_path_id);
+    }
+
+    /// Retire a connection ID that was issued for a specific path.
+    pub fn retire_path_cid(&mut self, path_id: PathId, seqno: u64) {
+        if let Some(p) = self.paths.get_mut(&path_id) {
+            p.connection_ids.retire(seqno);
+        }
+        self.lost_path_new_connection_id
+            .retain(|(id, e)| *id != path_id || e.seqno != seqno);
+    }
+
+    /// Stop using all connection IDs for a path, which has been closed.
+    pub fn close_path(&mut self, path_id: PathId) {
+        self.paths.remove(&path_id);
+        self.closed_paths.insert(path_id);
+        self.lost_path_new_connection_id
+            .retain(|(id, _)| *id != path_id);
     }
 
     pub fn retire(&mut self, seqno: u64) {
@@ -600,6 +684,65 @@ impl ConnectionIdManager {
         }
     }
 
+    /// Write `PATH_NEW_CONNECTION_ID` frames for paths other than the initial path.
+    pub fn write_path_frames<B: Buffer>(
+        &mut self,
+        builder: &mut packet::Builder<B>,
+        tokens: &mut recovery::Tokens,
+        stats: &mut FrameStats,
+    ) {
+        if self.max_path_id.is_initial() || self.generator.deref().borrow().generates_empty_cids() {
+            return;
+        }
+
+        while let Some((path_id, entry)) = self.lost_path_new_connection_id.pop() {
+            if entry.write_for_path(path_id, builder, stats) {
+                tokens.push(recovery::Token::PathNewConnectionId(path_id, entry));
+            } else {
+                self.lost_path_new_connection_id.push((path_id, entry));
+                break;
+            }
+        }
+
+        let mut path_id = PathId::INITIAL.next();
+        while path_id <= self.max_path_id {
+            if !self.closed_paths.contains(&path_id) {
+                let path = self.paths.entry(path_id).or_default();
+                // As above, but allowing for a longer frame type and the path ID.
+                while path.connection_ids.len() < self.limit && builder.remaining() >= 54 {
+                    let Some(cid) = self.generator.borrow_mut().generate_cid() else {
+                        break;
+                    };
+                    assert_ne!(cid.len(), 0);
+                    let seqno = path.next_seqno;
+                    path.next_seqno += 1;
+                    path.connection_ids
+                        .add_local(ConnectionIdEntry::new(seqno, cid.clone(), ()));
+                    let entry = ConnectionIdEntry::new(seqno, cid, Srt::random());
+                    entry.write_for_path(path_id, builder, stats);
+                    tokens.push(recovery::Token::PathNewConnectionId(path_id, entry));
+                }
+            }
+            path_id = path_id.next();
+        }
+    }
+
+    pub fn path_lost(&mut self, path_id: PathId, entry: &ConnectionIdEntry<Srt>) {
+        let active = self
+            .paths
+            .get(&path_id)
+            .is_some_and(|p| p.connection_ids.cids.iter().any(|c| c.seqno == entry.seqno));
+        if active {
+            self.lost_path_new_connection_id
+                .push((path_id, entry.clone()));
+        }
+    }
+
+    pub fn path_acked(&mut self, path_id: PathId, entry: &ConnectionIdEntry<Srt>) {
+        self.lost_path_new_connection_id
+            .retain(|(id, e)| *id != path_id || e.seqno != entry.seqno);
+    }
+
     pub fn lost(&mut self, entry: &ConnectionIdEntry<Srt>) {
         self.lost_new_connection_id.push(entry.clone());
     }
@@ -22,8 +22,9 @@ use neqo_common::{
     hex, hex_snip_middle, hex_with_len, hrtime, qdebug, qerror, qinfo, qlog::Qlog, qtrace, qwarn,
 };
 use neqo_crypto::{
-    Agent, AntiReplay, AuthenticationStatus, Cipher, Client, Group, HandshakeState, PrivateKey,
-    PublicKey, ResumptionToken, SecretAgentInfo, SecretAgentPreInfo, Server, ZeroRttChecker,
+    Agent, AntiReplay, AuthenticationStatus, Cipher, Client, Group, HandshakeState, KeyLog,
+    PrivateKey, PublicKey, ResumptionToken, SecretAgentInfo, SecretAgentPreInfo, Server,
+    ZeroRttChecker,
     agent::{CertificateCompressor, CertificateInfo},
 };
 use smallvec::SmallVec;
@@ -40,10 +41,12 @@ use crate::{
     ecn,
     events::{ConnectionEvent, ConnectionEvents, OutgoingDatagramOutcome},
     frame::{CloseError, Frame, FrameEncoder as _, FrameType},
+    multipath::{self, LowestRtt, Multipath, PathId, PathInfo, PathStatus},
     packet::{self},
     path::{Path, PathRef, Paths},
     qlog,
     quic_datagrams::{DATAGRAM_FRAME_TYPE_VARINT_LEN, DatagramTracking, QuicDatagrams},
+    recorder::{Direction, Recorder},
     recovery::{self, SendProfile, sent},
     recv_stream,
     rtt::{GRANULARITY, RttEstimate},
@@ -57,8 +60,9 @@ use crate::{
         self,
         TransportParameterId::{
             self, AckDelayExponent, ActiveConnectionIdLimit, DisableMigration, GreaseQuicBit,
-            InitialSourceConnectionId, MaxAckDelay, MaxDatagramFrameSize, MinAckDelay,
-            OriginalDestinationConnectionId, RetrySourceConnectionId, StatelessResetToken,
+            InitialMaxPathId, InitialSourceConnectionId, MaxAckDelay, MaxDatagramFrameSize,
+            MinAckDelay, OriginalDestinationConnectionId, ResetStreamAt, RetrySourceConnectionId,
+            StatelessResetToken,
         },
         TransportParameters, TransportParametersHandler,
     },
@@ -313,10 +317,15 @@ pub struct Connection {
     streams: Streams,
     state_signaling: StateSignaling,
     loss_recovery: recovery::Loss,
+    /// The state of the multipath extension, if it was negotiated.
+    multipath: Option<Multipath>,
+    /// Whether this connection uses the latency spin bit.
+    spin_bit: bool,
     events: ConnectionEvents,
     new_token: NewTokenState,
     stats: StatsCell,
     qlog: Qlog,
+    recorder: Option<Recorder>,
     /// A session ticket was received without `NEW_TOKEN`,
     /// this is when that turns into an event without `NEW_TOKEN`.
     release_resumption_token_timer: Option<Instant>,
@@ -372,6 +381,7 @@ impl Connection {
             Role::Client,
             &dcid,
             c.conn_params.randomize_first_pn_enabled(),
+            now,
         )?;
         c.original_destination_cid = Some(dcid);
         let path = Path::temporary(
@@ -461,10 +471,13 @@ impl Connection {
             cids: ConnectionIdStore::default(),
             state_signaling: StateSignaling::Idle,
             loss_recovery: recovery::Loss::new(stats.clone(), conn_params.get_fast_pto()),
+            multipath: None,
+            spin_bit: conn_params.get_spin_bit().enabled(),
             events,
             new_token: NewTokenState::new(role),
             stats,
             qlog: Qlog::disabled(),
+            recorder: None,
             release_resumption_token_timer: None,
             conn_params,
             hrtime: hrtime::Time::get(Self::LOOSE_TIMER_RESOLUTION),
@@ -522,9 +535,31 @@ impl Connection {
     pub fn set_qlog(&mut self, qlog: Qlog) {
         self.loss_recovery.set_qlog(qlog.clone());
         self.paths.set_qlog(qlog.clone());
+        self.crypto.set_qlog(qlog.clone());
         self.qlog = qlog;
     }
 
+    /// Record every datagram that this connection sends or receives from now
+    /// on, so that the exchange can be replayed later.  `None` stops recording.
+    ///
+    /// Unless [`Connection::set_keylog`] was used, the TLS secrets are also
+    /// recorded, so that [`crate::recorder::Capture::decrypt`] can read the
+    /// packets.  That needs this to be called before the handshake starts.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        if let Some(r) = &recorder
+            && self.crypto.tls().keylog().is_none()
+        {
+            self.crypto.set_keylog(r.keylog());
+        }
+        self.recorder = recorder;
+    }
+
+    /// Write the TLS secrets of this connection to `keylog`, including those
+    /// from key updates.  This needs to be called before the handshake starts.
+    pub fn set_keylog(&mut self, keylog: KeyLog) {
+        self.crypto.set_keylog(keylog);
+    }
+
     /// Get the qlog (if any) for this connection.
     pub const fn qlog_mut(&mut self) -> &mut Qlog {
         &mut self.qlog
@@ -686,7 +721,8 @@ impl Connection {
     /// Get the simplest PTO calculation for all those cases where we need
     /// a value of this approximate order.  Don't use this for loss recovery,
     /// only use it where a more precise value is not important.
-    fn pto(&self) -> Duration {
+    #[must_use]
+    pub fn pto(&self) -> Duration {
         self.paths.primary().map_or_else(
             || RttEstimate::new(self.conn_params.get_initial_rtt()).pto(self.confirmed()),
             |p| p.borrow().rtt().pto(self.confirmed()),
@@ -893,6 +929,12 @@ impl Connection {
         Ok(self.crypto.tls().preinfo()?)
     }
 
+    /// Get the name of the server.  This is only available for a client.
+    #[must_use]
+    pub fn server_name(&self) -> Option<&str> {
+        self.crypto.server_name()
+    }
+
     /// Get the peer's certificate chain and other info.
     #[must_use]
     pub fn peer_certificate(&self) -> Option<CertificateInfo> {
@@ -1057,7 +1099,7 @@ impl Connection {
 
         if let Some(path) = self.paths.primary() {
             let lost = self.loss_recovery.timeout(&path, now);
-            self.handle_lost_packets(&lost);
+            self.handle_lost_packets(&lost, now);
             qlog::packets_lost(&mut self.qlog, &lost, now);
         }
 
@@ -1071,6 +1113,48 @@ impl Connection {
         {
             qinfo!("[{self}] last available path failed");
             self.absorb_error::<Error>(now, Err(Error::NoAvailablePath));
+            return;
+        }
+
+        self.process_multipath_timer(now, pto);
+    }
+
+    /// Run timers for multipath paths: loss recovery, cleanup of failed paths,
+    /// and removal of paths that were abandoned.
+    fn process_multipath_timer(&mut self, now: Instant, pto: Duration) {
+        let Some(mp) = self.multipath.as_mut() else {
+            return;
+        };
+        let mut lost = Vec::new();
+        for (_, space) in mp.spaces_mut() {
+            lost.append(&mut space.timeout(now));
+        }
+        // Paths that failed validation are no longer tracked by `Paths`.
+        let failed = mp
+            .spaces()
+            .filter(|(_, s)| !s.is_abandoned() && self.paths.is_temporary(s.path()))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        for path_id in failed {
+            qinfo!("Multipath path {path_id} failed");
+            lost.append(&mut mp.abandon(path_id, multipath::PATH_UNSTABLE_OR_POOR, now));
+        }
+        for (path_id, path) in mp.remove_abandoned(now, pto) {
+            qinfo!("[{}] Removing path {path_id}", path.borrow());
+            self.paths.remove(&path);
+            self.cid_manager.close_path(path_id);
+            self.events.path_closed(path_id);
+        }
+        self.handle_lost_packets(&lost, now);
+        qlog::packets_lost(&mut self.qlog, &lost, now);
+    }
+
+    /// Tell the application about multipath paths that can be used.
+    fn report_opened_paths(&mut self) {
+        if let Some(mp) = self.multipath.as_mut() {
+            for path_id in mp.take_opened() {
+                self.events.path_opened(path_id);
+            }
         }
     }
 
@@ -1138,7 +1222,7 @@ impl Connection {
                 delays.push(keep_alive_time);
             }
 
-            if let Some(lr_time) = self.loss_recovery.next_timeout(&path) {
+            if let Some(lr_time) = self.loss_recovery.update_timer(&path, now) {
                 qtrace!("[{self}] Loss recovery timer {lr_time:?}");
                 delays.push(lr_time);
             }
@@ -1152,6 +1236,29 @@ impl Connection {
                 qtrace!("[{self}] Path probe timer {path_time:?}");
                 delays.push(path_time);
             }
+
+            if let Some(mp) = &self.multipath {
+                for (_, space) in mp.spaces() {
+                    if let Some(lr_time) = space.next_timeout() {
+                        delays.push(lr_time);
+                    }
+                    let path = space.path().borrow();
+                    if paced
+                        && let Some(pace_time) = path
+                            .sender()
+                            .next_paced(path.rtt().estimate())
+                            .filter(|t| *t > now)
+                    {
+                        delays.push(pace_time);
+                    }
+                }
+                if let Some(ack_time) = mp.ack_time(now) {
+                    delays.push(ack_time);
+                }
+                if let Some(removal_time) = mp.next_removal(pto) {
+                    delays.push(removal_time);
+                }
+            }
         }
 
         if let Some(key_update_time) = self.crypto.states().update_time() {
@@ -1210,7 +1317,14 @@ impl Connection {
         }
 
         match self.output(now, max_datagrams) {
-            SendOptionBatch::Yes(dgram) => OutputBatch::DatagramBatch(dgram),
+            SendOptionBatch::Yes(dgram) => {
+                if let Some(recorder) = &self.recorder {
+                    for d in dgram.iter() {
+                        recorder.record(Direction::Sent, &d, now);
+                    }
+                }
+                OutputBatch::DatagramBatch(dgram)
+            }
             SendOptionBatch::No(paced) => match self.state {
                 State::Init | State::Closed(_) => OutputBatch::None,
                 State::Closing { timeout, .. } | State::Draining { timeout, .. } => {
@@ -1310,13 +1424,14 @@ impl Connection {
         );
 
         let lost_packets = self.loss_recovery.retry(&path, now);
-        self.handle_lost_packets(&lost_packets);
+        self.handle_lost_packets(&lost_packets, now);
 
         self.crypto.states_mut().init(
             self.conn_params.get_versions().compatible(),
             self.role,
             &retry_scid,
             false, // don't randomize on Retry
+            now,
         )?;
         self.address_validation = AddressValidationInfo::Retry {
             token: packet.token().to_vec(),
@@ -1326,7 +1441,7 @@ impl Connection {
     }
 
     fn discard_keys(&mut self, space: PacketNumberSpace, now: Instant) {
-        if self.crypto.discard(space) {
+        if self.crypto.discard(space, now) {
             qdebug!("[{self}] Drop packet number space {space}");
             if let Some(path) = self.paths.primary() {
                 self.loss_recovery.discard(&path, space, now);
@@ -1512,6 +1627,7 @@ impl Connection {
                     version,
                     &dcid,
                     self.conn_params.randomize_first_pn_enabled(),
+                    now,
                 )?;
                 self.original_destination_cid = Some(dcid);
                 self.set_state(State::WaitInitial, now);
@@ -1632,6 +1748,7 @@ impl Connection {
     fn postprocess_packet(
         &mut self,
         path: &PathRef,
+        path_id: Option<PathId>,
         tos: Tos,
         remote: SocketAddr,
         packet: &packet::Decrypted,
@@ -1652,12 +1769,30 @@ impl Connection {
         stats.ecn_last_mark = Some(ecn_mark);
         drop(stats);
         let space = PacketNumberSpace::from(packet.packet_type());
-        if let Some(space) = self.acks.get_mut(space) {
-            *space.ecn_marks() += ecn_mark;
+        let recvd = if let Some(path_id) = path_id {
+            self.multipath
+                .as_mut()
+                .and_then(|mp| mp.space_mut(path_id))
+                .map(multipath::PathSpace::recvd_mut)
+        } else {
+            self.acks.get_mut(space)
+        };
+        if let Some(recvd) = recvd {
+            *recvd.ecn_marks() += ecn_mark;
         } else {
             qtrace!("Not tracking ECN for dropped packet number space");
         }
 
+        if self.spin_bit && packet.packet_type() == packet::Type::Short {
+            path.borrow_mut().spin_received(
+                self.role,
+                packet_number,
+                packet.spin(),
+                &mut self.stats.borrow_mut(),
+                now,
+            );
+        }
+
         if self.state == State::WaitInitial {
             self.start_handshake(path, packet, now);
         }
@@ -1671,7 +1806,7 @@ impl Connection {
             self.set_state(new_state, now);
             if self.role == Role::Server && self.state == State::Handshaking {
                 self.zero_rtt_state =
-                    if self.crypto.enable_0rtt(self.version, self.role) == Ok(true) {
+                    if self.crypto.enable_0rtt(self.version, self.role, now) == Ok(true) {
                         qdebug!("[{self}] Accepted 0-RTT");
                         ZeroRttState::AcceptedServer
                     } else {
@@ -1700,6 +1835,9 @@ impl Connection {
         received: Instant,
         now: Instant,
     ) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(Direction::Received, &d, received);
+        }
         // First determine the path.
         let path = self.paths.find_path(
             d.destination(),
@@ -1711,8 +1849,10 @@ impl Connection {
         path.borrow_mut().add_received(d.len());
         let res = self.input_path(&path, d, received);
         _ = self.capture_error(Some(path), now, FrameType::Padding, res);
+        self.report_opened_paths();
     }
 
+    #[expect(clippy::too_many_lines, reason = "Multipath added a few lines.")]
     fn input_path(
         &mut self,
         path: &PathRef,
@@ -1752,8 +1892,21 @@ impl Connection {
 
             qtrace!("[{self}] Received unverified packet {packet:?}");
 
+            // Packets for multipath paths use the packet number space for that path.
+            let mp_path = if let Some(path_id) = self.multipath_path_id(&packet) {
+                let Some(expected_pn) = self.multipath_expected_pn(path, path_id) else {
+                    self.stats
+                        .borrow_mut()
+                        .pkt_dropped(format!("Unable to use path {path_id}"));
+                    break;
+                };
+                Some((path_id, expected_pn))
+            } else {
+                None
+            };
+
             let packet_len = packet.len();
-            match packet.decrypt(self.crypto.states_mut(), now + pto) {
+            match packet.decrypt_for_path(self.crypto.states_mut(), mp_path, now, now + pto) {
                 Ok(payload) => {
                     // OK, we have a valid packet.
                     let pn = payload.pn();
@@ -1774,15 +1927,17 @@ impl Connection {
                     }
 
                     let space = PacketNumberSpace::from(payload.packet_type());
-                    if let Some(space) = self.acks.get_mut(space) {
+                    if let Some((path_id, _)) = mp_path {
+                        self.input_multipath_packet(path, path_id, tos, remote, &payload, now)?;
+                    } else if let Some(space) = self.acks.get_mut(space) {
                         if space.is_duplicate(pn) {
                             qdebug!("Duplicate packet {space}-{pn}");
                             self.stats.borrow_mut().dups_rx += 1;
                         } else {
-                            match self.process_packet(path, &payload, now) {
+                            match self.process_packet(path, None, &payload, now) {
                                 Ok(migrate) => {
                                     self.postprocess_packet(
-                                        path, tos, remote, &payload, pn, migrate, now,
+                                        path, None, tos, remote, &payload, pn, migrate, now,
                                     );
                                 }
                                 Err(e) => {
@@ -1806,6 +1961,7 @@ impl Connection {
                             // This packet can't be decrypted because we don't have the keys yet.
                             // Don't check this packet for a stateless reset, just return.
                             let remaining = slc_len;
+                            qlog::packet_buffered(&mut self.qlog, &e, now);
                             self.save_datagram(epoch, d, remaining, now);
                             return Ok(());
                         }
@@ -1831,6 +1987,82 @@ impl Connection {
         Ok(())
     }
 
+    /// Determine if a packet is for a multipath path other than the initial path.
+    fn multipath_path_id(&self, packet: &packet::Public) -> Option<PathId> {
+        if self.multipath.is_none() || packet.packet_type() != packet::Type::Short {
+            return None;
+        }
+        self.cid_manager
+            .path_id(packet.dcid())
+            .filter(|path_id| !path_id.is_initial())
+    }
+
+    /// Find the packet number that is next expected on a multipath path.
+    /// This returns `None` if packets for that path can't be accepted on `path`.
+    fn multipath_expected_pn(&self, path: &PathRef, path_id: PathId) -> Option<packet::Number> {
+        let mp = self.multipath.as_ref()?;
+        mp.space(path_id).map_or_else(
+            // Only servers accept new paths from their peer.
+            || (self.role == Role::Server && !mp.is_closed(path_id)).then_some(0),
+            |space| Rc::ptr_eq(space.path(), path).then(|| space.expected_pn()),
+        )
+    }
+
+    /// Process a packet that was received on a multipath path other than the initial path.
+    /// If this is the first packet for that path, the path is added.
+    fn input_multipath_packet(
+        &mut self,
+        path: &PathRef,
+        path_id: PathId,
+        tos: Tos,
+        remote: SocketAddr,
+        packet: &packet::Decrypted,
+        now: Instant,
+    ) -> Res<()> {
+        let mp = self.multipath.as_mut().ok_or(Error::Internal)?;
+        if mp.space(path_id).is_none() {
+            if !self.paths.is_temporary(path) {
+                // This could be NAT rebinding, but that is hard to tell apart
+                // from the peer using the same addresses for multiple paths.
+                self.stats
+                    .borrow_mut()
+                    .pkt_dropped(format!("Path {path_id} on an existing path"));
+                return Ok(());
+            }
+            let Some(cid) = mp.take_remote_cid(path_id) else {
+                qinfo!("[{}] No connection ID for path {path_id}", path.borrow());
+                self.stats
+                    .borrow_mut()
+                    .pkt_dropped(format!("No connection ID for path {path_id}"));
+                return Ok(());
+            };
+            self.paths
+                .add_multipath(path, path_id, cid, now, &mut self.stats.borrow_mut());
+            mp.add_space(path_id, Rc::clone(path), now);
+        }
+
+        let space = mp.space(path_id).ok_or(Error::Internal)?;
+        if space.recvd().is_duplicate(packet.pn()) {
+            qdebug!("Duplicate packet {}-{}", space.recvd(), packet.pn());
+            self.stats.borrow_mut().dups_rx += 1;
+            return Ok(());
+        }
+        self.process_packet(path, Some(path_id), packet, now)?;
+        // Packets on these paths never cause migration, but they do keep the path alive.
+        self.postprocess_packet(
+            path,
+            Some(path_id),
+            tos,
+            remote,
+            packet,
+            packet.pn(),
+            false,
+            now,
+        );
+        path.borrow_mut().update(now);
+        Ok(())
+    }
+
     /// Handle receiving a packet for which keys have been discarded.
     fn handle_keys_discarded(&mut self, epoch: Epoch) {
         // Client: receiving undecryptable Initial packets while waiting
@@ -1846,9 +2078,11 @@ impl Connection {
     }
 
     /// Process a packet.  Returns true if the packet might initiate migration.
+    /// For packets on multipath paths, `path_id` identifies the path.
     fn process_packet(
         &mut self,
         path: &PathRef,
+        path_id: Option<PathId>,
         packet: &packet::Decrypted,
         now: Instant,
     ) -> Res<bool> {
@@ -1896,10 +2130,16 @@ impl Connection {
             }
         }
 
-        let largest_received = if let Some(space) = self
-            .acks
-            .get_mut(PacketNumberSpace::from(packet.packet_type()))
-        {
+        let recvd = if let Some(path_id) = path_id {
+            self.multipath
+                .as_mut()
+                .and_then(|mp| mp.space_mut(path_id))
+                .map(multipath::PathSpace::recvd_mut)
+        } else {
+            self.acks
+                .get_mut(PacketNumberSpace::from(packet.packet_type()))
+        };
+        let largest_received = if let Some(space) = recvd {
             space.set_received(
                 now,
                 packet.pn(),
@@ -1920,7 +2160,7 @@ impl Connection {
             false
         };
 
-        Ok(largest_received && !probing)
+        Ok(largest_received && !probing && path_id.is_none())
     }
 
     /// During connection setup, the first path needs to be setup.
@@ -2057,17 +2297,7 @@ impl Connection {
         let local = local.unwrap_or_else(|| path.borrow().local_address());
         let remote = remote.unwrap_or_else(|| path.borrow().remote_address());
 
-        if mem::discriminant(&local.ip()) != mem::discriminant(&remote.ip()) {
-            // Can't mix address families.
-            return Err(Error::InvalidMigration);
-        }
-        if local.port() == 0 || remote.ip().is_unspecified() || remote.port() == 0 {
-            // All but the local address need to be specified.
-            return Err(Error::InvalidMigration);
-        }
-        if (local.ip().is_loopback() ^ remote.ip().is_loopback()) && !local.ip().is_unspecified() {
-            // Block attempts to migrate to a path with loopback on only one end, unless the local
-            // address is unspecified.
+        if !Self::valid_path_addresses(local, remote) {
             return Err(Error::InvalidMigration);
         }
 
@@ -2093,6 +2323,122 @@ impl Connection {
         Ok(())
     }
 
+    /// Check that a pair of addresses can be used for a new path.
+    fn valid_path_addresses(local: SocketAddr, remote: SocketAddr) -> bool {
+        // Can't mix address families.
+        mem::discriminant(&local.ip()) == mem::discriminant(&remote.ip())
+            // All but the local address need to be specified.
+            && local.port() != 0
+            && !remote.ip().is_unspecified()
+            && remote.port() != 0
+            // Block paths with loopback on only one end, unless the local
+            // address is unspecified.
+            && (local.ip().is_loopback() == remote.ip().is_loopback()
+                || local.ip().is_unspecified())
+    }
+
+    /// Whether the multipath extension was negotiated.
+    #[must_use]
+    pub const fn multipath_enabled(&self) -> bool {
+        self.multipath.is_some()
+    }
+
+    /// Open a new path using the multipath extension.  Unlike migration,
+    /// the existing path continues to be used.  The new path is probed
+    /// before it is used for sending; a [`ConnectionEvent::PathOpened`] event
+    /// is generated once that succeeds.
+    ///
+    /// # Errors
+    ///
+    /// `Error::WrongRole` if this is a server; only clients open paths.
+    /// `Error::NotAvailable` if multipath was not negotiated.
+    /// `Error::InvalidMigration` if the connection is not confirmed, the addresses
+    /// are not valid, or the addresses are already in use.
+    /// `Error::ConnectionIdsExhausted` if no path identifier or connection ID is available.
+    pub fn open_path(
+        &mut self,
+        local: SocketAddr,
+        remote: SocketAddr,
+        now: Instant,
+    ) -> Res<PathId> {
+        if self.role != Role::Client {
+            return Err(Error::WrongRole);
+        }
+        if self.multipath.is_none() {
+            return Err(Error::NotAvailable);
+        }
+        if !matches!(self.state(), State::Confirmed) || !Self::valid_path_addresses(local, remote) {
+            return Err(Error::InvalidMigration);
+        }
+        let path = self.paths.find_path(
+            local,
+            remote,
+            &self.conn_params,
+            now,
+            &mut self.stats.borrow_mut(),
+        );
+        if !self.paths.is_temporary(&path) {
+            return Err(Error::InvalidMigration);
+        }
+
+        let mp = self.multipath.as_mut().ok_or(Error::NotAvailable)?;
+        let path_id = mp.next_path_id().ok_or(Error::ConnectionIdsExhausted)?;
+        let cid = mp
+            .take_remote_cid(path_id)
+            .ok_or(Error::ConnectionIdsExhausted)?;
+        qinfo!("[{}] Opening path {path_id}", path.borrow());
+        self.paths
+            .add_multipath(&path, path_id, cid, now, &mut self.stats.borrow_mut());
+        mp.add_space(path_id, path, now);
+        Ok(path_id)
+    }
+
+    /// The identifiers of the multipath paths that can be used for sending.
+    /// This doesn't include the initial path.
+    #[must_use]
+    pub fn path_ids(&self) -> Vec<PathId> {
+        self.multipath.as_ref().map_or_else(Vec::new, |mp| {
+            mp.spaces()
+                .filter_map(|(id, s)| s.is_usable().then_some(id))
+                .collect()
+        })
+    }
+
+    /// Set the status of a multipath path.  The peer is told about this status,
+    /// so that both endpoints only use a backup path if no other path is available.
+    ///
+    /// # Errors
+    ///
+    /// `Error::NotAvailable` if multipath was not negotiated.
+    /// `Error::InvalidInput` if the path doesn't exist or was abandoned.
+    pub fn set_path_status(&mut self, path_id: PathId, status: PathStatus) -> Res<()> {
+        self.multipath
+            .as_mut()
+            .ok_or(Error::NotAvailable)?
+            .set_local_status(path_id, status)
+    }
+
+    /// Stop using a multipath path.  Anything that was outstanding on the path
+    /// is sent again using other paths.  A [`ConnectionEvent::PathClosed`] event
+    /// is generated when the path is removed.
+    ///
+    /// # Errors
+    ///
+    /// `Error::NotAvailable` if multipath was not negotiated.
+    /// `Error::InvalidInput` if the path doesn't exist or was already abandoned.
+    pub fn abandon_path(&mut self, path_id: PathId, now: Instant) -> Res<()> {
+        let mp = self.multipath.as_mut().ok_or(Error::NotAvailable)?;
+        if mp
+            .space(path_id)
+            .is_none_or(multipath::PathSpace::is_abandoned)
+        {
+            return Err(Error::InvalidInput);
+        }
+        let lost = mp.abandon(path_id, multipath::APPLICATION_ABANDON_PATH, now);
+        self.handle_lost_packets(&lost, now);
+        Ok(())
+    }
+
     fn migrate_to_preferred_address(&mut self, now: Instant) -> Res<()> {
         let spa: Option<(tparams::PreferredAddress, ConnectionIdEntry<Srt>)> = if matches!(
             self.conn_params.get_preferred_address(),
@@ -2175,11 +2521,27 @@ impl Connection {
             | State::WaitVersion
             | State::Handshaking
             | State::Connected
-            | State::Confirmed => self.paths.select_path().map_or_else(
+            | State::Confirmed => self.select_output_path(now).map_or_else(
                 || Ok(SendOptionBatch::default()),
                 |path| {
                     let res = self.output_dgram_batch_on_path(&path, now, None, max_datagrams);
-                    self.capture_error(Some(path), now, FrameType::Padding, res)
+                    let res =
+                        self.capture_error(Some(Rc::clone(&path)), now, FrameType::Padding, res);
+                    match (res, self.paths.primary()) {
+                        // A multipath path might have nothing to send, when the primary path does.
+                        (Ok(SendOptionBatch::No(paced)), Some(primary))
+                            if self.multipath.is_some() && !Rc::ptr_eq(&path, &primary) =>
+                        {
+                            let res =
+                                self.output_dgram_batch_on_path(&primary, now, None, max_datagrams);
+                            self.capture_error(Some(primary), now, FrameType::Padding, res)
+                                .map(|res| match res {
+                                    SendOptionBatch::No(p) => SendOptionBatch::No(p || paced),
+                                    res @ SendOptionBatch::Yes(_) => res,
+                                })
+                        }
+                        (res, _) => res,
+                    }
                 },
             ),
             State::Closing { .. } | State::Draining { .. } | State::Closed(_) => {
@@ -2210,6 +2572,51 @@ impl Connection {
         res.unwrap_or_default()
     }
 
+    /// Select a path to send on.  Paths that need to be probed come first.
+    /// With multipath, any path that has a pending PTO is next.  Otherwise, the
+    /// [`crate::PathScheduler`] chooses from the available paths that are able to send.
+    /// Paths with a status of backup are only used for probing.
+    fn select_output_path(&self, now: Instant) -> Option<PathRef> {
+        let selected = self.paths.select_path();
+        let Some(mp) = self.multipath.as_ref() else {
+            return selected;
+        };
+        let primary = self.paths.primary()?;
+        if selected.as_ref().is_some_and(|p| !Rc::ptr_eq(p, &primary)) {
+            return selected;
+        }
+        if self.loss_recovery.pto_pending() {
+            return Some(primary);
+        }
+        if let Some((_, space)) = mp.spaces().find(|(_, s)| s.pto_pending()) {
+            return Some(Rc::clone(space.path()));
+        }
+
+        let can_send = |p: &PathRef| {
+            let p = p.borrow();
+            p.sender().cwnd_avail() > 0
+                && p.sender()
+                    .next_paced(p.rtt().estimate())
+                    .is_none_or(|t| t <= now)
+        };
+        let (info, paths): (Vec<_>, Vec<_>) = iter::once((PathId::INITIAL, Rc::clone(&primary)))
+            .chain(
+                mp.spaces()
+                    .filter(|(_, s)| s.is_usable() && s.status() == PathStatus::Available)
+                    .map(|(id, s)| (id, Rc::clone(s.path()))),
+            )
+            .filter(|(_, p)| can_send(p))
+            .map(|(id, p)| (PathInfo::new(id, &p), p))
+            .unzip();
+        if paths.is_empty() {
+            return Some(primary);
+        }
+        let scheduler = self.conn_params.get_path_scheduler().unwrap_or(&LowestRtt);
+        let i = scheduler.select(&info);
+        let i = if i < paths.len() { i } else { 0 };
+        paths.into_iter().nth(i)
+    }
+
     #[expect(clippy::too_many_arguments, reason = "no easy way to simplify")]
     fn build_packet_header<'a>(
         path: &Path,
@@ -2219,6 +2626,7 @@ impl Connection {
         address_validation: &AddressValidationInfo,
         version: Version,
         grease_quic_bit: bool,
+        spin_bit: bool,
         limit: usize,
         largest_acknowledged: Option<packet::Number>,
     ) -> (
@@ -2247,24 +2655,32 @@ impl Connection {
         };
         if builder.remaining() > 0 {
             builder.scramble(grease_quic_bit);
+            if spin_bit {
+                builder.spin(path.spin());
+            }
             if pt == packet::Type::Initial {
                 builder.initial_token(address_validation.token());
             }
         }
 
         let pn = tx.next_pn();
+        builder.pn(pn, Self::pn_len(pn, largest_acknowledged));
+
+        (pt, builder, pn)
+    }
+
+    /// Determine how many bytes are needed to encode a packet number.
+    fn pn_len(pn: packet::Number, largest_acknowledged: Option<packet::Number>) -> usize {
         let unacked_range = largest_acknowledged.map_or_else(|| pn + 1, |la| (pn - la) << 1);
         // Count how many bytes in this range are non-zero.
         let pn_len = size_of::<packet::Number>()
             - usize::try_from(unacked_range.leading_zeros() / 8).expect("u32 fits in usize");
         assert!(
             pn_len > 0,
-            "pn_len can't be zero as unacked_range should be > 0, pn {pn}, largest_acknowledged {largest_acknowledged:?}, tx {tx}"
+            "pn_len can't be zero as unacked_range should be > 0, pn {pn}, largest_acknowledged {largest_acknowledged:?}"
         );
         // TODO(mt) also use `4*path CWND/path MTU` to set a minimum length.
-        builder.pn(pn, pn_len);
-
-        (pt, builder, pn)
+        pn_len
     }
 
     fn can_grease_quic_bit(&self) -> bool {
@@ -2329,6 +2745,16 @@ impl Connection {
             return;
         }
 
+        // PATH_NEW_CONNECTION_ID, PATH_RETIRE_CONNECTION_ID, PATH_ABANDON, and PATH_STATUS.
+        if let Some(mp) = self.multipath.as_mut() {
+            self.cid_manager
+                .write_path_frames(builder, tokens, frame_stats);
+            mp.write_frames(builder, tokens, frame_stats);
+            if builder.is_full() {
+                return;
+            }
+        }
+
         for prio in [TransmissionPriority::High, TransmissionPriority::Normal] {
             self.streams
                 .write_frames(prio, builder, tokens, &mut stats.frame_tx);
@@ -2433,14 +2859,14 @@ impl Connection {
 
         if primary {
             let stats = &mut self.stats.borrow_mut().frame_tx;
-            self.acks.write_frame(
-                space,
-                now,
-                path.borrow().rtt().estimate(),
-                builder,
-                &mut tokens,
-                stats,
-            );
+            let rtt = path.borrow().rtt().estimate();
+            self.acks
+                .write_frame(space, now, rtt, builder, &mut tokens, stats);
+            if space == PacketNumberSpace::ApplicationData
+                && let Some(mp) = self.multipath.as_mut()
+            {
+                mp.write_acks(now, rtt, builder, &mut tokens, stats);
+            }
         }
         let ack_end = builder.len();
 
@@ -2644,6 +3070,7 @@ impl Connection {
             num_datagrams,
             max_datagram_size.ok_or(Error::Internal)?,
             &mut self.stats.borrow_mut(),
+            now,
         );
 
         Ok(SendOptionBatch::Yes(batch))
@@ -2660,6 +3087,10 @@ impl Connection {
         mut encoder: Encoder<&mut Vec<u8>>,
         packet_tos: Tos,
     ) -> Res<SendOption> {
+        if let Some(path_id) = self.multipath.as_ref().and_then(|mp| mp.path_id(path)) {
+            return self.output_multipath_dgram(path, path_id, now, closing_frame, encoder);
+        }
+
         let mut initial_sent = None;
         let mut needs_padding = false;
         let grease_quic_bit = self.can_grease_quic_bit();
@@ -2698,6 +3129,7 @@ impl Connection {
                 &self.address_validation,
                 version,
                 grease_quic_bit,
+                self.spin_bit,
                 // Limit the packet builder further to leave space for AEAD
                 // expansion added in `builder.build` below.
                 limit - aead_expansion,
@@ -2760,7 +3192,7 @@ impl Connection {
                 .tx_mut(self.version, epoch)
                 .ok_or(Error::Internal)?;
             encoder = builder.build(tx)?;
-            self.crypto.states_mut().auto_update()?;
+            self.crypto.states_mut().auto_update(now)?;
 
             if ack_eliciting {
                 self.idle_timeout.on_packet_sent(now);
@@ -2838,15 +3270,159 @@ impl Connection {
         }
     }
 
+    /// Build a datagram for a multipath path other than the initial path.
+    /// These paths only carry 1-RTT packets, using the packet number space for the path.
+    #[expect(clippy::too_many_lines, reason = "Packet building is long-winded.")]
+    fn output_multipath_dgram(
+        &mut self,
+        path: &PathRef,
+        path_id: PathId,
+        now: Instant,
+        closing_frame: Option<&ClosingFrame>,
+        encoder: Encoder<&mut Vec<u8>>,
+    ) -> Res<SendOption> {
+        let (key_phase, aead_expansion) = match self
+            .crypto
+            .states()
+            .select_tx(self.version, PacketNumberSpace::ApplicationData)
+        {
+            Some((Epoch::ApplicationData, tx)) => (tx.key_phase(), tx.expansion()),
+            _ => return Ok(SendOption::No(false)),
+        };
+        let grease_quic_bit = self.can_grease_quic_bit();
+        let mp = self.multipath.as_mut().ok_or(Error::Internal)?;
+        let space = mp.space_mut(path_id).ok_or(Error::Internal)?;
+        let profile = space.send_profile(now);
+        qdebug!(
+            "[{}] output_multipath_dgram send_profile {profile:?}",
+            path.borrow()
+        );
+        let pn = space.next_pn();
+        let pn_len = Self::pn_len(pn, space.largest_acknowledged());
+        let usable = space.is_usable();
+
+        let header_start = encoder.len();
+        let mut builder = packet::Builder::short(
+            encoder,
+            key_phase,
+            path.borrow().remote_cid(),
+            profile.limit() - aead_expansion,
+        );
+        if builder.remaining() > 0 {
+            builder.scramble(grease_quic_bit);
+            if self.spin_bit {
+                builder.spin(path.borrow().spin());
+            }
+        }
+        builder.pn(pn, pn_len);
+        if builder.is_full() {
+            _ = builder.abort();
+            return Ok(SendOption::No(profile.paced()));
+        }
+
+        let payload_start = builder.len();
+        let mut tokens = recovery::Tokens::new();
+        if let Some(close) = closing_frame {
+            close.write_frame(&mut builder);
+            self.stats.borrow_mut().frame_tx.connection_close += 1;
+        } else {
+            // Acknowledgments for every path can be sent on any path.
+            let rtt = path.borrow().rtt().estimate();
+            let stats = &mut self.stats.borrow_mut().frame_tx;
+            self.acks.write_frame(
+                PacketNumberSpace::ApplicationData,
+                now,
+                rtt,
+                &mut builder,
+                &mut tokens,
+                stats,
+            );
+            if let Some(mp) = self.multipath.as_mut() {
+                mp.write_acks(now, rtt, &mut builder, &mut tokens, stats);
+            }
+        }
+        let ack_end = builder.len();
+
+        let full_mtu = profile.limit() == path.borrow().plpmtu();
+        if closing_frame.is_none() {
+            if path.borrow_mut().write_frames(
+                &mut builder,
+                &mut self.stats.borrow_mut().frame_tx,
+                full_mtu,
+                now,
+            ) {
+                builder.enable_padding(true);
+            }
+            if usable && !profile.ack_only(PacketNumberSpace::ApplicationData) {
+                self.write_appdata_frames(&mut builder, &mut tokens, now);
+            }
+        }
+
+        let mut ack_eliciting = builder.len() > ack_end;
+        let pto = path.borrow().rtt().pto(self.confirmed());
+        let space = self
+            .multipath
+            .as_mut()
+            .and_then(|mp| mp.space_mut(path_id))
+            .ok_or(Error::Internal)?;
+        if !ack_eliciting
+            && closing_frame.is_none()
+            && (profile.should_probe(PacketNumberSpace::ApplicationData)
+                || (!builder.packet_empty() && space.should_probe(pto, now)))
+        {
+            builder.encode_frame(FrameType::Ping, |_| {});
+            self.stats.borrow_mut().frame_tx.ping += 1;
+            ack_eliciting = true;
+        }
+        if builder.packet_empty() {
+            _ = builder.abort();
+            return Ok(SendOption::No(profile.paced()));
+        }
+        if ack_eliciting && full_mtu && builder.pad() {
+            self.stats.borrow_mut().frame_tx.padding += 1;
+        }
+
+        self.log_packet(
+            packet::MetaData::new_out(
+                path,
+                packet::Type::Short,
+                pn,
+                builder.len() + aead_expansion,
+                &builder.as_ref()[payload_start..],
+                Tos::default(),
+            ),
+            now,
+        );
+        self.stats.borrow_mut().packets_tx += 1;
+        let tx = self
+            .crypto
+            .states_mut()
+            .tx_mut(self.version, Epoch::ApplicationData)
+            .ok_or(Error::Internal)?;
+        let encoder = builder.build_for_path(tx, path_id)?;
+        if ack_eliciting {
+            self.idle_timeout.on_packet_sent(now);
+        }
+        let len = encoder.len() - header_start;
+        let sent = sent::Packet::new(packet::Type::Short, pn, now, ack_eliciting, tokens, len);
+        self.multipath
+            .as_mut()
+            .and_then(|mp| mp.space_mut(path_id))
+            .ok_or(Error::Internal)?
+            .on_packet_sent(sent, now);
+        path.borrow_mut().add_sent(len);
+        Ok(SendOption::Yes)
+    }
+
     /// # Errors
     /// When connection state is not valid.
-    pub fn initiate_key_update(&mut self) -> Res<()> {
+    pub fn initiate_key_update(&mut self, now: Instant) -> Res<()> {
         if self.state == State::Confirmed {
             let la = self
                 .loss_recovery
                 .largest_acknowledged_pn(PacketNumberSpace::ApplicationData);
             qinfo!("[{self}] Initiating key update");
-            self.crypto.states_mut().initiate_key_update(la)
+            self.crypto.states_mut().initiate_key_update(la, now)
         } else {
             Err(Error::KeyUpdateBlocked)
         }
@@ -2872,7 +3448,7 @@ impl Connection {
 
         self.handshake(now, self.version, PacketNumberSpace::Initial, None)?;
         self.set_state(State::WaitInitial, now);
-        self.zero_rtt_state = if self.crypto.enable_0rtt(self.version, self.role)? {
+        self.zero_rtt_state = if self.crypto.enable_0rtt(self.version, self.role, now)? {
             qdebug!("[{self}] Enabled 0-RTT");
             ZeroRttState::Sending
         } else {
@@ -2966,6 +3542,30 @@ impl Connection {
 
             let max_active_cids = remote.get_integer(ActiveConnectionIdLimit);
             self.cid_manager.set_limit(max_active_cids);
+
+            if remote.has_value(InitialMaxPathId) {
+                // Multipath can't be used with zero-length connection IDs.
+                if self
+                    .remote_initial_source_cid
+                    .as_ref()
+                    .is_none_or(|cid| cid.is_empty())
+                {
+                    return Err(Error::TransportParameter);
+                }
+                if tps.local().has_value(InitialMaxPathId) {
+                    let local_max = PathId::try_from(tps.local().get_integer(InitialMaxPathId))?;
+                    let remote_max = PathId::try_from(remote.get_integer(InitialMaxPathId))?;
+                    qinfo!("[{self}] Multipath negotiated, max path {local_max}/{remote_max}");
+                    let mp = Multipath::new(
+                        local_max,
+                        remote_max,
+                        self.stats.clone(),
+                        self.conn_params.get_fast_pto(),
+                    );
+                    self.cid_manager.set_max_path_id(mp.max_path_id());
+                    self.multipath = Some(mp);
+                }
+            }
         }
         self.set_initial_limits();
         qlog::connection_tparams_set(&mut self.qlog, &self.tps.borrow(), now);
@@ -3088,7 +3688,7 @@ impl Connection {
     }
 
     /// Commit to a particular version.
-    fn compatible_upgrade(&mut self, packet_version: Version) -> Res<()> {
+    fn compatible_upgrade(&mut self, packet_version: Version, now: Instant) -> Res<()> {
         if !matches!(self.state, State::WaitInitial | State::WaitVersion) {
             return Ok(());
         }
@@ -3102,7 +3702,9 @@ impl Connection {
                 .as_ref()
                 .ok_or(Error::ProtocolViolation)?;
             // No need to randomize the starting packet number; that's already taken care of.
-            self.crypto.states_mut().init_server(version, dcid, false)?;
+            self.crypto
+                .states_mut()
+                .init_server(version, dcid, false, now)?;
             version
         };
 
@@ -3159,9 +3761,9 @@ impl Connection {
                 self.set_initial_limits();
             }
             if self.crypto.tls().has_secret(Epoch::Handshake) {
-                self.compatible_upgrade(packet_version)?;
+                self.compatible_upgrade(packet_version, now)?;
             }
-            if self.crypto.install_keys(self.role)? {
+            if self.crypto.install_keys(self.role, now)? {
                 self.saved_datagrams.make_available(Epoch::Handshake);
             }
         }
@@ -3179,7 +3781,7 @@ impl Connection {
                 .pmtud_mut()
                 .start(now, &mut self.stats.borrow_mut());
         }
-        self.paths.start_ecn(&mut self.stats.borrow_mut());
+        self.paths.start_ecn(&mut self.stats.borrow_mut(), now);
         Ok(())
     }
 
@@ -3197,6 +3799,10 @@ impl Connection {
             qinfo!("frame not allowed: {frame:?} {packet_type:?}");
             return Err(Error::ProtocolViolation);
         }
+        if frame.is_multipath() && self.multipath.is_none() {
+            qinfo!("[{self}] multipath frame without multipath: {frame:?}");
+            return Err(Error::ProtocolViolation);
+        }
         let space = PacketNumberSpace::from(packet_type);
         if frame.is_stream() {
             return self
@@ -3251,7 +3857,7 @@ impl Connection {
                     // If the server has switched versions, switch to that version.
                     // This is an assumption, but very often a good one.
                     // This function does nothing if we already have a version.
-                    self.compatible_upgrade(packet_version)?;
+                    self.compatible_upgrade(packet_version, now)?;
                 }
 
                 let mut buf = Vec::new();
@@ -3390,6 +3996,87 @@ impl Connection {
                 self.quic_datagrams
                     .handle_datagram(data, &mut self.stats.borrow_mut())?;
             }
+            Frame::PathAck {
+                path_id,
+                largest_acknowledged,
+                ack_delay,
+                first_ack_range,
+                ack_ranges,
+                ecn_count,
+            } => {
+                let ranges =
+                    Frame::decode_ack_frame(largest_acknowledged, first_ack_range, &ack_ranges)?;
+                if path_id.is_initial() {
+                    // This is no different to an ACK frame.
+                    if largest_acknowledged >= next_pn {
+                        qwarn!("Largest ACKed {largest_acknowledged} was never sent");
+                        return Err(Error::AckedUnsentPacket);
+                    }
+                    self.handle_ack(space, ranges, ecn_count.as_ref(), ack_delay, now)?;
+                } else {
+                    self.handle_path_ack(
+                        path_id,
+                        largest_acknowledged,
+                        ranges,
+                        ecn_count.as_ref(),
+                        ack_delay,
+                        now,
+                    )?;
+                }
+            }
+            Frame::PathAbandon {
+                path_id,
+                error_code,
+            } => {
+                self.stats.borrow_mut().frame_rx.path_abandon += 1;
+                qinfo!("[{self}] Peer abandoned path {path_id} with error {error_code:x}");
+                let mp = self.multipath.as_mut().ok_or(Error::Internal)?;
+                let lost = mp.peer_abandoned(path_id, now)?;
+                self.handle_lost_packets(&lost, now);
+            }
+            Frame::PathStatus {
+                path_id,
+                seqno,
+                status,
+            } => {
+                self.stats.borrow_mut().frame_rx.path_status += 1;
+                let mp = self.multipath.as_mut().ok_or(Error::Internal)?;
+                mp.set_remote_status(path_id, seqno, status);
+            }
+            Frame::PathNewConnectionId {
+                path_id,
+                sequence_number,
+                retire_prior,
+                connection_id,
+                stateless_reset_token,
+            } => {
+                self.stats.borrow_mut().frame_rx.new_connection_id += 1;
+                let mp = self.multipath.as_mut().ok_or(Error::Internal)?;
+                mp.add_remote_cid(
+                    path_id,
+                    ConnectionIdEntry::new(
+                        sequence_number,
+                        ConnectionId::from(connection_id),
+                        stateless_reset_token,
+                    ),
+                    retire_prior,
+                )?;
+            }
+            Frame::PathRetireConnectionId {
+                path_id,
+                sequence_number,
+            } => {
+                self.stats.borrow_mut().frame_rx.retire_connection_id += 1;
+                self.cid_manager.retire_path_cid(path_id, sequence_number);
+            }
+            Frame::MaxPathId { path_id } => {
+                let mp = self.multipath.as_mut().ok_or(Error::Internal)?;
+                mp.set_remote_max(path_id);
+                self.cid_manager.set_max_path_id(mp.max_path_id());
+            }
+            Frame::PathsBlocked { .. } | Frame::PathCidsBlocked { .. } => {
+                // We don't limit paths beyond the initial values, so there is nothing to do.
+            }
             _ => unreachable!("All other frames are for streams"),
         }
 
@@ -3399,14 +4086,16 @@ impl Connection {
     /// Given a set of `sent::Packet` instances, ensure that the source of the packet
     /// is told that they are lost.  This gives the frame generation code a chance
     /// to retransmit the frame as needed.
-    fn handle_lost_packets(&mut self, lost_packets: &[sent::Packet]) {
+    fn handle_lost_packets(&mut self, lost_packets: &[sent::Packet], now: Instant) {
         for lost in lost_packets {
             for token in lost.tokens() {
                 qdebug!("[{self}] Lost: {token:?}");
                 match token {
                     recovery::Token::Ack(ack_token) => {
                         // If we lost an ACK frame during the handshake, send another one.
-                        if ack_token.space() != PacketNumberSpace::ApplicationData {
+                        if ack_token.path_id().is_initial()
+                            && ack_token.space() != PacketNumberSpace::ApplicationData
+                        {
                             self.acks.immediate_ack(ack_token.space(), lost.time_sent());
                         }
                     }
@@ -3425,9 +4114,29 @@ impl Connection {
                             .datagram_outcome(dgram_tracker, OutgoingDatagramOutcome::Lost);
                         self.stats.borrow_mut().datagram_tx.lost += 1;
                     }
-                    recovery::Token::EcnEct0 => self.paths.lost_ecn(&mut self.stats.borrow_mut()),
+                    recovery::Token::EcnEct0 => {
+                        self.paths.lost_ecn(&mut self.stats.borrow_mut(), now);
+                    }
                     // PMTUD probe loss is handled by the PMTUD state machine.
                     recovery::Token::PmtudProbe => (),
+                    recovery::Token::PathNewConnectionId(path_id, entry) => {
+                        self.cid_manager.path_lost(*path_id, entry);
+                    }
+                    recovery::Token::PathRetireConnectionId(path_id, seqno) => {
+                        if let Some(mp) = self.multipath.as_mut() {
+                            mp.lost_retire_cid(*path_id, *seqno);
+                        }
+                    }
+                    recovery::Token::PathAbandon(path_id) => {
+                        if let Some(mp) = self.multipath.as_mut() {
+                            mp.lost_abandon(*path_id);
+                        }
+                    }
+                    recovery::Token::PathStatus(path_id, seqno) => {
+                        if let Some(mp) = self.multipath.as_mut() {
+                            mp.lost_status(*path_id, *seqno);
+                        }
+                    }
                 }
             }
         }
@@ -3478,11 +4187,30 @@ impl Connection {
             now,
         );
         let largest_acknowledged = acked_packets.first().map(sent::Packet::pn);
+        self.handle_acked_packets(&acked_packets);
+        self.handle_lost_packets(&lost_packets, now);
+        qlog::packets_lost(&mut self.qlog, &lost_packets, now);
+        let stats = &mut self.stats.borrow_mut().frame_rx;
+        stats.ack += 1;
+        if let Some(largest_acknowledged) = largest_acknowledged {
+            stats.largest_acknowledged = max(stats.largest_acknowledged, largest_acknowledged);
+        }
+        Ok(())
+    }
+
+    /// Tell the source of each frame in the given packets that they were acknowledged.
+    fn handle_acked_packets(&mut self, acked_packets: &[sent::Packet]) {
         for acked in acked_packets {
             for token in acked.tokens() {
                 match token {
                     recovery::Token::Stream(stream_token) => self.streams.acked(stream_token),
-                    recovery::Token::Ack(at) => self.acks.acked(at),
+                    recovery::Token::Ack(at) => {
+                        if at.path_id().is_initial() {
+                            self.acks.acked(at);
+                        } else if let Some(mp) = self.multipath.as_mut() {
+                            mp.acked(at);
+                        }
+                    }
                     recovery::Token::Crypto(ct) => self.crypto.acked(ct),
                     recovery::Token::NewToken(seqno) => self.new_token.acked(*seqno),
                     recovery::Token::NewConnectionId(entry) => self.cid_manager.acked(entry),
@@ -3495,18 +4223,54 @@ impl Connection {
                         .events
                         .datagram_outcome(dgram_tracker, OutgoingDatagramOutcome::Acked),
                     recovery::Token::EcnEct0 => self.paths.acked_ecn(),
+                    recovery::Token::PathNewConnectionId(path_id, entry) => {
+                        self.cid_manager.path_acked(*path_id, entry);
+                    }
+                    recovery::Token::PathRetireConnectionId(path_id, seqno) => {
+                        if let Some(mp) = self.multipath.as_mut() {
+                            mp.acked_retire_cid(*path_id, *seqno);
+                        }
+                    }
                     // We don't care about these being ACK'ed
-                    recovery::Token::HandshakeDone | recovery::Token::PmtudProbe => (),
+                    recovery::Token::HandshakeDone
+                    | recovery::Token::PmtudProbe
+                    | recovery::Token::PathAbandon(_)
+                    | recovery::Token::PathStatus(..) => (),
                 }
             }
         }
-        self.handle_lost_packets(&lost_packets);
+    }
+
+    /// Handle a `PATH_ACK` frame for a path other than the initial path.
+    fn handle_path_ack<R>(
+        &mut self,
+        path_id: PathId,
+        largest_acknowledged: packet::Number,
+        ack_ranges: R,
+        ack_ecn: Option<&ecn::Count>,
+        ack_delay: u64,
+        now: Instant,
+    ) -> Res<()>
+    where
+        R: IntoIterator<Item = RangeInclusive<packet::Number>> + Debug,
+        R::IntoIter: ExactSizeIterator,
+    {
+        qdebug!("[{self}] Rx PATH_ACK path={path_id}, ranges={ack_ranges:?}");
+        let ack_delay = self.decode_ack_delay(ack_delay)?;
+        self.stats.borrow_mut().frame_rx.path_ack += 1;
+        let Some(space) = self.multipath.as_mut().and_then(|mp| mp.space_mut(path_id)) else {
+            // The path might have been removed already.
+            return Ok(());
+        };
+        if largest_acknowledged >= space.next_pn() {
+            qwarn!("Largest ACKed {largest_acknowledged} on path {path_id} was never sent");
+            return Err(Error::AckedUnsentPacket);
+        }
+        let (acked_packets, lost_packets) =
+            space.on_ack_received(ack_ranges, ack_ecn, ack_delay, now);
+        self.handle_acked_packets(&acked_packets);
+        self.handle_lost_packets(&lost_packets, now);
         qlog::packets_lost(&mut self.qlog, &lost_packets, now);
-        let stats = &mut self.stats.borrow_mut().frame_rx;
-        stats.ack += 1;
-        if let Some(largest_acknowledged) = largest_acknowledged {
-            stats.largest_acknowledged = max(stats.largest_acknowledged, largest_acknowledged);
-        }
         Ok(())
     }
 
@@ -3514,7 +4278,7 @@ impl Connection {
     fn resend_0rtt(&mut self, now: Instant) {
         if let Some(path) = self.paths.primary() {
             let dropped = self.loss_recovery.drop_0rtt(&path, now);
-            self.handle_lost_packets(&dropped);
+            self.handle_lost_packets(&dropped, now);
         }
     }
 
@@ -3526,7 +4290,7 @@ impl Connection {
         qdebug!("[{self}] 0-RTT rejected");
         self.resend_0rtt(now);
         self.streams.zero_rtt_rejected();
-        self.crypto.states_mut().discard_0rtt_keys();
+        self.crypto.states_mut().discard_0rtt_keys(now);
         self.events.client_0rtt_rejected();
     }
 
@@ -3569,7 +4333,7 @@ impl Connection {
         // Setting application keys has to occur after 0-RTT rejection.
         let pto = self.pto();
         self.crypto
-            .install_application_keys(self.version, now + pto)?;
+            .install_application_keys(self.version, now + pto, now)?;
         self.process_tps(now)?;
         self.set_state(State::Connected, now);
         self.create_resumption_token(now);
@@ -3772,6 +4536,34 @@ impl Connection {
         Ok(())
     }
 
+    /// Abandon transmission of stream data, except for the first `reliable_size` bytes,
+    /// which are still delivered to the peer.  This uses `RESET_STREAM_AT`,
+    /// which the peer needs to support.  The reliable size can be reduced by calling
+    /// this function again, but it cannot be increased.
+    ///
+    /// # Errors
+    /// `InvalidStreamId` if the stream ID is invalid.
+    /// `NotAvailable` if the peer does not support `RESET_STREAM_AT`.
+    /// `InvalidInput` if `reliable_size` is more than has been written to the stream.
+    pub fn stream_reset_send_at(
+        &mut self,
+        stream_id: StreamId,
+        err: AppError,
+        reliable_size: u64,
+    ) -> Res<()> {
+        let stream = self.streams.get_send_stream_mut(stream_id)?;
+        let supported = {
+            let tph = self.tps.borrow();
+            tph.remote_handshake()
+                .or_else(|| tph.remote_0rtt())
+                .is_some_and(|r| r.get_empty(ResetStreamAt))
+        };
+        if reliable_size > 0 && !supported {
+            return Err(Error::NotAvailable);
+        }
+        stream.reset_at(err, reliable_size)
+    }
+
     /// Read buffered data from stream. bool says whether read bytes includes
     /// the final data on stream.
     ///
@@ -3786,6 +4578,22 @@ impl Connection {
         Ok(rb)
     }
 
+    pub(crate) fn stream_readable(&mut self, stream_id: StreamId) -> Res<bool> {
+        Ok(self.streams.get_recv_stream_mut(stream_id)?.readable())
+    }
+
+    pub(crate) fn recv_stream_final_size(&mut self, stream_id: StreamId) -> Res<Option<u64>> {
+        Ok(self.streams.get_recv_stream_mut(stream_id)?.final_size())
+    }
+
+    pub(crate) fn send_stream_final_size(&self, stream_id: StreamId) -> Res<Option<u64>> {
+        Ok(self.streams.get_send_stream(stream_id)?.final_size())
+    }
+
+    pub(crate) const fn event_queue(&self) -> &ConnectionEvents {
+        &self.events
+    }
+
     /// Application is no longer interested in this stream.
     /// # Errors
     /// When the stream ID is invalid.
@@ -3861,6 +4669,7 @@ impl Connection {
             &self.address_validation,
             version,
             false,
+            false,
             usize::MAX,
             self.loss_recovery
                 .largest_acknowledged_pn(PacketNumberSpace::ApplicationData),
@@ -4,11 +4,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::{cmp::max, time::Duration};
+use std::{cmp::max, rc::Rc, time::Duration};
+
+use neqo_crypto::random;
 
 pub use crate::recovery::FAST_PTO_SCALE;
 use crate::{
-    CongestionControlAlgorithm, DEFAULT_INITIAL_RTT, Res,
+    CongestionControlAlgorithm, CongestionControllerFactory, DEFAULT_INITIAL_RTT, PathId,
+    PathScheduler, Res,
     connection::{ConnectionIdManager, Role},
     rtt::GRANULARITY,
     stream_id::StreamType,
@@ -16,9 +19,9 @@ use crate::{
         PreferredAddress, TransportParameter,
         TransportParameterId::{
             self, ActiveConnectionIdLimit, DisableMigration, GreaseQuicBit, InitialMaxData,
-            InitialMaxStreamDataBidiLocal, InitialMaxStreamDataBidiRemote, InitialMaxStreamDataUni,
-            InitialMaxStreamsBidi, InitialMaxStreamsUni, MaxAckDelay, MaxDatagramFrameSize,
-            MinAckDelay,
+            InitialMaxPathId, InitialMaxStreamDataBidiLocal, InitialMaxStreamDataBidiRemote,
+            InitialMaxStreamDataUni, InitialMaxStreamsBidi, InitialMaxStreamsUni, MaxAckDelay,
+            MaxDatagramFrameSize, MinAckDelay, ResetStreamAt,
         },
         TransportParametersHandler,
     },
@@ -99,6 +102,31 @@ pub enum PreferredAddressConfig {
     Address(PreferredAddress),
 }
 
+/// Whether to use the latency spin bit (RFC 9000, Section 17.4).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpinBitConfig {
+    /// The spin bit is set to a random value and ignored on receipt.
+    #[default]
+    Disabled,
+    /// The spin bit is used, except on a random one in every 16 connections,
+    /// as RFC 9000 recommends.
+    Random,
+    /// The spin bit is always used.
+    Enabled,
+}
+
+impl SpinBitConfig {
+    /// Decide whether a new connection uses the spin bit.
+    #[must_use]
+    pub fn enabled(self) -> bool {
+        match self {
+            Self::Disabled => false,
+            Self::Random => random::<1>()[0] & 0x0f != 0,
+            Self::Enabled => true,
+        }
+    }
+}
+
 /// `ConnectionParameters` use for setting initial value for QUIC parameters.
 /// This collects configuration like initial limits, protocol version, and
 /// congestion control algorithm.
@@ -107,6 +135,8 @@ pub enum PreferredAddressConfig {
 pub struct ConnectionParameters {
     versions: version::Config,
     cc_algorithm: CongestionControlAlgorithm,
+    /// A factory for congestion controllers that replaces `cc_algorithm`.
+    congestion_controller: Option<Rc<dyn CongestionControllerFactory>>,
     /// Initial connection-level flow control limit.
     max_data: u64,
     /// Initial flow control limit for receiving data on bidirectional streams that the peer
@@ -150,6 +180,14 @@ pub struct ConnectionParameters {
     mlkem: bool,
     /// Whether to randomize the packet number of the first Initial packet.
     randomize_first_pn: bool,
+    /// The largest path identifier to allow for multipath, or `None` to disable multipath.
+    multipath: Option<PathId>,
+    /// Chooses between paths when multipath is used, or `None` for [`crate::LowestRtt`].
+    path_scheduler: Option<Rc<dyn PathScheduler>>,
+    /// Whether to accept `RESET_STREAM_AT` frames from the peer.
+    reset_stream_at: bool,
+    /// Whether to use the latency spin bit.
+    spin_bit: SpinBitConfig,
 }
 
 impl Default for ConnectionParameters {
@@ -157,6 +195,7 @@ impl Default for ConnectionParameters {
         Self {
             versions: version::Config::default(),
             cc_algorithm: CongestionControlAlgorithm::Cubic,
+            congestion_controller: None,
             max_data: INITIAL_LOCAL_MAX_DATA,
             max_stream_data_bidi_remote: u64::try_from(INITIAL_LOCAL_MAX_STREAM_DATA)
                 .expect("usize fits in u64"),
@@ -182,6 +221,10 @@ impl Default for ConnectionParameters {
             sni_slicing: true,
             mlkem: true,
             randomize_first_pn: true,
+            multipath: None,
+            path_scheduler: None,
+            reset_stream_at: false,
+            spin_bit: SpinBitConfig::Disabled,
         }
     }
 }
@@ -225,6 +268,19 @@ impl ConnectionParameters {
         self
     }
 
+    #[must_use]
+    pub fn get_congestion_controller(&self) -> Option<&dyn CongestionControllerFactory> {
+        self.congestion_controller.as_deref()
+    }
+
+    /// Use congestion controllers from `factory` instead of one of the built-in
+    /// algorithms.  Each path of the connection gets its own controller.
+    #[must_use]
+    pub fn congestion_controller(mut self, factory: Rc<dyn CongestionControllerFactory>) -> Self {
+        self.congestion_controller = Some(factory);
+        self
+    }
+
     #[must_use]
     pub const fn get_max_data(&self) -> u64 {
         self.max_data
@@ -488,6 +544,63 @@ impl ConnectionParameters {
         self
     }
 
+    #[must_use]
+    pub const fn get_multipath(&self) -> Option<PathId> {
+        self.multipath
+    }
+
+    /// Enable the multipath extension, allowing paths with identifiers up to
+    /// and including `max_path_id` to be used.  Multipath is disabled by default.
+    /// Multipath cannot be used with zero-length connection IDs.
+    #[must_use]
+    pub const fn multipath(mut self, max_path_id: Option<PathId>) -> Self {
+        self.multipath = max_path_id;
+        self
+    }
+
+    #[must_use]
+    pub fn get_path_scheduler(&self) -> Option<&dyn PathScheduler> {
+        self.path_scheduler.as_deref()
+    }
+
+    /// Use `scheduler` to choose which path each packet is sent on when
+    /// multipath is used.  By default, packets are sent on the path with
+    /// the lowest RTT that is able to send.
+    #[must_use]
+    pub fn path_scheduler(mut self, scheduler: Rc<dyn PathScheduler>) -> Self {
+        self.path_scheduler = Some(scheduler);
+        self
+    }
+
+    #[must_use]
+    pub const fn reset_stream_at_enabled(&self) -> bool {
+        self.reset_stream_at
+    }
+
+    /// Allow the peer to use `RESET_STREAM_AT` to reset streams that this endpoint
+    /// receives on, while still delivering some of the data.
+    /// This is disabled by default.
+    #[must_use]
+    pub const fn reset_stream_at(mut self, reset_stream_at: bool) -> Self {
+        self.reset_stream_at = reset_stream_at;
+        self
+    }
+
+    #[must_use]
+    pub const fn get_spin_bit(&self) -> SpinBitConfig {
+        self.spin_bit
+    }
+
+    /// Set whether the latency spin bit is used.  The spin bit is not used by
+    /// default.  [`SpinBitConfig::Random`] disables it on one in every 16
+    /// connections, which makes it harder for an observer to single out
+    /// connections that opt out.
+    #[must_use]
+    pub const fn spin_bit(mut self, spin_bit: SpinBitConfig) -> Self {
+        self.spin_bit = spin_bit;
+        self
+    }
+
     /// # Errors
     /// When a connection ID cannot be obtained.
     /// # Panics
@@ -552,6 +665,15 @@ impl ConnectionParameters {
         }
         tps.local_mut()
             .set_integer(MaxDatagramFrameSize, self.datagram_size);
+        if let Some(max_path_id) = self.multipath
+            && !cid_manager.generator().borrow().generates_empty_cids()
+        {
+            tps.local_mut()
+                .set_integer(InitialMaxPathId, max_path_id.as_u64());
+        }
+        if self.reset_stream_at {
+            tps.local_mut().set_empty(ResetStreamAt);
+        }
         Ok(tps)
     }
 }
@@ -576,4 +698,20 @@ mod tests {
         let params = params.pmtud_iface_mtu(false);
         assert!(!params.pmtud_iface_mtu_enabled());
     }
+
+    #[test]
+    fn multipath_default() {
+        let params = ConnectionParameters::default();
+        assert_eq!(params.get_multipath(), None);
+        let params = params.multipath(Some(PathId::new(4)));
+        assert_eq!(params.get_multipath(), Some(PathId::new(4)));
+    }
+
+    #[test]
+    fn reset_stream_at_default() {
+        let params = ConnectionParameters::default();
+        assert!(!params.reset_stream_at_enabled());
+        let params = params.reset_stream_at(true);
+        assert!(params.reset_stream_at_enabled());
+    }
 }
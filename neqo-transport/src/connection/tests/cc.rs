@@ -4,7 +4,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::time::Duration;
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use neqo_common::{Datagram, Ecn, qdebug, qinfo};
 
@@ -14,7 +18,8 @@ use super::{
     default_server, fill_cwnd, induce_persistent_congestion, send_something,
 };
 use crate::{
-    CongestionControlAlgorithm, ConnectionParameters,
+    CongestionControlAlgorithm, CongestionController, CongestionControllerFactory,
+    ConnectionParameters, PacketInfo, RttSample,
     connection::tests::{connect_with_rtt, new_client, new_server, now},
     packet,
     recovery::{ACK_ONLY_SIZE_LIMIT, PACKET_THRESHOLD},
@@ -458,3 +463,132 @@ fn pace() {
     assert_ne!(fin, Duration::new(0, 0));
     assert_ne!(fin, gap);
 }
+
+/// A controller with a fixed window that counts the acknowledgments it sees.
+#[derive(Debug)]
+struct FixedWindow {
+    cwnd: usize,
+    acked: Rc<Cell<usize>>,
+}
+
+impl CongestionController for FixedWindow {
+    fn cwnd(&self) -> usize {
+        self.cwnd
+    }
+
+    fn on_packets_acked(
+        &mut self,
+        acked: &[PacketInfo],
+        rtt: &RttSample,
+        _bytes_in_flight: usize,
+        _now: Instant,
+    ) {
+        assert!(rtt.smoothed > Duration::ZERO);
+        self.acked.set(self.acked.get() + acked.len());
+    }
+
+    fn on_packets_lost(
+        &mut self,
+        _lost: &[PacketInfo],
+        _pto: Duration,
+        _bytes_in_flight: usize,
+        _now: Instant,
+    ) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Default)]
+struct FixedWindowFactory {
+    acked: Rc<Cell<usize>>,
+}
+
+impl CongestionControllerFactory for FixedWindowFactory {
+    fn new_controller(&self, max_datagram_size: usize) -> Box<dyn CongestionController> {
+        Box::new(FixedWindow {
+            cwnd: max_datagram_size * 4,
+            acked: Rc::clone(&self.acked),
+        })
+    }
+}
+
+#[test]
+fn custom_congestion_controller() {
+    let factory = Rc::new(FixedWindowFactory::default());
+    let acked = Rc::clone(&factory.acked);
+    let mut client = new_client(ConnectionParameters::default().congestion_controller(factory));
+    let mut server = default_server();
+    let now = connect_rtt_idle(&mut client, &mut server, DEFAULT_RTT);
+    let acked_in_handshake = acked.get();
+    assert!(acked_in_handshake > 0);
+
+    // The client only sends as much as the controller allows.
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    let (c_tx_dgrams, now) = fill_cwnd(&mut client, stream_id, now);
+    assert_full_cwnd(&c_tx_dgrams, client.plpmtu() * 4, client.plpmtu());
+
+    let s_ack = ack_bytes(&mut server, stream_id, c_tx_dgrams, now);
+    client.process_input(s_ack, now);
+    assert!(acked.get() > acked_in_handshake);
+    assert_eq!(cwnd(&client), client.plpmtu() * 4);
+}
+
+/// A controller that closes its window and asks for a pacing rate of zero.
+#[derive(Debug)]
+struct Closed;
+
+impl CongestionController for Closed {
+    fn cwnd(&self) -> usize {
+        0
+    }
+
+    fn pacing_rate(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    fn on_packets_acked(
+        &mut self,
+        _acked: &[PacketInfo],
+        _rtt: &RttSample,
+        _bytes_in_flight: usize,
+        _now: Instant,
+    ) {
+    }
+
+    fn on_packets_lost(
+        &mut self,
+        _lost: &[PacketInfo],
+        _pto: Duration,
+        _bytes_in_flight: usize,
+        _now: Instant,
+    ) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+struct ClosedFactory;
+
+impl CongestionControllerFactory for ClosedFactory {
+    fn new_controller(&self, _max_datagram_size: usize) -> Box<dyn CongestionController> {
+        Box::new(Closed)
+    }
+}
+
+#[test]
+fn custom_congestion_controller_zero() {
+    let mut client =
+        new_client(ConnectionParameters::default().congestion_controller(Rc::new(ClosedFactory)));
+    let mut server = default_server();
+    let now = connect_rtt_idle(&mut client, &mut server, DEFAULT_RTT);
+
+    // The window is kept at the minimum and pacing falls back to the window.
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    let (c_tx_dgrams, now) = fill_cwnd(&mut client, stream_id, now);
+    assert_full_cwnd(&c_tx_dgrams, client.plpmtu() * 2, client.plpmtu());
+
+    let s_ack = ack_bytes(&mut server, stream_id, c_tx_dgrams, now);
+    client.process_input(s_ack, now);
+    let (c_tx_dgrams, _) = fill_cwnd(&mut client, stream_id, now);
+    assert_eq!(c_tx_dgrams.len(), 2);
+}
@@ -225,21 +225,23 @@ fn debug() {
   frames rx:
     crypto 0 done 0 token 0 close 0
     ack 0 (max 0) ping 0 padding 0
-    stream 0 reset 0 stop 0
+    stream 0 reset 0 reset_at 0 stop 0
     max: stream 0 data 0 stream_data 0
     blocked: stream 0 data 0 stream_data 0
     datagram 0
     ncid 0 rcid 0 pchallenge 0 presponse 0
     ack_frequency 0
+    path: ack 0 abandon 0 status 0
   frames tx:
     crypto 0 done 0 token 0 close 0
     ack 0 (max 0) ping 0 padding 0
-    stream 0 reset 0 stop 0
+    stream 0 reset 0 reset_at 0 stop 0
     max: stream 0 data 0 stream_data 0
     blocked: stream 0 data 0 stream_data 0
     datagram 0
     ncid 0 rcid 0 pchallenge 0 presponse 0
     ack_frequency 0
+    path: ack 0 abandon 0 status 0
   ecn:
     tx:
     acked:
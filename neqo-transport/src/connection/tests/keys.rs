@@ -5,7 +5,9 @@
 // except according to those terms.
 
 use neqo_common::{Datagram, qdebug};
+use neqo_crypto::KeyLog;
 use test_fixture::{
+    SharedVec,
     assertions::{is_handshake, is_initial},
     now, split_datagram,
 };
@@ -44,7 +46,7 @@ fn check_discarded(
 
 fn assert_update_blocked(c: &mut Connection) {
     assert_eq!(
-        c.initiate_key_update().unwrap_err(),
+        c.initiate_key_update(now()).unwrap_err(),
         Error::KeyUpdateBlocked
     );
 }
@@ -116,7 +118,7 @@ fn key_update_client() {
     assert_eq!(client.get_epochs(), (Some(3), Some(3))); // (write, read)
     assert_eq!(server.get_epochs(), (Some(3), Some(3)));
 
-    assert!(client.initiate_key_update().is_ok());
+    assert!(client.initiate_key_update(now).is_ok());
     assert_update_blocked(&mut client);
 
     // Initiating an update should only increase the write epoch.
@@ -188,7 +190,7 @@ fn key_update_consecutive() {
     connect(&mut client, &mut server);
     let now = now();
 
-    assert!(server.initiate_key_update().is_ok());
+    assert!(server.initiate_key_update(now).is_ok());
     assert_eq!(server.get_epochs(), (Some(4), Some(3)));
 
     // Server sends something.
@@ -212,7 +214,7 @@ fn key_update_consecutive() {
     }
 
     // Now update keys on the server again.
-    assert!(server.initiate_key_update().is_ok());
+    assert!(server.initiate_key_update(now).is_ok());
     assert_eq!(server.get_epochs(), (Some(5), Some(4)));
 
     let dgram = send_something(&mut server, now + AT_LEAST_PTO);
@@ -264,12 +266,12 @@ fn key_update_before_confirmed() {
     // Server HANDSHAKE_DONE
     let dgram = server.process(dgram, now()).dgram();
     assert!(dgram.is_some());
-    assert!(server.initiate_key_update().is_ok());
+    assert!(server.initiate_key_update(now()).is_ok());
 
     // Client receives HANDSHAKE_DONE
     let dgram = client.process(dgram, now()).dgram();
     assert!(dgram.is_none());
-    assert!(client.initiate_key_update().is_ok());
+    assert!(client.initiate_key_update(now()).is_ok());
 }
 
 #[test]
@@ -347,7 +349,7 @@ fn automatic_update_write_keys_blocked() {
     connect_force_idle(&mut client, &mut server);
 
     // An outstanding key update will block the automatic update.
-    client.initiate_key_update().unwrap();
+    client.initiate_key_update(now()).unwrap();
 
     overwrite_invocations(UPDATE_WRITE_KEYS_AT);
     let stream_id = client.stream_create(StreamType::UniDi).unwrap();
@@ -409,3 +411,50 @@ fn initial_handshake_pto_padding() {
     }
     assert!(found_coalesced);
 }
+
+#[test]
+fn keylog() {
+    let mut client = default_client();
+    let mut server = default_server();
+    let client_lines = SharedVec::default();
+    let server_lines = SharedVec::default();
+    client.set_keylog(KeyLog::new(client_lines.clone()));
+    server.set_keylog(KeyLog::new(server_lines.clone()));
+    connect_force_idle(&mut client, &mut server);
+
+    let labels = |lines: &SharedVec| {
+        let lines = lines.to_string();
+        let mut labels = lines
+            .lines()
+            .map(|l| l.split(' ').next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels
+    };
+    let mut expected = vec![
+        "CLIENT_HANDSHAKE_TRAFFIC_SECRET",
+        "CLIENT_TRAFFIC_SECRET_0",
+        "SERVER_HANDSHAKE_TRAFFIC_SECRET",
+        "SERVER_TRAFFIC_SECRET_0",
+    ];
+    assert_eq!(labels(&client_lines), expected);
+    assert_eq!(labels(&server_lines), expected);
+
+    // Key updates are logged as both peers switch keys.
+    client.initiate_key_update(now()).unwrap();
+    let ack = send_and_receive(&mut client, &mut server, now()).unwrap();
+    client.process_input(ack, now());
+    expected.extend(["CLIENT_TRAFFIC_SECRET_1", "SERVER_TRAFFIC_SECRET_1"]);
+    expected.sort_unstable();
+    assert_eq!(labels(&client_lines), expected);
+    assert_eq!(labels(&server_lines), expected);
+
+    // Both peers log the same secrets, with the same client random.
+    let sorted = |lines: &SharedVec| {
+        let lines = lines.to_string();
+        let mut lines = lines.lines().map(String::from).collect::<Vec<_>>();
+        lines.sort();
+        lines
+    };
+    assert_eq!(sorted(&client_lines), sorted(&server_lines));
+}
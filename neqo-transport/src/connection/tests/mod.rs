@@ -44,12 +44,16 @@ mod handshake;
 mod idle;
 mod keys;
 mod migration;
+mod multipath;
 mod null;
 mod pmtud;
 mod priority;
+mod qlog;
 mod recovery;
 mod resumption;
+mod spin;
 mod stream;
+mod stream_handle;
 mod vn;
 mod zerortt;
 
@@ -0,0 +1,436 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use neqo_common::{Datagram, Ecn, event::Provider as _};
+use test_fixture::{DEFAULT_ADDR, DEFAULT_ADDR_V4, now};
+
+use super::{
+    super::{Connection, State},
+    AT_LEAST_PTO, DEFAULT_RTT, connect_force_idle, connect_rtt_idle, default_client,
+    default_server, new_client, new_server,
+};
+use crate::{
+    CloseReason, ConnectionEvent, ConnectionParameters, Error, PathId, PathInfo, PathScheduler,
+    PathStatus, SpinBitConfig, StreamType, connection::test_internal, ecn, frame::FrameType,
+    packet,
+};
+
+const MAX_PATH_ID: PathId = PathId::new(3);
+
+// These tests use two paths: the connection is established on a path with
+// the same IPv6 address on both ends, and the second path has the same IPv4
+// address on both ends.
+
+fn multipath_params() -> ConnectionParameters {
+    ConnectionParameters::default().multipath(Some(MAX_PATH_ID))
+}
+
+fn multipath_client() -> Connection {
+    new_client(multipath_params())
+}
+
+fn multipath_server() -> Connection {
+    new_server(multipath_params())
+}
+
+/// Deliver datagrams between the two endpoints until neither has anything to send.
+/// Each flight takes half of `rtt`.  Returns the number of datagrams that the client
+/// sent on the second path and the time at the end.
+fn exchange(
+    client: &mut Connection,
+    server: &mut Connection,
+    rtt: Duration,
+    mut now: Instant,
+) -> (usize, Instant) {
+    let mut second_path = 0;
+    let mut to_server = Vec::new();
+    for _ in 0..1000 {
+        while let Some(d) = client.process_output(now).dgram() {
+            second_path += usize::from(d.source() == DEFAULT_ADDR_V4);
+            to_server.push(d);
+        }
+        if to_server.is_empty() {
+            // The client might be paced, or waiting on the server.
+            let to_client = drain(server, now);
+            if to_client.is_empty() {
+                break;
+            }
+            now += rtt / 2;
+            client.process_multiple_input(to_client, now);
+            continue;
+        }
+        now += rtt / 2;
+        server.process_multiple_input(std::mem::take(&mut to_server), now);
+        let to_client = drain(server, now);
+        now += rtt / 2;
+        client.process_multiple_input(to_client, now);
+    }
+    (second_path, now)
+}
+
+fn drain(c: &mut Connection, now: Instant) -> Vec<Datagram> {
+    let mut dgrams = Vec::new();
+    while let Some(d) = c.process_output(now).dgram() {
+        dgrams.push(d);
+    }
+    dgrams
+}
+
+fn opened(c: &mut Connection) -> Vec<PathId> {
+    c.events()
+        .filter_map(|e| match e {
+            ConnectionEvent::PathOpened { path_id } => Some(path_id),
+            _ => None,
+        })
+        .collect()
+}
+
+fn closed(c: &mut Connection) -> Vec<PathId> {
+    c.events()
+        .filter_map(|e| match e {
+            ConnectionEvent::PathClosed { path_id } => Some(path_id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Connect and open a second path.
+fn connect_two_paths(client: &mut Connection, server: &mut Connection) -> (PathId, Instant) {
+    let now = connect_rtt_idle(client, server, DEFAULT_RTT);
+    let path_id = client
+        .open_path(DEFAULT_ADDR_V4, DEFAULT_ADDR_V4, now)
+        .unwrap();
+    let (used, now) = exchange(client, server, DEFAULT_RTT, now);
+    assert!(used > 0);
+    assert_eq!(opened(client), [path_id]);
+    assert_eq!(opened(server), [path_id]);
+    (path_id, now)
+}
+
+/// Send `len` bytes from the client to the server and check that they arrive.
+/// Returns the number of datagrams sent on the second path.
+fn send_data(
+    client: &mut Connection,
+    server: &mut Connection,
+    len: usize,
+    now: Instant,
+) -> (usize, Instant) {
+    let data = vec![0x6d; len];
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    let mut sent = 0;
+    let mut second_path = 0;
+    let mut now = now;
+    while sent < len {
+        sent += client.stream_send(stream_id, &data[sent..]).unwrap();
+        let (used, t) = exchange(client, server, DEFAULT_RTT, now);
+        second_path += used;
+        now = t;
+    }
+    client.stream_close_send(stream_id).unwrap();
+    let (used, now) = exchange(client, server, DEFAULT_RTT, now);
+    second_path += used;
+
+    let mut buf = vec![0; len + 1];
+    let (recvd, fin) = server.stream_recv(stream_id, &mut buf).unwrap();
+    assert_eq!(&buf[..recvd], &data[..]);
+    assert!(fin);
+    (second_path, now)
+}
+
+#[test]
+fn negotiate() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    connect_force_idle(&mut client, &mut server);
+    assert!(client.multipath_enabled());
+    assert!(server.multipath_enabled());
+}
+
+#[test]
+fn negotiate_client_only() {
+    let mut client = multipath_client();
+    let mut server = default_server();
+    connect_force_idle(&mut client, &mut server);
+    assert!(!client.multipath_enabled());
+    assert!(!server.multipath_enabled());
+}
+
+#[test]
+fn negotiate_server_only() {
+    let mut client = default_client();
+    let mut server = multipath_server();
+    connect_force_idle(&mut client, &mut server);
+    assert!(!client.multipath_enabled());
+    assert!(!server.multipath_enabled());
+}
+
+#[test]
+fn open_path_not_negotiated() {
+    let mut client = default_client();
+    let mut server = default_server();
+    connect_force_idle(&mut client, &mut server);
+    assert_eq!(
+        client.open_path(DEFAULT_ADDR_V4, DEFAULT_ADDR_V4, now()),
+        Err(Error::NotAvailable)
+    );
+}
+
+#[test]
+fn open_path_server() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    connect_force_idle(&mut client, &mut server);
+    assert_eq!(
+        server.open_path(DEFAULT_ADDR_V4, DEFAULT_ADDR_V4, now()),
+        Err(Error::WrongRole)
+    );
+}
+
+#[test]
+fn open_path_existing() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    connect_force_idle(&mut client, &mut server);
+    assert_eq!(
+        client.open_path(DEFAULT_ADDR, DEFAULT_ADDR, now()),
+        Err(Error::InvalidMigration)
+    );
+}
+
+#[test]
+fn open_path() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    let (path_id, _) = connect_two_paths(&mut client, &mut server);
+    assert_eq!(path_id, PathId::new(1));
+    assert_eq!(client.path_ids(), [path_id]);
+    assert_eq!(server.path_ids(), [path_id]);
+}
+
+#[test]
+fn open_path_exhausted() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    let now = connect_rtt_idle(&mut client, &mut server, DEFAULT_RTT);
+    for i in 1..=MAX_PATH_ID.as_u32() {
+        let addr = DEFAULT_ADDR_V4.ip();
+        let local = std::net::SocketAddr::new(addr, 443 + u16::try_from(i).unwrap());
+        assert_eq!(client.open_path(local, local, now), Ok(PathId::new(i)));
+    }
+    assert_eq!(
+        client.open_path(DEFAULT_ADDR_V4, DEFAULT_ADDR_V4, now),
+        Err(Error::ConnectionIdsExhausted)
+    );
+}
+
+#[test]
+fn send_on_both_paths() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    let (_, now) = connect_two_paths(&mut client, &mut server);
+
+    let before = client.stats();
+    let (second_path, _) = send_data(&mut client, &mut server, 200_000, now);
+    let after = client.stats();
+    // Both paths carry data.
+    let total = after.packets_tx - before.packets_tx;
+    assert!(second_path > 0);
+    assert!(second_path < total);
+    assert!(after.frame_rx.path_ack > before.frame_rx.path_ack);
+}
+
+/// A scheduler that takes turns between the paths that can send.
+#[derive(Debug, Default)]
+struct RoundRobin {
+    next: Cell<usize>,
+}
+
+impl PathScheduler for RoundRobin {
+    fn select(&self, paths: &[PathInfo]) -> usize {
+        let i = self.next.get();
+        self.next.set(i.wrapping_add(1));
+        i % paths.len()
+    }
+}
+
+/// A custom scheduler can spread even a small flight across both paths.
+#[test]
+fn path_scheduler() {
+    let scheduler = Rc::new(RoundRobin::default());
+    let mut client = new_client(multipath_params().path_scheduler(Rc::clone(&scheduler) as _));
+    let mut server = multipath_server();
+    let (_, now) = connect_two_paths(&mut client, &mut server);
+
+    let data = [0x72; 3_000];
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    assert_eq!(client.stream_send(stream_id, &data).unwrap(), data.len());
+    client.stream_close_send(stream_id).unwrap();
+    let flight = drain(&mut client, now);
+    let second_path = flight
+        .iter()
+        .filter(|d| d.source() == DEFAULT_ADDR_V4)
+        .count();
+    assert!(second_path > 0);
+    assert!(second_path < flight.len());
+    assert!(scheduler.next.get() > 0);
+
+    server.process_multiple_input(flight, now + DEFAULT_RTT / 2);
+    exchange(&mut client, &mut server, DEFAULT_RTT, now + DEFAULT_RTT / 2);
+    let mut buf = [0; 3_001];
+    let (recvd, fin) = server.stream_recv(stream_id, &mut buf).unwrap();
+    assert_eq!(&buf[..recvd], &data[..]);
+    assert!(fin);
+}
+
+/// Packets on the second path are subject to the same ECN validation
+/// and counting as packets on the initial path.
+#[test]
+fn ecn_on_second_path() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    let (_, now) = connect_two_paths(&mut client, &mut server);
+
+    let before = server.stats().ecn_rx[packet::Type::Short][Ecn::Ect0];
+    let (second_path, _) = send_data(&mut client, &mut server, 100_000, now);
+    assert!(second_path > 0);
+    let after = server.stats().ecn_rx[packet::Type::Short][Ecn::Ect0];
+    assert!(after - before >= u64::try_from(second_path).unwrap());
+
+    for (outcome, count) in client.stats().ecn_path_validation.iter() {
+        match outcome {
+            ecn::ValidationOutcome::Capable => assert_eq!(*count, 2),
+            ecn::ValidationOutcome::NotCapable(_) => assert_eq!(*count, 0),
+        }
+    }
+}
+
+/// Each path has its own latency spin bit, which changes on the second path
+/// just as it does on the first.
+#[test]
+fn spin_on_second_path() {
+    let params = || multipath_params().spin_bit(SpinBitConfig::Enabled);
+    let mut client = new_client(params());
+    let mut server = new_server(params());
+    let (_, mut now) = connect_two_paths(&mut client, &mut server);
+
+    // Send in both directions, so that both endpoints use both paths.
+    let data = [0x73; 10_000];
+    let stream_id = client.stream_create(StreamType::BiDi).unwrap();
+    client.stream_send(stream_id, &data).unwrap();
+    let (_, t) = exchange(&mut client, &mut server, DEFAULT_RTT, now);
+    now = t;
+
+    let mut seen = [false; 2];
+    for _ in 0..20 {
+        client.stream_send(stream_id, &data).unwrap();
+        server.stream_send(stream_id, &data).unwrap();
+        let to_server = drain(&mut client, now);
+        // The value only changes between flights.
+        let spins = to_server
+            .iter()
+            .filter(|d| d.source() == DEFAULT_ADDR_V4)
+            .map(|d| d[0] & packet::BIT_SPIN == packet::BIT_SPIN)
+            .collect::<Vec<_>>();
+        assert!(spins.iter().all(|&s| Some(&s) == spins.first()));
+        for spin in spins {
+            seen[usize::from(spin)] = true;
+        }
+        now += DEFAULT_RTT / 2;
+        server.process_multiple_input(to_server, now);
+        let to_client = drain(&mut server, now);
+        now += DEFAULT_RTT / 2;
+        client.process_multiple_input(to_client, now);
+    }
+    assert_eq!(seen, [true, true]);
+}
+
+#[test]
+fn backup_path() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    let (path_id, now) = connect_two_paths(&mut client, &mut server);
+
+    client.set_path_status(path_id, PathStatus::Backup).unwrap();
+    let (_, now) = exchange(&mut client, &mut server, DEFAULT_RTT, now);
+    assert_eq!(server.stats().frame_rx.path_status, 1);
+
+    // Neither endpoint sends data on the backup path.
+    let (second_path, _) = send_data(&mut client, &mut server, 50_000, now);
+    assert_eq!(second_path, 0);
+}
+
+#[test]
+fn set_path_status_unknown() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    connect_force_idle(&mut client, &mut server);
+    assert_eq!(
+        client.set_path_status(PathId::new(1), PathStatus::Backup),
+        Err(Error::InvalidInput)
+    );
+}
+
+#[test]
+fn abandon_path() {
+    let mut client = multipath_client();
+    let mut server = multipath_server();
+    let (path_id, now) = connect_two_paths(&mut client, &mut server);
+
+    client.abandon_path(path_id, now).unwrap();
+    assert_eq!(client.abandon_path(path_id, now), Err(Error::InvalidInput));
+    let (_, now) = exchange(&mut client, &mut server, DEFAULT_RTT, now);
+    assert_eq!(server.stats().frame_rx.path_abandon, 1);
+    assert_eq!(client.stats().frame_rx.path_abandon, 1);
+    assert!(client.path_ids().is_empty());
+    assert!(server.path_ids().is_empty());
+
+    // After a while, both endpoints remove the path.
+    let now = now + AT_LEAST_PTO * 3;
+    _ = client.process_output(now);
+    _ = server.process_output(now);
+    assert_eq!(closed(&mut client), [path_id]);
+    assert_eq!(closed(&mut server), [path_id]);
+
+    // Data is only sent on the remaining path.
+    let (second_path, _) = send_data(&mut client, &mut server, 10_000, now);
+    assert_eq!(second_path, 0);
+}
+
+/// Multipath frames are not allowed if multipath wasn't negotiated.
+#[test]
+fn multipath_frame_without_negotiation() {
+    struct MaxPathIdWriter {}
+
+    impl test_internal::FrameWriter for MaxPathIdWriter {
+        fn write_frames(&mut self, builder: &mut packet::Builder<&mut Vec<u8>>) {
+            builder.encode_varint(FrameType::MaxPathId);
+            builder.encode_varint(4_u64);
+        }
+    }
+
+    let mut client = default_client();
+    let mut server = default_server();
+    connect_force_idle(&mut client, &mut server);
+
+    let dgram = client
+        .test_write_frames(MaxPathIdWriter {}, now())
+        .dgram()
+        .unwrap();
+    server.process_input(dgram, now());
+    assert!(matches!(
+        server.state(),
+        State::Closing {
+            error: CloseReason::Transport(Error::ProtocolViolation),
+            ..
+        }
+    ));
+}
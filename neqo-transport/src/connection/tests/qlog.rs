@@ -0,0 +1,150 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use qlog::events::EventImportance;
+use test_fixture::{SharedVec, new_neqo_qlog, new_neqo_qlog_with_importance, now};
+
+use super::{
+    Connection, DEFAULT_RTT, connect, connect_force_idle, connect_rtt_idle, default_client,
+    default_server, fill_cwnd, induce_persistent_congestion, new_client, new_server,
+    send_something,
+};
+use crate::{ConnectionParameters, SpinBitConfig, StreamType};
+
+fn with_qlog(mut c: Connection) -> (Connection, SharedVec) {
+    let (log, contents) = new_neqo_qlog();
+    c.set_qlog(log);
+    (c, contents)
+}
+
+/// Like `with_qlog`, but also logs events of extra importance.
+fn with_extra_qlog(mut c: Connection) -> (Connection, SharedVec) {
+    let (log, contents) = new_neqo_qlog_with_importance(EventImportance::Extra);
+    c.set_qlog(log);
+    (c, contents)
+}
+
+#[test]
+fn keys() {
+    let (mut client, contents) = with_qlog(default_client());
+    let mut server = default_server();
+    connect(&mut client, &mut server);
+
+    let contents = contents.to_string();
+    assert!(contents.contains(r#""name":"security:key_updated""#));
+    assert!(contents.contains(r#""key_type":"client_handshake_secret","new":"","trigger":"tls""#));
+    assert!(
+        contents
+            .contains(r#""key_type":"server_1rtt_secret","new":"","generation":0,"trigger":"tls""#)
+    );
+    // `qlog` calls this event "key_retired".
+    assert!(
+        contents.contains(
+            r#""name":"security:key_retired","data":{"key_type":"client_initial_secret"}"#
+        )
+    );
+    assert!(contents.contains(r#""key_type":"server_handshake_secret"}"#));
+}
+
+#[test]
+fn key_update() {
+    let (mut client, contents) = with_qlog(default_client());
+    let mut server = default_server();
+    connect(&mut client, &mut server);
+
+    client.initiate_key_update(now()).unwrap();
+    assert!(contents.to_string().contains(
+        r#""key_type":"client_1rtt_secret","new":"","generation":1,"trigger":"local_update""#
+    ));
+}
+
+#[test]
+fn loss_timer() {
+    let (mut client, contents) = with_extra_qlog(default_client());
+    let now = now();
+    drop(client.process_output(now).dgram());
+    let pto = client.process_output(now).callback();
+    drop(client.process_output(now + pto).dgram());
+
+    let contents = contents.to_string();
+    assert!(contents.contains(
+        r#""name":"recovery:loss_timer_updated","data":{"timer_type":"pto","packet_number_space":"initial","event_type":"set""#
+    ));
+    assert!(
+        contents.contains(
+            r#""timer_type":"pto","packet_number_space":"initial","event_type":"expired"}"#
+        )
+    );
+}
+
+#[test]
+fn paths_and_ecn() {
+    let mut client = default_client();
+    let (mut server, contents) = with_qlog(default_server());
+    connect(&mut client, &mut server);
+
+    let contents = contents.to_string();
+    assert!(contents.contains(
+        r#""name":"connectivity:path_assigned","data":{"path_id":"","path_local":{"ip_v6":"fe80::1","port_v6":443,"connection_ids":["#
+    ));
+    assert!(contents.contains(r#""name":"recovery:ecn_state_updated","data":{"new":"testing"}"#));
+}
+
+#[test]
+fn spin_bit_updated() {
+    let params = || ConnectionParameters::default().spin_bit(SpinBitConfig::Enabled);
+    let (mut client, contents) = with_qlog(new_client(params()));
+    let mut server = new_server(params());
+    connect_force_idle(&mut client, &mut server);
+
+    for _ in 0..3 {
+        let c = send_something(&mut client, now());
+        server.process_input(c, now());
+        let s = send_something(&mut server, now());
+        client.process_input(s, now());
+    }
+
+    // One event for each change to the value that the client sends.
+    let contents = contents.to_string();
+    let updates = contents.matches(r#""name":"connectivity:spin_bit_updated""#);
+    assert!(client.stats().spin_edges >= 3);
+    assert_eq!(updates.count(), client.stats().spin_edges);
+    assert!(contents.contains(r#""name":"connectivity:spin_bit_updated","data":{"state":"#));
+}
+
+#[test]
+fn mtu_updated() {
+    let (mut client, contents) =
+        with_extra_qlog(new_client(ConnectionParameters::default().pmtud(true)));
+    let mut server = default_server();
+    connect(&mut client, &mut server);
+
+    let mut now = now();
+    while !contents.to_string().contains("connectivity:mtu_updated") {
+        let dgram = send_something(&mut client, now);
+        let ack = server.process(Some(dgram), now).dgram();
+        now += client.process(ack, now).callback();
+    }
+    assert!(
+        contents
+            .to_string()
+            .contains(r#""old":1232,"new":1332,"done":false"#)
+    );
+}
+
+#[test]
+fn persistent_congestion() {
+    let (mut client, contents) = with_qlog(default_client());
+    let mut server = default_server();
+    let now = connect_rtt_idle(&mut client, &mut server, DEFAULT_RTT);
+
+    let stream = client.stream_create(StreamType::BiDi).unwrap();
+    let (_, now) = fill_cwnd(&mut client, stream, now);
+    induce_persistent_congestion(&mut client, &mut server, stream, now);
+    assert!(contents.to_string().contains(
+        r#""name":"recovery:congestion_state_updated","data":{"old":"recovery","new":"slow_start","trigger":"persistent_congestion"}"#
+    ));
+}
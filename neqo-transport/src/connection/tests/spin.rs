@@ -0,0 +1,106 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use neqo_common::Datagram;
+use test_fixture::now;
+
+use super::{
+    Connection, connect_force_idle, default_client, default_server, new_client, new_server,
+    send_something,
+};
+use crate::{ConnectionParameters, SpinBitConfig, packet};
+
+fn spin(d: &Datagram) -> bool {
+    assert_eq!(
+        d[0] & packet::BIT_LONG,
+        0,
+        "should be a short header packet"
+    );
+    d[0] & packet::BIT_SPIN == packet::BIT_SPIN
+}
+
+fn connect_with_spin(config: SpinBitConfig) -> (Connection, Connection) {
+    let mut client = new_client(ConnectionParameters::default().spin_bit(config));
+    let mut server = new_server(ConnectionParameters::default().spin_bit(config));
+    connect_force_idle(&mut client, &mut server);
+    (client, server)
+}
+
+/// The server reflects the spin bit and the client inverts it,
+/// so the value the client sends changes once per round trip.
+#[test]
+fn spin_each_round_trip() {
+    let (mut client, mut server) = connect_with_spin(SpinBitConfig::Enabled);
+    let client_edges = client.stats().spin_edges;
+
+    let mut expected = None;
+    for _ in 0..4 {
+        let c = send_something(&mut client, now());
+        let client_spin = spin(&c);
+        if let Some(expected) = expected {
+            assert_eq!(client_spin, expected);
+        }
+        server.process_input(c, now());
+        let s = send_something(&mut server, now());
+        assert_eq!(spin(&s), client_spin);
+        client.process_input(s, now());
+        expected = Some(!client_spin);
+    }
+    assert_eq!(client.stats().spin_edges, client_edges + 4);
+}
+
+/// A packet that arrives out of order does not change the spin bit.
+#[test]
+fn spin_reordered() {
+    let (mut client, mut server) = connect_with_spin(SpinBitConfig::Enabled);
+    let c = send_something(&mut client, now());
+    server.process_input(c, now());
+    let s = send_something(&mut server, now());
+    let delayed = send_something(&mut server, now());
+    client.process_input(s, now());
+    let c = send_something(&mut client, now());
+    server.process_input(c, now());
+    let s = send_something(&mut server, now());
+    assert_ne!(spin(&s), spin(&delayed));
+    client.process_input(s, now());
+    let client_spin = spin(&send_something(&mut client, now()));
+
+    client.process_input(delayed, now());
+    assert_eq!(spin(&send_something(&mut client, now())), client_spin);
+}
+
+#[test]
+fn spin_disabled() {
+    let (mut client, mut server) = connect_with_spin(SpinBitConfig::Disabled);
+    for _ in 0..8 {
+        let c = send_something(&mut client, now());
+        server.process_input(c, now());
+        let s = send_something(&mut server, now());
+        client.process_input(s, now());
+    }
+    assert_eq!(client.stats().spin_edges, 0);
+    assert_eq!(server.stats().spin_edges, 0);
+}
+
+/// The spin bit is only used when it is enabled.
+#[test]
+fn spin_off_by_default() {
+    assert_eq!(
+        ConnectionParameters::default().get_spin_bit(),
+        SpinBitConfig::Disabled
+    );
+    let mut client = default_client();
+    let mut server = default_server();
+    connect_force_idle(&mut client, &mut server);
+    for _ in 0..8 {
+        let c = send_something(&mut client, now());
+        server.process_input(c, now());
+        let s = send_something(&mut server, now());
+        client.process_input(s, now());
+    }
+    assert_eq!(client.stats().spin_edges, 0);
+    assert_eq!(server.stats().spin_edges, 0);
+}
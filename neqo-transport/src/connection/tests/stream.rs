@@ -1316,3 +1316,99 @@ fn client_stream_creatable_event() {
     connect_w_different_limit(1, 0);
     connect_w_different_limit(1, 1);
 }
+
+fn connect_reset_stream_at() -> (Connection, Connection) {
+    let params = ConnectionParameters::default().reset_stream_at(true);
+    let mut client = new_client(params.clone());
+    let mut server = new_server(params);
+    connect(&mut client, &mut server);
+    (client, server)
+}
+
+#[test]
+fn reset_stream_at() {
+    const RELIABLE: usize = 1000;
+    let (mut client, mut server) = connect_reset_stream_at();
+
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, &[0x61; 3000]).unwrap();
+    client
+        .stream_reset_send_at(stream_id, 7, u64::try_from(RELIABLE).unwrap())
+        .unwrap();
+    exchange_data(&mut client, &mut server);
+    assert_eq!(client.stats().frame_tx.reset_stream_at, 1);
+    assert_eq!(client.stats().frame_tx.reset_stream, 0);
+
+    // The reset is only reported once the reliable part of the stream is read.
+    let reset = |e| matches!(e, ConnectionEvent::RecvStreamReset { stream_id: id, app_error: 7 } if id == stream_id);
+    assert!(!server.events().any(reset));
+    let mut buf = [0; 3000];
+    let (len, fin) = server.stream_recv(stream_id, &mut buf).unwrap();
+    assert_eq!(len, RELIABLE);
+    assert!(!fin);
+    assert!(buf[..len].iter().all(|&b| b == 0x61));
+    assert!(server.events().any(reset));
+}
+
+#[test]
+fn reset_stream_at_reduce() {
+    let (mut client, mut server) = connect_reset_stream_at();
+
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, &[0x61; 3000]).unwrap();
+    client.stream_reset_send_at(stream_id, 7, 2000).unwrap();
+    // The reliable size can't be increased, but it can be reduced.
+    client.stream_reset_send_at(stream_id, 7, 2500).unwrap();
+    client.stream_reset_send_at(stream_id, 7, 500).unwrap();
+    exchange_data(&mut client, &mut server);
+
+    let mut buf = [0; 3000];
+    let (len, fin) = server.stream_recv(stream_id, &mut buf).unwrap();
+    assert_eq!(len, 500);
+    assert!(!fin);
+}
+
+#[test]
+fn reset_stream_at_too_large() {
+    let (mut client, _server) = connect_reset_stream_at();
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, &[0x61; 100]).unwrap();
+    assert_eq!(
+        client.stream_reset_send_at(stream_id, 7, 101),
+        Err(Error::InvalidInput)
+    );
+}
+
+#[test]
+fn reset_stream_at_not_supported() {
+    let mut client = default_client();
+    let mut server = default_server();
+    connect(&mut client, &mut server);
+
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, &[0x61; 100]).unwrap();
+    assert_eq!(
+        client.stream_reset_send_at(stream_id, 7, 10),
+        Err(Error::NotAvailable)
+    );
+    // Without any reliable data, this is the same as `stream_reset_send`.
+    client.stream_reset_send_at(stream_id, 7, 0).unwrap();
+    exchange_data(&mut client, &mut server);
+    assert_eq!(client.stats().frame_tx.reset_stream, 1);
+}
+
+/// A peer that didn't enable `RESET_STREAM_AT` treats the frame as an error.
+#[test]
+fn reset_stream_at_not_negotiated() {
+    let mut client = default_client();
+    let mut server = default_server();
+    connect(&mut client, &mut server);
+
+    let dgram = send_with_extra(
+        &mut client,
+        Writer(vec![FrameType::ResetStreamAt.into(), 2, 0, 10, 5]),
+        now(),
+    );
+    server.process_input(dgram, now());
+    assert_error(&server, &CloseReason::Transport(Error::ProtocolViolation));
+}
@@ -0,0 +1,127 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{cell::RefCell, io, rc::Rc};
+
+use test_fixture::now;
+
+use super::{connect, new_client, new_server};
+use crate::{
+    Connection, ConnectionParameters, RecvStreamHandle, SendStreamHandle, StreamError, StreamType,
+};
+
+/// Less than the data that the tests send, so that the sender has to buffer.
+const STREAM_LIMIT: u64 = 1000;
+
+fn connected() -> (Rc<RefCell<Connection>>, Rc<RefCell<Connection>>) {
+    let mut client = new_client(ConnectionParameters::default().pacing(false));
+    let mut server = new_server(
+        ConnectionParameters::default()
+            .pacing(false)
+            .max_stream_data(StreamType::BiDi, true, STREAM_LIMIT),
+    );
+    connect(&mut client, &mut server);
+    (Rc::new(RefCell::new(client)), Rc::new(RefCell::new(server)))
+}
+
+fn exchange(client: &Rc<RefCell<Connection>>, server: &Rc<RefCell<Connection>>) {
+    let mut dgram = None;
+    loop {
+        let out = client.borrow_mut().process(dgram, now()).dgram();
+        let back = server.borrow_mut().process(out.clone(), now()).dgram();
+        if out.is_none() && back.is_none() {
+            break;
+        }
+        dgram = back;
+    }
+}
+
+/// Open a stream on `client` that `server` knows about.
+fn open(
+    client: &Rc<RefCell<Connection>>,
+    server: &Rc<RefCell<Connection>>,
+) -> (SendStreamHandle, RecvStreamHandle) {
+    let stream_id = client.borrow_mut().stream_create(StreamType::BiDi).unwrap();
+    let mut send = SendStreamHandle::new(Rc::clone(client), stream_id).unwrap();
+    send.write(&[0]).unwrap();
+    exchange(client, server);
+    let recv = RecvStreamHandle::new(Rc::clone(server), stream_id).unwrap();
+    (send, recv)
+}
+
+#[test]
+fn buffered_transfer() {
+    let (client, server) = connected();
+    let (mut send, mut recv) = open(&client, &server);
+    let body = vec![7; 10_000];
+    assert_eq!(send.write(&body).unwrap(), body.len());
+    assert!(send.buffered() > 0);
+    assert!(!send.is_writable());
+    send.close().unwrap();
+    assert_eq!(send.final_size(), None);
+
+    let mut received = Vec::new();
+    assert!(recv.is_readable());
+    while !recv.is_finished() {
+        recv.read_to_end(&mut received).unwrap();
+        // Credit from the receiver lets the sender pass on more of its buffer.
+        exchange(&client, &server);
+        send.flush().unwrap();
+        exchange(&client, &server);
+    }
+    assert_eq!(received.len(), body.len() + 1);
+    assert_eq!(send.buffered(), 0);
+    assert_eq!(send.final_size(), Some(received.len() as u64));
+    assert_eq!(recv.final_size(), Some(received.len() as u64));
+    assert!(!recv.is_readable());
+    assert_eq!(recv.read(&mut [0; 1]).unwrap(), (0, true));
+}
+
+#[test]
+fn would_block() {
+    let (client, server) = connected();
+    let (mut send, mut recv) = open(&client, &server);
+    let mut buf = [0; 10];
+    assert_eq!(io::Read::read(&mut recv, &mut buf).unwrap(), 1);
+    assert!(!recv.is_readable());
+    assert_eq!(
+        io::Read::read(&mut recv, &mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    io::Write::write_all(&mut send, &[1; 2000]).unwrap();
+    assert_eq!(
+        io::Write::flush(&mut send).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+}
+
+#[test]
+fn peer_reset() {
+    let (client, server) = connected();
+    let (mut send, mut recv) = open(&client, &server);
+    send.reset(11).unwrap();
+    assert_eq!(send.final_size(), Some(1));
+    exchange(&client, &server);
+
+    assert!(recv.is_readable());
+    assert_eq!(recv.read(&mut [0; 10]), Err(StreamError::Reset(11)));
+    let err = io::Read::read(&mut recv, &mut [0; 10]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+}
+
+#[test]
+fn peer_stop_sending() {
+    let (client, server) = connected();
+    let (mut send, recv) = open(&client, &server);
+    recv.stop_sending(12).unwrap();
+    exchange(&client, &server);
+
+    assert!(!send.is_writable());
+    assert_eq!(send.write(&[0]), Err(StreamError::StopSending(12)));
+    let err = io::Write::write(&mut send, &[0]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
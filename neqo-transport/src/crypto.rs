@@ -14,23 +14,25 @@ use std::{
     time::Instant,
 };
 
+use ::qlog::events::security::KeyUpdateOrRetiredTrigger;
 use enum_map::EnumMap;
-use neqo_common::{Buffer, Encoder, Role, hex, hex_snip_middle, qdebug, qinfo, qtrace};
+use neqo_common::{Buffer, Encoder, Role, hex, hex_snip_middle, qdebug, qinfo, qlog::Qlog, qtrace};
 pub use neqo_crypto::Epoch;
 use neqo_crypto::{
-    Aead, AeadTrait as _, Agent, AntiReplay, Cipher, Error as CryptoError, HandshakeState,
-    PrivateKey, PublicKey, Record, RecordList, ResumptionToken, SymKey, TLS_AES_128_GCM_SHA256,
-    TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256, TLS_CT_HANDSHAKE, TLS_GRP_EC_SECP256R1,
-    TLS_GRP_EC_SECP384R1, TLS_GRP_EC_SECP521R1, TLS_GRP_EC_X25519, TLS_GRP_KEM_MLKEM768X25519,
-    TLS_VERSION_1_3, ZeroRttChecker, hkdf, hp, random,
+    Aead, AeadTrait as _, Agent, AntiReplay, Cipher, Error as CryptoError, HandshakeState, KeyLog,
+    KeyLogLabel, PrivateKey, PublicKey, Record, RecordList, ResumptionToken, SymKey,
+    TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256, TLS_CT_HANDSHAKE,
+    TLS_GRP_EC_SECP256R1, TLS_GRP_EC_SECP384R1, TLS_GRP_EC_SECP521R1, TLS_GRP_EC_X25519,
+    TLS_GRP_KEM_MLKEM768X25519, TLS_VERSION_1_3, ZeroRttChecker, hkdf, hp, random,
 };
 
 use crate::{
     ConnectionParameters, Error, Res,
     cid::ConnectionIdRef,
     frame::{FrameEncoder as _, FrameType},
+    multipath::PathId,
     packet::{self},
-    recovery,
+    qlog, recovery,
     recv_stream::RxStreamOrderer,
     send_stream::TxBuffer,
     sni::find_sni,
@@ -112,15 +114,27 @@ impl Crypto {
             Version::Draft29 => 0xffa5,
         };
         agent.extension_handler(extension, tphandler)?;
+        let role = match agent {
+            Agent::Client(_) => Role::Client,
+            Agent::Server(_) => Role::Server,
+        };
         Ok(Self {
             version,
             protocols,
             tls: agent,
             streams: CryptoStreams::default(),
-            states: CryptoStates::default(),
+            states: CryptoStates::new(role),
         })
     }
 
+    pub fn set_qlog(&mut self, qlog: Qlog) {
+        self.states.set_qlog(qlog);
+    }
+
+    pub fn set_keylog(&mut self, keylog: KeyLog) {
+        self.tls.set_keylog(keylog);
+    }
+
     /// Get the name of the server.  (Only works for the client currently).
     pub fn server_name(&self) -> Option<&str> {
         if let Agent::Client(c) = &self.tls {
@@ -218,7 +232,7 @@ impl Crypto {
     }
 
     /// Enable 0-RTT and return `true` if it is enabled successfully.
-    pub fn enable_0rtt(&mut self, version: Version, role: Role) -> Res<bool> {
+    pub fn enable_0rtt(&mut self, version: Version, role: Role, now: Instant) -> Res<bool> {
         let info = self.tls.preinfo()?;
         // `info.early_data()` returns false for a server,
         // so use `early_data_cipher()` to tell if 0-RTT is enabled.
@@ -238,7 +252,7 @@ impl Crypto {
         };
         let secret = secret.ok_or(Error::Internal)?;
         self.states
-            .set_0rtt_keys(version, dir, &secret, cipher.ok_or(Error::Internal)?)?;
+            .set_0rtt_keys(version, dir, &secret, cipher.ok_or(Error::Internal)?, now)?;
         Ok(true)
     }
 
@@ -250,19 +264,19 @@ impl Crypto {
     }
 
     /// Returns true if new handshake keys were installed.
-    pub fn install_keys(&mut self, role: Role) -> Res<bool> {
+    pub fn install_keys(&mut self, role: Role, now: Instant) -> Res<bool> {
         if self.tls.state().is_final() {
             Ok(false)
         } else {
-            let installed_hs = self.install_handshake_keys()?;
+            let installed_hs = self.install_handshake_keys(now)?;
             if role == Role::Server {
-                self.maybe_install_application_write_key(self.version)?;
+                self.maybe_install_application_write_key(self.version, now)?;
             }
             Ok(installed_hs)
         }
     }
 
-    fn install_handshake_keys(&mut self) -> Res<bool> {
+    fn install_handshake_keys(&mut self, now: Instant) -> Res<bool> {
         qtrace!("[{self}] Attempt to install handshake keys");
         let Some(write_secret) = self.tls.write_secret(Epoch::Handshake) else {
             // No keys is fine.
@@ -278,7 +292,7 @@ impl Crypto {
         }
         .ok_or(Error::Internal)?;
         self.states
-            .set_handshake_keys(self.version, &write_secret, &read_secret, cipher)?;
+            .set_handshake_keys(self.version, &write_secret, &read_secret, cipher, now)?;
         qdebug!("[{self}] Handshake keys installed");
         Ok(true)
     }
@@ -288,17 +302,23 @@ impl Crypto {
         self.states.handshake.is_some() || self.states.app_write.is_some()
     }
 
-    fn maybe_install_application_write_key(&mut self, version: Version) -> Res<()> {
+    fn maybe_install_application_write_key(&mut self, version: Version, now: Instant) -> Res<()> {
         qtrace!("[{self}] Attempt to install application write key");
         if let Some(secret) = self.tls.write_secret(Epoch::ApplicationData) {
-            self.states.set_application_write_key(version, &secret)?;
+            self.states
+                .set_application_write_key(version, &secret, now)?;
             qdebug!("[{self}] Application write key installed");
         }
         Ok(())
     }
 
-    pub fn install_application_keys(&mut self, version: Version, expire_0rtt: Instant) -> Res<()> {
-        self.maybe_install_application_write_key(version)?;
+    pub fn install_application_keys(
+        &mut self,
+        version: Version,
+        expire_0rtt: Instant,
+        now: Instant,
+    ) -> Res<()> {
+        self.maybe_install_application_write_key(version, now)?;
         // The write key might have been installed earlier, but it should
         // always be installed now.
         debug_assert!(self.states.app_write.is_some());
@@ -307,8 +327,12 @@ impl Crypto {
             .read_secret(Epoch::ApplicationData)
             .ok_or(Error::Internal)?;
         self.states
-            .set_application_read_key(version, &read_secret, expire_0rtt)?;
+            .set_application_read_key(version, &read_secret, expire_0rtt, now)?;
         qdebug!("[{self}] application read keys installed");
+        // Secrets from key updates are derived here rather than in TLS.
+        if let (Some(keylog), Some(client_random)) = (self.tls.keylog(), self.tls.client_random()) {
+            self.states.keylog = Some((keylog.clone(), *client_random));
+        }
         Ok(())
     }
 
@@ -364,9 +388,9 @@ impl Crypto {
 
     /// Discard state for a packet number space and return true
     /// if something was discarded.
-    pub fn discard(&mut self, space: PacketNumberSpace) -> bool {
+    pub fn discard(&mut self, space: PacketNumberSpace, now: Instant) -> bool {
         self.streams.discard(space);
-        self.states.discard(space)
+        self.states.discard(space, now)
     }
 
     pub fn create_resumption_token(
@@ -678,6 +702,21 @@ impl CryptoDxState {
         pn: packet::Number,
         hdr: Range<usize>,
         data: &mut [u8],
+    ) -> Res<usize> {
+        self.encrypt_for_path(PathId::INITIAL, pn, hdr, data)
+    }
+
+    /// Encrypt a packet that is sent on the identified path.
+    ///
+    /// Paths other than the initial path have their own packet number spaces,
+    /// so their packet numbers are not tracked here.  This means that only
+    /// packets on the initial path contribute to decisions about key updates.
+    pub fn encrypt_for_path(
+        &mut self,
+        path_id: PathId,
+        pn: packet::Number,
+        hdr: Range<usize>,
+        data: &mut [u8],
     ) -> Res<usize> {
         debug_assert_eq!(self.direction, CryptoDxDirection::Write);
         qtrace!(
@@ -701,6 +740,13 @@ impl CryptoDxState {
         let (prev, data) = data.split_at_mut(hdr.end);
         // `prev` may have already-encrypted packets this one is being coalesced with.
         // Use only the actual current header for AAD.
+        if !path_id.is_initial() {
+            let len =
+                self.aead
+                    .encrypt_in_place_for_path(path_id.as_u32(), pn, &prev[hdr], data)?;
+            qtrace!("[{self}] encrypt path={path_id} ct={}", hex(&data[..len]));
+            return Ok(len);
+        }
         let len = self.aead.encrypt_in_place(pn, &prev[hdr], data)?;
 
         qtrace!("[{self}] encrypt ct={}", hex(&data[..len]));
@@ -719,6 +765,18 @@ impl CryptoDxState {
         pn: packet::Number,
         hdr: Range<usize>,
         data: &mut [u8],
+    ) -> Res<usize> {
+        self.decrypt_for_path(PathId::INITIAL, pn, hdr, data)
+    }
+
+    /// Decrypt a packet that was received on the identified path.
+    /// As with `encrypt_for_path`, only the initial path tracks packet numbers.
+    pub fn decrypt_for_path(
+        &mut self,
+        path_id: PathId,
+        pn: packet::Number,
+        hdr: Range<usize>,
+        data: &mut [u8],
     ) -> Res<usize> {
         debug_assert_eq!(self.direction, CryptoDxDirection::Read);
         qtrace!(
@@ -728,6 +786,11 @@ impl CryptoDxState {
         );
         self.invoked()?;
         let (hdr, data) = data.split_at_mut(hdr.end);
+        if !path_id.is_initial() {
+            return Ok(self
+                .aead
+                .decrypt_in_place_for_path(path_id.as_u32(), pn, hdr, data)?);
+        }
         let len = self.aead.decrypt_in_place(pn, hdr, data)?;
         self.used(pn)?;
         Ok(len)
@@ -820,8 +883,9 @@ impl CryptoDxAppData {
 /// Note that the methods on this struct take a version but those are only ever
 /// used for Initial keys; a version has been selected at the time we need to
 /// get other keys, so those have fixed versions.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct CryptoStates {
+    role: Role,
     initials: EnumMap<Version, Option<CryptoState>>,
     handshake: Option<CryptoState>,
     zero_rtt: Option<CryptoDxState>, // One direction only!
@@ -832,9 +896,89 @@ pub struct CryptoStates {
     // If this is set, then we have noticed a genuine update.
     // Once this time passes, we should switch in new keys.
     read_update_time: Option<Instant>,
+    qlog: Qlog,
+    /// Where to write the secrets from key updates, with the `ClientHello`
+    /// random that identifies this connection.
+    keylog: Option<(KeyLog, [u8; 32])>,
 }
 
 impl CryptoStates {
+    #[must_use]
+    pub fn new(role: Role) -> Self {
+        Self {
+            role,
+            initials: EnumMap::default(),
+            handshake: None,
+            zero_rtt: None,
+            cipher: 0,
+            app_write: None,
+            app_read: None,
+            app_read_next: None,
+            read_update_time: None,
+            qlog: Qlog::default(),
+            keylog: None,
+        }
+    }
+
+    pub fn set_qlog(&mut self, qlog: Qlog) {
+        self.qlog = qlog;
+    }
+
+    fn key_updated(
+        &mut self,
+        direction: CryptoDxDirection,
+        epoch: usize,
+        trigger: Option<KeyUpdateOrRetiredTrigger>,
+        now: Instant,
+    ) {
+        qlog::key_updated(&mut self.qlog, self.role, direction, epoch, trigger, now);
+    }
+
+    fn key_discarded(
+        &mut self,
+        direction: CryptoDxDirection,
+        epoch: usize,
+        trigger: Option<KeyUpdateOrRetiredTrigger>,
+        now: Instant,
+    ) {
+        qlog::key_discarded(&mut self.qlog, self.role, direction, epoch, trigger, now);
+    }
+
+    /// Log both directions of keys for `epoch` as installed or discarded.
+    fn keys_updated(
+        &mut self,
+        epoch: Epoch,
+        trigger: Option<KeyUpdateOrRetiredTrigger>,
+        now: Instant,
+    ) {
+        self.key_updated(
+            CryptoDxDirection::Write,
+            usize::from(epoch),
+            trigger.clone(),
+            now,
+        );
+        self.key_updated(CryptoDxDirection::Read, usize::from(epoch), trigger, now);
+    }
+
+    fn keys_discarded(&mut self, epoch: Epoch, now: Instant) {
+        self.key_discarded(CryptoDxDirection::Write, usize::from(epoch), None, now);
+        self.key_discarded(CryptoDxDirection::Read, usize::from(epoch), None, now);
+    }
+
+    /// Log the secret for the keys that follow those in `app`.
+    fn log_next_secret(&self, direction: CryptoDxDirection, app: &CryptoDxAppData) {
+        let Some((keylog, client_random)) = &self.keylog else {
+            return;
+        };
+        let generation = app.epoch() + 1 - usize::from(Epoch::ApplicationData);
+        let label = if (direction == CryptoDxDirection::Write) == (self.role == Role::Client) {
+            KeyLogLabel::ClientTraffic(generation)
+        } else {
+            KeyLogLabel::ServerTraffic(generation)
+        };
+        keylog.log(label, client_random, &app.next_secret);
+    }
+
     fn initials_is_empty(&self) -> bool {
         self.initials.values().flatten().count() == 0
     }
@@ -1019,6 +1163,7 @@ impl CryptoStates {
         role: Role,
         dcid: &[u8],
         randomize_first_pn: bool,
+        now: Instant,
     ) -> Res<()>
     where
         V: IntoIterator<Item = &'v Version>,
@@ -1065,6 +1210,7 @@ impl CryptoStates {
             }
             self.initials[*v] = Some(initial);
         }
+        self.keys_updated(Epoch::Initial, None, now);
         Ok(())
     }
 
@@ -1079,9 +1225,10 @@ impl CryptoStates {
         version: Version,
         dcid: &[u8],
         randomize_first_pn: bool,
+        now: Instant,
     ) -> Res<()> {
         if self.initials[version].is_none() {
-            self.init(&[version], Role::Server, dcid, randomize_first_pn)?;
+            self.init(&[version], Role::Server, dcid, randomize_first_pn, now)?;
         }
         Ok(())
     }
@@ -1110,6 +1257,7 @@ impl CryptoStates {
         dir: CryptoDxDirection,
         secret: &SymKey,
         cipher: Cipher,
+        now: Instant,
     ) -> Res<()> {
         qtrace!("[{self}] install 0-RTT keys");
         self.zero_rtt = Some(CryptoDxState::new(
@@ -1120,29 +1268,51 @@ impl CryptoStates {
             cipher,
             0,
         )?);
+        self.key_updated(
+            dir,
+            usize::from(Epoch::ZeroRtt),
+            Some(KeyUpdateOrRetiredTrigger::Tls),
+            now,
+        );
         Ok(())
     }
 
     /// Discard keys and return true if that happened.
-    pub fn discard(&mut self, space: PacketNumberSpace) -> bool {
+    pub fn discard(&mut self, space: PacketNumberSpace, now: Instant) -> bool {
         match space {
             PacketNumberSpace::Initial => {
                 let empty = self.initials_is_empty();
                 self.initials.clear();
+                if !empty {
+                    self.keys_discarded(Epoch::Initial, now);
+                }
                 !empty
             }
-            PacketNumberSpace::Handshake => self.handshake.take().is_some(),
+            PacketNumberSpace::Handshake => {
+                let discarded = self.handshake.take().is_some();
+                if discarded {
+                    self.keys_discarded(Epoch::Handshake, now);
+                }
+                discarded
+            }
             PacketNumberSpace::ApplicationData => panic!("Can't drop application data keys"),
         }
     }
 
-    pub fn discard_0rtt_keys(&mut self) {
+    /// Drop 0-RTT keys, if there are any.
+    fn drop_0rtt(&mut self, now: Instant) {
+        if let Some(z) = self.zero_rtt.take() {
+            self.key_discarded(z.direction, usize::from(Epoch::ZeroRtt), None, now);
+        }
+    }
+
+    pub fn discard_0rtt_keys(&mut self, now: Instant) {
         qtrace!("[{self}] discard 0-RTT keys");
         assert!(
             self.app_read.is_none(),
             "Can't discard 0-RTT after setting application keys"
         );
-        self.zero_rtt = None;
+        self.drop_0rtt(now);
     }
 
     pub fn set_handshake_keys(
@@ -1151,6 +1321,7 @@ impl CryptoStates {
         write_secret: &SymKey,
         read_secret: &SymKey,
         cipher: Cipher,
+        now: Instant,
     ) -> Res<()> {
         self.cipher = cipher;
         self.handshake = Some(CryptoState {
@@ -1171,10 +1342,16 @@ impl CryptoStates {
                 0,
             )?,
         });
+        self.keys_updated(Epoch::Handshake, Some(KeyUpdateOrRetiredTrigger::Tls), now);
         Ok(())
     }
 
-    pub fn set_application_write_key(&mut self, version: Version, secret: &SymKey) -> Res<()> {
+    pub fn set_application_write_key(
+        &mut self,
+        version: Version,
+        secret: &SymKey,
+        now: Instant,
+    ) -> Res<()> {
         debug_assert!(self.app_write.is_none());
         debug_assert_ne!(self.cipher, 0);
         let mut app = CryptoDxAppData::new(version, CryptoDxDirection::Write, secret, self.cipher)?;
@@ -1183,8 +1360,14 @@ impl CryptoStates {
         {
             app.dx.continuation(z)?;
         }
-        self.zero_rtt = None;
+        self.drop_0rtt(now);
         self.app_write = Some(app);
+        self.key_updated(
+            CryptoDxDirection::Write,
+            usize::from(Epoch::ApplicationData),
+            Some(KeyUpdateOrRetiredTrigger::Tls),
+            now,
+        );
         Ok(())
     }
 
@@ -1193,6 +1376,7 @@ impl CryptoStates {
         version: Version,
         secret: &SymKey,
         expire_0rtt: Instant,
+        now: Instant,
     ) -> Res<()> {
         debug_assert!(self.app_write.is_some(), "should have write keys installed");
         debug_assert!(self.app_read.is_none());
@@ -1205,11 +1389,21 @@ impl CryptoStates {
         }
         self.app_read_next = Some(app.next()?);
         self.app_read = Some(app);
+        self.key_updated(
+            CryptoDxDirection::Read,
+            usize::from(Epoch::ApplicationData),
+            Some(KeyUpdateOrRetiredTrigger::Tls),
+            now,
+        );
         Ok(())
     }
 
     /// Update the write keys.
-    pub fn initiate_key_update(&mut self, largest_acknowledged: Option<packet::Number>) -> Res<()> {
+    pub fn initiate_key_update(
+        &mut self,
+        largest_acknowledged: Option<packet::Number>,
+        now: Instant,
+    ) -> Res<()> {
         // Only update if we are able to. We can only do this if we have
         // received an acknowledgement for a packet in the current phase.
         // Also, skip this if we are waiting for read keys on the existing
@@ -1218,7 +1412,7 @@ impl CryptoStates {
         if write.can_update(largest_acknowledged) && self.read_update_time.is_none() {
             // This call additionally checks that we don't advance to the next
             // epoch while a key update is in progress.
-            if self.maybe_update_write()? {
+            if self.maybe_update_write(KeyUpdateOrRetiredTrigger::LocalUpdate, now)? {
                 Ok(())
             } else {
                 qdebug!("[{self}] Write keys already updated");
@@ -1231,7 +1425,11 @@ impl CryptoStates {
     }
 
     /// Try to update, and return true if it happened.
-    fn maybe_update_write(&mut self) -> Res<bool> {
+    fn maybe_update_write(
+        &mut self,
+        trigger: KeyUpdateOrRetiredTrigger,
+        now: Instant,
+    ) -> Res<bool> {
         // Update write keys.  But only do so if the write keys are not already
         // ahead of the read keys.  If we initiated the key update, the write keys
         // will already be ahead.
@@ -1240,7 +1438,11 @@ impl CryptoStates {
         let read = &self.app_read.as_ref().ok_or(Error::Internal)?;
         if write.epoch() == read.epoch() {
             qdebug!("[{self}] Update write keys to epoch={}", write.epoch() + 1);
-            self.app_write = Some(write.next()?);
+            self.log_next_secret(CryptoDxDirection::Write, write);
+            let next = write.next()?;
+            let epoch = next.epoch();
+            self.app_write = Some(next);
+            self.key_updated(CryptoDxDirection::Write, epoch, Some(trigger), now);
             Ok(true)
         } else {
             Ok(false)
@@ -1250,12 +1452,12 @@ impl CryptoStates {
     /// Check whether write keys are close to running out of invocations.
     /// If that is close, update them if possible.  Failing to update at
     /// this stage is cause for a fatal error.
-    pub fn auto_update(&mut self) -> Res<()> {
+    pub fn auto_update(&mut self, now: Instant) -> Res<()> {
         if let Some(app_write) = self.app_write.as_ref()
             && app_write.dx.should_update()
         {
             qinfo!("[{self}] Initiating automatic key update");
-            if !self.maybe_update_write()? {
+            if !self.maybe_update_write(KeyUpdateOrRetiredTrigger::LocalUpdate, now)? {
                 return Err(Error::KeysExhausted);
             }
         }
@@ -1272,19 +1474,22 @@ impl CryptoStates {
     /// Prepare to update read keys.  This doesn't happen immediately as
     /// we want to ensure that we can continue to receive any delayed
     /// packets that use the old keys.  So we just set a timer.
-    pub fn key_update_received(&mut self, expiration: Instant) -> Res<()> {
+    pub fn key_update_received(&mut self, expiration: Instant, now: Instant) -> Res<()> {
         qtrace!("[{self}] Key update received");
         // If we received a key update, then we assume that the peer has
         // acknowledged a packet we sent in this epoch. It's OK to do that
         // because they aren't allowed to update without first having received
         // something from us. If the ACK isn't in the packet that triggered this
         // key update, it must be in some other packet they have sent.
-        _ = self.maybe_update_write()?;
+        _ = self.maybe_update_write(KeyUpdateOrRetiredTrigger::RemoteUpdate, now)?;
+        if let Some(app_read) = &self.app_read {
+            self.log_next_secret(CryptoDxDirection::Read, app_read);
+        }
 
         // We shouldn't have 0-RTT keys at this point, but if we do, dump them.
         debug_assert_eq!(self.read_update_time.is_some(), self.has_0rtt_read());
         if self.has_0rtt_read() {
-            self.zero_rtt = None;
+            self.drop_0rtt(now);
         }
         self.read_update_time = Some(expiration);
         Ok(())
@@ -1304,12 +1509,19 @@ impl CryptoStates {
             if now >= expiry {
                 if self.has_0rtt_read() {
                     qtrace!("[{self}] Discarding 0-RTT keys");
-                    self.zero_rtt = None;
+                    self.drop_0rtt(now);
                 } else {
                     qtrace!("[{self}] Rotating read keys");
                     mem::swap(&mut self.app_read, &mut self.app_read_next);
-                    self.app_read_next =
-                        Some(self.app_read.as_ref().ok_or(Error::Internal)?.next()?);
+                    let app_read = self.app_read.as_ref().ok_or(Error::Internal)?;
+                    let epoch = app_read.epoch();
+                    self.app_read_next = Some(app_read.next()?);
+                    self.key_updated(
+                        CryptoDxDirection::Read,
+                        epoch,
+                        Some(KeyUpdateOrRetiredTrigger::RemoteUpdate),
+                        now,
+                    );
                 }
                 self.read_update_time = None;
             }
@@ -1362,6 +1574,7 @@ impl CryptoStates {
             None,
         ]);
         Self {
+            role: Role::Client,
             initials,
             handshake: None,
             zero_rtt: None,
@@ -1371,6 +1584,8 @@ impl CryptoStates {
             app_read: Some(app_read(3)),
             app_read_next: Some(app_read(4)),
             read_update_time: None,
+            qlog: Qlog::default(),
+            keylog: None,
         }
     }
 
@@ -1411,6 +1626,7 @@ impl CryptoStates {
             next_secret: secret.clone(),
         };
         Self {
+            role: Role::Client,
             initials: EnumMap::default(),
             handshake: None,
             zero_rtt: None,
@@ -1419,6 +1635,8 @@ impl CryptoStates {
             app_read: Some(app_read(3)),
             app_read_next: Some(app_read(4)),
             read_update_time: None,
+            qlog: Qlog::default(),
+            keylog: None,
         }
     }
 }
@@ -4,12 +4,15 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::ops::{AddAssign, Deref, DerefMut, Sub};
+use std::{
+    ops::{AddAssign, Deref, DerefMut, Sub},
+    time::Instant,
+};
 
 use enum_map::{Enum, EnumMap};
-use neqo_common::{Ecn, qdebug, qinfo};
+use neqo_common::{Ecn, qdebug, qinfo, qlog::Qlog};
 
-use crate::{Stats, packet, recovery::sent};
+use crate::{Stats, packet, qlog, recovery::sent};
 
 /// The number of packets to use for testing a path for ECN capability.
 pub(crate) const TEST_COUNT: usize = 10;
@@ -44,6 +47,17 @@ enum ValidationState {
 }
 
 impl ValidationState {
+    /// The name of the state in qlog.  `NotStarted` is not logged.
+    const fn to_qlog(self) -> Option<&'static str> {
+        match self {
+            Self::NotStarted => None,
+            Self::Testing { .. } => Some("testing"),
+            Self::Unknown => Some("unknown"),
+            Self::Failed(_) => Some("failed"),
+            Self::Capable => Some("capable"),
+        }
+    }
+
     fn set(&mut self, new: Self, stats: &mut Stats) {
         let old = std::mem::replace(self, new);
 
@@ -167,21 +181,36 @@ pub(crate) struct Info {
 
     /// The ECN counts from the last ACK frame that increased `largest_acked`.
     baseline: Count,
+
+    qlog: Qlog,
 }
 
 impl Info {
-    pub(crate) fn start(&mut self, stats: &mut Stats) {
+    pub(crate) fn set_qlog(&mut self, qlog: Qlog) {
+        self.qlog = qlog;
+    }
+
+    fn set_state(&mut self, new: ValidationState, stats: &mut Stats, now: Instant) {
+        let old = self.state.to_qlog();
+        self.state.set(new, stats);
+        if let Some(new) = new.to_qlog() {
+            qlog::ecn_state_updated(&mut self.qlog, old, new, now);
+        }
+    }
+
+    pub(crate) fn start(&mut self, stats: &mut Stats, now: Instant) {
         if !matches!(self.state, ValidationState::NotStarted) {
             return;
         }
 
-        self.state.set(
+        self.set_state(
             ValidationState::Testing {
                 probes_sent: 0,
                 initial_probes_acked: 0,
                 initial_probes_lost: 0,
             },
             stats,
+            now,
         );
     }
 
@@ -199,20 +228,20 @@ impl Info {
     /// Exit ECN validation if the number of packets sent exceeds `TEST_COUNT`.
     /// We do not implement the part of the RFC that says to exit ECN validation if the time since
     /// the start of ECN validation exceeds 3 * PTO, since this seems to happen much too quickly.
-    pub(crate) fn on_packet_sent(&mut self, num_datagrams: usize, stats: &mut Stats) {
+    pub(crate) fn on_packet_sent(&mut self, num_datagrams: usize, stats: &mut Stats, now: Instant) {
         if let ValidationState::Testing { probes_sent, .. } = &mut self.state {
             *probes_sent += num_datagrams;
             qdebug!("ECN probing: sent {probes_sent} probes");
             if *probes_sent >= TEST_COUNT {
                 qdebug!("ECN probing concluded with {probes_sent} probes sent");
-                self.state.set(ValidationState::Unknown, stats);
+                self.set_state(ValidationState::Unknown, stats, now);
             }
         }
     }
 
     /// Disable ECN.
-    pub(crate) fn disable_ecn(&mut self, stats: &mut Stats, reason: ValidationError) {
-        self.state.set(ValidationState::Failed(reason), stats);
+    pub(crate) fn disable_ecn(&mut self, stats: &mut Stats, reason: ValidationError, now: Instant) {
+        self.set_state(ValidationState::Failed(reason), stats, now);
     }
 
     /// Process ECN counts from an ACK frame.
@@ -223,10 +252,11 @@ impl Info {
         acked_packets: &[sent::Packet],
         ack_ecn: Option<&Count>,
         stats: &mut Stats,
+        now: Instant,
     ) -> bool {
         let prev_baseline = self.baseline;
 
-        self.validate_ack_ecn_and_update(acked_packets, ack_ecn, stats);
+        self.validate_ack_ecn_and_update(acked_packets, ack_ecn, stats, now);
 
         matches!(self.state, ValidationState::Capable)
             && (self.baseline - prev_baseline)[Ecn::Ce] > 0
@@ -244,7 +274,7 @@ impl Info {
     }
 
     /// An [`Ecn::Ect0`] marked packet has been declared lost.
-    pub(crate) fn lost_ecn(&mut self, stats: &mut Stats) {
+    pub(crate) fn lost_ecn(&mut self, stats: &mut Stats, now: Instant) {
         if let ValidationState::Testing {
             initial_probes_acked: probes_acked,
             initial_probes_lost: probes_lost,
@@ -258,7 +288,7 @@ impl Info {
                 qdebug!(
                     "ECN validation failed, all {probes_lost} initial marked packets were lost"
                 );
-                self.disable_ecn(stats, ValidationError::BlackHole);
+                self.disable_ecn(stats, ValidationError::BlackHole, now);
             }
         }
     }
@@ -269,6 +299,7 @@ impl Info {
         acked_packets: &[sent::Packet],
         ack_ecn: Option<&Count>,
         stats: &mut Stats,
+        now: Instant,
     ) {
         // RFC 9000, Section 13.4.2.1:
         //
@@ -303,7 +334,7 @@ impl Info {
         // > corresponding ECN counts are not present in the ACK frame.
         let Some(ack_ecn) = ack_ecn else {
             qinfo!("ECN validation failed, no ECN counts in ACK frame");
-            self.disable_ecn(stats, ValidationError::Bleaching);
+            self.disable_ecn(stats, ValidationError::Bleaching, now);
             return;
         };
         let ack_ecn = *ack_ecn;
@@ -324,13 +355,13 @@ impl Info {
             qinfo!(
                 "ECN validation failed, ACK counted {sum_inc} new marks, but {newly_acked_sent_with_ect0} of newly acked packets were sent with ECT(0)"
             );
-            self.disable_ecn(stats, ValidationError::Bleaching);
+            self.disable_ecn(stats, ValidationError::Bleaching, now);
         } else if ecn_diff[Ecn::Ect1] > 0 {
             qinfo!("ECN validation failed, ACK counted ECT(1) marks that were never sent");
-            self.disable_ecn(stats, ValidationError::ReceivedUnsentECT1);
+            self.disable_ecn(stats, ValidationError::ReceivedUnsentECT1, now);
         } else if self.state != ValidationState::Capable {
             qinfo!("ECN validation succeeded, path is capable");
-            self.state.set(ValidationState::Capable, stats);
+            self.set_state(ValidationState::Capable, stats, now);
         }
         self.baseline = ack_ecn;
         self.largest_acked = largest_acked.pn();
@@ -358,7 +389,12 @@ impl Info {
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 mod tests {
-    use super::Count;
+    use std::time::{Duration, Instant};
+
+    use test_fixture::new_neqo_qlog;
+
+    use super::{Count, Info};
+    use crate::Stats;
 
     #[test]
     fn count_predicates() {
@@ -371,4 +407,28 @@ mod tests {
         assert!(!Count::new(1, 0, 0, 0).is_empty());
         assert!(!Count::new(1, 0, 0, 0).is_some()); // not_ect alone
     }
+
+    /// Changes in state are logged at the time that they happen.
+    #[test]
+    fn qlog_time() {
+        let (qlog, contents) = new_neqo_qlog();
+        let mut info = Info::default();
+        info.set_qlog(qlog);
+        info.start(
+            &mut Stats::default(),
+            Instant::now() + Duration::from_secs(1),
+        );
+
+        let contents = contents.to_string();
+        let event = contents
+            .lines()
+            .find(|l| l.contains("recovery:ecn_state_updated"))
+            .unwrap();
+        let time = event
+            .split(r#""time":"#)
+            .nth(1)
+            .and_then(|t| t.split(',').next())
+            .unwrap();
+        assert!(time.parse::<f64>().unwrap() >= 1000.0);
+    }
 }
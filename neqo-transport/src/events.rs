@@ -10,10 +10,12 @@ use std::{cell::RefCell, collections::VecDeque, rc::Rc};
 
 use neqo_common::event::Provider as EventProvider;
 use neqo_crypto::ResumptionToken;
+use rustc_hash::FxHashMap as HashMap;
 
 use crate::{
     AppError, Stats,
     connection::State,
+    multipath::PathId,
     quic_datagrams::DatagramTracking,
     stream_id::{StreamId, StreamType},
 };
@@ -78,14 +80,68 @@ pub enum ConnectionEvent {
         outcome: OutgoingDatagramOutcome,
     },
     IncomingDatagramDropped,
+    /// A path that was added using multipath can now be used for sending.
+    PathOpened {
+        path_id: PathId,
+    },
+    /// A multipath path was closed, either by this endpoint or the peer.
+    PathClosed {
+        path_id: PathId,
+    },
+}
+
+/// What the peer signaled on a stream that has handles watching it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PeerStreamErrors {
+    /// The error code from `RESET_STREAM` or `RESET_STREAM_AT`.
+    pub reset: Option<AppError>,
+    /// The error code from `STOP_SENDING`.
+    pub stop_sending: Option<AppError>,
+}
+
+#[derive(Debug, Default)]
+struct Watched {
+    handles: usize,
+    errors: PeerStreamErrors,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct ConnectionEvents {
     events: Rc<RefCell<VecDeque<ConnectionEvent>>>,
+    /// Streams that have handles.  The errors that the peer signals on these
+    /// are kept here, because the streams are removed once they are done and
+    /// the handles need to report why.
+    watched: Rc<RefCell<HashMap<StreamId, Watched>>>,
 }
 
 impl ConnectionEvents {
+    pub fn watch_stream(&self, stream_id: StreamId) {
+        self.watched
+            .borrow_mut()
+            .entry(stream_id)
+            .or_default()
+            .handles += 1;
+    }
+
+    pub fn unwatch_stream(&self, stream_id: StreamId) {
+        let mut watched = self.watched.borrow_mut();
+        if let Some(w) = watched.get_mut(&stream_id) {
+            w.handles -= 1;
+            if w.handles == 0 {
+                watched.remove(&stream_id);
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn peer_stream_errors(&self, stream_id: StreamId) -> PeerStreamErrors {
+        self.watched
+            .borrow()
+            .get(&stream_id)
+            .map(|w| w.errors)
+            .unwrap_or_default()
+    }
+
     pub fn authentication_needed(&self) {
         self.insert(ConnectionEvent::AuthenticationNeeded);
     }
@@ -103,6 +159,9 @@ impl ConnectionEvents {
     }
 
     pub fn recv_stream_reset(&self, stream_id: StreamId, app_error: AppError) {
+        if let Some(w) = self.watched.borrow_mut().get_mut(&stream_id) {
+            w.errors.reset = Some(app_error);
+        }
         // If reset, no longer readable.
         self.remove(|evt| matches!(evt, ConnectionEvent::RecvStreamReadable { stream_id: x } if *x == stream_id.as_u64()));
 
@@ -117,6 +176,9 @@ impl ConnectionEvents {
     }
 
     pub fn send_stream_stop_sending(&self, stream_id: StreamId, app_error: AppError) {
+        if let Some(w) = self.watched.borrow_mut().get_mut(&stream_id) {
+            w.errors.stop_sending = Some(app_error);
+        }
         // If stopped, no longer writable.
         self.remove(|evt| matches!(evt, ConnectionEvent::SendStreamWritable { stream_id: x } if *x == stream_id));
 
@@ -207,6 +269,17 @@ impl ConnectionEvents {
         }
     }
 
+    pub fn path_opened(&self, path_id: PathId) {
+        self.insert(ConnectionEvent::PathOpened { path_id });
+    }
+
+    pub fn path_closed(&self, path_id: PathId) {
+        self.remove(
+            |evt| matches!(evt, ConnectionEvent::PathOpened { path_id: x } if *x == path_id),
+        );
+        self.insert(ConnectionEvent::PathClosed { path_id });
+    }
+
     fn insert(&self, event: ConnectionEvent) {
         let mut q = self.events.borrow_mut();
 
@@ -12,7 +12,9 @@ use neqo_common::{Buffer, Decoder, Encoder, MAX_VARINT, qtrace};
 use strum::FromRepr;
 
 use crate::{
-    AppError, ConnectionId, Error, Res, TransportError, ecn, packet,
+    AppError, ConnectionId, Error, Res, TransportError, ecn,
+    multipath::{PathId, PathStatus},
+    packet,
     stateless_reset::Token as Srt,
     stream_id::{StreamId, StreamType},
 };
@@ -56,6 +58,19 @@ pub enum FrameType {
     // draft-ietf-quic-datagram
     Datagram = 0x30,
     DatagramWithLen = 0x31,
+    // draft-ietf-quic-reliable-stream-reset
+    ResetStreamAt = 0x24,
+    // draft-ietf-quic-multipath
+    PathAck = 0x1522_8c00,
+    PathAckEcn = 0x1522_8c01,
+    PathAbandon = 0x1522_8c05,
+    PathStatusBackup = 0x1522_8c07,
+    PathStatusAvailable = 0x1522_8c08,
+    PathNewConnectionId = 0x1522_8c09,
+    PathRetireConnectionId = 0x1522_8c0a,
+    MaxPathId = 0x1522_8c0c,
+    PathsBlocked = 0x1522_8c0d,
+    PathCidsBlocked = 0x1522_8c0e,
 }
 
 impl From<FrameType> for u64 {
@@ -65,6 +80,10 @@ impl From<FrameType> for u64 {
 }
 
 impl From<FrameType> for u8 {
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "Only used for frame types that fit in a byte."
+    )]
     fn from(val: FrameType) -> Self {
         val as Self
     }
@@ -164,6 +183,13 @@ pub enum Frame<'a> {
         application_error_code: AppError,
         final_size: u64,
     },
+    /// A reset that still guarantees delivery of data up to `reliable_size`.
+    ResetStreamAt {
+        stream_id: StreamId,
+        application_error_code: AppError,
+        final_size: u64,
+        reliable_size: u64,
+    },
     StopSending {
         stream_id: StreamId,
         application_error_code: AppError,
@@ -243,6 +269,45 @@ pub enum Frame<'a> {
         data: &'a [u8],
         fill: bool,
     },
+    /// An acknowledgment for packets sent on the identified path.
+    PathAck {
+        path_id: PathId,
+        largest_acknowledged: u64,
+        ack_delay: u64,
+        first_ack_range: u64,
+        ack_ranges: Vec<AckRange>,
+        ecn_count: Option<ecn::Count>,
+    },
+    PathAbandon {
+        path_id: PathId,
+        error_code: TransportError,
+    },
+    PathStatus {
+        path_id: PathId,
+        seqno: u64,
+        status: PathStatus,
+    },
+    PathNewConnectionId {
+        path_id: PathId,
+        sequence_number: u64,
+        retire_prior: u64,
+        connection_id: &'a [u8],
+        stateless_reset_token: Srt,
+    },
+    PathRetireConnectionId {
+        path_id: PathId,
+        sequence_number: u64,
+    },
+    MaxPathId {
+        path_id: PathId,
+    },
+    PathsBlocked {
+        path_id: PathId,
+    },
+    PathCidsBlocked {
+        path_id: PathId,
+        next_sequence_number: u64,
+    },
 }
 
 impl<'a> Frame<'a> {
@@ -253,6 +318,7 @@ impl<'a> Frame<'a> {
             Self::Ping => FrameType::Ping,
             Self::Ack { .. } => FrameType::Ack,
             Self::ResetStream { .. } => FrameType::ResetStream,
+            Self::ResetStreamAt { .. } => FrameType::ResetStreamAt,
             Self::StopSending { .. } => FrameType::StopSending,
             Self::Crypto { .. } => FrameType::Crypto,
             Self::NewToken { .. } => FrameType::NewToken,
@@ -285,6 +351,20 @@ impl<'a> Frame<'a> {
                 false => FrameType::Datagram,
                 true => FrameType::DatagramWithLen,
             },
+            Self::PathAck { ecn_count, .. } => match ecn_count {
+                None => FrameType::PathAck,
+                Some(_) => FrameType::PathAckEcn,
+            },
+            Self::PathAbandon { .. } => FrameType::PathAbandon,
+            Self::PathStatus { status, .. } => match status {
+                PathStatus::Available => FrameType::PathStatusAvailable,
+                PathStatus::Backup => FrameType::PathStatusBackup,
+            },
+            Self::PathNewConnectionId { .. } => FrameType::PathNewConnectionId,
+            Self::PathRetireConnectionId { .. } => FrameType::PathRetireConnectionId,
+            Self::MaxPathId { .. } => FrameType::MaxPathId,
+            Self::PathsBlocked { .. } => FrameType::PathsBlocked,
+            Self::PathCidsBlocked { .. } => FrameType::PathCidsBlocked,
         }
     }
 
@@ -293,6 +373,7 @@ impl<'a> Frame<'a> {
         matches!(
             self,
             Self::ResetStream { .. }
+                | Self::ResetStreamAt { .. }
                 | Self::StopSending { .. }
                 | Self::Stream { .. }
                 | Self::MaxData { .. }
@@ -324,7 +405,10 @@ impl<'a> Frame<'a> {
     pub const fn ack_eliciting(&self) -> bool {
         !matches!(
             self,
-            Self::Ack { .. } | Self::Padding { .. } | Self::ConnectionClose { .. }
+            Self::Ack { .. }
+                | Self::PathAck { .. }
+                | Self::Padding { .. }
+                | Self::ConnectionClose { .. }
         )
     }
 
@@ -341,6 +425,22 @@ impl<'a> Frame<'a> {
         )
     }
 
+    /// If the frame is part of the multipath extension.
+    #[must_use]
+    pub const fn is_multipath(&self) -> bool {
+        matches!(
+            self,
+            Self::PathAck { .. }
+                | Self::PathAbandon { .. }
+                | Self::PathStatus { .. }
+                | Self::PathNewConnectionId { .. }
+                | Self::PathRetireConnectionId { .. }
+                | Self::MaxPathId { .. }
+                | Self::PathsBlocked { .. }
+                | Self::PathCidsBlocked { .. }
+        )
+    }
+
     /// Converts `AckRanges` as encoded in a ACK frame (see -transport
     /// 19.3.1) into ranges of acked packets (end, start), inclusive of
     /// start and end values.
@@ -422,7 +522,16 @@ impl<'a> Frame<'a> {
                 error_code: CloseError::Transport(_),
                 ..
             } => pt != packet::Type::ZeroRtt,
-            Self::NewToken { .. } | Self::ConnectionClose { .. } => pt == packet::Type::Short,
+            Self::NewToken { .. }
+            | Self::ConnectionClose { .. }
+            | Self::PathAck { .. }
+            | Self::PathAbandon { .. }
+            | Self::PathStatus { .. }
+            | Self::PathNewConnectionId { .. }
+            | Self::PathRetireConnectionId { .. }
+            | Self::MaxPathId { .. }
+            | Self::PathsBlocked { .. }
+            | Self::PathCidsBlocked { .. } => pt == packet::Type::Short,
             _ => pt == packet::Type::ZeroRtt || pt == packet::Type::Short,
         }
     }
@@ -452,6 +561,9 @@ impl<'a> Frame<'a> {
         fn dv(dec: &mut Decoder) -> Res<u64> {
             d(dec.decode_varint())
         }
+        fn dp(dec: &mut Decoder) -> Res<PathId> {
+            PathId::try_from(dv(dec)?)
+        }
 
         fn decode_ack<'a>(dec: &mut Decoder<'a>, ecn: bool) -> Res<Frame<'a>> {
             let la = dv(dec)?;
@@ -519,6 +631,21 @@ impl<'a> Frame<'a> {
                     None => return Err(Error::NoMoreData),
                 },
             }),
+            FrameType::ResetStreamAt => {
+                let stream_id = StreamId::from(dv(dec)?);
+                let application_error_code = dv(dec)?;
+                let final_size = dv(dec)?;
+                let reliable_size = dv(dec)?;
+                if reliable_size > final_size {
+                    return Err(Error::FrameEncoding);
+                }
+                Ok(Self::ResetStreamAt {
+                    stream_id,
+                    application_error_code,
+                    final_size,
+                    reliable_size,
+                })
+            }
             FrameType::Ack => decode_ack(dec, false),
             FrameType::AckEcn => decode_ack(dec, true),
             FrameType::StopSending => Ok(Self::StopSending {
@@ -679,6 +806,67 @@ impl<'a> Frame<'a> {
                 };
                 Ok(Self::Datagram { data, fill })
             }
+            FrameType::PathAck | FrameType::PathAckEcn => {
+                let path_id = dp(dec)?;
+                let Self::Ack {
+                    largest_acknowledged,
+                    ack_delay,
+                    first_ack_range,
+                    ack_ranges,
+                    ecn_count,
+                } = decode_ack(dec, t == FrameType::PathAckEcn)?
+                else {
+                    unreachable!("decode_ack only produces ACK frames");
+                };
+                Ok(Self::PathAck {
+                    path_id,
+                    largest_acknowledged,
+                    ack_delay,
+                    first_ack_range,
+                    ack_ranges,
+                    ecn_count,
+                })
+            }
+            FrameType::PathAbandon => Ok(Self::PathAbandon {
+                path_id: dp(dec)?,
+                error_code: dv(dec)?,
+            }),
+            FrameType::PathStatusBackup | FrameType::PathStatusAvailable => Ok(Self::PathStatus {
+                path_id: dp(dec)?,
+                seqno: dv(dec)?,
+                status: if t == FrameType::PathStatusBackup {
+                    PathStatus::Backup
+                } else {
+                    PathStatus::Available
+                },
+            }),
+            FrameType::PathNewConnectionId => {
+                let path_id = dp(dec)?;
+                let sequence_number = dv(dec)?;
+                let retire_prior = dv(dec)?;
+                let connection_id = d(dec.decode_vec(1))?;
+                if connection_id.is_empty() || connection_id.len() > ConnectionId::MAX_LEN {
+                    return Err(Error::FrameEncoding);
+                }
+                let stateless_reset_token = Srt::try_from(dec)?;
+                Ok(Self::PathNewConnectionId {
+                    path_id,
+                    sequence_number,
+                    retire_prior,
+                    connection_id,
+                    stateless_reset_token,
+                })
+            }
+            FrameType::PathRetireConnectionId => Ok(Self::PathRetireConnectionId {
+                path_id: dp(dec)?,
+                sequence_number: dv(dec)?,
+            }),
+            FrameType::MaxPathId => Ok(Self::MaxPathId { path_id: dp(dec)? }),
+            FrameType::PathsBlocked => Ok(Self::PathsBlocked { path_id: dp(dec)? }),
+            FrameType::PathCidsBlocked => Ok(Self::PathCidsBlocked {
+                path_id: dp(dec)?,
+                next_sequence_number: dv(dec)?,
+            }),
         }
     }
 }
@@ -729,6 +917,7 @@ mod tests {
         CloseError, ConnectionId, Error, StreamId, StreamType, Token as Srt,
         ecn::Count,
         frame::{AckRange, Frame, FrameType},
+        multipath::{PathId, PathStatus},
     };
 
     fn just_dec(f: &Frame, s: &str) {
@@ -795,6 +984,28 @@ mod tests {
         just_dec(&f, "04523440777456");
     }
 
+    #[test]
+    fn reset_stream_at() {
+        let f = Frame::ResetStreamAt {
+            stream_id: StreamId::from(0x1234),
+            application_error_code: 0x77,
+            final_size: 0x3456,
+            reliable_size: 0x10,
+        };
+
+        just_dec(&f, "2452344077745610");
+    }
+
+    #[test]
+    fn reset_stream_at_reliable_too_large() {
+        // The reliable size (0x10) is larger than the final size (0x0).
+        let enc = Encoder::from_hex("24523440770010");
+        assert_eq!(
+            Frame::decode(&mut enc.as_decoder()).unwrap_err(),
+            Error::FrameEncoding
+        );
+    }
+
     #[test]
     fn stop_sending() {
         let f = Frame::StopSending {
@@ -1085,6 +1296,117 @@ mod tests {
         just_dec(&f, "3103010203");
     }
 
+    #[test]
+    fn path_ack() {
+        let f = Frame::PathAck {
+            path_id: PathId::new(1),
+            largest_acknowledged: 0x1234,
+            ack_delay: 0x1235,
+            first_ack_range: 0x1236,
+            ack_ranges: vec![AckRange { gap: 1, range: 2 }],
+            ecn_count: None,
+        };
+        just_dec(&f, "95228c0001523452350152360102");
+
+        let f = Frame::PathAck {
+            path_id: PathId::new(2),
+            largest_acknowledged: 0x1234,
+            ack_delay: 0x1235,
+            first_ack_range: 0x1236,
+            ack_ranges: Vec::new(),
+            ecn_count: Some(Count::new(0, 1, 2, 3)),
+        };
+        just_dec(&f, "95228c010252345235005236010203");
+    }
+
+    #[test]
+    fn path_abandon() {
+        let f = Frame::PathAbandon {
+            path_id: PathId::new(3),
+            error_code: 0x1234,
+        };
+        just_dec(&f, "95228c05035234");
+    }
+
+    #[test]
+    fn path_status() {
+        let f = Frame::PathStatus {
+            path_id: PathId::new(1),
+            seqno: 2,
+            status: PathStatus::Backup,
+        };
+        just_dec(&f, "95228c070102");
+        let f = Frame::PathStatus {
+            path_id: PathId::new(1),
+            seqno: 3,
+            status: PathStatus::Available,
+        };
+        just_dec(&f, "95228c080103");
+    }
+
+    #[test]
+    fn path_new_connection_id() {
+        let f = Frame::PathNewConnectionId {
+            path_id: PathId::new(1),
+            sequence_number: 0x1234,
+            retire_prior: 0,
+            connection_id: &[0x01, 0x02],
+            stateless_reset_token: Srt::new([9; Srt::LEN]),
+        };
+        just_dec(
+            &f,
+            "95228c09015234000201020909090909090909090909090909090909",
+        );
+
+        // A zero-length connection ID is not allowed.
+        let enc = Encoder::from_hex("95228c0901523400000909090909090909090909090909090909");
+        assert_eq!(
+            Frame::decode(&mut enc.as_decoder()).unwrap_err(),
+            Error::FrameEncoding
+        );
+    }
+
+    #[test]
+    fn path_retire_connection_id() {
+        let f = Frame::PathRetireConnectionId {
+            path_id: PathId::new(1),
+            sequence_number: 0x1234,
+        };
+        just_dec(&f, "95228c0a015234");
+    }
+
+    #[test]
+    fn path_limits() {
+        just_dec(
+            &Frame::MaxPathId {
+                path_id: PathId::new(7),
+            },
+            "95228c0c07",
+        );
+        just_dec(
+            &Frame::PathsBlocked {
+                path_id: PathId::new(7),
+            },
+            "95228c0d07",
+        );
+        just_dec(
+            &Frame::PathCidsBlocked {
+                path_id: PathId::new(7),
+                next_sequence_number: 2,
+            },
+            "95228c0e0702",
+        );
+    }
+
+    #[test]
+    fn path_id_too_large() {
+        let enc = Encoder::from_hex("95228c0cc000000100000000");
+        assert_eq!(
+            Frame::decode(&mut enc.as_decoder()).unwrap_err(),
+            Error::FrameEncoding
+        );
+    }
+
     #[test]
     fn frame_decode_enforces_bound_on_ack_range() {
         let mut e = Encoder::default();
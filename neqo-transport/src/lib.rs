@@ -15,6 +15,7 @@ mod ackrate;
 pub mod addr_valid;
 #[cfg(not(fuzzing))]
 mod addr_valid;
+mod admission;
 mod cc;
 mod cid;
 mod connection;
@@ -26,6 +27,7 @@ mod fc;
 pub mod frame;
 #[cfg(not(fuzzing))]
 mod frame;
+mod multipath;
 mod pace;
 #[cfg(any(fuzzing, feature = "bench"))]
 pub mod packet;
@@ -35,6 +37,7 @@ mod path;
 mod pmtud;
 mod qlog;
 mod quic_datagrams;
+pub mod recorder;
 #[cfg(feature = "bench")]
 pub mod recovery;
 #[cfg(not(feature = "bench"))]
@@ -48,6 +51,7 @@ pub mod server;
 mod sni;
 mod stateless_reset;
 mod stats;
+mod stream_handle;
 pub mod stream_id;
 pub mod streams;
 pub mod tparams;
@@ -55,7 +59,10 @@ mod tracking;
 pub mod version;
 
 pub use self::{
-    cc::{CongestionControlAlgorithm, CongestionEvent},
+    cc::{
+        CongestionControlAlgorithm, CongestionController, CongestionControllerFactory,
+        CongestionEvent, PacketInfo, RttSample,
+    },
     cid::{
         ConnectionId, ConnectionIdDecoder, ConnectionIdGenerator, ConnectionIdRef,
         EmptyConnectionIdGenerator, RandomConnectionIdGenerator,
@@ -64,11 +71,12 @@ pub use self::{
         Connection, Output, OutputBatch, State, ZeroRttState,
         params::{
             ConnectionParameters, INITIAL_LOCAL_MAX_DATA, INITIAL_LOCAL_MAX_STREAM_DATA,
-            MAX_LOCAL_MAX_STREAM_DATA,
+            MAX_LOCAL_MAX_STREAM_DATA, SpinBitConfig,
         },
     },
-    events::{ConnectionEvent, ConnectionEvents},
+    events::{ConnectionEvent, ConnectionEvents, PeerStreamErrors},
     frame::CloseError,
+    multipath::{LowestRtt, PathId, PathInfo, PathScheduler, PathStatus},
     packet::MIN_INITIAL_PACKET_SIZE,
     pmtud::Pmtud,
     quic_datagrams::DatagramTracking,
@@ -76,7 +84,9 @@ pub use self::{
     sni::find_sni,
     stateless_reset::Token,
     stats::Stats,
+    stream_handle::{RecvStreamHandle, SendStreamHandle, StreamError},
     stream_id::{StreamId, StreamType},
+    tracking::PacketNumberSpace,
     version::Version,
 };
 
@@ -0,0 +1,649 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// The multipath extension to QUIC; see draft-ietf-quic-multipath.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Debug, Display, Formatter},
+    time::{Duration, Instant},
+};
+
+use neqo_common::{Buffer, Encoder, qdebug, qinfo};
+
+use crate::{
+    Error, Res, TransportError,
+    cid::{ConnectionIdManager, ConnectionIdStore, RemoteConnectionIdEntry},
+    ecn,
+    frame::{FrameEncoder as _, FrameType},
+    packet,
+    path::PathRef,
+    recovery::{self, Loss, SendProfile, sent},
+    stateless_reset::Token as Srt,
+    stats::{FrameStats, StatsCell},
+    tracking::{AckToken, PacketNumberSpace, RecvdPackets},
+};
+
+/// The error code used in `PATH_ABANDON` when the application closes a path.
+pub const APPLICATION_ABANDON_PATH: TransportError = 0x0041_5050_4142_414e;
+/// The error code used in `PATH_ABANDON` when a path stops working.
+pub const PATH_UNSTABLE_OR_POOR: TransportError = 0x0055_4e53_5441_4c45;
+
+/// The identifier of a path when using multipath.
+/// The path that is used for the handshake always has an identifier of 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct PathId(u32);
+
+impl PathId {
+    /// The path that the connection is established on.
+    pub const INITIAL: Self = Self(0);
+
+    #[must_use]
+    pub const fn new(v: u32) -> Self {
+        Self(v)
+    }
+
+    #[must_use]
+    pub const fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    #[must_use]
+    pub const fn as_u64(self) -> u64 {
+        self.0 as u64
+    }
+
+    #[must_use]
+    pub const fn is_initial(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The next path identifier.  This saturates at the largest value.
+    #[must_use]
+    pub const fn next(self) -> Self {
+        Self(self.0.saturating_add(1))
+    }
+}
+
+impl From<PathId> for u64 {
+    fn from(id: PathId) -> Self {
+        id.as_u64()
+    }
+}
+
+impl TryFrom<u64> for PathId {
+    type Error = Error;
+
+    fn try_from(v: u64) -> Res<Self> {
+        u32::try_from(v).map(Self).map_err(|_| Error::FrameEncoding)
+    }
+}
+
+impl Display for PathId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Whether a path should be used for sending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathStatus {
+    /// The path can be used for sending.
+    #[default]
+    Available,
+    /// The path should only be used if no other path is available.
+    Backup,
+}
+
+/// What a [`PathScheduler`] is told about a path that it can choose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathInfo {
+    path_id: PathId,
+    rtt: Duration,
+    cwnd: usize,
+    cwnd_avail: usize,
+}
+
+impl PathInfo {
+    pub(crate) fn new(path_id: PathId, path: &PathRef) -> Self {
+        let path = path.borrow();
+        Self {
+            path_id,
+            rtt: path.rtt().estimate(),
+            cwnd: path.sender().cwnd(),
+            cwnd_avail: path.sender().cwnd_avail(),
+        }
+    }
+
+    #[must_use]
+    pub const fn path_id(&self) -> PathId {
+        self.path_id
+    }
+
+    /// The smoothed RTT estimate for the path.
+    #[must_use]
+    pub const fn rtt(&self) -> Duration {
+        self.rtt
+    }
+
+    /// The congestion window of the path, in bytes.
+    #[must_use]
+    pub const fn cwnd(&self) -> usize {
+        self.cwnd
+    }
+
+    /// The number of bytes that the congestion window allows to be sent now.
+    #[must_use]
+    pub const fn cwnd_avail(&self) -> usize {
+        self.cwnd_avail
+    }
+}
+
+/// Chooses the path for each packet when multipath is used.
+/// See [`crate::ConnectionParameters::path_scheduler`].
+///
+/// The scheduler is only asked when there is a choice to make.  Probes and
+/// retransmissions after a PTO are sent on the path that needs them, and
+/// paths with a status of backup are never offered.
+pub trait PathScheduler: Debug {
+    /// Choose a path from `paths`, returning its index.  `paths` contains
+    /// the paths that the congestion controller and pacer allow to send now,
+    /// with the initial path first if it is included.  It is never empty.
+    /// An index that is out of range selects the first path.
+    fn select(&self, paths: &[PathInfo]) -> usize;
+}
+
+/// The default scheduler, which sends on the path with the lowest RTT.
+#[derive(Debug, Default)]
+pub struct LowestRtt;
+
+impl PathScheduler for LowestRtt {
+    fn select(&self, paths: &[PathInfo]) -> usize {
+        paths
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, p)| p.rtt())
+            .map_or(0, |(i, _)| i)
+    }
+}
+
+/// The sending and receiving state for a path other than the initial path.
+/// Each of these paths has its own packet number space, with its own loss recovery.
+/// The congestion controller and RTT estimate are part of the `Path`.
+#[derive(Debug)]
+pub struct PathSpace {
+    path: PathRef,
+    recvd: RecvdPackets,
+    loss: Loss,
+    next_pn: packet::Number,
+    /// The status that we want the peer to use, with the sequence number
+    /// of the `PATH_STATUS` frame that carries it.
+    local_status: PathStatus,
+    local_status_seqno: u64,
+    /// Whether `PATH_STATUS` needs to be sent.
+    status_pending: bool,
+    /// The status that the peer asked us to use.
+    remote_status: PathStatus,
+    remote_status_seqno: Option<u64>,
+    /// If the path was abandoned, when that happened and the error code.
+    abandoned: Option<(Instant, TransportError)>,
+    /// Whether `PATH_ABANDON` needs to be sent.
+    abandon_pending: bool,
+    /// Whether the application has been told about this path.
+    reported: bool,
+}
+
+impl PathSpace {
+    fn new(path: PathRef, path_id: PathId, stats: StatsCell, fast_pto: u8, now: Instant) -> Self {
+        let mut loss = Loss::new(stats, fast_pto);
+        // Only the application data space is used on these paths.
+        loss.discard(&path, PacketNumberSpace::Initial, now);
+        loss.discard(&path, PacketNumberSpace::Handshake, now);
+        Self {
+            path,
+            recvd: RecvdPackets::new_for_path(path_id),
+            loss,
+            next_pn: 0,
+            local_status: PathStatus::Available,
+            local_status_seqno: 0,
+            status_pending: false,
+            remote_status: PathStatus::Available,
+            remote_status_seqno: None,
+            abandoned: None,
+            abandon_pending: false,
+            reported: false,
+        }
+    }
+
+    pub const fn path(&self) -> &PathRef {
+        &self.path
+    }
+
+    pub const fn recvd(&self) -> &RecvdPackets {
+        &self.recvd
+    }
+
+    pub const fn recvd_mut(&mut self) -> &mut RecvdPackets {
+        &mut self.recvd
+    }
+
+    /// The packet number that is next expected from the peer.
+    pub fn expected_pn(&self) -> packet::Number {
+        self.recvd.largest_pn().map_or(0, |pn| pn + 1)
+    }
+
+    pub const fn next_pn(&self) -> packet::Number {
+        self.next_pn
+    }
+
+    pub fn largest_acknowledged(&self) -> Option<packet::Number> {
+        self.loss
+            .largest_acknowledged_pn(PacketNumberSpace::ApplicationData)
+    }
+
+    pub const fn is_abandoned(&self) -> bool {
+        self.abandoned.is_some()
+    }
+
+    /// Whether this path can be used to send application data.
+    pub fn is_usable(&self) -> bool {
+        self.abandoned.is_none() && self.path.borrow().is_valid()
+    }
+
+    /// The status of the path, taking into account what both endpoints asked for.
+    pub fn status(&self) -> PathStatus {
+        if self.local_status == PathStatus::Backup || self.remote_status == PathStatus::Backup {
+            PathStatus::Backup
+        } else {
+            PathStatus::Available
+        }
+    }
+
+    pub fn send_profile(&mut self, now: Instant) -> SendProfile {
+        self.loss.send_profile(&self.path.borrow(), now)
+    }
+
+    pub fn pto_pending(&self) -> bool {
+        self.loss.pto_pending()
+    }
+
+    pub fn should_probe(&self, pto: Duration, now: Instant) -> bool {
+        self.loss.should_probe(pto, now)
+    }
+
+    pub fn on_packet_sent(&mut self, sent: sent::Packet, now: Instant) {
+        debug_assert_eq!(sent.pn(), self.next_pn);
+        self.next_pn += 1;
+        self.loss.on_packet_sent(&self.path, sent, now);
+    }
+
+    /// Returns (acked packets, lost packets).
+    pub fn on_ack_received<R>(
+        &mut self,
+        acked_ranges: R,
+        ack_ecn: Option<&ecn::Count>,
+        ack_delay: Duration,
+        now: Instant,
+    ) -> (Vec<sent::Packet>, Vec<sent::Packet>)
+    where
+        R: IntoIterator<Item = std::ops::RangeInclusive<packet::Number>>,
+        R::IntoIter: ExactSizeIterator,
+    {
+        self.loss.on_ack_received(
+            &self.path,
+            PacketNumberSpace::ApplicationData,
+            acked_ranges,
+            ack_ecn,
+            ack_delay,
+            now,
+        )
+    }
+
+    pub fn timeout(&mut self, now: Instant) -> Vec<sent::Packet> {
+        self.loss.timeout(&self.path, now)
+    }
+
+    pub fn next_timeout(&self) -> Option<Instant> {
+        self.loss.next_timeout(&self.path.borrow())
+    }
+
+    /// Stop sending on this path.  This returns all outstanding packets,
+    /// which need to be treated as lost.
+    fn abandon(&mut self, error: TransportError, now: Instant) -> Vec<sent::Packet> {
+        self.abandoned = Some((now, error));
+        self.abandon_pending = true;
+        self.loss
+            .retry(&self.path, now)
+            .into_iter()
+            .filter(|p| !p.lost())
+            .collect()
+    }
+}
+
+/// The state of the multipath extension for a connection.
+#[derive(Debug)]
+pub struct Multipath {
+    /// The largest path identifier that we allow.
+    local_max: PathId,
+    /// The largest path identifier that the peer allows.
+    remote_max: PathId,
+    /// The next path identifier to use when opening a path.
+    next_path_id: PathId,
+    spaces: BTreeMap<PathId, PathSpace>,
+    /// Connection IDs that the peer provided for each path.
+    remote_cids: BTreeMap<PathId, ConnectionIdStore<Srt>>,
+    /// Paths that have been closed and cannot be used again.
+    closed: BTreeSet<PathId>,
+    /// Connection IDs that need to be retired with `PATH_RETIRE_CONNECTION_ID`.
+    to_retire: Vec<(PathId, u64)>,
+    stats: StatsCell,
+    fast_pto: u8,
+}
+
+impl Multipath {
+    pub const fn new(
+        local_max: PathId,
+        remote_max: PathId,
+        stats: StatsCell,
+        fast_pto: u8,
+    ) -> Self {
+        Self {
+            local_max,
+            remote_max,
+            next_path_id: PathId::INITIAL.next(),
+            spaces: BTreeMap::new(),
+            remote_cids: BTreeMap::new(),
+            closed: BTreeSet::new(),
+            to_retire: Vec::new(),
+            stats,
+            fast_pto,
+        }
+    }
+
+    /// The largest path identifier that can be used.
+    pub fn max_path_id(&self) -> PathId {
+        self.local_max.min(self.remote_max)
+    }
+
+    /// Handle a `MAX_PATH_ID` frame.
+    pub fn set_remote_max(&mut self, max: PathId) {
+        self.remote_max = self.remote_max.max(max);
+    }
+
+    /// Find the identifier for the next path to open, if one is available.
+    pub fn next_path_id(&self) -> Option<PathId> {
+        (self.next_path_id <= self.max_path_id()).then_some(self.next_path_id)
+    }
+
+    pub fn space(&self, path_id: PathId) -> Option<&PathSpace> {
+        self.spaces.get(&path_id)
+    }
+
+    pub fn space_mut(&mut self, path_id: PathId) -> Option<&mut PathSpace> {
+        self.spaces.get_mut(&path_id)
+    }
+
+    pub fn spaces(&self) -> impl Iterator<Item = (PathId, &PathSpace)> {
+        self.spaces.iter().map(|(id, s)| (*id, s))
+    }
+
+    pub fn spaces_mut(&mut self) -> impl Iterator<Item = (PathId, &mut PathSpace)> {
+        self.spaces.iter_mut().map(|(id, s)| (*id, s))
+    }
+
+    /// Find the path identifier that is used for the given path.
+    pub fn path_id(&self, path: &PathRef) -> Option<PathId> {
+        path.borrow()
+            .path_id()
+            .filter(|id| self.spaces.contains_key(id))
+    }
+
+    pub fn is_closed(&self, path_id: PathId) -> bool {
+        self.closed.contains(&path_id)
+    }
+
+    /// Take a connection ID that the peer provided for the identified path.
+    pub fn take_remote_cid(&mut self, path_id: PathId) -> Option<RemoteConnectionIdEntry> {
+        self.remote_cids.get_mut(&path_id)?.next()
+    }
+
+    /// Start tracking a new path.
+    pub fn add_space(&mut self, path_id: PathId, path: PathRef, now: Instant) {
+        debug_assert!(!path_id.is_initial());
+        debug_assert!(!self.closed.contains(&path_id));
+        qdebug!("[{}] Adding multipath space {path_id}", path.borrow());
+        self.next_path_id = self.next_path_id.max(path_id.next());
+        self.spaces.insert(
+            path_id,
+            PathSpace::new(path, path_id, self.stats.clone(), self.fast_pto, now),
+        );
+    }
+
+    /// Handle a `PATH_NEW_CONNECTION_ID` frame.
+    pub fn add_remote_cid(
+        &mut self,
+        path_id: PathId,
+        entry: RemoteConnectionIdEntry,
+        retire_prior: u64,
+    ) -> Res<()> {
+        if path_id.is_initial() || path_id > self.local_max {
+            return Err(Error::ProtocolViolation);
+        }
+        if self.closed.contains(&path_id) {
+            // Retire these immediately.
+            self.to_retire.push((path_id, entry.sequence_number()));
+            return Ok(());
+        }
+        let store = self.remote_cids.entry(path_id).or_default();
+        store.add_remote(entry)?;
+        for seqno in store.retire_prior_to(retire_prior) {
+            self.to_retire.push((path_id, seqno));
+        }
+        if store.len() > ConnectionIdManager::ACTIVE_LIMIT {
+            qinfo!("Received too many connection IDs for path {path_id}");
+            return Err(Error::ConnectionIdLimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// Abandon a path.  This returns any packets that were outstanding on the path.
+    pub fn abandon(
+        &mut self,
+        path_id: PathId,
+        error: TransportError,
+        now: Instant,
+    ) -> Vec<sent::Packet> {
+        let Some(space) = self.spaces.get_mut(&path_id) else {
+            return Vec::new();
+        };
+        if space.is_abandoned() {
+            return Vec::new();
+        }
+        qinfo!(
+            "[{}] Abandoning path {path_id}: {error:x}",
+            space.path.borrow()
+        );
+        space.abandon(error, now)
+    }
+
+    /// Handle a `PATH_ABANDON` frame.  This abandons the path, if it wasn't already.
+    pub fn peer_abandoned(&mut self, path_id: PathId, now: Instant) -> Res<Vec<sent::Packet>> {
+        if path_id.is_initial() || path_id > self.local_max {
+            return Err(Error::ProtocolViolation);
+        }
+        self.closed.insert(path_id);
+        Ok(self.abandon(path_id, APPLICATION_ABANDON_PATH, now))
+    }
+
+    /// Handle a `PATH_STATUS` frame.
+    pub fn set_remote_status(&mut self, path_id: PathId, seqno: u64, status: PathStatus) {
+        if let Some(space) = self.spaces.get_mut(&path_id)
+            && space.remote_status_seqno.is_none_or(|s| s < seqno)
+        {
+            space.remote_status_seqno = Some(seqno);
+            space.remote_status = status;
+        }
+    }
+
+    /// Set the local status for a path, which is sent to the peer.
+    pub fn set_local_status(&mut self, path_id: PathId, status: PathStatus) -> Res<()> {
+        let space = self
+            .spaces
+            .get_mut(&path_id)
+            .filter(|s| !s.is_abandoned())
+            .ok_or(Error::InvalidInput)?;
+        if space.local_status != status {
+            space.local_status = status;
+            space.local_status_seqno += 1;
+            space.status_pending = true;
+        }
+        Ok(())
+    }
+
+    /// Remove paths that were abandoned long enough ago.
+    /// This returns the removed paths, which need to be cleaned up.
+    pub fn remove_abandoned(&mut self, now: Instant, pto: Duration) -> Vec<(PathId, PathRef)> {
+        let expired = self
+            .spaces
+            .iter()
+            .filter_map(|(id, s)| {
+                s.abandoned
+                    .is_some_and(|(t, _)| t + pto * 3 <= now)
+                    .then_some(*id)
+            })
+            .collect::<Vec<_>>();
+        let mut removed = Vec::with_capacity(expired.len());
+        for id in expired {
+            self.closed.insert(id);
+            self.remote_cids.remove(&id);
+            if let Some(s) = self.spaces.remove(&id) {
+                removed.push((id, s.path));
+            }
+        }
+        removed
+    }
+
+    /// The time that abandoned paths need to be removed.
+    pub fn next_removal(&self, pto: Duration) -> Option<Instant> {
+        self.spaces
+            .values()
+            .filter_map(|s| s.abandoned.map(|(t, _)| t + pto * 3))
+            .min()
+    }
+
+    /// Determine the earliest time that a `PATH_ACK` might be needed.
+    pub fn ack_time(&self, now: Instant) -> Option<Instant> {
+        self.spaces
+            .values()
+            .filter_map(|s| s.recvd.ack_time().filter(|t| *t > now))
+            .min()
+    }
+
+    /// Paths that have been validated and that haven't been reported.
+    pub fn take_opened(&mut self) -> Vec<PathId> {
+        self.spaces
+            .iter_mut()
+            .filter(|(_, s)| !s.reported && s.is_usable())
+            .map(|(id, s)| {
+                s.reported = true;
+                *id
+            })
+            .collect()
+    }
+
+    /// Write `PATH_ACK` frames for any path that needs them.
+    pub fn write_acks<B: Buffer>(
+        &mut self,
+        now: Instant,
+        rtt: Duration,
+        builder: &mut packet::Builder<B>,
+        tokens: &mut recovery::Tokens,
+        stats: &mut FrameStats,
+    ) {
+        for space in self.spaces.values_mut() {
+            space.recvd.write_frame(now, rtt, builder, tokens, stats);
+        }
+    }
+
+    /// Write `PATH_ABANDON`, `PATH_STATUS`, and `PATH_RETIRE_CONNECTION_ID` frames.
+    pub fn write_frames<B: Buffer>(
+        &mut self,
+        builder: &mut packet::Builder<B>,
+        tokens: &mut recovery::Tokens,
+        stats: &mut FrameStats,
+    ) {
+        for (path_id, space) in &mut self.spaces {
+            let id = path_id.as_u64();
+            if space.abandon_pending
+                && let Some((_, error)) = space.abandoned
+                && builder.write_varint_frame(&[FrameType::PathAbandon.into(), id, error])
+            {
+                space.abandon_pending = false;
+                tokens.push(recovery::Token::PathAbandon(*path_id));
+                stats.path_abandon += 1;
+            }
+            if space.status_pending && space.abandoned.is_none() {
+                let frame_type = match space.local_status {
+                    PathStatus::Available => FrameType::PathStatusAvailable,
+                    PathStatus::Backup => FrameType::PathStatusBackup,
+                };
+                let seqno = space.local_status_seqno;
+                if builder.write_varint_frame(&[frame_type.into(), id, seqno]) {
+                    space.status_pending = false;
+                    tokens.push(recovery::Token::PathStatus(*path_id, seqno));
+                    stats.path_status += 1;
+                }
+            }
+        }
+
+        while let Some((path_id, seqno)) = self.to_retire.pop() {
+            let len = Encoder::varint_len(FrameType::PathRetireConnectionId.into())
+                + Encoder::varint_len(path_id.as_u64())
+                + Encoder::varint_len(seqno);
+            if builder.remaining() < len {
+                self.to_retire.push((path_id, seqno));
+                break;
+            }
+            builder.encode_frame(FrameType::PathRetireConnectionId, |b| {
+                b.encode_varint(path_id.as_u64());
+                b.encode_varint(seqno);
+            });
+            tokens.push(recovery::Token::PathRetireConnectionId(path_id, seqno));
+            stats.retire_connection_id += 1;
+        }
+    }
+
+    pub fn acked(&mut self, token: &AckToken) {
+        if let Some(space) = self.spaces.get_mut(&token.path_id()) {
+            space.recvd.acknowledged(token.ranges());
+        }
+    }
+
+    pub fn lost_abandon(&mut self, path_id: PathId) {
+        if let Some(space) = self.spaces.get_mut(&path_id)
+            && space.abandoned.is_some()
+        {
+            space.abandon_pending = true;
+        }
+    }
+
+    pub fn lost_status(&mut self, path_id: PathId, seqno: u64) {
+        if let Some(space) = self.spaces.get_mut(&path_id)
+            && space.local_status_seqno == seqno
+        {
+            space.status_pending = true;
+        }
+    }
+
+    pub fn lost_retire_cid(&mut self, path_id: PathId, seqno: u64) {
+        self.to_retire.push((path_id, seqno));
+    }
+
+    pub fn acked_retire_cid(&mut self, path_id: PathId, seqno: u64) {
+        self.to_retire
+            .retain(|&(id, s)| id != path_id || s != seqno);
+    }
+}
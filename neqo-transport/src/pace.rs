@@ -77,30 +77,45 @@ impl Pacer {
     /// doesn't update state.  This returns a time, which could be in the past
     /// (this object doesn't know what the current time is).
     pub fn next(&self, rtt: Duration, cwnd: usize) -> Instant {
+        self.next_at(cwnd * Self::SPEEDUP, rtt)
+    }
+
+    /// Determine when the next packet will be available when pacing at `rate`,
+    /// in bytes per second, rather than at a rate derived from the congestion window.
+    pub fn next_at_rate(&self, rate: usize) -> Instant {
+        self.next_at(rate, Duration::from_secs(1))
+    }
+
+    /// Determine when the next packet will be available when `bytes` can be sent every
+    /// `interval`.
+    fn next_at(&self, bytes: usize, interval: Duration) -> Instant {
         let packet = isize::try_from(self.p).expect("packet size fits into isize");
 
         if self.c >= packet {
-            qtrace!("[{self}] next {cwnd}/{rtt:?} no wait = {:?}", self.t);
+            qtrace!("[{self}] next {bytes}/{interval:?} no wait = {:?}", self.t);
             return self.t;
         }
 
         // This is the inverse of the function in `spend`:
-        // self.t + rtt * (self.p - self.c) / (Self::SPEEDUP * cwnd)
-        let r = rtt.as_nanos();
+        // self.t + interval * (self.p - self.c) / bytes
+        let r = interval.as_nanos();
         let deficit =
             u128::try_from(packet - self.c).expect("packet is larger than current credit");
         let d = r.saturating_mul(deficit);
-        let add = d / u128::try_from(cwnd * Self::SPEEDUP).expect("usize fits into u128");
-        let w = u64::try_from(add).map_or(rtt, Duration::from_nanos);
+        // A rate of zero waits for the whole interval.
+        let w = d
+            .checked_div(u128::try_from(bytes).expect("usize fits into u128"))
+            .and_then(|add| u64::try_from(add).ok())
+            .map_or(interval, Duration::from_nanos);
 
         // If the increment is below the timer granularity, send immediately.
         if w < GRANULARITY {
-            qtrace!("[{self}] next {cwnd}/{rtt:?} below granularity ({w:?})",);
+            qtrace!("[{self}] next {bytes}/{interval:?} below granularity ({w:?})");
             return self.t;
         }
 
         let nxt = self.t + w;
-        qtrace!("[{self}] next {cwnd}/{rtt:?} wait {w:?} = {nxt:?}");
+        qtrace!("[{self}] next {bytes}/{interval:?} wait {w:?} = {nxt:?}");
         nxt
     }
 
@@ -112,20 +127,31 @@ impl Pacer {
     /// trip time (`rtt`), the estimated congestion window (`cwnd`), and the
     /// number of bytes that were sent (`count`).
     pub fn spend(&mut self, now: Instant, rtt: Duration, cwnd: usize, count: usize) {
+        self.spend_at(now, cwnd * Self::SPEEDUP, rtt, count);
+    }
+
+    /// Spend credit when pacing at `rate`, in bytes per second, rather than at a rate
+    /// derived from the congestion window.
+    pub fn spend_at_rate(&mut self, now: Instant, rate: usize, count: usize) {
+        self.spend_at(now, rate, Duration::from_secs(1), count);
+    }
+
+    /// Spend credit when `bytes` can be sent every `interval`.
+    fn spend_at(&mut self, now: Instant, bytes: usize, interval: Duration, count: usize) {
         if !self.enabled {
             self.t = now;
             return;
         }
 
-        qtrace!("[{self}] spend {count} over {cwnd}, {rtt:?}");
+        qtrace!("[{self}] spend {count} over {bytes}, {interval:?}");
         // Increase the capacity by:
-        //    `(now - self.t) * Self::SPEEDUP * cwnd / rtt`
-        // That is, the elapsed fraction of the RTT times rate that data is added.
+        //    `(now - self.t) * bytes / interval`
+        // That is, the elapsed fraction of the interval times rate that data is added.
         let incr = now
             .saturating_duration_since(self.t)
             .as_nanos()
-            .saturating_mul(u128::try_from(cwnd * Self::SPEEDUP).expect("usize fits into u128"))
-            .checked_div(rtt.as_nanos())
+            .saturating_mul(u128::try_from(bytes).expect("usize fits into u128"))
+            .checked_div(interval.as_nanos())
             .and_then(|i| usize::try_from(i).ok())
             .unwrap_or(self.m);
 
@@ -184,6 +210,15 @@ mod tests {
         assert_eq!(p.next(RTT, CWND), n + (RTT / 20));
     }
 
+    #[test]
+    fn zero_rate() {
+        let n = now();
+        let mut p = Pacer::new(true, n, PACKET, PACKET);
+        p.spend(n, RTT, 0, PACKET);
+        assert_eq!(p.next(RTT, 0), n + RTT);
+        assert_eq!(p.next_at_rate(0), n + Duration::from_secs(1));
+    }
+
     #[test]
     fn pacing_disabled() {
         let n = now();
@@ -23,6 +23,7 @@ use crate::{
     cid::{ConnectionId, ConnectionIdDecoder, ConnectionIdRef},
     crypto::{CryptoDxState, CryptoStates, Epoch},
     frame::{FrameEncoder as _, FrameType},
+    multipath::PathId,
     version::{self, Version},
 };
 
@@ -33,7 +34,7 @@ pub const MIN_INITIAL_PACKET_SIZE: usize = 1200;
 pub const BIT_LONG: u8 = 0x80;
 const BIT_SHORT: u8 = 0x00;
 const BIT_FIXED_QUIC: u8 = 0x40;
-const BIT_SPIN: u8 = 0x20;
+pub const BIT_SPIN: u8 = 0x20;
 const BIT_KEY_PHASE: u8 = 0x04;
 
 const HP_MASK_LONG: u8 = 0x0f;
@@ -365,6 +366,17 @@ impl<B: Buffer> Builder<B> {
         self.encoder.as_mut()[first] ^= random::<1>()[0] & mask;
     }
 
+    /// Set the latency spin bit, replacing any value set by `scramble`.
+    /// This does nothing for long header packets.
+    pub fn spin(&mut self, spin: bool) {
+        if self.is_long() {
+            return;
+        }
+        let first = self.header.start;
+        let byte = &mut self.encoder.as_mut()[first];
+        *byte = (*byte & !BIT_SPIN) | if spin { BIT_SPIN } else { 0 };
+    }
+
     /// For an Initial packet, encode the token.
     /// If you fail to do this, then you will not get a valid packet.
     pub fn initial_token(&mut self, token: &[u8]) {
@@ -470,7 +482,21 @@ impl<B: Buffer> Builder<B> {
     /// # Errors
     ///
     /// This will return an error if the packet is too large.
-    pub fn build(mut self, crypto: &mut CryptoDxState) -> Res<Encoder<B>> {
+    pub fn build(self, crypto: &mut CryptoDxState) -> Res<Encoder<B>> {
+        self.build_for_path(crypto, PathId::INITIAL)
+    }
+
+    /// Build the packet for sending on the identified path and return the encoder.
+    /// The path identifier is only used when multipath is negotiated.
+    ///
+    /// # Errors
+    ///
+    /// This will return an error if the packet is too large.
+    pub fn build_for_path(
+        mut self,
+        crypto: &mut CryptoDxState,
+        path_id: PathId,
+    ) -> Res<Encoder<B>> {
         if self.len() > self.limit {
             qwarn!("Packet contents are more than the limit");
             debug_assert!(
@@ -499,7 +525,7 @@ impl<B: Buffer> Builder<B> {
         self.pad_to(data_end + crypto.expansion(), 0);
 
         // Calculate the mask.
-        crypto.encrypt(self.pn, self.header.clone(), self.encoder.as_mut())?;
+        crypto.encrypt_for_path(path_id, self.pn, self.header.clone(), self.encoder.as_mut())?;
         // `decode()` already checked that `decoder.remaining() >= SAMPLE_OFFSET + SAMPLE_SIZE`.
         let sample_start = self.header.end + SAMPLE_OFFSET - self.offsets.pn.len();
         let sample = self.encoder.as_ref()[sample_start..sample_start + SAMPLE_SIZE]
@@ -816,7 +842,12 @@ impl<'a> Public<'a> {
     }
 
     /// Decrypt the header of the packet.
-    fn decrypt_header(&mut self, crypto: &CryptoDxState) -> Res<(bool, Number, Range<usize>)> {
+    /// The expected packet number is taken from `crypto` unless one is provided.
+    fn decrypt_header(
+        &mut self,
+        crypto: &CryptoDxState,
+        expected_pn: Option<Number>,
+    ) -> Res<(bool, Number, Range<usize>)> {
         debug_assert_ne!(self.packet_type, Type::Retry);
         debug_assert_ne!(self.packet_type, Type::VersionNegotiation);
 
@@ -864,18 +895,40 @@ impl<'a> Public<'a> {
 
         let key_phase =
             self.packet_type == Type::Short && (first_byte & BIT_KEY_PHASE) == BIT_KEY_PHASE;
-        let pn = Self::decode_pn(crypto.next_pn(), pn_encoded, pn_len);
+        let expected_pn = expected_pn.unwrap_or_else(|| crypto.next_pn());
+        let pn = Self::decode_pn(expected_pn, pn_encoded, pn_len);
         Ok((key_phase, pn, hdrbytes))
     }
 
     /// # Errors
     ///
     /// This will return an error if the packet cannot be decrypted.
+    #[cfg(test)]
     pub fn decrypt(
+        self,
+        crypto: &mut CryptoStates,
+        release_at: Instant,
+    ) -> Result<Decrypted<'a>, DecryptionError<'a>> {
+        self.decrypt_for_path(crypto, None, release_at, release_at)
+    }
+
+    /// Decrypt a packet, which might have been sent on a path other than the initial path.
+    /// For those paths, `path` holds the path identifier and the next packet number
+    /// that is expected on that path.  A key update that this packet starts
+    /// is completed at `release_at`.
+    ///
+    /// # Errors
+    ///
+    /// This will return an error if the packet cannot be decrypted.
+    pub fn decrypt_for_path(
         mut self,
         crypto: &mut CryptoStates,
+        path: Option<(PathId, Number)>,
+        now: Instant,
         release_at: Instant,
     ) -> Result<Decrypted<'a>, DecryptionError<'a>> {
+        let (path_id, expected_pn) =
+            path.map_or((PathId::INITIAL, None), |(id, pn)| (id, Some(pn)));
         let epoch = match self.packet_type.try_into() {
             Ok(e) => e,
             Err(e) => return Err((self, e).into()),
@@ -896,7 +949,7 @@ impl<'a> Public<'a> {
         // This is OK in this case because we the only reason this can
         // fail is if the cryptographic module is bad or the packet is
         // too small (which is public information).
-        let (key_phase, pn, header) = match self.decrypt_header(rx) {
+        let (key_phase, pn, header) = match self.decrypt_header(rx, expected_pn) {
             Ok(v) => v,
             Err(e) => return Err((self, e).into()),
         };
@@ -905,7 +958,8 @@ impl<'a> Public<'a> {
         };
         let version = rx.version(); // Version fixup; see above.
         let header_end = header.end;
-        let payload_len = match rx.decrypt(pn, header, self.data) {
+        let spin = self.data[0] & BIT_SPIN == BIT_SPIN;
+        let payload_len = match rx.decrypt_for_path(path_id, pn, header, self.data) {
             Ok(v) => v,
             Err(e) => return Err((self, e).into()),
         };
@@ -920,7 +974,9 @@ impl<'a> Public<'a> {
         // If this is the first packet ever successfully decrypted
         // using `rx`, make sure to initiate a key update.
         if rx.needs_update() {
-            crypto.key_update_received(release_at).map_err(make_err)?;
+            crypto
+                .key_update_received(release_at, now)
+                .map_err(make_err)?;
         }
         crypto.check_pn_overlap().map_err(make_err)?;
         Ok(Decrypted {
@@ -929,6 +985,7 @@ impl<'a> Public<'a> {
             pn,
             dcid: self.dcid,
             scid: self.scid,
+            spin,
             data,
         })
     }
@@ -1015,6 +1072,7 @@ pub struct Decrypted<'a> {
     data: &'a [u8],
     dcid: ConnectionId,
     scid: Option<ConnectionId>,
+    spin: bool,
 }
 
 impl Decrypted<'_> {
@@ -1048,6 +1106,12 @@ impl Decrypted<'_> {
             .expect("should only be called for long header packets")
             .as_cid_ref()
     }
+    /// The value of the latency spin bit.  This is only meaningful for short
+    /// header packets.
+    #[must_use]
+    pub const fn spin(&self) -> bool {
+        self.spin
+    }
 }
 
 impl Deref for Decrypted<'_> {
@@ -1224,6 +1288,26 @@ mod tests {
         assert!(!firsts.iter().all(is_set(packet::BIT_SPIN)));
     }
 
+    #[test]
+    fn spin_short() {
+        fixture_init();
+        for spin in [false, true, false, true] {
+            let mut builder = Builder::short(
+                Encoder::default(),
+                true,
+                Some(ConnectionId::from(SERVER_CID)),
+                packet::LIMIT,
+            );
+            builder.scramble(true);
+            builder.spin(spin);
+            builder.pn(0, 1);
+            assert_eq!(
+                builder.as_ref()[0] & packet::BIT_SPIN == packet::BIT_SPIN,
+                spin
+            );
+        }
+    }
+
     #[test]
     fn decode_short() {
         fixture_init();
@@ -13,7 +13,9 @@ use std::{
     time::{Duration, Instant},
 };
 
-use neqo_common::{Buffer, Encoder, Tos, datagram, hex, qdebug, qinfo, qlog::Qlog, qtrace, qwarn};
+use neqo_common::{
+    Buffer, Encoder, Role, Tos, datagram, hex, qdebug, qinfo, qlog::Qlog, qtrace, qwarn,
+};
 use neqo_crypto::random;
 
 use crate::{
@@ -22,8 +24,10 @@ use crate::{
     cid::{ConnectionId, ConnectionIdRef, ConnectionIdStore, RemoteConnectionIdEntry},
     ecn,
     frame::{FrameEncoder as _, FrameType},
+    multipath::PathId,
     packet,
     pmtud::Pmtud,
+    qlog,
     recovery::{self, sent},
     rtt::{RttEstimate, RttSource},
     sender::PacketSender,
@@ -113,6 +117,10 @@ impl Paths {
     }
 
     fn retire(to_retire: &mut Vec<u64>, retired: &PathRef) {
+        // Connection IDs for multipath paths are retired separately.
+        if retired.borrow().path_id().is_some() {
+            return;
+        }
         if let Some(cid) = &retired.borrow().remote_cid {
             let seqno = cid.sequence_number();
             if cid.connection_id().is_empty() {
@@ -136,9 +144,16 @@ impl Paths {
 
         // Make sure not to track too many paths.
         // This protects index 0, which contains the primary path.
+        // Paths that are used for multipath are only removed if there is no other choice.
         if self.paths.len() >= MAX_PATHS {
             debug_assert_eq!(self.paths.len(), MAX_PATHS);
-            let removed = self.paths.remove(1);
+            let idx = self
+                .paths
+                .iter()
+                .skip(1)
+                .position(|p| p.borrow().path_id().is_none())
+                .map_or(1, |i| i + 1);
+            let removed = self.paths.remove(idx);
             Self::retire(&mut self.to_retire, &removed);
             if self
                 .migration_target
@@ -151,7 +166,7 @@ impl Paths {
                 );
                 self.migration_target = None;
             }
-            debug_assert_eq!(Rc::strong_count(&removed), 1);
+            debug_assert!(removed.borrow().path_id().is_some() || Rc::strong_count(&removed) == 1);
         }
 
         qdebug!("[{}] Make permanent", path.borrow());
@@ -162,6 +177,24 @@ impl Paths {
         }
     }
 
+    /// Adopt a temporary path for use with multipath.
+    /// Unlike migration, this doesn't change the primary path.
+    /// The path is probed, so that it can be used once probing succeeds.
+    pub fn add_multipath(
+        &mut self,
+        path: &PathRef,
+        path_id: PathId,
+        remote_cid: RemoteConnectionIdEntry,
+        now: Instant,
+        stats: &mut Stats,
+    ) {
+        debug_assert!(self.primary.is_some());
+        path.borrow_mut().set_path_id(path_id);
+        self.make_permanent(path, None, remote_cid, now);
+        path.borrow_mut().start_ecn(stats, now);
+        path.borrow_mut().probe(stats, now);
+    }
+
     /// Select a path as the primary.  Returns the old primary path.
     /// Using the old path is only necessary if this change in path is a reaction
     /// to a migration from a peer, in which case the old path needs to be probed.
@@ -181,6 +214,7 @@ impl Paths {
         self.paths.swap(0, idx);
 
         path.borrow_mut().set_primary(true, now);
+        qlog::path_assigned(&mut self.qlog, &path.borrow(), now);
         old_path
     }
 
@@ -202,7 +236,7 @@ impl Paths {
             |p| p.borrow().ecn_info.baseline(),
         );
         path.borrow_mut().set_ecn_baseline(baseline);
-        path.borrow_mut().start_ecn(stats);
+        path.borrow_mut().start_ecn(stats, now);
         if force || path.borrow().is_valid() {
             path.borrow_mut().set_valid(now);
             drop(self.select_primary(path, now));
@@ -210,7 +244,7 @@ impl Paths {
         } else {
             self.migration_target = Some(Rc::clone(path));
         }
-        path.borrow_mut().probe(stats);
+        path.borrow_mut().probe(stats, now);
         self.migration_target.is_none()
     }
 
@@ -246,7 +280,7 @@ impl Paths {
                 .paths
                 .iter()
                 .rev() // More recent paths are toward the end.
-                .find(|p| p.borrow().is_valid())
+                .find(|p| p.borrow().is_valid() && p.borrow().path_id().is_none())
             {
                 // Need a clone as `fallback` is borrowed from `self`.
                 let path = Rc::clone(fallback);
@@ -267,6 +301,14 @@ impl Paths {
         }
     }
 
+    /// Stop tracking a path.  This is used for multipath paths that are abandoned.
+    pub fn remove(&mut self, path: &PathRef) {
+        debug_assert!(!path.borrow().is_primary());
+        self.paths.retain(|p| !Rc::ptr_eq(p, path));
+        self.migration_target
+            .take_if(|target| Rc::ptr_eq(target, path));
+    }
+
     /// Get when the next call to `process_timeout()` should be scheduled.
     pub fn next_timeout(&self, pto: Duration) -> Option<Instant> {
         self.paths
@@ -299,7 +341,7 @@ impl Paths {
 
         if let Some(old_path) = self.select_primary(path, now) {
             // Need to probe the old path if the peer migrates.
-            old_path.borrow_mut().probe(stats);
+            old_path.borrow_mut().probe(stats, now);
             // TODO(mt) - suppress probing if the path was valid within 3PTO.
         }
 
@@ -361,6 +403,10 @@ impl Paths {
             let Some(current) = path.remote_cid.as_ref() else {
                 return true;
             };
+            if path.path_id.is_some() {
+                // Multipath paths use connection IDs from a different space.
+                return true;
+            }
             if current.sequence_number() < retire_prior && !current.connection_id().is_empty() {
                 to_retire.push(current.sequence_number());
                 let new_cid = store.next();
@@ -369,6 +415,7 @@ impl Paths {
                 // keep that path at the first index.
                 debug_assert!(!path.is_primary() || has_replacement);
                 path.remote_cid = new_cid;
+                path.spin = false;
                 if !has_replacement
                     && migration_target
                         .as_ref()
@@ -437,15 +484,15 @@ impl Paths {
         }
     }
 
-    pub fn lost_ecn(&self, stats: &mut Stats) {
+    pub fn lost_ecn(&self, stats: &mut Stats, now: Instant) {
         if let Some(path) = self.primary() {
-            path.borrow_mut().lost_ecn(stats);
+            path.borrow_mut().lost_ecn(stats, now);
         }
     }
 
-    pub fn start_ecn(&self, stats: &mut Stats) {
+    pub fn start_ecn(&self, stats: &mut Stats, now: Instant) {
         if let Some(path) = self.primary() {
-            path.borrow_mut().start_ecn(stats);
+            path.borrow_mut().start_ecn(stats, now);
         }
     }
 
@@ -522,6 +569,9 @@ pub struct Path {
 
     /// Whether this is the primary path.
     primary: bool,
+    /// The multipath path identifier, for paths other than the initial path.
+    #[expect(clippy::struct_field_names, reason = "This is the best name.")]
+    path_id: Option<PathId>,
     /// Whether the current path is considered valid.
     state: ProbeState,
     /// For a path that is not validated, this is `None`.  For a validated
@@ -543,6 +593,10 @@ pub struct Path {
     sent_bytes: usize,
     /// The ECN-related state for this path (see RFC9000, Section 13.4 and Appendix A.4)
     ecn_info: ecn::Info,
+    /// The latency spin bit value to send on this path (see RFC9000, Section 17.4).
+    spin: bool,
+    /// The largest packet number received in a short header packet on this path.
+    spin_pn: Option<packet::Number>,
     /// For logging of events.
     qlog: Qlog,
 }
@@ -587,12 +641,15 @@ impl Path {
         };
         let mut sender = PacketSender::new(conn_params, Pmtud::new(remote.ip(), iface_mtu), now);
         sender.set_qlog(qlog.clone());
+        let mut ecn_info = ecn::Info::default();
+        ecn_info.set_qlog(qlog.clone());
         Self {
             local,
             remote,
             local_cid: None,
             remote_cid: None,
             primary: false,
+            path_id: None,
             state: ProbeState::ProbeNeeded { probe_count: 0 },
             validated: None,
             challenge: None,
@@ -600,7 +657,9 @@ impl Path {
             sender,
             received_bytes: 0,
             sent_bytes: 0,
-            ecn_info: ecn::Info::default(),
+            ecn_info,
+            spin: false,
+            spin_pn: None,
             qlog,
         }
     }
@@ -619,6 +678,17 @@ impl Path {
         self.primary
     }
 
+    /// The multipath path identifier, which is only set for paths that are
+    /// used for multipath, other than the initial path.
+    pub const fn path_id(&self) -> Option<PathId> {
+        self.path_id
+    }
+
+    /// Mark this path as being used for multipath with the given identifier.
+    pub const fn set_path_id(&mut self, path_id: PathId) {
+        self.path_id = Some(path_id);
+    }
+
     /// Whether this path is a temporary one.
     pub const fn is_temporary(&self) -> bool {
         self.remote_cid.is_none()
@@ -728,11 +798,12 @@ impl Path {
         num_datagrams: usize,
         datagram_size: usize,
         stats: &mut Stats,
+        now: Instant,
     ) -> datagram::Batch {
         // Make sure to use the TOS value from before calling ecn::Info::on_packet_sent, which may
         // update the ECN state and can hence change it - this packet should still be sent
         // with the current value.
-        self.ecn_info.on_packet_sent(num_datagrams, stats);
+        self.ecn_info.on_packet_sent(num_datagrams, stats, now);
         datagram::Batch::new(
             self.local,
             self.remote,
@@ -765,7 +836,7 @@ impl Path {
                 self.set_valid(now);
                 if need_full_probe {
                     qdebug!("[{self}] Sub-MTU probe successful, reset probe count");
-                    self.probe(stats);
+                    self.probe(stats, now);
                 }
                 true
             } else {
@@ -784,7 +855,7 @@ impl Path {
 
     /// At the next opportunity, send a probe.
     /// If the probe count has been exhausted already, marks the path as failed.
-    fn probe(&mut self, stats: &mut Stats) {
+    fn probe(&mut self, stats: &mut Stats, now: Instant) {
         let probe_count = match &self.state {
             ProbeState::Probing { probe_count, .. } => *probe_count + 1,
             ProbeState::ProbeNeeded { probe_count, .. } => *probe_count,
@@ -795,7 +866,7 @@ impl Path {
                 // The path validation failure may be due to ECN blackholing, try again without ECN.
                 qinfo!("[{self}] Possible ECN blackhole, disabling ECN and re-probing path");
                 self.ecn_info
-                    .disable_ecn(stats, ecn::ValidationError::BlackHole);
+                    .disable_ecn(stats, ecn::ValidationError::BlackHole, now);
                 ProbeState::ProbeNeeded { probe_count: 0 }
             } else {
                 qinfo!("[{self}] Probing failed");
@@ -881,12 +952,12 @@ impl Path {
         self.ecn_info.acked_ecn();
     }
 
-    pub fn lost_ecn(&mut self, stats: &mut Stats) {
-        self.ecn_info.lost_ecn(stats);
+    pub fn lost_ecn(&mut self, stats: &mut Stats, now: Instant) {
+        self.ecn_info.lost_ecn(stats, now);
     }
 
-    pub fn start_ecn(&mut self, stats: &mut Stats) {
-        self.ecn_info.start(stats);
+    pub fn start_ecn(&mut self, stats: &mut Stats, now: Instant) {
+        self.ecn_info.start(stats, now);
     }
 
     pub fn acked_ack_frequency(&mut self, acked: &AckRate) {
@@ -899,13 +970,13 @@ impl Path {
         if let ProbeState::Probing { sent, .. } = &self.state
             && now >= *sent + pto
         {
-            self.probe(stats);
+            self.probe(stats, now);
         }
         if matches!(self.state, ProbeState::Failed) {
             // Retire failed paths immediately.
             false
-        } else if self.primary {
-            // Keep valid primary paths otherwise.
+        } else if self.primary || self.path_id.is_some() {
+            // Keep valid primary and multipath paths otherwise.
             true
         } else if matches!(self.state, ProbeState::Valid) {
             // Retire validated, non-primary paths.
@@ -991,9 +1062,39 @@ impl Path {
         self.sent_bytes = self.sent_bytes.saturating_add(count);
     }
 
+    /// The value of the latency spin bit to send on this path.
+    pub const fn spin(&self) -> bool {
+        self.spin
+    }
+
+    /// Update the latency spin bit from a short header packet received on this path.
+    /// Only a packet with a larger packet number than any before it counts.
+    /// A server reflects the value that it receives and a client inverts it,
+    /// so the value that each endpoint sends changes once per round trip.
+    pub fn spin_received(
+        &mut self,
+        role: Role,
+        pn: packet::Number,
+        spin: bool,
+        stats: &mut Stats,
+        now: Instant,
+    ) {
+        if self.spin_pn.is_some_and(|largest| pn <= largest) {
+            return;
+        }
+        self.spin_pn = Some(pn);
+        let spin = spin ^ (role == Role::Client);
+        if spin != self.spin {
+            qtrace!("[{self}] Spin bit now {}", u8::from(spin));
+            self.spin = spin;
+            stats.spin_edges += 1;
+            qlog::spin_bit_updated(&mut self.qlog, spin, now);
+        }
+    }
+
     /// Record a packet as having been sent on this path.
     pub fn packet_sent(&mut self, sent: &mut sent::Packet, now: Instant) {
-        if !self.is_primary() {
+        if !self.is_primary() && self.path_id.is_none() {
             sent.clear_primary_path();
         }
         self.sender.on_packet_sent(sent, self.rtt.estimate(), now);
@@ -1032,9 +1133,11 @@ impl Path {
         now: Instant,
         stats: &mut Stats,
     ) {
-        debug_assert!(self.is_primary());
+        debug_assert!(self.is_primary() || self.path_id.is_some());
 
-        let ecn_ce_received = self.ecn_info.on_packets_acked(acked_pkts, ack_ecn, stats);
+        let ecn_ce_received = self
+            .ecn_info
+            .on_packets_acked(acked_pkts, ack_ecn, stats, now);
         if ecn_ce_received {
             let cwnd_reduced = self.sender.on_ecn_ce_received(
                 acked_pkts.first().expect("must be there"),
@@ -1059,7 +1162,7 @@ impl Path {
         stats: &mut Stats,
         now: Instant,
     ) {
-        debug_assert!(self.is_primary());
+        debug_assert!(self.is_primary() || self.path_id.is_some());
         let cwnd_reduced = self.sender.on_packets_lost(
             self.rtt.first_sample_time(),
             prev_largest_acked_sent,
@@ -1106,7 +1209,9 @@ impl Path {
 
     /// Update the `QLog` instance.
     pub fn set_qlog(&mut self, qlog: Qlog) {
-        self.sender.set_qlog(qlog);
+        self.sender.set_qlog(qlog.clone());
+        self.ecn_info.set_qlog(qlog.clone());
+        self.qlog = qlog;
     }
 }
 
@@ -1119,6 +1224,9 @@ impl Display for Path {
             write!(f, "unv-")?; // unvalidated
         }
         write!(f, "path")?;
+        if let Some(path_id) = self.path_id {
+            write!(f, "-{path_id}")?;
+        }
         if let Some(entry) = self.remote_cid.as_ref() {
             write!(f, ":{}", entry.connection_id())?;
         }
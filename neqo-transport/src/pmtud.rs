@@ -9,13 +9,13 @@ use std::{
     time::{Duration, Instant},
 };
 
-use neqo_common::{Buffer, qdebug, qinfo};
+use neqo_common::{Buffer, qdebug, qinfo, qlog::Qlog};
 use static_assertions::const_assert;
 
 use crate::{
     Stats,
     frame::{FrameEncoder as _, FrameType},
-    packet,
+    packet, qlog,
     recovery::{self, sent},
 };
 
@@ -55,6 +55,7 @@ pub struct Pmtud {
     probe_count: usize,
     probe_state: Probe,
     raise_timer: Option<Instant>,
+    qlog: Qlog,
 }
 
 impl Pmtud {
@@ -88,6 +89,22 @@ impl Pmtud {
             probe_count: 0,
             probe_state: Probe::NotNeeded,
             raise_timer: None,
+            qlog: Qlog::disabled(),
+        }
+    }
+
+    pub fn set_qlog(&mut self, qlog: Qlog) {
+        self.qlog = qlog;
+    }
+
+    /// Set the MTU to the entry at `idx` in the search table.
+    fn set_mtu(&mut self, idx: usize, done: bool, now: Instant, stats: &mut Stats) {
+        let old = self.plpmtu();
+        self.mtu = self.search_table[idx];
+        stats.pmtud_pmtu = self.mtu;
+        let new = self.plpmtu();
+        if done || old != new {
+            qlog::mtu_updated(&mut self.qlog, old, new, done, now);
         }
     }
 
@@ -169,8 +186,7 @@ impl Pmtud {
 
         // A probe was ACKed, confirm the new MTU and try to probe upwards further.
         stats.pmtud_ack += acked;
-        self.mtu = self.search_table[self.probe_index];
-        stats.pmtud_pmtu = self.mtu;
+        self.set_mtu(self.probe_index, false, now, stats);
         qdebug!("PMTUD probe of size {} succeeded", self.mtu);
         self.next(now, stats);
     }
@@ -179,8 +195,7 @@ impl Pmtud {
     fn stop(&mut self, idx: usize, now: Instant, stats: &mut Stats) {
         self.probe_state = Probe::NotNeeded; // We don't need to send any more probes
         self.probe_index = idx; // Index of the last successful probe
-        self.mtu = self.search_table[idx]; // Leading to this MTU
-        stats.pmtud_pmtu = self.mtu;
+        self.set_mtu(idx, true, now, stats); // Leading to this MTU
         self.probe_count = 0; // Reset the count
         self.raise_timer = Some(now + PMTU_RAISE_TIMER);
         qinfo!(
@@ -222,8 +237,7 @@ impl Pmtud {
     /// Starts PMTUD from the minimum MTU, probing upward.
     pub fn start(&mut self, now: Instant, stats: &mut Stats) {
         self.probe_index = 0;
-        self.mtu = self.search_table[self.probe_index];
-        stats.pmtud_pmtu = self.mtu;
+        self.set_mtu(self.probe_index, false, now, stats);
         self.raise_timer = None;
         qdebug!("PMTUD started, PLPMTU is now {}", self.mtu);
         self.next(now, stats);
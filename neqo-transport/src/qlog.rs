@@ -7,27 +7,35 @@
 // Functions that handle capturing QLOG traces.
 
 use std::{
+    net::SocketAddr,
     ops::{Deref as _, RangeInclusive},
     time::{Duration, Instant},
 };
 
-use neqo_common::{Decoder, Ecn, hex, qinfo, qlog::Qlog};
+use neqo_common::{Decoder, Ecn, Role, hex, qinfo, qlog::Qlog};
 use qlog::events::{
     EventData, RawInfo,
-    connectivity::{ConnectionStarted, ConnectionState, ConnectionStateUpdated},
+    connectivity::{
+        ConnectionStarted, ConnectionState, ConnectionStateUpdated, MtuUpdated, SpinBitUpdated,
+    },
     quic::{
-        AckedRanges, ErrorSpace, MetricsUpdated, PacketDropped, PacketHeader, PacketLost,
-        PacketReceived, PacketSent, QuicFrame, StreamType, VersionInformation,
+        AckedRanges, CongestionStateUpdated, CongestionStateUpdatedTrigger, ErrorSpace,
+        LossTimerEventType, LossTimerUpdated, MetricsUpdated, PacketBuffered,
+        PacketBufferedTrigger, PacketDropped, PacketHeader, PacketLost, PacketReceived, PacketSent,
+        QuicFrame, StreamType, TimerType, VersionInformation,
     },
+    security::{KeyDiscarded, KeyType, KeyUpdateOrRetiredTrigger, KeyUpdated},
 };
+use serde_derive::Serialize;
 use smallvec::SmallVec;
 
 use crate::{
     connection::State,
+    crypto::{CryptoDxDirection, Epoch},
     frame::{CloseError, Frame},
     packet::{self, metadata::Direction},
-    path::PathRef,
-    recovery::sent,
+    path::{Path, PathRef},
+    recovery::{LossTimer, sent},
     stream_id::StreamType as NeqoStreamType,
     tparams::{
         TransportParameterId::{
@@ -38,6 +46,7 @@ use crate::{
         },
         TransportParametersHandler,
     },
+    tracking::PacketNumberSpace,
     version::{self, Version},
 };
 
@@ -274,6 +283,28 @@ pub fn packet_dropped(qlog: &mut Qlog, decrypt_err: &packet::DecryptionError, no
     );
 }
 
+/// Log a packet that can't be decrypted yet and is kept until the keys arrive.
+pub fn packet_buffered(qlog: &mut Qlog, decrypt_err: &packet::DecryptionError, now: Instant) {
+    qlog.add_event_at(
+        || {
+            let header =
+                PacketHeader::with_type(decrypt_err.packet_type().into(), None, None, None, None);
+            let raw = RawInfo {
+                length: Some(decrypt_err.len() as u64),
+                ..Default::default()
+            };
+
+            Some(EventData::PacketBuffered(PacketBuffered {
+                header: Some(header),
+                raw: Some(raw),
+                datagram_id: None,
+                trigger: Some(PacketBufferedTrigger::KeysUnavailable),
+            }))
+        },
+        now,
+    );
+}
+
 pub fn packets_lost(qlog: &mut Qlog, pkts: &[sent::Packet], now: Instant) {
     qlog.add_event_with_stream(|stream| {
         for pkt in pkts {
@@ -366,6 +397,223 @@ pub fn metrics_updated(qlog: &mut Qlog, updated_metrics: &[Metric], now: Instant
     );
 }
 
+pub fn congestion_state_updated(
+    qlog: &mut Qlog,
+    old: &str,
+    new: &str,
+    trigger: Option<CongestionStateUpdatedTrigger>,
+    now: Instant,
+) {
+    qlog.add_event_at(
+        || {
+            Some(EventData::CongestionStateUpdated(CongestionStateUpdated {
+                old: Some(old.to_owned()),
+                new: new.to_owned(),
+                trigger,
+            }))
+        },
+        now,
+    );
+}
+
+pub fn loss_timer_updated(
+    qlog: &mut Qlog,
+    event_type: LossTimerEventType,
+    timer: &LossTimer,
+    now: Instant,
+) {
+    qlog.add_event_at(
+        || {
+            let delta = (event_type == LossTimerEventType::Set)
+                .then(|| timer.expiry().saturating_duration_since(now).as_secs_f32() * 1000.0);
+            Some(EventData::LossTimerUpdated(LossTimerUpdated {
+                timer_type: Some(if timer.is_pto() {
+                    TimerType::Pto
+                } else {
+                    TimerType::Ack
+                }),
+                packet_number_space: Some(timer.space().into()),
+                event_type,
+                delta,
+            }))
+        },
+        now,
+    );
+}
+
+/// Log a change to the PLPMTU.  `done` is set when the search has concluded.
+pub fn mtu_updated(qlog: &mut Qlog, old: usize, new: usize, done: bool, now: Instant) {
+    qlog.add_event_at(
+        || {
+            Some(EventData::MtuUpdated(MtuUpdated {
+                old: Some(u16::try_from(old).unwrap_or(u16::MAX)),
+                new: u16::try_from(new).unwrap_or(u16::MAX),
+                done: Some(done),
+            }))
+        },
+        now,
+    );
+}
+
+/// The data of a `recovery:ecn_state_updated` event.
+#[derive(Serialize)]
+struct EcnStateUpdated<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old: Option<&'a str>,
+    new: &'a str,
+}
+
+/// Log a change in the state of ECN validation for a path.
+pub fn ecn_state_updated(qlog: &mut Qlog, old: Option<&str>, new: &str, now: Instant) {
+    qlog.add_custom_event(
+        "recovery:ecn_state_updated",
+        || Some(EcnStateUpdated { old, new }),
+        Some(now),
+    );
+}
+
+/// One end of a path, as logged in a `connectivity:path_assigned` event.
+#[derive(Serialize)]
+struct PathEndpointInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip_v4: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port_v4: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip_v6: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port_v6: Option<u16>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    connection_ids: Vec<String>,
+}
+
+impl PathEndpointInfo {
+    fn new(addr: SocketAddr, cid: Option<String>) -> Self {
+        let (ip, port) = (Some(addr.ip().to_string()), Some(addr.port()));
+        let (ip_v4, port_v4, ip_v6, port_v6) = if addr.is_ipv4() {
+            (ip, port, None, None)
+        } else {
+            (None, None, ip, port)
+        };
+        Self {
+            ip_v4,
+            port_v4,
+            ip_v6,
+            port_v6,
+            connection_ids: cid.into_iter().collect(),
+        }
+    }
+}
+
+/// The data of a `connectivity:path_assigned` event.
+#[derive(Serialize)]
+#[expect(
+    clippy::struct_field_names,
+    reason = "These are the names that qlog uses."
+)]
+struct PathAssigned {
+    path_id: String,
+    path_local: PathEndpointInfo,
+    path_remote: PathEndpointInfo,
+}
+
+/// Log that `path` is now used as the primary path.
+pub fn path_assigned(qlog: &mut Qlog, path: &Path, now: Instant) {
+    qlog.add_custom_event(
+        "connectivity:path_assigned",
+        || {
+            Some(PathAssigned {
+                path_id: path.path_id().map(|id| id.to_string()).unwrap_or_default(),
+                path_local: PathEndpointInfo::new(
+                    path.local_address(),
+                    path.local_cid().map(ToString::to_string),
+                ),
+                path_remote: PathEndpointInfo::new(
+                    path.remote_address(),
+                    path.remote_cid().map(ToString::to_string),
+                ),
+            })
+        },
+        Some(now),
+    );
+}
+
+/// Log a change to the value of the latency spin bit that is sent on a path.
+pub fn spin_bit_updated(qlog: &mut Qlog, state: bool, now: Instant) {
+    qlog.add_event_at(
+        || Some(EventData::SpinBitUpdated(SpinBitUpdated { state })),
+        now,
+    );
+}
+
+/// Work out the qlog key type for keys that are used in `direction` by an
+/// endpoint with the given `role`.  For application data keys, this also
+/// provides the generation, which counts key updates.
+fn key_type(role: Role, direction: CryptoDxDirection, epoch: usize) -> (KeyType, Option<u32>) {
+    let client = (role == Role::Client) == (direction == CryptoDxDirection::Write);
+    let one_rtt = usize::from(Epoch::ApplicationData);
+    let key_type = match (epoch, client) {
+        (0, true) => KeyType::ClientInitialSecret,
+        (0, false) => KeyType::ServerInitialSecret,
+        (1, true) => KeyType::Client0RttSecret,
+        (1, false) => KeyType::Server0RttSecret,
+        (2, true) => KeyType::ClientHandshakeSecret,
+        (2, false) => KeyType::ServerHandshakeSecret,
+        (_, true) => KeyType::Client1RttSecret,
+        (_, false) => KeyType::Server1RttSecret,
+    };
+    let generation = epoch
+        .checked_sub(one_rtt)
+        .and_then(|g| u32::try_from(g).ok());
+    (key_type, generation)
+}
+
+/// Log the installation of new keys.  The key material itself is not logged.
+pub fn key_updated(
+    qlog: &mut Qlog,
+    role: Role,
+    direction: CryptoDxDirection,
+    epoch: usize,
+    trigger: Option<KeyUpdateOrRetiredTrigger>,
+    now: Instant,
+) {
+    qlog.add_event_at(
+        || {
+            let (key_type, generation) = key_type(role, direction, epoch);
+            Some(EventData::KeyUpdated(KeyUpdated {
+                key_type,
+                old: None,
+                new: String::new(),
+                generation,
+                trigger,
+            }))
+        },
+        now,
+    );
+}
+
+pub fn key_discarded(
+    qlog: &mut Qlog,
+    role: Role,
+    direction: CryptoDxDirection,
+    epoch: usize,
+    trigger: Option<KeyUpdateOrRetiredTrigger>,
+    now: Instant,
+) {
+    qlog.add_event_at(
+        || {
+            let (key_type, generation) = key_type(role, direction, epoch);
+            Some(EventData::KeyDiscarded(KeyDiscarded {
+                key_type,
+                key: None,
+                generation,
+                trigger,
+            }))
+        },
+        now,
+    );
+}
+
 // Helper functions
 
 #[expect(clippy::too_many_lines, reason = "Yeah, but it's a nice match.")]
@@ -536,7 +784,16 @@ impl From<Frame<'_>> for QuicFrame {
                 trigger_frame_type: Some(frame_type),
             },
             Frame::HandshakeDone => Self::HandshakeDone,
-            Frame::AckFrequency { .. } => Self::Unknown {
+            Frame::AckFrequency { .. }
+            | Frame::ResetStreamAt { .. }
+            | Frame::PathAck { .. }
+            | Frame::PathAbandon { .. }
+            | Frame::PathStatus { .. }
+            | Frame::PathNewConnectionId { .. }
+            | Frame::PathRetireConnectionId { .. }
+            | Frame::MaxPathId { .. }
+            | Frame::PathsBlocked { .. }
+            | Frame::PathCidsBlocked { .. } => Self::Unknown {
                 frame_type_value: None,
                 raw_frame_type: frame.get_type().into(),
                 raw: None,
@@ -549,6 +806,16 @@ impl From<Frame<'_>> for QuicFrame {
     }
 }
 
+impl From<PacketNumberSpace> for qlog::events::quic::PacketNumberSpace {
+    fn from(value: PacketNumberSpace) -> Self {
+        match value {
+            PacketNumberSpace::Initial => Self::Initial,
+            PacketNumberSpace::Handshake => Self::Handshake,
+            PacketNumberSpace::ApplicationData => Self::ApplicationData,
+        }
+    }
+}
+
 impl From<packet::Type> for qlog::events::quic::PacketType {
     fn from(value: packet::Type) -> Self {
         match value {
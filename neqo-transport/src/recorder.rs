@@ -0,0 +1,576 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Recording the datagrams that a connection sends and receives, so that they
+// can be replayed later.
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug, Formatter},
+    io::{self, Write},
+    mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use neqo_common::{Datagram, Decoder, Encoder, Role, qdebug, qwarn};
+use neqo_crypto::{
+    Cipher, KeyLog, SymKey, TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384,
+    TLS_CHACHA20_POLY1305_SHA256, TLS_VERSION_1_3, hkdf,
+};
+
+use crate::{
+    ConnectionIdDecoder, Error, Res, Version,
+    crypto::{CryptoDxDirection, CryptoStates, Epoch},
+    packet,
+    tracking::PacketNumberSpace,
+};
+
+/// The start of every capture, which includes a version number.
+const MAGIC: &[u8] = b"neqo-capture\x02";
+
+/// The tag for a line of the key log in a capture.  Datagrams are tagged with
+/// their [`Direction`].
+const KEYLOG_LINE: u8 = 2;
+
+/// How long the keys from before a key update remain usable when decrypting.
+const KEY_UPDATE_EXPIRY: Duration = Duration::from_secs(1);
+
+/// Whether a recorded datagram was received or sent by the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+/// A datagram from a capture, along with the time since the start of the
+/// capture at which it was received or sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub direction: Direction,
+    pub time: Duration,
+    pub datagram: Datagram,
+}
+
+/// A packet from a capture, as decrypted by [`Capture::decrypt`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    /// The time since the start of the capture at which the packet was received.
+    pub time: Duration,
+    pub space: PacketNumberSpace,
+    pub pn: u64,
+    /// The frames in the packet.
+    pub payload: Vec<u8>,
+}
+
+/// The destination of a capture, shared with the key log that writes to it.
+/// This is `None` after a write fails.
+type Output = Rc<RefCell<Option<Box<dyn Write>>>>;
+
+fn write_record(out: &Output, record: &[u8]) {
+    let mut out = out.borrow_mut();
+    if let Some(w) = out.as_mut()
+        && let Err(e) = w.write_all(record)
+    {
+        qwarn!("Stopping capture after write error: {e}");
+        *out = None;
+    }
+}
+
+/// Writes the datagrams that a connection sends and receives to the provided
+/// writer, see [`crate::Connection::set_recorder`].
+///
+/// The format is specific to neqo; read it back with [`Capture::decode`].
+pub struct Recorder {
+    start: Instant,
+    out: Output,
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Recorder started {:?}", self.start)
+    }
+}
+
+impl Recorder {
+    /// Start a capture for a connection with the given role.  Times in the
+    /// capture are relative to `start`.
+    ///
+    /// # Errors
+    ///
+    /// When the header of the capture can't be written.
+    pub fn new<W: Write + 'static>(role: Role, start: Instant, mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[u8::from(role == Role::Server)])?;
+        Ok(Self {
+            start,
+            out: Rc::new(RefCell::new(Some(Box::new(out)))),
+        })
+    }
+
+    /// A key log that adds the TLS secrets of the connection to the capture,
+    /// so that [`Capture::decrypt`] can read the packets in it.
+    pub(crate) fn keylog(&self) -> KeyLog {
+        KeyLog::new(KeyLogWriter {
+            out: Rc::clone(&self.out),
+            line: Vec::new(),
+        })
+    }
+
+    pub(crate) fn record<D: AsRef<[u8]>>(
+        &self,
+        direction: Direction,
+        d: &Datagram<D>,
+        now: Instant,
+    ) {
+        if self.out.borrow().is_none() {
+            return;
+        }
+        let time = now.saturating_duration_since(self.start);
+        let mut enc = Encoder::with_capacity(d.len() + 64);
+        enc.encode_byte(u8::from(direction == Direction::Sent));
+        enc.encode_varint(u64::try_from(time.as_micros()).unwrap_or(u64::MAX));
+        encode_addr(&mut enc, d.source());
+        encode_addr(&mut enc, d.destination());
+        enc.encode_byte(d.tos().into());
+        enc.encode_vvec(d.as_ref());
+        write_record(&self.out, enc.as_ref());
+    }
+}
+
+/// Collects the lines that a [`KeyLog`] writes and adds each to the capture.
+struct KeyLogWriter {
+    out: Output,
+    line: Vec<u8>,
+}
+
+impl Write for KeyLogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    /// [`KeyLog`] flushes after every line.
+    fn flush(&mut self) -> io::Result<()> {
+        let line = mem::take(&mut self.line);
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        if !line.is_empty() {
+            let mut enc = Encoder::with_capacity(line.len() + 3);
+            enc.encode_byte(KEYLOG_LINE);
+            enc.encode_vvec(line);
+            write_record(&self.out, enc.as_ref());
+        }
+        Ok(())
+    }
+}
+
+fn encode_addr(enc: &mut Encoder, addr: SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(ip) => enc.encode_byte(4).encode(ip.octets()),
+        IpAddr::V6(ip) => enc.encode_byte(6).encode(ip.octets()),
+    };
+    enc.encode_uint(2, addr.port());
+}
+
+fn decode_addr(dec: &mut Decoder) -> Res<SocketAddr> {
+    let ip = match dec.decode_uint::<u8>().ok_or(Error::NoMoreData)? {
+        4 => {
+            let octets = <[u8; 4]>::try_from(dec.decode(4).ok_or(Error::NoMoreData)?)
+                .map_err(|_| Error::InvalidInput)?;
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        6 => {
+            let octets = <[u8; 16]>::try_from(dec.decode(16).ok_or(Error::NoMoreData)?)
+                .map_err(|_| Error::InvalidInput)?;
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return Err(Error::InvalidInput),
+    };
+    let port = dec.decode_uint::<u16>().ok_or(Error::NoMoreData)?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// The contents of a capture written by a [`Recorder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    role: Role,
+    records: Vec<Record>,
+    keylog: String,
+}
+
+impl Capture {
+    /// Read a capture.  A capture that ends partway through a record, as might
+    /// happen if the process that wrote it crashed, is not an error; the
+    /// partial record is dropped.
+    ///
+    /// # Errors
+    ///
+    /// When `data` doesn't hold a capture, or a record is malformed.
+    pub fn decode(data: &[u8]) -> Res<Self> {
+        let mut dec = Decoder::from(data);
+        if dec.decode(MAGIC.len()) != Some(MAGIC) {
+            return Err(Error::InvalidInput);
+        }
+        let role = match dec.decode_uint::<u8>() {
+            Some(0) => Role::Client,
+            Some(1) => Role::Server,
+            _ => return Err(Error::InvalidInput),
+        };
+        let mut capture = Self {
+            role,
+            records: Vec::new(),
+            keylog: String::new(),
+        };
+        while dec.remaining() > 0 {
+            match capture.decode_record(&mut dec) {
+                Ok(()) => {}
+                Err(Error::NoMoreData) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(capture)
+    }
+
+    fn decode_record(&mut self, dec: &mut Decoder) -> Res<()> {
+        let direction = match dec.decode_uint::<u8>().ok_or(Error::NoMoreData)? {
+            0 => Direction::Received,
+            1 => Direction::Sent,
+            KEYLOG_LINE => {
+                let line = dec.decode_vvec().ok_or(Error::NoMoreData)?;
+                let line = str::from_utf8(line).map_err(|_| Error::InvalidInput)?;
+                self.keylog.push_str(line);
+                self.keylog.push('\n');
+                return Ok(());
+            }
+            _ => return Err(Error::InvalidInput),
+        };
+        let time = Duration::from_micros(dec.decode_varint().ok_or(Error::NoMoreData)?);
+        let src = decode_addr(dec)?;
+        let dst = decode_addr(dec)?;
+        let tos = dec.decode_uint::<u8>().ok_or(Error::NoMoreData)?;
+        let data = dec.decode_vvec().ok_or(Error::NoMoreData)?;
+        self.records.push(Record {
+            direction,
+            time,
+            datagram: Datagram::new(src, dst, tos.into(), data),
+        });
+        Ok(())
+    }
+
+    /// The role of the connection that made the capture.
+    #[must_use]
+    pub const fn role(&self) -> Role {
+        self.role
+    }
+
+    /// All of the recorded datagrams, in the order they were recorded.
+    #[must_use]
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// The datagrams that the connection received.
+    pub fn received(&self) -> impl Iterator<Item = &Record> {
+        self.records
+            .iter()
+            .filter(|r| r.direction == Direction::Received)
+    }
+
+    /// The TLS secrets of the connection, in the format of `SSLKEYLOGFILE`.
+    /// This is empty if the connection had its own key log.
+    #[must_use]
+    pub fn keylog(&self) -> &str {
+        &self.keylog
+    }
+
+    /// Decrypt the packets that the connection received, using the secrets in
+    /// `keylog`, which is usually [`Capture::keylog`].  `cid_decoder` reads the
+    /// connection IDs that the connection issued.
+    ///
+    /// Unlike feeding the capture to a new connection, this doesn't depend on
+    /// the handshake being repeated exactly, so the result is the same every
+    /// time.  Only packets on the initial path can be decrypted.
+    ///
+    /// # Errors
+    ///
+    /// When `keylog` is missing a secret that is needed, or when a packet
+    /// can't be decrypted.
+    pub fn decrypt(&self, keylog: &str, cid_decoder: &dyn ConnectionIdDecoder) -> Res<Vec<Packet>> {
+        let secrets = Secrets::parse(keylog, self.role)?;
+        let odcid = self.original_dcid(cid_decoder)?;
+        let ciphers: &[Cipher] = match secrets.len() {
+            48 => &[TLS_AES_256_GCM_SHA384],
+            _ => &[TLS_AES_128_GCM_SHA256, TLS_CHACHA20_POLY1305_SHA256],
+        };
+        // The cipher suite isn't in the key log, so try each that fits.
+        let mut result = Err(Error::Decrypt);
+        for &cipher in ciphers {
+            result = self.decrypt_with(&secrets, cipher, &odcid, cid_decoder);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    /// The connection ID that the client chose for its first Initial packet,
+    /// from which the Initial keys are derived.
+    fn original_dcid(&self, cid_decoder: &dyn ConnectionIdDecoder) -> Res<Vec<u8>> {
+        let from_client = match self.role {
+            Role::Client => Direction::Sent,
+            Role::Server => Direction::Received,
+        };
+        let first = self
+            .records
+            .iter()
+            .find(|r| r.direction == from_client)
+            .ok_or(Error::NoMoreData)?;
+        let mut data = first.datagram.to_vec();
+        let (packet, _) = packet::Public::decode(&mut data, cid_decoder)?;
+        if packet.packet_type() != packet::Type::Initial {
+            return Err(Error::InvalidPacket);
+        }
+        Ok(packet.dcid().to_vec())
+    }
+
+    fn decrypt_with(
+        &self,
+        secrets: &Secrets,
+        cipher: Cipher,
+        odcid: &[u8],
+        cid_decoder: &dyn ConnectionIdDecoder,
+    ) -> Res<Vec<Packet>> {
+        // `CryptoStates` uses time only to retire old keys after a key update.
+        let start = Instant::now();
+        let mut states = CryptoStates::new(self.role);
+        states.init(&Version::all(), self.role, odcid, false, start)?;
+        let mut version = None;
+        if let (Role::Server, Some(early)) = (self.role, &secrets.early) {
+            states.set_0rtt_keys(
+                Version::default(),
+                CryptoDxDirection::Read,
+                early,
+                cipher,
+                start,
+            )?;
+        }
+
+        let mut packets = Vec::new();
+        for r in self.received() {
+            let now = start + r.time;
+            states.check_key_update(now)?;
+            let mut data = r.datagram.to_vec();
+            let mut slice = &mut data[..];
+            let mut dcid = None;
+            while !slice.is_empty() {
+                let (packet, remainder) = packet::Public::decode(slice, cid_decoder)?;
+                slice = remainder;
+                // Like the connection, ignore anything after the first packet
+                // that doesn't have the same connection ID, which is usually
+                // padding.
+                if dcid.get_or_insert_with(|| packet.dcid().to_vec()) != &packet.dcid()[..] {
+                    break;
+                }
+                match packet.packet_type() {
+                    packet::Type::VersionNegotiation | packet::Type::Retry => continue,
+                    packet::Type::OtherVersion => return Err(Error::InvalidPacket),
+                    _ => {}
+                }
+                if let Some(v) = packet.version() {
+                    version = Some(v);
+                }
+                if let Some(epoch) = Self::pending_keys(&states, &packet) {
+                    let v = version.ok_or(Error::InvalidPacket)?;
+                    secrets.install(&mut states, epoch, v, cipher, now)?;
+                }
+                let decrypted = packet
+                    .decrypt_for_path(&mut states, None, now, now + KEY_UPDATE_EXPIRY)
+                    .map_err(|e| e.error)?;
+                packets.push(Packet {
+                    time: r.time,
+                    space: PacketNumberSpace::from(decrypted.packet_type()),
+                    pn: decrypted.pn(),
+                    payload: decrypted.as_ref().to_vec(),
+                });
+            }
+        }
+        Ok(packets)
+    }
+
+    /// The keys that are needed for `packet`, if they aren't installed yet.
+    fn pending_keys(states: &CryptoStates, packet: &packet::Public) -> Option<Epoch> {
+        let epoch = Epoch::try_from(packet.packet_type()).ok()?;
+        states.rx_pending(epoch).then_some(epoch)
+    }
+}
+
+/// The secrets from a key log that [`Capture::decrypt`] needs, from the point
+/// of view of the connection that made the capture.
+struct Secrets {
+    handshake: Option<(SymKey, SymKey)>,
+    application: Option<(SymKey, SymKey)>,
+    early: Option<SymKey>,
+    len: usize,
+}
+
+impl Secrets {
+    fn parse(keylog: &str, role: Role) -> Res<Self> {
+        let mut found = std::collections::HashMap::new();
+        let mut len = 0;
+        for line in keylog.lines() {
+            let mut parts = line.split_whitespace();
+            let (Some(label), Some(_client_random), Some(secret)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            let secret = decode_hex(secret).ok_or(Error::InvalidInput)?;
+            len = secret.len();
+            let key = hkdf::import_key(TLS_VERSION_1_3, &secret)?;
+            // Keep the first of each; later ones follow key updates.
+            found.entry(label.to_string()).or_insert(key);
+        }
+        let (ours, theirs) = match role {
+            Role::Client => ("CLIENT", "SERVER"),
+            Role::Server => ("SERVER", "CLIENT"),
+        };
+        let mut pair = |label: &str| {
+            let write = found.remove(&format!("{ours}_{label}"))?;
+            let read = found.remove(&format!("{theirs}_{label}"))?;
+            Some((write, read))
+        };
+        let handshake = pair("HANDSHAKE_TRAFFIC_SECRET");
+        let application = pair("TRAFFIC_SECRET_0");
+        Ok(Self {
+            handshake,
+            application,
+            early: found.remove("CLIENT_EARLY_TRAFFIC_SECRET"),
+            len,
+        })
+    }
+
+    /// The length of the secrets, which depends on the cipher suite.
+    const fn len(&self) -> usize {
+        self.len
+    }
+
+    fn install(
+        &self,
+        states: &mut CryptoStates,
+        epoch: Epoch,
+        version: Version,
+        cipher: Cipher,
+        now: Instant,
+    ) -> Res<()> {
+        qdebug!("Installing {epoch:?} keys to decrypt capture");
+        match epoch {
+            Epoch::Handshake => {
+                let (write, read) = self.handshake.as_ref().ok_or(Error::KeysPending(epoch))?;
+                states.set_handshake_keys(version, write, read, cipher, now)
+            }
+            Epoch::ApplicationData => {
+                let (write, read) = self.application.as_ref().ok_or(Error::KeysPending(epoch))?;
+                if self.handshake.is_none() {
+                    return Err(Error::KeysPending(Epoch::Handshake));
+                }
+                states.set_application_write_key(version, write, now)?;
+                states.set_application_read_key(version, read, now + KEY_UPDATE_EXPIRY, now)
+            }
+            Epoch::Initial | Epoch::ZeroRtt => Err(Error::KeysPending(epoch)),
+        }
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{
+        cell::RefCell,
+        io::{self, Write},
+        net::SocketAddr,
+        rc::Rc,
+        time::Duration,
+    };
+
+    use neqo_common::{Datagram, Ecn, Role};
+    use test_fixture::{DEFAULT_ADDR, DEFAULT_ADDR_V4, now};
+
+    use super::{Capture, Direction, KeyLogWriter, Recorder};
+    use crate::Error;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = Shared::default();
+        let r = Recorder::new(Role::Server, now(), buf.clone()).unwrap();
+        let v4 = Datagram::new(DEFAULT_ADDR_V4, DEFAULT_ADDR_V4, Ecn::Ce.into(), [1; 3]);
+        let v6 = Datagram::new(
+            DEFAULT_ADDR,
+            SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 1)),
+            Ecn::Ect0.into(),
+            [2; 4],
+        );
+        r.record(Direction::Received, &v4, now() + Duration::from_millis(3));
+        let mut keylog = KeyLogWriter {
+            out: Rc::clone(&r.out),
+            line: Vec::new(),
+        };
+        keylog.write_all(b"LABEL 00").unwrap();
+        keylog.write_all(b" 11\n").unwrap();
+        keylog.flush().unwrap();
+        r.record(Direction::Sent, &v6, now() + Duration::from_secs(1));
+
+        let capture = Capture::decode(&buf.0.borrow()).unwrap();
+        assert_eq!(capture.role(), Role::Server);
+        let records = capture.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, Direction::Received);
+        assert_eq!(records[0].time, Duration::from_millis(3));
+        assert_eq!(records[0].datagram, v4);
+        assert_eq!(records[1].direction, Direction::Sent);
+        assert_eq!(records[1].time, Duration::from_secs(1));
+        assert_eq!(records[1].datagram, v6);
+        assert_eq!(capture.received().count(), 1);
+        assert_eq!(capture.keylog(), "LABEL 00 11\n");
+    }
+
+    #[test]
+    fn truncated() {
+        let buf = Shared::default();
+        let r = Recorder::new(Role::Client, now(), buf.clone()).unwrap();
+        let d = Datagram::new(DEFAULT_ADDR, DEFAULT_ADDR, Ecn::default().into(), [0; 10]);
+        r.record(Direction::Sent, &d, now());
+        r.record(Direction::Sent, &d, now());
+
+        let data = buf.0.borrow();
+        let capture = Capture::decode(&data[..data.len() - 1]).unwrap();
+        assert_eq!(capture.records().len(), 1);
+        assert_eq!(Capture::decode(&data[1..]), Err(Error::InvalidInput));
+    }
+}
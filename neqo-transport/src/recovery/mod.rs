@@ -16,6 +16,7 @@ use std::{
     time::{Duration, Instant},
 };
 
+use ::qlog::events::quic::LossTimerEventType;
 use enum_map::EnumMap;
 use enumset::enum_set;
 use neqo_common::{qdebug, qinfo, qlog::Qlog, qtrace, qwarn};
@@ -479,12 +480,40 @@ impl PtoState {
     }
 }
 
+/// The loss detection timer, which is either the time at which a packet is
+/// declared lost or the probe timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LossTimer {
+    pto: bool,
+    space: PacketNumberSpace,
+    expiry: Instant,
+}
+
+impl LossTimer {
+    #[must_use]
+    pub const fn is_pto(&self) -> bool {
+        self.pto
+    }
+
+    #[must_use]
+    pub const fn space(&self) -> PacketNumberSpace {
+        self.space
+    }
+
+    #[must_use]
+    pub const fn expiry(&self) -> Instant {
+        self.expiry
+    }
+}
+
 #[derive(Debug)]
 pub struct Loss {
     /// When the handshake was confirmed, if it has been.
     confirmed_time: Option<Instant>,
     pto_state: Option<PtoState>,
     spaces: LossRecoverySpaces,
+    /// The loss detection timer, as last set by `update_timer`.
+    timer: Option<LossTimer>,
     qlog: Qlog,
     stats: StatsCell,
     /// The factor by which the PTO period is reduced.
@@ -499,6 +528,7 @@ impl Loss {
             confirmed_time: None,
             pto_state: None,
             spaces: LossRecoverySpaces::default(),
+            timer: None,
             qlog: Qlog::default(),
             stats,
             fast_pto,
@@ -550,6 +580,11 @@ impl Loss {
 
     /// Whether to probe the path.
     #[must_use]
+    /// Whether a PTO has fired and probes remain to be sent.
+    pub fn pto_pending(&self) -> bool {
+        self.pto_state.as_ref().is_some_and(|pto| pto.packets > 0)
+    }
+
     pub fn should_probe(&self, pto: Duration, now: Instant) -> bool {
         self.spaces
             .get(PacketNumberSpace::ApplicationData)
@@ -762,6 +797,24 @@ impl Loss {
     /// and the PTO timer; either or both might be disabled, so this can return `None`.
     #[must_use]
     pub fn next_timeout(&self, path: &Path) -> Option<Instant> {
+        self.timer(path).map(|t| t.expiry)
+    }
+
+    /// As for `next_timeout`, but also remember the timer, so that changes can be logged.
+    pub fn update_timer(&mut self, path: &Path, now: Instant) -> Option<Instant> {
+        let timer = self.timer(path);
+        if timer != self.timer {
+            if let Some(t) = &timer {
+                qlog::loss_timer_updated(&mut self.qlog, LossTimerEventType::Set, t, now);
+            } else if let Some(old) = &self.timer {
+                qlog::loss_timer_updated(&mut self.qlog, LossTimerEventType::Cancelled, old, now);
+            }
+            self.timer = timer;
+        }
+        timer.map(|t| t.expiry)
+    }
+
+    fn timer(&self, path: &Path) -> Option<LossTimer> {
         let rtt = path.rtt();
         let loss_time = self.earliest_loss_time(rtt);
         let pto_time = if path.pto_possible() {
@@ -770,21 +823,22 @@ impl Loss {
             None
         };
         qtrace!("[{self}] next_timeout loss={loss_time:?} pto={pto_time:?}");
-        match (loss_time, pto_time) {
-            (Some(loss_time), Some(pto_time)) => Some(min(loss_time, pto_time)),
-            (Some(loss_time), None) => Some(loss_time),
-            (None, Some(pto_time)) => Some(pto_time),
-            (None, None) => None,
-        }
+        let (pto, (space, expiry)) = match (loss_time, pto_time) {
+            (Some(loss_time), Some(pto_time)) if pto_time.1 < loss_time.1 => (true, pto_time),
+            (Some(loss_time), _) => (false, loss_time),
+            (None, Some(pto_time)) => (true, pto_time),
+            (None, None) => return None,
+        };
+        Some(LossTimer { pto, space, expiry })
     }
 
     /// Find when the earliest sent packet should be considered lost.
-    fn earliest_loss_time(&self, rtt: &RttEstimate) -> Option<Instant> {
+    fn earliest_loss_time(&self, rtt: &RttEstimate) -> Option<(PacketNumberSpace, Instant)> {
         self.spaces
             .iter()
-            .filter_map(LossRecoverySpace::loss_recovery_timer_start)
-            .min()
-            .map(|val| val + rtt.loss_delay())
+            .filter_map(|sp| Some((sp.space, sp.loss_recovery_timer_start()?)))
+            .min_by_key(|(_, t)| *t)
+            .map(|(space, t)| (space, t + rtt.loss_delay()))
     }
 
     /// Simple wrapper for the PTO calculation that avoids borrow check rules.
@@ -824,15 +878,15 @@ impl Loss {
 
     /// Find the earliest PTO time for all active packet number spaces.
     /// Ignore Application if either Initial or Handshake have an active PTO.
-    fn earliest_pto(&self, rtt: &RttEstimate) -> Option<Instant> {
+    fn earliest_pto(&self, rtt: &RttEstimate) -> Option<(PacketNumberSpace, Instant)> {
+        let pto = |space| Some((space, self.pto_time(rtt, space)?));
         if self.confirmed() {
-            self.pto_time(rtt, PacketNumberSpace::ApplicationData)
+            pto(PacketNumberSpace::ApplicationData)
         } else {
-            self.pto_time(rtt, PacketNumberSpace::Initial)
-                .iter()
-                .chain(self.pto_time(rtt, PacketNumberSpace::Handshake).iter())
-                .min()
-                .copied()
+            pto(PacketNumberSpace::Initial)
+                .into_iter()
+                .chain(pto(PacketNumberSpace::Handshake))
+                .min_by_key(|(_, t)| *t)
         }
     }
 
@@ -916,6 +970,10 @@ impl Loss {
     pub fn timeout(&mut self, primary_path: &PathRef, now: Instant) -> Vec<sent::Packet> {
         qtrace!("[{self}] timeout {now:?}");
 
+        if let Some(timer) = self.timer.take_if(|t| t.expiry <= now) {
+            qlog::loss_timer_updated(&mut self.qlog, LossTimerEventType::Expired, &timer, now);
+        }
+
         let loss_delay = primary_path.borrow().rtt().loss_delay();
         let confirmed = self.confirmed();
 
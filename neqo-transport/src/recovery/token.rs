@@ -8,6 +8,7 @@ use crate::{
     ackrate::AckRate,
     cid::ConnectionIdEntry,
     crypto::CryptoRecoveryToken,
+    multipath::PathId,
     quic_datagrams::DatagramTracking,
     send_stream,
     stateless_reset::Token as Srt,
@@ -69,4 +70,9 @@ pub enum Token {
     EcnEct0,
     /// A PMTUD probe packet.
     PmtudProbe,
+    PathNewConnectionId(PathId, ConnectionIdEntry<Srt>),
+    PathRetireConnectionId(PathId, u64),
+    PathAbandon(PathId),
+    /// A `PATH_STATUS` frame, with its sequence number.
+    PathStatus(PathId, u64),
 }
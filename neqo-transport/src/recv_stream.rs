@@ -9,7 +9,7 @@
 
 use std::{
     cell::RefCell,
-    cmp::max,
+    cmp::{max, min},
     collections::BTreeMap,
     fmt::Debug,
     mem,
@@ -353,6 +353,14 @@ impl RxStreamOrderer {
         copied
     }
 
+    /// Discard any data at or after `end`.
+    fn truncate(&mut self, end: u64) {
+        _ = self.data_ranges.split_off(&end);
+        if let Some((&start, data)) = self.data_ranges.iter_mut().next_back() {
+            data.truncate(usize::try_from(end - start).unwrap_or(usize::MAX));
+        }
+    }
+
     /// Extend the given Vector with any available data.
     pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> usize {
         let orig_len = buf.len();
@@ -380,6 +388,15 @@ enum RecvStreamState {
         session_fc: Rc<RefCell<ReceiverFlowControl<()>>>,
         recv_buf: RxStreamOrderer,
     },
+    /// `RESET_STREAM_AT` was received, but the application has not read
+    /// all of the data before `reliable_size` yet.
+    ReliableReset {
+        fc: ReceiverFlowControl<StreamId>,
+        session_fc: Rc<RefCell<ReceiverFlowControl<()>>>,
+        recv_buf: RxStreamOrderer,
+        err: AppError,
+        reliable_size: u64,
+    },
     DataRead {
         final_received: u64,
         final_read: u64,
@@ -423,7 +440,8 @@ impl RecvStreamState {
         match self {
             Self::Recv { recv_buf, .. }
             | Self::SizeKnown { recv_buf, .. }
-            | Self::DataRecvd { recv_buf, .. } => Some(recv_buf),
+            | Self::DataRecvd { recv_buf, .. }
+            | Self::ReliableReset { recv_buf, .. } => Some(recv_buf),
             Self::DataRead { .. }
             | Self::AbortReading { .. }
             | Self::WaitForReset { .. }
@@ -435,9 +453,9 @@ impl RecvStreamState {
         let (fc, session_fc, final_size_reached, retire_data) = match self {
             Self::Recv { fc, session_fc, .. } => (fc, session_fc, false, false),
             Self::WaitForReset { fc, session_fc, .. } => (fc, session_fc, false, true),
-            Self::SizeKnown { fc, session_fc, .. } | Self::DataRecvd { fc, session_fc, .. } => {
-                (fc, session_fc, true, false)
-            }
+            Self::SizeKnown { fc, session_fc, .. }
+            | Self::DataRecvd { fc, session_fc, .. }
+            | Self::ReliableReset { fc, session_fc, .. } => (fc, session_fc, true, false),
             Self::AbortReading {
                 fc,
                 session_fc,
@@ -567,7 +585,8 @@ impl RecvStream {
         match &self.state {
             RecvStreamState::Recv { recv_buf, .. }
             | RecvStreamState::SizeKnown { recv_buf, .. }
-            | RecvStreamState::DataRecvd { recv_buf, .. } => {
+            | RecvStreamState::DataRecvd { recv_buf, .. }
+            | RecvStreamState::ReliableReset { recv_buf, .. } => {
                 let received = recv_buf.received();
                 let read = recv_buf.retired();
                 Stats::new(received, read)
@@ -655,6 +674,17 @@ impl RecvStream {
                     });
                 }
             }
+            RecvStreamState::ReliableReset {
+                recv_buf,
+                reliable_size,
+                ..
+            } => {
+                // Only keep data that will be delivered.
+                if offset < *reliable_size {
+                    let end = usize::try_from(*reliable_size - offset).unwrap_or(usize::MAX);
+                    recv_buf.inbound_frame(offset, &data[..min(end, data.len())]);
+                }
+            }
             RecvStreamState::DataRecvd { .. }
             | RecvStreamState::DataRead { .. }
             | RecvStreamState::AbortReading { .. }
@@ -685,6 +715,12 @@ impl RecvStream {
                 fc,
                 session_fc,
                 recv_buf,
+            }
+            | RecvStreamState::ReliableReset {
+                fc,
+                session_fc,
+                recv_buf,
+                ..
             } => {
                 // make flow control consumes new data that not really exist.
                 Self::flow_control_retire_data(final_size - fc.retired(), fc, session_fc);
@@ -728,6 +764,72 @@ impl RecvStream {
         Ok(())
     }
 
+    /// Handle a `RESET_STREAM_AT` frame.  Data before `reliable_size` is
+    /// delivered to the application before the reset is reported.
+    ///
+    /// # Errors
+    /// When the reset occurs at an invalid point.
+    pub fn reset_at(
+        &mut self,
+        application_error_code: AppError,
+        final_size: u64,
+        reliable_size: u64,
+    ) -> Res<()> {
+        // The reliable size can only be reduced.
+        let reliable_size = match &self.state {
+            RecvStreamState::ReliableReset {
+                reliable_size: current,
+                ..
+            } => min(*current, reliable_size),
+            _ => reliable_size,
+        };
+        if self
+            .state
+            .recv_buf()
+            .is_none_or(|recv_buf| recv_buf.retired() >= reliable_size)
+        {
+            return self.reset(application_error_code, final_size);
+        }
+
+        self.state.flow_control_consume_data(final_size, true)?;
+        match &mut self.state {
+            RecvStreamState::Recv {
+                fc,
+                session_fc,
+                recv_buf,
+            }
+            | RecvStreamState::SizeKnown {
+                fc,
+                session_fc,
+                recv_buf,
+            } => {
+                recv_buf.truncate(reliable_size);
+                let buf = mem::take(recv_buf);
+                let fc_copy = mem::take(fc);
+                let session_fc_copy = mem::take(session_fc);
+                self.set_state(RecvStreamState::ReliableReset {
+                    fc: fc_copy,
+                    session_fc: session_fc_copy,
+                    recv_buf: buf,
+                    err: application_error_code,
+                    reliable_size,
+                });
+            }
+            RecvStreamState::ReliableReset {
+                recv_buf,
+                reliable_size: current,
+                ..
+            } => {
+                recv_buf.truncate(reliable_size);
+                *current = reliable_size;
+            }
+            _ => {
+                // Ignore reset if in DataRecvd, DataRead, or ResetRecvd
+            }
+        }
+        Ok(())
+    }
+
     fn flow_control_retire_data(
         new_read: u64,
         fc: &mut ReceiverFlowControl<StreamId>,
@@ -762,6 +864,29 @@ impl RecvStream {
         )
     }
 
+    /// Whether [`RecvStream::read`] has something to report: data, the end of
+    /// the stream, or that the peer reset it.
+    #[must_use]
+    pub fn readable(&self) -> bool {
+        self.data_ready()
+            || matches!(
+                self.state,
+                RecvStreamState::DataRecvd { .. } | RecvStreamState::ResetRecvd { .. }
+            )
+    }
+
+    /// The final size of the stream, once the peer has sent it with a FIN.
+    #[must_use]
+    pub const fn final_size(&self) -> Option<u64> {
+        match &self.state {
+            RecvStreamState::SizeKnown { fc, .. } | RecvStreamState::DataRecvd { fc, .. } => {
+                Some(fc.consumed())
+            }
+            RecvStreamState::DataRead { final_received, .. } => Some(*final_received),
+            _ => None,
+        }
+    }
+
     // App got all data but did not get the fin signal.
     const fn needs_to_inform_app_about_fin(&self) -> bool {
         matches!(self.state, RecvStreamState::DataRecvd { .. })
@@ -813,6 +938,28 @@ impl RecvStream {
                 };
                 Ok((bytes_read, fin_read))
             }
+            RecvStreamState::ReliableReset {
+                recv_buf,
+                fc,
+                session_fc,
+                err,
+                reliable_size,
+            } => {
+                let bytes_read = recv_buf.read(buf);
+                Self::flow_control_retire_data(u64::try_from(bytes_read)?, fc, session_fc);
+                if recv_buf.retired() >= *reliable_size {
+                    // All the reliable data was read, so report the reset.
+                    Self::flow_control_retire_data(fc.consumed() - fc.retired(), fc, session_fc);
+                    self.conn_events.recv_stream_reset(self.stream_id, *err);
+                    let received = recv_buf.received();
+                    let read = recv_buf.retired();
+                    self.set_state(RecvStreamState::ResetRecvd {
+                        final_received: received,
+                        final_read: read,
+                    });
+                }
+                Ok((bytes_read, false))
+            }
             RecvStreamState::DataRead { .. }
             | RecvStreamState::AbortReading { .. }
             | RecvStreamState::WaitForReset { .. }
@@ -862,6 +1009,21 @@ impl RecvStream {
                     final_read: read,
                 });
             }
+            RecvStreamState::ReliableReset {
+                fc,
+                session_fc,
+                recv_buf,
+                ..
+            } => {
+                // The peer has already reset the stream, so STOP_SENDING isn't needed.
+                Self::flow_control_retire_data(fc.consumed() - fc.retired(), fc, session_fc);
+                let received = recv_buf.received();
+                let read = recv_buf.retired();
+                self.set_state(RecvStreamState::ResetRecvd {
+                    final_received: received,
+                    final_read: read,
+                });
+            }
             RecvStreamState::DataRead { .. }
             | RecvStreamState::AbortReading { .. }
             | RecvStreamState::WaitForReset { .. }
@@ -2210,6 +2372,28 @@ mod tests {
         check_fc(s.fc().unwrap(), SW / 2 + 20, SW / 2 + 20);
     }
 
+    #[test]
+    fn reset_at_delivers_reliable_data() {
+        const SW: u64 = 1024;
+        let fc = Rc::new(RefCell::new(ReceiverFlowControl::new((), SW)));
+        let mut s = create_stream_with_fc(Rc::clone(&fc), SW);
+
+        s.inbound_stream_frame(false, 0, &[1; 100]).unwrap();
+        s.reset_at(7, 200, 50).unwrap();
+        check_fc(&fc.borrow(), 200, 0);
+
+        // A larger reliable size doesn't bring the discarded data back.
+        s.reset_at(7, 200, 150).unwrap();
+        // Data after the reliable size is ignored.
+        s.inbound_stream_frame(false, 100, &[2; 100]).unwrap();
+        assert!(!s.is_terminal());
+
+        let mut buf = [0; 200];
+        assert_eq!(s.read(&mut buf).unwrap(), (50, false));
+        assert!(s.is_terminal());
+        check_fc(&fc.borrow(), 200, 200);
+    }
+
     /// Test flow control in `RecvStreamState::WaitForReset`
     #[test]
     fn fc_state_wait_for_reset() {
@@ -600,6 +600,12 @@ pub enum State {
     ResetSent {
         err: AppError,
         final_size: u64,
+        /// For `RESET_STREAM_AT`, data before this offset is still delivered.
+        reliable_size: u64,
+        /// The data that is still to be delivered, if `reliable_size` is non-zero.
+        send_buf: Option<TxBuffer>,
+        /// Whether the peer acknowledged the reset, while data is still outstanding.
+        reset_acked: bool,
         priority: Option<TransmissionPriority>,
         final_retired: u64,
         final_written: u64,
@@ -613,7 +619,12 @@ pub enum State {
 impl State {
     const fn tx_buf_mut(&mut self) -> Option<&mut TxBuffer> {
         match self {
-            Self::Send { send_buf, .. } | Self::DataSent { send_buf, .. } => Some(send_buf),
+            Self::Send { send_buf, .. }
+            | Self::DataSent { send_buf, .. }
+            | Self::ResetSent {
+                send_buf: Some(send_buf),
+                ..
+            } => Some(send_buf),
             Self::Ready { .. }
             | Self::DataRecvd { .. }
             | Self::ResetSent { .. }
@@ -732,7 +743,7 @@ impl SendStream {
         tokens: &mut recovery::Tokens,
         stats: &mut FrameStats,
     ) -> bool {
-        if !self.write_reset_frame(priority, builder, tokens, stats) {
+        if !self.write_reset_frame(priority, builder, tokens, stats) || self.is_reset_reliable() {
             self.write_blocked_frame(priority, builder, tokens, stats);
             if builder.is_full() {
                 return false;
@@ -818,7 +829,12 @@ impl SendStream {
     #[must_use]
     pub const fn bytes_acked(&self) -> u64 {
         match &self.state {
-            State::Send { send_buf, .. } | State::DataSent { send_buf, .. } => send_buf.retired(),
+            State::Send { send_buf, .. }
+            | State::DataSent { send_buf, .. }
+            | State::ResetSent {
+                send_buf: Some(send_buf),
+                ..
+            } => send_buf.retired(),
             State::DataRecvd { retired, .. } => *retired,
             State::ResetSent { final_retired, .. } | State::ResetRecvd { final_retired, .. } => {
                 *final_retired
@@ -868,6 +884,16 @@ impl SendStream {
                     Some((used, &[]))
                 }
             }
+            State::ResetSent {
+                send_buf: Some(ref mut send_buf),
+                reliable_size,
+                ..
+            } => {
+                // Only send data that is covered by the reliable size.
+                let (offset, slice) = send_buf.next_bytes()?;
+                let delta = usize::try_from(reliable_size.checked_sub(offset)?).ok()?;
+                (delta > 0).then(|| (offset, &slice[..min(delta, slice.len())]))
+            }
             State::Ready { .. }
             | State::DataRecvd { .. }
             | State::ResetSent { .. }
@@ -919,7 +945,8 @@ impl SendStream {
         };
 
         let id = self.stream_id;
-        let final_size = self.final_size();
+        // After a reset, the final size is carried in `RESET_STREAM_AT`.
+        let final_size = self.final_size().filter(|_| !self.is_reset_reliable());
         if let Some((offset, data)) = self.next_bytes(retransmission) {
             let overhead = 1 // Frame type
                 + Encoder::varint_len(id.as_u64())
@@ -981,14 +1008,54 @@ impl SendStream {
                 qtrace!("[{self}] Reset acked while in {:?} state?", self.state);
             }
             State::ResetSent {
-                final_retired,
-                final_written,
+                ref mut reset_acked,
+                ..
+            } => {
+                *reset_acked = true;
+                self.maybe_reset_recvd();
+            }
+            State::ResetRecvd { .. } => qtrace!("[{self}] already in ResetRecvd state"),
+        }
+    }
+
+    /// Whether this stream was reset with `RESET_STREAM_AT` and some data
+    /// still needs to be delivered.
+    const fn is_reset_reliable(&self) -> bool {
+        matches!(
+            self.state,
+            State::ResetSent {
+                send_buf: Some(_),
                 ..
-            } => self.state.transition(State::ResetRecvd {
+            }
+        )
+    }
+
+    /// Move from `ResetSent` to `ResetRecvd` once the reset is acknowledged
+    /// and all data before the reliable size is acknowledged.
+    fn maybe_reset_recvd(&mut self) {
+        if let State::ResetSent {
+            reliable_size,
+            send_buf,
+            reset_acked: true,
+            final_retired,
+            final_written,
+            ..
+        } = &self.state
+        {
+            let (reliable_size, final_retired, final_written) =
+                (*reliable_size, *final_retired, *final_written);
+            let (final_retired, final_written) = match send_buf {
+                Some(send_buf) if send_buf.retired() < reliable_size => return,
+                Some(send_buf) => (
+                    send_buf.retired(),
+                    final_retired + final_written - send_buf.retired(),
+                ),
+                None => (final_retired, final_written),
+            };
+            self.state.transition(State::ResetRecvd {
                 final_retired,
                 final_written,
-            }),
-            State::ResetRecvd { .. } => qtrace!("[{self}] already in ResetRecvd state"),
+            });
         }
     }
 
@@ -1015,6 +1082,7 @@ impl SendStream {
         if let State::ResetSent {
             final_size,
             err,
+            reliable_size,
             ref mut priority,
             ..
         } = self.state
@@ -1022,16 +1090,31 @@ impl SendStream {
             if *priority != Some(p) {
                 return false;
             }
-            if builder.write_varint_frame(&[
-                FrameType::ResetStream.into(),
-                self.stream_id.as_u64(),
-                err,
-                final_size,
-            ]) {
+            let written = if reliable_size > 0 {
+                builder.write_varint_frame(&[
+                    FrameType::ResetStreamAt.into(),
+                    self.stream_id.as_u64(),
+                    err,
+                    final_size,
+                    reliable_size,
+                ])
+            } else {
+                builder.write_varint_frame(&[
+                    FrameType::ResetStream.into(),
+                    self.stream_id.as_u64(),
+                    err,
+                    final_size,
+                ])
+            };
+            if written {
                 tokens.push(recovery::Token::Stream(StreamRecoveryToken::ResetStream {
                     stream_id: self.stream_id,
                 }));
-                stats.reset_stream += 1;
+                if reliable_size > 0 {
+                    stats.reset_stream_at += 1;
+                } else {
+                    stats.reset_stream += 1;
+                }
                 *priority = None;
                 true
             } else {
@@ -1121,6 +1204,13 @@ impl SendStream {
                     });
                 }
             }
+            State::ResetSent {
+                send_buf: Some(ref mut send_buf),
+                ..
+            } => {
+                send_buf.mark_as_acked(offset, len);
+                self.maybe_reset_recvd();
+            }
             _ => qtrace!("[{self}] mark_as_acked called from state {:?}", self.state),
         }
     }
@@ -1303,6 +1393,9 @@ impl SendStream {
                 self.state.transition(State::ResetSent {
                     err,
                     final_size,
+                    reliable_size: 0,
+                    send_buf: None,
+                    reset_acked: false,
                     priority: Some(self.priority),
                     final_retired: 0,
                     final_written: 0,
@@ -1315,6 +1408,9 @@ impl SendStream {
                 self.state.transition(State::ResetSent {
                     err,
                     final_size,
+                    reliable_size: 0,
+                    send_buf: None,
+                    reset_acked: false,
                     priority: Some(self.priority),
                     final_retired,
                     final_written: buffered,
@@ -1327,6 +1423,9 @@ impl SendStream {
                 self.state.transition(State::ResetSent {
                     err,
                     final_size,
+                    reliable_size: 0,
+                    send_buf: None,
+                    reset_acked: false,
                     priority: Some(self.priority),
                     final_retired,
                     final_written: buffered,
@@ -1338,6 +1437,65 @@ impl SendStream {
         }
     }
 
+    /// Reset the stream using `RESET_STREAM_AT`, which ensures that the first
+    /// `reliable_size` bytes of the stream are still delivered to the peer.
+    /// If the stream was already reset this way, the reliable size can be reduced.
+    ///
+    /// # Errors
+    /// `InvalidInput` if `reliable_size` is more than was written to the stream.
+    pub fn reset_at(&mut self, err: AppError, reliable_size: u64) -> Res<()> {
+        if reliable_size == 0 {
+            self.reset(err);
+            return Ok(());
+        }
+        if let State::ResetSent {
+            reliable_size: ref mut current,
+            ref mut send_buf,
+            ref mut reset_acked,
+            ref mut priority,
+            ..
+        } = self.state
+        {
+            // The reliable size can only be reduced.
+            if reliable_size < *current {
+                *current = reliable_size;
+                *reset_acked = false;
+                *priority = Some(self.priority);
+            } else if send_buf.is_none() {
+                qtrace!("[{self}] already in ResetSent state");
+            }
+            return Ok(());
+        }
+        if reliable_size > self.bytes_written() {
+            return Err(Error::InvalidInput);
+        }
+        let (final_size, send_buf) = match &mut self.state {
+            State::Send { fc, send_buf, .. } => {
+                (fc.used(), mem::replace(send_buf, TxBuffer::new()))
+            }
+            State::DataSent { send_buf, .. } => {
+                (send_buf.used(), mem::replace(send_buf, TxBuffer::new()))
+            }
+            _ => {
+                qtrace!("[{self}] reset_at ignored in state {:?}", self.state);
+                return Ok(());
+            }
+        };
+        let final_retired = send_buf.retired();
+        let buffered = u64::try_from(send_buf.buffered())?;
+        self.state.transition(State::ResetSent {
+            err,
+            final_size,
+            reliable_size,
+            send_buf: Some(send_buf),
+            reset_acked: false,
+            priority: Some(self.priority),
+            final_retired,
+            final_written: buffered,
+        });
+        Ok(())
+    }
+
     #[cfg(test)]
     pub(crate) const fn state(&mut self) -> &mut State {
         &mut self.state
@@ -2910,6 +3068,64 @@ mod tests {
         assert_eq!(stats.stream, 0);
     }
 
+    #[test]
+    fn reset_at_retransmit() {
+        let conn_fc = connection_fc(MAX_VARINT);
+        let mut s = SendStream::new(
+            StreamId::new(2),
+            MAX_VARINT,
+            conn_fc,
+            ConnectionEvents::default(),
+        );
+        _ = s.send(&[0x61; 100]).unwrap();
+        s.mark_as_sent(0, 100, false);
+        s.reset_at(7, 50).unwrap();
+
+        let write = |s: &mut SendStream| {
+            let mut builder =
+                packet::Builder::short(Encoder::default(), false, None::<&[u8]>, packet::LIMIT);
+            let mut tokens = recovery::Tokens::new();
+            let mut stats = FrameStats::default();
+            s.write_frames(
+                TransmissionPriority::default(),
+                &mut builder,
+                &mut tokens,
+                &mut stats,
+            );
+            (tokens, stats)
+        };
+
+        // Only the reset is sent, as all the data has already been sent.
+        let (_, stats) = write(&mut s);
+        assert_eq!(stats.reset_stream_at, 1);
+        assert_eq!(stats.stream, 0);
+
+        // Only data before the reliable size is retransmitted.
+        s.mark_as_lost(0, 100, false);
+        let (tokens, stats) = write(&mut s);
+        assert_eq!(stats.reset_stream_at, 0);
+        assert_eq!(stats.stream, 1);
+        assert!(matches!(
+            tokens.first(),
+            Some(recovery::Token::Stream(StreamRecoveryToken::Stream(
+                RecoveryToken {
+                    offset: 0,
+                    length: 50,
+                    fin: false,
+                    ..
+                }
+            )))
+        ));
+
+        // The stream is done when both the reset and the reliable data are acknowledged.
+        s.reset_acked();
+        assert!(!s.is_terminal());
+        s.mark_as_acked(0, 50, false);
+        assert!(s.is_terminal());
+        assert_eq!(s.bytes_acked(), 50);
+        assert_eq!(s.bytes_written(), 100);
+    }
+
     /// Create a `SendStream` and force it into a state where it believes that
     /// `offset` bytes have already been sent and acknowledged.
     fn stream_with_sent(stream: u64, offset: usize) -> SendStream {
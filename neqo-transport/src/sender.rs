@@ -12,7 +12,10 @@ use neqo_common::{qdebug, qlog::Qlog};
 
 use crate::{
     ConnectionParameters, Stats,
-    cc::{ClassicCongestionControl, CongestionControl, CongestionControlAlgorithm, Cubic, NewReno},
+    cc::{
+        Bbr, ClassicCongestionControl, CongestionControl, CongestionControlAlgorithm, Cubic,
+        Custom, NewReno,
+    },
     pace::Pacer,
     pmtud::Pmtud,
     recovery::sent,
@@ -34,13 +37,18 @@ impl PacketSender {
     pub fn new(conn_params: &ConnectionParameters, pmtud: Pmtud, now: Instant) -> Self {
         let mtu = pmtud.plpmtu();
         Self {
-            cc: match conn_params.get_cc_algorithm() {
-                CongestionControlAlgorithm::NewReno => {
+            cc: match (
+                conn_params.get_congestion_controller(),
+                conn_params.get_cc_algorithm(),
+            ) {
+                (Some(factory), _) => Box::new(Custom::new(factory, pmtud)),
+                (None, CongestionControlAlgorithm::NewReno) => {
                     Box::new(ClassicCongestionControl::new(NewReno::default(), pmtud))
                 }
-                CongestionControlAlgorithm::Cubic => {
+                (None, CongestionControlAlgorithm::Cubic) => {
                     Box::new(ClassicCongestionControl::new(Cubic::default(), pmtud))
                 }
+                (None, CongestionControlAlgorithm::Bbr) => Box::new(Bbr::new(pmtud)),
             },
             pacer: Pacer::new(
                 conn_params.pacing_enabled(),
@@ -52,6 +60,7 @@ impl PacketSender {
     }
 
     pub fn set_qlog(&mut self, qlog: Qlog) {
+        self.pmtud_mut().set_qlog(qlog.clone());
         self.cc.set_qlog(qlog);
     }
 
@@ -149,15 +158,24 @@ impl PacketSender {
     }
 
     pub fn on_packet_sent(&mut self, pkt: &sent::Packet, rtt: Duration, now: Instant) {
-        self.pacer
-            .spend(pkt.time_sent(), rtt, self.cc.cwnd(), pkt.len());
+        if let Some(rate) = self.cc.pacing_rate() {
+            self.pacer.spend_at_rate(pkt.time_sent(), rate, pkt.len());
+        } else {
+            self.pacer
+                .spend(pkt.time_sent(), rtt, self.cc.cwnd(), pkt.len());
+        }
         self.cc.on_packet_sent(pkt, now);
     }
 
     #[must_use]
     pub fn next_paced(&self, rtt: Duration) -> Option<Instant> {
         // Only pace if there are bytes in flight.
-        (self.cc.bytes_in_flight() > 0).then(|| self.pacer.next(rtt, self.cc.cwnd()))
+        (self.cc.bytes_in_flight() > 0).then(|| {
+            self.cc.pacing_rate().map_or_else(
+                || self.pacer.next(rtt, self.cc.cwnd()),
+                |rate| self.pacer.next_at_rate(rate),
+            )
+        })
     }
 
     #[must_use]
@@ -19,24 +19,30 @@ use std::{
 };
 
 use neqo_common::{
-    Datagram, Role, Tos, event::Provider as _, hex, qdebug, qerror, qinfo, qlog::Qlog, qtrace,
-    qwarn,
+    Datagram, Encoder, Role, Tos, event::Provider as _, hex, qdebug, qerror, qinfo, qlog::Qlog,
+    qtrace, qwarn,
 };
 use neqo_crypto::{
-    AntiReplay, Cipher, PrivateKey, PublicKey, ZeroRttCheckResult, ZeroRttChecker,
+    AntiReplay, Cipher, KeyLog, PrivateKey, PublicKey, ZeroRttCheckResult, ZeroRttChecker,
     encode_ech_config,
 };
 use rustc_hash::FxHashSet as HashSet;
 
-pub use crate::addr_valid::ValidateAddress;
 use crate::{
-    ConnectionParameters, OutputBatch, Res, Version,
+    ConnectionParameters, Error, OutputBatch, Res, Version,
     addr_valid::{AddressValidation, AddressValidationResult},
+    admission::{Admission, Admitter},
     cid::{ConnectionId, ConnectionIdGenerator, ConnectionIdRef},
     connection::{Connection, Output, State},
+    crypto::{CryptoDxDirection, CryptoDxState},
+    frame::{FrameEncoder as _, FrameType},
     packet::{self, MIN_INITIAL_PACKET_SIZE, Public},
     saved::SavedDatagram,
 };
+pub use crate::{
+    addr_valid::ValidateAddress,
+    admission::{AdmissionControl, AdmissionStats, RateLimit},
+};
 
 /// A `ServerZeroRttChecker` is a simple wrapper around a single checker.
 /// It uses `RefCell` so that the wrapped checker can be shared between
@@ -119,8 +125,13 @@ pub struct Server {
     connections: Vec<Rc<RefCell<Connection>>>,
     /// Address validation logic, which determines whether we send a Retry.
     address_validation: Rc<RefCell<AddressValidation>>,
+    /// Admission control, which determines whether new connections are
+    /// refused or need a Retry.
+    admission: Admitter,
     /// Directory to create qlog traces in
     qlog_dir: Option<PathBuf>,
+    /// Where to write the TLS secrets of new connections.
+    keylog: Option<KeyLog>,
     /// Encrypted client hello (ECH) configuration.
     ech_config: Option<EchConfig>,
     /// Remaining datagrams of a batch of datagrams provided via
@@ -164,7 +175,9 @@ impl Server {
             conn_params,
             connections: Vec::new(),
             address_validation: Rc::new(RefCell::new(validation)),
+            admission: Admitter::default(),
             qlog_dir: None,
+            keylog: None,
             ech_config: None,
             saved_datagrams: VecDeque::new(),
         })
@@ -175,11 +188,27 @@ impl Server {
         self.qlog_dir = dir;
     }
 
+    /// Set or clear the key log that new connections write their TLS secrets to.
+    pub fn set_keylog(&mut self, keylog: Option<KeyLog>) {
+        self.keylog = keylog;
+    }
+
     /// Set the policy for address validation.
     pub fn set_validation(&self, v: ValidateAddress) {
         self.address_validation.borrow_mut().set_validation(v);
     }
 
+    /// Set limits on the new connections that are accepted.
+    pub fn set_admission_control(&mut self, control: AdmissionControl) {
+        self.admission.set_control(control);
+    }
+
+    /// Counts of the connection attempts that admission control turned away.
+    #[must_use]
+    pub const fn admission_stats(&self) -> AdmissionStats {
+        self.admission.stats()
+    }
+
     /// Set the cipher suites that should be used.  Set an empty value to use
     /// default values.
     pub fn set_ciphers<A: AsRef<[Cipher]>>(&mut self, ciphers: A) {
@@ -234,18 +263,35 @@ impl Server {
         qdebug!("[{self}] Handle initial");
         #[cfg(feature = "build-fuzzing-corpus")]
         Self::write_addr_valid_corpus(dgram.source(), &initial.token);
-        let res = self
-            .address_validation
-            .borrow()
-            .validate(&initial.token, dgram.source(), now);
+        let active = self.connections.len();
+        if self.admission.full(active) {
+            return self.refuse_connection(&initial, &dgram);
+        }
+        let under_load = self.admission.under_load(active);
+        let res = {
+            let validation = self.address_validation.borrow();
+            if under_load {
+                validation.validate_under_load(&initial.token, dgram.source(), now)
+            } else {
+                validation.validate(&initial.token, dgram.source(), now)
+            }
+        };
         match res {
             AddressValidationResult::Invalid => Output::None,
+            AddressValidationResult::Pass | AddressValidationResult::ValidRetry(_)
+                if self.admission.admit(dgram.source(), now) == Admission::Refuse =>
+            {
+                self.refuse_connection(&initial, &dgram)
+            }
             AddressValidationResult::Pass => self.accept_connection(initial, dgram, None, now),
             AddressValidationResult::ValidRetry(orig_dcid) => {
                 self.accept_connection(initial, dgram, Some(orig_dcid), now)
             }
             AddressValidationResult::Validate => {
                 qinfo!("[{self}] Send retry for {:?}", initial.dst_cid);
+                if under_load {
+                    self.admission.retried();
+                }
 
                 let res = self.address_validation.borrow().generate_retry_token(
                     &initial.dst_cid,
@@ -295,6 +341,56 @@ impl Server {
         }
     }
 
+    /// Close a connection with `CONNECTION_REFUSED` before it starts.  This
+    /// sends an Initial packet with just a `CONNECTION_CLOSE` frame and
+    /// creates no state.
+    fn refuse_connection(
+        &self,
+        initial: &InitialDetails,
+        dgram: &Datagram<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Output {
+        qinfo!("[{self}] Refuse connection {:?}", initial.dst_cid);
+        let packet = CryptoDxState::new_initial(
+            initial.version,
+            CryptoDxDirection::Write,
+            "server in",
+            &initial.dst_cid,
+            0,
+        )
+        .and_then(|mut crypto| {
+            let mut builder = packet::Builder::long(
+                Encoder::default(),
+                packet::Type::Initial,
+                initial.version,
+                Some(&initial.src_cid),
+                Some(&initial.dst_cid),
+                MIN_INITIAL_PACKET_SIZE,
+            );
+            builder.initial_token(&[]);
+            builder.pn(0, 1);
+            builder.encode_frame(FrameType::ConnectionCloseTransport, |b| {
+                b.encode_varint(Error::ConnectionRefused.code());
+                b.encode_varint(0u64);
+                b.encode_vvec(&[]);
+            });
+            builder.build(&mut crypto)
+        });
+        packet.map_or_else(
+            |_| {
+                qerror!("[{self}] unable to encode refusal, dropping packet");
+                Output::None
+            },
+            |p| {
+                Output::Datagram(Datagram::new(
+                    dgram.destination(),
+                    dgram.source(),
+                    Tos::default(),
+                    Vec::from(p),
+                ))
+            },
+        )
+    }
+
     fn create_qlog_trace(&self, odcid: ConnectionIdRef<'_>, now: Instant) -> Qlog {
         self.qlog_dir
             .as_ref()
@@ -331,6 +427,9 @@ impl Server {
         }
         c.set_validation(&self.address_validation);
         c.set_qlog(self.create_qlog_trace(orig_dcid.unwrap_or(initial.dst_cid).as_cid_ref(), now));
+        if let Some(keylog) = &self.keylog {
+            c.set_keylog(keylog.clone());
+        }
         if let Some(cfg) = &self.ech_config
             && c.server_enable_ech(cfg.config, &cfg.public_name, &cfg.sk, &cfg.pk)
                 .is_err()
@@ -371,7 +470,7 @@ impl Server {
             }
             Err(e) => {
                 qwarn!("[{self}] Unable to create connection");
-                if e == crate::Error::VersionNegotiation {
+                if e == Error::VersionNegotiation {
                     crate::qlog::server_version_information_failed(
                         &mut self.create_qlog_trace(
                             orig_dcid.unwrap_or(initial.dst_cid).as_cid_ref(),
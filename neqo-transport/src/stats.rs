@@ -28,6 +28,7 @@ pub struct FrameStats {
     pub crypto: usize,
     pub stream: usize,
     pub reset_stream: usize,
+    pub reset_stream_at: usize,
     pub stop_sending: usize,
 
     pub ping: usize,
@@ -52,6 +53,10 @@ pub struct FrameStats {
 
     pub ack_frequency: usize,
     pub datagram: usize,
+
+    pub path_ack: usize,
+    pub path_abandon: usize,
+    pub path_status: usize,
 }
 
 impl Debug for FrameStats {
@@ -68,8 +73,8 @@ impl Debug for FrameStats {
         )?;
         writeln!(
             f,
-            "    stream {} reset {} stop {}",
-            self.stream, self.reset_stream, self.stop_sending,
+            "    stream {} reset {} reset_at {} stop {}",
+            self.stream, self.reset_stream, self.reset_stream_at, self.stop_sending,
         )?;
         writeln!(
             f,
@@ -90,7 +95,12 @@ impl Debug for FrameStats {
             self.path_challenge,
             self.path_response,
         )?;
-        writeln!(f, "    ack_frequency {}", self.ack_frequency)
+        writeln!(f, "    ack_frequency {}", self.ack_frequency)?;
+        writeln!(
+            f,
+            "    path: ack {} abandon {} status {}",
+            self.path_ack, self.path_abandon, self.path_status,
+        )
     }
 }
 
@@ -101,6 +111,7 @@ impl FrameStats {
             + self.crypto
             + self.stream
             + self.reset_stream
+            + self.reset_stream_at
             + self.stop_sending
             + self.ping
             + self.padding
@@ -119,6 +130,9 @@ impl FrameStats {
             + self.new_token
             + self.ack_frequency
             + self.datagram
+            + self.path_ack
+            + self.path_abandon
+            + self.path_status
     }
 }
 
@@ -333,6 +347,9 @@ pub struct Stats {
 
     /// Counters for DSCP values received.
     pub dscp_rx: DscpCount,
+
+    /// The number of times that the latency spin bit value that is sent changed.
+    pub spin_edges: usize,
 }
 
 impl Stats {
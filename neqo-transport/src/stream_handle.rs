@@ -0,0 +1,341 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Owned handles for the sending and receiving parts of a stream.
+
+use std::{cell::RefCell, collections::VecDeque, io, rc::Rc};
+
+use crate::{AppError, Connection, Error, Res, StreamId};
+
+/// Why a stream handle could not complete an operation.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum StreamError {
+    #[error("stream reset by the peer with error {0}")]
+    Reset(AppError),
+    #[error("peer sent STOP_SENDING with error {0}")]
+    StopSending(AppError),
+    #[error(transparent)]
+    Transport(#[from] Error),
+}
+
+impl From<StreamError> for io::Error {
+    fn from(err: StreamError) -> Self {
+        match err {
+            StreamError::Reset(_) => Self::new(io::ErrorKind::ConnectionReset, err),
+            StreamError::StopSending(_) => Self::new(io::ErrorKind::BrokenPipe, err),
+            StreamError::Transport(_) => Self::other(err),
+        }
+    }
+}
+
+/// The sending part of a stream.
+///
+/// Data that the connection can't take yet is buffered in the handle and
+/// passed on by [`SendStreamHandle::flush`].  This needs to be called again
+/// after [`crate::ConnectionEvent::SendStreamWritable`].
+#[derive(Debug)]
+pub struct SendStreamHandle {
+    conn: Rc<RefCell<Connection>>,
+    stream_id: StreamId,
+    buffer: VecDeque<u8>,
+    close_pending: bool,
+    final_size: Option<u64>,
+}
+
+impl SendStreamHandle {
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the stream does not exist or can't be sent on.
+    pub fn new(conn: Rc<RefCell<Connection>>, stream_id: StreamId) -> Res<Self> {
+        {
+            let c = conn.borrow();
+            c.stream_avail_send_space(stream_id)?;
+            c.event_queue().watch_stream(stream_id);
+        }
+        Ok(Self {
+            conn,
+            stream_id,
+            buffer: VecDeque::new(),
+            close_pending: false,
+            final_size: None,
+        })
+    }
+
+    #[must_use]
+    pub const fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    fn check(&self) -> Result<(), StreamError> {
+        let errors = self
+            .conn
+            .borrow()
+            .event_queue()
+            .peer_stream_errors(self.stream_id);
+        errors
+            .stop_sending
+            .map_or(Ok(()), |e| Err(StreamError::StopSending(e)))
+    }
+
+    /// Write all of `data`.  Whatever the connection can't take is buffered.
+    ///
+    /// # Errors
+    ///
+    /// `StopSending` if the peer asked to stop sending, or `FinalSize` if the
+    /// stream was already closed.
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, StreamError> {
+        if self.close_pending || self.final_size.is_some() {
+            return Err(Error::FinalSize.into());
+        }
+        self.check()?;
+        self.buffer.extend(data);
+        self.flush()?;
+        Ok(data.len())
+    }
+
+    /// Pass buffered data to the connection.  Returns `true` when nothing is
+    /// left in the buffer.
+    ///
+    /// # Errors
+    ///
+    /// `StopSending` if the peer asked to stop sending, or a transport error
+    /// if the stream is gone.
+    pub fn flush(&mut self) -> Result<bool, StreamError> {
+        self.check()?;
+        let mut conn = self.conn.borrow_mut();
+        while !self.buffer.is_empty() {
+            let sent = conn.stream_send(self.stream_id, self.buffer.as_slices().0)?;
+            if sent == 0 {
+                break;
+            }
+            self.buffer.drain(..sent);
+        }
+        if self.close_pending && self.buffer.is_empty() {
+            conn.stream_close_send(self.stream_id)?;
+            self.close_pending = false;
+            self.final_size = conn.send_stream_final_size(self.stream_id)?;
+        }
+        Ok(self.buffer.is_empty())
+    }
+
+    /// The number of bytes that are waiting in the handle.
+    #[must_use]
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Whether the connection can take more data right now.
+    #[must_use]
+    pub fn is_writable(&self) -> bool {
+        let conn = self.conn.borrow();
+        conn.event_queue()
+            .peer_stream_errors(self.stream_id)
+            .stop_sending
+            .is_none()
+            && conn
+                .stream_avail_send_space(self.stream_id)
+                .is_ok_and(|space| space > 0)
+    }
+
+    /// End the stream once the buffered data is passed to the connection.
+    ///
+    /// # Errors
+    ///
+    /// As for [`SendStreamHandle::flush`].
+    pub fn close(&mut self) -> Result<(), StreamError> {
+        if self.final_size.is_none() {
+            self.close_pending = true;
+        }
+        self.flush().map(|_| ())
+    }
+
+    /// Abandon the stream, discarding any buffered data.
+    ///
+    /// # Errors
+    ///
+    /// When the stream is gone.
+    pub fn reset(&mut self, err: AppError) -> Result<(), StreamError> {
+        self.buffer.clear();
+        self.close_pending = false;
+        let mut conn = self.conn.borrow_mut();
+        conn.stream_reset_send(self.stream_id, err)?;
+        self.final_size = conn.send_stream_final_size(self.stream_id)?;
+        Ok(())
+    }
+
+    /// The final size of the stream, once it is closed and all data was
+    /// passed to the connection, or once it is reset.
+    #[must_use]
+    pub const fn final_size(&self) -> Option<u64> {
+        self.final_size
+    }
+}
+
+impl io::Write for SendStreamHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(Self::write(self, buf)?)
+    }
+
+    /// Returns `WouldBlock` while data is still buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        if Self::flush(self)? {
+            Ok(())
+        } else {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+}
+
+impl Drop for SendStreamHandle {
+    fn drop(&mut self) {
+        if let Ok(conn) = self.conn.try_borrow() {
+            conn.event_queue().unwatch_stream(self.stream_id);
+        }
+    }
+}
+
+/// The receiving part of a stream.
+#[derive(Debug)]
+pub struct RecvStreamHandle {
+    conn: Rc<RefCell<Connection>>,
+    stream_id: StreamId,
+    read: u64,
+    fin: bool,
+}
+
+impl RecvStreamHandle {
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the stream does not exist or can't be received on.
+    pub fn new(conn: Rc<RefCell<Connection>>, stream_id: StreamId) -> Res<Self> {
+        {
+            let mut c = conn.borrow_mut();
+            c.recv_stream_stats(stream_id)?;
+            c.event_queue().watch_stream(stream_id);
+        }
+        Ok(Self {
+            conn,
+            stream_id,
+            read: 0,
+            fin: false,
+        })
+    }
+
+    #[must_use]
+    pub const fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Read into `buf`.  The returned flag is set when the end of the stream
+    /// was reached.
+    ///
+    /// # Errors
+    ///
+    /// `Reset` if the peer reset the stream, or a transport error if the
+    /// stream is gone.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<(usize, bool), StreamError> {
+        if self.fin {
+            return Ok((0, true));
+        }
+        let res = self.conn.borrow_mut().stream_recv(self.stream_id, buf);
+        match res {
+            Ok((n, fin)) => {
+                self.read += u64::try_from(n).map_err(|_| Error::Internal)?;
+                self.fin = fin;
+                Ok((n, fin))
+            }
+            Err(e) => Err(self.reset_error().unwrap_or(StreamError::Transport(e))),
+        }
+    }
+
+    /// Read everything that is available onto the end of `out`.  Returns
+    /// `true` when the end of the stream was reached.
+    ///
+    /// # Errors
+    ///
+    /// As for [`RecvStreamHandle::read`].
+    pub fn read_to_end(&mut self, out: &mut Vec<u8>) -> Result<bool, StreamError> {
+        let mut buf = [0; 4096];
+        loop {
+            let (n, fin) = self.read(&mut buf)?;
+            out.extend_from_slice(&buf[..n]);
+            if fin || n == 0 {
+                return Ok(fin);
+            }
+        }
+    }
+
+    fn reset_error(&self) -> Option<StreamError> {
+        self.conn
+            .borrow()
+            .event_queue()
+            .peer_stream_errors(self.stream_id)
+            .reset
+            .map(StreamError::Reset)
+    }
+
+    /// Whether [`RecvStreamHandle::read`] would return data, the end of the
+    /// stream, or an error.
+    #[must_use]
+    pub fn is_readable(&self) -> bool {
+        !self.fin
+            && (self.reset_error().is_some()
+                || self
+                    .conn
+                    .borrow_mut()
+                    .stream_readable(self.stream_id)
+                    .unwrap_or(true))
+    }
+
+    /// Whether all of the data on the stream was read.
+    #[must_use]
+    pub const fn is_finished(&self) -> bool {
+        self.fin
+    }
+
+    /// The final size of the stream, once the peer has ended it.
+    #[must_use]
+    pub fn final_size(&self) -> Option<u64> {
+        if self.fin {
+            return Some(self.read);
+        }
+        self.conn
+            .borrow_mut()
+            .recv_stream_final_size(self.stream_id)
+            .ok()
+            .flatten()
+    }
+
+    /// Ask the peer to stop sending.
+    ///
+    /// # Errors
+    ///
+    /// When the stream is gone.
+    pub fn stop_sending(&self, err: AppError) -> Result<(), StreamError> {
+        Ok(self
+            .conn
+            .borrow_mut()
+            .stream_stop_sending(self.stream_id, err)?)
+    }
+}
+
+impl io::Read for RecvStreamHandle {
+    /// Returns `WouldBlock` when no data is available yet.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match Self::read(self, buf)? {
+            (0, false) if !buf.is_empty() => Err(io::ErrorKind::WouldBlock.into()),
+            (n, _) => Ok(n),
+        }
+    }
+}
+
+impl Drop for RecvStreamHandle {
+    fn drop(&mut self) {
+        if let Ok(conn) = self.conn.try_borrow() {
+            conn.event_queue().unwatch_stream(self.stream_id);
+        }
+    }
+}
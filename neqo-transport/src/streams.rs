@@ -27,7 +27,7 @@ use crate::{
     tparams::{
         TransportParameterId::{
             InitialMaxData, InitialMaxStreamDataBidiLocal, InitialMaxStreamDataBidiRemote,
-            InitialMaxStreamDataUni, InitialMaxStreamsBidi, InitialMaxStreamsUni,
+            InitialMaxStreamDataUni, InitialMaxStreamsBidi, InitialMaxStreamsUni, ResetStreamAt,
         },
         TransportParametersHandler,
     },
@@ -124,6 +124,20 @@ impl Streams {
                     rs.reset(*application_error_code, *final_size)?;
                 }
             }
+            Frame::ResetStreamAt {
+                stream_id,
+                application_error_code,
+                final_size,
+                reliable_size,
+            } => {
+                stats.reset_stream_at += 1;
+                if !self.tps.borrow().local().get_empty(ResetStreamAt) {
+                    return Err(Error::ProtocolViolation);
+                }
+                if let (_, Some(rs)) = self.obtain_stream(*stream_id)? {
+                    rs.reset_at(*application_error_code, *final_size, *reliable_size)?;
+                }
+            }
             Frame::StopSending {
                 stream_id,
                 application_error_code,
@@ -56,6 +56,10 @@ pub enum TransportParameterId {
     GreaseQuicBit = 0x2ab2,
     MinAckDelay = 0xff02_de1a,
     MaxDatagramFrameSize = 0x0020,
+    /// The multipath extension; see draft-ietf-quic-multipath.
+    InitialMaxPathId = 0x0f73_9bbc_1b66_6d0c,
+    /// The reliable stream reset extension; see draft-ietf-quic-reliable-stream-reset.
+    ResetStreamAt = 0x17_f758_6d2c_b571,
     #[cfg(test)]
     TestTransportParameter = 0xce16,
 }
@@ -316,15 +320,19 @@ impl TransportParameter {
                 Some(v) if v >= 2 => Self::Integer(v),
                 _ => return Err(Error::TransportParameter),
             },
-            TransportParameterId::DisableMigration | TransportParameterId::GreaseQuicBit => {
-                Self::Empty
-            }
+            TransportParameterId::DisableMigration
+            | TransportParameterId::GreaseQuicBit
+            | TransportParameterId::ResetStreamAt => Self::Empty,
             TransportParameterId::PreferredAddress => Self::decode_preferred_address(&mut d)?,
             TransportParameterId::MinAckDelay => match d.decode_varint() {
                 Some(v) if v < (1 << 24) => Self::Integer(v),
                 _ => return Err(Error::TransportParameter),
             },
             TransportParameterId::VersionInformation => Self::decode_versions(&mut d)?,
+            TransportParameterId::InitialMaxPathId => match d.decode_varint() {
+                Some(v) if v < (1 << 32) => Self::Integer(v),
+                _ => return Err(Error::TransportParameter),
+            },
             #[cfg(test)]
             TransportParameterId::TestTransportParameter => {
                 Self::Bytes(d.decode_remainder().to_vec())
@@ -415,7 +423,8 @@ impl TransportParameters {
             | TransportParameterId::InitialMaxStreamsBidi
             | TransportParameterId::InitialMaxStreamsUni
             | TransportParameterId::MinAckDelay
-            | TransportParameterId::MaxDatagramFrameSize => 0,
+            | TransportParameterId::MaxDatagramFrameSize
+            | TransportParameterId::InitialMaxPathId => 0,
             TransportParameterId::MaxUdpPayloadSize => 65527,
             TransportParameterId::AckDelayExponent => 3,
             TransportParameterId::MaxAckDelay => DEFAULT_REMOTE_ACK_DELAY
@@ -449,7 +458,8 @@ impl TransportParameters {
             | TransportParameterId::MaxAckDelay
             | TransportParameterId::ActiveConnectionIdLimit
             | TransportParameterId::MinAckDelay
-            | TransportParameterId::MaxDatagramFrameSize => {
+            | TransportParameterId::MaxDatagramFrameSize
+            | TransportParameterId::InitialMaxPathId => {
                 self.set(tp, TransportParameter::Integer(value));
             }
             _ => panic!("Transport parameter not known"),
@@ -493,7 +503,9 @@ impl TransportParameters {
     /// When the transport parameter isn't recognized as being empty.
     pub fn set_empty(&mut self, tp: TransportParameterId) {
         match tp {
-            TransportParameterId::DisableMigration | TransportParameterId::GreaseQuicBit => {
+            TransportParameterId::DisableMigration
+            | TransportParameterId::GreaseQuicBit
+            | TransportParameterId::ResetStreamAt => {
                 self.set(tp, TransportParameter::Empty);
             }
             _ => panic!("Transport parameter not known or not type empty"),
@@ -559,6 +571,7 @@ impl TransportParameters {
                         | TransportParameterId::MaxAckDelay
                         | TransportParameterId::ActiveConnectionIdLimit
                         | TransportParameterId::PreferredAddress
+                        | TransportParameterId::InitialMaxPathId
                 )
             {
                 continue;
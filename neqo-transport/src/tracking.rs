@@ -16,7 +16,7 @@ use std::{
 use enum_map::{Enum, EnumMap};
 use enumset::{EnumSet, EnumSetType};
 use log::{Level, log_enabled};
-use neqo_common::{Buffer, Ecn, MAX_VARINT, qdebug, qtrace, qwarn};
+use neqo_common::{Buffer, Ecn, Encoder, MAX_VARINT, qdebug, qtrace, qwarn};
 use neqo_crypto::Epoch;
 use smallvec::SmallVec;
 use strum::{Display, EnumIter};
@@ -24,6 +24,7 @@ use strum::{Display, EnumIter};
 use crate::{
     Error, Res, Stats, ecn,
     frame::{FrameEncoder as _, FrameType},
+    multipath::PathId,
     packet,
     recovery::{self},
     stats::FrameStats,
@@ -177,6 +178,7 @@ const MAX_ACKS_PER_FRAME: usize = 32;
 #[derive(Debug, Clone)]
 pub struct AckToken {
     space: PacketNumberSpace,
+    path_id: PathId,
     ranges: Box<[PacketRange]>,
 }
 
@@ -185,6 +187,17 @@ impl AckToken {
     pub const fn space(&self) -> PacketNumberSpace {
         self.space
     }
+
+    /// Get the path that the acknowledged packets were received on.
+    /// This is only something other than the initial path for multipath.
+    pub const fn path_id(&self) -> PathId {
+        self.path_id
+    }
+
+    /// Get the ranges that were acknowledged.
+    pub const fn ranges(&self) -> &[PacketRange] {
+        &self.ranges
+    }
 }
 
 /// A structure that tracks what packets have been received,
@@ -192,6 +205,9 @@ impl AckToken {
 #[derive(Debug)]
 pub struct RecvdPackets {
     space: PacketNumberSpace,
+    /// The path that this tracks packets for, which determines whether
+    /// this sends ACK or `PATH_ACK` frames.
+    path_id: PathId,
     ranges: VecDeque<PacketRange>,
     /// The packet number of the lowest number packet that we are tracking.
     min_tracked: packet::Number,
@@ -224,6 +240,7 @@ impl RecvdPackets {
     pub fn new(space: PacketNumberSpace) -> Self {
         Self {
             space,
+            path_id: PathId::INITIAL,
             ranges: VecDeque::new(),
             min_tracked: 0,
             largest_pn_time: None,
@@ -243,6 +260,20 @@ impl RecvdPackets {
         }
     }
 
+    /// Make a new `RecvdPackets` for the application data packet number space
+    /// of a path other than the initial path.
+    pub fn new_for_path(path_id: PathId) -> Self {
+        Self {
+            path_id,
+            ..Self::new(PacketNumberSpace::ApplicationData)
+        }
+    }
+
+    /// The largest packet number that has been received, if any.
+    pub fn largest_pn(&self) -> Option<packet::Number> {
+        self.ranges.front().map(|r| r.largest)
+    }
+
     /// Get the ECN counts.
     pub const fn ecn_marks(&mut self) -> &mut ecn::Count {
         &mut self.ecn_count
@@ -409,6 +440,7 @@ impl RecvdPackets {
     pub const USEFUL_ACK_LEN: usize = 1 + 8 + 8 + 1 + 8 + 3 * 8;
 
     /// Generate an ACK frame for this packet number space.
+    /// For paths other than the initial path, this is a `PATH_ACK` frame.
     ///
     /// Unlike other frame generators this doesn't modify the underlying instance
     /// to track what has been sent. This only clears the delayed ACK timer.
@@ -418,7 +450,7 @@ impl RecvdPackets {
     ///
     /// We don't send ranges that have been acknowledged, but they still need
     /// to be tracked so that duplicates can be detected.
-    fn write_frame<B: Buffer>(
+    pub(crate) fn write_frame<B: Buffer>(
         &mut self,
         now: Instant,
         rtt: Duration,
@@ -437,7 +469,15 @@ impl RecvdPackets {
         // When congestion limited, ACK-only packets are 255 bytes at most
         // (`recovery::ACK_ONLY_SIZE_LIMIT - 1`).  This results in limiting the
         // ranges to 13 here.
-        let max_ranges = if let Some(avail) = builder.remaining().checked_sub(Self::USEFUL_ACK_LEN)
+        let path_overhead = if self.path_id.is_initial() {
+            0
+        } else {
+            // The frame type is four bytes longer, plus the path ID.
+            3 + Encoder::varint_len(self.path_id.as_u64())
+        };
+        let max_ranges = if let Some(avail) = builder
+            .remaining()
+            .checked_sub(Self::USEFUL_ACK_LEN + path_overhead)
         {
             // Apply a hard maximum to keep plenty of space for other stuff.
             min(1 + (avail / 16), MAX_ACKS_PER_FRAME)
@@ -458,8 +498,12 @@ impl RecvdPackets {
 
         let mut iter = ranges.iter();
         let Some(first) = iter.next() else { return };
-        stats.largest_acknowledged = first.largest;
-        stats.ack += 1;
+        if self.path_id.is_initial() {
+            stats.largest_acknowledged = first.largest;
+            stats.ack += 1;
+        } else {
+            stats.path_ack += 1;
+        }
 
         let Some(largest_pn_time) = self.largest_pn_time else {
             return;
@@ -472,34 +516,36 @@ impl RecvdPackets {
             return;
         };
 
-        builder.encode_frame(
-            if self.ecn_count.is_some() {
-                FrameType::AckEcn
-            } else {
-                FrameType::Ack
-            },
-            |b| {
-                b.encode_varint(first.largest);
-                b.encode_varint(ack_delay);
-                b.encode_varint(extra_ranges); // extra ranges
-                b.encode_varint(first.len() - 1); // first range
-
-                let mut last = first.smallest;
-                for r in iter {
-                    // The difference must be at least 2 because 0-length gaps,
-                    // (difference 1) are illegal.
-                    b.encode_varint(last - r.largest - 2); // Gap
-                    b.encode_varint(r.len() - 1); // Range
-                    last = r.smallest;
-                }
+        let frame_type = match (self.path_id.is_initial(), self.ecn_count.is_some()) {
+            (true, false) => FrameType::Ack,
+            (true, true) => FrameType::AckEcn,
+            (false, false) => FrameType::PathAck,
+            (false, true) => FrameType::PathAckEcn,
+        };
+        builder.encode_frame(frame_type, |b| {
+            if !self.path_id.is_initial() {
+                b.encode_varint(self.path_id.as_u64());
+            }
+            b.encode_varint(first.largest);
+            b.encode_varint(ack_delay);
+            b.encode_varint(extra_ranges); // extra ranges
+            b.encode_varint(first.len() - 1); // first range
+
+            let mut last = first.smallest;
+            for r in iter {
+                // The difference must be at least 2 because 0-length gaps,
+                // (difference 1) are illegal.
+                b.encode_varint(last - r.largest - 2); // Gap
+                b.encode_varint(r.len() - 1); // Range
+                last = r.smallest;
+            }
 
-                if self.ecn_count.is_some() {
-                    b.encode_varint(self.ecn_count[Ecn::Ect0]);
-                    b.encode_varint(self.ecn_count[Ecn::Ect1]);
-                    b.encode_varint(self.ecn_count[Ecn::Ce]);
-                }
-            },
-        );
+            if self.ecn_count.is_some() {
+                b.encode_varint(self.ecn_count[Ecn::Ect0]);
+                b.encode_varint(self.ecn_count[Ecn::Ect1]);
+                b.encode_varint(self.ecn_count[Ecn::Ce]);
+            }
+        });
 
         // We've sent an ACK, reset the timer.
         self.ack_time = None;
@@ -508,6 +554,7 @@ impl RecvdPackets {
 
         tokens.push(recovery::Token::Ack(AckToken {
             space: self.space,
+            path_id: self.path_id,
             ranges: ranges.into_boxed_slice(),
         }));
     }
@@ -515,7 +562,11 @@ impl RecvdPackets {
 
 impl Display for RecvdPackets {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "Recvd-{}", self.space)
+        if self.path_id.is_initial() {
+            write!(f, "Recvd-{}", self.space)
+        } else {
+            write!(f, "Recvd-{}-{}", self.space, self.path_id)
+        }
     }
 }
 
@@ -589,6 +640,7 @@ impl AckTracker {
     }
 
     pub fn acked(&mut self, token: &AckToken) {
+        debug_assert!(token.path_id.is_initial());
         if let Some(space) = self.get_mut(token.space) {
             space.acknowledged(&token.ranges);
         }
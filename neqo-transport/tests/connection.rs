@@ -5,16 +5,25 @@
 // except according to those terms.
 
 mod common;
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+    time::Duration,
+};
+
 use common::assert_dscp;
 use neqo_common::{Datagram, Decoder, Encoder, Role};
 use neqo_crypto::AeadTrait as _;
 use neqo_transport::{
-    CloseReason, ConnectionParameters, Error, MIN_INITIAL_PACKET_SIZE, State, StreamType, Version,
+    CloseReason, ConnectionParameters, Error, MIN_INITIAL_PACKET_SIZE, Output, PacketNumberSpace,
+    State, StreamType, Version,
+    recorder::{Capture, Direction, Recorder},
 };
 use test_fixture::{
     CountingConnectionIdGenerator, DEFAULT_ALPN, default_client, default_server,
     header_protection::{self, decode_initial_header, initial_aead_and_hp},
-    new_client, new_server, now, split_datagram,
+    new_client, new_server, now, replay, split_datagram,
 };
 
 #[test]
@@ -392,3 +401,123 @@ fn server_initial_packet_number() {
         );
     }
 }
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn record_and_replay() {
+    const DATA: &[u8] = b"recorded stream data";
+    let mut client = default_client();
+    let mut server = default_server();
+    let buf = SharedBuffer::default();
+    server.set_recorder(Some(
+        Recorder::new(Role::Server, now(), buf.clone()).unwrap(),
+    ));
+    test_fixture::handshake(&mut client, &mut server);
+    assert_eq!(*client.state(), State::Confirmed);
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, DATA).unwrap();
+    client.stream_close_send(stream_id).unwrap();
+    let dgram = client.process_output(now()).dgram();
+    server.process_input(dgram.unwrap(), now());
+    let mut buffer = [0; 64];
+    let (len, fin) = server.stream_recv(stream_id, &mut buffer).unwrap();
+    assert_eq!((&buffer[..len], fin), (DATA, true));
+
+    let capture = Capture::decode(&buf.0.borrow()).unwrap();
+    assert_eq!(capture.role(), Role::Server);
+    assert!(capture.received().count() > 0);
+    assert!(capture.records().len() > capture.received().count());
+    assert!(!capture.keylog().is_empty());
+
+    // The recorded secrets recover every packet that the server read, the
+    // same way every time.  The server drops only the padding after the
+    // client Initial.
+    let decoder = CountingConnectionIdGenerator::default();
+    let packets = capture.decrypt(capture.keylog(), &decoder).unwrap();
+    let stats = server.stats();
+    assert_eq!(packets.len(), stats.packets_rx - stats.dropped_rx);
+    assert_eq!(
+        capture.decrypt(capture.keylog(), &decoder).unwrap(),
+        packets
+    );
+    let last = packets.last().unwrap();
+    assert_eq!(last.space, PacketNumberSpace::ApplicationData);
+    assert!(last.payload.windows(DATA.len()).any(|w| w == DATA));
+
+    // A new server responds to the same client Initial in the same way.
+    let mut replayed = default_server();
+    let sent = replay::first_flight(&mut replayed, &capture, now());
+    let first = capture
+        .records()
+        .iter()
+        .position(|r| r.direction == Direction::Sent)
+        .unwrap();
+    assert_eq!(sent[0].time, capture.records()[first].time);
+    assert_eq!(
+        sent[0].datagram.len(),
+        capture.records()[first].datagram.len()
+    );
+}
+
+/// Replaying a capture that continues past the handshake leaves the new
+/// connection where the first flight and its own timers take it.
+#[test]
+fn replay_past_handshake() {
+    const DATA: &[u8] = b"data after the handshake";
+    let mut client = default_client();
+    let mut server = default_server();
+    let buf = SharedBuffer::default();
+    server.set_recorder(Some(
+        Recorder::new(Role::Server, now(), buf.clone()).unwrap(),
+    ));
+    test_fixture::handshake(&mut client, &mut server);
+    let later = now() + Duration::from_secs(1);
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, DATA).unwrap();
+    let dgram = client.process_output(later).dgram();
+    server.process_input(dgram.unwrap(), later);
+    assert_eq!(*server.state(), State::Confirmed);
+    let capture = Capture::decode(&buf.0.borrow()).unwrap();
+    assert_eq!(capture.records().last().unwrap().time, later - now());
+
+    let mut replayed = default_server();
+    let sent = replay::first_flight(&mut replayed, &capture, now());
+
+    // A server that only sees the first flight, with its timers run to the
+    // end of the capture.
+    let mut reference = default_server();
+    let first = capture.received().next().unwrap();
+    reference.process_input(first.datagram.clone(), now());
+    let mut t = now();
+    let mut expected = Vec::new();
+    loop {
+        match reference.process_output(t) {
+            Output::Datagram(d) => expected.push((t - now(), d.len())),
+            Output::Callback(delay) if t + delay <= later => t += delay,
+            _ => break,
+        }
+    }
+    // The server retransmits on a timer, so the clock moved between records.
+    assert!(expected.len() > 1);
+    assert_eq!(
+        sent.iter()
+            .map(|r| (r.time, r.datagram.len()))
+            .collect::<Vec<_>>(),
+        expected
+    );
+    assert_eq!(*replayed.state(), State::Handshaking);
+    assert_eq!(replayed.state(), reference.state());
+    assert!(replayed.stream_recv(stream_id, &mut [0; 64]).is_err());
+}
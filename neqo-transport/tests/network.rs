@@ -4,15 +4,28 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::{ops::Range, time::Duration};
+use std::{
+    cell::RefCell,
+    net::SocketAddr,
+    ops::Range,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
-use neqo_transport::{CloseReason, ConnectionParameters, Error, State};
+use neqo_common::{Datagram, Ecn};
+use neqo_transport::{
+    CloseReason, CongestionControlAlgorithm, ConnectionParameters, Error, Output, SpinBitConfig,
+    State,
+};
 use test_fixture::{
-    boxed,
+    DEFAULT_ADDR, boxed,
     sim::{
-        Simulator,
+        self, Simulator,
         connection::{Node, ReachState, ReceiveData, SendData},
-        network::{Drop, RandomDelay, TailDrop},
+        network::{
+            Delay, Drop, Duplicate, GilbertElliott, Outage, RandomDelay, Rebind, Reorder, TailDrop,
+            TokenBucket,
+        },
     },
     simulate,
 };
@@ -28,6 +41,30 @@ const fn weeks(m: u32) -> Duration {
     Duration::from_secs((m as u64) * 60 * 60 * 24 * 7)
 }
 
+/// Connection parameters for the BBR tests, otherwise matching `Node::default_client`.
+fn bbr() -> ConnectionParameters {
+    ConnectionParameters::default()
+        .pmtud(true)
+        .mlkem(false)
+        .cc_algorithm(CongestionControlAlgorithm::Bbr)
+}
+
+fn bbr_client(goal: SendData) -> Node {
+    Node::new_client(
+        bbr(),
+        boxed![ReachState::new(State::Confirmed)],
+        boxed![goal],
+    )
+}
+
+fn bbr_server(goal: ReceiveData) -> Node {
+    Node::new_server(
+        bbr(),
+        boxed![ReachState::new(State::Confirmed)],
+        boxed![goal],
+    )
+}
+
 simulate!(
     connect_direct,
     [
@@ -192,6 +229,195 @@ simulate!(
     ],
 );
 
+simulate!(
+    transfer_taildrop_bbr,
+    [
+        bbr_client(SendData::new(TRANSFER_AMOUNT)),
+        TailDrop::dsl_downlink(),
+        bbr_server(ReceiveData::new(TRANSFER_AMOUNT)),
+        TailDrop::dsl_uplink(),
+    ],
+);
+
+simulate!(
+    transfer_taildrop_jitter_bbr,
+    [
+        bbr_client(SendData::new(TRANSFER_AMOUNT)),
+        TailDrop::dsl_downlink(),
+        RandomDelay::new(ZERO..JITTER),
+        bbr_server(ReceiveData::new(TRANSFER_AMOUNT)),
+        TailDrop::dsl_uplink(),
+        RandomDelay::new(ZERO..JITTER),
+    ],
+);
+
+simulate!(
+    transfer_taildrop_ecn_bbr,
+    [
+        bbr_client(SendData::new(TRANSFER_AMOUNT)),
+        TailDrop::new(1_000_000, 65_536, true, Duration::from_millis(50)),
+        bbr_server(ReceiveData::new(TRANSFER_AMOUNT)),
+        TailDrop::new(200_000, 16_384, true, Duration::from_millis(50))
+    ],
+);
+
+simulate!(
+    transfer_random_delay_bbr,
+    [
+        bbr_client(SendData::new(TRANSFER_AMOUNT)),
+        RandomDelay::new(DELAY_RANGE),
+        bbr_server(ReceiveData::new(TRANSFER_AMOUNT)),
+        RandomDelay::new(DELAY_RANGE),
+    ],
+);
+
+simulate!(
+    transfer_delay_drop_bbr,
+    [
+        bbr_client(SendData::new(TRANSFER_AMOUNT)),
+        RandomDelay::new(DELAY_RANGE),
+        Drop::percentage(1),
+        bbr_server(ReceiveData::new(TRANSFER_AMOUNT)),
+        RandomDelay::new(DELAY_RANGE),
+        Drop::percentage(1),
+    ],
+);
+
+simulate!(
+    transfer_reorder,
+    [
+        Node::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        Delay::new(DELAY),
+        Reorder::new(10, 5, JITTER),
+        Node::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        Delay::new(DELAY),
+        Reorder::new(10, 5, JITTER),
+    ],
+);
+
+simulate!(
+    transfer_duplicate,
+    [
+        Node::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        RandomDelay::new(DELAY_RANGE),
+        Duplicate::percentage(5),
+        Node::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        RandomDelay::new(DELAY_RANGE),
+        Duplicate::percentage(5),
+    ],
+);
+
+simulate!(
+    transfer_token_bucket,
+    [
+        Node::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        TokenBucket::new(1_000_000, 32_768),
+        Delay::new(DELAY),
+        Node::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        TokenBucket::new(200_000, 8_192),
+        Delay::new(DELAY),
+    ],
+);
+
+simulate!(
+    transfer_bursty_loss,
+    [
+        Node::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        RandomDelay::new(DELAY_RANGE),
+        GilbertElliott::new(1, 30),
+        Node::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        RandomDelay::new(DELAY_RANGE),
+        GilbertElliott::new(1, 30).loss(1, 80),
+    ],
+);
+
+simulate!(
+    transfer_outage,
+    [
+        Node::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        Delay::new(DELAY),
+        Outage::new(Duration::from_millis(500), Duration::from_secs(2)),
+        Node::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        Delay::new(DELAY),
+        Outage::new(Duration::from_millis(500), Duration::from_secs(2)),
+    ],
+);
+
+/// An on-path observer that records the destination and ECN marking of each
+/// datagram that passes.
+#[derive(Debug, Default)]
+struct Tap {
+    seen: Rc<RefCell<Vec<(SocketAddr, Ecn)>>>,
+}
+
+impl sim::Node for Tap {
+    fn process(&mut self, d: Option<Datagram>, _now: Instant) -> Output {
+        d.map_or(Output::None, |d| {
+            self.seen
+                .borrow_mut()
+                .push((d.destination(), Ecn::from(d.tos())));
+            Output::Datagram(d)
+        })
+    }
+}
+
+/// The client address changes partway through, so the server has to migrate.
+const REBOUND_ADDR: SocketAddr = SocketAddr::new(DEFAULT_ADDR.ip(), 4433);
+
+#[test]
+fn transfer_rebind() {
+    let tap = Tap::default();
+    let seen = Rc::clone(&tap.seen);
+    Simulator::new(
+        "transfer_rebind",
+        boxed![
+            Node::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+            Delay::new(DELAY),
+            Rebind::source(Duration::from_millis(500), DEFAULT_ADDR, REBOUND_ADDR),
+            Node::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+            tap,
+            Delay::new(DELAY),
+            Rebind::destination(Duration::from_millis(500), REBOUND_ADDR, DEFAULT_ADDR),
+        ],
+    )
+    .run();
+
+    // The server first sends to the original address, then moves to the new
+    // one and stays there.
+    let seen = seen.borrow();
+    let moved = seen
+        .iter()
+        .position(|&(dst, _)| dst == REBOUND_ADDR)
+        .expect("server should send to the new client address");
+    assert!(moved > 0);
+    assert!(seen[..moved].iter().all(|&(dst, _)| dst == DEFAULT_ADDR));
+    assert_eq!(seen.last().unwrap().0, REBOUND_ADDR);
+}
+
+#[test]
+fn transfer_taildrop_ce_threshold() {
+    let tap = Tap::default();
+    let seen = Rc::clone(&tap.seen);
+    Simulator::new(
+        "transfer_taildrop_ce_threshold",
+        boxed![
+            Node::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+            TailDrop::dsl_downlink().mark_threshold(16_384),
+            tap,
+            Node::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+            TailDrop::dsl_uplink().mark_threshold(4_096),
+        ],
+    )
+    .run();
+
+    // The queue fills past the threshold, so some of what the client sends is
+    // marked, but not all of it.
+    let seen = seen.borrow();
+    let marked = seen.iter().filter(|&&(_, ecn)| ecn == Ecn::Ce).count();
+    assert!(marked > 0, "no CE marks");
+    assert!(marked < seen.len() / 2, "{marked} of {} marked", seen.len());
+}
+
 /// This test is a nasty piece of work.  Delays are anything from 0 to 50ms and 1% of
 /// packets get dropped.
 #[test]
@@ -210,3 +436,100 @@ fn transfer_fixed_seed() {
     sim.seed_str("117f65d90ee5c1a7fb685f3af502c7730ba5d31866b758d98f5e3c2117cf9b86");
     sim.run();
 }
+
+/// Random loss does not indicate congestion, so a model-based congestion controller
+/// should complete a transfer much faster than a loss-based one.
+#[test]
+fn transfer_random_loss_bbr_faster_than_cubic() {
+    const SEED: &str = "117f65d90ee5c1a7fb685f3af502c7730ba5d31866b758d98f5e3c2117cf9b86";
+    let run = |client: Node, server: Node| {
+        let mut sim = Simulator::new(
+            "transfer_random_loss",
+            boxed![
+                client,
+                RandomDelay::new(DELAY_RANGE),
+                Drop::percentage(2),
+                server,
+                RandomDelay::new(DELAY_RANGE),
+                Drop::percentage(2),
+            ],
+        );
+        sim.seed_str(SEED);
+        sim.setup().run()
+    };
+    let cubic = run(
+        Node::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        Node::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+    );
+    let bbr = run(
+        bbr_client(SendData::new(TRANSFER_AMOUNT)),
+        bbr_server(ReceiveData::new(TRANSFER_AMOUNT)),
+    );
+    assert!(bbr * 2 < cubic, "BBR took {bbr:?}, Cubic took {cubic:?}");
+}
+
+/// An on-path observer that records the times at which the spin bit changes.
+#[derive(Debug, Default)]
+struct SpinObserver {
+    spin: Option<bool>,
+    edges: Rc<RefCell<Vec<Instant>>>,
+}
+
+impl sim::Node for SpinObserver {
+    fn process(&mut self, d: Option<Datagram>, now: Instant) -> Output {
+        d.map_or(Output::None, |d| {
+            // Only short header packets carry the spin bit.
+            if d[0] & 0x80 == 0 {
+                let spin = d[0] & 0x20 == 0x20;
+                if self.spin.is_some_and(|s| s != spin) {
+                    self.edges.borrow_mut().push(now);
+                }
+                self.spin = Some(spin);
+            }
+            Output::Datagram(d)
+        })
+    }
+}
+
+/// An observer of the packets that the client sends sees the spin bit change
+/// once per round trip.
+#[test]
+fn spin_period_matches_rtt() {
+    let params = || {
+        ConnectionParameters::default()
+            .pmtud(true)
+            .mlkem(false)
+            .spin_bit(SpinBitConfig::Enabled)
+    };
+    let observer = SpinObserver::default();
+    let edges = Rc::clone(&observer.edges);
+    Simulator::new(
+        "spin_period_matches_rtt",
+        boxed![
+            Node::new_client(
+                params(),
+                boxed![ReachState::new(State::Confirmed)],
+                boxed![SendData::new(TRANSFER_AMOUNT)]
+            ),
+            observer,
+            Delay::new(DELAY),
+            Node::new_server(
+                params(),
+                boxed![ReachState::new(State::Confirmed)],
+                boxed![ReceiveData::new(TRANSFER_AMOUNT)]
+            ),
+            Delay::new(DELAY),
+        ],
+    )
+    .run();
+
+    let edges = edges.borrow();
+    let periods = edges.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+    assert!(periods.len() >= 4, "too few spin edges: {periods:?}");
+    // The period also includes any time that the server waits to acknowledge.
+    let rtt = DELAY * 2;
+    assert!(
+        periods.iter().all(|&p| p >= rtt && p <= rtt + rtt / 4),
+        "spin periods {periods:?} do not match RTT {rtt:?}"
+    );
+}
@@ -17,7 +17,7 @@ use neqo_crypto::{
 use neqo_transport::{
     CloseReason, Connection, ConnectionParameters, Error, MIN_INITIAL_PACKET_SIZE, Output, State,
     StreamType, Version,
-    server::{ConnectionRef, Server, ValidateAddress},
+    server::{AdmissionControl, ConnectionRef, RateLimit, Server, ValidateAddress},
     version,
 };
 use test_fixture::{
@@ -961,3 +961,61 @@ fn saved_datagrams() {
         .dgram()
         .expect("fourth packet triggers third vn");
 }
+
+/// Have a new client try to connect to `server` and check that it is refused.
+fn assert_refused(server: &mut Server) {
+    let mut client = default_client();
+    let dgram = client.process_output(now()).dgram();
+    let refusal = server.process(dgram, now()).dgram().expect("a refusal");
+    assertions::assert_initial(&refusal, false);
+    client.process_input(refusal, now());
+    assert!(matches!(
+        *client.state(),
+        State::Draining { error: CloseReason::Transport(Error::Peer(code)), .. } if code == Error::ConnectionRefused.code()
+    ));
+}
+
+#[test]
+fn admission_max_connections() {
+    let mut server = default_server();
+    server.set_admission_control(AdmissionControl::default().max_connections(1));
+    let mut client = default_client();
+    complete_connection(&mut client, &mut server, None);
+
+    assert_refused(&mut server);
+    assert_eq!(server.admission_stats().refused_full, 1);
+}
+
+#[test]
+fn admission_retry_under_load() {
+    let mut server = default_server();
+    server.set_admission_control(AdmissionControl::default().retry_above(1));
+    let mut client = default_client();
+    complete_connection(&mut client, &mut server, None);
+
+    // With one connection open, the next client has to validate its address.
+    let mut client = default_client();
+    let dgram = client.process_output(now()).dgram();
+    let retry = server.process(dgram, now()).dgram().unwrap();
+    assertions::assert_retry(&retry);
+    assert_eq!(server.admission_stats().retried, 1);
+
+    // Once it does, it is accepted.
+    let dgram = client.process(Some(retry), now()).dgram();
+    let dgram = server.process(dgram, now()).dgram();
+    complete_connection(&mut client, &mut server, dgram);
+    assert_eq!(server.admission_stats().retried, 1);
+}
+
+#[test]
+fn admission_rate_limit() {
+    let mut server = default_server();
+    server.set_admission_control(AdmissionControl::default().rate_limit(RateLimit::new(1, 1)));
+    let mut client = default_client();
+    complete_connection(&mut client, &mut server, None);
+
+    // The next client comes from the same address.
+    assert_refused(&mut server);
+    assert_eq!(server.admission_stats().refused_rate, 1);
+    assert_eq!(server.admission_stats().refused_full, 0);
+}
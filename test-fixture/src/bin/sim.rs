@@ -0,0 +1,78 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Run simulation scenarios from files, see [`test_fixture::sim::scenario`].
+//!
+//! Arguments are scenario files, or directories of them.  With `--json`, the
+//! reports are printed as JSON, including traces of the congestion window.
+
+use std::{env, path::PathBuf, process::ExitCode};
+
+use test_fixture::sim::scenario::Scenario;
+
+const USAGE: &str = "usage: neqo-sim [--json] <scenario file or directory>...";
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut files = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => {
+                let path = PathBuf::from(arg);
+                if path.is_dir() {
+                    match Scenario::find(&path) {
+                        Ok(found) => files.extend(found),
+                        Err(e) => {
+                            eprintln!("{e}");
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+    }
+    if files.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let mut passed = true;
+    let mut reports = Vec::new();
+    for f in files {
+        match Scenario::load(&f) {
+            Ok(scenario) => {
+                let report = scenario.run();
+                passed &= report.passed;
+                if !json {
+                    println!("{report}");
+                }
+                reports.push(report);
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                passed = false;
+            }
+        }
+    }
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&reports).expect("reports serialize")
+        );
+    }
+    if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
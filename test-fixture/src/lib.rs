@@ -37,6 +37,7 @@ use qlog::{events::EventImportance, streamer::QlogStreamer};
 
 pub mod assertions;
 pub mod header_protection;
+pub mod replay;
 pub mod sim;
 
 /// The path for the database used in tests.
@@ -536,6 +537,16 @@ impl Display for SharedVec {
 /// Panics if the log cannot be created.
 #[must_use]
 pub fn new_neqo_qlog() -> (Qlog, SharedVec) {
+    new_neqo_qlog_with_importance(EventImportance::Base)
+}
+
+/// Like [`new_neqo_qlog`], but logs events up to `importance`.
+///
+/// # Panics
+///
+/// Panics if the log cannot be created.
+#[must_use]
+pub fn new_neqo_qlog_with_importance(importance: EventImportance) -> (Qlog, SharedVec) {
     let buf = SharedVec::default();
 
     if cfg!(feature = "bench") {
@@ -553,7 +564,7 @@ pub fn new_neqo_qlog() -> (Qlog, SharedVec) {
         None,
         Instant::now(),
         trace,
-        EventImportance::Base,
+        importance,
         Box::new(buf),
     );
     let log = Qlog::enabled(streamer, PathBuf::from(""));
@@ -0,0 +1,85 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Replay of captures made with [`neqo_transport::recorder::Recorder`].
+
+use std::{fs, path::Path, time::Instant};
+
+use neqo_common::qtrace;
+use neqo_transport::{
+    Connection, Output,
+    recorder::{Capture, Direction, Record},
+};
+
+/// Read a capture from a file.
+///
+/// # Panics
+///
+/// When the file can't be read or doesn't contain a capture.
+#[must_use]
+pub fn read_capture<P: AsRef<Path>>(path: P) -> Capture {
+    let data = fs::read(path).expect("read capture");
+    Capture::decode(&data).expect("decode capture")
+}
+
+/// Feed the datagrams that were received in `capture` to `conn`.
+///
+/// The clock starts at `start` and follows the timing of the capture.
+/// Datagrams that were received at the same time are passed to
+/// [`Connection::process_multiple_input`] together.  After each record,
+/// `conn` is asked for output, and its timers run until the time of the next
+/// record, so that it can send what it would have sent in that time.
+///
+/// This returns what `conn` sent, with times relative to `start`.
+///
+/// Only the response to the first flight can be reproduced.  `conn` makes its
+/// own TLS key share, so it can't decrypt any packet that is protected with
+/// keys from the recorded handshake.  Those packets are dropped, just as they
+/// would be for any other connection, which leaves `conn` in the same state
+/// as it would be in after only the first flight and its own timers.  Use
+/// [`Capture::decrypt`] to read all of the packets in a capture.
+pub fn first_flight(conn: &mut Connection, capture: &Capture, start: Instant) -> Vec<Record> {
+    let mut sent = Vec::new();
+    let mut records = capture.records().iter().peekable();
+    while let Some(r) = records.next() {
+        let now = start + r.time;
+        if r.direction == Direction::Received {
+            let mut batch = vec![r.datagram.clone()];
+            while let Some(next) =
+                records.next_if(|n| n.direction == Direction::Received && n.time == r.time)
+            {
+                batch.push(next.datagram.clone());
+            }
+            qtrace!("Replaying {} datagrams at {:?}", batch.len(), r.time);
+            conn.process_multiple_input(batch, now);
+        }
+        let until = records.peek().map_or(now, |n| start + n.time);
+        drain(conn, start, now, until, &mut sent);
+    }
+    sent
+}
+
+/// Collect what `conn` sends, advancing the clock from `now` to each timer
+/// that `conn` sets, up to `until`.
+fn drain(
+    conn: &mut Connection,
+    start: Instant,
+    mut now: Instant,
+    until: Instant,
+    sent: &mut Vec<Record>,
+) {
+    loop {
+        match conn.process_output(now) {
+            Output::Datagram(d) => sent.push(Record {
+                direction: Direction::Sent,
+                time: now - start,
+                datagram: d,
+            }),
+            Output::Callback(delay) if !delay.is_zero() && now + delay <= until => now += delay,
+            _ => break,
+        }
+    }
+}
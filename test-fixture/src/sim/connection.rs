@@ -12,11 +12,11 @@ use std::{
     time::Instant,
 };
 
-use neqo_common::{Datagram, event::Provider as _, qdebug, qinfo, qtrace};
+use neqo_common::{Datagram, event::Provider as _, qdebug, qinfo, qlog::Qlog, qtrace};
 use neqo_crypto::AuthenticationStatus;
 use neqo_transport::{
     Connection, ConnectionEvent, ConnectionParameters, EmptyConnectionIdGenerator, Output, State,
-    StreamId, StreamType,
+    Stats, StreamId, StreamType,
 };
 
 use crate::{
@@ -96,6 +96,15 @@ impl Node {
         )
     }
 
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        self.c.stats()
+    }
+
+    pub fn set_qlog(&mut self, qlog: Qlog) {
+        self.c.set_qlog(qlog);
+    }
+
     pub fn clear_goals(&mut self) {
         self.goals.clear();
     }
@@ -0,0 +1,103 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![expect(clippy::unwrap_used, reason = "This is test code.")]
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug},
+    time::Instant,
+};
+
+use neqo_common::{Datagram, qtrace};
+use neqo_transport::Output;
+
+use super::{Node, Rng};
+
+/// Duplicates a percentage of datagrams.  The copy follows immediately after
+/// the original.
+pub struct Duplicate {
+    pct: u64,
+    queue: VecDeque<Datagram>,
+    rng: Option<Rng>,
+}
+
+impl Duplicate {
+    #[must_use]
+    pub fn percentage(pct: u8) -> Self {
+        Self {
+            pct: u64::from(pct),
+            queue: VecDeque::new(),
+            rng: None,
+        }
+    }
+}
+
+impl Node for Duplicate {
+    fn init(&mut self, rng: Rng, _now: Instant) {
+        self.rng = Some(rng);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, _now: Instant) -> Output {
+        if let Some(dgram) = d {
+            if self.rng.as_ref().unwrap().borrow_mut().random_from(0..100) < self.pct {
+                qtrace!("duplicate {}", dgram.len());
+                self.queue.push_back(dgram.clone());
+            }
+            self.queue.push_back(dgram);
+        }
+        self.queue
+            .pop_front()
+            .map_or(Output::None, Output::Datagram)
+    }
+}
+
+impl Debug for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("duplicate")
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use neqo_transport::Output;
+
+    use super::Duplicate;
+    use crate::{
+        datagram, now,
+        sim::{Node as _, rng::Random},
+    };
+
+    #[test]
+    fn duplicate_all() {
+        let mut d = Duplicate::percentage(100);
+        d.init(Rc::new(RefCell::new(Random::new(&[1; 32]))), now());
+        let out = d.process(Some(datagram(vec![1])), now());
+        assert_eq!(out.dgram().unwrap()[0], 1);
+        // The copy follows, before the next datagram.
+        let out = d.process(Some(datagram(vec![2])), now());
+        assert_eq!(out.dgram().unwrap()[0], 1);
+        for _ in 0..2 {
+            let out = d.process(None, now());
+            assert_eq!(out.dgram().unwrap()[0], 2);
+        }
+        assert_eq!(d.process(None, now()), Output::None);
+    }
+
+    #[test]
+    fn duplicate_none() {
+        let mut d = Duplicate::percentage(0);
+        d.init(Rc::new(RefCell::new(Random::new(&[1; 32]))), now());
+        for i in 0..10 {
+            let out = d.process(Some(datagram(vec![i])), now());
+            assert_eq!(out.dgram().unwrap()[0], i);
+            assert_eq!(d.process(None, now()), Output::None);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![expect(clippy::unwrap_used, reason = "This is test code.")]
+
+use std::{
+    fmt::{self, Debug},
+    time::Instant,
+};
+
+use neqo_common::{Datagram, qtrace};
+use neqo_transport::Output;
+
+use super::{Node, Rng};
+
+/// Bursty loss, following the Gilbert-Elliott model.
+///
+/// The link is either in a good state or a bad state, each with its own loss
+/// rate.  Before each datagram, the link moves from good to bad with one
+/// probability and from bad to good with another.  All probabilities are
+/// percentages.
+pub struct GilbertElliott {
+    to_bad: u64,
+    to_good: u64,
+    good_loss: u64,
+    bad_loss: u64,
+    bad: bool,
+    rng: Option<Rng>,
+}
+
+impl GilbertElliott {
+    /// The simple Gilbert model, where every datagram is lost in the bad state
+    /// and none are lost in the good state.  The average length of a burst of
+    /// loss is `100 / to_good` datagrams.
+    #[must_use]
+    pub fn new(to_bad: u8, to_good: u8) -> Self {
+        Self {
+            to_bad: u64::from(to_bad),
+            to_good: u64::from(to_good),
+            good_loss: 0,
+            bad_loss: 100,
+            bad: false,
+            rng: None,
+        }
+    }
+
+    /// Set the loss rate in each state.
+    #[must_use]
+    pub fn loss(mut self, good: u8, bad: u8) -> Self {
+        self.good_loss = u64::from(good);
+        self.bad_loss = u64::from(bad);
+        self
+    }
+
+    fn chance(&self, pct: u64) -> bool {
+        self.rng.as_ref().unwrap().borrow_mut().random_from(0..100) < pct
+    }
+
+    /// Move between states, then determine whether to drop a datagram.
+    fn drop(&mut self) -> bool {
+        self.bad = if self.bad {
+            !self.chance(self.to_good)
+        } else {
+            self.chance(self.to_bad)
+        };
+        self.chance(if self.bad {
+            self.bad_loss
+        } else {
+            self.good_loss
+        })
+    }
+}
+
+impl Node for GilbertElliott {
+    fn init(&mut self, rng: Rng, _now: Instant) {
+        self.rng = Some(rng);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, _now: Instant) -> Output {
+        d.map_or(Output::None, |dgram| {
+            if self.drop() {
+                qtrace!("gilbert-elliott drop {} bad={}", dgram.len(), self.bad);
+                Output::None
+            } else {
+                Output::Datagram(dgram)
+            }
+        })
+    }
+}
+
+impl Debug for GilbertElliott {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("gilbert-elliott")
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::GilbertElliott;
+    use crate::{
+        now,
+        sim::{Node as _, rng::Random},
+    };
+
+    /// Losses come in bursts with the expected average length.
+    #[test]
+    fn bursts() {
+        let mut ge = GilbertElliott::new(2, 25);
+        ge.init(Rc::new(RefCell::new(Random::new(&[7; 32]))), now());
+        let (mut lost, mut bursts, mut previous) = (0, 0, false);
+        for _ in 0..100_000 {
+            let drop = ge.drop();
+            lost += usize::from(drop);
+            bursts += usize::from(drop && !previous);
+            previous = drop;
+        }
+        // Steady state loss is 2 / (2 + 25), or about 7.4%.
+        assert!((7_000..7_800).contains(&lost), "lost {lost}");
+        // Bursts average 4 datagrams.
+        assert!(
+            (3_500..4_500).contains(&(lost * 1000 / bursts)),
+            "{lost} in {bursts}"
+        );
+    }
+}
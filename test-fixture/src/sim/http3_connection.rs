@@ -13,13 +13,13 @@ use std::{
     time::Instant,
 };
 
-use neqo_common::{Datagram, event::Provider as _, qdebug, qinfo, qtrace};
+use neqo_common::{Datagram, event::Provider as _, qdebug, qinfo, qlog::Qlog, qtrace};
 use neqo_crypto::AuthenticationStatus;
 use neqo_http3::{
     Header, Http3Client, Http3ClientEvent, Http3Parameters, Http3Server, Http3ServerEvent,
     Http3State, Priority,
 };
-use neqo_transport::{ConnectionParameters, Output, StreamId};
+use neqo_transport::{ConnectionParameters, Output, Stats, StreamId, server::ConnectionRef};
 
 use crate::{
     boxed, http3_client_with_params, http3_server_with_params, now,
@@ -41,6 +41,8 @@ pub trait Goal: Debug {
 
 pub struct Node {
     c: Endpoint,
+    /// For a server, the connection that it accepted.
+    server_conn: Option<ConnectionRef>,
     setup_goals: Vec<Box<dyn Goal>>,
     goals: Vec<Box<dyn Goal>>,
 }
@@ -93,6 +95,7 @@ impl Node {
     ) -> Self {
         Self {
             c: Endpoint::Client(http3_client_with_params(params)),
+            server_conn: None,
             setup_goals: setup.into_iter().collect(),
             goals: goals.into_iter().collect(),
         }
@@ -108,6 +111,7 @@ impl Node {
     ) -> Self {
         Self {
             c: Endpoint::Server(http3_server_with_params(params)),
+            server_conn: None,
             setup_goals: setup.into_iter().collect(),
             goals: goals.into_iter().collect(),
         }
@@ -135,6 +139,24 @@ impl Node {
         )
     }
 
+    /// Transport statistics.  These are only available for a server once it
+    /// has accepted a connection.
+    #[must_use]
+    pub fn stats(&self) -> Option<Stats> {
+        match &self.c {
+            Endpoint::Client(c) => Some(c.transport_stats()),
+            Endpoint::Server(_) => self.server_conn.as_ref().map(|c| c.borrow().stats()),
+        }
+    }
+
+    /// Set the qlog for a client.  Servers create their own connections, so
+    /// this does nothing for a server.
+    pub fn set_qlog(&mut self, qlog: Qlog) {
+        if let Endpoint::Client(c) = &mut self.c {
+            c.set_qlog(qlog);
+        }
+    }
+
     /// On the first call to this method, the setup goals will turn into the active goals.
     /// On the second call, they will be swapped back and the main goals will run.
     fn setup_goals(&mut self, now: Instant) {
@@ -178,6 +200,9 @@ impl sim::Node for Node {
             let mut active = false;
             while let Some(e) = self.c.next_event() {
                 qtrace!("[{}] received event {e:?}", self.c);
+                if let Event::Server(Http3ServerEvent::StateChange { conn, .. }) = &e {
+                    self.server_conn.get_or_insert_with(|| conn.clone());
+                }
 
                 // Perform authentication automatically.
                 if matches!(e, Event::Client(Http3ClientEvent::AuthenticationNeeded)) {
@@ -212,9 +237,10 @@ impl sim::Node for Node {
     }
 
     fn print_summary(&self, test_name: &str) {
-        match &self.c {
-            Endpoint::Client(c) => qinfo!("{test_name}: {:?}", c.transport_stats()),
-            Endpoint::Server(_) => qinfo!("{test_name}: Server (no stats available on server)"),
+        if let Some(stats) = self.stats() {
+            qinfo!("{test_name}: {stats:?}");
+        } else {
+            qinfo!("{test_name}: Server (no connection)");
         }
     }
 }
@@ -10,10 +10,16 @@
 pub mod connection;
 mod delay;
 mod drop;
+mod duplicate;
+mod gilbert_elliott;
 pub mod http3_connection;
 mod mtu;
+mod reorder;
 pub mod rng;
+pub mod scenario;
+mod schedule;
 mod taildrop;
+mod token_bucket;
 
 use std::{
     cell::RefCell,
@@ -37,8 +43,13 @@ pub mod network {
     pub use super::{
         delay::{Delay, RandomDelay},
         drop::Drop,
+        duplicate::Duplicate,
+        gilbert_elliott::GilbertElliott,
         mtu::Mtu,
+        reorder::Reorder,
+        schedule::{Outage, Rebind},
         taildrop::TailDrop,
+        token_bucket::TokenBucket,
     };
 }
 
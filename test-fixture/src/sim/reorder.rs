@@ -0,0 +1,184 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![expect(clippy::unwrap_used, reason = "This is test code.")]
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug},
+    time::{Duration, Instant},
+};
+
+use neqo_common::{Datagram, qtrace};
+use neqo_transport::Output;
+
+use super::{Node, Rng};
+
+/// A datagram that is being held back.
+struct Held {
+    /// The number of datagrams that still need to pass before this is released.
+    remaining: usize,
+    /// The time at which this is released if not enough datagrams pass.
+    deadline: Instant,
+    dgram: Datagram,
+}
+
+/// Reorders datagrams within a window.
+///
+/// Each datagram is held back with the given probability.  A held datagram is
+/// released after between one and `window` later datagrams have passed, or
+/// after `max_hold`, whichever happens first.
+pub struct Reorder {
+    pct: u64,
+    window: usize,
+    max_hold: Duration,
+    held: Vec<Held>,
+    ready: VecDeque<Datagram>,
+    rng: Option<Rng>,
+}
+
+impl Reorder {
+    /// # Panics
+    ///
+    /// When `window` is zero.
+    #[must_use]
+    pub fn new(pct: u8, window: usize, max_hold: Duration) -> Self {
+        assert!(window > 0, "a zero window does nothing");
+        Self {
+            pct: u64::from(pct),
+            window,
+            max_hold,
+            held: Vec::new(),
+            ready: VecDeque::new(),
+            rng: None,
+        }
+    }
+
+    fn insert(&mut self, d: Datagram, now: Instant) {
+        // Everything that is held back falls one place further behind.
+        for h in &mut self.held {
+            h.remaining = h.remaining.saturating_sub(1);
+        }
+        let mut rng = self.rng.as_ref().unwrap().borrow_mut();
+        if rng.random_from(0..100) < self.pct {
+            let remaining = if self.window > 1 {
+                let window = u64::try_from(self.window).unwrap();
+                usize::try_from(rng.random_from(1..window + 1)).unwrap()
+            } else {
+                1
+            };
+            qtrace!("reorder holding {} behind {remaining}", d.len());
+            self.held.push(Held {
+                remaining,
+                deadline: now + self.max_hold,
+                dgram: d,
+            });
+        } else {
+            self.ready.push_back(d);
+        }
+    }
+
+    fn release(&mut self, now: Instant) {
+        let mut i = 0;
+        while i < self.held.len() {
+            if self.held[i].remaining == 0 || self.held[i].deadline <= now {
+                self.ready.push_back(self.held.remove(i).dgram);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl Node for Reorder {
+    fn init(&mut self, rng: Rng, _now: Instant) {
+        self.rng = Some(rng);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, now: Instant) -> Output {
+        if let Some(dgram) = d {
+            self.insert(dgram, now);
+        }
+        self.release(now);
+        if let Some(d) = self.ready.pop_front() {
+            Output::Datagram(d)
+        } else if let Some(t) = self.held.iter().map(|h| h.deadline).min() {
+            Output::Callback(t - now)
+        } else {
+            Output::None
+        }
+    }
+}
+
+impl Debug for Reorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "reorder-{}", self.window)
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use neqo_transport::Output;
+
+    use super::Reorder;
+    use crate::{
+        datagram, now,
+        sim::{Node as _, rng::Random},
+    };
+
+    #[test]
+    fn reorder_all() {
+        let mut r = Reorder::new(100, 1, Duration::from_millis(10));
+        r.init(Rc::new(RefCell::new(Random::new(&[1; 32]))), now());
+        assert_eq!(
+            r.process(Some(datagram(vec![1])), now()),
+            Output::Callback(Duration::from_millis(10))
+        );
+        // The second datagram releases the first, but is held itself.
+        let out = r.process(Some(datagram(vec![2])), now());
+        assert_eq!(out.dgram().unwrap()[0], 1);
+        // After the timeout, the second is released.
+        let out = r.process(None, now() + Duration::from_millis(10));
+        assert_eq!(out.dgram().unwrap()[0], 2);
+        assert_eq!(
+            r.process(None, now() + Duration::from_millis(10)),
+            Output::None
+        );
+    }
+
+    #[test]
+    fn reorder_window() {
+        const WINDOW: usize = 3;
+        const COUNT: u8 = 50;
+        let mut r = Reorder::new(50, WINDOW, Duration::from_millis(10));
+        r.init(Rc::new(RefCell::new(Random::new(&[1; 32]))), now());
+        let mut order = Vec::new();
+        for i in 0..COUNT {
+            if let Some(d) = r.process(Some(datagram(vec![i])), now()).dgram() {
+                order.push(d[0]);
+            }
+        }
+        while let Some(d) = r.process(None, now() + Duration::from_millis(10)).dgram() {
+            order.push(d[0]);
+        }
+
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..COUNT).collect::<Vec<_>>());
+        // Count the datagrams that overtook each one.
+        let overtaken = order
+            .iter()
+            .enumerate()
+            .map(|(pos, &i)| order[..pos].iter().filter(|&&j| j > i).count())
+            .collect::<Vec<_>>();
+        assert!(overtaken.iter().all(|&n| n <= WINDOW), "{order:?}");
+        // Some datagrams fall more than one place behind.
+        assert!(overtaken.iter().any(|&n| n > 1), "{order:?}");
+    }
+}
@@ -0,0 +1,843 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Simulations that are described in JSON files rather than in code.
+//!
+//! A scenario names the protocol, the parameters for the client and the
+//! server, the network nodes in each direction, the goals, and what counts as
+//! success.  For example:
+//!
+//! ```json
+//! {
+//!     "name": "dsl",
+//!     "client": { "cc": "bbr" },
+//!     "client_to_server": [
+//!         { "type": "tail_drop", "rate": 1000000, "capacity": 32768, "delay_ms": 50 }
+//!     ],
+//!     "server_to_client": [{ "type": "delay", "ms": 50 }],
+//!     "goals": { "upload": 1048576 },
+//!     "expect": { "max_time_ms": 15000 }
+//! }
+//! ```
+//!
+//! The `neqo-sim` binary runs scenario files.  Files in the `scenarios`
+//! directory of this crate are also run as tests.
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug, Display, Formatter},
+    fs, iter,
+    net::SocketAddr,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr as _,
+    time::{Duration, Instant},
+};
+
+use neqo_common::{
+    Datagram, Role,
+    qlog::{Qlog, new_trace},
+};
+use neqo_http3::Http3Parameters;
+use neqo_transport::{
+    CongestionControlAlgorithm, CongestionEvent, ConnectionParameters, Output, Stats, StreamType,
+};
+use qlog::{events::EventImportance, streamer::QlogStreamer};
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+    Node, Rng, Simulator, connection,
+    http3_connection::{self, Requests, Responses},
+    network::{
+        Delay, Drop, Duplicate, GilbertElliott, Mtu, Outage, RandomDelay, Rebind, Reorder,
+        TailDrop, TokenBucket,
+    },
+};
+use crate::{DEFAULT_ADDR, SharedVec, boxed};
+
+/// The protocol that the client and server use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    #[default]
+    Transport,
+    Http3,
+}
+
+/// Parameters for one endpoint.  Anything that isn't set takes the value
+/// that [`connection::Node::default_client`] uses.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Params {
+    /// The congestion control algorithm: "newreno", "cubic" or "bbr".
+    pub cc: Option<String>,
+    pub pacing: Option<bool>,
+    pub pmtud: Option<bool>,
+    pub max_data: Option<u64>,
+    /// The flow control limit for each stream.
+    pub max_stream_data: Option<u64>,
+    pub idle_timeout_ms: Option<u64>,
+    pub initial_rtt_ms: Option<u64>,
+    pub ack_ratio: Option<u8>,
+    /// The QPACK table size, for HTTP/3.
+    pub max_table_size: Option<u64>,
+    /// The number of streams that can be blocked on QPACK, for HTTP/3.
+    pub max_blocked_streams: Option<u16>,
+}
+
+impl Params {
+    fn connection_parameters(&self) -> Result<ConnectionParameters, String> {
+        // Simulator logic does not work with multi-packet MLKEM crypto flights.
+        let mut params = ConnectionParameters::default()
+            .pmtud(self.pmtud.unwrap_or(true))
+            .mlkem(false);
+        if let Some(cc) = &self.cc {
+            let cc = CongestionControlAlgorithm::from_str(cc)
+                .map_err(|_| format!("unknown congestion control algorithm {cc:?}"))?;
+            params = params.cc_algorithm(cc);
+        }
+        if let Some(pacing) = self.pacing {
+            params = params.pacing(pacing);
+        }
+        if let Some(v) = self.max_data {
+            params = params.max_data(v);
+        }
+        if let Some(v) = self.max_stream_data {
+            params = params
+                .max_stream_data(StreamType::BiDi, false, v)
+                .max_stream_data(StreamType::BiDi, true, v)
+                .max_stream_data(StreamType::UniDi, true, v);
+        }
+        if let Some(ms) = self.idle_timeout_ms {
+            params = params.idle_timeout(Duration::from_millis(ms));
+        }
+        if let Some(ms) = self.initial_rtt_ms {
+            params = params.initial_rtt(Duration::from_millis(ms));
+        }
+        if let Some(v) = self.ack_ratio {
+            params = params.ack_ratio(v);
+        }
+        Ok(params)
+    }
+
+    fn http3_parameters(&self) -> Result<Http3Parameters, String> {
+        let mut params =
+            Http3Parameters::default().connection_parameters(self.connection_parameters()?);
+        if let Some(v) = self.max_table_size {
+            params = params.max_table_size_encoder(v).max_table_size_decoder(v);
+        }
+        if let Some(v) = self.max_blocked_streams {
+            params = params.max_blocked_streams(v);
+        }
+        Ok(params)
+    }
+}
+
+/// A network node, see [`super::network`].  Times are in milliseconds and
+/// probabilities are percentages.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Link {
+    Delay {
+        ms: u64,
+    },
+    RandomDelay {
+        min_ms: u64,
+        max_ms: u64,
+    },
+    Drop {
+        pct: u8,
+    },
+    Mtu {
+        mtu: usize,
+    },
+    TailDrop {
+        /// Bytes per second.
+        rate: usize,
+        /// Bytes.
+        capacity: usize,
+        #[serde(default)]
+        ecn: bool,
+        delay_ms: u64,
+        /// Mark CE above this many queued bytes, instead of using RED.
+        mark_threshold: Option<usize>,
+    },
+    Reorder {
+        pct: u8,
+        window: usize,
+        max_hold_ms: u64,
+    },
+    Duplicate {
+        pct: u8,
+    },
+    TokenBucket {
+        /// Bytes per second.
+        rate: usize,
+        /// Bytes.
+        burst: usize,
+    },
+    GilbertElliott {
+        to_bad: u8,
+        to_good: u8,
+        #[serde(default)]
+        good_loss: u8,
+        #[serde(default = "full_loss")]
+        bad_loss: u8,
+    },
+    Outage {
+        start_ms: u64,
+        length_ms: u64,
+    },
+    /// The client moves to a new port, which the server has to follow.  This
+    /// needs to be in both directions, with the same time.
+    Rebind {
+        at_ms: u64,
+        port: u16,
+    },
+}
+
+const fn full_loss() -> u8 {
+    100
+}
+
+impl Link {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::RandomDelay { min_ms, max_ms } if min_ms >= max_ms => Err(format!(
+                "random_delay needs min_ms < max_ms, not {min_ms}..{max_ms}"
+            )),
+            Self::TailDrop { rate, .. } | Self::TokenBucket { rate, .. }
+                if *rate == 0 || *rate > 1_000_000_000 =>
+            {
+                Err(format!("rate {rate} is out of range"))
+            }
+            Self::Reorder { window: 0, .. } => Err("reorder needs a window".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    fn node(&self, to_server: bool) -> Box<dyn Node> {
+        let ms = Duration::from_millis;
+        match *self {
+            Self::Delay { ms: d } => Box::new(Delay::new(ms(d))),
+            Self::RandomDelay { min_ms, max_ms } => {
+                Box::new(RandomDelay::new(ms(min_ms)..ms(max_ms)))
+            }
+            Self::Drop { pct } => Box::new(Drop::percentage(pct)),
+            Self::Mtu { mtu } => Box::new(Mtu::new(mtu)),
+            Self::TailDrop {
+                rate,
+                capacity,
+                ecn,
+                delay_ms,
+                mark_threshold,
+            } => {
+                let mut td = TailDrop::new(rate, capacity, ecn, ms(delay_ms));
+                if let Some(threshold) = mark_threshold {
+                    td = td.mark_threshold(threshold);
+                }
+                Box::new(td)
+            }
+            Self::Reorder {
+                pct,
+                window,
+                max_hold_ms,
+            } => Box::new(Reorder::new(pct, window, ms(max_hold_ms))),
+            Self::Duplicate { pct } => Box::new(Duplicate::percentage(pct)),
+            Self::TokenBucket { rate, burst } => Box::new(TokenBucket::new(rate, burst)),
+            Self::GilbertElliott {
+                to_bad,
+                to_good,
+                good_loss,
+                bad_loss,
+            } => Box::new(GilbertElliott::new(to_bad, to_good).loss(good_loss, bad_loss)),
+            Self::Outage {
+                start_ms,
+                length_ms,
+            } => Box::new(Outage::new(ms(start_ms), ms(length_ms))),
+            Self::Rebind { at_ms, port } => {
+                let rebound = SocketAddr::new(DEFAULT_ADDR.ip(), port);
+                if to_server {
+                    Box::new(Rebind::source(ms(at_ms), DEFAULT_ADDR, rebound))
+                } else {
+                    Box::new(Rebind::destination(ms(at_ms), rebound, DEFAULT_ADDR))
+                }
+            }
+        }
+    }
+}
+
+/// What the endpoints need to achieve.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Goals {
+    /// Bytes that the client sends to the server on a stream.
+    pub upload: usize,
+    /// Bytes that the server sends to the client on a stream.
+    pub download: usize,
+    /// The number of HTTP/3 requests.
+    pub requests: usize,
+    /// The size of the body of each HTTP/3 request.
+    pub request_size: usize,
+}
+
+/// Conditions for success, beyond reaching the goals.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Expect {
+    /// The most simulated time that reaching the goals can take.
+    pub max_time_ms: Option<u64>,
+    /// The most packets that the client and server can declare lost, in total.
+    pub max_lost: Option<usize>,
+}
+
+impl Expect {
+    fn check(
+        &self,
+        time: Duration,
+        client: &EndpointReport,
+        server: &EndpointReport,
+    ) -> Option<String> {
+        if let Some(max) = self.max_time_ms.map(Duration::from_millis)
+            && time > max
+        {
+            return Some(format!("took {time:?}, more than {max:?}"));
+        }
+        let lost = [client, server]
+            .iter()
+            .filter_map(|e| e.stats.as_ref())
+            .map(|s| s.lost)
+            .sum::<usize>();
+        if let Some(max) = self.max_lost
+            && lost > max
+        {
+            return Some(format!("lost {lost} packets, more than {max}"));
+        }
+        None
+    }
+}
+
+/// A simulation, as read from a file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    /// 32 bytes of hex, for a reproducible run.
+    pub seed: Option<String>,
+    #[serde(default)]
+    pub protocol: Protocol,
+    #[serde(default)]
+    pub client: Params,
+    #[serde(default)]
+    pub server: Params,
+    #[serde(default)]
+    pub client_to_server: Vec<Link>,
+    #[serde(default)]
+    pub server_to_client: Vec<Link>,
+    pub goals: Goals,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+impl Scenario {
+    /// # Errors
+    ///
+    /// When `s` isn't a valid scenario.
+    pub fn from_json(s: &str) -> Result<Self, String> {
+        let scenario: Self = serde_json::from_str(s).map_err(|e| e.to_string())?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Read a scenario from a file.
+    ///
+    /// # Errors
+    ///
+    /// When the file can't be read or isn't a valid scenario.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let s = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::from_json(&s).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Find the scenario files in a directory, in order.
+    ///
+    /// # Errors
+    ///
+    /// When the directory can't be read.
+    pub fn find<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>, String> {
+        let dir = dir.as_ref();
+        let entries = fs::read_dir(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        let mut files = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(seed) = &self.seed
+            && (seed.len() != 64 || !seed.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Err("seed needs to be 64 hex digits".to_string());
+        }
+        match self.protocol {
+            Protocol::Transport => {
+                self.client.connection_parameters()?;
+                self.server.connection_parameters()?;
+                if self.goals.upload == 0 && self.goals.download == 0 {
+                    return Err("transport scenarios need an upload or download goal".to_string());
+                }
+                if self.goals.requests > 0 {
+                    return Err("requests are only for HTTP/3".to_string());
+                }
+            }
+            Protocol::Http3 => {
+                self.client.http3_parameters()?;
+                self.server.http3_parameters()?;
+                if self.goals.requests == 0 {
+                    return Err("HTTP/3 scenarios need a requests goal".to_string());
+                }
+                if self.goals.upload > 0 || self.goals.download > 0 {
+                    return Err("upload and download are only for transport".to_string());
+                }
+            }
+        }
+        self.client_to_server
+            .iter()
+            .chain(&self.server_to_client)
+            .try_for_each(Link::validate)?;
+        let rebinds = |links: &[Link]| {
+            links
+                .iter()
+                .filter_map(|l| match *l {
+                    Link::Rebind { at_ms, port } => Some((at_ms, port)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        if rebinds(&self.client_to_server) != rebinds(&self.server_to_client) {
+            return Err("rebind needs to be in both directions, with the same time".to_string());
+        }
+        Ok(())
+    }
+
+    fn endpoints(&self) -> (Observer, Observer) {
+        let goals = &self.goals;
+        match self.protocol {
+            Protocol::Transport => {
+                let mut client_goals = Vec::<Box<dyn connection::Goal>>::new();
+                let mut server_goals = Vec::<Box<dyn connection::Goal>>::new();
+                if goals.upload > 0 {
+                    client_goals.push(Box::new(connection::SendData::new(goals.upload)));
+                    server_goals.push(Box::new(connection::ReceiveData::new(goals.upload)));
+                }
+                if goals.download > 0 {
+                    server_goals.push(Box::new(connection::SendData::new(goals.download)));
+                    client_goals.push(Box::new(connection::ReceiveData::new(goals.download)));
+                }
+                let setup = || {
+                    boxed![connection::ReachState::new(
+                        neqo_transport::State::Confirmed
+                    )]
+                };
+                let client = connection::Node::new_client(
+                    self.client.connection_parameters().unwrap_or_default(),
+                    setup(),
+                    client_goals,
+                );
+                let server = connection::Node::new_server(
+                    self.server.connection_parameters().unwrap_or_default(),
+                    setup(),
+                    server_goals,
+                );
+                (
+                    Observer::new(client, Role::Client),
+                    Observer::new(server, Role::Server),
+                )
+            }
+            Protocol::Http3 => {
+                let setup = || {
+                    boxed![http3_connection::ReachState::new(
+                        neqo_http3::Http3State::Connected
+                    )]
+                };
+                let client = http3_connection::Node::new_client(
+                    self.client.http3_parameters().unwrap_or_default(),
+                    setup(),
+                    boxed![Requests::new(goals.requests, goals.request_size)],
+                );
+                let server = http3_connection::Node::new_server(
+                    self.server.http3_parameters().unwrap_or_default(),
+                    setup(),
+                    boxed![Responses::new(goals.requests, goals.request_size)],
+                );
+                (
+                    Observer::new(client, Role::Client),
+                    Observer::new(server, Role::Server),
+                )
+            }
+        }
+    }
+
+    /// Run the scenario.  Failures, including panics in the simulation, are
+    /// captured in the report.
+    #[must_use]
+    pub fn run(&self) -> Report {
+        let (client, server) = self.endpoints();
+        let nodes = iter::once(client.node())
+            .chain(self.client_to_server.iter().map(|l| l.node(true)))
+            .chain(iter::once(server.node()))
+            .chain(self.server_to_client.iter().map(|l| l.node(false)));
+        let mut sim = Simulator::new(&self.name, nodes);
+        if let Some(seed) = &self.seed {
+            sim.seed_str(seed);
+        }
+        let res = panic::catch_unwind(AssertUnwindSafe(|| sim.setup().run()));
+
+        let client = client.report();
+        let server = server.report();
+        let (time, failure) = match res {
+            Ok(time) => (Some(time), self.expect.check(time, &client, &server)),
+            Err(e) => {
+                let msg = e
+                    .downcast_ref::<&str>()
+                    .map(ToString::to_string)
+                    .or_else(|| e.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "panicked".to_string());
+                (None, Some(msg))
+            }
+        };
+        Report {
+            name: self.name.clone(),
+            passed: failure.is_none(),
+            failure,
+            time_ms: time.map(|t| t.as_secs_f64() * 1000.0),
+            client,
+            server,
+        }
+    }
+}
+
+/// An endpoint node that the simulator can use while it is being observed.
+trait Endpoint: Node {
+    fn stats(&self) -> Option<Stats>;
+    fn set_qlog(&mut self, qlog: Qlog);
+}
+
+impl Endpoint for connection::Node {
+    fn stats(&self) -> Option<Stats> {
+        Some(Self::stats(self))
+    }
+
+    fn set_qlog(&mut self, qlog: Qlog) {
+        Self::set_qlog(self, qlog);
+    }
+}
+
+impl Endpoint for http3_connection::Node {
+    fn stats(&self) -> Option<Stats> {
+        Self::stats(self)
+    }
+
+    fn set_qlog(&mut self, qlog: Qlog) {
+        Self::set_qlog(self, qlog);
+    }
+}
+
+/// Holds on to an endpoint and its qlog so that they can be inspected after
+/// the simulator is done with them.
+struct Observer {
+    node: Rc<RefCell<dyn Endpoint>>,
+    qlog: SharedVec,
+}
+
+impl Observer {
+    fn new<E: Endpoint + 'static>(mut node: E, role: Role) -> Self {
+        let qlog = SharedVec::default();
+        let streamer = QlogStreamer::new(
+            qlog::QLOG_VERSION.to_string(),
+            None,
+            None,
+            None,
+            crate::now(),
+            new_trace(role),
+            EventImportance::Core,
+            Box::new(qlog.clone()),
+        );
+        node.set_qlog(Qlog::enabled(streamer, PathBuf::new()).expect("qlog"));
+        Self {
+            node: Rc::new(RefCell::new(node)),
+            qlog,
+        }
+    }
+
+    fn node(&self) -> Box<dyn Node> {
+        Box::new(Shared(Rc::clone(&self.node)))
+    }
+
+    fn report(&self) -> EndpointReport {
+        EndpointReport {
+            stats: self.node.borrow().stats().as_ref().map(Counters::from),
+            cwnd: cwnd_trace(&self.qlog.to_string()),
+        }
+    }
+}
+
+/// A node that is shared with an [`Observer`].
+struct Shared(Rc<RefCell<dyn Endpoint>>);
+
+impl Node for Shared {
+    fn init(&mut self, rng: Rng, now: Instant) {
+        self.0.borrow_mut().init(rng, now);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, now: Instant) -> Output {
+        self.0.borrow_mut().process(d, now)
+    }
+
+    fn prepare(&mut self, now: Instant) {
+        self.0.borrow_mut().prepare(now);
+    }
+
+    fn done(&self) -> bool {
+        self.0.borrow().done()
+    }
+
+    fn print_summary(&self, test_name: &str) {
+        self.0.borrow().print_summary(test_name);
+    }
+}
+
+impl Debug for Shared {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Debug::fmt(&*self.0.borrow(), f)
+    }
+}
+
+/// Pull the congestion window from the `metrics_updated` events in a qlog.
+fn cwnd_trace(qlog: &str) -> Vec<(f64, u64)> {
+    qlog.split('\u{1e}')
+        .filter_map(|record| serde_json::from_str::<serde_json::Value>(record).ok())
+        .filter(|event| event["name"] == "recovery:metrics_updated")
+        .filter_map(|event| {
+            Some((
+                event["time"].as_f64()?,
+                event["data"]["congestion_window"].as_u64()?,
+            ))
+        })
+        .collect()
+}
+
+/// A selection of the counters from [`Stats`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Counters {
+    pub packets_tx: usize,
+    pub packets_rx: usize,
+    pub lost: usize,
+    pub dups_rx: usize,
+    pub dropped_rx: usize,
+    pub late_ack: usize,
+    pub pto: usize,
+    pub congestion_loss: usize,
+    pub congestion_ecn: usize,
+    pub congestion_spurious: usize,
+    pub rtt_ms: f64,
+}
+
+impl From<&Stats> for Counters {
+    fn from(s: &Stats) -> Self {
+        Self {
+            packets_tx: s.packets_tx,
+            packets_rx: s.packets_rx,
+            lost: s.lost,
+            dups_rx: s.dups_rx,
+            dropped_rx: s.dropped_rx,
+            late_ack: s.late_ack,
+            pto: s.pto_counts.iter().sum(),
+            congestion_loss: s.cc.congestion_events[CongestionEvent::Loss],
+            congestion_ecn: s.cc.congestion_events[CongestionEvent::Ecn],
+            congestion_spurious: s.cc.congestion_events[CongestionEvent::Spurious],
+            rtt_ms: s.rtt.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+impl Display for Counters {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "tx {} rx {} lost {} dups {} dropped {} late-ack {} pto {} \
+             congestion loss {} ecn {} spurious {} rtt {:.1}ms",
+            self.packets_tx,
+            self.packets_rx,
+            self.lost,
+            self.dups_rx,
+            self.dropped_rx,
+            self.late_ack,
+            self.pto,
+            self.congestion_loss,
+            self.congestion_ecn,
+            self.congestion_spurious,
+            self.rtt_ms,
+        )
+    }
+}
+
+/// What happened at one endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointReport {
+    /// This is missing for HTTP/3 servers.
+    pub stats: Option<Counters>,
+    /// The congestion window in bytes after each change, with the time in
+    /// milliseconds since the start of the simulation.
+    pub cwnd: Vec<(f64, u64)>,
+}
+
+impl Display for EndpointReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(stats) = &self.stats {
+            write!(f, "{stats}")?;
+        } else {
+            write!(f, "no stats")?;
+        }
+        if let Some(max) = self.cwnd.iter().map(|(_, cwnd)| cwnd).max() {
+            write!(f, " cwnd changes {} max {max}", self.cwnd.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of running a [`Scenario`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub name: String,
+    pub passed: bool,
+    pub failure: Option<String>,
+    /// The simulated time after setup that it took to reach the goals.
+    pub time_ms: Option<f64>,
+    pub client: EndpointReport,
+    pub server: EndpointReport,
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            if self.passed { "PASS" } else { "FAIL" },
+            self.name
+        )?;
+        if let Some(t) = self.time_ms {
+            write!(f, " in {t:.1}ms")?;
+        }
+        if let Some(failure) = &self.failure {
+            write!(f, ": {failure}")?;
+        }
+        write!(f, "\n  client: {}\n  server: {}", self.client, self.server)
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::Scenario;
+
+    const UPLOAD: &str = r#"{
+        "name": "upload",
+        "seed": "117f65d90ee5c1a7fb685f3af502c7730ba5d31866b758d98f5e3c2117cf9b86",
+        "client": { "cc": "newreno" },
+        "client_to_server": [{ "type": "delay", "ms": 20 }, { "type": "drop", "pct": 1 }],
+        "server_to_client": [{ "type": "delay", "ms": 20 }],
+        "goals": { "upload": 100000 }
+    }"#;
+
+    #[test]
+    fn upload() {
+        let scenario = Scenario::from_json(UPLOAD).unwrap();
+        let report = scenario.run();
+        assert!(report.passed, "{report}");
+        assert!(report.time_ms.unwrap() > 0.0);
+        assert!(report.client.stats.as_ref().unwrap().packets_tx > 0);
+        assert!(!report.client.cwnd.is_empty());
+    }
+
+    #[test]
+    fn http3() {
+        let scenario = Scenario::from_json(
+            r#"{
+                "name": "requests",
+                "protocol": "http3",
+                "client_to_server": [{ "type": "tail_drop", "rate": 200000, "capacity": 8192, "delay_ms": 10 }],
+                "server_to_client": [{ "type": "delay", "ms": 10 }],
+                "goals": { "requests": 5, "request_size": 1000 }
+            }"#,
+        )
+        .unwrap();
+        let report = scenario.run();
+        assert!(report.passed, "{report}");
+        let server = report.server.stats.as_ref().unwrap();
+        assert!(server.packets_rx > 0);
+        assert!(server.packets_tx > 0);
+    }
+
+    #[test]
+    fn expectation_not_met() {
+        let upload = UPLOAD.replacen('{', r#"{ "expect": { "max_time_ms": 1 },"#, 1);
+        let scenario = Scenario::from_json(&upload).unwrap();
+        let report = scenario.run();
+        assert!(!report.passed);
+        assert!(report.failure.unwrap().starts_with("took "));
+    }
+
+    #[test]
+    fn stalled() {
+        let scenario = Scenario::from_json(
+            r#"{
+                "name": "stalled",
+                "client": { "idle_timeout_ms": 1000 },
+                "server": { "idle_timeout_ms": 1000 },
+                "client_to_server": [{ "type": "outage", "start_ms": 0, "length_ms": 100000 }],
+                "goals": { "upload": 100000 }
+            }"#,
+        )
+        .unwrap();
+        let report = scenario.run();
+        assert!(!report.passed);
+        assert!(report.failure.is_some());
+    }
+
+    #[test]
+    fn invalid() {
+        for s in [
+            r#"{ "name": "x", "goals": { "upload": 1 }, "client": { "cc": "fast" } }"#,
+            r#"{ "name": "x", "goals": { "upload": 1 }, "seed": "00" }"#,
+            r#"{ "name": "x", "goals": { "requests": 1 } }"#,
+            r#"{ "name": "x", "goals": { "upload": 1 }, "client_to_server": [{ "type": "warp" }] }"#,
+            r#"{ "name": "x", "goals": { "upload": 1 }, "client_to_server": [{ "type": "drop", "pct": 1, "extra": 2 }] }"#,
+            r#"{ "name": "x", "goals": { "upload": 1 }, "client_to_server": [{ "type": "reorder", "pct": 1, "window": 0, "max_hold_ms": 1 }] }"#,
+            r#"{ "name": "x", "goals": {} }"#,
+            r#"{ "name": "x", "goals": { "upload": 1 }, "client_to_server": [{ "type": "rebind", "at_ms": 1, "port": 2 }] }"#,
+            r#"{ "name": "x", "goals": { "upload": 1 }, "client_to_server": [{ "type": "rebind", "at_ms": 1, "port": 2 }], "server_to_client": [{ "type": "rebind", "at_ms": 3, "port": 2 }] }"#,
+        ] {
+            assert!(Scenario::from_json(s).is_err(), "{s}");
+        }
+    }
+
+    /// The scenarios in the `scenarios` directory all pass.
+    #[test]
+    fn files() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios");
+        let files = Scenario::find(dir).unwrap();
+        assert!(!files.is_empty());
+        for f in files {
+            let report = Scenario::load(&f).unwrap().run();
+            assert!(report.passed, "{}: {report}", f.display());
+        }
+    }
+}
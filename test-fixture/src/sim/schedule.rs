@@ -0,0 +1,142 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    fmt::{self, Debug},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use neqo_common::{Datagram, qtrace};
+use neqo_transport::Output;
+
+use super::Node;
+
+/// Drops everything for a period.  The outage starts `start` after setup
+/// completes and lasts for `length`.
+pub struct Outage {
+    start: Duration,
+    length: Duration,
+    /// When setup completed.
+    base: Option<Instant>,
+}
+
+impl Outage {
+    #[must_use]
+    pub const fn new(start: Duration, length: Duration) -> Self {
+        Self {
+            start,
+            length,
+            base: None,
+        }
+    }
+}
+
+impl Node for Outage {
+    fn prepare(&mut self, now: Instant) {
+        self.base = Some(now);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, now: Instant) -> Output {
+        let Some(dgram) = d else {
+            return Output::None;
+        };
+        if let Some(base) = self.base {
+            let start = base + self.start;
+            if start <= now && now < start + self.length {
+                qtrace!("outage dropping {}", dgram.len());
+                return Output::None;
+            }
+        }
+        Output::Datagram(dgram)
+    }
+}
+
+impl Debug for Outage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("outage")
+    }
+}
+
+/// Changes an address, as a NAT might when its binding changes.  From `at`
+/// after setup completes, `from` is replaced with `to`.
+///
+/// Use [`Rebind::source`] for datagrams leaving the endpoint that moves and
+/// [`Rebind::destination`] with the addresses swapped for those going to it.
+/// After the change, the latter drops datagrams that are sent to the old
+/// address, so the peer has to migrate to keep the connection alive.
+pub struct Rebind {
+    at: Duration,
+    from: SocketAddr,
+    to: SocketAddr,
+    source: bool,
+    /// When setup completed.
+    base: Option<Instant>,
+}
+
+impl Rebind {
+    /// Rewrite the source address.
+    #[must_use]
+    pub const fn source(at: Duration, from: SocketAddr, to: SocketAddr) -> Self {
+        Self {
+            at,
+            from,
+            to,
+            source: true,
+            base: None,
+        }
+    }
+
+    /// Rewrite the destination address.
+    #[must_use]
+    pub const fn destination(at: Duration, from: SocketAddr, to: SocketAddr) -> Self {
+        Self {
+            at,
+            from,
+            to,
+            source: false,
+            base: None,
+        }
+    }
+
+    fn rewrite(&self, d: Datagram) -> Option<Datagram> {
+        let (mut src, mut dst) = (d.source(), d.destination());
+        let addr = if self.source { &mut src } else { &mut dst };
+        if *addr == self.from {
+            *addr = self.to;
+            qtrace!("rebind {} to {}", self.from, self.to);
+            Some(Datagram::new(src, dst, d.tos(), d.to_vec()))
+        } else if !self.source && *addr == self.to {
+            // The old binding is gone.
+            qtrace!("rebind dropping {} for {}", d.len(), self.to);
+            None
+        } else {
+            Some(d)
+        }
+    }
+}
+
+impl Node for Rebind {
+    fn prepare(&mut self, now: Instant) {
+        self.base = Some(now);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, now: Instant) -> Output {
+        d.map_or(Output::None, |dgram| {
+            if self.base.is_some_and(|base| base + self.at <= now) {
+                self.rewrite(dgram).map_or(Output::None, Output::Datagram)
+            } else {
+                Output::Datagram(dgram)
+            }
+        })
+    }
+}
+
+impl Debug for Rebind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("rebind")
+    }
+}
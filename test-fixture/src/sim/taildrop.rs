@@ -122,6 +122,9 @@ pub struct TailDrop {
     capacity: usize,
     /// Whether to apply ECN markings.
     ecn: bool,
+    /// If set, mark every packet when the queue is deeper than this many bytes,
+    /// rather than using RED.
+    mark_threshold: Option<usize>,
 
     /// A counter for how many bytes are enqueued.
     used: usize,
@@ -165,6 +168,7 @@ impl TailDrop {
             rate,
             capacity,
             ecn,
+            mark_threshold: None,
             used: 0,
             queue: VecDeque::new(),
             next_deque: None,
@@ -188,6 +192,15 @@ impl TailDrop {
         Self::new(200_000, 8_192, false, Duration::from_millis(50))
     }
 
+    /// Enable ECN and mark every ECT packet that arrives when more than
+    /// `threshold` bytes are queued, like a step AQM.
+    #[must_use]
+    pub const fn mark_threshold(mut self, threshold: usize) -> Self {
+        self.ecn = true;
+        self.mark_threshold = Some(threshold);
+        self
+    }
+
     /// How "big" is this datagram, accounting for overheads.
     /// This approximates by using the same overhead for storing in the queue
     /// and for sending on the wire.
@@ -275,6 +288,10 @@ impl TailDrop {
     }
 
     fn should_mark(&self, used: usize) -> bool {
+        if let Some(threshold) = self.mark_threshold {
+            return used > threshold;
+        }
+
         // Apply RED which starts at 0 mark chance at 40% of the capacity.
         // From there, follow a quadratic that reaches 1 at 90% capacity.
         // Cap at around 95% mark probability.
@@ -0,0 +1,94 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    fmt::{self, Debug},
+    time::Instant,
+};
+
+use neqo_common::{Datagram, qtrace};
+use neqo_transport::Output;
+
+use super::{Node, Rng};
+
+/// One second in nanoseconds.
+const ONE_SECOND_NS: u128 = 1_000_000_000;
+
+/// A token bucket policer.  Tokens accumulate at `rate` bytes per second, up
+/// to `burst` bytes.  Datagrams that arrive when there aren't enough tokens
+/// are dropped.
+///
+/// Unlike [`super::network::TailDrop`], this doesn't queue, so it doesn't add
+/// any delay.
+pub struct TokenBucket {
+    rate: u128,
+    burst: u128,
+    /// Available tokens, in units of bytes * nanoseconds per second.
+    tokens: u128,
+    last: Option<Instant>,
+    dropped: usize,
+}
+
+impl TokenBucket {
+    /// # Panics
+    ///
+    /// When `rate` is zero.
+    #[must_use]
+    pub const fn new(rate: usize, burst: usize) -> Self {
+        assert!(rate != 0, "zero rate gets you nowhere");
+        let burst = (burst as u128) * ONE_SECOND_NS;
+        Self {
+            rate: rate as u128,
+            burst,
+            tokens: burst,
+            last: None,
+            dropped: 0,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(last) = self.last {
+            let elapsed = now.saturating_duration_since(last).as_nanos();
+            self.tokens = self.burst.min(
+                self.tokens
+                    .saturating_add(elapsed.saturating_mul(self.rate)),
+            );
+        }
+        self.last = Some(now);
+    }
+}
+
+impl Node for TokenBucket {
+    fn init(&mut self, _rng: Rng, now: Instant) {
+        self.last = Some(now);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, now: Instant) -> Output {
+        let Some(dgram) = d else {
+            return Output::None;
+        };
+        self.refill(now);
+        let cost = (dgram.len() as u128) * ONE_SECOND_NS;
+        if cost <= self.tokens {
+            self.tokens -= cost;
+            Output::Datagram(dgram)
+        } else {
+            qtrace!("token bucket dropping {}", dgram.len());
+            self.dropped += 1;
+            Output::None
+        }
+    }
+
+    fn print_summary(&self, test_name: &str) {
+        neqo_common::qinfo!("{test_name}: token bucket: dropped {}", self.dropped);
+    }
+}
+
+impl Debug for TokenBucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("token-bucket")
+    }
+}